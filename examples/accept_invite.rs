@@ -55,10 +55,22 @@ impl Layer for InviteAcceptLayer {
 
                     event.respond_success(response).await.unwrap();
                 }
+                InviteSessionEvent::UpdateReceived(event) => {
+                    event.process_default().await.unwrap();
+                }
+                InviteSessionEvent::ReferReceived(event) => {
+                    event.reject(StatusCode::NOT_IMPLMENTED).await.unwrap();
+                }
+                InviteSessionEvent::NotifyReceived(event) => {
+                    event.process_default().await.unwrap();
+                }
+                InviteSessionEvent::InfoReceived(event) => {
+                    event.process_default().await.unwrap();
+                }
                 InviteSessionEvent::Bye(event) => {
                     event.process_default().await.unwrap();
                 }
-                InviteSessionEvent::Terminated => {
+                InviteSessionEvent::SessionExpired | InviteSessionEvent::Terminated => {
                     break;
                 }
             }
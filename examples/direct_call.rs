@@ -0,0 +1,124 @@
+//! Call a user agent in the local network directly, without registration or authentication
+//!
+//! Usage: `direct_call <target ip or sip uri> [local ip]`
+
+use bytesstr::BytesStr;
+use session::{
+    AsyncEvent, AsyncSdpSession, Codec, Codecs, Direction, MediaType, Options, SessionDescription,
+};
+use sip_core::transport::udp::Udp;
+use sip_core::Endpoint;
+use sip_types::header::typed::Contact;
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{Name, StatusCode};
+use sip_ua::dialog::DialogLayer;
+use sip_ua::invite::initiator::{InviteInitiator, Response};
+use sip_ua::invite::session::InviteSessionEvent;
+use sip_ua::invite::InviteLayer;
+use std::error::Error;
+use std::net::IpAddr;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt::init();
+
+    let mut args = std::env::args().skip(1);
+
+    let target = args.next().unwrap_or_else(|| "127.0.0.1".into());
+    let target: SipUri = if target.starts_with("sip:") {
+        target.parse()?
+    } else {
+        format!("sip:{target}").parse()?
+    };
+
+    let local_ip: IpAddr = args.next().as_deref().unwrap_or("127.0.0.1").parse()?;
+
+    let mut builder = Endpoint::builder();
+
+    builder.add_layer(DialogLayer::default());
+    builder.add_layer(InviteLayer::default());
+
+    Udp::spawn(&mut builder, (local_ip, 5070)).await?;
+
+    let endpoint = builder.build();
+
+    // Plain RTP audio with the codecs supported by virtually every user agent
+    let mut media = AsyncSdpSession::new(local_ip, Options::lan());
+
+    let audio = media
+        .add_local_media(
+            Codecs::new(MediaType::Audio)
+                .with_codec(Codec::PCMU)
+                .with_codec(Codec::PCMA),
+            1,
+            Direction::SendRecv,
+        )
+        .expect("payload types are available");
+
+    media.add_media(audio, Direction::SendRecv);
+
+    let offer = media.create_sdp_offer().await?;
+
+    let local_uri: SipUri = format!("sip:{local_ip}:5070").parse()?;
+
+    let mut initiator = InviteInitiator::new(
+        endpoint,
+        NameAddr::uri(local_uri.clone()),
+        Contact::new(NameAddr::uri(local_uri)),
+        target,
+    );
+
+    let mut invite = initiator.create_invite();
+    invite.headers.insert(Name::CONTENT_TYPE, "application/sdp");
+    invite.body = offer.to_string().into();
+
+    initiator.send_invite(invite).await?;
+
+    let mut session = loop {
+        match initiator.receive().await? {
+            Response::Provisional(_) | Response::EarlyEvent => {}
+            Response::Failure(response) => {
+                println!("Call failed: {}", response.line.code.into_u16());
+                return Ok(());
+            }
+            Response::Early(..) => {}
+            Response::Session(session, response) => {
+                let answer = BytesStr::from_utf8_bytes(response.body)?;
+                media
+                    .receive_sdp_answer(SessionDescription::parse(&answer)?)
+                    .await?;
+
+                break session;
+            }
+            Response::Finished => return Ok(()),
+        }
+    };
+
+    println!("Call established");
+
+    loop {
+        tokio::select! {
+            event = session.drive() => match event? {
+                InviteSessionEvent::RefreshNeeded(event) => event.process_default().await?,
+                InviteSessionEvent::ReInviteReceived(_) => {}
+                InviteSessionEvent::UpdateReceived(event) => event.process_default().await?,
+                InviteSessionEvent::ReferReceived(event) => {
+                    event.reject(StatusCode::NOT_IMPLMENTED).await?
+                }
+                InviteSessionEvent::NotifyReceived(event) => event.process_default().await?,
+                InviteSessionEvent::InfoReceived(event) => event.process_default().await?,
+                InviteSessionEvent::Bye(event) => event.process_default().await?,
+                InviteSessionEvent::SessionExpired | InviteSessionEvent::Terminated => break,
+            },
+            event = media.run() => {
+                if let AsyncEvent::ReceiveRTP { packet, .. } = event? {
+                    println!("Received RTP packet, seq={}", packet.sequence_number.0);
+                }
+            }
+        }
+    }
+
+    println!("Call terminated");
+
+    Ok(())
+}
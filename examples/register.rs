@@ -48,9 +48,17 @@ async fn main() -> Result<()> {
         let mut transaction = endpoint.send_request(request, &mut target).await?;
         let response = transaction.receive_final().await?;
 
-        match response.line.code.kind() {
-            CodeKind::Success => {}
-            _ => panic!("registration failed!"),
+        if response.line.code.kind() != CodeKind::Success {
+            if registration.receive_error_response(response) {
+                continue;
+            }
+
+            // Back off using the registration's retry policy
+            if !registration.wait_for_retry().await {
+                panic!("registration failed!");
+            }
+
+            continue;
         }
 
         registration.receive_success_response(response);
@@ -38,7 +38,7 @@ async fn main() -> Result<()> {
     loop {
         let mut invite = initiator.create_invite();
 
-        authenticator.authorize_request(&mut invite.headers);
+        authenticator.authorize_request(&invite.line, &mut invite.headers, &invite.body);
 
         initiator.send_invite(invite).await?;
 
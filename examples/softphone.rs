@@ -0,0 +1,43 @@
+//! Minimal softphone answering all incoming calls, or calling a target if one is given
+//!
+//! Usage: `softphone <local ip> [target sip uri]`
+
+use sip_types::print::AppendCtx;
+use softphone::{Softphone, SoftphoneEvent};
+use std::error::Error;
+use std::net::IpAddr;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    tracing_subscriber::fmt::init();
+
+    let mut args = std::env::args().skip(1);
+
+    let local_ip: IpAddr = args.next().as_deref().unwrap_or("127.0.0.1").parse()?;
+
+    let mut phone = Softphone::builder(format!("sip:alice@{local_ip}").parse()?, local_ip)
+        .display_name("Alice")
+        .build()
+        .await?;
+
+    if let Some(target) = args.next() {
+        phone.dial(target.parse()?);
+    }
+
+    while let Some(event) = phone.next_event().await {
+        match event {
+            SoftphoneEvent::IncomingCall { call, from, .. } => {
+                println!("Incoming call from {}", from.uri.default_print_ctx());
+                phone.answer(call).await?;
+            }
+            SoftphoneEvent::Ringing { .. } => println!("Ringing"),
+            SoftphoneEvent::Established { .. } => println!("Call established"),
+            SoftphoneEvent::Dtmf { digit, .. } => println!("Received DTMF {digit}"),
+            SoftphoneEvent::Rtp { .. } => {}
+            SoftphoneEvent::Ended { reason, .. } => println!("Call ended: {reason:?}"),
+            event => println!("{event:?}"),
+        }
+    }
+
+    Ok(())
+}
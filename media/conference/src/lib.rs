@@ -0,0 +1,404 @@
+//! Audio conference mixer
+//!
+//! The [`Mixer`] receives the RTP audio of every participant, decodes it and sends each participant the mix of all
+//! other participants' audio (minus-one mixing), encoded as RTP packets with the participant's own codec. It is
+//! transport agnostic: received packets are passed to [`Mixer::receive_rtp`], the mixed packets returned by
+//! [`Mixer::poll`] must be sent to the participants, e.g. using `SdpSession::send_rtp` of their call.
+//!
+//! ```
+//! # use ezk_conference::{Mixer, ParticipantConfig};
+//! # use rtp::{G711, RtpPacket};
+//! # use std::time::Instant;
+//! # fn example(mut receive_rtp: impl FnMut() -> Option<(u32, RtpPacket)>, send_rtp: impl Fn(u32, RtpPacket)) {
+//! let mut mixer = Mixer::new(20);
+//! mixer.add_participant(1, ParticipantConfig::new(G711::Pcmu, 0));
+//! mixer.add_participant(2, ParticipantConfig::new(G711::Pcma, 8).with_gain(0.5));
+//!
+//! loop {
+//!     std::thread::sleep(mixer.timeout(Instant::now()));
+//!
+//!     while let Some((participant, packet)) = receive_rtp() {
+//!         mixer.receive_rtp(participant, &packet);
+//!     }
+//!
+//!     while let Some(packets) = mixer.poll(Instant::now()) {
+//!         for (participant, packet) in packets {
+//!             send_rtp(participant, packet);
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+
+use rtp::{AudioMixer, AudioPacketizer, RtpPacket, RtpTimestamp, SequenceNumber, G711};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Sample rate the audio is mixed at, the clock rate of G.711
+const SAMPLE_RATE: u32 = 8000;
+
+/// Maximum number of frames of received audio buffered per participant, older audio is dropped to bound the delay
+const MAX_BUFFERED_FRAMES: usize = 5;
+
+/// Codec and gain of a participant of the [`Mixer`]
+#[derive(Debug, Clone, Copy)]
+pub struct ParticipantConfig {
+    law: G711,
+    pt: u8,
+    gain: f32,
+}
+
+impl ParticipantConfig {
+    /// Participant sending and receiving G.711 audio using the given law and negotiated payload type
+    pub fn new(law: G711, pt: u8) -> Self {
+        Self { law, pt, gain: 1.0 }
+    }
+
+    /// Linear gain applied to the participant's audio before mixing it, defaults to `1.0`
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Participant<K> {
+    key: K,
+    config: ParticipantConfig,
+
+    /// Decoded received audio which has not been mixed yet
+    input: VecDeque<i16>,
+    last_sequence_number: Option<SequenceNumber>,
+
+    output: AudioPacketizer,
+}
+
+/// Minus-one audio mixer for a conference of RTP participants, see the [crate level documentation](crate)
+///
+/// Mixes a frame of audio every packet time, participants which sent no audio for a frame are treated as silent.
+/// Only G.711 is supported.
+#[derive(Debug)]
+pub struct Mixer<K> {
+    ptime_ms: u32,
+    audio_mixer: AudioMixer<K>,
+    participants: Vec<Participant<K>>,
+
+    /// When the first frame was mixed
+    start: Option<Instant>,
+    frames_mixed: u32,
+}
+
+impl<K: Copy + PartialEq> Mixer<K> {
+    /// Create a mixer sending packets of `ptime_ms` milliseconds of audio
+    pub fn new(ptime_ms: u32) -> Self {
+        Self {
+            ptime_ms: ptime_ms.max(1),
+            audio_mixer: AudioMixer::new(),
+            participants: vec![],
+            start: None,
+            frames_mixed: 0,
+        }
+    }
+
+    /// Add a participant, replaces the configuration of an existing one
+    ///
+    /// Audio of an existing participant which was encoded but not sent yet is discarded when its codec or payload
+    /// type changes.
+    pub fn add_participant(&mut self, key: K, config: ParticipantConfig) {
+        let ptime_ms = self.ptime_ms;
+
+        if let Some(participant) = self.participant_mut(key) {
+            if participant.config.pt != config.pt || participant.config.law != config.law {
+                participant.output = packetizer(config.pt, ptime_ms);
+            }
+
+            participant.config = config;
+            return;
+        }
+
+        self.audio_mixer.add(key);
+        self.participants.push(Participant {
+            key,
+            config,
+            input: VecDeque::new(),
+            last_sequence_number: None,
+            output: packetizer(config.pt, ptime_ms),
+        });
+    }
+
+    /// Remove a participant, its buffered audio is discarded
+    pub fn remove_participant(&mut self, key: K) {
+        self.audio_mixer.remove(key);
+        self.participants
+            .retain(|participant| participant.key != key);
+    }
+
+    /// Set the linear gain applied to the participant's audio before mixing it
+    pub fn set_gain(&mut self, key: K, gain: f32) {
+        if let Some(participant) = self.participant_mut(key) {
+            participant.config.gain = gain;
+        }
+    }
+
+    /// Mute or unmute a participant, muted participants still hear the others
+    pub fn set_muted(&mut self, key: K, muted: bool) {
+        self.audio_mixer.set_muted(key, muted);
+    }
+
+    /// Put a participant on hold or resume it, participants on hold neither hear nor are heard by the others and
+    /// are sent no packets
+    pub fn set_on_hold(&mut self, key: K, on_hold: bool) {
+        self.audio_mixer.set_on_hold(key, on_hold);
+    }
+
+    /// Handle an RTP packet received from a participant
+    ///
+    /// Packets with another payload type than the participant's, e.g. telephone-events, and late or duplicate
+    /// packets are ignored.
+    pub fn receive_rtp(&mut self, key: K, packet: &RtpPacket) {
+        let frame_len = self.frame_len();
+
+        let Some(participant) = self.participant_mut(key) else {
+            return;
+        };
+
+        if packet.pt != participant.config.pt {
+            return;
+        }
+
+        if let Some(last) = participant.last_sequence_number {
+            // Sequence numbers wrap around, newer packets are less than half the range ahead
+            let ahead = packet.sequence_number.0.wrapping_sub(last.0);
+
+            if ahead == 0 || ahead >= 0x8000 {
+                return;
+            }
+        }
+
+        participant.last_sequence_number = Some(packet.sequence_number);
+
+        let mut samples = Vec::with_capacity(packet.payload.len());
+        participant.config.law.decode(&packet.payload, &mut samples);
+        participant.input.extend(samples);
+
+        let max_len = frame_len * MAX_BUFFERED_FRAMES;
+
+        if participant.input.len() > max_len {
+            let excess = participant.input.len() - max_len;
+            participant.input.drain(..excess);
+        }
+    }
+
+    /// Duration until the next frame must be mixed using [`poll`](Self::poll)
+    pub fn timeout(&self, now: Instant) -> Duration {
+        self.next_frame_at()
+            .map_or(Duration::ZERO, |at| at.saturating_duration_since(now))
+    }
+
+    /// Mix the next frame if it is due, returns the packet to send to each participant which is not on hold
+    ///
+    /// Mixing starts with the first call. Must be called until it returns `None`, as multiple frames may be due if
+    /// it wasn't called in time.
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<(K, RtpPacket)>> {
+        if self.next_frame_at().is_some_and(|at| at > now) {
+            return None;
+        }
+
+        self.start.get_or_insert(now);
+        self.frames_mixed += 1;
+
+        let frame_len = self.frame_len();
+
+        let inputs: Vec<(K, Vec<i16>)> = self
+            .participants
+            .iter_mut()
+            .map(|participant| {
+                let available = participant.input.len().min(frame_len);
+                let gain = participant.config.gain;
+
+                let frame = participant
+                    .input
+                    .drain(..available)
+                    .map(|sample| {
+                        (f32::from(sample) * gain).clamp(f32::from(i16::MIN), f32::from(i16::MAX))
+                            as i16
+                    })
+                    .collect();
+
+                (participant.key, frame)
+            })
+            .collect();
+
+        let inputs: Vec<(K, &[i16])> = inputs
+            .iter()
+            .map(|(key, frame)| (*key, frame.as_slice()))
+            .collect();
+
+        let mut packets = vec![];
+
+        for (key, mut frame) in self.audio_mixer.mix(&inputs) {
+            let Some(participant) = self.participants.iter_mut().find(|p| p.key == key) else {
+                continue;
+            };
+
+            // Missing audio is silence
+            frame.resize(frame_len, 0);
+
+            let mut encoded = Vec::with_capacity(frame_len);
+            participant.config.law.encode(&frame, &mut encoded);
+            participant.output.push(&encoded);
+
+            if let Some(packet) = participant.output.pop_packet() {
+                packets.push((key, packet));
+            }
+        }
+
+        Some(packets)
+    }
+
+    fn participant_mut(&mut self, key: K) -> Option<&mut Participant<K>> {
+        self.participants
+            .iter_mut()
+            .find(|participant| participant.key == key)
+    }
+
+    /// Number of samples in a frame
+    fn frame_len(&self) -> usize {
+        (SAMPLE_RATE * self.ptime_ms / 1000) as usize
+    }
+
+    fn next_frame_at(&self) -> Option<Instant> {
+        let ptime = Duration::from_millis(u64::from(self.ptime_ms));
+
+        self.start.map(|start| start + ptime * self.frames_mixed)
+    }
+}
+
+fn packetizer(pt: u8, ptime_ms: u32) -> AudioPacketizer {
+    let mut packetizer = AudioPacketizer::g711(pt)
+        .with_start(SequenceNumber(rand::random()), RtpTimestamp(rand::random()));
+    packetizer.set_ptime(Some(ptime_ms), None);
+    packetizer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtp::{RtpExtensions, Ssrc};
+
+    fn packet(pt: u8, sequence_number: u16, law: G711, sample: i16) -> RtpPacket {
+        let mut payload = vec![];
+        law.encode(&[sample; 160], &mut payload);
+
+        RtpPacket {
+            pt,
+            marker: false,
+            sequence_number: SequenceNumber(sequence_number),
+            ssrc: Ssrc(1),
+            timestamp: RtpTimestamp(0),
+            extensions: RtpExtensions::default(),
+            payload: payload.into(),
+            padding: None,
+        }
+    }
+
+    fn decoded(packets: &[(u32, RtpPacket)], key: u32, law: G711) -> Vec<i16> {
+        let (_, packet) = packets.iter().find(|(k, _)| *k == key).unwrap();
+
+        let mut samples = vec![];
+        law.decode(&packet.payload, &mut samples);
+        samples
+    }
+
+    #[test]
+    fn minus_one_with_gain() {
+        let mut mixer = Mixer::new(20);
+        mixer.add_participant(1, ParticipantConfig::new(G711::Pcmu, 0));
+        mixer.add_participant(2, ParticipantConfig::new(G711::Pcma, 8).with_gain(0.5));
+        mixer.add_participant(3, ParticipantConfig::new(G711::Pcmu, 0));
+
+        mixer.receive_rtp(1, &packet(0, 1, G711::Pcmu, 1000));
+        mixer.receive_rtp(2, &packet(8, 1, G711::Pcma, 2000));
+        // Wrong payload type, e.g. a telephone-event
+        mixer.receive_rtp(3, &packet(101, 1, G711::Pcmu, 8000));
+
+        let start = Instant::now();
+        let packets = mixer.poll(start).unwrap();
+
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[1].1.pt, 8);
+        assert_eq!(packets[1].1.payload.len(), 160);
+
+        // Participant 1 hears participant 2 at half the volume
+        let heard = decoded(&packets, 1, G711::Pcmu);
+        assert!((heard[0] - 1000).abs() < 64, "{}", heard[0]);
+
+        // Participant 2 hears only participant 1
+        let heard = decoded(&packets, 2, G711::Pcma);
+        assert!((heard[0] - 1000).abs() < 64, "{}", heard[0]);
+
+        let heard = decoded(&packets, 3, G711::Pcmu);
+        assert!((heard[0] - 2000).abs() < 128, "{}", heard[0]);
+
+        assert!(mixer.poll(start).is_none());
+        assert_eq!(mixer.timeout(start), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn late_packets_and_silence() {
+        let mut mixer = Mixer::new(20);
+        mixer.add_participant(1, ParticipantConfig::new(G711::Pcmu, 0));
+        mixer.add_participant(2, ParticipantConfig::new(G711::Pcmu, 0));
+
+        mixer.receive_rtp(1, &packet(0, 5, G711::Pcmu, 1000));
+        mixer.receive_rtp(1, &packet(0, 4, G711::Pcmu, 1000));
+        mixer.receive_rtp(1, &packet(0, 5, G711::Pcmu, 1000));
+
+        let start = Instant::now();
+
+        let first = mixer.poll(start).unwrap();
+        let heard = decoded(&first, 2, G711::Pcmu);
+        assert!(heard[0] > 900);
+        assert_eq!(decoded(&first, 1, G711::Pcmu), [0; 160]);
+
+        // The duplicate and late packets were dropped, nothing is left to mix
+        let second = mixer.poll(start + Duration::from_millis(20)).unwrap();
+        assert_eq!(decoded(&second, 2, G711::Pcmu), [0; 160]);
+
+        let sequence_number = |packets: &[(u32, RtpPacket)]| packets[0].1.sequence_number.0;
+        assert_eq!(
+            sequence_number(&second),
+            sequence_number(&first).wrapping_add(1)
+        );
+    }
+
+    #[test]
+    fn codec_change_of_participant() {
+        let mut mixer = Mixer::new(20);
+        mixer.add_participant(1, ParticipantConfig::new(G711::Pcmu, 0));
+        mixer.add_participant(2, ParticipantConfig::new(G711::Pcmu, 0));
+
+        let start = Instant::now();
+        let packets = mixer.poll(start).unwrap();
+        assert!(packets.iter().all(|(_, packet)| packet.pt == 0));
+
+        // Renegotiated to PCMA
+        mixer.add_participant(2, ParticipantConfig::new(G711::Pcma, 8));
+
+        // Audio with the old payload type is ignored
+        mixer.receive_rtp(2, &packet(0, 1, G711::Pcmu, 1000));
+        mixer.receive_rtp(1, &packet(0, 1, G711::Pcmu, 1000));
+
+        let packets = mixer.poll(start + Duration::from_millis(20)).unwrap();
+        assert_eq!(packets.len(), 2);
+
+        let (_, to_second) = packets.iter().find(|(key, _)| *key == 2).unwrap();
+        assert_eq!(to_second.pt, 8);
+        assert_eq!(to_second.payload.len(), 160);
+
+        let heard = decoded(&packets, 2, G711::Pcma);
+        assert!((heard[0] - 1000).abs() < 64, "{}", heard[0]);
+
+        assert_eq!(decoded(&packets, 1, G711::Pcmu), [0; 160]);
+    }
+}
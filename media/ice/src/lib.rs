@@ -22,8 +22,12 @@ use stun_types::{
     },
     Class, Message, TransactionId,
 };
+use turn::{TurnAllocation, TurnResponse};
 
 mod stun;
+mod turn;
+
+pub use turn::{InvalidTurnUri, TurnCredentials, TurnTransport, TurnUri};
 
 /// A message received on a UDP socket
 pub struct ReceivedPkt<D = Vec<u8>> {
@@ -67,6 +71,10 @@ pub enum IceEvent {
         source: Option<IpAddr>,
         target: SocketAddr,
     },
+    /// A new local candidate has been discovered, which can be signaled to the peer using trickle ICE
+    ///
+    /// Emitted for all candidates returned by [`IceAgent::ice_candidates`].
+    NewLocalCandidate { candidate: IceCandidate },
 }
 
 /// The ICE agent state machine
@@ -74,6 +82,7 @@ pub struct IceAgent {
     stun_config: StunConfig,
 
     stun_server: Vec<StunServerBinding>,
+    turn_server: Vec<TurnAllocation>,
 
     local_credentials: IceCredentials,
     remote_credentials: Option<IceCredentials>,
@@ -144,7 +153,7 @@ enum CandidateKind {
     Host = 126,
     PeerReflexive = 110,
     ServerReflexive = 100,
-    // TODO: Relayed = 0,
+    Relayed = 0,
 }
 
 struct Candidate {
@@ -229,6 +238,7 @@ impl IceAgent {
         IceAgent {
             stun_config: StunConfig::new(),
             stun_server: vec![],
+            turn_server: vec![],
             local_credentials,
             remote_credentials: Some(remote_credentials),
             local_candidates: SlotMap::with_key(),
@@ -255,6 +265,7 @@ impl IceAgent {
         IceAgent {
             stun_config: StunConfig::new(),
             stun_server: vec![],
+            turn_server: vec![],
             local_credentials,
             remote_credentials: None,
             local_candidates: SlotMap::with_key(),
@@ -286,6 +297,7 @@ impl IceAgent {
         // Remove all rtcp candidates and stun server bindings rtcp-mux is enabled
         if rtcp_mux {
             self.stun_server.retain(|s| s.component() == Component::Rtp);
+            self.turn_server.retain(|s| s.component() == Component::Rtp);
             self.local_candidates
                 .retain(|_, c| c.component == Component::Rtp);
         }
@@ -323,6 +335,40 @@ impl IceAgent {
         self.add_local_candidate(component, CandidateKind::Host, addr, addr);
     }
 
+    /// Add a known public address the local `base` address is mapped to, e.g. by a static NAT
+    ///
+    /// The address is added as server reflexive candidate, as if it had been discovered using STUN.
+    pub fn add_mapped_addr(&mut self, component: Component, base: SocketAddr, addr: SocketAddr) {
+        if base == addr {
+            return;
+        }
+
+        self.add_local_candidate(component, CandidateKind::ServerReflexive, base, addr);
+    }
+
+    /// Add a server reflexive address which was discovered for the local `base` address using the STUN `server`
+    /// before the agent was created, e.g. by a socket that was bound ahead of time
+    ///
+    /// If the STUN server was added to the agent, no binding request is sent for the component, the binding is only
+    /// refreshed.
+    pub fn add_server_reflexive_addr(
+        &mut self,
+        component: Component,
+        server: SocketAddr,
+        base: SocketAddr,
+        addr: SocketAddr,
+    ) {
+        if let Some(binding) = self
+            .stun_server
+            .iter_mut()
+            .find(|binding| binding.component() == component && binding.server() == server)
+        {
+            binding.complete(&self.stun_config, addr);
+        }
+
+        self.add_local_candidate(component, CandidateKind::ServerReflexive, base, addr);
+    }
+
     /// Add a STUN server which the ICE agent should use to gather additional (server-reflexive) candidates.
     pub fn add_stun_server(&mut self, server: SocketAddr) {
         // TODO: ideally we create a stun server binding for every local interface
@@ -335,6 +381,25 @@ impl IceAgent {
         }
     }
 
+    /// Add a TURN server which the ICE agent should use to allocate relayed candidates.
+    ///
+    /// The server is contacted using UDP, the address of a [`TurnUri`] must be resolved by the caller.
+    ///
+    /// The allocations, permissions for the remote candidates and channel bindings of nominated pairs are created and
+    /// refreshed while polling the agent.
+    pub fn add_turn_server(&mut self, server: SocketAddr, credentials: TurnCredentials) {
+        if !self.rtcp_mux {
+            self.turn_server.push(TurnAllocation::new(
+                server,
+                Component::Rtcp,
+                credentials.clone(),
+            ));
+        }
+
+        self.turn_server
+            .push(TurnAllocation::new(server, Component::Rtp, credentials));
+    }
+
     /// Returns the current ICE candidate gathering state
     pub fn gathering_state(&self) -> IceGatheringState {
         self.gathering_state
@@ -371,7 +436,14 @@ impl IceAgent {
             CandidateKind::Host => (65535 / 4) * 3,
             CandidateKind::PeerReflexive => (65535 / 4) * 2,
             CandidateKind::ServerReflexive => 65535 / 4,
-            // CandidateKind::Relayed => 0,
+            CandidateKind::Relayed => 0,
+        };
+
+        // Prefer IPv6 over IPv4 host candidates (RFC 8421)
+        let address_family_preference = if kind == CandidateKind::Host && addr.is_ipv6() {
+            65535 / 8
+        } else {
+            0
         };
 
         let local_preference = self
@@ -379,13 +451,14 @@ impl IceAgent {
             .values()
             .filter(|c| c.kind == kind)
             .count() as u32
-            + local_preference_offset;
+            + local_preference_offset
+            + address_family_preference;
 
         let kind_preference = (kind as u32) << 24;
         let local_preference = local_preference << 8;
         let priority = kind_preference + local_preference + (256 - component as u32);
 
-        self.local_candidates.insert(Candidate {
+        let id = self.local_candidates.insert(Candidate {
             addr,
             kind,
             priority,
@@ -394,6 +467,13 @@ impl IceAgent {
             base,
         });
 
+        // Peer-reflexive candidates are never signaled
+        if kind != CandidateKind::PeerReflexive {
+            let candidate = self.to_ice_candidate(&self.local_candidates[id]);
+            self.events
+                .push_back(IceEvent::NewLocalCandidate { candidate });
+        }
+
         self.form_pairs();
     }
 
@@ -402,6 +482,7 @@ impl IceAgent {
         let kind = match candidate.typ.as_str() {
             "host" => CandidateKind::Host,
             "srflx" => CandidateKind::ServerReflexive,
+            "relay" => CandidateKind::Relayed,
             _ => return,
         };
 
@@ -427,8 +508,20 @@ impl IceAgent {
             UntaggedAddress::IpAddress(ip_addr) => ip_addr,
         };
 
+        let addr = SocketAddr::new(ip, candidate.port);
+
+        // Candidates may be received both trickled and in a SDP
+        let already_exists = self
+            .remote_candidates
+            .values()
+            .any(|c| c.kind == kind && c.component == component && c.addr == addr);
+
+        if already_exists {
+            return;
+        }
+
         self.remote_candidates.insert(Candidate {
-            addr: SocketAddr::new(ip, candidate.port),
+            addr,
             kind,
             priority: u32::try_from(candidate.priority).unwrap(),
             foundation: candidate.foundation.to_string(),
@@ -625,6 +718,10 @@ impl IceAgent {
             return;
         }
 
+        if self.receive_turn_response(&mut pkt) {
+            return;
+        }
+
         // Store messages later if the remote credentials aren't set yet
         let Some(remote_credentials) = &self.remote_credentials else {
             self.backlog.push(pkt);
@@ -711,7 +808,40 @@ impl IceAgent {
         }
     }
 
+    /// Check if the STUN response belongs to a TURN allocation, returns true if it has been handled
+    fn receive_turn_response(&mut self, pkt: &mut ReceivedPkt<Message>) -> bool {
+        // TURN responses are authenticated using the long-term credentials of the allocation, not the ICE credentials
+        for turn_allocation in &mut self.turn_server {
+            if !turn_allocation.wants_stun_response(pkt.data.transaction_id()) {
+                continue;
+            }
+
+            let component = turn_allocation.component();
+            let response =
+                turn_allocation.receive_stun_response(&self.stun_config, &mut pkt.data, |event| {
+                    self.events.push_back(event)
+                });
+
+            if let TurnResponse::Allocated { relayed, mapped } = response {
+                // The base of a relayed candidate is the relayed address itself
+                self.add_local_candidate(component, CandidateKind::Relayed, relayed, relayed);
+
+                if let Some(mapped) = mapped {
+                    self.add_mapped_addr(component, pkt.destination, mapped);
+                }
+            }
+
+            return true;
+        }
+
+        false
+    }
+
     fn receive_stun_error(&mut self, mut pkt: ReceivedPkt<Message>) {
+        if self.receive_turn_response(&mut pkt) {
+            return;
+        }
+
         let Some(remote_credentials) = &self.remote_credentials else {
             self.backlog.push(pkt);
             return;
@@ -791,12 +921,14 @@ impl IceAgent {
                         self.control_tie_breaker,
                     );
 
-                    self.events.push_back(IceEvent::SendData {
-                        component: pkt.component,
-                        data: response,
-                        source: Some(pkt.destination.ip()),
-                        target: pkt.source,
-                    });
+                    send_data(
+                        &self.turn_server,
+                        &mut self.events,
+                        pkt.component,
+                        response,
+                        pkt.destination,
+                        pkt.source,
+                    );
 
                     return;
                 } else {
@@ -816,12 +948,14 @@ impl IceAgent {
                         self.control_tie_breaker,
                     );
 
-                    self.events.push_back(IceEvent::SendData {
-                        component: pkt.component,
-                        data: response,
-                        source: Some(pkt.destination.ip()),
-                        target: pkt.source,
-                    });
+                    send_data(
+                        &self.turn_server,
+                        &mut self.events,
+                        pkt.component,
+                        response,
+                        pkt.destination,
+                        pkt.source,
+                    );
                     return;
                 } else {
                     self.is_controlling = true;
@@ -830,11 +964,10 @@ impl IceAgent {
             }
         }
 
-        let local_id = match self
-            .local_candidates
-            .iter()
-            .find(|(_, c)| c.kind == CandidateKind::Host && c.addr == pkt.destination)
-        {
+        let local_id = match self.local_candidates.iter().find(|(_, c)| {
+            matches!(c.kind, CandidateKind::Host | CandidateKind::Relayed)
+                && c.addr == pkt.destination
+        }) {
             Some((id, _)) => id,
             None => {
                 log::warn!(
@@ -902,12 +1035,14 @@ impl IceAgent {
             pkt.source,
         );
 
-        self.events.push_back(IceEvent::SendData {
-            component: pair.component,
-            data: stun_response,
-            source: Some(self.local_candidates[local_id].base.ip()),
-            target: pkt.source,
-        });
+        send_data(
+            &self.turn_server,
+            &mut self.events,
+            pair.component,
+            stun_response,
+            self.local_candidates[local_id].base,
+            pkt.source,
+        );
 
         // Check nomination state if we received a use-candidate
         if use_candidate {
@@ -922,6 +1057,12 @@ impl IceAgent {
             stun_server_bindings.poll(now, &self.stun_config, |event| self.events.push_back(event));
         }
 
+        // Progress all TURN allocations (used to create and maintain relayed candidates)
+        self.sync_turn_allocations(now);
+        for turn_allocation in &mut self.turn_server {
+            turn_allocation.poll(now, &self.stun_config, |event| self.events.push_back(event));
+        }
+
         // Handle pending stun retransmissions
         self.poll_retransmit(now);
         self.poll_state();
@@ -989,7 +1130,8 @@ impl IceAgent {
                 pair.nominated,
             );
 
-            let source = self.local_candidates[pair.local].base.ip();
+            let base = self.local_candidates[pair.local].base;
+            let source = base.ip();
             let target = self.remote_candidates[pair.remote].addr;
 
             pair.state = CandidatePairState::InProgress {
@@ -1001,12 +1143,38 @@ impl IceAgent {
                 target,
             };
 
-            self.events.push_back(IceEvent::SendData {
-                component: pair.component,
-                data: stun_request,
-                source: Some(source),
+            send_data(
+                &self.turn_server,
+                &mut self.events,
+                pair.component,
+                stun_request,
+                base,
                 target,
-            });
+            );
+        }
+    }
+
+    /// Keep the permissions and channel bindings of the TURN allocations in sync with the remote candidates and
+    /// nominated pairs
+    fn sync_turn_allocations(&mut self, now: Instant) {
+        for turn_allocation in &mut self.turn_server {
+            let Some(relayed) = turn_allocation.relayed_addr() else {
+                continue;
+            };
+
+            for remote_candidate in self.remote_candidates.values() {
+                if remote_candidate.component == turn_allocation.component()
+                    && remote_candidate.addr.is_ipv4() == relayed.is_ipv4()
+                {
+                    turn_allocation.add_permission(remote_candidate.addr.ip(), now);
+                }
+            }
+
+            for pair in &self.pairs {
+                if pair.nominated && self.local_candidates[pair.local].base == relayed {
+                    turn_allocation.add_channel(self.remote_candidates[pair.remote].addr, now);
+                }
+            }
         }
     }
 
@@ -1018,7 +1186,7 @@ impl IceAgent {
                 stun_request,
                 retransmit_at,
                 retransmits,
-                source,
+                source: _,
                 target,
             } = &mut pair.state
             else {
@@ -1037,12 +1205,14 @@ impl IceAgent {
             *retransmits += 1;
             *retransmit_at += self.stun_config.retransmit_delta(*retransmits);
 
-            self.events.push_back(IceEvent::SendData {
-                component: pair.component,
-                data: stun_request.clone(),
-                source: Some(*source),
-                target: *target,
-            });
+            send_data(
+                &self.turn_server,
+                &mut self.events,
+                pair.component,
+                stun_request.clone(),
+                self.local_candidates[pair.local].base,
+                *target,
+            );
         }
     }
 
@@ -1054,6 +1224,11 @@ impl IceAgent {
                 all_completed = false;
             }
         }
+        for turn_allocation in &self.turn_server {
+            if !turn_allocation.is_completed() {
+                all_completed = false;
+            }
+        }
 
         if all_completed && self.gathering_state != IceGatheringState::Complete {
             self.events.push_back(IceEvent::GatheringStateChanged {
@@ -1237,6 +1412,26 @@ impl IceAgent {
         self.events.pop_front()
     }
 
+    /// Move all timers which expired before `now` to `now`, e.g. after the system was suspended
+    ///
+    /// Otherwise the next [`poll`](IceAgent::poll) catches up on every missed retransmission at once, failing
+    /// all transactions in progress.
+    pub fn skip_expired_timers(&mut self, now: Instant) {
+        for stun_server_bindings in &mut self.stun_server {
+            stun_server_bindings.skip_expired_timers(now);
+        }
+
+        for turn_allocation in &mut self.turn_server {
+            turn_allocation.skip_expired_timers(now);
+        }
+
+        for pair in &mut self.pairs {
+            if let CandidatePairState::InProgress { retransmit_at, .. } = &mut pair.state {
+                *retransmit_at = (*retransmit_at).max(now);
+            }
+        }
+    }
+
     /// Returns a duration after which to call [`poll`](IceAgent::poll)
     pub fn timeout(&self, now: Instant) -> Option<Duration> {
         // Next TA trigger
@@ -1251,40 +1446,118 @@ impl IceAgent {
         // Next stun binding refresh/retransmit
         let stun_bindings = self.stun_server.iter().filter_map(|b| b.timeout(now)).min();
 
-        opt_min(Some(ta), stun_bindings)
+        // Next TURN allocation, permission or channel refresh/retransmit
+        let turn_allocations = self.turn_server.iter().filter_map(|a| a.timeout(now)).min();
+
+        opt_min(Some(ta), opt_min(stun_bindings, turn_allocations))
+    }
+
+    /// Prepare data to be sent to `target` on the given component
+    ///
+    /// If the nominated pair for the target uses a relayed candidate, the data is wrapped to be sent through the TURN
+    /// server. Returns the data and the address to send it to.
+    pub fn wrap_relayed(
+        &self,
+        component: Component,
+        data: Vec<u8>,
+        target: SocketAddr,
+    ) -> (Vec<u8>, SocketAddr) {
+        let relayed = self
+            .pairs
+            .iter()
+            .filter(|p| {
+                p.component == component
+                    && p.nominated
+                    && self.remote_candidates[p.remote].addr == target
+            })
+            .map(|p| &self.local_candidates[p.local])
+            .find(|c| c.kind == CandidateKind::Relayed);
+
+        let turn_allocation = relayed.and_then(|c| {
+            self.turn_server
+                .iter()
+                .find(|a| a.relayed_addr() == Some(c.base))
+        });
+
+        match turn_allocation {
+            Some(turn_allocation) => (
+                turn_allocation.wrap(&data, target),
+                turn_allocation.server(),
+            ),
+            None => (data, target),
+        }
+    }
+
+    /// Unwrap data that a TURN server relayed from a peer to one of the relayed candidates
+    ///
+    /// The returned packet contains the data as sent by the peer, with the peer as source and the relayed address as
+    /// destination. Packets which weren't relayed are returned unchanged.
+    pub fn unwrap_relayed(&self, pkt: ReceivedPkt) -> ReceivedPkt {
+        let turn_allocation = self
+            .turn_server
+            .iter()
+            .find(|a| a.server() == pkt.source && a.component() == pkt.component);
+
+        let Some((turn_allocation, relayed)) =
+            turn_allocation.and_then(|a| Some((a, a.relayed_addr()?)))
+        else {
+            return pkt;
+        };
+
+        match turn_allocation.unwrap(&pkt.data) {
+            Some((peer, data)) => ReceivedPkt {
+                data,
+                source: peer,
+                destination: relayed,
+                component: pkt.component,
+            },
+            None => pkt,
+        }
     }
 
     /// Returns all discovered local ice agents, does not include peer-reflexive candidates
     pub fn ice_candidates(&self) -> Vec<IceCandidate> {
         self.local_candidates
             .values()
-            .filter(|c| matches!(c.kind, CandidateKind::Host | CandidateKind::ServerReflexive))
-            .map(|c| {
-                let rel_addr = if c.kind == CandidateKind::ServerReflexive {
-                    Some(c.base)
-                } else {
-                    None
-                };
-
-                IceCandidate {
-                    foundation: c.foundation.clone().into(),
-                    component: c.component as _,
-                    transport: "UDP".into(),
-                    priority: c.priority.into(),
-                    address: UntaggedAddress::IpAddress(c.addr.ip()),
-                    port: c.addr.port(),
-                    typ: match c.kind {
-                        CandidateKind::Host => "host".into(),
-                        CandidateKind::ServerReflexive => "srflx".into(),
-                        _ => unreachable!(),
-                    },
-                    rel_addr: rel_addr.map(|addr| UntaggedAddress::IpAddress(addr.ip())),
-                    rel_port: rel_addr.map(|addr| addr.port()),
-                    unknown: vec![],
-                }
+            .filter(|c| {
+                matches!(
+                    c.kind,
+                    CandidateKind::Host | CandidateKind::ServerReflexive | CandidateKind::Relayed
+                )
             })
+            .map(|c| self.to_ice_candidate(c))
             .collect()
     }
+
+    fn to_ice_candidate(&self, c: &Candidate) -> IceCandidate {
+        let rel_addr = match c.kind {
+            CandidateKind::ServerReflexive => Some(c.base),
+            CandidateKind::Relayed => self
+                .turn_server
+                .iter()
+                .find(|a| a.relayed_addr() == Some(c.base))
+                .and_then(|a| a.mapped_addr()),
+            _ => None,
+        };
+
+        IceCandidate {
+            foundation: c.foundation.clone().into(),
+            component: c.component as _,
+            transport: "UDP".into(),
+            priority: c.priority.into(),
+            address: UntaggedAddress::IpAddress(c.addr.ip()),
+            port: c.addr.port(),
+            typ: match c.kind {
+                CandidateKind::Host => "host".into(),
+                CandidateKind::ServerReflexive => "srflx".into(),
+                CandidateKind::Relayed => "relay".into(),
+                CandidateKind::PeerReflexive => "prflx".into(),
+            },
+            rel_addr: rel_addr.map(|addr| UntaggedAddress::IpAddress(addr.ip())),
+            rel_port: rel_addr.map(|addr| addr.port()),
+            unknown: vec![],
+        }
+    }
 }
 
 fn pair_priority(
@@ -1331,7 +1604,10 @@ impl fmt::Display for DisplayPair<'_> {
                 }
                 CandidateKind::ServerReflexive => {
                     write!(f, "server-reflexive(base:{}, server:{})", c.base, c.addr)
-                } // CandidateKind::Relayed => write!(f, "relayed(base:{}, relay:{})", c.base, c.addr),
+                }
+                CandidateKind::Relayed => {
+                    write!(f, "relayed(base:{}, relay:{})", c.base, c.addr)
+                }
             }
         }
 
@@ -1341,6 +1617,33 @@ impl fmt::Display for DisplayPair<'_> {
     }
 }
 
+/// Send data from a local candidate's base, relaying it through the TURN server if the base is a relayed address
+fn send_data(
+    turn_server: &[TurnAllocation],
+    events: &mut VecDeque<IceEvent>,
+    component: Component,
+    data: Vec<u8>,
+    base: SocketAddr,
+    target: SocketAddr,
+) {
+    let event = match turn_server.iter().find(|a| a.relayed_addr() == Some(base)) {
+        Some(turn_allocation) => IceEvent::SendData {
+            component,
+            data: turn_allocation.wrap(&data, target),
+            source: None,
+            target: turn_allocation.server(),
+        },
+        None => IceEvent::SendData {
+            component,
+            data,
+            source: Some(base.ip()),
+            target,
+        },
+    };
+
+    events.push_back(event);
+}
+
 fn opt_min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
     match (a, b) {
         (None, None) => None,
@@ -1349,3 +1652,83 @@ fn opt_min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
         (Some(a), Some(b)) => Some(min(a, b)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent_to(agent: &mut IceAgent, target: SocketAddr) -> usize {
+        agent.poll(Instant::now());
+
+        std::iter::from_fn(|| agent.pop_event())
+            .filter(|event| matches!(event, IceEvent::SendData { target: t, .. } if *t == target))
+            .count()
+    }
+
+    #[test]
+    fn skip_expired_timers() {
+        let server: SocketAddr = "198.51.100.1:3478".parse().unwrap();
+        let base: SocketAddr = "192.168.0.2:10000".parse().unwrap();
+
+        let sent = |agent: &mut IceAgent, now| {
+            agent.poll(now);
+            std::iter::from_fn(|| agent.pop_event())
+                .filter(
+                    |event| matches!(event, IceEvent::SendData { target, .. } if *target == server),
+                )
+                .count()
+        };
+
+        let mut agent = IceAgent::new_for_offer(IceCredentials::random(), true, true);
+        agent.add_host_addr(Component::Rtp, base);
+        agent.add_stun_server(server);
+
+        let now = Instant::now();
+        assert_eq!(sent(&mut agent, now), 1);
+
+        // A minute later the request is retransmitted once, not once for every missed retransmission
+        let later = now + Duration::from_secs(60);
+        agent.skip_expired_timers(later);
+        assert_eq!(sent(&mut agent, later), 1);
+        assert_eq!(sent(&mut agent, later), 0);
+        assert_ne!(agent.gathering_state(), IceGatheringState::Complete);
+    }
+
+    #[test]
+    fn server_reflexive_addr_completes_stun_binding() {
+        let server: SocketAddr = "198.51.100.1:3478".parse().unwrap();
+        let base: SocketAddr = "192.168.0.2:10000".parse().unwrap();
+        let mapped: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+
+        let mut agent = IceAgent::new_for_offer(IceCredentials::random(), true, true);
+        agent.add_host_addr(Component::Rtp, base);
+        agent.add_stun_server(server);
+        agent.add_server_reflexive_addr(Component::Rtp, server, base, mapped);
+
+        assert_eq!(sent_to(&mut agent, server), 0);
+        assert_eq!(agent.gathering_state(), IceGatheringState::Complete);
+
+        assert!(agent.ice_candidates().iter().any(|candidate| {
+            candidate.typ == "srflx"
+                && candidate.port == mapped.port()
+                && candidate.rel_port == Some(base.port())
+        }));
+    }
+
+    #[test]
+    fn server_reflexive_addr_of_other_server() {
+        let server: SocketAddr = "198.51.100.1:3478".parse().unwrap();
+        let other: SocketAddr = "198.51.100.2:3478".parse().unwrap();
+        let base: SocketAddr = "192.168.0.2:10000".parse().unwrap();
+        let mapped: SocketAddr = "203.0.113.7:40000".parse().unwrap();
+
+        let mut agent = IceAgent::new_for_offer(IceCredentials::random(), true, true);
+        agent.add_host_addr(Component::Rtp, base);
+        agent.add_stun_server(server);
+        agent.add_server_reflexive_addr(Component::Rtp, other, base, mapped);
+
+        // The binding of the agent's own server still has to be created
+        assert_eq!(sent_to(&mut agent, server), 1);
+        assert_ne!(agent.gathering_state(), IceGatheringState::Complete);
+    }
+}
@@ -218,6 +218,18 @@ impl StunServerBinding {
         self.component
     }
 
+    pub(crate) fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Complete the binding using an address previously discovered with this server, the binding is only refreshed
+    pub(crate) fn complete(&mut self, stun_config: &StunConfig, mapped_addr: SocketAddr) {
+        self.state = StunServerBindingState::WaitingForRefresh {
+            refresh_at: Instant::now() + stun_config.binding_refresh_interval,
+        };
+        self.last_mapped_addr = Some(mapped_addr);
+    }
+
     /// Returns if the binding has either been completed or failed to complete
     pub(crate) fn is_completed(&self) -> bool {
         self.last_mapped_addr.is_some() || matches!(self.state, StunServerBindingState::Failed)
@@ -240,6 +252,13 @@ impl StunServerBinding {
         }
     }
 
+    /// Retransmit an expired binding request once at `now` instead of catching up on all missed retransmissions
+    pub(crate) fn skip_expired_timers(&mut self, now: Instant) {
+        if let StunServerBindingState::InProgress { retransmit_at, .. } = &mut self.state {
+            *retransmit_at = (*retransmit_at).max(now);
+        }
+    }
+
     pub(crate) fn poll(
         &mut self,
         now: Instant,
@@ -0,0 +1,1250 @@
+use super::{IceEvent, StunConfig};
+use crate::Component;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use stun_types::{
+    attributes::{
+        long_term_password_md5,
+        turn::{
+            ChannelNumber, Data, Lifetime, RequestedTransport, XorPeerAddress, XorRelayedAddress,
+        },
+        ErrorCode, Fingerprint, MessageIntegrity, MessageIntegrityKey, Nonce, Realm, Username,
+        XorMappedAddress,
+    },
+    Class, Message, MessageBuilder, Method, TransactionId,
+};
+
+/// IANA protocol number of UDP, used in the REQUESTED-TRANSPORT attribute
+const UDP_PROTOCOL_NUMBER: u8 = 17;
+
+/// Permissions expire after 5 minutes (RFC 8656 section 9), refresh them a minute earlier
+const PERMISSION_REFRESH_INTERVAL: Duration = Duration::from_secs(4 * 60);
+/// Channel bindings expire after 10 minutes (RFC 8656 section 12), refresh them a minute earlier
+const CHANNEL_REFRESH_INTERVAL: Duration = Duration::from_secs(9 * 60);
+/// Refresh the allocation this long before its lifetime expires
+const ALLOCATION_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Channel numbers usable by clients (RFC 8656 section 12)
+const FIRST_CHANNEL_NUMBER: u16 = 0x4000;
+const LAST_CHANNEL_NUMBER: u16 = 0x4FFF;
+
+/// Default port of `turn:` URIs
+const DEFAULT_PORT: u16 = 3478;
+/// Default port of `turns:` URIs
+const DEFAULT_SECURE_PORT: u16 = 5349;
+
+/// Address of a TURN server as a `turn:` or `turns:` URI (RFC 7065), e.g. `turn:turn.example.org?transport=udp`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurnUri {
+    /// The URI uses the `turns` scheme, the server must be contacted using TLS
+    pub secure: bool,
+    /// Hostname or IP address of the server
+    pub host: String,
+    /// Port of the URI or the default port of its scheme
+    pub port: u16,
+    /// Transport set in the URI's `transport` parameter
+    pub transport: Option<TurnTransport>,
+}
+
+/// Transport of a [`TurnUri`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnTransport {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug)]
+pub struct InvalidTurnUri(String);
+
+impl fmt::Display for InvalidTurnUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid TURN URI {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidTurnUri {}
+
+impl TurnUri {
+    /// Returns if the ICE agent can use the server, only TURN over UDP is implemented
+    pub fn is_supported(&self) -> bool {
+        !self.secure && self.transport != Some(TurnTransport::Tcp)
+    }
+}
+
+impl FromStr for TurnUri {
+    type Err = InvalidTurnUri;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidTurnUri(s.into());
+
+        let (scheme, rest) = s.split_once(':').ok_or_else(invalid)?;
+
+        let secure = if scheme.eq_ignore_ascii_case("turn") {
+            false
+        } else if scheme.eq_ignore_ascii_case("turns") {
+            true
+        } else {
+            return Err(invalid());
+        };
+
+        let (host_port, transport) = match rest.split_once('?') {
+            None => (rest, None),
+            Some((host_port, query)) => {
+                let transport = query.strip_prefix("transport=").ok_or_else(invalid)?;
+
+                let transport = if transport.eq_ignore_ascii_case("udp") {
+                    TurnTransport::Udp
+                } else if transport.eq_ignore_ascii_case("tcp") {
+                    TurnTransport::Tcp
+                } else {
+                    return Err(invalid());
+                };
+
+                (host_port, Some(transport))
+            }
+        };
+
+        // IPv6 addresses are enclosed in brackets
+        let (host, port) = if let Some(ipv6) = host_port.strip_prefix('[') {
+            let (host, port) = ipv6.split_once(']').ok_or_else(invalid)?;
+            host.parse::<Ipv6Addr>().map_err(|_| invalid())?;
+
+            match port {
+                "" => (host, None),
+                port => (host, Some(port.strip_prefix(':').ok_or_else(invalid)?)),
+            }
+        } else {
+            match host_port.split_once(':') {
+                None => (host_port, None),
+                Some((host, port)) => (host, Some(port)),
+            }
+        };
+
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None if secure => DEFAULT_SECURE_PORT,
+            None => DEFAULT_PORT,
+        };
+
+        Ok(Self {
+            secure,
+            host: host.into(),
+            port,
+            transport,
+        })
+    }
+}
+
+/// Long-term credentials used to authenticate with a TURN server
+#[derive(Debug, Clone)]
+pub struct TurnCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// Values received from the TURN server to compute the message integrity of requests
+struct TurnAuth {
+    realm: String,
+    nonce: Vec<u8>,
+    key: Vec<u8>,
+}
+
+/// Allocation of a relayed transport address on a TURN server
+pub(crate) struct TurnAllocation {
+    server: SocketAddr,
+    component: Component,
+    credentials: TurnCredentials,
+    auth: Option<TurnAuth>,
+    state: TurnAllocationState,
+
+    /// STUN transactions currently in progress
+    transactions: Vec<TurnTransaction>,
+
+    /// Peers which are allowed to send data to the relayed address
+    permissions: Vec<Permission>,
+    /// Channels bound to peers to send data with less overhead
+    channels: Vec<Channel>,
+    next_channel: u16,
+}
+
+enum TurnAllocationState {
+    /// Waiting to be polled to send the allocate request
+    Waiting,
+    /// Allocate request is in progress
+    Allocating,
+    /// Allocation was created
+    Allocated {
+        /// Relayed transport address on the TURN server
+        relayed: SocketAddr,
+        /// Server-reflexive address as seen by the TURN server
+        mapped: Option<SocketAddr>,
+        refresh_at: Instant,
+    },
+    /// Failed to create or refresh the allocation
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TurnRequest {
+    Allocate,
+    Refresh,
+    CreatePermission(IpAddr),
+    ChannelBind { peer: SocketAddr, number: u16 },
+}
+
+struct TurnTransaction {
+    transaction_id: TransactionId,
+    request: TurnRequest,
+    stun_request: Vec<u8>,
+    retransmit_at: Instant,
+    retransmits: u32,
+    /// The request has already been repeated after an authentication error
+    reauthenticated: bool,
+}
+
+struct Permission {
+    ip: IpAddr,
+    refresh_at: Instant,
+}
+
+struct Channel {
+    peer: SocketAddr,
+    number: u16,
+    refresh_at: Instant,
+    /// Set once the server confirmed the channel binding
+    bound: bool,
+}
+
+/// Result of receiving a STUN response for a [`TurnAllocation`]
+pub(crate) enum TurnResponse {
+    /// The allocation has been created
+    Allocated {
+        relayed: SocketAddr,
+        mapped: Option<SocketAddr>,
+    },
+    /// Response was handled and requires no further action
+    Handled,
+}
+
+impl TurnAllocation {
+    pub(crate) fn new(
+        server: SocketAddr,
+        component: Component,
+        credentials: TurnCredentials,
+    ) -> Self {
+        Self {
+            server,
+            component,
+            credentials,
+            auth: None,
+            state: TurnAllocationState::Waiting,
+            transactions: vec![],
+            permissions: vec![],
+            channels: vec![],
+            next_channel: FIRST_CHANNEL_NUMBER,
+        }
+    }
+
+    pub(crate) fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    pub(crate) fn component(&self) -> Component {
+        self.component
+    }
+
+    /// Returns the relayed transport address if the allocation has been created
+    pub(crate) fn relayed_addr(&self) -> Option<SocketAddr> {
+        match &self.state {
+            TurnAllocationState::Allocated { relayed, .. } => Some(*relayed),
+            _ => None,
+        }
+    }
+
+    /// Returns the server-reflexive address reported by the TURN server
+    pub(crate) fn mapped_addr(&self) -> Option<SocketAddr> {
+        match &self.state {
+            TurnAllocationState::Allocated { mapped, .. } => *mapped,
+            _ => None,
+        }
+    }
+
+    /// Returns if the allocation has either been created or failed to be created
+    pub(crate) fn is_completed(&self) -> bool {
+        matches!(
+            self.state,
+            TurnAllocationState::Allocated { .. } | TurnAllocationState::Failed
+        )
+    }
+
+    /// Make sure a permission for the given peer address is installed and kept alive
+    pub(crate) fn add_permission(&mut self, ip: IpAddr, now: Instant) {
+        if !self.permissions.iter().any(|p| p.ip == ip) {
+            self.permissions.push(Permission {
+                ip,
+                refresh_at: now,
+            });
+        }
+    }
+
+    /// Bind a channel to the given peer, which is then used to relay data to and from the peer
+    pub(crate) fn add_channel(&mut self, peer: SocketAddr, now: Instant) {
+        if self.channels.iter().any(|c| c.peer == peer) || self.next_channel > LAST_CHANNEL_NUMBER {
+            return;
+        }
+
+        self.channels.push(Channel {
+            peer,
+            number: self.next_channel,
+            refresh_at: now,
+            bound: false,
+        });
+
+        self.next_channel += 1;
+    }
+
+    pub(crate) fn timeout(&self, now: Instant) -> Option<Duration> {
+        let refreshes = match &self.state {
+            TurnAllocationState::Waiting => return Some(Duration::ZERO),
+            TurnAllocationState::Allocating => None,
+            TurnAllocationState::Allocated { refresh_at, .. } => self
+                .permissions
+                .iter()
+                .map(|p| p.refresh_at)
+                .chain(self.channels.iter().map(|c| c.refresh_at))
+                .chain(Some(*refresh_at))
+                .min(),
+            TurnAllocationState::Failed => return None,
+        };
+
+        self.transactions
+            .iter()
+            .map(|t| t.retransmit_at)
+            .chain(refreshes)
+            .min()
+            .map(|at| at.checked_duration_since(now).unwrap_or(Duration::ZERO))
+    }
+
+    /// Retransmit expired requests once at `now` instead of catching up on all missed retransmissions
+    pub(crate) fn skip_expired_timers(&mut self, now: Instant) {
+        for transaction in &mut self.transactions {
+            transaction.retransmit_at = transaction.retransmit_at.max(now);
+        }
+    }
+
+    pub(crate) fn poll(
+        &mut self,
+        now: Instant,
+        stun_config: &StunConfig,
+        mut on_event: impl FnMut(IceEvent),
+    ) {
+        self.poll_retransmit(now, stun_config, &mut on_event);
+
+        match &mut self.state {
+            TurnAllocationState::Waiting => {
+                self.state = TurnAllocationState::Allocating;
+                self.start_transaction(now, stun_config, TurnRequest::Allocate, false, on_event);
+            }
+            TurnAllocationState::Allocating | TurnAllocationState::Failed => {}
+            TurnAllocationState::Allocated { refresh_at, .. } => {
+                let mut requests = vec![];
+
+                if *refresh_at <= now {
+                    // Postpone the next refresh until this one has been answered
+                    *refresh_at = now + ALLOCATION_REFRESH_MARGIN;
+                    requests.push(TurnRequest::Refresh);
+                }
+
+                for permission in &mut self.permissions {
+                    if permission.refresh_at <= now {
+                        permission.refresh_at = now + PERMISSION_REFRESH_INTERVAL;
+                        requests.push(TurnRequest::CreatePermission(permission.ip));
+                    }
+                }
+
+                for channel in &mut self.channels {
+                    if channel.refresh_at <= now {
+                        channel.refresh_at = now + CHANNEL_REFRESH_INTERVAL;
+                        requests.push(TurnRequest::ChannelBind {
+                            peer: channel.peer,
+                            number: channel.number,
+                        });
+                    }
+                }
+
+                for request in requests {
+                    self.start_transaction(now, stun_config, request, false, &mut on_event);
+                }
+            }
+        }
+    }
+
+    fn poll_retransmit(
+        &mut self,
+        now: Instant,
+        stun_config: &StunConfig,
+        on_event: &mut impl FnMut(IceEvent),
+    ) {
+        let mut failed = vec![];
+
+        for transaction in &mut self.transactions {
+            if transaction.retransmit_at > now {
+                continue;
+            }
+
+            if transaction.retransmits >= stun_config.max_retransmits {
+                failed.push(transaction.transaction_id);
+                continue;
+            }
+
+            transaction.retransmits += 1;
+            transaction.retransmit_at += stun_config.retransmit_delta(transaction.retransmits);
+
+            on_event(IceEvent::SendData {
+                component: self.component,
+                data: transaction.stun_request.clone(),
+                source: None,
+                target: self.server,
+            });
+        }
+
+        for transaction_id in failed {
+            self.fail_transaction(transaction_id);
+        }
+    }
+
+    fn start_transaction(
+        &mut self,
+        now: Instant,
+        stun_config: &StunConfig,
+        request: TurnRequest,
+        reauthenticated: bool,
+        mut on_event: impl FnMut(IceEvent),
+    ) {
+        let transaction_id = TransactionId::random();
+        let stun_request = self.make_request(transaction_id, request);
+
+        on_event(IceEvent::SendData {
+            component: self.component,
+            data: stun_request.clone(),
+            source: None,
+            target: self.server,
+        });
+
+        self.transactions.push(TurnTransaction {
+            transaction_id,
+            request,
+            stun_request,
+            retransmit_at: now + stun_config.retransmit_delta(0),
+            retransmits: 0,
+            reauthenticated,
+        });
+    }
+
+    fn make_request(&self, transaction_id: TransactionId, request: TurnRequest) -> Vec<u8> {
+        let method = match request {
+            TurnRequest::Allocate => Method::Allocate,
+            TurnRequest::Refresh => Method::Refresh,
+            TurnRequest::CreatePermission(..) => Method::CreatePermission,
+            TurnRequest::ChannelBind { .. } => Method::ChannelBind,
+        };
+
+        let mut builder = MessageBuilder::new(Class::Request, method, transaction_id);
+
+        match request {
+            TurnRequest::Allocate => builder.add_attr(RequestedTransport {
+                protocol_number: UDP_PROTOCOL_NUMBER,
+            }),
+            TurnRequest::Refresh => {}
+            TurnRequest::CreatePermission(ip) => {
+                // The port is ignored by the server
+                builder.add_attr(XorPeerAddress(SocketAddr::new(ip, 0)));
+            }
+            TurnRequest::ChannelBind { peer, number } => {
+                builder.add_attr(ChannelNumber(number));
+                builder.add_attr(XorPeerAddress(peer));
+            }
+        }
+
+        if let Some(auth) = &self.auth {
+            builder.add_attr(Username::new(&self.credentials.username));
+            builder.add_attr(Realm::new(&auth.realm));
+            builder.add_attr(Nonce::new(&auth.nonce));
+            builder.add_attr_with(MessageIntegrity, MessageIntegrityKey::new(&auth.key));
+        }
+
+        builder.add_attr(Fingerprint);
+        builder.finish()
+    }
+
+    fn fail_transaction(&mut self, transaction_id: TransactionId) {
+        let Some(index) = self
+            .transactions
+            .iter()
+            .position(|t| t.transaction_id == transaction_id)
+        else {
+            return;
+        };
+
+        let transaction = self.transactions.remove(index);
+
+        match transaction.request {
+            TurnRequest::Allocate | TurnRequest::Refresh => {
+                log::warn!(
+                    "TURN {:?} request to {} failed, allocation is unusable",
+                    transaction.request,
+                    self.server
+                );
+
+                self.state = TurnAllocationState::Failed;
+                self.transactions.clear();
+            }
+            TurnRequest::CreatePermission(..) | TurnRequest::ChannelBind { .. } => {
+                // Will be retried with the next refresh
+                log::debug!(
+                    "TURN {:?} request to {} failed",
+                    transaction.request,
+                    self.server
+                );
+            }
+        }
+    }
+
+    pub(crate) fn wants_stun_response(&self, transaction_id: TransactionId) -> bool {
+        self.transactions
+            .iter()
+            .any(|t| t.transaction_id == transaction_id)
+    }
+
+    /// Receive a STUN success or error response to one of the allocation's requests
+    pub(crate) fn receive_stun_response(
+        &mut self,
+        stun_config: &StunConfig,
+        stun_msg: &mut Message,
+        on_event: impl FnMut(IceEvent),
+    ) -> TurnResponse {
+        let now = Instant::now();
+
+        let Some(index) = self
+            .transactions
+            .iter()
+            .position(|t| t.transaction_id == stun_msg.transaction_id())
+        else {
+            return TurnResponse::Handled;
+        };
+
+        if stun_msg.class() == Class::Error {
+            return self.receive_error_response(now, stun_config, index, stun_msg, on_event);
+        }
+
+        // Responses to authenticated requests must be authenticated as well
+        if let Some(auth) = &self.auth {
+            let passed_integrity_check = stun_msg
+                .attribute_with::<MessageIntegrity>(MessageIntegrityKey::new(&auth.key))
+                .is_some_and(|r| r.is_ok());
+
+            if !passed_integrity_check {
+                log::debug!("TURN response failed the integrity check, discarding");
+                return TurnResponse::Handled;
+            }
+        }
+
+        let transaction = self.transactions.remove(index);
+        let lifetime = stun_msg
+            .attribute::<Lifetime>()
+            .and_then(Result::ok)
+            .map(|lifetime| Duration::from_secs(lifetime.0.into()));
+
+        match transaction.request {
+            TurnRequest::Allocate => {
+                let Some(Ok(relayed)) = stun_msg.attribute::<XorRelayedAddress>() else {
+                    log::warn!("TURN allocate response is missing the XOR-RELAYED-ADDRESS");
+                    self.state = TurnAllocationState::Failed;
+                    return TurnResponse::Handled;
+                };
+                let relayed = relayed.0;
+
+                let mapped = stun_msg
+                    .attribute::<XorMappedAddress>()
+                    .and_then(Result::ok)
+                    .map(|mapped| mapped.0);
+
+                self.state = TurnAllocationState::Allocated {
+                    relayed,
+                    mapped,
+                    refresh_at: refresh_at(now, lifetime),
+                };
+
+                TurnResponse::Allocated { relayed, mapped }
+            }
+            TurnRequest::Refresh => {
+                if let TurnAllocationState::Allocated { refresh_at: at, .. } = &mut self.state {
+                    *at = refresh_at(now, lifetime);
+                }
+
+                TurnResponse::Handled
+            }
+            TurnRequest::CreatePermission(..) => TurnResponse::Handled,
+            TurnRequest::ChannelBind { number, .. } => {
+                if let Some(channel) = self.channels.iter_mut().find(|c| c.number == number) {
+                    channel.bound = true;
+                }
+
+                TurnResponse::Handled
+            }
+        }
+    }
+
+    fn receive_error_response(
+        &mut self,
+        now: Instant,
+        stun_config: &StunConfig,
+        index: usize,
+        stun_msg: &mut Message,
+        on_event: impl FnMut(IceEvent),
+    ) -> TurnResponse {
+        let transaction = self.transactions.remove(index);
+
+        let error_code = stun_msg
+            .attribute::<ErrorCode>()
+            .and_then(Result::ok)
+            .map(|error_code| error_code.number);
+
+        // 401 (Unauthenticated) & 438 (Stale Nonce) must be answered by repeating the request with the provided
+        // realm & nonce, but only once to avoid looping forever with invalid credentials
+        if matches!(error_code, Some(401 | 438)) && !transaction.reauthenticated {
+            let realm = stun_msg
+                .attribute::<Realm>()
+                .and_then(Result::ok)
+                .map(|realm| realm.0.to_owned());
+            let nonce = stun_msg
+                .attribute::<Nonce>()
+                .and_then(Result::ok)
+                .map(|nonce| nonce.0.to_vec());
+
+            if let Some((realm, nonce)) = realm.zip(nonce) {
+                let key = long_term_password_md5(
+                    &self.credentials.username,
+                    &realm,
+                    &self.credentials.password,
+                );
+
+                self.auth = Some(TurnAuth { realm, nonce, key });
+                self.start_transaction(now, stun_config, transaction.request, true, on_event);
+
+                return TurnResponse::Handled;
+            }
+        }
+
+        log::debug!(
+            "TURN {:?} request to {} failed with code={error_code:?}",
+            transaction.request,
+            self.server
+        );
+
+        // Put it back to use the common failure handling
+        let transaction_id = transaction.transaction_id;
+        self.transactions.push(transaction);
+        self.fail_transaction(transaction_id);
+
+        TurnResponse::Handled
+    }
+
+    /// Wrap data to be sent from the relayed address to the given peer
+    pub(crate) fn wrap(&self, data: &[u8], peer: SocketAddr) -> Vec<u8> {
+        if let Some(channel) = self.channels.iter().find(|c| c.bound && c.peer == peer) {
+            // ChannelData message, padding is not required over UDP
+            let mut message = Vec::with_capacity(4 + data.len());
+            message.extend_from_slice(&channel.number.to_be_bytes());
+            message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            message.extend_from_slice(data);
+            return message;
+        }
+
+        let mut builder =
+            MessageBuilder::new(Class::Indication, Method::Send, TransactionId::random());
+        builder.add_attr(XorPeerAddress(peer));
+        builder.add_attr(Data::new(data));
+        builder.finish()
+    }
+
+    /// Unwrap data the TURN server relayed from a peer, either inside a ChannelData message or a Data indication
+    ///
+    /// Returns the peer's address and the data it sent.
+    pub(crate) fn unwrap(&self, data: &[u8]) -> Option<(SocketAddr, Vec<u8>)> {
+        if let [b0 @ 0x40..=0x4F, b1, l0, l1, payload @ ..] = data {
+            let number = u16::from_be_bytes([*b0, *b1]);
+            let len = usize::from(u16::from_be_bytes([*l0, *l1]));
+
+            let channel = self.channels.iter().find(|c| c.number == number)?;
+
+            return Some((channel.peer, payload.get(..len)?.to_vec()));
+        }
+
+        let mut stun_msg = Message::parse(data).ok()?;
+
+        if stun_msg.class() != Class::Indication || stun_msg.method() != Method::Data {
+            return None;
+        }
+
+        let peer = stun_msg.attribute::<XorPeerAddress>()?.ok()?.0;
+        let data = stun_msg.attribute::<Data>()?.ok()?.0.to_vec();
+
+        Some((peer, data))
+    }
+}
+
+fn refresh_at(now: Instant, lifetime: Option<Duration>) -> Instant {
+    // Default lifetime of an allocation is 10 minutes
+    let lifetime = lifetime.unwrap_or(Duration::from_secs(600));
+
+    now + lifetime
+        .saturating_sub(ALLOCATION_REFRESH_MARGIN)
+        .max(ALLOCATION_REFRESH_MARGIN / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials() -> TurnCredentials {
+        TurnCredentials {
+            username: "user".into(),
+            password: "pass".into(),
+        }
+    }
+
+    fn sent_requests(allocation: &mut TurnAllocation, now: Instant) -> Vec<Message> {
+        let mut sent = vec![];
+
+        allocation.poll(now, &StunConfig::new(), |event| {
+            if let IceEvent::SendData { data, .. } = event {
+                sent.push(Message::parse(data).unwrap());
+            }
+        });
+
+        sent
+    }
+
+    #[test]
+    fn allocate_with_authentication() {
+        let server: SocketAddr = "198.51.100.1:3478".parse().unwrap();
+        let relayed: SocketAddr = "198.51.100.1:50000".parse().unwrap();
+        let now = Instant::now();
+
+        let mut allocation = TurnAllocation::new(server, Component::Rtp, credentials());
+
+        let mut requests = sent_requests(&mut allocation, now);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method(), Method::Allocate);
+        assert!(requests[0].attribute::<Username>().is_none());
+
+        // Server requests authentication
+        let mut response =
+            MessageBuilder::new(Class::Error, Method::Allocate, requests[0].transaction_id());
+        response.add_attr(ErrorCode {
+            number: 401,
+            reason: "Unauthenticated",
+        });
+        response.add_attr(Realm::new("example.org"));
+        response.add_attr(Nonce::new(b"nonce"));
+
+        let mut resent = vec![];
+        allocation.receive_stun_response(
+            &StunConfig::new(),
+            &mut Message::parse(response.finish()).unwrap(),
+            |event| resent.push(event),
+        );
+
+        let Some(IceEvent::SendData { data, target, .. }) = resent.pop() else {
+            panic!("expected authenticated allocate request");
+        };
+        assert_eq!(target, server);
+
+        let mut request = Message::parse(data).unwrap();
+        let key = long_term_password_md5("user", "example.org", "pass");
+        assert!(request
+            .attribute_with::<MessageIntegrity>(MessageIntegrityKey::new(&key))
+            .unwrap()
+            .is_ok());
+
+        let mut response =
+            MessageBuilder::new(Class::Success, Method::Allocate, request.transaction_id());
+        response.add_attr(XorRelayedAddress(relayed));
+        response.add_attr(Lifetime(600));
+        response.add_attr_with(MessageIntegrity, MessageIntegrityKey::new(&key));
+
+        let response = allocation.receive_stun_response(
+            &StunConfig::new(),
+            &mut Message::parse(response.finish()).unwrap(),
+            |_| {},
+        );
+
+        assert!(
+            matches!(response, TurnResponse::Allocated { relayed: r, mapped: None } if r == relayed)
+        );
+        assert!(allocation.is_completed());
+        assert_eq!(allocation.relayed_addr(), Some(relayed));
+    }
+
+    #[test]
+    fn wrap_unwrap() {
+        let server: SocketAddr = "198.51.100.1:3478".parse().unwrap();
+        let peer: SocketAddr = "203.0.113.5:6000".parse().unwrap();
+
+        let mut allocation = TurnAllocation::new(server, Component::Rtp, credentials());
+
+        // Send indication without a channel
+        let wrapped = allocation.wrap(b"hello", peer);
+        assert_eq!(allocation.unwrap(&wrapped), None);
+
+        let mut indication =
+            MessageBuilder::new(Class::Indication, Method::Data, TransactionId::random());
+        indication.add_attr(XorPeerAddress(peer));
+        indication.add_attr(Data::new(b"hello"));
+        assert_eq!(
+            allocation.unwrap(&indication.finish()),
+            Some((peer, b"hello".to_vec()))
+        );
+
+        // ChannelData once the channel is bound
+        allocation.add_channel(peer, Instant::now());
+        allocation.channels[0].bound = true;
+
+        let wrapped = allocation.wrap(b"hello", peer);
+        assert_eq!(&wrapped[..4], &[0x40, 0x00, 0x00, 0x05]);
+        assert_eq!(allocation.unwrap(&wrapped), Some((peer, b"hello".to_vec())));
+    }
+
+    const SERVER: &str = "198.51.100.1:3478";
+    const RELAYED: &str = "198.51.100.1:50000";
+
+    fn key() -> Vec<u8> {
+        long_term_password_md5("user", "example.org", "pass")
+    }
+
+    /// Answer a request of the allocation, returns the requests it sent in reaction
+    fn respond(
+        allocation: &mut TurnAllocation,
+        request: &Message,
+        class: Class,
+        build: impl FnOnce(&mut MessageBuilder),
+    ) -> Vec<Message> {
+        let mut response = MessageBuilder::new(class, request.method(), request.transaction_id());
+        build(&mut response);
+
+        if class == Class::Success {
+            response.add_attr_with(MessageIntegrity, MessageIntegrityKey::new(key()));
+        }
+
+        let mut sent = vec![];
+        allocation.receive_stun_response(
+            &StunConfig::new(),
+            &mut Message::parse(response.finish()).unwrap(),
+            |event| {
+                if let IceEvent::SendData { data, .. } = event {
+                    sent.push(Message::parse(data).unwrap());
+                }
+            },
+        );
+
+        sent
+    }
+
+    fn error(number: u32, nonce: &'static [u8]) -> impl FnOnce(&mut MessageBuilder) {
+        move |response| {
+            response.add_attr(ErrorCode { number, reason: "" });
+            response.add_attr(Realm::new("example.org"));
+            response.add_attr(Nonce::new(nonce));
+        }
+    }
+
+    fn single(mut requests: Vec<Message>, method: Method) -> Message {
+        assert_eq!(requests.len(), 1);
+        let request = requests.remove(0);
+        assert_eq!(request.method(), method);
+        request
+    }
+
+    /// Allocation created with a lifetime of 10 minutes
+    fn allocated(now: Instant) -> TurnAllocation {
+        let mut allocation =
+            TurnAllocation::new(SERVER.parse().unwrap(), Component::Rtp, credentials());
+
+        let request = single(sent_requests(&mut allocation, now), Method::Allocate);
+        let request = single(
+            respond(
+                &mut allocation,
+                &request,
+                Class::Error,
+                error(401, b"nonce"),
+            ),
+            Method::Allocate,
+        );
+        respond(&mut allocation, &request, Class::Success, |response| {
+            response.add_attr(XorRelayedAddress(RELAYED.parse().unwrap()));
+            response.add_attr(Lifetime(600));
+        });
+
+        assert_eq!(allocation.relayed_addr(), Some(RELAYED.parse().unwrap()));
+        allocation
+    }
+
+    fn nonce(request: &mut Message) -> Vec<u8> {
+        request.attribute::<Nonce>().unwrap().unwrap().0.to_vec()
+    }
+
+    #[test]
+    fn parse_uri() {
+        let valid = [
+            ("turn:example.org", false, "example.org", 3478, None),
+            ("turns:example.org", true, "example.org", 5349, None),
+            ("TURN:example.org:8000", false, "example.org", 8000, None),
+            (
+                "turn:198.51.100.1?transport=udp",
+                false,
+                "198.51.100.1",
+                3478,
+                Some(TurnTransport::Udp),
+            ),
+            (
+                "turns:[2001:db8::1]:443?transport=tcp",
+                true,
+                "2001:db8::1",
+                443,
+                Some(TurnTransport::Tcp),
+            ),
+            ("turn:[2001:db8::1]", false, "2001:db8::1", 3478, None),
+        ];
+
+        for (input, secure, host, port, transport) in valid {
+            let uri: TurnUri = input.parse().unwrap();
+
+            assert_eq!(
+                uri,
+                TurnUri {
+                    secure,
+                    host: host.into(),
+                    port,
+                    transport
+                },
+                "{input}"
+            );
+        }
+
+        let invalid = [
+            "",
+            "example.org",
+            "stun:example.org",
+            "turn:",
+            "turn::3478",
+            "turn:example.org:",
+            "turn:example.org:70000",
+            "turn:example.org?transport=sctp",
+            "turn:example.org?foo=bar",
+            "turn:[2001:db8::1",
+            "turn:[example.org]",
+            "turn:2001:db8::1",
+        ];
+
+        for input in invalid {
+            assert!(input.parse::<TurnUri>().is_err(), "{input}");
+        }
+
+        assert!("turn:example.org"
+            .parse::<TurnUri>()
+            .unwrap()
+            .is_supported());
+        assert!("turn:example.org?transport=udp"
+            .parse::<TurnUri>()
+            .unwrap()
+            .is_supported());
+        assert!(!"turn:example.org?transport=tcp"
+            .parse::<TurnUri>()
+            .unwrap()
+            .is_supported());
+        assert!(!"turns:example.org"
+            .parse::<TurnUri>()
+            .unwrap()
+            .is_supported());
+    }
+
+    #[test]
+    fn refresh_allocation() {
+        let now = Instant::now();
+        let mut allocation = allocated(now);
+
+        // Refreshed a minute before the lifetime expires
+        assert!(sent_requests(&mut allocation, now + Duration::from_secs(535)).is_empty());
+
+        let refresh_time = now + Duration::from_secs(545);
+        let mut request = single(
+            sent_requests(&mut allocation, refresh_time),
+            Method::Refresh,
+        );
+
+        assert!(request
+            .attribute_with::<MessageIntegrity>(MessageIntegrityKey::new(key()))
+            .unwrap()
+            .is_ok());
+        assert!(sent_requests(&mut allocation, refresh_time).is_empty());
+
+        respond(&mut allocation, &request, Class::Success, |response| {
+            response.add_attr(Lifetime(300));
+        });
+
+        // The lifetime of the refresh response is used
+        assert!(sent_requests(&mut allocation, now + Duration::from_secs(235)).is_empty());
+        single(
+            sent_requests(&mut allocation, now + Duration::from_secs(245)),
+            Method::Refresh,
+        );
+        assert!(allocation.relayed_addr().is_some());
+    }
+
+    #[test]
+    fn refresh_with_stale_nonce() {
+        let now = Instant::now();
+        let mut allocation = allocated(now);
+
+        let request = single(
+            sent_requests(&mut allocation, now + Duration::from_secs(545)),
+            Method::Refresh,
+        );
+
+        // The request is repeated with the new nonce
+        let mut request = single(
+            respond(
+                &mut allocation,
+                &request,
+                Class::Error,
+                error(438, b"fresh"),
+            ),
+            Method::Refresh,
+        );
+        assert_eq!(nonce(&mut request), b"fresh");
+
+        respond(&mut allocation, &request, Class::Success, |response| {
+            response.add_attr(Lifetime(600));
+        });
+        assert!(allocation.relayed_addr().is_some());
+
+        // Later requests use the new nonce as well
+        allocation.add_permission("203.0.113.5".parse().unwrap(), now);
+        let mut request = single(
+            sent_requests(&mut allocation, now),
+            Method::CreatePermission,
+        );
+        assert_eq!(nonce(&mut request), b"fresh");
+    }
+
+    #[test]
+    fn repeated_stale_nonce_fails_refresh() {
+        let now = Instant::now();
+        let mut allocation = allocated(now);
+
+        let request = single(
+            sent_requests(&mut allocation, now + Duration::from_secs(545)),
+            Method::Refresh,
+        );
+        let request = single(
+            respond(
+                &mut allocation,
+                &request,
+                Class::Error,
+                error(438, b"fresh"),
+            ),
+            Method::Refresh,
+        );
+
+        assert!(respond(
+            &mut allocation,
+            &request,
+            Class::Error,
+            error(438, b"again")
+        )
+        .is_empty());
+        assert!(allocation.relayed_addr().is_none());
+        assert!(allocation.is_completed());
+    }
+
+    #[test]
+    fn permission_refresh() {
+        let now = Instant::now();
+        let mut allocation = allocated(now);
+
+        let peer: IpAddr = "203.0.113.5".parse().unwrap();
+        allocation.add_permission(peer, now);
+        // Adding the same peer again has no effect
+        allocation.add_permission(peer, now);
+
+        let mut request = single(
+            sent_requests(&mut allocation, now),
+            Method::CreatePermission,
+        );
+        assert_eq!(
+            request
+                .attribute::<XorPeerAddress>()
+                .unwrap()
+                .unwrap()
+                .0
+                .ip(),
+            peer
+        );
+        respond(&mut allocation, &request, Class::Success, |_| {});
+
+        // Permissions expire after 5 minutes and are refreshed a minute earlier
+        assert!(sent_requests(&mut allocation, now + Duration::from_secs(235)).is_empty());
+        let request = single(
+            sent_requests(&mut allocation, now + Duration::from_secs(240)),
+            Method::CreatePermission,
+        );
+
+        // A failed permission doesn't affect the allocation and is retried with the next refresh
+        respond(&mut allocation, &request, Class::Error, |response| {
+            response.add_attr(ErrorCode {
+                number: 403,
+                reason: "Forbidden",
+            });
+        });
+        assert!(allocation.relayed_addr().is_some());
+
+        single(
+            sent_requests(&mut allocation, now + Duration::from_secs(480)),
+            Method::CreatePermission,
+        );
+    }
+
+    #[test]
+    fn channel_refresh() {
+        let now = Instant::now();
+        let mut allocation = allocated(now);
+
+        let peer: SocketAddr = "203.0.113.5:6000".parse().unwrap();
+        allocation.add_channel(peer, now);
+
+        let mut request = single(sent_requests(&mut allocation, now), Method::ChannelBind);
+        assert_eq!(
+            request.attribute::<ChannelNumber>().unwrap().unwrap().0,
+            FIRST_CHANNEL_NUMBER
+        );
+        assert_eq!(
+            request.attribute::<XorPeerAddress>().unwrap().unwrap().0,
+            peer
+        );
+
+        // ChannelData is only used once the binding was confirmed
+        assert_eq!(allocation.wrap(b"hello", peer)[0], 0x00);
+        respond(&mut allocation, &request, Class::Success, |_| {});
+        assert_eq!(allocation.wrap(b"hello", peer)[0], 0x40);
+
+        // Channels expire after 10 minutes and are refreshed a minute earlier, the allocation is refreshed as well
+        assert!(sent_requests(&mut allocation, now + Duration::from_secs(535)).is_empty());
+        let requests = sent_requests(&mut allocation, now + Duration::from_secs(545));
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].method(), Method::Refresh);
+        assert_eq!(requests[1].method(), Method::ChannelBind);
+    }
+
+    #[test]
+    fn allocation_rejected() {
+        let now = Instant::now();
+
+        let mut allocation =
+            TurnAllocation::new(SERVER.parse().unwrap(), Component::Rtp, credentials());
+        let request = single(sent_requests(&mut allocation, now), Method::Allocate);
+
+        assert!(
+            respond(&mut allocation, &request, Class::Error, |response| {
+                response.add_attr(ErrorCode {
+                    number: 486,
+                    reason: "Allocation Quota Reached",
+                });
+            })
+            .is_empty()
+        );
+
+        assert!(allocation.is_completed());
+        assert!(allocation.relayed_addr().is_none());
+        assert_eq!(allocation.timeout(now), None);
+    }
+
+    #[test]
+    fn allocation_with_invalid_credentials() {
+        let now = Instant::now();
+
+        let mut allocation =
+            TurnAllocation::new(SERVER.parse().unwrap(), Component::Rtp, credentials());
+        let request = single(sent_requests(&mut allocation, now), Method::Allocate);
+        let request = single(
+            respond(
+                &mut allocation,
+                &request,
+                Class::Error,
+                error(401, b"nonce"),
+            ),
+            Method::Allocate,
+        );
+
+        // Authentication is only attempted once
+        assert!(respond(
+            &mut allocation,
+            &request,
+            Class::Error,
+            error(401, b"nonce")
+        )
+        .is_empty());
+        assert!(allocation.is_completed());
+        assert!(allocation.relayed_addr().is_none());
+    }
+
+    #[test]
+    fn allocation_without_relayed_address() {
+        let now = Instant::now();
+
+        let mut allocation =
+            TurnAllocation::new(SERVER.parse().unwrap(), Component::Rtp, credentials());
+        let request = single(sent_requests(&mut allocation, now), Method::Allocate);
+
+        // Unauthenticated success response
+        let mut response =
+            MessageBuilder::new(Class::Success, Method::Allocate, request.transaction_id());
+        response.add_attr(Lifetime(600));
+        allocation.receive_stun_response(
+            &StunConfig::new(),
+            &mut Message::parse(response.finish()).unwrap(),
+            |_| {},
+        );
+
+        assert!(allocation.is_completed());
+        assert!(allocation.relayed_addr().is_none());
+    }
+
+    #[test]
+    fn allocation_timeout() {
+        let mut now = Instant::now();
+        let config = StunConfig::new();
+
+        let mut allocation =
+            TurnAllocation::new(SERVER.parse().unwrap(), Component::Rtp, credentials());
+
+        let mut sent = 0;
+
+        while let Some(timeout) = allocation.timeout(now) {
+            allocation.poll(now, &config, |_| sent += 1);
+            now += timeout;
+        }
+
+        assert_eq!(sent, 1 + config.max_retransmits);
+        assert!(allocation.is_completed());
+        assert!(allocation.relayed_addr().is_none());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::NtpTimestamp;
+use std::time::Duration;
+
+/// Absolute capture time of the media in a RTP packet, transmitted using the
+/// [abs-capture-time](http://www.webrtc.org/experiments/rtp-hdrext/abs-capture-time) header extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AbsCaptureTime {
+    /// NTP timestamp of when the first audio sample or the video frame of the packet was captured, using the
+    /// clock of the capture system
+    pub capture_timestamp: NtpTimestamp,
+
+    /// Estimated offset between the clock of the capture system and the sender's clock as Q32.32 fixed point
+    /// seconds, only set by intermediate systems (e.g. mixers) which are not the capture system
+    pub estimated_capture_clock_offset: Option<i64>,
+}
+
+impl AbsCaptureTime {
+    /// Create the capture time for media that was captured by the sender itself
+    pub fn new(capture_timestamp: NtpTimestamp) -> Self {
+        Self {
+            capture_timestamp,
+            estimated_capture_clock_offset: None,
+        }
+    }
+
+    /// Estimate the time passed since the media was captured
+    ///
+    /// Assumes the clock of the sender is synchronized with the local clock (e.g. using NTP).
+    /// Returns `None` if the capture time lies in the future.
+    pub fn latency(&self, now: NtpTimestamp) -> Option<Duration> {
+        let offset = self.estimated_capture_clock_offset.unwrap_or_default() as f64
+            / f64::from(1u32 << 16)
+            / f64::from(1u32 << 16);
+
+        let latency = (now - self.capture_timestamp).as_seconds_f64() + offset;
+
+        Duration::try_from_secs_f64(latency).ok()
+    }
+
+    pub(crate) fn from_extension_data(data: &[u8]) -> Option<Self> {
+        let capture_timestamp = data.get(..8)?.try_into().ok()?;
+        let estimated_capture_clock_offset = data
+            .get(8..16)
+            .map(|offset| i64::from_be_bytes(offset.try_into().expect("slice has a length of 8")));
+
+        Some(Self {
+            capture_timestamp: NtpTimestamp::from_fixed_u64(u64::from_be_bytes(capture_timestamp)),
+            estimated_capture_clock_offset,
+        })
+    }
+
+    pub(crate) fn to_extension_data(self) -> Vec<u8> {
+        let mut data = self.capture_timestamp.to_fixed_u64().to_be_bytes().to_vec();
+
+        if let Some(offset) = self.estimated_capture_clock_offset {
+            data.extend_from_slice(&offset.to_be_bytes());
+        }
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extension_data() {
+        let capture_timestamp = NtpTimestamp::from_fixed_u64(0x1234_5678_0000_0000);
+
+        let abs_capture_time = AbsCaptureTime::new(capture_timestamp);
+        let data = abs_capture_time.to_extension_data();
+
+        assert_eq!(data.len(), 8);
+        assert_eq!(
+            AbsCaptureTime::from_extension_data(&data),
+            Some(abs_capture_time)
+        );
+
+        let abs_capture_time = AbsCaptureTime {
+            capture_timestamp,
+            estimated_capture_clock_offset: Some(-(1 << 31)),
+        };
+        let data = abs_capture_time.to_extension_data();
+
+        assert_eq!(data.len(), 16);
+        assert_eq!(
+            AbsCaptureTime::from_extension_data(&data),
+            Some(abs_capture_time)
+        );
+
+        assert_eq!(AbsCaptureTime::from_extension_data(&data[..4]), None);
+    }
+
+    #[test]
+    fn latency() {
+        let capture_timestamp = NtpTimestamp::from_fixed_u64(100 << 32);
+        let now = NtpTimestamp::from_fixed_u64(101 << 32);
+
+        let abs_capture_time = AbsCaptureTime::new(capture_timestamp);
+        assert_eq!(abs_capture_time.latency(now), Some(Duration::from_secs(1)));
+
+        // Capture clock is half a second behind the sender's clock
+        let abs_capture_time = AbsCaptureTime {
+            capture_timestamp,
+            estimated_capture_clock_offset: Some(1 << 31),
+        };
+        assert_eq!(
+            abs_capture_time.latency(now),
+            Some(Duration::from_millis(1500))
+        );
+
+        assert_eq!(AbsCaptureTime::new(now).latency(capture_timestamp), None);
+    }
+}
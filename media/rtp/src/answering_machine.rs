@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+/// Length of a single analysis frame
+const FRAME_DURATION_MS: u32 = 20;
+
+/// Verdict of an [`AnsweringMachineDetector`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsweringMachineVerdict {
+    /// The call was answered by a person
+    Human,
+
+    /// The call was answered by an answering machine or voicemail
+    Machine,
+
+    /// No decision could be made
+    Unknown,
+}
+
+/// Answering machine detection (AMD), fed with the decoded downlink audio of an answered call
+pub trait AnsweringMachineDetector: Send + 'static {
+    /// Process decoded 16 bit PCM samples, returns the verdict once a decision has been made
+    ///
+    /// Must not be called anymore after a verdict has been returned.
+    fn process_samples(&mut self, samples: &[i16]) -> Option<AnsweringMachineVerdict>;
+}
+
+/// Configuration of the [`EnergyAnsweringMachineDetector`]
+#[derive(Debug, Clone)]
+pub struct EnergyAmdConfig {
+    /// Minimum mean power of a frame to be considered voice
+    pub voice_threshold: f64,
+
+    /// Maximum silence before the first word, after which the verdict is [`AnsweringMachineVerdict::Unknown`]
+    pub initial_silence: Duration,
+
+    /// Greetings longer than this are considered to be spoken by a machine
+    pub max_greeting: Duration,
+
+    /// Silence after the greeting which ends it, a short greeting followed by it is considered human
+    pub after_greeting_silence: Duration,
+
+    /// Maximum duration of the analysis, after which the verdict is [`AnsweringMachineVerdict::Unknown`]
+    pub total_analysis: Duration,
+}
+
+impl Default for EnergyAmdConfig {
+    fn default() -> Self {
+        Self {
+            // roughly -40 dBov
+            voice_threshold: 1e-4,
+            initial_silence: Duration::from_millis(2500),
+            max_greeting: Duration::from_millis(1500),
+            after_greeting_silence: Duration::from_millis(800),
+            total_analysis: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Simple [`AnsweringMachineDetector`] based on the energy of the audio
+///
+/// People usually answer with a short greeting ("Hello?") and then wait for the caller, while answering machines
+/// play a long uninterrupted greeting. The detector measures the length of the first utterance, pauses shorter
+/// than [`after_greeting_silence`](EnergyAmdConfig::after_greeting_silence) are counted as part of it.
+#[derive(Debug)]
+pub struct EnergyAnsweringMachineDetector {
+    config: EnergyAmdConfig,
+
+    frame_len: usize,
+    frame_energy: f64,
+    frame_samples: usize,
+
+    elapsed: Duration,
+    /// Duration of the greeting since the first voice frame, including short pauses
+    greeting: Option<Duration>,
+    /// Duration of the current silence
+    silence: Duration,
+}
+
+impl EnergyAnsweringMachineDetector {
+    /// Create a new detector for audio with the given sample rate
+    pub fn new(sample_rate: u32, config: EnergyAmdConfig) -> Self {
+        Self {
+            config,
+            frame_len: (sample_rate * FRAME_DURATION_MS / 1000) as usize,
+            frame_energy: 0.0,
+            frame_samples: 0,
+            elapsed: Duration::ZERO,
+            greeting: None,
+            silence: Duration::ZERO,
+        }
+    }
+
+    fn process_frame(&mut self, power: f64) -> Option<AnsweringMachineVerdict> {
+        let frame_duration = Duration::from_millis(FRAME_DURATION_MS.into());
+
+        self.elapsed += frame_duration;
+
+        if power >= self.config.voice_threshold {
+            self.silence = Duration::ZERO;
+
+            let greeting = self.greeting.get_or_insert(Duration::ZERO);
+            *greeting += frame_duration;
+
+            if *greeting > self.config.max_greeting {
+                return Some(AnsweringMachineVerdict::Machine);
+            }
+        } else {
+            self.silence += frame_duration;
+
+            match &mut self.greeting {
+                None if self.silence >= self.config.initial_silence => {
+                    return Some(AnsweringMachineVerdict::Unknown);
+                }
+                None => {}
+                Some(_) if self.silence >= self.config.after_greeting_silence => {
+                    return Some(AnsweringMachineVerdict::Human);
+                }
+                Some(greeting) => *greeting += frame_duration,
+            }
+        }
+
+        if self.elapsed >= self.config.total_analysis {
+            return Some(AnsweringMachineVerdict::Unknown);
+        }
+
+        None
+    }
+}
+
+impl AnsweringMachineDetector for EnergyAnsweringMachineDetector {
+    fn process_samples(&mut self, samples: &[i16]) -> Option<AnsweringMachineVerdict> {
+        for &sample in samples {
+            let sample = f64::from(sample) / f64::from(i16::MAX);
+
+            self.frame_energy += sample * sample;
+            self.frame_samples += 1;
+
+            if self.frame_samples == self.frame_len {
+                let power = self.frame_energy / self.frame_samples as f64;
+
+                self.frame_energy = 0.0;
+                self.frame_samples = 0;
+
+                if let Some(verdict) = self.process_frame(power) {
+                    return Some(verdict);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(duration_ms: u32) -> Vec<i16> {
+        (0..8 * duration_ms)
+            .map(|i| if i % 2 == 0 { 3000 } else { -3000 })
+            .collect()
+    }
+
+    fn silence(duration_ms: u32) -> Vec<i16> {
+        vec![0; 8 * duration_ms as usize]
+    }
+
+    fn detect(samples: &[Vec<i16>]) -> Option<AnsweringMachineVerdict> {
+        let mut detector = EnergyAnsweringMachineDetector::new(8000, EnergyAmdConfig::default());
+
+        samples
+            .iter()
+            .find_map(|samples| detector.process_samples(samples))
+    }
+
+    #[test]
+    fn human() {
+        assert_eq!(
+            detect(&[silence(500), voice(600), silence(1000)]),
+            Some(AnsweringMachineVerdict::Human)
+        );
+
+        // Short pauses in the greeting
+        assert_eq!(
+            detect(&[voice(400), silence(300), voice(400), silence(1000)]),
+            Some(AnsweringMachineVerdict::Human)
+        );
+    }
+
+    #[test]
+    fn machine() {
+        assert_eq!(
+            detect(&[silence(500), voice(3000)]),
+            Some(AnsweringMachineVerdict::Machine)
+        );
+
+        assert_eq!(
+            detect(&[voice(700), silence(300), voice(700), silence(1000)]),
+            Some(AnsweringMachineVerdict::Machine)
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        assert_eq!(
+            detect(&[silence(3000)]),
+            Some(AnsweringMachineVerdict::Unknown)
+        );
+
+        assert_eq!(detect(&[silence(1000), voice(500)]), None);
+    }
+}
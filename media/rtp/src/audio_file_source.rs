@@ -0,0 +1,157 @@
+use crate::{AudioPacketizer, RtpPacket, RtpTimestamp, SequenceNumber, WavAudio, G711};
+use std::time::{Duration, Instant};
+
+/// Plays a WAV file as G.711 encoded RTP packets, paced in real time, e.g. for announcements and IVR prompts
+///
+/// The audio is mixed down, resampled to 8 kHz and encoded when the source is created. Packets are returned by
+/// [`poll`](Self::poll) once they are due, [`timeout`](Self::timeout) returns how long to wait for the next one.
+/// The SSRC is left for the session to set.
+///
+/// ```
+/// # use ezk_rtp::{AudioFileSource, G711, WavAudio};
+/// # use std::time::Instant;
+/// # fn example(audio: WavAudio, send_rtp: impl Fn(ezk_rtp::RtpPacket)) {
+/// let mut source = AudioFileSource::new(&audio, G711::Pcmu, 0);
+///
+/// while let Some(timeout) = source.timeout(Instant::now()) {
+///     std::thread::sleep(timeout);
+///
+///     while let Some(packet) = source.poll(Instant::now()) {
+///         send_rtp(packet);
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AudioFileSource {
+    packetizer: AudioPacketizer,
+    /// When the first packet was returned
+    start: Option<Instant>,
+    packets_sent: u32,
+    finished: bool,
+}
+
+impl AudioFileSource {
+    /// Create a source sending the audio using the given G.711 law and payload type
+    pub fn new(audio: &WavAudio, law: G711, pt: u8) -> Self {
+        let mut encoded = vec![];
+        law.encode(&audio.to_mono(8000), &mut encoded);
+
+        let mut packetizer = AudioPacketizer::g711(pt);
+        packetizer.push(&encoded);
+
+        Self {
+            packetizer,
+            start: None,
+            packets_sent: 0,
+            finished: encoded.is_empty(),
+        }
+    }
+
+    /// Set the sequence number and timestamp of the first packet, see [`AudioPacketizer::with_start`]
+    pub fn with_start(mut self, sequence_number: SequenceNumber, timestamp: RtpTimestamp) -> Self {
+        self.packetizer = self.packetizer.with_start(sequence_number, timestamp);
+        self
+    }
+
+    /// Set the packet time from the negotiated `ptime` and `maxptime` attributes, see
+    /// [`AudioPacketizer::set_ptime`]
+    pub fn with_ptime(mut self, ptime: Option<u32>, maxptime: Option<u32>) -> Self {
+        self.packetizer.set_ptime(ptime, maxptime);
+        self
+    }
+
+    /// All packets of the file have been returned
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Duration until the next packet is due, `None` once the file has been played completely
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        if self.finished {
+            return None;
+        }
+
+        Some(
+            self.next_packet_at()
+                .map_or(Duration::ZERO, |at| at.saturating_duration_since(now)),
+        )
+    }
+
+    /// Returns the next packet if it is due, playback starts with the first call
+    ///
+    /// Must be called until it returns `None`, as multiple packets may be due if it wasn't called in time.
+    pub fn poll(&mut self, now: Instant) -> Option<RtpPacket> {
+        if self.finished {
+            return None;
+        }
+
+        if self.next_packet_at().is_some_and(|at| at > now) {
+            return None;
+        }
+
+        let packet = self
+            .packetizer
+            .pop_packet()
+            .or_else(|| self.packetizer.flush());
+
+        let Some(packet) = packet else {
+            self.finished = true;
+            return None;
+        };
+
+        self.start.get_or_insert(now);
+        self.packets_sent += 1;
+
+        Some(packet)
+    }
+
+    fn next_packet_at(&self) -> Option<Instant> {
+        let ptime = Duration::from_millis(u64::from(self.packetizer.ptime()));
+
+        self.start.map(|start| start + ptime * self.packets_sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paced_playback() {
+        // 50ms of audio at 16 kHz
+        let audio = WavAudio {
+            sample_rate: 16000,
+            channels: 1,
+            samples: vec![0; 800],
+        };
+
+        let mut source = AudioFileSource::new(&audio, G711::Pcma, 8)
+            .with_start(SequenceNumber(10), RtpTimestamp(1000));
+
+        let start = Instant::now();
+        assert_eq!(source.timeout(start), Some(Duration::ZERO));
+
+        let first = source.poll(start).unwrap();
+        assert_eq!(first.payload.len(), 160);
+        assert_eq!(first.timestamp, RtpTimestamp(1000));
+        assert!(first.payload.iter().all(|byte| *byte == 0xD5));
+
+        assert!(source.poll(start).is_none());
+        assert_eq!(source.timeout(start), Some(Duration::from_millis(20)));
+
+        // Both remaining packets are due if polled late
+        let late = start + Duration::from_millis(45);
+
+        let second = source.poll(late).unwrap();
+        assert_eq!(second.sequence_number, SequenceNumber(11));
+        assert_eq!(second.timestamp, RtpTimestamp(1160));
+
+        let last = source.poll(late).unwrap();
+        assert_eq!(last.payload.len(), 80);
+
+        assert!(source.poll(late + Duration::from_secs(1)).is_none());
+        assert!(source.is_finished());
+        assert_eq!(source.timeout(late), None);
+    }
+}
@@ -0,0 +1,201 @@
+/// Lowest level that can be represented in dBov, everything below is considered silence
+const MIN_DBOV: f32 = -127.0;
+
+/// Audio level of the audio data in a RTP packet, transmitted using the client-to-mixer audio level
+/// header extension ([RFC6464](https://www.rfc-editor.org/rfc/rfc6464.html))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioLevel {
+    /// Level in -dBov, ranging from 0 (loudest) to 127 (silence)
+    pub level: u8,
+
+    /// Voice activity flag, set if the sender detected voice in the packet
+    pub voice: bool,
+}
+
+impl AudioLevel {
+    /// Create the audio level from a level in dBov, values are clamped to `-127..=0`
+    pub fn from_dbov(dbov: f32, voice: bool) -> Self {
+        Self {
+            level: (-dbov).clamp(0.0, 127.0) as u8,
+            voice,
+        }
+    }
+
+    /// Level in dBov
+    pub fn dbov(&self) -> f32 {
+        -f32::from(self.level)
+    }
+
+    pub(crate) fn from_extension_data(data: &[u8]) -> Option<Self> {
+        let &[b, ..] = data else {
+            return None;
+        };
+
+        Some(Self {
+            level: b & 0x7F,
+            voice: b & 0x80 != 0,
+        })
+    }
+
+    pub(crate) fn to_extension_data(self) -> [u8; 1] {
+        [(self.level & 0x7F) | if self.voice { 0x80 } else { 0 }]
+    }
+}
+
+/// Levels of an audio stream over a period of time, created by [`AudioLevelMeter::report`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioLevelReport {
+    /// RMS level in dBov
+    pub rms_dbov: f32,
+
+    /// Peak level in dBov
+    pub peak_dbov: f32,
+
+    /// Voice activity was detected
+    pub voice_activity: bool,
+}
+
+/// Lightweight audio level meter with a simple level based voice activity detection
+///
+/// Levels can either be computed from decoded audio samples or taken from the audio level header extension.
+#[derive(Debug, Clone)]
+pub struct AudioLevelMeter {
+    vad_threshold_dbov: f32,
+
+    /// Sum of the squared normalized sample values
+    power_sum: f64,
+    count: u64,
+    peak: f64,
+    voice: bool,
+}
+
+impl Default for AudioLevelMeter {
+    fn default() -> Self {
+        Self::new(-50.0)
+    }
+}
+
+impl AudioLevelMeter {
+    /// Create a new meter which considers every audio louder than `vad_threshold_dbov` as voice activity
+    pub fn new(vad_threshold_dbov: f32) -> Self {
+        Self {
+            vad_threshold_dbov,
+            power_sum: 0.0,
+            count: 0,
+            peak: 0.0,
+            voice: false,
+        }
+    }
+
+    /// Process decoded 16 bit PCM samples
+    pub fn process_samples(&mut self, samples: &[i16]) {
+        for &sample in samples {
+            let sample = f64::from(sample) / f64::from(i16::MAX);
+
+            self.power_sum += sample * sample;
+            self.peak = self.peak.max(sample.abs());
+        }
+
+        self.count += samples.len() as u64;
+    }
+
+    /// Process the audio level of a packet received using the header extension
+    pub fn process_audio_level(&mut self, level: AudioLevel) {
+        let amplitude = 10f64.powf(f64::from(level.dbov()) / 20.0);
+
+        self.power_sum += amplitude * amplitude;
+        self.peak = self.peak.max(amplitude);
+        self.count += 1;
+        self.voice |= level.voice;
+    }
+
+    /// Returns the levels of everything processed since the last report and resets the meter
+    ///
+    /// Returns `None` if nothing was processed.
+    pub fn report(&mut self) -> Option<AudioLevelReport> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rms = (self.power_sum / self.count as f64).sqrt();
+
+        let rms_dbov = to_dbov(rms);
+        let peak_dbov = to_dbov(self.peak);
+
+        let report = AudioLevelReport {
+            rms_dbov,
+            peak_dbov,
+            voice_activity: self.voice || rms_dbov > self.vad_threshold_dbov,
+        };
+
+        self.power_sum = 0.0;
+        self.count = 0;
+        self.peak = 0.0;
+        self.voice = false;
+
+        Some(report)
+    }
+}
+
+fn to_dbov(amplitude: f64) -> f32 {
+    if amplitude <= 0.0 {
+        return MIN_DBOV;
+    }
+
+    ((20.0 * amplitude.log10()) as f32).clamp(MIN_DBOV, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extension_data() {
+        let level = AudioLevel {
+            level: 42,
+            voice: true,
+        };
+
+        assert_eq!(level.to_extension_data(), [0x80 | 42]);
+        assert_eq!(AudioLevel::from_extension_data(&[0x80 | 42]), Some(level));
+        assert_eq!(AudioLevel::from_extension_data(&[]), None);
+    }
+
+    #[test]
+    fn meter_samples() {
+        let mut meter = AudioLevelMeter::default();
+
+        meter.process_samples(&[i16::MAX, i16::MIN + 1, i16::MAX, i16::MIN + 1]);
+
+        let report = meter.report().unwrap();
+        assert!(report.rms_dbov > -0.1);
+        assert!(report.peak_dbov > -0.1);
+        assert!(report.voice_activity);
+
+        meter.process_samples(&[0; 160]);
+
+        let report = meter.report().unwrap();
+        assert_eq!(report.rms_dbov, MIN_DBOV);
+        assert!(!report.voice_activity);
+
+        assert!(meter.report().is_none());
+    }
+
+    #[test]
+    fn meter_audio_levels() {
+        let mut meter = AudioLevelMeter::default();
+
+        meter.process_audio_level(AudioLevel::from_dbov(-20.0, false));
+        meter.process_audio_level(AudioLevel::from_dbov(-20.0, false));
+
+        let report = meter.report().unwrap();
+        assert!((report.rms_dbov + 20.0).abs() < 0.01);
+        assert!((report.peak_dbov + 20.0).abs() < 0.01);
+        assert!(report.voice_activity);
+
+        meter.process_audio_level(AudioLevel::from_dbov(-90.0, true));
+
+        let report = meter.report().unwrap();
+        assert!(report.voice_activity);
+    }
+}
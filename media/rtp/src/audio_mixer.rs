@@ -0,0 +1,175 @@
+/// State of a single participant of the [`AudioMixer`]
+#[derive(Debug, Clone, Copy)]
+struct Participant<K> {
+    key: K,
+    /// The participant's audio is not heard by the others
+    muted: bool,
+    /// The participant neither hears nor is heard by the others
+    on_hold: bool,
+}
+
+/// Mixes the decoded audio of multiple participants into a conference
+///
+/// Every participant receives the sum of the audio of all other participants (N-1 mixing), so nobody hears
+/// their own voice. E.g. a local three-way conference consists of the local party (microphone & speaker) and the
+/// two remote calls.
+///
+/// All participants' audio must use the same sample rate.
+#[derive(Debug, Clone)]
+pub struct AudioMixer<K> {
+    participants: Vec<Participant<K>>,
+}
+
+impl<K> Default for AudioMixer<K> {
+    fn default() -> Self {
+        Self {
+            participants: vec![],
+        }
+    }
+}
+
+impl<K: Copy + PartialEq> AudioMixer<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a participant, does nothing if it already exists
+    pub fn add(&mut self, key: K) {
+        if !self.participants.iter().any(|p| p.key == key) {
+            self.participants.push(Participant {
+                key,
+                muted: false,
+                on_hold: false,
+            });
+        }
+    }
+
+    /// Remove a participant
+    pub fn remove(&mut self, key: K) {
+        self.participants.retain(|p| p.key != key);
+    }
+
+    /// Mute or unmute a participant, muted participants still hear the others
+    pub fn set_muted(&mut self, key: K, muted: bool) {
+        if let Some(participant) = self.participants.iter_mut().find(|p| p.key == key) {
+            participant.muted = muted;
+        }
+    }
+
+    /// Put a participant on hold or resume it, participants on hold are excluded from the mix entirely
+    pub fn set_on_hold(&mut self, key: K, on_hold: bool) {
+        if let Some(participant) = self.participants.iter_mut().find(|p| p.key == key) {
+            participant.on_hold = on_hold;
+        }
+    }
+
+    /// Mix a frame of audio
+    ///
+    /// Takes a frame of samples of each participant, missing participants or shorter frames are treated as
+    /// silence. Returns the frame to play out to each participant which is not on hold, with the length of the
+    /// longest input frame.
+    pub fn mix(&self, inputs: &[(K, &[i16])]) -> Vec<(K, Vec<i16>)> {
+        let len = inputs
+            .iter()
+            .map(|(_, samples)| samples.len())
+            .max()
+            .unwrap_or(0);
+
+        let audible = |key: K| {
+            self.participants
+                .iter()
+                .any(|p| p.key == key && !p.muted && !p.on_hold)
+        };
+
+        // Sum up all audible participants, to subtract each participant's own audio from it later
+        let mut sum = vec![0i32; len];
+        for (key, samples) in inputs {
+            if audible(*key) {
+                for (sum, sample) in sum.iter_mut().zip(samples.iter()) {
+                    *sum += i32::from(*sample);
+                }
+            }
+        }
+
+        self.participants
+            .iter()
+            .filter(|participant| !participant.on_hold)
+            .map(|participant| {
+                let own = inputs
+                    .iter()
+                    .find(|(key, _)| *key == participant.key && audible(*key))
+                    .map(|(_, samples)| *samples)
+                    .unwrap_or_default();
+
+                let frame = sum
+                    .iter()
+                    .enumerate()
+                    .map(|(i, sum)| {
+                        let own = own.get(i).copied().map_or(0, i32::from);
+
+                        (sum - own).clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16
+                    })
+                    .collect();
+
+                (participant.key, frame)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(outputs: &[(&'static str, Vec<i16>)], key: &str) -> Vec<i16> {
+        outputs.iter().find(|(k, _)| *k == key).unwrap().1.clone()
+    }
+
+    #[test]
+    fn three_way() {
+        let mut mixer = AudioMixer::new();
+        mixer.add("local");
+        mixer.add("a");
+        mixer.add("b");
+
+        let outputs = mixer.mix(&[("local", &[1, 1]), ("a", &[10, 10]), ("b", &[100])]);
+
+        assert_eq!(output(&outputs, "local"), [110, 10]);
+        assert_eq!(output(&outputs, "a"), [101, 1]);
+        assert_eq!(output(&outputs, "b"), [11, 11]);
+    }
+
+    #[test]
+    fn mute_and_hold() {
+        let mut mixer = AudioMixer::new();
+        mixer.add("local");
+        mixer.add("a");
+        mixer.add("b");
+
+        mixer.set_muted("a", true);
+        mixer.set_on_hold("b", true);
+
+        let outputs = mixer.mix(&[("local", &[1]), ("a", &[10]), ("b", &[100])]);
+
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(output(&outputs, "local"), [0]);
+        assert_eq!(output(&outputs, "a"), [1]);
+
+        mixer.set_on_hold("b", false);
+
+        let outputs = mixer.mix(&[("local", &[1]), ("a", &[10]), ("b", &[100])]);
+        assert_eq!(output(&outputs, "b"), [1]);
+    }
+
+    #[test]
+    fn clipping() {
+        let mut mixer = AudioMixer::new();
+        mixer.add(1);
+        mixer.add(2);
+        mixer.add(3);
+
+        let outputs = mixer.mix(&[(1, &[i16::MAX]), (2, &[i16::MAX])]);
+
+        assert_eq!(outputs[2], (3, vec![i16::MAX]));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{RtpExtensions, RtpPacket, RtpTimestamp, SequenceNumber, Ssrc};
+use bytes::BytesMut;
+
+/// Packet time used if none was negotiated (RFC 3551 section 4.5)
+const DEFAULT_PTIME_MS: u32 = 20;
+
+/// Packetizes the encoded audio of sample based codecs like G.711 and G.722 into RTP packets of a fixed duration
+///
+/// The encoded audio is pushed in chunks of any size, every full packet time of audio is returned as RTP packet
+/// with consecutive sequence numbers and timestamps. The SSRC is left for the session to set.
+///
+/// G.722 uses an RTP clock rate of 8000 Hz despite sampling at 16 kHz (RFC 3551 section 4.5.2), so both codecs
+/// produce one byte per clock tick.
+#[derive(Debug)]
+pub struct AudioPacketizer {
+    pt: u8,
+    clock_rate: u32,
+    /// Encoded bytes per clock tick
+    bytes_per_tick: u32,
+    ptime_ms: u32,
+
+    buffer: BytesMut,
+    sequence_number: SequenceNumber,
+    timestamp: RtpTimestamp,
+    /// Set the marker bit on the next packet, as it starts a talkspurt
+    marker: bool,
+}
+
+impl AudioPacketizer {
+    /// Create a packetizer for a codec producing `bytes_per_tick` encoded bytes per tick of the RTP clock
+    pub fn new(pt: u8, clock_rate: u32, bytes_per_tick: u32) -> Self {
+        Self {
+            pt,
+            clock_rate,
+            bytes_per_tick: bytes_per_tick.max(1),
+            ptime_ms: DEFAULT_PTIME_MS,
+            buffer: BytesMut::new(),
+            sequence_number: SequenceNumber(0),
+            timestamp: RtpTimestamp(0),
+            marker: true,
+        }
+    }
+
+    /// Create a packetizer for G.711 (PCMU or PCMA) using the given payload type
+    pub fn g711(pt: u8) -> Self {
+        Self::new(pt, 8000, 1)
+    }
+
+    /// Create a packetizer for G.722 using the given payload type
+    pub fn g722(pt: u8) -> Self {
+        Self::new(pt, 8000, 1)
+    }
+
+    /// Set the sequence number and timestamp of the first packet, which should be random (RFC 3550 section 5.1)
+    pub fn with_start(mut self, sequence_number: SequenceNumber, timestamp: RtpTimestamp) -> Self {
+        self.sequence_number = sequence_number;
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Set the packet time from the negotiated `ptime` and `maxptime` attributes, in milliseconds
+    ///
+    /// Uses 20ms if no `ptime` is given, the packet time never exceeds `maxptime`.
+    pub fn set_ptime(&mut self, ptime: Option<u32>, maxptime: Option<u32>) {
+        let ptime = ptime.unwrap_or(DEFAULT_PTIME_MS);
+
+        self.ptime_ms = maxptime
+            .map_or(ptime, |maxptime| ptime.min(maxptime))
+            .max(1);
+    }
+
+    /// Packet time in milliseconds
+    pub fn ptime(&self) -> u32 {
+        self.ptime_ms
+    }
+
+    /// Number of RTP clock ticks covered by a single packet
+    pub fn ticks_per_packet(&self) -> u32 {
+        self.clock_rate * self.ptime_ms / 1000
+    }
+
+    /// Queue encoded audio, following directly after the previously pushed audio
+    pub fn push(&mut self, encoded: &[u8]) {
+        self.buffer.extend_from_slice(encoded);
+    }
+
+    /// Returns the next full packet of the queued audio
+    pub fn pop_packet(&mut self) -> Option<RtpPacket> {
+        let packet_len = (self.ticks_per_packet() * self.bytes_per_tick) as usize;
+
+        if packet_len == 0 || self.buffer.len() < packet_len {
+            return None;
+        }
+
+        Some(self.make_packet(packet_len))
+    }
+
+    /// Returns the remaining queued audio as a shorter packet, e.g. at the end of a talkspurt
+    pub fn flush(&mut self) -> Option<RtpPacket> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let len = self.buffer.len() - self.buffer.len() % self.bytes_per_tick as usize;
+
+        if len == 0 {
+            self.buffer.clear();
+            return None;
+        }
+
+        let packet = self.make_packet(len);
+        self.buffer.clear();
+
+        Some(packet)
+    }
+
+    /// Advance the timestamp for a period of `ticks` RTP clock ticks without sending audio (e.g. silence
+    /// suppression), the next packet starts a new talkspurt with the marker bit set
+    ///
+    /// Queued audio which does not fill a whole packet is dropped.
+    pub fn skip(&mut self, ticks: u32) {
+        let queued_ticks = (self.buffer.len() / self.bytes_per_tick as usize) as u32;
+
+        self.buffer.clear();
+        self.timestamp = RtpTimestamp(self.timestamp.0.wrapping_add(queued_ticks + ticks));
+        self.marker = true;
+    }
+
+    fn make_packet(&mut self, len: usize) -> RtpPacket {
+        let payload = self.buffer.split_to(len).freeze();
+        let ticks = len as u32 / self.bytes_per_tick;
+
+        let packet = RtpPacket {
+            pt: self.pt,
+            marker: self.marker,
+            sequence_number: self.sequence_number,
+            ssrc: Ssrc(0),
+            timestamp: self.timestamp,
+            extensions: RtpExtensions::default(),
+            payload,
+            padding: None,
+        };
+
+        self.marker = false;
+        self.sequence_number = SequenceNumber(self.sequence_number.0.wrapping_add(1));
+        self.timestamp = RtpTimestamp(self.timestamp.0.wrapping_add(ticks));
+
+        packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packetize() {
+        let mut packetizer = AudioPacketizer::g711(0)
+            .with_start(SequenceNumber(u16::MAX), RtpTimestamp(u32::MAX - 100));
+
+        packetizer.push(&[0xFF; 100]);
+        assert!(packetizer.pop_packet().is_none());
+
+        packetizer.push(&[0xFF; 300]);
+
+        let first = packetizer.pop_packet().unwrap();
+        assert_eq!(first.payload.len(), 160);
+        assert_eq!(first.sequence_number, SequenceNumber(u16::MAX));
+        assert_eq!(first.timestamp, RtpTimestamp(u32::MAX - 100));
+        assert!(first.marker);
+
+        let second = packetizer.pop_packet().unwrap();
+        assert_eq!(second.sequence_number, SequenceNumber(0));
+        assert_eq!(second.timestamp, RtpTimestamp(59));
+        assert!(!second.marker);
+
+        assert!(packetizer.pop_packet().is_none());
+
+        let rest = packetizer.flush().unwrap();
+        assert_eq!(rest.payload.len(), 80);
+        assert_eq!(rest.timestamp, RtpTimestamp(219));
+    }
+
+    #[test]
+    fn ptime() {
+        let mut packetizer = AudioPacketizer::g722(9);
+
+        packetizer.set_ptime(Some(30), None);
+        assert_eq!(packetizer.ticks_per_packet(), 240);
+
+        packetizer.set_ptime(Some(60), Some(40));
+        assert_eq!(packetizer.ptime(), 40);
+
+        packetizer.set_ptime(None, None);
+        assert_eq!(packetizer.ptime(), 20);
+    }
+
+    #[test]
+    fn skip_starts_talkspurt() {
+        let mut packetizer = AudioPacketizer::g711(8);
+
+        packetizer.push(&[0xD5; 200]);
+        assert!(packetizer.pop_packet().unwrap().marker);
+
+        // 40 queued bytes are dropped together with 800 ticks of silence
+        packetizer.skip(800);
+
+        packetizer.push(&[0xD5; 160]);
+
+        let packet = packetizer.pop_packet().unwrap();
+        assert!(packet.marker);
+        assert_eq!(packet.sequence_number, SequenceNumber(1));
+        assert_eq!(packet.timestamp, RtpTimestamp(160 + 40 + 800));
+    }
+}
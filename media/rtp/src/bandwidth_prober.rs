@@ -0,0 +1,320 @@
+use crate::{RtpExtensions, RtpPacket, RtpTimestamp, SequenceNumber, Ssrc};
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+
+/// Estimated size of the RTP, UDP and IP headers of a probe packet
+const PACKET_OVERHEAD: u32 = 12 + 8 + 20;
+
+/// Minimum number of packets sent for each probed bitrate
+const MIN_CLUSTER_PACKETS: u32 = 5;
+
+/// Configuration of the [`BandwidthProber`]
+#[derive(Debug, Clone)]
+pub struct ProbeConfig {
+    /// Bitrates to probe in bits per second, must be sorted in increasing order
+    pub bitrates: Vec<u32>,
+
+    /// How long each bitrate is probed
+    pub cluster_duration: Duration,
+
+    /// Number of padding bytes in each probe packet, must not be 0
+    pub padding_size: u8,
+
+    /// Maximum fraction of lost packets for a probed bitrate to be considered sustainable
+    pub max_loss: f32,
+
+    /// How long to wait for a receiver report after all probes have been sent
+    pub report_timeout: Duration,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            bitrates: vec![300_000, 1_000_000, 2_500_000],
+            cluster_duration: Duration::from_millis(100),
+            padding_size: 255,
+            max_loss: 0.1,
+            report_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Startup bandwidth estimation by sending clusters of padding-only RTP packets at increasing bitrates
+///
+/// The probes are sent using a dedicated SSRC, so they don't interfere with the sequence numbers of the media.
+/// After all probes have been sent, the loss reported by the receiver in the RTCP report block for that SSRC
+/// is used to estimate the available bandwidth. Losses are attributed to the highest bitrates first.
+#[derive(Debug)]
+pub struct BandwidthProber {
+    ssrc: Ssrc,
+    pt: u8,
+    config: ProbeConfig,
+    packet_size: u32,
+
+    /// Probed bitrates and the number of packets sent for each
+    clusters: Vec<(u32, u32)>,
+    sequence_number: u16,
+
+    state: State,
+    estimate: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Probing {
+        cluster: usize,
+        sent: u32,
+        next_send: Instant,
+    },
+    WaitingForReport {
+        deadline: Instant,
+    },
+    Finished,
+}
+
+impl BandwidthProber {
+    /// Create a new prober which sends its packets using the given SSRC and payload type
+    pub fn new(ssrc: Ssrc, pt: u8, config: ProbeConfig) -> Self {
+        let packet_size = u32::from(config.padding_size) + PACKET_OVERHEAD;
+
+        let clusters = config
+            .bitrates
+            .iter()
+            .map(|&bitrate| {
+                let bytes = f64::from(bitrate) / 8.0 * config.cluster_duration.as_secs_f64();
+                let packets = (bytes / f64::from(packet_size)).ceil() as u32;
+
+                (bitrate, packets.max(MIN_CLUSTER_PACKETS))
+            })
+            .collect();
+
+        Self {
+            ssrc,
+            pt,
+            config,
+            packet_size,
+            clusters,
+            sequence_number: 0,
+            state: State::Idle,
+            estimate: None,
+        }
+    }
+
+    /// SSRC used to send the probe packets
+    pub fn ssrc(&self) -> Ssrc {
+        self.ssrc
+    }
+
+    /// Start sending probes, does nothing if probing was already started
+    pub fn start(&mut self, now: Instant) {
+        if self.state != State::Idle {
+            return;
+        }
+
+        self.state = if self.clusters.is_empty() {
+            State::Finished
+        } else {
+            State::Probing {
+                cluster: 0,
+                sent: 0,
+                next_send: now,
+            }
+        };
+    }
+
+    /// Returns if probing was started
+    pub fn is_started(&self) -> bool {
+        self.state != State::Idle
+    }
+
+    /// Returns if probing has finished, either with or without an [`estimate`](Self::estimate)
+    pub fn is_finished(&self) -> bool {
+        self.state == State::Finished
+    }
+
+    /// Estimated available bandwidth in bits per second, available once probing has finished
+    pub fn estimate(&self) -> Option<u32> {
+        self.estimate
+    }
+
+    /// Returns the duration until [`poll`](Self::poll) must be called
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        match self.state {
+            State::Probing { next_send, .. } => {
+                Some(next_send.checked_duration_since(now).unwrap_or_default())
+            }
+            State::WaitingForReport { deadline } => {
+                Some(deadline.checked_duration_since(now).unwrap_or_default())
+            }
+            State::Idle | State::Finished => None,
+        }
+    }
+
+    /// Returns the next probe packet to send, must be called until it returns `None`
+    pub fn poll(&mut self, now: Instant) -> Option<RtpPacket> {
+        match self.state {
+            State::Probing {
+                cluster,
+                sent,
+                next_send,
+            } if next_send <= now => {
+                let (bitrate, packets) = self.clusters[cluster];
+
+                let interval =
+                    Duration::from_secs_f64(f64::from(self.packet_size * 8) / f64::from(bitrate));
+
+                self.state = if sent + 1 < packets {
+                    State::Probing {
+                        cluster,
+                        sent: sent + 1,
+                        next_send: next_send + interval,
+                    }
+                } else if cluster + 1 < self.clusters.len() {
+                    State::Probing {
+                        cluster: cluster + 1,
+                        sent: 0,
+                        next_send: next_send + interval,
+                    }
+                } else {
+                    State::WaitingForReport {
+                        deadline: now + self.config.report_timeout,
+                    }
+                };
+
+                Some(self.make_packet())
+            }
+            State::WaitingForReport { deadline } if deadline <= now => {
+                // No receiver report received for the probes
+                self.state = State::Finished;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Handle the values of a RTCP report block received for the [`ssrc`](Self::ssrc) of the prober
+    pub fn receive_report(&mut self, extended_sequence_number: u32, cumulative_lost: u32) {
+        if !matches!(self.state, State::WaitingForReport { .. }) {
+            return;
+        }
+
+        // Ignore reports which do not yet cover all probes
+        let last_sequence_number = u32::from(self.sequence_number.wrapping_sub(1));
+        if extended_sequence_number & u32::from(u16::MAX) != last_sequence_number {
+            return;
+        }
+
+        self.estimate = estimate(&self.clusters, cumulative_lost, self.config.max_loss);
+        self.state = State::Finished;
+    }
+
+    fn make_packet(&mut self) -> RtpPacket {
+        let sequence_number = self.sequence_number;
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+
+        RtpPacket {
+            pt: self.pt,
+            marker: false,
+            sequence_number: SequenceNumber(sequence_number),
+            ssrc: self.ssrc,
+            // Increase the timestamp with every packet, so receivers don't treat the probes as a single frame
+            timestamp: RtpTimestamp(u32::from(sequence_number)),
+            extensions: RtpExtensions::default(),
+            payload: Bytes::new(),
+            padding: Some(self.config.padding_size),
+        }
+    }
+}
+
+fn estimate(clusters: &[(u32, u32)], lost: u32, max_loss: f32) -> Option<u32> {
+    let mut remaining_lost = lost;
+
+    for &(bitrate, packets) in clusters.iter().rev() {
+        let lost = remaining_lost.min(packets);
+        remaining_lost -= lost;
+
+        let loss = f64::from(lost) / f64::from(packets);
+
+        if loss <= f64::from(max_loss) {
+            return Some((f64::from(bitrate) * (1.0 - loss)) as u32);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe_all(prober: &mut BandwidthProber, start: Instant) -> Vec<RtpPacket> {
+        let mut now = start;
+        let mut packets = vec![];
+
+        prober.start(now);
+
+        while let Some(timeout) = prober.timeout(now) {
+            if matches!(prober.state, State::WaitingForReport { .. }) {
+                break;
+            }
+
+            now += timeout;
+
+            while let Some(packet) = prober.poll(now) {
+                packets.push(packet);
+            }
+        }
+
+        packets
+    }
+
+    #[test]
+    fn probe_clusters() {
+        let mut prober = BandwidthProber::new(Ssrc(1), 96, ProbeConfig::default());
+        let start = Instant::now();
+
+        assert!(prober.poll(start).is_none());
+
+        let packets = probe_all(&mut prober, start);
+
+        // 100ms of 300kbit/s, 1Mbit/s and 2.5Mbit/s using 295 byte packets
+        assert_eq!(packets.len(), 13 + 43 + 106);
+        assert!(packets
+            .iter()
+            .enumerate()
+            .all(|(i, p)| p.sequence_number.0 == i as u16 && p.padding == Some(255)));
+
+        assert!(!prober.is_finished());
+
+        // Report which doesn't cover all probes yet
+        prober.receive_report(100, 0);
+        assert!(!prober.is_finished());
+
+        prober.receive_report(161, 0);
+        assert!(prober.is_finished());
+        assert_eq!(prober.estimate(), Some(2_500_000));
+    }
+
+    #[test]
+    fn estimate_with_loss() {
+        let clusters = [(300_000, 13), (1_000_000, 43), (2_500_000, 106)];
+
+        assert_eq!(estimate(&clusters, 0, 0.1), Some(2_500_000));
+        assert_eq!(estimate(&clusters, 106, 0.1), Some(1_000_000));
+        assert_eq!(estimate(&clusters, 110, 0.1), Some(906_976));
+        assert_eq!(estimate(&clusters, 162, 0.1), None);
+    }
+
+    #[test]
+    fn report_timeout() {
+        let mut prober = BandwidthProber::new(Ssrc(1), 96, ProbeConfig::default());
+        let start = Instant::now();
+
+        probe_all(&mut prober, start);
+
+        assert!(prober.poll(start + Duration::from_secs(11)).is_none());
+        assert!(prober.is_finished());
+        assert_eq!(prober.estimate(), None);
+    }
+}
@@ -0,0 +1,172 @@
+/// Priority and bitrate limits of a single sender, used by the [`BitrateAllocator`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitrateAllocation {
+    /// Relative priority, the bitrate above the minimum is split proportionally to it
+    pub priority: f32,
+
+    /// Minimum bitrate in bits per second, the sender gets nothing if this cannot be satisfied
+    pub min_bitrate: u32,
+
+    /// Maximum bitrate in bits per second
+    pub max_bitrate: u32,
+}
+
+impl BitrateAllocation {
+    pub fn new(priority: f32, min_bitrate: u32, max_bitrate: u32) -> Self {
+        Self {
+            priority,
+            min_bitrate,
+            max_bitrate: max_bitrate.max(min_bitrate),
+        }
+    }
+}
+
+/// Splits a single bandwidth estimate across multiple senders sharing a transport
+///
+/// Minimum bitrates are satisfied first in order of priority, senders whose minimum doesn't fit anymore get a
+/// bitrate of 0. The rest is split proportionally to the priorities, without exceeding the maximum bitrates.
+#[derive(Debug, Clone)]
+pub struct BitrateAllocator<K> {
+    senders: Vec<(K, BitrateAllocation)>,
+}
+
+impl<K> Default for BitrateAllocator<K> {
+    fn default() -> Self {
+        Self { senders: vec![] }
+    }
+}
+
+impl<K: Copy + PartialEq> BitrateAllocator<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sender or update its allocation
+    pub fn set(&mut self, key: K, allocation: BitrateAllocation) {
+        if let Some((_, existing)) = self.senders.iter_mut().find(|(k, _)| *k == key) {
+            *existing = allocation;
+        } else {
+            self.senders.push((key, allocation));
+        }
+    }
+
+    /// Remove a sender
+    pub fn remove(&mut self, key: K) {
+        self.senders.retain(|(k, _)| *k != key);
+    }
+
+    /// Split the given bitrate (in bits per second) across all senders
+    pub fn allocate(&self, bitrate: u32) -> Vec<(K, u32)> {
+        let mut allocated = vec![0u32; self.senders.len()];
+        let mut active = vec![false; self.senders.len()];
+        let mut remaining = bitrate;
+
+        let mut by_priority: Vec<usize> = (0..self.senders.len()).collect();
+        by_priority.sort_by(|&a, &b| {
+            self.senders[b]
+                .1
+                .priority
+                .total_cmp(&self.senders[a].1.priority)
+        });
+
+        for i in by_priority {
+            let min_bitrate = self.senders[i].1.min_bitrate;
+
+            if min_bitrate <= remaining {
+                allocated[i] = min_bitrate;
+                active[i] = true;
+                remaining -= min_bitrate;
+            }
+        }
+
+        // Fill up the senders proportionally to their priority, redistributing what is left when reaching a maximum
+        while remaining > 0 {
+            let unsaturated: Vec<usize> = (0..self.senders.len())
+                .filter(|&i| active[i] && allocated[i] < self.senders[i].1.max_bitrate)
+                .collect();
+
+            let priority_sum: f64 = unsaturated
+                .iter()
+                .map(|&i| f64::from(self.senders[i].1.priority.max(0.0)))
+                .sum();
+
+            if priority_sum <= 0.0 {
+                break;
+            }
+
+            let mut distributed = 0;
+
+            for i in unsaturated {
+                let priority = f64::from(self.senders[i].1.priority.max(0.0));
+                let share = (f64::from(remaining) * priority / priority_sum) as u32;
+                let share = share.min(self.senders[i].1.max_bitrate - allocated[i]);
+
+                allocated[i] += share;
+                distributed += share;
+            }
+
+            if distributed == 0 {
+                break;
+            }
+
+            remaining -= distributed;
+        }
+
+        self.senders
+            .iter()
+            .zip(allocated)
+            .map(|((key, _), bitrate)| (*key, bitrate))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_by_priority() {
+        let mut allocator = BitrateAllocator::new();
+        allocator.set("video", BitrateAllocation::new(2.0, 100_000, 10_000_000));
+        allocator.set("screen", BitrateAllocation::new(1.0, 100_000, 10_000_000));
+
+        assert_eq!(
+            allocator.allocate(1_000_000),
+            [("video", 633_333), ("screen", 366_666)]
+        );
+    }
+
+    #[test]
+    fn redistribute_above_max() {
+        let mut allocator = BitrateAllocator::new();
+        allocator.set("audio", BitrateAllocation::new(10.0, 16_000, 64_000));
+        allocator.set("video", BitrateAllocation::new(1.0, 100_000, 2_000_000));
+
+        assert_eq!(
+            allocator.allocate(1_000_000),
+            [("audio", 64_000), ("video", 936_000)]
+        );
+
+        assert_eq!(
+            allocator.allocate(3_000_000),
+            [("audio", 64_000), ("video", 2_000_000)]
+        );
+    }
+
+    #[test]
+    fn minimum_not_satisfiable() {
+        let mut allocator = BitrateAllocator::new();
+        allocator.set("audio", BitrateAllocation::new(10.0, 16_000, 64_000));
+        allocator.set("video", BitrateAllocation::new(1.0, 100_000, 2_000_000));
+
+        assert_eq!(
+            allocator.allocate(50_000),
+            [("audio", 50_000), ("video", 0)]
+        );
+
+        allocator.remove("audio");
+
+        assert_eq!(allocator.allocate(50_000), [("video", 0)]);
+        assert_eq!(allocator.allocate(150_000), [("video", 150_000)]);
+    }
+}
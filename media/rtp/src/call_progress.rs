@@ -0,0 +1,311 @@
+use crate::tone_detector::Goertzel;
+use std::collections::VecDeque;
+
+/// Length of a single analysis block, long enough to tell 425 Hz and 440 Hz apart
+const BLOCK_DURATION_MS: u32 = 50;
+
+/// Minimum mean power of a block to not be considered silence (roughly -40 dBov)
+const MIN_BLOCK_POWER: f64 = 1e-4;
+
+/// Minimum share of the block's energy in the frequencies of a tone
+const MIN_ENERGY_RATIO: f64 = 0.6;
+/// Minimum share of the block's energy in each frequency of a dual tone
+const MIN_DUAL_TONE_RATIO: f64 = 0.15;
+
+/// Minimum length of a continuous tone to be considered ringback
+const RINGBACK_MIN_BLOCKS: u32 = 800 / BLOCK_DURATION_MS;
+/// Length range of the on and off periods of a busy or congestion tone
+const BUSY_BLOCKS: (u32, u32) = (150 / BLOCK_DURATION_MS, 700 / BLOCK_DURATION_MS);
+/// Length range of each of the three special information tone segments
+const SIT_BLOCKS: (u32, u32) = (150 / BLOCK_DURATION_MS, 500 / BLOCK_DURATION_MS);
+
+/// Frequencies of the first, second and third special information tone segment (ITU-T E.180 / Telcordia)
+const SIT_FREQUENCIES: [&[f64]; 3] = [&[913.8, 985.2], &[1370.6, 1428.5], &[1776.7]];
+
+/// Network tone detected by the [`CallProgressDetector`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallProgressTone {
+    /// The remote party is being alerted
+    Ringback,
+
+    /// The remote party is busy or the network is congested
+    Busy,
+
+    /// Special information tone, announcing e.g. a disconnected number
+    SpecialInformation,
+}
+
+/// Detects network call progress tones (ringback, busy, SIT) in decoded audio, e.g. in the early media of an
+/// outbound call
+///
+/// Detects the North American (440+480 Hz ringback, 480+620 Hz busy) and European (425 Hz) tones by their
+/// frequencies and cadence. Every tone is reported once when it is first detected, and again only after a
+/// different tone has been reported.
+#[derive(Debug)]
+pub struct CallProgressDetector {
+    block_len: usize,
+    block: Vec<f64>,
+
+    f425: Goertzel,
+    f440: Goertzel,
+    f480: Goertzel,
+    f620: Goertzel,
+    sit: [Vec<Goertzel>; 3],
+
+    current: (Signal, u32),
+    history: VecDeque<(Signal, u32)>,
+
+    last_detected: Option<CallProgressTone>,
+    detected: VecDeque<CallProgressTone>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Signal {
+    Silence,
+    /// 440+480 Hz
+    Ringback,
+    /// 480+620 Hz
+    Busy,
+    /// 425 Hz, used for both ringback and busy
+    Single425,
+    /// Segment of the special information tone
+    Sit(usize),
+    /// Anything else, e.g. voice
+    Other,
+}
+
+impl CallProgressDetector {
+    /// Create a new detector for audio with the given sample rate
+    pub fn new(sample_rate: u32) -> Self {
+        let block_len = (sample_rate * BLOCK_DURATION_MS / 1000) as usize;
+
+        Self {
+            block_len,
+            block: Vec::with_capacity(block_len),
+            f425: Goertzel::new(425.0, sample_rate),
+            f440: Goertzel::new(440.0, sample_rate),
+            f480: Goertzel::new(480.0, sample_rate),
+            f620: Goertzel::new(620.0, sample_rate),
+            sit: SIT_FREQUENCIES.map(|frequencies| {
+                frequencies
+                    .iter()
+                    .map(|&f| Goertzel::new(f, sample_rate))
+                    .collect()
+            }),
+            current: (Signal::Silence, 0),
+            history: VecDeque::new(),
+            last_detected: None,
+            detected: VecDeque::new(),
+        }
+    }
+
+    /// Process decoded 16 bit PCM samples, detected tones can be retrieved using [`pop_tone`](Self::pop_tone)
+    pub fn process_samples(&mut self, samples: &[i16]) {
+        for &sample in samples {
+            self.block.push(f64::from(sample) / f64::from(i16::MAX));
+
+            if self.block.len() == self.block_len {
+                let signal = self.classify_block();
+                self.block.clear();
+
+                self.update(signal);
+            }
+        }
+    }
+
+    /// Returns the next detected tone
+    pub fn pop_tone(&mut self) -> Option<CallProgressTone> {
+        self.detected.pop_front()
+    }
+
+    fn classify_block(&self) -> Signal {
+        let energy: f64 = self.block.iter().map(|s| s * s).sum();
+
+        if energy / (self.block.len() as f64) < MIN_BLOCK_POWER {
+            return Signal::Silence;
+        }
+
+        // Normalize the Goertzel power, so that a pure sine at the frequency results in 1.0
+        let norm = energy * self.block.len() as f64 / 2.0;
+        let ratio = |g: &Goertzel| g.power(&self.block) / norm;
+        let dual = |a: f64, b: f64| {
+            a + b > MIN_ENERGY_RATIO && a > MIN_DUAL_TONE_RATIO && b > MIN_DUAL_TONE_RATIO
+        };
+
+        let f480 = ratio(&self.f480);
+
+        if dual(ratio(&self.f440), f480) {
+            return Signal::Ringback;
+        }
+
+        if dual(f480, ratio(&self.f620)) {
+            return Signal::Busy;
+        }
+
+        if ratio(&self.f425) > MIN_ENERGY_RATIO {
+            return Signal::Single425;
+        }
+
+        for (i, frequencies) in self.sit.iter().enumerate() {
+            if frequencies.iter().any(|g| ratio(g) > MIN_ENERGY_RATIO) {
+                return Signal::Sit(i);
+            }
+        }
+
+        Signal::Other
+    }
+
+    fn update(&mut self, signal: Signal) {
+        if self.current.0 == signal {
+            self.current.1 += 1;
+        } else {
+            // Blocks spanning the change between two tones contain both, ignore them
+            if self.current != (Signal::Other, 1) {
+                self.history.push_back(self.current);
+            }
+
+            self.current = (signal, 1);
+
+            if self.history.len() > 3 {
+                self.history.pop_front();
+            }
+
+            if let Some(tone) = self.check_history() {
+                self.report(tone);
+            }
+        }
+
+        if matches!(self.current.0, Signal::Ringback | Signal::Single425)
+            && self.current.1 == RINGBACK_MIN_BLOCKS
+        {
+            self.report(CallProgressTone::Ringback);
+        }
+    }
+
+    /// Check the last completed segments for the busy or SIT cadence
+    fn check_history(&self) -> Option<CallProgressTone> {
+        let in_range = |len: u32, (min, max): (u32, u32)| (min..=max).contains(&len);
+
+        let [a, b, c] = [0, 1, 2].map(|i| self.history.get(i).copied());
+        let ((a, a_len), (b, b_len), (c, c_len)) = (a?, b?, c?);
+
+        let busy_on = |signal: Signal, len: u32| {
+            matches!(signal, Signal::Busy | Signal::Single425) && in_range(len, BUSY_BLOCKS)
+        };
+
+        if busy_on(a, a_len)
+            && b == Signal::Silence
+            && in_range(b_len, BUSY_BLOCKS)
+            && c == a
+            && busy_on(c, c_len)
+        {
+            return Some(CallProgressTone::Busy);
+        }
+
+        if [(a, a_len), (b, b_len), (c, c_len)]
+            .iter()
+            .enumerate()
+            .all(|(i, &(signal, len))| signal == Signal::Sit(i) && in_range(len, SIT_BLOCKS))
+        {
+            return Some(CallProgressTone::SpecialInformation);
+        }
+
+        None
+    }
+
+    fn report(&mut self, tone: CallProgressTone) {
+        if self.last_detected != Some(tone) {
+            self.last_detected = Some(tone);
+            self.detected.push_back(tone);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn tone(frequencies: &[f64], duration_ms: u32) -> Vec<i16> {
+        let len = 8000 * duration_ms / 1000;
+        let amplitude = 0.4 / frequencies.len() as f64;
+
+        (0..len)
+            .map(|i| {
+                let t = f64::from(i) / 8000.0;
+
+                let v: f64 = frequencies
+                    .iter()
+                    .map(|f| amplitude * (2.0 * PI * f * t).sin())
+                    .sum();
+
+                (v * f64::from(i16::MAX)) as i16
+            })
+            .collect()
+    }
+
+    fn silence(duration_ms: u32) -> Vec<i16> {
+        vec![0; (8000 * duration_ms / 1000) as usize]
+    }
+
+    fn detect(samples: &[Vec<i16>]) -> Vec<CallProgressTone> {
+        let mut detector = CallProgressDetector::new(8000);
+
+        for samples in samples {
+            detector.process_samples(samples);
+        }
+
+        std::iter::from_fn(|| detector.pop_tone()).collect()
+    }
+
+    #[test]
+    fn ringback() {
+        assert_eq!(
+            detect(&[
+                tone(&[440.0, 480.0], 2000),
+                silence(4000),
+                tone(&[440.0, 480.0], 2000)
+            ]),
+            [CallProgressTone::Ringback]
+        );
+
+        assert_eq!(
+            detect(&[tone(&[425.0], 1000), silence(4000)]),
+            [CallProgressTone::Ringback]
+        );
+    }
+
+    #[test]
+    fn busy() {
+        let cycle = [tone(&[480.0, 620.0], 500), silence(500)];
+        assert_eq!(
+            detect(&[cycle.concat().repeat(3)]),
+            [CallProgressTone::Busy]
+        );
+
+        let cycle = [tone(&[425.0], 500), silence(500)];
+        assert_eq!(
+            detect(&[cycle.concat().repeat(3)]),
+            [CallProgressTone::Busy]
+        );
+    }
+
+    #[test]
+    fn special_information_tone() {
+        assert_eq!(
+            detect(&[
+                tone(&[913.8], 380),
+                tone(&[1370.6], 380),
+                tone(&[1776.7], 380),
+                silence(1000)
+            ]),
+            [CallProgressTone::SpecialInformation]
+        );
+    }
+
+    #[test]
+    fn no_tones_in_other_audio() {
+        assert!(detect(&[tone(&[1000.0], 3000), silence(1000)]).is_empty());
+        assert!(detect(&[tone(&[350.0, 440.0], 3000)]).is_empty());
+    }
+}
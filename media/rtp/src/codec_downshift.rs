@@ -0,0 +1,141 @@
+use std::time::{Duration, Instant};
+
+/// Configuration of the [`CodecDownshiftPolicy`]
+#[derive(Debug, Clone)]
+pub struct DownshiftConfig {
+    /// Fraction of lost packets at or above which the loss is considered high
+    pub high_loss: f32,
+
+    /// Fraction of lost packets at or below which the conditions are considered good again
+    pub low_loss: f32,
+
+    /// How long the loss must stay high before switching to the robust codec
+    pub downshift_after: Duration,
+
+    /// How long the loss must stay low before switching back to the original codec
+    pub upshift_after: Duration,
+}
+
+impl Default for DownshiftConfig {
+    fn default() -> Self {
+        Self {
+            high_loss: 0.1,
+            low_loss: 0.02,
+            downshift_after: Duration::from_secs(5),
+            upshift_after: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Decision of the [`CodecDownshiftPolicy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownshiftDecision {
+    /// Switch to the more robust codec
+    Downshift,
+
+    /// Switch back to the original codec
+    Upshift,
+}
+
+/// Decides when to switch an audio stream to a more robust codec based on the loss reported by the peer
+///
+/// Uses hysteresis so short loss bursts or short recoveries don't cause constant renegotiations.
+#[derive(Debug)]
+pub struct CodecDownshiftPolicy {
+    config: DownshiftConfig,
+    downshifted: bool,
+
+    /// Since when the loss has been beyond the threshold of the current state
+    since: Option<Instant>,
+}
+
+impl CodecDownshiftPolicy {
+    pub fn new(config: DownshiftConfig) -> Self {
+        Self {
+            config,
+            downshifted: false,
+            since: None,
+        }
+    }
+
+    /// Returns if the robust codec is currently in use
+    pub fn is_downshifted(&self) -> bool {
+        self.downshifted
+    }
+
+    /// Process the fraction of lost packets reported by the peer, e.g. in a RTCP report block
+    pub fn report_loss(&mut self, now: Instant, fraction_lost: f32) -> Option<DownshiftDecision> {
+        let (beyond_threshold, hold) = if self.downshifted {
+            (
+                fraction_lost <= self.config.low_loss,
+                self.config.upshift_after,
+            )
+        } else {
+            (
+                fraction_lost >= self.config.high_loss,
+                self.config.downshift_after,
+            )
+        };
+
+        if !beyond_threshold {
+            self.since = None;
+            return None;
+        }
+
+        let since = *self.since.get_or_insert(now);
+
+        if now.duration_since(since) < hold {
+            return None;
+        }
+
+        self.since = None;
+        self.downshifted = !self.downshifted;
+
+        if self.downshifted {
+            Some(DownshiftDecision::Downshift)
+        } else {
+            Some(DownshiftDecision::Upshift)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downshift_and_upshift() {
+        let mut policy = CodecDownshiftPolicy::new(DownshiftConfig::default());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(policy.report_loss(at(0), 0.2), None);
+        assert_eq!(policy.report_loss(at(3), 0.2), None);
+        assert_eq!(
+            policy.report_loss(at(5), 0.15),
+            Some(DownshiftDecision::Downshift)
+        );
+        assert!(policy.is_downshifted());
+
+        assert_eq!(policy.report_loss(at(10), 0.0), None);
+        assert_eq!(policy.report_loss(at(30), 0.01), None);
+        assert_eq!(
+            policy.report_loss(at(40), 0.0),
+            Some(DownshiftDecision::Upshift)
+        );
+        assert!(!policy.is_downshifted());
+    }
+
+    #[test]
+    fn short_burst_is_ignored() {
+        let mut policy = CodecDownshiftPolicy::new(DownshiftConfig::default());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(policy.report_loss(at(0), 0.3), None);
+        assert_eq!(policy.report_loss(at(3), 0.05), None);
+        assert_eq!(policy.report_loss(at(6), 0.3), None);
+        assert_eq!(policy.report_loss(at(10), 0.3), None);
+        assert!(!policy.is_downshifted());
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Number of delay samples used to estimate the delay trend
+const TRENDLINE_WINDOW: usize = 20;
+
+/// Smoothing factor of the accumulated delay
+const SMOOTHING: f64 = 0.9;
+
+/// Gain applied to the delay trend before it's compared to the threshold
+const TRENDLINE_GAIN: f64 = 4.0;
+
+/// Feedback about a single sent packet, e.g. taken from transport-wide congestion control (TWCC) feedback
+#[derive(Debug, Clone, Copy)]
+pub struct PacketFeedback {
+    /// When the packet was sent
+    pub send_time: Instant,
+
+    /// When the packet arrived at the receiver, relative to an arbitrary reference point of the receiver's clock
+    /// which must be the same for all feedback. `None` if the packet was lost.
+    pub arrival_time: Option<Duration>,
+
+    /// Size of the packet in bytes
+    pub size: usize,
+}
+
+/// Congestion controller estimating the available send bitrate from per packet feedback
+pub trait CongestionController: Send + 'static {
+    /// Process the feedback of packets, in the order they were sent
+    fn on_feedback(&mut self, now: Instant, feedback: &[PacketFeedback]);
+
+    /// Returns the current target send bitrate in bits per second
+    fn target_bitrate(&self) -> u32;
+}
+
+/// Configuration of the [`GccController`]
+#[derive(Debug, Clone)]
+pub struct GccConfig {
+    /// Bitrate to start with in bits per second
+    pub initial_bitrate: u32,
+
+    /// The target bitrate never drops below this
+    pub min_bitrate: u32,
+
+    /// The target bitrate never exceeds this
+    pub max_bitrate: u32,
+}
+
+impl Default for GccConfig {
+    fn default() -> Self {
+        Self {
+            initial_bitrate: 300_000,
+            min_bitrate: 30_000,
+            max_bitrate: 5_000_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BandwidthUsage {
+    Normal,
+    Overusing,
+    Underusing,
+}
+
+/// Delay-based [`CongestionController`] modeled after Google Congestion Control (GCC)
+///
+/// The trend of the one-way delay variation is estimated using a linear regression. When the delay is growing
+/// faster than an adaptive threshold the link is considered overused and the bitrate is reduced below the
+/// acknowledged bitrate. Otherwise the bitrate is increased multiplicatively. High packet loss reduces the
+/// bitrate as well.
+#[derive(Debug)]
+pub struct GccController {
+    config: GccConfig,
+    target_bitrate: f64,
+
+    /// Send & arrival time of the previous received packet
+    previous: Option<(Instant, Duration)>,
+    first_arrival: Option<Duration>,
+
+    accumulated_delay: f64,
+    smoothed_delay: f64,
+    /// Arrival time and smoothed delay in milliseconds
+    samples: VecDeque<(f64, f64)>,
+    num_deltas: usize,
+
+    threshold: f64,
+    previous_trend: f64,
+    overuse_time: f64,
+    overuse_count: u32,
+    usage: BandwidthUsage,
+
+    last_update: Option<Instant>,
+}
+
+impl GccController {
+    pub fn new(config: GccConfig) -> Self {
+        Self {
+            target_bitrate: f64::from(config.initial_bitrate),
+            config,
+            previous: None,
+            first_arrival: None,
+            accumulated_delay: 0.0,
+            smoothed_delay: 0.0,
+            samples: VecDeque::with_capacity(TRENDLINE_WINDOW),
+            num_deltas: 0,
+            threshold: 12.5,
+            previous_trend: 0.0,
+            overuse_time: 0.0,
+            overuse_count: 0,
+            usage: BandwidthUsage::Normal,
+            last_update: None,
+        }
+    }
+
+    fn on_packet_received(&mut self, send_time: Instant, arrival_time: Duration) {
+        let first_arrival = *self.first_arrival.get_or_insert(arrival_time);
+
+        let Some((previous_send, previous_arrival)) =
+            self.previous.replace((send_time, arrival_time))
+        else {
+            return;
+        };
+
+        let send_delta = ms(send_time.saturating_duration_since(previous_send));
+        let arrival_delta = ms(arrival_time) - ms(previous_arrival);
+
+        self.accumulated_delay += arrival_delta - send_delta;
+        self.smoothed_delay =
+            SMOOTHING * self.smoothed_delay + (1.0 - SMOOTHING) * self.accumulated_delay;
+
+        self.num_deltas += 1;
+
+        if self.samples.len() == TRENDLINE_WINDOW {
+            self.samples.pop_front();
+        }
+
+        self.samples
+            .push_back((ms(arrival_time) - ms(first_arrival), self.smoothed_delay));
+
+        if self.samples.len() == TRENDLINE_WINDOW {
+            let trend = self.trendline_slope() * self.num_deltas.min(60) as f64 * TRENDLINE_GAIN;
+
+            self.detect(trend, send_delta);
+        }
+    }
+
+    /// Slope of the linear regression of the smoothed delay over the arrival time
+    fn trendline_slope(&self) -> f64 {
+        let n = self.samples.len() as f64;
+        let mean_x = self.samples.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = self.samples.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+        let (numerator, denominator) =
+            self.samples
+                .iter()
+                .fold((0.0, 0.0), |(numerator, denominator), (x, y)| {
+                    (
+                        numerator + (x - mean_x) * (y - mean_y),
+                        denominator + (x - mean_x).powi(2),
+                    )
+                });
+
+        if denominator == 0.0 {
+            0.0
+        } else {
+            numerator / denominator
+        }
+    }
+
+    fn detect(&mut self, trend: f64, delta: f64) {
+        if trend > self.threshold {
+            self.overuse_time += delta;
+            self.overuse_count += 1;
+
+            if self.overuse_time > 10.0 && self.overuse_count > 1 && trend >= self.previous_trend {
+                self.usage = BandwidthUsage::Overusing;
+                self.overuse_time = 0.0;
+                self.overuse_count = 0;
+            }
+        } else if trend < -self.threshold {
+            self.usage = BandwidthUsage::Underusing;
+            self.overuse_time = 0.0;
+            self.overuse_count = 0;
+        } else {
+            self.usage = BandwidthUsage::Normal;
+            self.overuse_time = 0.0;
+            self.overuse_count = 0;
+        }
+
+        self.previous_trend = trend;
+
+        // Adapt the threshold, ignoring sudden spikes
+        if trend.abs() - self.threshold <= 15.0 {
+            let k = if trend.abs() < self.threshold {
+                0.039
+            } else {
+                0.0087
+            };
+
+            self.threshold += k * (trend.abs() - self.threshold) * delta.min(100.0);
+            self.threshold = self.threshold.clamp(6.0, 600.0);
+        }
+    }
+}
+
+impl CongestionController for GccController {
+    fn on_feedback(&mut self, now: Instant, feedback: &[PacketFeedback]) {
+        let mut received_bytes = 0;
+        let mut arrival_range: Option<(Duration, Duration)> = None;
+        let mut lost = 0;
+
+        for packet in feedback {
+            let Some(arrival_time) = packet.arrival_time else {
+                lost += 1;
+                continue;
+            };
+
+            self.on_packet_received(packet.send_time, arrival_time);
+
+            received_bytes += packet.size;
+            arrival_range = Some(match arrival_range {
+                Some((first, last)) => (first.min(arrival_time), last.max(arrival_time)),
+                None => (arrival_time, arrival_time),
+            });
+        }
+
+        let acked_bitrate = arrival_range
+            .map(|(first, last)| (last - first).as_secs_f64())
+            .filter(|duration| *duration > 0.0)
+            .map(|duration| (received_bytes * 8) as f64 / duration);
+
+        let loss = if feedback.is_empty() {
+            0.0
+        } else {
+            f64::from(lost) / feedback.len() as f64
+        };
+
+        let elapsed = self
+            .last_update
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64().min(1.0));
+        self.last_update = Some(now);
+
+        match self.usage {
+            BandwidthUsage::Overusing => {
+                let decreased = 0.85 * acked_bitrate.unwrap_or(self.target_bitrate);
+                self.target_bitrate = self.target_bitrate.min(decreased);
+            }
+            BandwidthUsage::Underusing => {
+                // Hold the bitrate until the queues have drained
+            }
+            BandwidthUsage::Normal if loss <= 0.02 => {
+                let mut increased = self.target_bitrate * 1.08f64.powf(elapsed);
+
+                // Don't run away from what the link actually delivered
+                if let Some(acked_bitrate) = acked_bitrate {
+                    increased = increased.min(1.5 * acked_bitrate + 10_000.0);
+                }
+
+                self.target_bitrate = self.target_bitrate.max(increased);
+            }
+            BandwidthUsage::Normal => {}
+        }
+
+        if loss > 0.1 {
+            self.target_bitrate *= 1.0 - 0.5 * loss;
+        }
+
+        self.target_bitrate = self.target_bitrate.clamp(
+            f64::from(self.config.min_bitrate),
+            f64::from(self.config.max_bitrate),
+        );
+    }
+
+    fn target_bitrate(&self) -> u32 {
+        self.target_bitrate as u32
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send a packet every 10ms and report feedback every 100ms, with the given extra queueing delay per packet
+    fn simulate(
+        controller: &mut GccController,
+        seconds: u32,
+        delay_increase: Duration,
+        lost: impl Fn(u32) -> bool,
+    ) {
+        let start = Instant::now();
+        let mut arrival = Duration::ZERO;
+        let mut batch = vec![];
+
+        for i in 0..seconds * 100 {
+            let send_time = start + Duration::from_millis(u64::from(i) * 10);
+            arrival += Duration::from_millis(10) + delay_increase;
+
+            batch.push(PacketFeedback {
+                send_time,
+                arrival_time: (!lost(i)).then_some(arrival),
+                size: 1000,
+            });
+
+            if batch.len() == 10 {
+                controller.on_feedback(send_time, &batch);
+                batch.clear();
+            }
+        }
+    }
+
+    #[test]
+    fn increase_on_stable_delay() {
+        let mut controller = GccController::new(GccConfig::default());
+
+        simulate(&mut controller, 5, Duration::ZERO, |_| false);
+
+        assert!(controller.target_bitrate() > 400_000);
+    }
+
+    #[test]
+    fn decrease_on_growing_delay() {
+        let mut controller = GccController::new(GccConfig {
+            initial_bitrate: 1_000_000,
+            ..GccConfig::default()
+        });
+
+        simulate(&mut controller, 5, Duration::from_millis(2), |_| false);
+
+        assert!(controller.target_bitrate() < 700_000);
+    }
+
+    #[test]
+    fn decrease_on_loss() {
+        let mut controller = GccController::new(GccConfig::default());
+
+        simulate(&mut controller, 5, Duration::ZERO, |i| i % 3 == 0);
+
+        assert!(controller.target_bitrate() < 300_000);
+    }
+}
@@ -23,10 +23,12 @@ impl<B: BufMut> RtpExtensionsWriter<B> {
 
             self.len += data.len() + 2;
         } else {
-            assert!(data.len() <= 15);
+            assert!(id > 0 && id < 15);
+            assert!(data.len() <= 16);
             assert!(!data.is_empty());
 
-            let mut b = data.len() as u8;
+            // The length is encoded as length - 1
+            let mut b = (data.len() - 1) as u8;
             b |= id << 4;
 
             self.writer.put_u8(b);
@@ -39,7 +41,7 @@ impl<B: BufMut> RtpExtensionsWriter<B> {
     }
 
     pub fn finish(mut self) -> u16 {
-        let id = if self.two_byte { 0xBEDE } else { 0x0100 };
+        let id = if self.two_byte { 0x1000 } else { 0xBEDE };
 
         let padding = padding_32_bit_boundry(self.len);
         self.writer.put_bytes(0, padding);
@@ -69,7 +71,7 @@ impl<T: Iterator, U: Iterator<Item = T::Item>> Iterator for ExtensionsIter<T, U>
 pub fn parse_extensions(profile: u16, data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
     if profile == 0xBEDE {
         ExtensionsIter::OneByte(parse_onebyte(data))
-    } else if (profile & 0xFFF) == 0x100 {
+    } else if (profile & 0xFFF0) == 0x1000 {
         ExtensionsIter::TwoBytes(parse_twobyte(data))
     } else {
         ExtensionsIter::None
@@ -79,14 +81,10 @@ pub fn parse_extensions(profile: u16, data: &[u8]) -> impl Iterator<Item = (u8,
 // https://www.rfc-editor.org/rfc/rfc8285#section-4.2
 fn parse_onebyte(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
     std::iter::from_fn(move || {
-        let &[b, ref remaining @ ..] = data else {
+        let &[b, ref remaining @ ..] = skip_padding(data) else {
             return None;
         };
 
-        if b == 0 {
-            return None;
-        }
-
         let id = (b & 0xF0) >> 4;
         if id == 15 {
             return None;
@@ -103,17 +101,13 @@ fn parse_onebyte(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
     })
 }
 
-// https://www.rfc-editor.org/rfc/rfc5285#section-4.3
+// https://www.rfc-editor.org/rfc/rfc8285#section-4.3
 fn parse_twobyte(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
     std::iter::from_fn(move || {
-        let &[id, len, ref remaining @ ..] = data else {
+        let &[id, len, ref remaining @ ..] = skip_padding(data) else {
             return None;
         };
 
-        if id == 0 && len == 0 {
-            return None;
-        }
-
         let len = len as usize;
 
         if remaining.len() >= len {
@@ -125,6 +119,12 @@ fn parse_twobyte(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
     })
 }
 
+/// Skip padding bytes, which may be placed before, between and after elements
+fn skip_padding(data: &[u8]) -> &[u8] {
+    let start = data.iter().position(|&b| b != 0).unwrap_or(data.len());
+    &data[start..]
+}
+
 fn padding_32_bit_boundry(i: usize) -> usize {
     match i % 4 {
         0 => 0,
@@ -134,3 +134,88 @@ fn padding_32_bit_boundry(i: usize) -> usize {
         _ => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(two_byte: bool, elements: &[(u8, &[u8])]) -> (u16, Vec<u8>) {
+        let mut buf = vec![];
+        let mut writer = RtpExtensionsWriter::new(&mut buf, two_byte);
+
+        for (id, data) in elements {
+            writer = writer.with(*id, data);
+        }
+
+        (writer.finish(), buf)
+    }
+
+    #[test]
+    fn one_byte() {
+        let (profile, buf) = write(false, &[(1, b"a"), (14, &[0xFF; 16])]);
+
+        assert_eq!(profile, 0xBEDE);
+        // ID in the upper 4 bits, length - 1 in the lower 4 bits
+        assert_eq!(buf[0], 0x10);
+        assert_eq!(buf[1], b'a');
+        assert_eq!(buf[2], 0xEF);
+        // Padded to 32 bits
+        assert_eq!(buf.len(), 20);
+
+        let parsed: Vec<_> = parse_extensions(profile, &buf).collect();
+        assert_eq!(parsed, [(1, &b"a"[..]), (14, &[0xFF; 16][..])]);
+    }
+
+    #[test]
+    fn two_byte() {
+        let (profile, buf) = write(true, &[(1, b""), (15, b"abc"), (200, &[0xFF; 17])]);
+
+        assert_eq!(profile, 0x1000);
+        assert_eq!(&buf[..7], &[1, 0, 15, 3, b'a', b'b', b'c']);
+        assert_eq!(&buf[7..9], &[200, 17]);
+        assert_eq!(buf.len(), 28);
+
+        let parsed: Vec<_> = parse_extensions(profile, &buf).collect();
+        assert_eq!(
+            parsed,
+            [(1, &b""[..]), (15, &b"abc"[..]), (200, &[0xFF; 17][..])]
+        );
+    }
+
+    #[test]
+    fn two_byte_app_bits() {
+        let buf = [1, 1, b'a', 0];
+
+        let parsed: Vec<_> = parse_extensions(0x100F, &buf).collect();
+        assert_eq!(parsed, [(1, &b"a"[..])]);
+
+        // Neither one-byte nor two-byte
+        assert_eq!(parse_extensions(0x0100, &buf).count(), 0);
+        assert_eq!(parse_extensions(0x1010, &buf).count(), 0);
+    }
+
+    #[test]
+    fn padding_between_elements() {
+        let buf = [0, 0x10, b'a', 0, 0, 0x21, b'b', b'c'];
+        let parsed: Vec<_> = parse_extensions(0xBEDE, &buf).collect();
+        assert_eq!(parsed, [(1, &b"a"[..]), (2, &b"bc"[..])]);
+
+        let buf = [0, 1, 1, b'a', 0, 2, 2, b'b', b'c', 0, 0, 0];
+        let parsed: Vec<_> = parse_extensions(0x1000, &buf).collect();
+        assert_eq!(parsed, [(1, &b"a"[..]), (2, &b"bc"[..])]);
+    }
+
+    #[test]
+    fn one_byte_reserved_id_stops_parsing() {
+        let buf = [0x10, b'a', 0xF0, b'x', 0x20, b'b'];
+
+        let parsed: Vec<_> = parse_extensions(0xBEDE, &buf).collect();
+        assert_eq!(parsed, [(1, &b"a"[..])]);
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(parse_extensions(0xBEDE, &[0x13, b'a']).count(), 0);
+        assert_eq!(parse_extensions(0x1000, &[1, 5, b'a']).count(), 0);
+    }
+}
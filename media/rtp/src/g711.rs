@@ -0,0 +1,137 @@
+/// G.711 companding law, PCMU (μ-law) or PCMA (A-law)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum G711 {
+    /// μ-law, static payload type 0
+    Pcmu,
+    /// A-law, static payload type 8
+    Pcma,
+}
+
+impl G711 {
+    /// Encode 16 bit PCM samples sampled at 8 kHz, appending one byte per sample to `out`
+    pub fn encode(self, samples: &[i16], out: &mut Vec<u8>) {
+        let encode = match self {
+            G711::Pcmu => encode_ulaw,
+            G711::Pcma => encode_alaw,
+        };
+
+        out.extend(samples.iter().map(|sample| encode(*sample)));
+    }
+
+    /// Decode G.711 bytes, appending one 16 bit PCM sample per byte to `out`
+    pub fn decode(self, encoded: &[u8], out: &mut Vec<i16>) {
+        let decode = match self {
+            G711::Pcmu => decode_ulaw,
+            G711::Pcma => decode_alaw,
+        };
+
+        out.extend(encoded.iter().map(|byte| decode(*byte)));
+    }
+}
+
+const ULAW_BIAS: i32 = 0x84;
+const ULAW_CLIP: i32 = 32635;
+
+fn encode_ulaw(sample: i16) -> u8 {
+    let sample = i32::from(sample);
+    let sign = if sample < 0 { 0x80 } else { 0 };
+
+    let magnitude = sample.abs().min(ULAW_CLIP) + ULAW_BIAS;
+
+    // The biased magnitude has its highest bit set somewhere between bit 7 and bit 14
+    let exponent = 24 - magnitude.leading_zeros() as i32;
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0F;
+
+    !(sign | (exponent << 4) | mantissa) as u8
+}
+
+fn decode_ulaw(byte: u8) -> i16 {
+    let byte = !byte;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = i32::from(byte & 0x0F);
+
+    let magnitude = (((mantissa << 3) + ULAW_BIAS) << exponent) - ULAW_BIAS;
+
+    if byte & 0x80 != 0 {
+        -magnitude as i16
+    } else {
+        magnitude as i16
+    }
+}
+
+fn encode_alaw(sample: i16) -> u8 {
+    // A-law uses 13 bit samples
+    let sample = i32::from(sample) >> 3;
+
+    let (sign, magnitude) = if sample >= 0 {
+        (0x80, sample)
+    } else {
+        (0, -sample - 1)
+    };
+
+    let magnitude = magnitude.min(0x0FFF);
+
+    let (exponent, mantissa) = if magnitude < 32 {
+        (0, magnitude >> 1)
+    } else {
+        let exponent = 27 - magnitude.leading_zeros() as i32;
+        (exponent, (magnitude >> exponent) & 0x0F)
+    };
+
+    ((sign | (exponent << 4) | mantissa) ^ 0x55) as u8
+}
+
+fn decode_alaw(byte: u8) -> i16 {
+    let byte = byte ^ 0x55;
+    let exponent = (byte >> 4) & 0x07;
+    let mantissa = i32::from(byte & 0x0F);
+
+    let mut magnitude = (mantissa << 4) + 8;
+
+    if exponent != 0 {
+        magnitude = (magnitude + 0x100) << (exponent - 1);
+    }
+
+    if byte & 0x80 != 0 {
+        magnitude as i16
+    } else {
+        -magnitude as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silence() {
+        let mut encoded = vec![];
+
+        G711::Pcmu.encode(&[0], &mut encoded);
+        G711::Pcma.encode(&[0], &mut encoded);
+
+        assert_eq!(encoded, [0xFF, 0xD5]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        for law in [G711::Pcmu, G711::Pcma] {
+            let samples = [-32768, -20000, -1000, -100, 0, 100, 1000, 20000, 32767];
+
+            let mut encoded = vec![];
+            law.encode(&samples, &mut encoded);
+
+            let mut decoded = vec![];
+            law.decode(&encoded, &mut decoded);
+
+            for (sample, decoded) in samples.iter().zip(decoded) {
+                // The quantization error grows with the magnitude, but stays below 1/16th of it
+                let error = (i32::from(*sample) - i32::from(decoded)).abs();
+                assert!(
+                    error <= (i32::from(*sample).abs() / 16).max(16),
+                    "{law:?}: {sample} decoded as {decoded}"
+                );
+            }
+        }
+    }
+}
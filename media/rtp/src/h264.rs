@@ -0,0 +1,840 @@
+use crate::{DePayloader, Payloader};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// NAL unit type of a single-time aggregation packet (RFC 6184 section 5.7.1)
+const STAP_A: u8 = 24;
+/// NAL unit type of a single-time aggregation packet with decoding order number
+const STAP_B: u8 = 25;
+/// NAL unit type of a multi-time aggregation packet with 16 bit timestamp offsets (RFC 6184 section 5.7.2)
+const MTAP16: u8 = 26;
+/// NAL unit type of a multi-time aggregation packet with 24 bit timestamp offsets
+const MTAP24: u8 = 27;
+/// NAL unit type of a fragmentation unit without decoding order number (RFC 6184 section 5.8)
+const FU_A: u8 = 28;
+/// NAL unit type of a fragmentation unit with decoding order number, only used for the first fragment
+const FU_B: u8 = 29;
+
+/// Size of the STAP-A NAL unit header
+const STAP_A_HEADER_SIZE: usize = 1;
+/// Size of the STAP-B NAL unit header and decoding order number
+const STAP_B_HEADER_SIZE: usize = 3;
+/// Size of the length prefix of every NAL unit in a STAP packet
+const STAP_LENGTH_SIZE: usize = 2;
+/// Size of the FU indicator and FU header
+const FU_A_HEADER_SIZE: usize = 2;
+/// Size of the FU indicator, FU header and decoding order number
+const FU_B_HEADER_SIZE: usize = 4;
+
+/// NAL unit type of a coded slice of an IDR picture
+const IDR: u8 = 5;
+/// NAL unit type of a sequence parameter set
+const SPS: u8 = 7;
+/// NAL unit type of a picture parameter set
+const PPS: u8 = 8;
+
+/// Start code prepended to every NAL unit returned by the [`H264DePayloader`] in Annex B format
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// Default number of NAL units the [`H264DePayloader`] buffers in interleaved mode to restore the decoding order
+const DEFAULT_INTERLEAVING_DEPTH: usize = 8;
+
+/// Packetization mode of the H.264 payload format, negotiated using the `packetization-mode` fmtp parameter
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum H264PacketizationMode {
+    /// Every RTP packet contains a single NAL unit (`packetization-mode=0`)
+    #[default]
+    SingleNal,
+
+    /// NAL units can be aggregated using STAP-A and fragmented using FU-A (`packetization-mode=1`)
+    NonInterleaved,
+
+    /// NAL units carry a decoding order number and may be sent out of order, aggregated using STAP-B or MTAP
+    /// and fragmented using FU-B (`packetization-mode=2`)
+    Interleaved,
+}
+
+/// Format of the NAL units returned by the [`H264DePayloader`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum H264DePayloaderOutputFormat {
+    /// Annex B byte stream, every NAL unit is prefixed with a 4 byte start code
+    #[default]
+    AnnexB,
+
+    /// Every NAL unit is prefixed with its size as 4 byte big endian integer, as in `avc1` samples of MP4 files
+    Avcc,
+}
+
+/// Payloader of H.264 access units in Annex B byte stream format (RFC 6184)
+///
+/// In non-interleaved mode NAL units that fit into a packet are aggregated into STAP-A packets as long as the
+/// packet size allows, so small parameter sets (SPS/PPS) and SEI messages are sent together with the slices
+/// following them. NAL units exceeding the packet size are fragmented using FU-A.
+///
+/// Interleaved mode works the same using STAP-B and FU-B, the NAL units are numbered consecutively and sent in
+/// decoding order.
+///
+/// In single NAL unit mode every NAL unit is sent in its own packet, regardless of its size.
+#[derive(Debug, Default)]
+pub struct H264Payloader {
+    mode: H264PacketizationMode,
+    /// Decoding order number of the next NAL unit in interleaved mode
+    don: u16,
+}
+
+impl H264Payloader {
+    pub fn new(mode: H264PacketizationMode) -> Self {
+        Self { mode, don: 0 }
+    }
+
+    fn payload_aggregated(&mut self, frame: &Bytes, max_size: usize) -> Vec<Bytes> {
+        let interleaved = self.mode == H264PacketizationMode::Interleaved;
+
+        let mut packets = vec![];
+        let mut aggregate = Aggregate::default();
+
+        for nal in nal_units(frame) {
+            let don = interleaved.then_some(self.don);
+            self.don = self.don.wrapping_add(1);
+
+            if !aggregate.fits(&nal, don, max_size) {
+                aggregate.flush(&mut packets);
+
+                if !aggregate.fits(&nal, don, max_size) {
+                    fragment(&nal, don, max_size, &mut packets);
+                    continue;
+                }
+            }
+
+            aggregate.push(nal, don);
+        }
+
+        aggregate.flush(&mut packets);
+
+        packets
+    }
+}
+
+impl Payloader for H264Payloader {
+    fn payload(&mut self, frame: &Bytes, max_size: usize) -> impl Iterator<Item = Bytes> + '_ {
+        let packets = match self.mode {
+            H264PacketizationMode::SingleNal => nal_units(frame).collect(),
+            H264PacketizationMode::NonInterleaved | H264PacketizationMode::Interleaved => {
+                self.payload_aggregated(frame, max_size)
+            }
+        };
+
+        packets.into_iter()
+    }
+}
+
+/// NAL units collected for a single STAP-A or STAP-B packet
+#[derive(Default)]
+struct Aggregate {
+    nals: Vec<Bytes>,
+    size: usize,
+    /// Decoding order number of the first NAL unit, only set in interleaved mode
+    don: Option<u16>,
+}
+
+impl Aggregate {
+    /// Returns if the NAL unit can be added without the packet exceeding `max_size`
+    fn fits(&self, nal: &Bytes, don: Option<u16>, max_size: usize) -> bool {
+        let size = match (self.nals.is_empty(), don) {
+            // Sent as single NAL unit packet unless another NAL unit is added
+            (true, None) => nal.len(),
+            (false, None) => STAP_A_HEADER_SIZE + self.size + STAP_LENGTH_SIZE + nal.len(),
+            // Interleaved mode has no single NAL unit packets
+            (_, Some(_)) => STAP_B_HEADER_SIZE + self.size + STAP_LENGTH_SIZE + nal.len(),
+        };
+
+        size <= max_size && nal.len() <= usize::from(u16::MAX)
+    }
+
+    fn push(&mut self, nal: Bytes, don: Option<u16>) {
+        if self.nals.is_empty() {
+            self.don = don;
+        }
+
+        self.size += STAP_LENGTH_SIZE + nal.len();
+        self.nals.push(nal);
+    }
+
+    /// Emit the collected NAL units, as single NAL unit packet if there is only one in non-interleaved mode
+    fn flush(&mut self, packets: &mut Vec<Bytes>) {
+        let nals = std::mem::take(&mut self.nals);
+        let size = std::mem::take(&mut self.size);
+        let don = self.don.take();
+
+        if nals.is_empty() {
+            return;
+        }
+
+        if nals.len() == 1 && don.is_none() {
+            packets.extend(nals);
+            return;
+        }
+
+        // The forbidden bit is set if it is set in any NAL unit, NRI is the highest of all NAL units
+        let forbidden = nals.iter().fold(0, |f, nal| f | (nal[0] & 0x80));
+        let nri = nals
+            .iter()
+            .map(|nal| nal[0] & 0x60)
+            .max()
+            .unwrap_or_default();
+
+        let mut packet = BytesMut::with_capacity(STAP_B_HEADER_SIZE + size);
+
+        if let Some(don) = don {
+            packet.put_u8(forbidden | nri | STAP_B);
+            packet.put_u16(don);
+        } else {
+            packet.put_u8(forbidden | nri | STAP_A);
+        }
+
+        for nal in &nals {
+            packet.put_u16(nal.len() as u16);
+            packet.put_slice(nal);
+        }
+
+        packets.push(packet.freeze());
+    }
+}
+
+/// Split a NAL unit into fragmentation units of at most `max_size` bytes
+///
+/// In interleaved mode the first fragment is a FU-B carrying the decoding order number, all others are FU-A.
+fn fragment(nal: &Bytes, don: Option<u16>, max_size: usize, packets: &mut Vec<Bytes>) {
+    let nri = nal[0] & 0xE0;
+    let nal_type = nal[0] & 0x1F;
+
+    let mut data = &nal[1..];
+    let mut first = true;
+
+    while first || !data.is_empty() {
+        let header_size = match (first, don) {
+            (true, Some(_)) => FU_B_HEADER_SIZE,
+            _ => FU_A_HEADER_SIZE,
+        };
+
+        let chunk_len = max_size.saturating_sub(header_size).max(1).min(data.len());
+        let (chunk, rest) = data.split_at(chunk_len);
+
+        let start = if first { 0x80 } else { 0 };
+        let end = if rest.is_empty() { 0x40 } else { 0 };
+
+        let mut packet = BytesMut::with_capacity(header_size + chunk.len());
+
+        match (first, don) {
+            (true, Some(don)) => {
+                packet.put_u8(nri | FU_B);
+                packet.put_u8(start | end | nal_type);
+                packet.put_u16(don);
+            }
+            _ => {
+                packet.put_u8(nri | FU_A);
+                packet.put_u8(start | end | nal_type);
+            }
+        }
+
+        packet.put_slice(chunk);
+        packets.push(packet.freeze());
+
+        data = rest;
+        first = false;
+    }
+}
+
+/// Depayloader of H.264 RTP packets (RFC 6184), returning the NAL units in the configured
+/// [output format](Self::with_output_format)
+///
+/// Single NAL unit, STAP-A and FU-A packets are handled in single NAL unit and non-interleaved mode. In
+/// interleaved mode STAP-B, MTAP16, MTAP24, FU-A and FU-B packets are handled and the NAL units are returned in
+/// the order of their decoding order number. For that up to the [interleaving depth](Self::with_interleaving_depth)
+/// NAL units are buffered, a missing NAL unit is skipped once the buffer is full.
+///
+/// Fragmentation units must be given in sequence number order, a fragmented NAL unit is dropped if one of its
+/// fragments is missing.
+///
+/// Senders often transmit the parameter sets only out of band or once at the start of the stream. With
+/// [parameter set injection](Self::with_parameter_set_injection) enabled the last received SPS and PPS are
+/// inserted before every IDR picture which isn't already preceded by them.
+#[derive(Debug)]
+pub struct H264DePayloader {
+    mode: H264PacketizationMode,
+    interleaving_depth: usize,
+    output_format: H264DePayloaderOutputFormat,
+
+    inject_parameter_sets: bool,
+    sps: Option<Bytes>,
+    pps: Option<Bytes>,
+    /// A parameter set was returned since the last slice
+    parameter_sets_written: bool,
+
+    /// NAL unit being reassembled from fragmentation units and its decoding order number
+    fragmented: Option<(BytesMut, Option<u16>)>,
+
+    /// NAL units waiting for their decoding order number to come up
+    reorder: Vec<(u16, Bytes)>,
+    /// Decoding order number of the next NAL unit to return, unknown until the reorder buffer filled once
+    next_don: Option<u16>,
+}
+
+impl H264DePayloader {
+    pub fn new(mode: H264PacketizationMode) -> Self {
+        Self {
+            mode,
+            interleaving_depth: DEFAULT_INTERLEAVING_DEPTH,
+            output_format: H264DePayloaderOutputFormat::default(),
+            inject_parameter_sets: false,
+            sps: None,
+            pps: None,
+            parameter_sets_written: false,
+            fragmented: None,
+            reorder: vec![],
+            next_don: None,
+        }
+    }
+
+    /// Set the number of NAL units buffered to restore the decoding order in interleaved mode
+    ///
+    /// Should be derived from the `sprop-interleaving-depth` fmtp parameter of the sender.
+    pub fn with_interleaving_depth(mut self, depth: usize) -> Self {
+        self.interleaving_depth = depth.max(1);
+        self
+    }
+
+    /// Set the format of the returned NAL units, Annex B by default
+    pub fn with_output_format(mut self, output_format: H264DePayloaderOutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Insert the last received SPS and PPS before IDR pictures which are not preceded by them
+    pub fn with_parameter_set_injection(mut self, enabled: bool) -> Self {
+        self.inject_parameter_sets = enabled;
+        self
+    }
+
+    /// Use the parameter sets of the `sprop-parameter-sets` fmtp parameter (comma separated base64 NAL units)
+    /// until the stream contains its own, invalid entries are ignored
+    pub fn with_sprop_parameter_sets(mut self, sprop_parameter_sets: &str) -> Self {
+        for nal in sprop_parameter_sets.split(',') {
+            let Ok(nal) = BASE64_STANDARD.decode(nal.trim()) else {
+                continue;
+            };
+
+            self.cache_parameter_set(Bytes::from(nal));
+        }
+
+        self
+    }
+
+    /// Return all NAL units still waiting in the reorder buffer, e.g. at the end of the stream
+    pub fn flush(&mut self) -> Option<Bytes> {
+        let mut output = BytesMut::new();
+
+        while let Some(nal) = self.pop_lowest_don() {
+            self.write_nal(&mut output, nal);
+        }
+
+        (!output.is_empty()).then(|| output.freeze())
+    }
+
+    fn receive(&mut self, payload: &Bytes, nals: &mut Vec<(Option<u16>, Bytes)>) -> Option<()> {
+        let interleaved = self.mode == H264PacketizationMode::Interleaved;
+
+        let nal_type = *payload.first()? & 0x1F;
+
+        match nal_type {
+            1..=23 if !interleaved => nals.push((None, payload.clone())),
+            STAP_A if !interleaved => {
+                let mut data = payload.slice(1..);
+
+                while !data.is_empty() {
+                    nals.push((None, read_sized_nal(&mut data)?));
+                }
+            }
+            STAP_B if interleaved => {
+                let mut data = payload.slice(1..);
+                let mut don = read_u16(&mut data)?;
+
+                while !data.is_empty() {
+                    nals.push((Some(don), read_sized_nal(&mut data)?));
+                    don = don.wrapping_add(1);
+                }
+            }
+            MTAP16 | MTAP24 if interleaved => {
+                let ts_offset_len = if nal_type == MTAP16 { 2 } else { 3 };
+
+                let mut data = payload.slice(1..);
+                let don_base = read_u16(&mut data)?;
+
+                while !data.is_empty() {
+                    let size = usize::from(read_u16(&mut data)?);
+
+                    // DOND and timestamp offset are part of the unit size
+                    let header_len = 1 + ts_offset_len;
+                    if size <= header_len || data.len() < size {
+                        return None;
+                    }
+
+                    let mut unit = data.split_to(size);
+                    let don = don_base.wrapping_add(u16::from(unit.get_u8()));
+                    unit.advance(ts_offset_len);
+
+                    nals.push((Some(don), unit));
+                }
+            }
+            FU_A | FU_B => {
+                let [indicator, header, ..] = payload[..] else {
+                    return None;
+                };
+
+                let is_start = header & 0x80 != 0;
+                let is_end = header & 0x40 != 0;
+
+                let mut data = payload.slice(2..);
+
+                if is_start {
+                    // FU-B is used as first fragment in interleaved mode, FU-A everywhere else
+                    let don = match (nal_type, interleaved) {
+                        (FU_B, true) => Some(read_u16(&mut data)?),
+                        (FU_A, false) => None,
+                        _ => return None,
+                    };
+
+                    let mut nal = BytesMut::with_capacity(1 + data.len());
+                    nal.put_u8((indicator & 0xE0) | (header & 0x1F));
+
+                    self.fragmented = Some((nal, don));
+                } else if nal_type != FU_A {
+                    return None;
+                }
+
+                let (nal, _) = self.fragmented.as_mut()?;
+                nal.put_slice(&data);
+
+                if is_end {
+                    let (nal, don) = self.fragmented.take()?;
+                    nals.push((don, nal.freeze()));
+                }
+            }
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    /// Remove and return the buffered NAL unit with the lowest decoding order number
+    fn pop_lowest_don(&mut self) -> Option<Bytes> {
+        let reference = self
+            .next_don
+            .or_else(|| self.reorder.first().map(|(don, _)| *don))?;
+
+        // Decoding order numbers wrap around, compare their distance to the expected one
+        let (i, _) = self
+            .reorder
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (don, _))| don.wrapping_sub(reference) as i16)?;
+
+        let (don, nal) = self.reorder.remove(i);
+        self.next_don = Some(don.wrapping_add(1));
+
+        Some(nal)
+    }
+
+    fn push_interleaved(&mut self, don: u16, nal: Bytes, output: &mut BytesMut) {
+        // Too late, its position in the decoding order was already skipped
+        if let Some(next_don) = self.next_don {
+            if (don.wrapping_sub(next_don) as i16) < 0 {
+                return;
+            }
+        }
+
+        self.reorder.push((don, nal));
+
+        loop {
+            let next = self
+                .next_don
+                .and_then(|next_don| self.reorder.iter().position(|(don, _)| *don == next_don));
+
+            if let Some(i) = next {
+                let (don, nal) = self.reorder.remove(i);
+                self.next_don = Some(don.wrapping_add(1));
+                self.write_nal(output, nal);
+            } else if self.reorder.len() > self.interleaving_depth {
+                if let Some(nal) = self.pop_lowest_don() {
+                    self.write_nal(output, nal);
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Keep the NAL unit if it is a SPS or PPS, to inject it before IDR pictures
+    fn cache_parameter_set(&mut self, nal: Bytes) {
+        match nal.first().map(|header| header & 0x1F) {
+            Some(SPS) => self.sps = Some(nal),
+            Some(PPS) => self.pps = Some(nal),
+            _ => {}
+        }
+    }
+
+    fn write_nal(&mut self, output: &mut BytesMut, nal: Bytes) {
+        let nal_type = nal[0] & 0x1F;
+
+        match nal_type {
+            SPS | PPS => {
+                self.parameter_sets_written = true;
+                self.cache_parameter_set(nal.clone());
+            }
+            1..=IDR => {
+                // The first slice of a picture starts with first_mb_in_slice = 0, encoded as a single 1 bit
+                let first_slice = nal.get(1).is_some_and(|b| b & 0x80 != 0);
+
+                if nal_type == IDR
+                    && first_slice
+                    && self.inject_parameter_sets
+                    && !self.parameter_sets_written
+                {
+                    if let (Some(sps), Some(pps)) = (self.sps.clone(), self.pps.clone()) {
+                        self.write_formatted(output, &sps);
+                        self.write_formatted(output, &pps);
+                    }
+                }
+
+                self.parameter_sets_written = false;
+            }
+            _ => {}
+        }
+
+        self.write_formatted(output, &nal);
+    }
+
+    fn write_formatted(&self, output: &mut BytesMut, nal: &[u8]) {
+        match self.output_format {
+            H264DePayloaderOutputFormat::AnnexB => output.put_slice(&START_CODE),
+            H264DePayloaderOutputFormat::Avcc => output.put_u32(nal.len() as u32),
+        }
+
+        output.put_slice(nal);
+    }
+}
+
+impl DePayloader for H264DePayloader {
+    fn depayload(&mut self, payload: &Bytes) -> Option<Bytes> {
+        let mut nals = vec![];
+
+        // NAL units parsed before an invalid part of the payload are still returned
+        let _ = self.receive(payload, &mut nals);
+
+        let mut output = BytesMut::new();
+
+        for (don, nal) in nals {
+            match don {
+                Some(don) => self.push_interleaved(don, nal, &mut output),
+                None => self.write_nal(&mut output, nal),
+            }
+        }
+
+        (!output.is_empty()).then(|| output.freeze())
+    }
+}
+
+fn read_u16(data: &mut Bytes) -> Option<u16> {
+    (data.len() >= 2).then(|| data.get_u16())
+}
+
+/// Read a NAL unit prefixed with its 16 bit size, as in STAP packets
+fn read_sized_nal(data: &mut Bytes) -> Option<Bytes> {
+    let size = usize::from(read_u16(data)?);
+
+    if size == 0 || data.len() < size {
+        return None;
+    }
+
+    Some(data.split_to(size))
+}
+
+/// Iterate over the NAL units of an Annex B byte stream, a frame without start code is a single NAL unit
+pub(crate) fn nal_units(frame: &Bytes) -> impl Iterator<Item = Bytes> + '_ {
+    let mut remaining = skip_start_code(frame);
+
+    std::iter::from_fn(move || loop {
+        if remaining.is_empty() {
+            return None;
+        }
+
+        let (nal, rest) = match find_start_code(&remaining) {
+            Some(start) => (remaining.slice(..start), remaining.slice(start + 3..)),
+            None => (remaining.clone(), Bytes::new()),
+        };
+
+        remaining = rest;
+
+        // Trailing zero bytes belong to the next start code (zero_byte of a 4 byte start code)
+        let end = nal.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+
+        if end > 0 {
+            return Some(nal.slice(..end));
+        }
+    })
+}
+
+/// Skip the leading start code of the frame, including the zero byte of a 4 byte start code
+fn skip_start_code(frame: &Bytes) -> Bytes {
+    match find_start_code(frame) {
+        Some(start) if frame[..start].iter().all(|&b| b == 0) => frame.slice(start + 3..),
+        _ => frame.clone(),
+    }
+}
+
+/// Returns the position of the first 3 byte start code (`00 00 01`)
+fn find_start_code(data: &[u8]) -> Option<usize> {
+    data.windows(3).position(|w| w == [0, 0, 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(mode: H264PacketizationMode, frame: &[u8], max_size: usize) -> Vec<Bytes> {
+        H264Payloader::new(mode)
+            .payload(&Bytes::copy_from_slice(frame), max_size)
+            .collect()
+    }
+
+    #[test]
+    fn split_nal_units() {
+        let frame = Bytes::from_static(&[
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x65, 4,
+        ]);
+
+        let nals: Vec<Bytes> = nal_units(&frame).collect();
+        assert_eq!(nals, [&[0x67, 1, 2][..], &[0x68, 3], &[0x65, 4]]);
+
+        // Without start code the frame is a single NAL unit
+        let frame = Bytes::from_static(&[0x65, 1, 2, 3]);
+        assert_eq!(nal_units(&frame).collect::<Vec<_>>(), [&frame[..]]);
+    }
+
+    #[test]
+    fn stap_a() {
+        let frame = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x45, 4, 5,
+        ];
+
+        let packets = payload(H264PacketizationMode::NonInterleaved, &frame, 1200);
+
+        // STAP-A header with the highest NRI, followed by every NAL unit with its size
+        let expected = [
+            &[0x60 | STAP_A][..],
+            &[0, 3, 0x67, 1, 2],
+            &[0, 2, 0x68, 3],
+            &[0, 3, 0x45, 4, 5],
+        ]
+        .concat();
+
+        assert_eq!(packets, [expected]);
+    }
+
+    #[test]
+    fn stap_a_respects_max_size() {
+        let frame = [
+            0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 1, 0x65, 4, 5, 6, 7,
+        ];
+
+        // SPS and PPS fit into a single STAP-A packet (1 + 5 + 4 bytes), the slice doesn't fit anymore
+        let packets = payload(H264PacketizationMode::NonInterleaved, &frame, 10);
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0][0] & 0x1F, STAP_A);
+        assert_eq!(packets[0].len(), 10);
+        assert_eq!(packets[1], &[0x65, 4, 5, 6, 7][..]);
+    }
+
+    #[test]
+    fn fu_a() {
+        let frame = [0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x65, 1, 2, 3, 4, 5, 6, 7];
+
+        let packets = payload(H264PacketizationMode::NonInterleaved, &frame, 5);
+
+        assert_eq!(
+            packets,
+            [
+                &[0x67, 1, 2][..],
+                &[0x60 | FU_A, 0x80 | 5, 1, 2, 3],
+                &[0x60 | FU_A, 5, 4, 5, 6],
+                &[0x60 | FU_A, 0x40 | 5, 7],
+            ]
+        );
+    }
+
+    #[test]
+    fn single_nal_mode() {
+        let frame = [
+            0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 1, 0x65, 4, 5, 6, 7,
+        ];
+
+        let packets = payload(H264PacketizationMode::SingleNal, &frame, 4);
+
+        assert_eq!(
+            packets,
+            [&[0x67, 1, 2][..], &[0x68, 3], &[0x65, 4, 5, 6, 7]]
+        );
+    }
+
+    #[test]
+    fn interleaved() {
+        let frame = [0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x65, 1, 2, 3, 4, 5, 6, 7];
+
+        let packets = payload(H264PacketizationMode::Interleaved, &frame, 8);
+
+        assert_eq!(
+            packets,
+            [
+                &[0x60 | STAP_B, 0, 0, 0, 3, 0x67, 1, 2][..],
+                &[0x60 | FU_B, 0x80 | 5, 0, 1, 1, 2, 3, 4],
+                &[0x60 | FU_A, 0x40 | 5, 5, 6, 7],
+            ]
+        );
+    }
+
+    fn depayload(depayloader: &mut H264DePayloader, packets: &[Bytes]) -> Vec<u8> {
+        let mut output: Vec<u8> = packets
+            .iter()
+            .filter_map(|packet| depayloader.depayload(packet))
+            .flatten()
+            .collect();
+
+        output.extend(depayloader.flush().into_iter().flatten());
+        output
+    }
+
+    #[test]
+    fn depayload_roundtrip() {
+        let frame = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x65, 1, 2, 3, 4, 5, 6, 7,
+        ];
+
+        for mode in [
+            H264PacketizationMode::SingleNal,
+            H264PacketizationMode::NonInterleaved,
+            H264PacketizationMode::Interleaved,
+        ] {
+            let packets = payload(mode, &frame, 8);
+
+            let mut depayloader = H264DePayloader::new(mode);
+            assert_eq!(depayload(&mut depayloader, &packets), frame, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn depayload_reorders_by_don() {
+        let packets = [
+            Bytes::from_static(&[STAP_B, 0, 2, 0, 1, 0x43]),
+            Bytes::from_static(&[STAP_B, 0, 0, 0, 1, 0x41]),
+            Bytes::from_static(&[STAP_B, 0, 1, 0, 1, 0x42]),
+        ];
+
+        let mut depayloader =
+            H264DePayloader::new(H264PacketizationMode::Interleaved).with_interleaving_depth(2);
+
+        // Nothing is returned until the buffer exceeds the interleaving depth
+        assert!(depayloader.depayload(&packets[0]).is_none());
+        assert!(depayloader.depayload(&packets[1]).is_none());
+        assert_eq!(
+            depayloader.depayload(&packets[2]).unwrap(),
+            &[0, 0, 0, 1, 0x41, 0, 0, 0, 1, 0x42, 0, 0, 0, 1, 0x43][..]
+        );
+
+        // Decoding order numbers already skipped are dropped
+        assert!(depayloader
+            .depayload(&Bytes::from_static(&[STAP_B, 0, 1, 0, 1, 0x42]))
+            .is_none());
+    }
+
+    #[test]
+    fn depayload_mtap() {
+        // Two NAL units with DON 6 and 5, each with decoding order number delta and timestamp offset
+        let mtap16 =
+            Bytes::from_static(&[MTAP16, 0, 5, 0, 5, 1, 0, 0, 0x65, 0xAA, 0, 4, 0, 0, 0, 0x41]);
+        let mtap24 = Bytes::from_static(&[MTAP24, 0, 7, 0, 5, 0, 0, 0, 0, 0x41]);
+
+        let mut depayloader = H264DePayloader::new(H264PacketizationMode::Interleaved);
+
+        assert_eq!(
+            depayload(&mut depayloader, &[mtap16, mtap24]),
+            [0, 0, 0, 1, 0x41, 0, 0, 0, 1, 0x65, 0xAA, 0, 0, 0, 1, 0x41]
+        );
+    }
+
+    fn idr_frame() -> [Bytes; 4] {
+        [
+            Bytes::from_static(&[0x67, 0x42, 0, 0x1F]),
+            Bytes::from_static(&[0x68, 0xCE, 0x3C, 0x80]),
+            Bytes::from_static(&[0x65, 0x88, 1]),
+            // Second slice of the same picture
+            Bytes::from_static(&[0x65, 0x40, 2]),
+        ]
+    }
+
+    #[test]
+    fn depayload_avcc() {
+        let mut depayloader = H264DePayloader::new(H264PacketizationMode::NonInterleaved)
+            .with_output_format(H264DePayloaderOutputFormat::Avcc);
+
+        assert_eq!(
+            depayload(&mut depayloader, &idr_frame()[2..3]),
+            [0, 0, 0, 3, 0x65, 0x88, 1]
+        );
+    }
+
+    #[test]
+    fn inject_parameter_sets_from_stream() {
+        let mut depayloader = H264DePayloader::new(H264PacketizationMode::NonInterleaved)
+            .with_parameter_set_injection(true);
+
+        let [sps, pps, idr, idr_slice] = idr_frame();
+        let p_frame = Bytes::from_static(&[0x41, 0x9A, 3]);
+
+        // Parameter sets present in the stream are not duplicated
+        let first = depayload(
+            &mut depayloader,
+            &[sps, pps, idr.clone(), idr_slice.clone()],
+        );
+        assert_eq!(first.len(), 4 * 4 + 4 + 4 + 3 + 3);
+
+        depayload(&mut depayloader, &[p_frame]);
+
+        // Parameter sets are injected only before the first slice of the next IDR picture
+        let second = depayload(&mut depayloader, &[idr, idr_slice]);
+        assert_eq!(
+            second,
+            depayload(
+                &mut H264DePayloader::new(H264PacketizationMode::NonInterleaved),
+                &idr_frame()
+            )
+        );
+    }
+
+    #[test]
+    fn inject_sprop_parameter_sets() {
+        let mut depayloader = H264DePayloader::new(H264PacketizationMode::NonInterleaved)
+            .with_parameter_set_injection(true)
+            .with_sprop_parameter_sets("Z0IAHw==,aM48gA==");
+
+        let [_, _, idr, _] = idr_frame();
+
+        assert_eq!(
+            depayload(&mut depayloader, &[idr]),
+            [
+                0, 0, 0, 1, 0x67, 0x42, 0, 0x1F, 0, 0, 0, 1, 0x68, 0xCE, 0x3C, 0x80, 0, 0, 0, 1,
+                0x65, 0x88, 1
+            ]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use super::{H265Level, H265Profile, H265Tier};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use bytes::Bytes;
+use std::fmt;
+
+/// Media format parameters of the H.265 payload format (RFC 7798 section 7.1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct H265FmtpOptions {
+    /// `profile-space`, 0 if absent
+    pub profile_space: u8,
+
+    /// `profile-id`, Main if absent
+    pub profile: H265Profile,
+
+    /// `tier-flag`, Main if absent
+    pub tier: H265Tier,
+
+    /// `level-id`, level 3.1 if absent
+    pub level: H265Level,
+
+    /// `max-recv-level-id`, highest level the receiver is able to decode if higher than `level-id`
+    pub max_recv_level: Option<H265Level>,
+
+    /// `sprop-max-don-diff`, a value greater than 0 means NAL units carry a decoding order number and may be sent
+    /// out of order, which the payloader and depayloader do not support
+    pub sprop_max_don_diff: u16,
+
+    /// Video parameter sets of `sprop-vps`
+    pub sprop_vps: Vec<Bytes>,
+
+    /// Sequence parameter sets of `sprop-sps`
+    pub sprop_sps: Vec<Bytes>,
+
+    /// Picture parameter sets of `sprop-pps`
+    pub sprop_pps: Vec<Bytes>,
+}
+
+impl Default for H265FmtpOptions {
+    fn default() -> Self {
+        Self {
+            profile_space: 0,
+            profile: H265Profile::Main,
+            tier: H265Tier::Main,
+            level: H265Level::Level3_1,
+            max_recv_level: None,
+            sprop_max_don_diff: 0,
+            sprop_vps: vec![],
+            sprop_sps: vec![],
+            sprop_pps: vec![],
+        }
+    }
+}
+
+impl H265FmtpOptions {
+    /// Parse the fmtp parameters of a H.265 payload type, unknown parameters are ignored
+    ///
+    /// Returns `None` if a known parameter has an invalid value.
+    pub fn parse(fmtp: &str) -> Option<Self> {
+        let mut options = Self::default();
+
+        for param in fmtp.split(';') {
+            let param = param.trim();
+
+            if param.is_empty() {
+                continue;
+            }
+
+            let (name, value) = param.split_once('=')?;
+            let value = value.trim();
+
+            match name.trim().to_ascii_lowercase().as_str() {
+                "profile-space" => options.profile_space = value.parse().ok()?,
+                "profile-id" => options.profile = H265Profile::from_profile_id(value.parse().ok()?),
+                "tier-flag" => options.tier = H265Tier::from_flag(parse_flag(value)?),
+                "level-id" => options.level = H265Level::from_level_id(value.parse().ok()?)?,
+                "max-recv-level-id" => {
+                    options.max_recv_level = Some(H265Level::from_level_id(value.parse().ok()?)?)
+                }
+                "sprop-max-don-diff" => options.sprop_max_don_diff = value.parse().ok()?,
+                "sprop-vps" => options.sprop_vps = parse_parameter_sets(value)?,
+                "sprop-sps" => options.sprop_sps = parse_parameter_sets(value)?,
+                "sprop-pps" => options.sprop_pps = parse_parameter_sets(value)?,
+                _ => {}
+            }
+        }
+
+        Some(options)
+    }
+
+    /// Highest level the receiver is able to decode
+    pub fn max_recv_level(&self) -> H265Level {
+        self.max_recv_level
+            .filter(|level| *level > self.level)
+            .unwrap_or(self.level)
+    }
+}
+
+impl fmt::Display for H265FmtpOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.profile_space != 0 {
+            write!(f, "profile-space={};", self.profile_space)?;
+        }
+
+        write!(
+            f,
+            "profile-id={};tier-flag={};level-id={}",
+            self.profile.profile_id(),
+            u8::from(self.tier.flag()),
+            self.level.level_id()
+        )?;
+
+        if let Some(max_recv_level) = self.max_recv_level {
+            write!(f, ";max-recv-level-id={}", max_recv_level.level_id())?;
+        }
+
+        if self.sprop_max_don_diff != 0 {
+            write!(f, ";sprop-max-don-diff={}", self.sprop_max_don_diff)?;
+        }
+
+        for (name, parameter_sets) in [
+            ("sprop-vps", &self.sprop_vps),
+            ("sprop-sps", &self.sprop_sps),
+            ("sprop-pps", &self.sprop_pps),
+        ] {
+            if parameter_sets.is_empty() {
+                continue;
+            }
+
+            write!(f, ";{name}=")?;
+
+            for (i, parameter_set) in parameter_sets.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+
+                f.write_str(&BASE64_STANDARD.encode(parameter_set))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+fn parse_parameter_sets(value: &str) -> Option<Vec<Bytes>> {
+    value
+        .split(',')
+        .map(|parameter_set| {
+            BASE64_STANDARD
+                .decode(parameter_set.trim())
+                .ok()
+                .map(Bytes::from)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let options = H265FmtpOptions::parse(
+            "profile-id=2; tier-flag=1;level-id=123;max-recv-level-id=153;sprop-vps=QAEMAf//;x-unknown=1",
+        )
+        .unwrap();
+
+        assert_eq!(options.profile, H265Profile::Main10);
+        assert_eq!(options.tier, H265Tier::High);
+        assert_eq!(options.level, H265Level::Level4_1);
+        assert_eq!(options.max_recv_level(), H265Level::Level5_1);
+        assert_eq!(
+            options.sprop_vps,
+            [&[0x40, 0x01, 0x0C, 0x01, 0xFF, 0xFF][..]]
+        );
+        assert!(options.sprop_sps.is_empty());
+
+        assert_eq!(
+            options.to_string(),
+            "profile-id=2;tier-flag=1;level-id=123;max-recv-level-id=153;sprop-vps=QAEMAf//"
+        );
+    }
+
+    #[test]
+    fn defaults() {
+        let options = H265FmtpOptions::parse("").unwrap();
+
+        assert_eq!(options, H265FmtpOptions::default());
+        assert_eq!(options.to_string(), "profile-id=1;tier-flag=0;level-id=93");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(H265FmtpOptions::parse("level-id=94").is_none());
+        assert!(H265FmtpOptions::parse("tier-flag=2").is_none());
+        assert!(H265FmtpOptions::parse("sprop-sps=!!").is_none());
+    }
+}
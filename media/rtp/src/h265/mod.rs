@@ -0,0 +1,319 @@
+use crate::{DePayloader, Payloader};
+use bytes::{BufMut, Bytes, BytesMut};
+
+mod fmtp;
+mod profile;
+
+pub use fmtp::H265FmtpOptions;
+pub use profile::{H265Level, H265LevelLimits, H265Profile, H265Tier};
+
+/// NAL unit type of an aggregation packet (RFC 7798 section 4.4.2)
+const AP: u8 = 48;
+/// NAL unit type of a fragmentation unit (RFC 7798 section 4.4.3)
+const FU: u8 = 49;
+
+/// Size of the H.265 NAL unit header and of the payload header of aggregation and fragmentation packets
+const NAL_HEADER_SIZE: usize = 2;
+/// Size of the length prefix of every NAL unit in an aggregation packet
+const AP_LENGTH_SIZE: usize = 2;
+/// Size of the payload header and FU header
+const FU_HEADER_SIZE: usize = 3;
+
+/// Start code prepended to every NAL unit returned by the [`H265DePayloader`]
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+/// Payloader of H.265 access units in Annex B byte stream format (RFC 7798)
+///
+/// NAL units that fit into a packet are combined into aggregation packets as long as the packet size allows, so
+/// parameter sets (VPS/SPS/PPS) are sent together with the slices following them. NAL units exceeding the packet
+/// size are split into fragmentation units.
+///
+/// NAL units are sent in decoding order without decoding order numbers, as required when the
+/// `sprop-max-don-diff` fmtp parameter is 0 or absent.
+#[derive(Debug, Default)]
+pub struct H265Payloader {}
+
+impl H265Payloader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Payloader for H265Payloader {
+    fn payload(&mut self, frame: &Bytes, max_size: usize) -> impl Iterator<Item = Bytes> + '_ {
+        let mut packets = vec![];
+        let mut aggregate = Aggregate::default();
+
+        for nal in nal_units(frame) {
+            if !aggregate.fits(&nal, max_size) {
+                aggregate.flush(&mut packets);
+
+                if nal.len() > max_size {
+                    fragment(&nal, max_size, &mut packets);
+                    continue;
+                }
+            }
+
+            aggregate.push(nal);
+        }
+
+        aggregate.flush(&mut packets);
+
+        packets.into_iter()
+    }
+}
+
+/// NAL units collected for a single aggregation packet
+#[derive(Default)]
+struct Aggregate {
+    nals: Vec<Bytes>,
+    size: usize,
+}
+
+impl Aggregate {
+    /// Returns if the NAL unit can be added without the packet exceeding `max_size`
+    fn fits(&self, nal: &Bytes, max_size: usize) -> bool {
+        let size = if self.nals.is_empty() {
+            nal.len()
+        } else {
+            NAL_HEADER_SIZE + self.size + AP_LENGTH_SIZE + nal.len()
+        };
+
+        size <= max_size && nal.len() <= usize::from(u16::MAX)
+    }
+
+    fn push(&mut self, nal: Bytes) {
+        self.size += AP_LENGTH_SIZE + nal.len();
+        self.nals.push(nal);
+    }
+
+    /// Emit the collected NAL units, as single NAL unit packet if there is only one
+    fn flush(&mut self, packets: &mut Vec<Bytes>) {
+        let nals = std::mem::take(&mut self.nals);
+        let size = std::mem::take(&mut self.size);
+
+        match nals.len() {
+            0 => {}
+            1 => packets.extend(nals),
+            _ => {
+                // The forbidden bit is set if it is set in any NAL unit, layer id and temporal id are the lowest
+                let forbidden = nals.iter().fold(0, |f, nal| f | (nal[0] & 0x80));
+                let layer_id = nals
+                    .iter()
+                    .map(|nal| layer_id(nal))
+                    .min()
+                    .unwrap_or_default();
+                let tid = nals
+                    .iter()
+                    .map(|nal| nal[1] & 0x07)
+                    .min()
+                    .unwrap_or_default();
+
+                let mut packet = BytesMut::with_capacity(NAL_HEADER_SIZE + size);
+                packet.put_u8(forbidden | (AP << 1) | (layer_id >> 5));
+                packet.put_u8((layer_id << 3) | tid);
+
+                for nal in &nals {
+                    packet.put_u16(nal.len() as u16);
+                    packet.put_slice(nal);
+                }
+
+                packets.push(packet.freeze());
+            }
+        }
+    }
+}
+
+/// Split a NAL unit into fragmentation units of at most `max_size` bytes
+fn fragment(nal: &Bytes, max_size: usize, packets: &mut Vec<Bytes>) {
+    // Payload header is the NAL unit header with the type replaced
+    let header0 = (nal[0] & 0x81) | (FU << 1);
+    let header1 = nal[1];
+    let nal_type = nal_type(nal);
+
+    let chunk_size = max_size.saturating_sub(FU_HEADER_SIZE).max(1);
+    let chunks = nal[NAL_HEADER_SIZE..].chunks(chunk_size);
+    let last = chunks.len().saturating_sub(1);
+
+    for (i, chunk) in chunks.enumerate() {
+        let start = if i == 0 { 0x80 } else { 0 };
+        let end = if i == last { 0x40 } else { 0 };
+
+        let mut packet = BytesMut::with_capacity(FU_HEADER_SIZE + chunk.len());
+        packet.put_u8(header0);
+        packet.put_u8(header1);
+        packet.put_u8(start | end | nal_type);
+        packet.put_slice(chunk);
+
+        packets.push(packet.freeze());
+    }
+}
+
+/// Depayloader of H.265 RTP packets (RFC 7798), returning the NAL units in Annex B byte stream format
+///
+/// Single NAL unit, aggregation and fragmentation unit packets are handled, PACI packets are dropped.
+/// Fragmentation units must be given in sequence number order, a fragmented NAL unit is dropped if one of its
+/// fragments is missing. Decoding order numbers (`sprop-max-don-diff` greater than 0) are not supported.
+#[derive(Debug, Default)]
+pub struct H265DePayloader {
+    /// NAL unit being reassembled from fragmentation units
+    fragmented: Option<BytesMut>,
+}
+
+impl H265DePayloader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn receive(&mut self, payload: &Bytes, output: &mut BytesMut) -> Option<()> {
+        if payload.len() <= NAL_HEADER_SIZE {
+            return None;
+        }
+
+        match nal_type(payload) {
+            0..=47 => write_nal(output, payload),
+            AP => {
+                let mut data = &payload[NAL_HEADER_SIZE..];
+
+                while let [s0, s1, rest @ ..] = data {
+                    let size = usize::from(u16::from_be_bytes([*s0, *s1]));
+
+                    if size == 0 || rest.len() < size {
+                        return None;
+                    }
+
+                    let (nal, rest) = rest.split_at(size);
+                    write_nal(output, nal);
+                    data = rest;
+                }
+            }
+            FU => {
+                let fu_header = payload[NAL_HEADER_SIZE];
+                let data = &payload[FU_HEADER_SIZE..];
+
+                if fu_header & 0x80 != 0 {
+                    let mut nal = BytesMut::with_capacity(NAL_HEADER_SIZE + data.len());
+                    nal.put_u8((payload[0] & 0x81) | ((fu_header & 0x3F) << 1));
+                    nal.put_u8(payload[1]);
+
+                    self.fragmented = Some(nal);
+                }
+
+                let nal = self.fragmented.as_mut()?;
+                nal.put_slice(data);
+
+                if fu_header & 0x40 != 0 {
+                    let nal = self.fragmented.take()?;
+                    write_nal(output, &nal);
+                }
+            }
+            _ => return None,
+        }
+
+        Some(())
+    }
+}
+
+impl DePayloader for H265DePayloader {
+    fn depayload(&mut self, payload: &Bytes) -> Option<Bytes> {
+        let mut output = BytesMut::new();
+
+        // NAL units parsed before an invalid part of the payload are still returned
+        let _ = self.receive(payload, &mut output);
+
+        (!output.is_empty()).then(|| output.freeze())
+    }
+}
+
+fn nal_type(nal: &[u8]) -> u8 {
+    (nal[0] >> 1) & 0x3F
+}
+
+fn layer_id(nal: &[u8]) -> u8 {
+    ((nal[0] & 0x01) << 5) | (nal[1] >> 3)
+}
+
+fn write_nal(output: &mut BytesMut, nal: &[u8]) {
+    output.put_slice(&START_CODE);
+    output.put_slice(nal);
+}
+
+/// Iterate over the NAL units of an Annex B byte stream, NAL units shorter than the NAL unit header are skipped
+fn nal_units(frame: &Bytes) -> impl Iterator<Item = Bytes> + '_ {
+    crate::h264::nal_units(frame).filter(|nal| nal.len() >= NAL_HEADER_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(frame: &[u8], max_size: usize) -> Vec<Bytes> {
+        H265Payloader::new()
+            .payload(&Bytes::copy_from_slice(frame), max_size)
+            .collect()
+    }
+
+    fn depayload(packets: &[Bytes]) -> Vec<u8> {
+        let mut depayloader = H265DePayloader::new();
+
+        packets
+            .iter()
+            .filter_map(|packet| depayloader.depayload(packet))
+            .flatten()
+            .collect()
+    }
+
+    // VPS, SPS, PPS and an IDR slice with temporal id 1
+    const FRAME: [u8; 34] = [
+        0, 0, 0, 1, 0x40, 0x01, 1, 2, //
+        0, 0, 0, 1, 0x42, 0x01, 3, //
+        0, 0, 0, 1, 0x44, 0x01, 4, //
+        0, 0, 0, 1, 0x26, 0x01, 1, 2, 3, 4, 5, 6,
+    ];
+
+    #[test]
+    fn aggregation_packet() {
+        let packets = payload(&FRAME, 24);
+
+        let expected = [
+            &[AP << 1, 0x01][..],
+            &[0, 4, 0x40, 0x01, 1, 2],
+            &[0, 3, 0x42, 0x01, 3],
+            &[0, 3, 0x44, 0x01, 4],
+        ]
+        .concat();
+
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0], expected);
+        assert_eq!(packets[1], &[0x26, 0x01, 1, 2, 3, 4, 5, 6][..]);
+    }
+
+    #[test]
+    fn fragmentation_unit() {
+        let packets = payload(&FRAME[22..], 6);
+
+        assert_eq!(
+            packets,
+            [
+                &[FU << 1, 0x01, 0x80 | 19, 1, 2, 3][..],
+                &[FU << 1, 0x01, 0x40 | 19, 4, 5, 6],
+            ]
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        for max_size in [6, 10, 24, 1200] {
+            assert_eq!(depayload(&payload(&FRAME, max_size)), FRAME, "{max_size}");
+        }
+    }
+
+    #[test]
+    fn missing_fragment() {
+        let packets = payload(&FRAME[22..], 5);
+        assert_eq!(packets.len(), 3);
+
+        // Without the start fragment the NAL unit is dropped
+        assert!(depayload(&packets[1..]).is_empty());
+    }
+}
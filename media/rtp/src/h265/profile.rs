@@ -0,0 +1,235 @@
+use std::fmt;
+
+/// H.265 profile, identified by `general_profile_idc` and the `profile-id` fmtp parameter (H.265 Annex A.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum H265Profile {
+    Main,
+    Main10,
+    MainStillPicture,
+    /// Format range extensions profiles, e.g. Main 4:2:2 10 or Main 4:4:4
+    RangeExtensions,
+    HighThroughput,
+    ScreenContentCoding,
+    Other(u8),
+}
+
+impl H265Profile {
+    pub fn from_profile_id(profile_id: u8) -> Self {
+        match profile_id {
+            1 => Self::Main,
+            2 => Self::Main10,
+            3 => Self::MainStillPicture,
+            4 => Self::RangeExtensions,
+            5 => Self::HighThroughput,
+            9 => Self::ScreenContentCoding,
+            other => Self::Other(other),
+        }
+    }
+
+    pub fn profile_id(self) -> u8 {
+        match self {
+            Self::Main => 1,
+            Self::Main10 => 2,
+            Self::MainStillPicture => 3,
+            Self::RangeExtensions => 4,
+            Self::HighThroughput => 5,
+            Self::ScreenContentCoding => 9,
+            Self::Other(other) => other,
+        }
+    }
+}
+
+/// H.265 tier, the high tier allows higher bitrates for levels 4 and above
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum H265Tier {
+    #[default]
+    Main,
+    High,
+}
+
+impl H265Tier {
+    /// Tier of the `tier-flag` fmtp parameter
+    pub fn from_flag(flag: bool) -> Self {
+        if flag {
+            Self::High
+        } else {
+            Self::Main
+        }
+    }
+
+    pub fn flag(self) -> bool {
+        self == Self::High
+    }
+}
+
+/// H.265 level, the `level-id` fmtp parameter is 30 times the level number (e.g. `93` for level 3.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum H265Level {
+    Level1,
+    Level2,
+    Level2_1,
+    Level3,
+    Level3_1,
+    Level4,
+    Level4_1,
+    Level5,
+    Level5_1,
+    Level5_2,
+    Level6,
+    Level6_1,
+    Level6_2,
+}
+
+/// Limits of a [`H265Level`] (H.265 Tables A.8 and A.9)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct H265LevelLimits {
+    /// Maximum picture size in luma samples
+    pub max_luma_picture_size: u32,
+
+    /// Maximum luma sample rate in samples per second
+    pub max_luma_sample_rate: u64,
+
+    /// Maximum bitrate of the main tier in kbit/s, for VCL data of the Main profile
+    pub max_bitrate_main: u32,
+
+    /// Maximum bitrate of the high tier in kbit/s, `None` for levels below 4 which have no high tier
+    pub max_bitrate_high: Option<u32>,
+
+    /// Maximum number of slice segments per picture
+    pub max_slice_segments: u32,
+}
+
+/// Level, `level-id` and limits of all levels
+#[rustfmt::skip]
+const LEVELS: [(H265Level, u8, H265LevelLimits); 13] = [
+    (H265Level::Level1,   30,  limits(36_864,     552_960,       128,     None,          16)),
+    (H265Level::Level2,   60,  limits(122_880,    3_686_400,     1_500,   None,          16)),
+    (H265Level::Level2_1, 63,  limits(245_760,    7_372_800,     3_000,   None,          20)),
+    (H265Level::Level3,   90,  limits(552_960,    16_588_800,    6_000,   None,          30)),
+    (H265Level::Level3_1, 93,  limits(983_040,    33_177_600,    10_000,  None,          40)),
+    (H265Level::Level4,   120, limits(2_228_224,  66_846_720,    12_000,  Some(30_000),  75)),
+    (H265Level::Level4_1, 123, limits(2_228_224,  133_693_440,   20_000,  Some(50_000),  75)),
+    (H265Level::Level5,   150, limits(8_912_896,  267_386_880,   25_000,  Some(100_000), 200)),
+    (H265Level::Level5_1, 153, limits(8_912_896,  534_773_760,   40_000,  Some(160_000), 200)),
+    (H265Level::Level5_2, 156, limits(8_912_896,  1_069_547_520, 60_000,  Some(240_000), 200)),
+    (H265Level::Level6,   180, limits(35_651_584, 1_069_547_520, 60_000,  Some(240_000), 600)),
+    (H265Level::Level6_1, 183, limits(35_651_584, 2_139_095_040, 120_000, Some(480_000), 600)),
+    (H265Level::Level6_2, 186, limits(35_651_584, 4_278_190_080, 240_000, Some(800_000), 600)),
+];
+
+const fn limits(
+    max_luma_picture_size: u32,
+    max_luma_sample_rate: u64,
+    max_bitrate_main: u32,
+    max_bitrate_high: Option<u32>,
+    max_slice_segments: u32,
+) -> H265LevelLimits {
+    H265LevelLimits {
+        max_luma_picture_size,
+        max_luma_sample_rate,
+        max_bitrate_main,
+        max_bitrate_high,
+        max_slice_segments,
+    }
+}
+
+impl H265Level {
+    /// Level of the `level-id` fmtp parameter, `None` for unknown values
+    pub fn from_level_id(level_id: u8) -> Option<Self> {
+        LEVELS
+            .iter()
+            .find(|(_, id, _)| *id == level_id)
+            .map(|(level, _, _)| *level)
+    }
+
+    pub fn level_id(self) -> u8 {
+        self.entry().1
+    }
+
+    pub fn limits(self) -> H265LevelLimits {
+        self.entry().2
+    }
+
+    /// Maximum bitrate in kbit/s of the level in the given tier
+    pub fn max_bitrate(self, tier: H265Tier) -> u32 {
+        let limits = self.limits();
+
+        match tier {
+            H265Tier::Main => limits.max_bitrate_main,
+            H265Tier::High => limits.max_bitrate_high.unwrap_or(limits.max_bitrate_main),
+        }
+    }
+
+    /// Returns if a resolution at the given framerate is within the picture size and sample rate limits
+    pub fn supports(self, width: u32, height: u32, framerate: u32) -> bool {
+        let limits = self.limits();
+        let picture_size = u64::from(width) * u64::from(height);
+
+        picture_size <= u64::from(limits.max_luma_picture_size)
+            && picture_size * u64::from(framerate) <= limits.max_luma_sample_rate
+    }
+
+    /// Lowest level supporting the resolution at the given framerate
+    pub fn lowest_supporting(width: u32, height: u32, framerate: u32) -> Option<Self> {
+        LEVELS
+            .iter()
+            .map(|(level, _, _)| *level)
+            .find(|level| level.supports(width, height, framerate))
+    }
+
+    fn entry(self) -> &'static (H265Level, u8, H265LevelLimits) {
+        LEVELS
+            .iter()
+            .find(|(level, _, _)| *level == self)
+            .expect("all levels are in the table")
+    }
+}
+
+impl fmt::Display for H265Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level_id = self.level_id();
+
+        if level_id.is_multiple_of(30) {
+            write!(f, "{}", level_id / 30)
+        } else {
+            write!(f, "{}.{}", level_id / 30, (level_id % 30) / 3)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_ids() {
+        assert_eq!(H265Level::from_level_id(93), Some(H265Level::Level3_1));
+        assert_eq!(H265Level::from_level_id(94), None);
+        assert_eq!(H265Level::Level5_1.level_id(), 153);
+        assert_eq!(H265Level::Level3_1.to_string(), "3.1");
+        assert_eq!(H265Level::Level4.to_string(), "4");
+    }
+
+    #[test]
+    fn level_limits() {
+        assert_eq!(
+            H265Level::lowest_supporting(1280, 720, 30),
+            Some(H265Level::Level3_1)
+        );
+        assert_eq!(
+            H265Level::lowest_supporting(1920, 1080, 60),
+            Some(H265Level::Level4_1)
+        );
+        assert_eq!(H265Level::lowest_supporting(16384, 16384, 30), None);
+
+        assert_eq!(H265Level::Level3.max_bitrate(H265Tier::High), 6_000);
+        assert_eq!(H265Level::Level4.max_bitrate(H265Tier::High), 30_000);
+    }
+
+    #[test]
+    fn profile_ids() {
+        assert_eq!(H265Profile::from_profile_id(2), H265Profile::Main10);
+        assert_eq!(H265Profile::from_profile_id(42), H265Profile::Other(42));
+        assert_eq!(H265Profile::ScreenContentCoding.profile_id(), 9);
+    }
+}
@@ -1,14 +1,76 @@
 use bytes::Bytes;
 
+mod abs_capture_time;
+mod answering_machine;
+mod audio_file_source;
+mod audio_level;
+mod audio_mixer;
+mod audio_packetizer;
+mod bandwidth_prober;
+mod bitrate_allocator;
+mod call_progress;
+mod codec_downshift;
+mod congestion_control;
 mod extensions;
+mod g711;
+mod h264;
+mod h265;
 mod ntp_timestamp;
+mod prompt_player;
+mod quality_monitor;
+mod remb;
+mod rewriter;
+mod ringback;
 mod rtp_packet;
+mod rtpdump;
+mod rtx;
 mod session;
-
+mod telephone_event;
+mod tone_detector;
+mod vp8;
+mod wav;
+
+pub use abs_capture_time::AbsCaptureTime;
+pub use answering_machine::{
+    AnsweringMachineDetector, AnsweringMachineVerdict, EnergyAmdConfig,
+    EnergyAnsweringMachineDetector,
+};
+pub use audio_file_source::AudioFileSource;
+pub use audio_level::{AudioLevel, AudioLevelMeter, AudioLevelReport};
+pub use audio_mixer::AudioMixer;
+pub use audio_packetizer::AudioPacketizer;
+pub use bandwidth_prober::{BandwidthProber, ProbeConfig};
+pub use bitrate_allocator::{BitrateAllocation, BitrateAllocator};
+pub use call_progress::{CallProgressDetector, CallProgressTone};
+pub use codec_downshift::{CodecDownshiftPolicy, DownshiftConfig, DownshiftDecision};
+pub use congestion_control::{CongestionController, GccConfig, GccController, PacketFeedback};
 pub use extensions::{parse_extensions, RtpExtensionsWriter};
+pub use g711::G711;
+pub use h264::{
+    H264DePayloader, H264DePayloaderOutputFormat, H264PacketizationMode, H264Payloader,
+};
+pub use h265::{
+    H265DePayloader, H265FmtpOptions, H265Level, H265LevelLimits, H265Payloader, H265Profile,
+    H265Tier,
+};
 pub use ntp_timestamp::NtpTimestamp;
+pub use prompt_player::{PromptId, PromptMode, PromptPlayer};
+pub use quality_monitor::{
+    QualityAlert, QualityMetric, QualityMonitor, QualitySample, QualityThreshold,
+};
+pub use remb::{Remb, RembEstimator};
+pub use rewriter::RtpRewriter;
+pub use ringback::{RingbackGenerator, RingbackRegion};
 pub use rtp_packet::{RtpExtensionIds, RtpExtensions, RtpPacket};
-pub use session::RtpSession;
+pub use rtpdump::RtpDumpWriter;
+pub use rtx::{rtx_decode, NackConfig, NackGenerator, RtxSender, DEFAULT_RTX_BUFFER_SIZE};
+pub use session::{JitterBufferConfig, JitterBufferMode, JitterBufferStats, RtpSession};
+pub use telephone_event::{DtmfReceiver, DtmfSender, TelephoneEvent, TelephoneEvents, FLASH_HOOK};
+pub use tone_detector::{Tone, ToneDetector};
+pub use vp8::{
+    vp8_is_keyframe, vp8_payload_is_keyframe, Vp8DePayloader, Vp8PayloadDescriptor, Vp8Payloader,
+};
+pub use wav::{WavAudio, WavError};
 
 pub use rtcp_types;
 pub use rtp_types;
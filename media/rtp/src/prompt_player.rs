@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+
+/// Identifies a prompt queued in a [`PromptPlayer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PromptId(u64);
+
+/// How a prompt is inserted into the audio it is played into
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PromptMode {
+    /// Mix the prompt with the audio, e.g. to keep the conversation going during a short announcement
+    Mix,
+
+    /// Replace the audio with the prompt
+    #[default]
+    Replace,
+}
+
+/// Inserts audio prompts (announcements) into an audio stream
+///
+/// Prompts are played one after another in the order they were queued. Once a prompt starts playing its id is
+/// returned by [`pop_started`](Self::pop_started), once it is played completely by
+/// [`pop_finished`](Self::pop_finished).
+#[derive(Debug, Default)]
+pub struct PromptPlayer {
+    next_id: u64,
+    queue: VecDeque<Prompt>,
+    started: VecDeque<(PromptId, usize)>,
+    finished: VecDeque<(PromptId, usize)>,
+}
+
+#[derive(Debug)]
+struct Prompt {
+    id: PromptId,
+    samples: Vec<i16>,
+    position: usize,
+    mode: PromptMode,
+}
+
+impl PromptPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a prompt of decoded 16 bit PCM samples, which must have the same sample rate as the audio it is
+    /// played into
+    pub fn play(&mut self, samples: Vec<i16>, mode: PromptMode) -> PromptId {
+        let id = PromptId(self.next_id);
+        self.next_id += 1;
+
+        self.queue.push_back(Prompt {
+            id,
+            samples,
+            position: 0,
+            mode,
+        });
+
+        id
+    }
+
+    /// Stop and remove all queued prompts, they are not reported as finished
+    pub fn stop(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Returns if any prompt is currently playing
+    pub fn is_playing(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Insert the queued prompts into the given samples
+    pub fn process_samples(&mut self, samples: &mut [i16]) {
+        let total = samples.len();
+        let mut samples = samples;
+
+        while let Some(prompt) = self.queue.front_mut() {
+            let offset = total - samples.len();
+
+            if prompt.position == 0 {
+                self.started.push_back((prompt.id, offset));
+            }
+
+            let remaining = &prompt.samples[prompt.position..];
+            let len = remaining.len().min(samples.len());
+
+            let (current, rest) = samples.split_at_mut(len);
+
+            match prompt.mode {
+                PromptMode::Mix => {
+                    for (sample, prompt_sample) in current.iter_mut().zip(remaining) {
+                        *sample = sample.saturating_add(*prompt_sample);
+                    }
+                }
+                PromptMode::Replace => current.copy_from_slice(&remaining[..len]),
+            }
+
+            prompt.position += len;
+            samples = rest;
+
+            if prompt.position == prompt.samples.len() {
+                self.finished.push_back((prompt.id, offset + len));
+                self.queue.pop_front();
+            }
+
+            if samples.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Returns the id of the next prompt that started playing, with the index of its first sample in the samples
+    /// passed to [`process_samples`](Self::process_samples)
+    pub fn pop_started(&mut self) -> Option<(PromptId, usize)> {
+        self.started.pop_front()
+    }
+
+    /// Returns the id of the next prompt that finished playing, with the index after its last sample in the samples
+    /// passed to [`process_samples`](Self::process_samples)
+    pub fn pop_finished(&mut self) -> Option<(PromptId, usize)> {
+        self.finished.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_and_mix() {
+        let mut player = PromptPlayer::new();
+
+        let first = player.play(vec![1; 5], PromptMode::Replace);
+        let second = player.play(vec![10; 4], PromptMode::Mix);
+
+        let mut samples = [100; 4];
+        player.process_samples(&mut samples);
+        assert_eq!(samples, [1, 1, 1, 1]);
+        assert_eq!(player.pop_started(), Some((first, 0)));
+        assert_eq!(player.pop_finished(), None);
+
+        let mut samples = [100; 4];
+        player.process_samples(&mut samples);
+        assert_eq!(samples, [1, 110, 110, 110]);
+        assert_eq!(player.pop_finished(), Some((first, 1)));
+        assert_eq!(player.pop_finished(), None);
+        assert_eq!(player.pop_started(), Some((second, 1)));
+
+        let mut samples = [100; 4];
+        player.process_samples(&mut samples);
+        assert_eq!(samples, [110, 100, 100, 100]);
+        assert_eq!(player.pop_finished(), Some((second, 1)));
+        assert_eq!(player.pop_started(), None);
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn stop() {
+        let mut player = PromptPlayer::new();
+        player.play(vec![1; 5], PromptMode::Replace);
+
+        player.stop();
+
+        let mut samples = [100; 4];
+        player.process_samples(&mut samples);
+        assert_eq!(samples, [100; 4]);
+        assert_eq!(player.pop_finished(), None);
+    }
+}
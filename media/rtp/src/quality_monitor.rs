@@ -0,0 +1,273 @@
+use std::time::{Duration, Instant};
+
+/// Call quality metric evaluated by the [`QualityMonitor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QualityMetric {
+    /// Packet loss in percent
+    Loss,
+
+    /// Interarrival jitter in milliseconds
+    Jitter,
+
+    /// Round trip time in milliseconds
+    Rtt,
+
+    /// Estimated mean opinion score (1.0 - 4.5), derived from loss, jitter and round trip time
+    Mos,
+}
+
+impl QualityMetric {
+    /// Returns if higher values of the metric mean worse quality
+    pub fn higher_is_worse(self) -> bool {
+        !matches!(self, QualityMetric::Mos)
+    }
+}
+
+/// Threshold of a [`QualityMetric`] registered on the [`QualityMonitor`]
+///
+/// For MOS the alert is raised at or below `alert` and cleared at or above `clear`, for all other metrics the
+/// alert is raised at or above `alert` and cleared at or below `clear`.
+#[derive(Debug, Clone)]
+pub struct QualityThreshold {
+    pub metric: QualityMetric,
+
+    /// Value at which the alert is raised
+    pub alert: f32,
+
+    /// Value at which the alert is cleared again
+    pub clear: f32,
+
+    /// How long the value must stay beyond `alert` before raising the alert
+    pub alert_after: Duration,
+
+    /// How long the value must stay beyond `clear` before clearing the alert
+    pub clear_after: Duration,
+}
+
+impl QualityThreshold {
+    pub fn new(metric: QualityMetric, alert: f32, clear: f32) -> Self {
+        Self {
+            metric,
+            alert,
+            clear,
+            alert_after: Duration::ZERO,
+            clear_after: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Measurement of the call quality, e.g. from a RTCP report block
+#[derive(Debug, Clone, Copy)]
+pub struct QualitySample {
+    /// Packet loss in percent
+    pub loss: f32,
+
+    /// Interarrival jitter in milliseconds
+    pub jitter: f32,
+
+    /// Round trip time in milliseconds, if known
+    pub rtt: Option<f32>,
+}
+
+impl QualitySample {
+    /// Estimate the mean opinion score using a simplified E-model (ITU-T G.107)
+    pub fn mos(&self) -> f32 {
+        let effective_latency = self.rtt.unwrap_or_default() / 2.0 + self.jitter * 2.0 + 10.0;
+
+        let r = if effective_latency < 160.0 {
+            93.2 - effective_latency / 40.0
+        } else {
+            93.2 - (effective_latency - 120.0) / 10.0
+        };
+
+        let r = (r - self.loss * 2.5).clamp(0.0, 100.0);
+
+        1.0 + 0.035 * r + 0.000007 * r * (r - 60.0) * (100.0 - r)
+    }
+
+    fn value(&self, metric: QualityMetric) -> Option<f32> {
+        match metric {
+            QualityMetric::Loss => Some(self.loss),
+            QualityMetric::Jitter => Some(self.jitter),
+            QualityMetric::Rtt => self.rtt,
+            QualityMetric::Mos => Some(self.mos()),
+        }
+    }
+}
+
+/// Alert raised or cleared by the [`QualityMonitor`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityAlert {
+    pub metric: QualityMetric,
+
+    /// `true` if the alert was raised, `false` if it was cleared
+    pub active: bool,
+
+    /// Value of the metric which caused the change
+    pub value: f32,
+}
+
+/// Evaluates [`QualitySample`]s against registered thresholds
+///
+/// Uses hysteresis so values fluctuating around a threshold don't cause constant alerts.
+#[derive(Debug, Default)]
+pub struct QualityMonitor {
+    thresholds: Vec<ThresholdState>,
+}
+
+#[derive(Debug)]
+struct ThresholdState {
+    threshold: QualityThreshold,
+    active: bool,
+
+    /// Since when the value has been beyond the threshold of the current state
+    since: Option<Instant>,
+}
+
+impl QualityMonitor {
+    pub fn new(thresholds: Vec<QualityThreshold>) -> Self {
+        Self {
+            thresholds: thresholds
+                .into_iter()
+                .map(|threshold| ThresholdState {
+                    threshold,
+                    active: false,
+                    since: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns if any alert is currently raised
+    pub fn is_alerting(&self) -> bool {
+        self.thresholds.iter().any(|state| state.active)
+    }
+
+    /// Process a new measurement, returns the alerts which were raised or cleared
+    pub fn report(&mut self, now: Instant, sample: QualitySample) -> Vec<QualityAlert> {
+        let mut alerts = vec![];
+
+        for state in &mut self.thresholds {
+            let threshold = &state.threshold;
+
+            let Some(value) = sample.value(threshold.metric) else {
+                continue;
+            };
+
+            let (limit, hold) = if state.active {
+                (threshold.clear, threshold.clear_after)
+            } else {
+                (threshold.alert, threshold.alert_after)
+            };
+
+            // Raising the alert requires the value to be at least as bad as the limit, clearing it to be at least
+            // as good as the limit
+            let beyond_threshold = if threshold.metric.higher_is_worse() != state.active {
+                value >= limit
+            } else {
+                value <= limit
+            };
+
+            if !beyond_threshold {
+                state.since = None;
+                continue;
+            }
+
+            let since = *state.since.get_or_insert(now);
+
+            if now.duration_since(since) < hold {
+                continue;
+            }
+
+            state.since = None;
+            state.active = !state.active;
+
+            alerts.push(QualityAlert {
+                metric: threshold.metric,
+                active: state.active,
+                value,
+            });
+        }
+
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(loss: f32) -> QualitySample {
+        QualitySample {
+            loss,
+            jitter: 5.0,
+            rtt: Some(50.0),
+        }
+    }
+
+    #[test]
+    fn alert_and_clear() {
+        let mut monitor =
+            QualityMonitor::new(vec![QualityThreshold::new(QualityMetric::Loss, 5.0, 1.0)]);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(monitor.report(at(0), sample(2.0)), vec![]);
+        assert_eq!(
+            monitor.report(at(5), sample(8.0)),
+            vec![QualityAlert {
+                metric: QualityMetric::Loss,
+                active: true,
+                value: 8.0
+            }]
+        );
+        assert!(monitor.is_alerting());
+
+        // Between the thresholds, stays alerting
+        assert_eq!(monitor.report(at(10), sample(3.0)), vec![]);
+        assert_eq!(monitor.report(at(15), sample(0.5)), vec![]);
+        assert_eq!(monitor.report(at(20), sample(0.0)), vec![]);
+        assert_eq!(
+            monitor.report(at(25), sample(0.0)),
+            vec![QualityAlert {
+                metric: QualityMetric::Loss,
+                active: false,
+                value: 0.0
+            }]
+        );
+        assert!(!monitor.is_alerting());
+    }
+
+    #[test]
+    fn mos_alert() {
+        let mut monitor =
+            QualityMonitor::new(vec![QualityThreshold::new(QualityMetric::Mos, 3.5, 4.0)]);
+        let now = Instant::now();
+
+        assert!(sample(0.0).mos() > 4.0);
+        assert_eq!(monitor.report(now, sample(0.0)), vec![]);
+
+        let alerts = monitor.report(now, sample(15.0));
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].active);
+        assert!(alerts[0].value < 3.5);
+    }
+
+    #[test]
+    fn unknown_rtt_is_ignored() {
+        let mut monitor = QualityMonitor::new(vec![QualityThreshold::new(
+            QualityMetric::Rtt,
+            300.0,
+            200.0,
+        )]);
+
+        let sample = QualitySample {
+            loss: 0.0,
+            jitter: 0.0,
+            rtt: None,
+        };
+
+        assert_eq!(monitor.report(Instant::now(), sample), vec![]);
+        assert!(!monitor.is_alerting());
+    }
+}
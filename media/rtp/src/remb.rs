@@ -0,0 +1,329 @@
+use crate::{CongestionController, GccConfig, GccController, PacketFeedback, RtpTimestamp};
+use std::time::{Duration, Instant};
+
+/// RTCP packet type of payload-specific feedback
+const PSFB: u8 = 206;
+/// Feedback message type of application layer feedback
+const AFB: u8 = 15;
+/// Unique identifier of REMB in the application layer feedback
+const REMB_IDENTIFIER: &[u8; 4] = b"REMB";
+
+/// How often the received packets are handed to the delay based estimator
+const FEEDBACK_INTERVAL: Duration = Duration::from_millis(100);
+/// Maximum interval between two REMB messages
+const REMB_INTERVAL: Duration = Duration::from_secs(1);
+/// A decrease of the estimate by more than this fraction is reported immediately
+const REMB_DECREASE_THRESHOLD: f64 = 0.03;
+
+/// Receiver estimated maximum bitrate, RTCP application layer feedback (draft-alvestrand-rmcat-remb)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remb {
+    /// Estimated available bitrate in bits per second
+    pub bitrate: u64,
+
+    /// SSRCs of the media streams the estimate applies to
+    pub ssrcs: Vec<u32>,
+}
+
+impl Remb {
+    /// Find a REMB message in a (decrypted) RTCP compound packet
+    pub fn find_in_compound(mut data: &[u8]) -> Option<Self> {
+        while data.len() >= 4 {
+            let len = (usize::from(u16::from_be_bytes([data[2], data[3]])) + 1) * 4;
+
+            let packet = data.get(..len)?;
+            data = &data[len..];
+
+            if packet[1] == PSFB && packet[0] & 0x1F == AFB {
+                if let Some(remb) = Self::parse(packet) {
+                    return Some(remb);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Parse a single RTCP application layer feedback packet
+    fn parse(packet: &[u8]) -> Option<Self> {
+        // Header, sender SSRC and media SSRC, followed by the identifier
+        let fci = packet.get(12..)?;
+
+        if fci.get(..4)? != REMB_IDENTIFIER {
+            return None;
+        }
+
+        let [num_ssrc, b0, b1, b2] = *fci.get(4..8)? else {
+            return None;
+        };
+
+        let exponent = b0 >> 2;
+        let mantissa = u64::from_be_bytes([0, 0, 0, 0, 0, b0 & 0x03, b1, b2]);
+
+        let ssrcs = fci
+            .get(8..8 + usize::from(num_ssrc) * 4)?
+            .chunks_exact(4)
+            .map(|ssrc| u32::from_be_bytes([ssrc[0], ssrc[1], ssrc[2], ssrc[3]]))
+            .collect();
+
+        Some(Self {
+            bitrate: mantissa.checked_shl(u32::from(exponent))?,
+            ssrcs,
+        })
+    }
+
+    /// Write the REMB message as a RTCP packet, sent using the given sender SSRC
+    pub fn to_vec(&self, sender_ssrc: u32) -> Vec<u8> {
+        let ssrcs = &self.ssrcs[..self.ssrcs.len().min(usize::from(u8::MAX))];
+
+        let mut mantissa = self.bitrate;
+        let mut exponent = 0u8;
+
+        while mantissa > 0x3FFFF {
+            mantissa >>= 1;
+            exponent += 1;
+        }
+
+        let len = 20 + ssrcs.len() * 4;
+
+        let mut packet = Vec::with_capacity(len);
+        packet.extend_from_slice(&[0x80 | AFB, PSFB]);
+        packet.extend_from_slice(&((len / 4 - 1) as u16).to_be_bytes());
+        packet.extend_from_slice(&sender_ssrc.to_be_bytes());
+        // The media source SSRC is unused
+        packet.extend_from_slice(&[0; 4]);
+        packet.extend_from_slice(REMB_IDENTIFIER);
+        packet.push(ssrcs.len() as u8);
+        packet.push((exponent << 2) | (mantissa >> 16) as u8);
+        packet.extend_from_slice(&(mantissa as u16).to_be_bytes());
+
+        for ssrc in ssrcs {
+            packet.extend_from_slice(&ssrc.to_be_bytes());
+        }
+
+        packet
+    }
+}
+
+/// Packets of the same RTP timestamp (e.g. a video frame), which were sent at the same time
+#[derive(Debug, Clone, Copy)]
+struct PacketGroup {
+    send_time: Instant,
+    arrival_time: Duration,
+    size: usize,
+}
+
+/// Receive side bandwidth estimation, producing the bitrate to report to the sender using [`Remb`] messages
+///
+/// The send time of a packet is derived from its RTP timestamp, so all packets of a frame form a group. The delay
+/// variation between the groups is evaluated using the delay based [`GccController`].
+#[derive(Debug)]
+pub struct RembEstimator {
+    controller: GccController,
+    clock_rate: u32,
+
+    /// Arrival time reference & the instant the first received RTP timestamp maps to
+    reference: Instant,
+    /// Last received RTP timestamp and its extended value relative to the first one
+    last_timestamp: Option<(RtpTimestamp, i64)>,
+
+    group: Option<(i64, PacketGroup)>,
+    feedback: Vec<PacketFeedback>,
+    next_feedback: Option<Instant>,
+
+    /// Last reported bitrate and when it was reported
+    reported: Option<(u32, Instant)>,
+}
+
+impl RembEstimator {
+    pub fn new(clock_rate: u32, config: GccConfig) -> Self {
+        Self {
+            controller: GccController::new(config),
+            clock_rate,
+            reference: Instant::now(),
+            last_timestamp: None,
+            group: None,
+            feedback: vec![],
+            next_feedback: None,
+            reported: None,
+        }
+    }
+
+    /// Register a received RTP packet with the given timestamp and size in bytes
+    pub fn receive(&mut self, now: Instant, timestamp: RtpTimestamp, size: usize) {
+        let extended = match self.last_timestamp {
+            Some((last, last_extended)) => {
+                last_extended + i64::from(timestamp.0.wrapping_sub(last.0) as i32)
+            }
+            None => {
+                self.reference = now;
+                self.next_feedback = Some(now + FEEDBACK_INTERVAL);
+                0
+            }
+        };
+
+        self.last_timestamp = Some((timestamp, extended));
+
+        let arrival_time = now.saturating_duration_since(self.reference);
+
+        match &mut self.group {
+            Some((group_timestamp, group)) if *group_timestamp == extended => {
+                group.arrival_time = arrival_time;
+                group.size += size;
+                return;
+            }
+            // Packets of a previous frame arriving late don't start a new group
+            Some((group_timestamp, _)) if *group_timestamp > extended => return,
+            _ => {}
+        }
+
+        let Ok(offset) = u64::try_from(extended) else {
+            // Reordered before the first packet
+            return;
+        };
+
+        let send_time = self.reference
+            + Duration::from_secs_f64(offset as f64 / f64::from(self.clock_rate.max(1)));
+
+        let previous = self.group.replace((
+            extended,
+            PacketGroup {
+                send_time,
+                arrival_time,
+                size,
+            },
+        ));
+
+        if let Some((_, group)) = previous {
+            self.feedback.push(PacketFeedback {
+                send_time: group.send_time,
+                arrival_time: Some(group.arrival_time),
+                size: group.size,
+            });
+        }
+    }
+
+    /// Current estimate in bits per second
+    pub fn estimate(&self) -> u32 {
+        self.controller.target_bitrate()
+    }
+
+    /// Time until [`poll`](Self::poll) must be called
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.next_feedback
+            .map(|next| next.saturating_duration_since(now))
+    }
+
+    /// Update the estimate, returns the bitrate to report if a REMB message is due
+    ///
+    /// A REMB is due every second or as soon as the estimate decreased significantly.
+    pub fn poll(&mut self, now: Instant) -> Option<u32> {
+        let next_feedback = self.next_feedback?;
+
+        if next_feedback > now {
+            return None;
+        }
+
+        self.next_feedback = Some(now + FEEDBACK_INTERVAL);
+
+        if !self.feedback.is_empty() {
+            self.controller.on_feedback(now, &self.feedback);
+            self.feedback.clear();
+        }
+
+        let estimate = self.estimate();
+
+        let due = match self.reported {
+            None => true,
+            Some((reported, at)) => {
+                now.duration_since(at) >= REMB_INTERVAL
+                    || f64::from(estimate) < f64::from(reported) * (1.0 - REMB_DECREASE_THRESHOLD)
+            }
+        };
+
+        if due {
+            self.reported = Some((estimate, now));
+            Some(estimate)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remb_roundtrip() {
+        let remb = Remb {
+            bitrate: 2_500_000,
+            ssrcs: vec![0x1234_5678, 0x9ABC_DEF0],
+        };
+
+        let packet = remb.to_vec(0xAABB_CCDD);
+        assert_eq!(packet.len(), 28);
+        assert_eq!(&packet[..4], &[0x8F, 206, 0, 6]);
+        assert_eq!(&packet[12..16], b"REMB");
+
+        let parsed = Remb::find_in_compound(&packet).unwrap();
+
+        // The mantissa looses the lowest bits of large bitrates
+        assert_eq!(parsed.ssrcs, remb.ssrcs);
+        assert!(remb.bitrate - parsed.bitrate < 1 << 4);
+    }
+
+    #[test]
+    fn find_in_compound() {
+        // Receiver report without report blocks, followed by the REMB
+        let mut compound = vec![0x80, 201, 0, 1, 0, 0, 0, 1];
+        compound.extend(
+            Remb {
+                bitrate: 64_000,
+                ssrcs: vec![7],
+            }
+            .to_vec(1),
+        );
+
+        assert_eq!(
+            Remb::find_in_compound(&compound),
+            Some(Remb {
+                bitrate: 64_000,
+                ssrcs: vec![7]
+            })
+        );
+
+        assert_eq!(Remb::find_in_compound(&compound[..8]), None);
+        assert_eq!(Remb::find_in_compound(&compound[..20]), None);
+    }
+
+    #[test]
+    fn estimator_reports() {
+        let start = Instant::now();
+        let mut estimator = RembEstimator::new(90_000, GccConfig::default());
+
+        assert_eq!(estimator.timeout(start), None);
+
+        // 30 frames per second of 3 packets each, arriving without delay variation
+        for frame in 0..30u32 {
+            let arrival = start + Duration::from_millis(u64::from(frame) * 33);
+
+            for _ in 0..3 {
+                estimator.receive(arrival, RtpTimestamp(frame * 3000), 1000);
+            }
+
+            if frame == 0 {
+                assert_eq!(estimator.poll(arrival), None);
+            }
+        }
+
+        let first = estimator.poll(start + Duration::from_millis(100)).unwrap();
+        assert_eq!(first, GccConfig::default().initial_bitrate);
+
+        // Not due again until a second has passed without a decrease
+        assert_eq!(estimator.poll(start + Duration::from_millis(200)), None);
+        assert!(estimator
+            .poll(start + Duration::from_millis(1100))
+            .is_some());
+    }
+}
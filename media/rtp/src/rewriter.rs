@@ -0,0 +1,226 @@
+use crate::{RtpPacket, RtpTimestamp, SequenceNumber, Ssrc};
+use bytes::Bytes;
+use std::time::Instant;
+
+/// Rewrites RTP packets of one or more source streams into a single continuous stream
+///
+/// Used when forwarding packets from one RTP session to another (e.g. in a SFU or B2BUA media relay).
+/// The SSRC, mid and optionally the payload type of every packet are replaced, while sequence numbers
+/// and timestamps are offset so that the outgoing stream stays continuous when switching between sources.
+#[derive(Debug)]
+pub struct RtpRewriter {
+    ssrc: Ssrc,
+    clock_rate: u32,
+    mid: Option<Bytes>,
+    pt: Option<u8>,
+
+    source: Option<Source>,
+    last_sent: Option<LastSent>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Source {
+    ssrc: Ssrc,
+    sequence_number_offset: u16,
+    timestamp_offset: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LastSent {
+    instant: Instant,
+    sequence_number: SequenceNumber,
+    timestamp: RtpTimestamp,
+}
+
+impl RtpRewriter {
+    /// Create a new rewriter which produces a stream with the given `ssrc`.
+    ///
+    /// The `clock_rate` is used to advance the timestamp when switching to another source stream.
+    pub fn new(ssrc: Ssrc, clock_rate: u32) -> Self {
+        Self {
+            ssrc,
+            clock_rate,
+            mid: None,
+            pt: None,
+            source: None,
+            last_sent: None,
+        }
+    }
+
+    /// Set the mid to write into the rewritten packets, `None` removes the mid from the packets
+    pub fn with_mid(mut self, mid: Option<Bytes>) -> Self {
+        self.mid = mid;
+        self
+    }
+
+    /// Replace the payload type of all rewritten packets
+    pub fn with_pt(mut self, pt: u8) -> Self {
+        self.pt = Some(pt);
+        self
+    }
+
+    /// SSRC of the rewritten stream
+    pub fn ssrc(&self) -> Ssrc {
+        self.ssrc
+    }
+
+    /// SSRC of the source stream that was rewritten last
+    pub fn source_ssrc(&self) -> Option<Ssrc> {
+        self.source.map(|source| source.ssrc)
+    }
+
+    /// Rewrite a packet to be forwarded
+    ///
+    /// A packet with a different SSRC than the previous one is treated as a switch to a new source stream.
+    pub fn rewrite(&mut self, packet: RtpPacket) -> RtpPacket {
+        self.rewrite_at(Instant::now(), packet)
+    }
+
+    fn rewrite_at(&mut self, now: Instant, mut packet: RtpPacket) -> RtpPacket {
+        let source = match self.source {
+            Some(source) if source.ssrc == packet.ssrc => source,
+            _ => self.switch_source(now, &packet),
+        };
+
+        let sequence_number = SequenceNumber(
+            packet
+                .sequence_number
+                .0
+                .wrapping_add(source.sequence_number_offset),
+        );
+        let timestamp = RtpTimestamp(packet.timestamp.0.wrapping_add(source.timestamp_offset));
+
+        // Only track the newest packet, reordered packets must not move the stream backwards
+        let is_newer = self.last_sent.is_none_or(|last_sent| {
+            let delta = sequence_number.0.wrapping_sub(last_sent.sequence_number.0);
+
+            delta != 0 && delta < 0x8000
+        });
+
+        if is_newer {
+            self.last_sent = Some(LastSent {
+                instant: now,
+                sequence_number,
+                timestamp,
+            });
+        }
+
+        packet.ssrc = self.ssrc;
+        packet.sequence_number = sequence_number;
+        packet.timestamp = timestamp;
+        packet.extensions.mid.clone_from(&self.mid);
+
+        if let Some(pt) = self.pt {
+            packet.pt = pt;
+        }
+
+        packet
+    }
+
+    fn switch_source(&mut self, now: Instant, packet: &RtpPacket) -> Source {
+        let source = if let Some(last_sent) = self.last_sent {
+            // Continue the sequence directly after the last sent packet and advance the timestamp
+            // by the time passed since then, at least by one to never repeat a timestamp
+            let elapsed = now.duration_since(last_sent.instant).as_secs_f64();
+            let elapsed = ((elapsed * f64::from(self.clock_rate)) as u32).max(1);
+
+            let sequence_number = last_sent.sequence_number.0.wrapping_add(1);
+            let timestamp = last_sent.timestamp.0.wrapping_add(elapsed);
+
+            Source {
+                ssrc: packet.ssrc,
+                sequence_number_offset: sequence_number.wrapping_sub(packet.sequence_number.0),
+                timestamp_offset: timestamp.wrapping_sub(packet.timestamp.0),
+            }
+        } else {
+            // First packet, keep the sequence numbers and timestamps of the source
+            Source {
+                ssrc: packet.ssrc,
+                sequence_number_offset: 0,
+                timestamp_offset: 0,
+            }
+        };
+
+        self.source = Some(source);
+
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RtpExtensions;
+    use std::time::Duration;
+
+    fn make_packet(ssrc: u32, seq: u16, timestamp: u32) -> RtpPacket {
+        RtpPacket {
+            pt: 0,
+            marker: false,
+            sequence_number: SequenceNumber(seq),
+            ssrc: Ssrc(ssrc),
+            timestamp: RtpTimestamp(timestamp),
+            extensions: RtpExtensions {
+                mid: Some(Bytes::from_static(b"source")),
+                audio_level: None,
+                abs_capture_time: None,
+            },
+            payload: Bytes::new(),
+            padding: None,
+        }
+    }
+
+    #[test]
+    fn rewrite_single_source() {
+        let mut rewriter = RtpRewriter::new(Ssrc(1), 8000)
+            .with_mid(Some(Bytes::from_static(b"0")))
+            .with_pt(8);
+
+        let packet = rewriter.rewrite(make_packet(100, 500, 1000));
+
+        assert_eq!(packet.ssrc, Ssrc(1));
+        assert_eq!(packet.pt, 8);
+        assert_eq!(packet.sequence_number, SequenceNumber(500));
+        assert_eq!(packet.timestamp, RtpTimestamp(1000));
+        assert_eq!(packet.extensions.mid, Some(Bytes::from_static(b"0")));
+        assert_eq!(rewriter.source_ssrc(), Some(Ssrc(100)));
+    }
+
+    #[test]
+    fn switch_source_stays_continuous() {
+        let start = Instant::now();
+        let mut rewriter = RtpRewriter::new(Ssrc(1), 8000);
+
+        rewriter.rewrite_at(start, make_packet(100, u16::MAX, 1000));
+
+        let packet = rewriter.rewrite_at(
+            start + Duration::from_millis(20),
+            make_packet(200, 10, 50_000),
+        );
+
+        assert_eq!(packet.sequence_number, SequenceNumber(0));
+        assert_eq!(packet.timestamp, RtpTimestamp(1160));
+
+        let packet = rewriter.rewrite_at(
+            start + Duration::from_millis(40),
+            make_packet(200, 11, 50_160),
+        );
+
+        assert_eq!(packet.sequence_number, SequenceNumber(1));
+        assert_eq!(packet.timestamp, RtpTimestamp(1320));
+    }
+
+    #[test]
+    fn reordered_packet_does_not_rewind_stream() {
+        let start = Instant::now();
+        let mut rewriter = RtpRewriter::new(Ssrc(1), 8000);
+
+        rewriter.rewrite_at(start, make_packet(100, 5, 800));
+        rewriter.rewrite_at(start, make_packet(100, 4, 640));
+
+        let packet = rewriter.rewrite_at(start, make_packet(200, 0, 0));
+
+        assert_eq!(packet.sequence_number, SequenceNumber(6));
+        assert_eq!(packet.timestamp, RtpTimestamp(801));
+    }
+}
@@ -0,0 +1,169 @@
+use std::f64::consts::PI;
+
+/// Amplitude of the generated tone relative to full scale, split between its frequencies
+const AMPLITUDE: f64 = 0.2;
+
+/// Country specific ringback tone generated by the [`RingbackGenerator`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingbackRegion {
+    /// 440+480 Hz, 2s on, 4s off
+    NorthAmerica,
+
+    /// 425 Hz, 1s on, 4s off (ITU-T E.180, e.g. Germany, Italy, Spain)
+    Europe,
+
+    /// 400+450 Hz, 0.4s on, 0.2s off, 0.4s on, 2s off
+    UnitedKingdom,
+
+    /// 440 Hz, 1.5s on, 3.5s off
+    France,
+
+    /// 400 Hz, 1s on, 2s off
+    Japan,
+}
+
+impl RingbackRegion {
+    fn frequencies(self) -> &'static [f64] {
+        match self {
+            RingbackRegion::NorthAmerica => &[440.0, 480.0],
+            RingbackRegion::Europe => &[425.0],
+            RingbackRegion::UnitedKingdom => &[400.0, 450.0],
+            RingbackRegion::France => &[440.0],
+            RingbackRegion::Japan => &[400.0],
+        }
+    }
+
+    /// Alternating on and off periods in milliseconds, starting with on
+    fn cadence_ms(self) -> &'static [u32] {
+        match self {
+            RingbackRegion::NorthAmerica => &[2000, 4000],
+            RingbackRegion::Europe => &[1000, 4000],
+            RingbackRegion::UnitedKingdom => &[400, 200, 400, 2000],
+            RingbackRegion::France => &[1500, 3500],
+            RingbackRegion::Japan => &[1000, 2000],
+        }
+    }
+}
+
+/// Generates ringback tone audio, e.g. to play locally while an outbound call is ringing without early media
+#[derive(Debug)]
+pub struct RingbackGenerator {
+    sample_rate: u32,
+    frequencies: &'static [f64],
+    /// Lengths of the on and off periods in samples
+    cadence: Vec<u64>,
+    /// Total length of one cadence cycle in samples
+    cycle: u64,
+    /// Number of samples generated so far
+    position: u64,
+}
+
+impl RingbackGenerator {
+    /// Create a new generator for audio with the given sample rate
+    pub fn new(region: RingbackRegion, sample_rate: u32) -> Self {
+        let cadence: Vec<u64> = region
+            .cadence_ms()
+            .iter()
+            .map(|ms| u64::from(sample_rate) * u64::from(*ms) / 1000)
+            .collect();
+
+        Self {
+            sample_rate,
+            frequencies: region.frequencies(),
+            cycle: cadence.iter().sum(),
+            cadence,
+            position: 0,
+        }
+    }
+
+    /// Fill the given samples with the next part of the tone
+    pub fn fill(&mut self, samples: &mut [i16]) {
+        let amplitude = AMPLITUDE / self.frequencies.len() as f64;
+
+        for sample in samples {
+            *sample = if self.is_on() {
+                let t = self.position as f64 / f64::from(self.sample_rate);
+
+                let v: f64 = self
+                    .frequencies
+                    .iter()
+                    .map(|f| amplitude * (2.0 * PI * f * t).sin())
+                    .sum();
+
+                (v * f64::from(i16::MAX)) as i16
+            } else {
+                0
+            };
+
+            self.position += 1;
+        }
+    }
+
+    /// Returns if the tone is on at the current position of the cadence
+    fn is_on(&self) -> bool {
+        let mut offset = self.position % self.cycle;
+
+        for (i, len) in self.cadence.iter().enumerate() {
+            if offset < *len {
+                return i % 2 == 0;
+            }
+
+            offset -= len;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CallProgressDetector, CallProgressTone};
+
+    fn generate(region: RingbackRegion, duration_ms: u32) -> Vec<i16> {
+        let mut generator = RingbackGenerator::new(region, 8000);
+
+        // Generate in 20ms chunks like a playback path would
+        let mut samples = vec![0; (8000 * duration_ms / 1000) as usize];
+
+        for chunk in samples.chunks_mut(160) {
+            generator.fill(chunk);
+        }
+
+        samples
+    }
+
+    fn detect(samples: &[i16]) -> Vec<CallProgressTone> {
+        let mut detector = CallProgressDetector::new(8000);
+        detector.process_samples(samples);
+
+        std::iter::from_fn(|| detector.pop_tone()).collect()
+    }
+
+    #[test]
+    fn cadence() {
+        let samples = generate(RingbackRegion::UnitedKingdom, 3500);
+
+        let on = |ms: usize| samples[ms * 8..(ms + 10) * 8].iter().any(|s| *s != 0);
+
+        assert!(on(0));
+        assert!(!on(450));
+        assert!(on(700));
+        assert!(!on(1500));
+        assert!(!on(2980));
+        assert!(on(3000));
+    }
+
+    #[test]
+    fn detected_as_ringback() {
+        assert_eq!(
+            detect(&generate(RingbackRegion::NorthAmerica, 6000)),
+            [CallProgressTone::Ringback]
+        );
+
+        assert_eq!(
+            detect(&generate(RingbackRegion::Europe, 5000)),
+            [CallProgressTone::Ringback]
+        );
+    }
+}
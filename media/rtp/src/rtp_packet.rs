@@ -1,40 +1,84 @@
-use crate::{parse_extensions, RtpExtensionsWriter, RtpTimestamp, SequenceNumber, Ssrc};
+use crate::{
+    parse_extensions, AbsCaptureTime, AudioLevel, RtpExtensionsWriter, RtpTimestamp,
+    SequenceNumber, Ssrc,
+};
 use bytes::Bytes;
 use rtp_types::{prelude::RtpPacketWriter, RtpPacketBuilder};
 
 #[derive(Debug, Clone)]
 pub struct RtpPacket {
     pub pt: u8,
+    /// Marker bit, e.g. set on the first packet of a telephone-event
+    pub marker: bool,
     pub sequence_number: SequenceNumber,
     pub ssrc: Ssrc,
     pub timestamp: RtpTimestamp,
     pub extensions: RtpExtensions,
     pub payload: Bytes,
+    /// Number of padding bytes appended to the payload
+    pub padding: Option<u8>,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct RtpExtensions {
     pub mid: Option<Bytes>,
+    pub audio_level: Option<AudioLevel>,
+    pub abs_capture_time: Option<AbsCaptureTime>,
 }
 
+/// Upper bound of the header extensions written by [`RtpExtensions`]: every extension with a two-byte header and up
+/// to 255 bytes of data, plus padding
+const MAX_EXTENSIONS_LEN: usize = 3 * (2 + u8::MAX as usize) + 3;
+
 /// ID to attribute type map to use when parsing or serializing RTP packets
 #[derive(Debug, Default, Clone, Copy)]
 pub struct RtpExtensionIds {
     pub mid: Option<u8>,
+    pub audio_level: Option<u8>,
+    pub abs_capture_time: Option<u8>,
 }
 
 impl RtpPacket {
     pub fn write_vec(&self, extension_ids: RtpExtensionIds, vec: &mut Vec<u8>) {
-        let builder = RtpPacketBuilder::<_, Vec<u8>>::new()
+        self.write_vec_reserve(extension_ids, vec, 0);
+    }
+
+    pub fn to_vec(&self, extension_ids: RtpExtensionIds) -> Vec<u8> {
+        let mut vec = vec![];
+        self.write_vec(extension_ids, &mut vec);
+        vec
+    }
+
+    /// Like [`to_vec`](Self::to_vec), but leaves room for `trailer_len` more bytes so appending e.g. an SRTP
+    /// authentication tag doesn't reallocate the vector
+    pub fn to_vec_with_trailer(
+        &self,
+        extension_ids: RtpExtensionIds,
+        trailer_len: usize,
+    ) -> Vec<u8> {
+        let mut vec = vec![];
+        self.write_vec_reserve(extension_ids, &mut vec, trailer_len);
+        vec
+    }
+
+    fn write_vec_reserve(&self, extension_ids: RtpExtensionIds, vec: &mut Vec<u8>, extra: usize) {
+        // Header extensions are written to the stack, to avoid allocating for every packet
+        let mut extensions_buf = [0u8; MAX_EXTENSIONS_LEN];
+
+        let builder = RtpPacketBuilder::<_, &[u8]>::new()
             .payload_type(self.pt)
+            .marker_bit(self.marker)
             .sequence_number(self.sequence_number.0)
             .ssrc(self.ssrc.0)
             .timestamp(self.timestamp.0)
-            .payload(&self.payload[..]);
+            .payload(&self.payload[..])
+            .maybe_padding(self.padding);
 
-        let builder = self.extensions.write(extension_ids, builder);
+        let builder = self
+            .extensions
+            .write(extension_ids, &mut extensions_buf, builder);
 
-        vec.reserve(builder.calculate_size().unwrap());
+        vec.reserve(builder.calculate_size().unwrap() + extra);
 
         let mut writer = RtpPacketWriterVec {
             output: vec,
@@ -43,12 +87,6 @@ impl RtpPacket {
         builder.write(&mut writer).unwrap();
     }
 
-    pub fn to_vec(&self, extension_ids: RtpExtensionIds) -> Vec<u8> {
-        let mut vec = vec![];
-        self.write_vec(extension_ids, &mut vec);
-        vec
-    }
-
     pub fn parse(
         extension_ids: RtpExtensionIds,
         bytes: impl Into<Bytes>,
@@ -60,16 +98,18 @@ impl RtpPacket {
         let extensions = if let Some((profile, extension_data)) = parsed.extension() {
             RtpExtensions::from_packet(extension_ids, &packet, profile, extension_data)
         } else {
-            RtpExtensions { mid: None }
+            RtpExtensions::default()
         };
 
         Ok(Self {
             pt: parsed.payload_type(),
+            marker: parsed.marker_bit(),
             sequence_number: SequenceNumber(parsed.sequence_number()),
             ssrc: Ssrc(parsed.ssrc()),
             timestamp: RtpTimestamp(parsed.timestamp()),
             extensions,
             payload: packet.slice_ref(parsed.payload()),
+            padding: parsed.padding(),
         })
     }
 }
@@ -81,11 +121,15 @@ impl RtpExtensions {
         profile: u16,
         extension_data: &[u8],
     ) -> Self {
-        let mut this = Self { mid: None };
+        let mut this = Self::default();
 
         for (id, data) in parse_extensions(profile, extension_data) {
             if Some(id) == ids.mid {
                 this.mid = Some(bytes.slice_ref(data));
+            } else if Some(id) == ids.audio_level {
+                this.audio_level = AudioLevel::from_extension_data(data);
+            } else if Some(id) == ids.abs_capture_time {
+                this.abs_capture_time = AbsCaptureTime::from_extension_data(data);
             }
         }
 
@@ -95,19 +139,40 @@ impl RtpExtensions {
     fn write<'b>(
         &self,
         ids: RtpExtensionIds,
-        packet_builder: RtpPacketBuilder<&'b [u8], Vec<u8>>,
-    ) -> RtpPacketBuilder<&'b [u8], Vec<u8>> {
-        let Some((id, mid)) = ids.mid.zip(self.mid.as_ref()) else {
+        buf: &'b mut [u8; MAX_EXTENSIONS_LEN],
+        packet_builder: RtpPacketBuilder<&'b [u8], &'b [u8]>,
+    ) -> RtpPacketBuilder<&'b [u8], &'b [u8]> {
+        let audio_level = self.audio_level.map(AudioLevel::to_extension_data);
+        let abs_capture_time = self.abs_capture_time.map(AbsCaptureTime::to_extension_data);
+
+        let elements = [
+            ids.mid.zip(self.mid.as_deref()),
+            ids.audio_level
+                .zip(audio_level.as_ref().map(|data| &data[..])),
+            ids.abs_capture_time.zip(abs_capture_time.as_deref()),
+        ];
+
+        if elements.iter().all(Option::is_none) {
             return packet_builder;
-        };
+        }
 
-        let mut buf = vec![];
+        // The one-byte header only supports ids below 15 with up to 16 bytes of data
+        let two_byte = elements
+            .iter()
+            .flatten()
+            .any(|(id, data)| *id >= 15 || data.len() > 16);
+
+        let mut remaining = &mut buf[..];
+        let mut writer = RtpExtensionsWriter::new(&mut remaining, two_byte);
+
+        for (id, data) in elements.into_iter().flatten() {
+            writer = writer.with(id, data);
+        }
 
-        let profile = RtpExtensionsWriter::new(&mut buf, mid.len() <= 16)
-            .with(id, mid)
-            .finish();
+        let profile = writer.finish();
+        let len = MAX_EXTENSIONS_LEN - remaining.len();
 
-        packet_builder.extension(profile, buf)
+        packet_builder.extension(profile, &buf[..len])
     }
 }
 
@@ -119,7 +184,7 @@ struct RtpPacketWriterVec<'a> {
 impl<'a> RtpPacketWriter for RtpPacketWriterVec<'a> {
     type Output = ();
     type Payload = &'a [u8];
-    type Extension = Vec<u8>;
+    type Extension = &'a [u8];
 
     fn reserve(&mut self, size: usize) {
         if self.output.len() < size {
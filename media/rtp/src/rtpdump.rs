@@ -0,0 +1,112 @@
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Writes RTP packets in the rtpdump format of the [rtptools](https://github.com/irtlab/rtptools), which can be
+/// replayed using `rtpplay` and opened by Wireshark
+///
+/// Each packet is stored with its offset to the creation of the writer in milliseconds.
+#[derive(Debug)]
+pub struct RtpDumpWriter<W> {
+    writer: W,
+    start: Instant,
+}
+
+impl<W: Write> RtpDumpWriter<W> {
+    /// Write the file header, `source` is the address the packets are recorded as being sent from
+    ///
+    /// IPv6 addresses can't be represented in the binary header and are stored as `0.0.0.0`.
+    pub fn new(mut writer: W, source: SocketAddr) -> io::Result<Self> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let address = match source.ip() {
+            IpAddr::V4(ip) => u32::from(ip),
+            IpAddr::V6(_) => 0,
+        };
+
+        writeln!(writer, "#!rtpplay1.0 {}/{}", source.ip(), source.port())?;
+
+        let mut header = [0u8; 16];
+        header[0..4].copy_from_slice(&(now.as_secs() as u32).to_be_bytes());
+        header[4..8].copy_from_slice(&now.subsec_micros().to_be_bytes());
+        header[8..12].copy_from_slice(&address.to_be_bytes());
+        header[12..14].copy_from_slice(&source.port().to_be_bytes());
+        writer.write_all(&header)?;
+
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    /// Write a serialized RTP packet captured at the given time
+    pub fn write_rtp(&mut self, captured: Instant, packet: &[u8]) -> io::Result<()> {
+        self.write_packet(captured, packet, true)
+    }
+
+    /// Write a serialized RTCP packet captured at the given time
+    pub fn write_rtcp(&mut self, captured: Instant, packet: &[u8]) -> io::Result<()> {
+        self.write_packet(captured, packet, false)
+    }
+
+    fn write_packet(&mut self, captured: Instant, packet: &[u8], rtp: bool) -> io::Result<()> {
+        // The record length includes its 8 byte header
+        let length = u16::try_from(packet.len() + 8)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "packet too large"))?;
+
+        // RTCP packets are marked using a RTP length of zero
+        let rtp_length = if rtp { packet.len() as u16 } else { 0 };
+        let offset = captured.saturating_duration_since(self.start).as_millis() as u32;
+
+        let mut header = [0u8; 8];
+        header[0..2].copy_from_slice(&length.to_be_bytes());
+        header[2..4].copy_from_slice(&rtp_length.to_be_bytes());
+        header[4..8].copy_from_slice(&offset.to_be_bytes());
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(packet)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn write_dump() {
+        let mut writer = RtpDumpWriter::new(vec![], "192.0.2.1:5004".parse().unwrap()).unwrap();
+        let start = writer.start;
+
+        writer
+            .write_rtp(start + Duration::from_millis(20), &[0x80, 0, 0, 1])
+            .unwrap();
+        writer.write_rtcp(start, &[0x81, 201]).unwrap();
+
+        let dump = writer.into_inner();
+        let line = b"#!rtpplay1.0 192.0.2.1/5004\n";
+
+        assert!(dump.starts_with(line));
+
+        let header = &dump[line.len()..line.len() + 16];
+        assert_eq!(header[8..12], [192, 0, 2, 1]);
+        assert_eq!(header[12..14], 5004u16.to_be_bytes());
+
+        let packets = &dump[line.len() + 16..];
+        assert_eq!(packets[..8], [0, 12, 0, 4, 0, 0, 0, 20]);
+        assert_eq!(packets[8..12], [0x80, 0, 0, 1]);
+        assert_eq!(packets[12..20], [0, 10, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(packets[20..], [0x81, 201]);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{RtpPacket, SequenceNumber, Ssrc};
+use bytes::{BufMut, BytesMut};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Default number of sent packets kept by the [`RtxSender`]
+pub const DEFAULT_RTX_BUFFER_SIZE: usize = 1000;
+
+/// Retransmits recently sent RTP packets as RTX packets (RFC 4588) when the receiver requests them using NACKs
+///
+/// RTX packets use their own SSRC and sequence numbers, the payload is prefixed with the original sequence number.
+/// Every sent packet must be added using [`push_sent`](Self::push_sent).
+#[derive(Debug)]
+pub struct RtxSender {
+    pt: u8,
+    ssrc: Ssrc,
+    sequence_number: SequenceNumber,
+
+    capacity: usize,
+    sent: VecDeque<RtpPacket>,
+}
+
+impl RtxSender {
+    /// Create a sender using the RTX payload type and SSRC, starting with the given sequence number which should be
+    /// random
+    pub fn new(pt: u8, ssrc: Ssrc, sequence_number: SequenceNumber) -> Self {
+        Self {
+            pt,
+            ssrc,
+            sequence_number,
+            capacity: DEFAULT_RTX_BUFFER_SIZE,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Set how many of the most recently sent packets are kept for retransmission
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.truncate();
+    }
+
+    /// SSRC of the RTX stream
+    pub fn ssrc(&self) -> Ssrc {
+        self.ssrc
+    }
+
+    /// Keep a packet which was sent, for retransmission
+    pub fn push_sent(&mut self, packet: &RtpPacket) {
+        self.sent.push_back(packet.clone());
+        self.truncate();
+    }
+
+    /// Returns the RTX packet retransmitting the sent packet with the given sequence number, `None` if the packet
+    /// is no longer kept
+    pub fn retransmit(&mut self, sequence_number: SequenceNumber) -> Option<RtpPacket> {
+        let original = self
+            .sent
+            .iter()
+            .rev()
+            .find(|packet| packet.sequence_number == sequence_number)?;
+
+        let mut payload = BytesMut::with_capacity(2 + original.payload.len());
+        payload.put_u16(original.sequence_number.0);
+        payload.put_slice(&original.payload);
+
+        let packet = RtpPacket {
+            pt: self.pt,
+            marker: original.marker,
+            sequence_number: self.sequence_number,
+            ssrc: self.ssrc,
+            timestamp: original.timestamp,
+            extensions: original.extensions.clone(),
+            payload: payload.freeze(),
+            padding: None,
+        };
+
+        self.sequence_number = SequenceNumber(self.sequence_number.0.wrapping_add(1));
+
+        Some(packet)
+    }
+
+    fn truncate(&mut self) {
+        while self.sent.len() > self.capacity {
+            self.sent.pop_front();
+        }
+    }
+}
+
+/// Restore the original packet from a received RTX packet, using the payload type and SSRC of the original stream
+pub fn rtx_decode(packet: &RtpPacket, pt: u8, ssrc: Ssrc) -> Option<RtpPacket> {
+    let [s0, s1, ..] = packet.payload[..] else {
+        return None;
+    };
+
+    Some(RtpPacket {
+        pt,
+        marker: packet.marker,
+        sequence_number: SequenceNumber(u16::from_be_bytes([s0, s1])),
+        ssrc,
+        timestamp: packet.timestamp,
+        extensions: packet.extensions.clone(),
+        payload: packet.payload.slice(2..),
+        padding: None,
+    })
+}
+
+/// Configuration of the [`NackGenerator`]
+#[derive(Debug, Clone)]
+pub struct NackConfig {
+    /// Delay between NACKs of the same missing packet, should be a bit above the round trip time
+    pub interval: Duration,
+
+    /// How often a missing packet is requested before giving up on it
+    pub max_retries: u32,
+
+    /// Packets older than this many sequence numbers are no longer requested, gaps larger than this are not
+    /// requested at all
+    pub max_age: u16,
+}
+
+impl Default for NackConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+            max_retries: 10,
+            max_age: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MissingPacket {
+    sequence_number: u16,
+    retries: u32,
+    next_nack: Instant,
+}
+
+/// Detects gaps in the sequence numbers of received RTP packets and decides when to request them using generic
+/// NACKs (RFC 4585 section 6.2.1)
+#[derive(Debug)]
+pub struct NackGenerator {
+    config: NackConfig,
+    highest: Option<u16>,
+    missing: Vec<MissingPacket>,
+}
+
+impl NackGenerator {
+    pub fn new(config: NackConfig) -> Self {
+        Self {
+            config,
+            highest: None,
+            missing: vec![],
+        }
+    }
+
+    /// Register a received packet, including retransmitted ones
+    pub fn receive(&mut self, sequence_number: SequenceNumber, now: Instant) {
+        let sequence_number = sequence_number.0;
+
+        let Some(highest) = self.highest else {
+            self.highest = Some(sequence_number);
+            return;
+        };
+
+        let delta = sequence_number.wrapping_sub(highest) as i16;
+
+        if delta <= 0 {
+            // Reordered or retransmitted packet
+            self.missing
+                .retain(|missing| missing.sequence_number != sequence_number);
+            return;
+        }
+
+        self.highest = Some(sequence_number);
+
+        if delta as u16 - 1 > self.config.max_age {
+            // Too many packets lost to recover them, e.g. after the sender restarted its stream
+            self.missing.clear();
+            return;
+        }
+
+        for i in 1..delta as u16 {
+            self.missing.push(MissingPacket {
+                sequence_number: highest.wrapping_add(i),
+                retries: 0,
+                next_nack: now,
+            });
+        }
+
+        let max_age = self.config.max_age;
+
+        self.missing
+            .retain(|missing| sequence_number.wrapping_sub(missing.sequence_number) <= max_age);
+    }
+
+    /// Time until the next NACK is due
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        self.missing
+            .iter()
+            .map(|missing| missing.next_nack.saturating_duration_since(now))
+            .min()
+    }
+
+    /// Returns the sequence numbers of the missing packets which are due to be requested
+    pub fn poll(&mut self, now: Instant) -> Vec<SequenceNumber> {
+        let mut due = vec![];
+
+        for missing in &mut self.missing {
+            if missing.next_nack <= now {
+                missing.retries += 1;
+                missing.next_nack = now + self.config.interval;
+
+                due.push(SequenceNumber(missing.sequence_number));
+            }
+        }
+
+        let max_retries = self.config.max_retries;
+
+        self.missing.retain(|missing| missing.retries < max_retries);
+
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RtpExtensions, RtpTimestamp};
+    use bytes::Bytes;
+
+    fn packet(sequence_number: u16) -> RtpPacket {
+        RtpPacket {
+            pt: 96,
+            marker: false,
+            sequence_number: SequenceNumber(sequence_number),
+            ssrc: Ssrc(1),
+            timestamp: RtpTimestamp(3000),
+            extensions: RtpExtensions::default(),
+            payload: Bytes::from_static(&[1, 2, 3]),
+            padding: None,
+        }
+    }
+
+    #[test]
+    fn retransmit() {
+        let mut sender = RtxSender::new(97, Ssrc(2), SequenceNumber(u16::MAX));
+        sender.set_capacity(2);
+
+        for sequence_number in 10..13 {
+            sender.push_sent(&packet(sequence_number));
+        }
+
+        assert!(sender.retransmit(SequenceNumber(10)).is_none());
+
+        let rtx = sender.retransmit(SequenceNumber(11)).unwrap();
+        assert_eq!(rtx.pt, 97);
+        assert_eq!(rtx.ssrc, Ssrc(2));
+        assert_eq!(rtx.sequence_number, SequenceNumber(u16::MAX));
+        assert_eq!(rtx.timestamp, RtpTimestamp(3000));
+        assert_eq!(&rtx.payload[..], &[0, 11, 1, 2, 3]);
+
+        let rtx = sender.retransmit(SequenceNumber(11)).unwrap();
+        assert_eq!(rtx.sequence_number, SequenceNumber(0));
+
+        let original = rtx_decode(&rtx, 96, Ssrc(1)).unwrap();
+        assert_eq!(original.sequence_number, SequenceNumber(11));
+        assert_eq!(original.pt, 96);
+        assert_eq!(original.payload, packet(11).payload);
+    }
+
+    #[test]
+    fn nack_gaps() {
+        let now = Instant::now();
+        let mut generator = NackGenerator::new(NackConfig {
+            max_retries: 2,
+            ..NackConfig::default()
+        });
+
+        generator.receive(SequenceNumber(u16::MAX - 1), now);
+        generator.receive(SequenceNumber(2), now);
+        assert_eq!(generator.timeout(now), Some(Duration::ZERO));
+
+        // Reordered packet arrives before the NACK is sent
+        generator.receive(SequenceNumber(0), now);
+
+        assert_eq!(
+            generator.poll(now),
+            [SequenceNumber(u16::MAX), SequenceNumber(1)]
+        );
+        assert!(generator.poll(now).is_empty());
+        assert_eq!(generator.timeout(now), Some(Duration::from_millis(100)));
+
+        // Retransmission received
+        generator.receive(SequenceNumber(1), now);
+
+        let later = now + Duration::from_millis(100);
+        assert_eq!(generator.poll(later), [SequenceNumber(u16::MAX)]);
+
+        // Given up after the maximum number of retries
+        assert_eq!(generator.timeout(later), None);
+    }
+
+    #[test]
+    fn nack_large_gap() {
+        let now = Instant::now();
+        let mut generator = NackGenerator::new(NackConfig::default());
+
+        generator.receive(SequenceNumber(0), now);
+        generator.receive(SequenceNumber(5000), now);
+
+        assert!(generator.poll(now).is_empty());
+    }
+}
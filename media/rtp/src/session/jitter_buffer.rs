@@ -1,5 +1,79 @@
 use crate::{ExtendedRtpTimestamp, ExtendedSequenceNumber, RtpPacket};
-use std::{cmp::Ordering, collections::VecDeque, fmt};
+use std::{cmp::Ordering, collections::VecDeque, fmt, time::Duration};
+
+/// The adaptive delay is this multiple of the measured interarrival jitter
+const ADAPTIVE_JITTER_FACTOR: f32 = 4.0;
+
+/// How the delay of a jitter buffer is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JitterBufferMode {
+    /// Packets are always delayed by the minimum delay
+    Fixed,
+    /// The delay follows the measured interarrival jitter, within the minimum and maximum delay
+    Adaptive,
+}
+
+/// Configuration of the jitter buffers of a [`RtpSession`](crate::RtpSession)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitterBufferConfig {
+    pub mode: JitterBufferMode,
+    pub min_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl JitterBufferConfig {
+    /// Always delay received packets by `delay`
+    pub fn fixed(delay: Duration) -> Self {
+        Self {
+            mode: JitterBufferMode::Fixed,
+            min_delay: delay,
+            max_delay: delay,
+        }
+    }
+
+    /// Adapt the delay of received packets to the network's jitter, between `min_delay` and `max_delay`
+    pub fn adaptive(min_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            mode: JitterBufferMode::Adaptive,
+            min_delay,
+            max_delay: max_delay.max(min_delay),
+        }
+    }
+
+    /// Delay to apply given the interarrival jitter in seconds
+    pub(crate) fn target_delay(&self, jitter: f32) -> Duration {
+        match self.mode {
+            JitterBufferMode::Fixed => self.min_delay,
+            JitterBufferMode::Adaptive => {
+                Duration::from_secs_f32((jitter * ADAPTIVE_JITTER_FACTOR).max(0.0))
+                    .min(self.max_delay)
+                    .max(self.min_delay)
+            }
+        }
+    }
+}
+
+impl Default for JitterBufferConfig {
+    fn default() -> Self {
+        Self::fixed(Duration::from_millis(100))
+    }
+}
+
+/// Statistics of the jitter buffer of a remote ssrc, see
+/// [`RtpSession::jitter_buffer_stats`](crate::RtpSession::jitter_buffer_stats)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitterBufferStats {
+    /// Number of packets currently buffered
+    pub buffered_packets: usize,
+    /// Media duration between the oldest and newest buffered packet
+    pub depth: Duration,
+    /// Delay currently applied to received packets
+    pub target_delay: Duration,
+    /// Packets which arrived after they were due to be played out
+    pub late_packets: u64,
+    /// Packets dropped in total, including late and duplicate packets and buffer overflows
+    pub dropped_packets: u64,
+}
 
 /// A queue based jitter buffer
 ///
@@ -14,6 +88,8 @@ pub(crate) struct JitterBuffer {
 
     /// num packets dropped due to being duplicate, too late or the receiver falling behind
     pub(crate) dropped: u64,
+    /// num packets which arrived after their sequence number was already played out
+    pub(crate) late: u64,
     /// num packets received
     pub(crate) received: u64,
     /// num packets not received
@@ -26,6 +102,7 @@ impl fmt::Debug for JitterBuffer {
             .field("max_entries", &self.max_entries)
             .field("queue (len)", &self.queue.len())
             .field("dropped", &self.dropped)
+            .field("late", &self.late)
             .field("received", &self.received)
             .field("lost", &self.lost)
             .finish()
@@ -39,6 +116,7 @@ impl Default for JitterBuffer {
             queue: VecDeque::new(),
             last_sequence_number_returned: None,
             dropped: 0,
+            late: 0,
             received: 0,
             lost: 0,
         }
@@ -92,6 +170,7 @@ impl JitterBuffer {
         if let Some(seq) = self.last_sequence_number_returned {
             if seq >= sequence_number {
                 self.dropped += 1;
+                self.late += 1;
                 return;
             }
         }
@@ -188,6 +267,27 @@ impl JitterBuffer {
         }
     }
 
+    /// Number of buffered packets
+    pub(crate) fn len(&self) -> usize {
+        self.queue
+            .iter()
+            .filter(|e| matches!(e, QueueEntry::Occupied { .. }))
+            .count()
+    }
+
+    /// Difference of the newest and oldest buffered timestamp, in RTP timestamp units
+    pub(crate) fn depth(&self) -> u64 {
+        let latest = self.queue.iter().rev().find_map(|e| match e {
+            QueueEntry::Vacant(..) => None,
+            QueueEntry::Occupied { timestamp: ts, .. } => Some(*ts),
+        });
+
+        match (self.timestamp_of_earliest_packet(), latest) {
+            (Some(earliest), Some(latest)) => latest.0.saturating_sub(earliest.0),
+            _ => 0,
+        }
+    }
+
     pub(crate) fn timestamp_of_earliest_packet(&self) -> Option<ExtendedRtpTimestamp> {
         self.queue.iter().find_map(|e| match e {
             QueueEntry::Vacant(..) => None,
@@ -205,11 +305,13 @@ mod tests {
     fn make_packet(seq: u16) -> RtpPacket {
         RtpPacket {
             pt: 0,
+            marker: false,
             sequence_number: SequenceNumber(seq),
             ssrc: Ssrc(0),
             timestamp: RtpTimestamp(0),
             extensions: RtpExtensions::default(),
             payload: Bytes::new(),
+            padding: None,
         }
     }
 
@@ -256,4 +358,56 @@ mod tests {
         );
         assert_eq!(jb.lost, 1)
     }
+
+    #[test]
+    fn late_packets() {
+        let mut jb = JitterBuffer::default();
+
+        jb.push(
+            ExtendedRtpTimestamp(100),
+            ExtendedSequenceNumber(1),
+            make_packet(1),
+        );
+        jb.push(
+            ExtendedRtpTimestamp(300),
+            ExtendedSequenceNumber(3),
+            make_packet(3),
+        );
+        assert_eq!(jb.len(), 2);
+        assert_eq!(jb.depth(), 200);
+
+        assert_eq!(
+            jb.pop(ExtendedRtpTimestamp(300)).unwrap().sequence_number.0,
+            1
+        );
+        assert_eq!(
+            jb.pop(ExtendedRtpTimestamp(300)).unwrap().sequence_number.0,
+            3
+        );
+
+        // Packet 2 arrives after packet 3 was played out
+        jb.push(
+            ExtendedRtpTimestamp(200),
+            ExtendedSequenceNumber(2),
+            make_packet(2),
+        );
+        assert_eq!(jb.len(), 0);
+        assert_eq!(jb.late, 1);
+        assert_eq!(jb.dropped, 1);
+    }
+
+    #[test]
+    fn adaptive_delay() {
+        let config =
+            JitterBufferConfig::adaptive(Duration::from_millis(40), Duration::from_millis(200));
+
+        assert_eq!(config.target_delay(0.0), Duration::from_millis(40));
+        assert_eq!(config.target_delay(1.0), Duration::from_millis(200));
+
+        let delay = config.target_delay(0.020);
+        assert!(delay > Duration::from_millis(79) && delay < Duration::from_millis(81));
+
+        let fixed = JitterBufferConfig::fixed(Duration::from_millis(60));
+        assert_eq!(fixed.target_delay(1.0), Duration::from_millis(60));
+    }
 }
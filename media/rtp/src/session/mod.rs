@@ -1,11 +1,13 @@
 use crate::{ExtendedRtpTimestamp, ExtendedSequenceNumber, NtpTimestamp, RtpPacket, Ssrc};
 use jitter_buffer::JitterBuffer;
+pub use jitter_buffer::{JitterBufferConfig, JitterBufferMode, JitterBufferStats};
 use rtcp_types::{
     CompoundBuilder, ReceiverReport, ReceiverReportBuilder, ReportBlock, RtcpPacketWriterExt,
     RtcpWriteError, SdesBuilder, SdesChunkBuilder, SdesItemBuilder, SenderReport,
     SenderReportBuilder,
 };
 use std::{
+    collections::VecDeque,
     fmt,
     time::{Duration, Instant},
 };
@@ -13,8 +15,6 @@ use time::ext::InstantExt;
 
 mod jitter_buffer;
 
-const DEFAULT_JITTERBUFFER_LENGTH: Duration = Duration::from_millis(100);
-
 /// Single RTP session, (1 sender, many receiver)
 ///
 /// This can be used to publish a single RTP source and receive others.
@@ -29,6 +29,11 @@ pub struct RtpSession {
 
     sender: Option<SenderState>,
     receiver: Vec<ReceiverState>,
+
+    jitter_buffer_config: JitterBufferConfig,
+    /// Deliver received packets in arrival order without buffering them
+    bypass_jitter_buffer: bool,
+    bypassed: VecDeque<RtpPacket>,
 }
 
 impl fmt::Debug for RtpSession {
@@ -39,6 +44,8 @@ impl fmt::Debug for RtpSession {
             .field("source_description_items", &self.source_description_items)
             .field("sender", &"[opaque]")
             .field("receiver", &"[opaque]")
+            .field("jitter_buffer_config", &self.jitter_buffer_config)
+            .field("bypass_jitter_buffer", &self.bypass_jitter_buffer)
             .finish()
     }
 }
@@ -61,8 +68,12 @@ struct ReceiverState {
     last_rtp_received: Option<(Instant, ExtendedRtpTimestamp, ExtendedSequenceNumber)>,
     jitter: f32,
 
-    last_sr: Option<NtpTimestamp>,
+    /// Middle 32 bits of the NTP timestamp of the last received sender report and when it was received
+    last_sr: Option<(u32, NtpTimestamp)>,
     total_lost: u64,
+
+    /// Smoothed end-to-end latency, estimated using the abs-capture-time header extension
+    end_to_end_latency: Option<Duration>,
 }
 
 impl RtpSession {
@@ -73,9 +84,62 @@ impl RtpSession {
             clock_rate,
             sender: None,
             receiver: vec![],
+            jitter_buffer_config: JitterBufferConfig::default(),
+            bypass_jitter_buffer: false,
+            bypassed: VecDeque::new(),
         }
     }
 
+    /// Set how long received packets are buffered
+    pub fn with_jitter_buffer_config(mut self, config: JitterBufferConfig) -> Self {
+        self.set_jitter_buffer_config(config);
+        self
+    }
+
+    /// Set how long received packets are buffered
+    pub fn set_jitter_buffer_config(&mut self, config: JitterBufferConfig) {
+        self.jitter_buffer_config = config;
+    }
+
+    /// Returns how long received packets are buffered
+    pub fn jitter_buffer_config(&self) -> JitterBufferConfig {
+        self.jitter_buffer_config
+    }
+
+    /// Returns the statistics of the jitter buffer of the given remote ssrc
+    pub fn jitter_buffer_stats(&self, ssrc: Ssrc) -> Option<JitterBufferStats> {
+        let receiver = self.receiver.iter().find(|r| r.ssrc == ssrc)?;
+
+        Some(JitterBufferStats {
+            buffered_packets: receiver.jitter_buffer.len(),
+            depth: Duration::from_secs_f64(
+                receiver.jitter_buffer.depth() as f64 / f64::from(self.clock_rate.max(1)),
+            ),
+            target_delay: self.target_delay(receiver),
+            late_packets: receiver.jitter_buffer.late,
+            dropped_packets: receiver.jitter_buffer.dropped,
+        })
+    }
+
+    /// Delay applied to the packets of a receiver, depending on its interarrival jitter in adaptive mode
+    fn target_delay(&self, receiver: &ReceiverState) -> Duration {
+        self.jitter_buffer_config
+            .target_delay(receiver.jitter / self.clock_rate.max(1) as f32)
+    }
+
+    /// Bypass the jitter buffer, delivering received packets immediately in arrival order
+    ///
+    /// Useful when forwarding packets (e.g. in a relay or SFU) where buffering should only happen at the final
+    /// receiver. Packets are neither reordered nor deduplicated in this mode.
+    pub fn set_jitter_buffer_bypass(&mut self, bypass: bool) {
+        self.bypass_jitter_buffer = bypass;
+    }
+
+    /// Returns if the jitter buffer is bypassed
+    pub fn jitter_buffer_bypass(&self) -> bool {
+        self.bypass_jitter_buffer
+    }
+
     /// Add an item to the RTCP packets source description
     pub fn with_source_description_item(
         mut self,
@@ -102,6 +166,26 @@ impl RtpSession {
         self.receiver.iter().map(|r| r.ssrc)
     }
 
+    /// Remove the remote ssrc after receiving a RTCP BYE for it, dropping its jitter buffer and statistics
+    ///
+    /// Returns if the ssrc was known. Packets received from the ssrc afterwards are handled as a new source.
+    pub fn remove_remote_ssrc(&mut self, ssrc: Ssrc) -> bool {
+        let len = self.receiver.len();
+        self.receiver.retain(|receiver| receiver.ssrc != ssrc);
+        self.receiver.len() != len
+    }
+
+    /// Estimated end-to-end latency of the media received from the given ssrc
+    ///
+    /// Measured from capture at the remote side until the packet was received, which requires the sender to
+    /// include the abs-capture-time header extension and both clocks to be synchronized (e.g. using NTP).
+    pub fn end_to_end_latency(&self, ssrc: Ssrc) -> Option<Duration> {
+        self.receiver
+            .iter()
+            .find(|r| r.ssrc == ssrc)
+            .and_then(|r| r.end_to_end_latency)
+    }
+
     /// Clock rate of the RTP timestamp
     pub fn clock_rate(&self) -> u32 {
         self.clock_rate
@@ -126,7 +210,8 @@ impl RtpSession {
 
     /// Receive an RTP packet.
     ///
-    /// The session consumes the packet and puts in into a internal jitterbuffer to fix potential reordering.
+    /// The session consumes the packet and puts in into a internal jitterbuffer to fix potential reordering,
+    /// unless the jitter buffer is bypassed.
     pub fn recv_rtp(&mut self, packet: RtpPacket) {
         let receiver_status = if let Some(receiver_status) =
             self.receiver.iter_mut().find(|r| r.ssrc == packet.ssrc)
@@ -145,6 +230,7 @@ impl RtpSession {
                 jitter: 0.0,
                 last_sr: None,
                 total_lost: 0,
+                end_to_end_latency: None,
             });
 
             self.receiver.last_mut().unwrap()
@@ -152,6 +238,18 @@ impl RtpSession {
 
         let now = Instant::now();
 
+        if let Some(latency) = packet
+            .extensions
+            .abs_capture_time
+            .and_then(|abs_capture_time| abs_capture_time.latency(NtpTimestamp::now()))
+        {
+            receiver_status.end_to_end_latency = Some(match receiver_status.end_to_end_latency {
+                Some(smoothed) if latency > smoothed => smoothed + (latency - smoothed) / 16,
+                Some(smoothed) => smoothed - (smoothed - latency) / 16,
+                None => latency,
+            });
+        }
+
         // Update jitter and find extended timestamp
         if let Some((last_rtp_instant, last_rtp_timestamp, last_sequence_number)) =
             receiver_status.last_rtp_received
@@ -177,9 +275,16 @@ impl RtpSession {
 
                 receiver_status.last_rtp_received = Some((now, timestamp, sequence_number));
 
-                receiver_status
-                    .jitter_buffer
-                    .push(timestamp, sequence_number, packet);
+                if !self.bypass_jitter_buffer {
+                    receiver_status
+                        .jitter_buffer
+                        .push(timestamp, sequence_number, packet);
+                    return;
+                }
+            }
+
+            if self.bypass_jitter_buffer {
+                self.bypassed.push_back(packet);
             }
         } else {
             let timestamp = ExtendedRtpTimestamp(packet.timestamp.0.into());
@@ -187,17 +292,33 @@ impl RtpSession {
 
             receiver_status.last_rtp_received = Some((now, timestamp, sequence_number));
 
-            receiver_status
-                .jitter_buffer
-                .push(timestamp, sequence_number, packet);
+            if self.bypass_jitter_buffer {
+                self.bypassed.push_back(packet);
+            } else {
+                receiver_status
+                    .jitter_buffer
+                    .push(timestamp, sequence_number, packet);
+            }
         }
     }
 
+    /// Pop the next received packet which was buffered long enough
+    ///
+    /// `jitter_buffer_length` overrides the delay of the [`JitterBufferConfig`].
     pub fn pop_rtp(&mut self, jitter_buffer_length: Option<Duration>) -> Option<RtpPacket> {
-        let pop_earliest =
-            Instant::now() - jitter_buffer_length.unwrap_or(DEFAULT_JITTERBUFFER_LENGTH);
+        if let Some(packet) = self.bypassed.pop_front() {
+            return Some(packet);
+        }
+
+        let now = Instant::now();
+
+        for index in 0..self.receiver.len() {
+            let delay =
+                jitter_buffer_length.unwrap_or_else(|| self.target_delay(&self.receiver[index]));
+            let pop_earliest = now - delay;
+
+            let receiver = &mut self.receiver[index];
 
-        for receiver in &mut self.receiver {
             let Some((last_rtp_received_instant, last_rtp_received_timestamp, _)) =
                 receiver.last_rtp_received
             else {
@@ -220,13 +341,18 @@ impl RtpSession {
     }
 
     pub fn pop_rtp_after(&self, jitter_buffer_length: Option<Duration>) -> Option<Duration> {
-        let jitter_buffer_length = jitter_buffer_length.unwrap_or(DEFAULT_JITTERBUFFER_LENGTH);
+        if !self.bypassed.is_empty() {
+            return Some(Duration::ZERO);
+        }
 
         let now = Instant::now();
 
         self.receiver
             .iter()
             .filter_map(|receiver| {
+                let jitter_buffer_length =
+                    jitter_buffer_length.unwrap_or_else(|| self.target_delay(receiver));
+
                 let (last_rtp_received_instant, last_rtp_received_timestamp, _) =
                     receiver.last_rtp_received?;
                 let earliest_timestamp = receiver.jitter_buffer.timestamp_of_earliest_packet()?;
@@ -249,7 +375,7 @@ impl RtpSession {
                 .iter_mut()
                 .find(|status| status.ssrc.0 == sr.ssrc())
             {
-                receiver.last_sr = Some(NtpTimestamp::now());
+                receiver.last_sr = Some(((sr.ntp_timestamp() >> 16) as u32, NtpTimestamp::now()));
             }
         }
     }
@@ -269,12 +395,10 @@ impl RtpSession {
             let fraction_lost = (lost as f64 / (received + lost) as f64) * 255.0;
             let fraction_lost = fraction_lost as u32;
 
-            let (last_sr, delay) = if let Some(last_sr) = receiver.last_sr {
-                let delay = now - last_sr;
+            let (last_sr, delay) = if let Some((last_sr, received_at)) = receiver.last_sr {
+                let delay = now - received_at;
                 let delay = (delay.as_seconds_f64() * 65536.0) as u32;
 
-                let last_sr = last_sr.to_fixed_u32();
-
                 (last_sr, delay)
             } else {
                 (0, 0)
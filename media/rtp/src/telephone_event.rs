@@ -0,0 +1,561 @@
+use crate::{RtpExtensions, RtpPacket, RtpTimestamp, SequenceNumber, Ssrc};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Telephone-event code of the flash-hook signal (RFC 4733 section 3.2)
+pub const FLASH_HOOK: u8 = 16;
+
+/// Payload of a RFC 4733 telephone-event RTP packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TelephoneEvent {
+    /// Event code, `0-9`, `*`, `#` and `A-D` are `0-15`, see [`FLASH_HOOK`] for event 16
+    pub event: u8,
+
+    /// Set on the last packets of the event
+    pub end: bool,
+
+    /// Power level of the tone in -dBm0 (0-63), zero for events which are not tones
+    pub volume: u8,
+
+    /// Duration of the event so far in units of the RTP clock rate
+    pub duration: u16,
+}
+
+impl TelephoneEvent {
+    /// Parse the payload of a telephone-event RTP packet
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let [event, flags, d0, d1, ..] = *payload else {
+            return None;
+        };
+
+        Some(Self {
+            event,
+            end: flags & 0x80 != 0,
+            volume: flags & 0x3F,
+            duration: u16::from_be_bytes([d0, d1]),
+        })
+    }
+
+    /// Returns the DTMF digit (`0-9`, `*`, `#`, `A-D`) of the event, `None` for events other than `0-15`
+    pub fn digit(&self) -> Option<char> {
+        const DIGITS: &[u8; 16] = b"0123456789*#ABCD";
+
+        DIGITS
+            .get(usize::from(self.event))
+            .map(|&digit| char::from(digit))
+    }
+
+    /// Encode the event into a RTP payload
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(4);
+
+        buf.put_u8(self.event);
+        buf.put_u8(if self.end { 0x80 } else { 0 } | (self.volume & 0x3F));
+        buf.put_u16(self.duration);
+
+        buf.freeze()
+    }
+}
+
+/// Interval between the packets of a telephone-event
+const PACKET_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often the final packet of a telephone-event is sent (RFC 4733 section 2.5.1.4)
+const END_PACKETS: u8 = 3;
+
+/// Power level of generated DTMF tones in -dBm0
+const DTMF_VOLUME: u8 = 10;
+
+/// Generates the RTP packets of a single telephone-event (RFC 4733 section 2.5)
+///
+/// All packets share the timestamp the event was [started](Self::start) with. The first packet has the marker
+/// bit set, every 50ms an update with the duration so far follows and the final duration is sent three times
+/// with the end bit set. The sequence number and SSRC of the packets must be set by the caller.
+#[derive(Debug)]
+pub struct DtmfSender {
+    pt: u8,
+    event: u8,
+    volume: u8,
+
+    /// Total duration of the event in units of the clock rate
+    duration: u16,
+    /// Duration added with each update packet in units of the clock rate
+    step: u16,
+
+    state: DtmfState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DtmfState {
+    Idle,
+    Sending {
+        timestamp: RtpTimestamp,
+        /// Set until the first packet, which carries the marker bit, has been sent
+        first: bool,
+        /// Duration reported in the next packet
+        reported: u16,
+        end_packets_sent: u8,
+        next_send: Instant,
+    },
+    Finished,
+}
+
+impl DtmfSender {
+    /// Create a sender for the given event code, using the telephone-event payload type and its clock rate
+    ///
+    /// The duration is capped to what the 16 bit duration field can represent.
+    pub fn new(pt: u8, clock_rate: u32, event: u8, duration: Duration) -> Self {
+        let to_units = |duration: Duration| {
+            (duration.as_secs_f64() * f64::from(clock_rate)).min(f64::from(u16::MAX)) as u16
+        };
+
+        let volume = if event <= 15 { DTMF_VOLUME } else { 0 };
+
+        Self {
+            pt,
+            event,
+            volume,
+            duration: to_units(duration).max(1),
+            step: to_units(PACKET_INTERVAL).max(1),
+            state: DtmfState::Idle,
+        }
+    }
+
+    /// Start sending the event, `timestamp` must be the RTP timestamp of the media at the start of the event
+    ///
+    /// Does nothing if the event was already started.
+    pub fn start(&mut self, now: Instant, timestamp: RtpTimestamp) {
+        if self.state != DtmfState::Idle {
+            return;
+        }
+
+        self.state = DtmfState::Sending {
+            timestamp,
+            first: true,
+            reported: self.step.min(self.duration),
+            end_packets_sent: 0,
+            next_send: now,
+        };
+    }
+
+    /// Returns if all packets of the event have been sent
+    pub fn is_finished(&self) -> bool {
+        self.state == DtmfState::Finished
+    }
+
+    /// Returns the duration until [`poll`](Self::poll) must be called
+    pub fn timeout(&self, now: Instant) -> Option<Duration> {
+        match self.state {
+            DtmfState::Sending { next_send, .. } => {
+                Some(next_send.checked_duration_since(now).unwrap_or_default())
+            }
+            DtmfState::Idle | DtmfState::Finished => None,
+        }
+    }
+
+    /// Returns the next packet to send, must be called until it returns `None`
+    pub fn poll(&mut self, now: Instant) -> Option<RtpPacket> {
+        let DtmfState::Sending {
+            timestamp,
+            first,
+            reported,
+            end_packets_sent,
+            next_send,
+        } = self.state
+        else {
+            return None;
+        };
+
+        if next_send > now {
+            return None;
+        }
+
+        let end = reported >= self.duration;
+
+        self.state = if !end {
+            DtmfState::Sending {
+                timestamp,
+                first: false,
+                reported: reported.saturating_add(self.step).min(self.duration),
+                end_packets_sent,
+                next_send: next_send + PACKET_INTERVAL,
+            }
+        } else if end_packets_sent + 1 < END_PACKETS {
+            DtmfState::Sending {
+                timestamp,
+                first: false,
+                reported,
+                end_packets_sent: end_packets_sent + 1,
+                next_send: next_send + PACKET_INTERVAL,
+            }
+        } else {
+            DtmfState::Finished
+        };
+
+        let event = TelephoneEvent {
+            event: self.event,
+            end,
+            volume: self.volume,
+            duration: reported,
+        };
+
+        Some(RtpPacket {
+            pt: self.pt,
+            marker: first,
+            sequence_number: SequenceNumber(0),
+            ssrc: Ssrc(0),
+            timestamp,
+            extensions: RtpExtensions::default(),
+            payload: event.to_bytes(),
+            padding: None,
+        })
+    }
+}
+
+/// Reassembles received telephone-event packets into complete events (RFC 4733 section 2.5.2)
+///
+/// Each event is identified by the timestamp of its packets and reported once, when its first packet with the end
+/// bit arrives. Retransmissions of the end packet are ignored. If all end packets of an event were lost, it is
+/// reported with the last known duration once a packet of the next event arrives.
+#[derive(Debug, Default)]
+pub struct DtmfReceiver {
+    current: Option<ReceivedEvent>,
+}
+
+#[derive(Debug)]
+struct ReceivedEvent {
+    timestamp: RtpTimestamp,
+    event: TelephoneEvent,
+    reported: bool,
+}
+
+impl DtmfReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle the payload of a received telephone-event packet, returns events which have completed
+    pub fn receive(
+        &mut self,
+        timestamp: RtpTimestamp,
+        event: TelephoneEvent,
+    ) -> Vec<TelephoneEvent> {
+        let mut completed = vec![];
+
+        match &mut self.current {
+            Some(current) if current.timestamp == timestamp => {
+                if current.reported {
+                    return completed;
+                }
+
+                current.event.duration = current.event.duration.max(event.duration);
+            }
+            _ => {
+                if let Some(previous) = self.current.take() {
+                    if !previous.reported {
+                        completed.push(TelephoneEvent {
+                            end: true,
+                            ..previous.event
+                        });
+                    }
+                }
+
+                self.current = Some(ReceivedEvent {
+                    timestamp,
+                    event,
+                    reported: false,
+                });
+            }
+        }
+
+        if event.end {
+            let current = self.current.as_mut().expect("current event was set above");
+
+            current.reported = true;
+            completed.push(TelephoneEvent {
+                end: true,
+                ..current.event
+            });
+        }
+
+        completed
+    }
+}
+
+/// Set of telephone-event codes, as listed in the fmtp of the telephone-event payload type (e.g. `0-15,66`)
+#[derive(Clone, PartialEq, Eq)]
+pub struct TelephoneEvents([u64; 4]);
+
+impl TelephoneEvents {
+    /// Set containing no events
+    pub const fn empty() -> Self {
+        Self([0; 4])
+    }
+
+    /// Set containing all events in the inclusive range
+    pub fn range(first: u8, last: u8) -> Self {
+        let mut events = Self::empty();
+
+        for event in first..=last {
+            events.insert(event);
+        }
+
+        events
+    }
+
+    /// Parse the fmtp parameters of a telephone-event payload type
+    pub fn parse(fmtp: &str) -> Option<Self> {
+        let mut events = Self::empty();
+
+        for item in fmtp.split(',') {
+            let item = item.trim();
+
+            let (first, last) = match item.split_once('-') {
+                Some((first, last)) => (first.trim().parse().ok()?, last.trim().parse().ok()?),
+                None => {
+                    let event = item.parse().ok()?;
+                    (event, event)
+                }
+            };
+
+            if first > last {
+                return None;
+            }
+
+            for event in first..=last {
+                events.insert(event);
+            }
+        }
+
+        Some(events)
+    }
+
+    pub fn insert(&mut self, event: u8) {
+        self.0[usize::from(event / 64)] |= 1 << (event % 64);
+    }
+
+    pub fn contains(&self, event: u8) -> bool {
+        self.0[usize::from(event / 64)] & (1 << (event % 64)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|bits| *bits == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX).filter(|event| self.contains(*event))
+    }
+}
+
+/// Events `0-15` (DTMF digits), which must be assumed if the fmtp is missing (RFC 4733 section 2.4.1)
+impl Default for TelephoneEvents {
+    fn default() -> Self {
+        Self::range(0, 15)
+    }
+}
+
+impl fmt::Display for TelephoneEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut events = self.iter().peekable();
+        let mut first_item = true;
+
+        while let Some(first) = events.next() {
+            let mut last = first;
+
+            while let Some(next) = events.next_if(|next| Some(*next) == last.checked_add(1)) {
+                last = next;
+            }
+
+            if !first_item {
+                f.write_str(",")?;
+            }
+            first_item = false;
+
+            if first == last {
+                write!(f, "{first}")?;
+            } else {
+                write!(f, "{first}-{last}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TelephoneEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TelephoneEvents({self})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fmtp() {
+        let events = TelephoneEvents::parse("0-15,66, 70").unwrap();
+
+        assert!(events.contains(0));
+        assert!(events.contains(15));
+        assert!(!events.contains(FLASH_HOOK));
+        assert!(events.contains(66));
+        assert!(events.contains(70));
+        assert_eq!(events.to_string(), "0-15,66,70");
+
+        assert_eq!(TelephoneEvents::parse("0-16").unwrap().to_string(), "0-16");
+        assert!(TelephoneEvents::parse("16-0").is_none());
+        assert!(TelephoneEvents::parse("0-256").is_none());
+        assert!(TelephoneEvents::parse("").is_none());
+    }
+
+    #[test]
+    fn payload() {
+        let event = TelephoneEvent {
+            event: FLASH_HOOK,
+            end: true,
+            volume: 10,
+            duration: 1600,
+        };
+
+        let bytes = event.to_bytes();
+        assert_eq!(&bytes[..], &[16, 0x8A, 0x06, 0x40]);
+        assert_eq!(TelephoneEvent::parse(&bytes), Some(event));
+        assert_eq!(TelephoneEvent::parse(&bytes[..3]), None);
+    }
+
+    fn send_all(sender: &mut DtmfSender, start: Instant) -> Vec<(Instant, RtpPacket)> {
+        let mut now = start;
+        let mut packets = vec![];
+
+        sender.start(now, RtpTimestamp(1000));
+
+        while let Some(timeout) = sender.timeout(now) {
+            now += timeout;
+
+            while let Some(packet) = sender.poll(now) {
+                packets.push((now, packet));
+            }
+        }
+
+        packets
+    }
+
+    #[test]
+    fn dtmf_sender() {
+        let start = Instant::now();
+        let mut sender = DtmfSender::new(101, 8000, 5, Duration::from_millis(120));
+
+        let packets = send_all(&mut sender, start);
+        assert!(sender.is_finished());
+
+        let events: Vec<TelephoneEvent> = packets
+            .iter()
+            .map(|(_, packet)| TelephoneEvent::parse(&packet.payload).unwrap())
+            .collect();
+
+        let durations: Vec<u16> = events.iter().map(|e| e.duration).collect();
+        assert_eq!(durations, [400, 800, 960, 960, 960]);
+
+        let ends: Vec<bool> = events.iter().map(|e| e.end).collect();
+        assert_eq!(ends, [false, false, true, true, true]);
+
+        let markers: Vec<bool> = packets.iter().map(|(_, p)| p.marker).collect();
+        assert_eq!(markers, [true, false, false, false, false]);
+
+        assert!(packets.iter().all(|(_, p)| p.pt == 101));
+        assert!(packets
+            .iter()
+            .all(|(_, p)| p.timestamp == RtpTimestamp(1000)));
+        assert!(events
+            .iter()
+            .all(|e| e.event == 5 && e.volume == DTMF_VOLUME));
+
+        assert_eq!(packets[0].0, start);
+        assert_eq!(packets[4].0 - start, PACKET_INTERVAL * 4);
+    }
+
+    #[test]
+    fn dtmf_sender_short_event() {
+        let mut sender = DtmfSender::new(101, 8000, 1, Duration::from_millis(20));
+
+        let packets = send_all(&mut sender, Instant::now());
+
+        assert_eq!(packets.len(), usize::from(END_PACKETS));
+        assert!(packets[0].1.marker);
+
+        for (_, packet) in &packets {
+            let event = TelephoneEvent::parse(&packet.payload).unwrap();
+            assert!(event.end);
+            assert_eq!(event.duration, 160);
+        }
+    }
+
+    fn event(event: u8, end: bool, duration: u16) -> TelephoneEvent {
+        TelephoneEvent {
+            event,
+            end,
+            volume: 10,
+            duration,
+        }
+    }
+
+    #[test]
+    fn digit() {
+        assert_eq!(event(0, false, 0).digit(), Some('0'));
+        assert_eq!(event(10, false, 0).digit(), Some('*'));
+        assert_eq!(event(15, false, 0).digit(), Some('D'));
+        assert_eq!(event(FLASH_HOOK, false, 0).digit(), None);
+    }
+
+    #[test]
+    fn dtmf_receiver() {
+        let mut receiver = DtmfReceiver::new();
+
+        assert!(receiver
+            .receive(RtpTimestamp(100), event(1, false, 400))
+            .is_empty());
+        assert!(receiver
+            .receive(RtpTimestamp(100), event(1, false, 800))
+            .is_empty());
+        assert_eq!(
+            receiver.receive(RtpTimestamp(100), event(1, true, 960)),
+            [event(1, true, 960)]
+        );
+
+        // Retransmitted end packets
+        assert!(receiver
+            .receive(RtpTimestamp(100), event(1, true, 960))
+            .is_empty());
+        assert!(receiver
+            .receive(RtpTimestamp(100), event(1, true, 960))
+            .is_empty());
+
+        // The same digit again, as a new event
+        assert!(receiver
+            .receive(RtpTimestamp(2000), event(1, false, 400))
+            .is_empty());
+        assert_eq!(
+            receiver.receive(RtpTimestamp(2000), event(1, true, 800)),
+            [event(1, true, 800)]
+        );
+    }
+
+    #[test]
+    fn dtmf_receiver_lost_end() {
+        let mut receiver = DtmfReceiver::new();
+
+        assert!(receiver
+            .receive(RtpTimestamp(100), event(4, false, 400))
+            .is_empty());
+        assert!(receiver
+            .receive(RtpTimestamp(100), event(4, false, 800))
+            .is_empty());
+
+        assert_eq!(
+            receiver.receive(RtpTimestamp(3000), event(7, false, 400)),
+            [event(4, true, 800)]
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+const DTMF_ROWS: [f64; 4] = [697.0, 770.0, 852.0, 941.0];
+const DTMF_COLUMNS: [f64; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+const DTMF_DIGITS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// Calling tone sent by a fax machine originating a call
+const CNG_FREQUENCY: f64 = 1100.0;
+/// Answer tone sent by a fax machine (or modem) answering a call
+const CED_FREQUENCY: f64 = 2100.0;
+
+/// Length of a single analysis block
+const BLOCK_DURATION_MS: u32 = 25;
+
+/// Minimum mean power of a block to be analyzed at all (roughly -40 dBov)
+const MIN_BLOCK_POWER: f64 = 1e-4;
+
+/// Minimum share of the block's energy in the two DTMF frequencies
+const DTMF_MIN_ENERGY_RATIO: f64 = 0.6;
+/// Maximum allowed level difference between the row and column frequency (8 dB)
+const DTMF_MAX_TWIST: f64 = 6.3;
+/// Number of consecutive blocks a digit must be present in to be reported
+const DTMF_MIN_BLOCKS: u32 = 2;
+
+/// Minimum share of the block's energy in the fax tone frequency
+const FAX_MIN_ENERGY_RATIO: f64 = 0.7;
+/// Number of consecutive blocks the CNG tone (500ms on, 3s off) must be present (400ms)
+const CNG_MIN_BLOCKS: u32 = 16;
+/// Number of consecutive blocks the CED tone (2.6s - 4s) must be present (500ms)
+const CED_MIN_BLOCKS: u32 = 20;
+
+/// Tone detected by the [`ToneDetector`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    /// Inband DTMF digit (`0-9`, `*`, `#`, `A-D`)
+    Dtmf(char),
+
+    /// Fax calling tone (1100 Hz)
+    FaxCng,
+
+    /// Fax answer tone (2100 Hz)
+    FaxCed,
+}
+
+/// Detects inband DTMF and fax tones (CNG/CED) in decoded audio using the Goertzel algorithm
+///
+/// Every tone is reported once, after it has been present long enough. It is reported again only after it stopped.
+#[derive(Debug)]
+pub struct ToneDetector {
+    block_len: usize,
+    block: Vec<f64>,
+
+    dtmf_rows: [Goertzel; 4],
+    dtmf_columns: [Goertzel; 4],
+    cng: Goertzel,
+    ced: Goertzel,
+
+    dtmf: ToneState<char>,
+    fax: ToneState<Tone>,
+
+    detected: VecDeque<Tone>,
+}
+
+impl ToneDetector {
+    /// Create a new detector for audio with the given sample rate
+    pub fn new(sample_rate: u32) -> Self {
+        let block_len = (sample_rate * BLOCK_DURATION_MS / 1000) as usize;
+
+        Self {
+            block_len,
+            block: Vec::with_capacity(block_len),
+            dtmf_rows: DTMF_ROWS.map(|f| Goertzel::new(f, sample_rate)),
+            dtmf_columns: DTMF_COLUMNS.map(|f| Goertzel::new(f, sample_rate)),
+            cng: Goertzel::new(CNG_FREQUENCY, sample_rate),
+            ced: Goertzel::new(CED_FREQUENCY, sample_rate),
+            dtmf: ToneState::default(),
+            fax: ToneState::default(),
+            detected: VecDeque::new(),
+        }
+    }
+
+    /// Process decoded 16 bit PCM samples, detected tones can be retrieved using [`pop_tone`](Self::pop_tone)
+    pub fn process_samples(&mut self, samples: &[i16]) {
+        for &sample in samples {
+            self.block.push(f64::from(sample) / f64::from(i16::MAX));
+
+            if self.block.len() == self.block_len {
+                self.analyze_block();
+                self.block.clear();
+            }
+        }
+    }
+
+    /// Returns the next detected tone
+    pub fn pop_tone(&mut self) -> Option<Tone> {
+        self.detected.pop_front()
+    }
+
+    fn analyze_block(&mut self) {
+        let energy: f64 = self.block.iter().map(|s| s * s).sum();
+
+        if energy / (self.block.len() as f64) < MIN_BLOCK_POWER {
+            self.dtmf.update(None);
+            self.fax.update(None);
+            return;
+        }
+
+        // Normalize the Goertzel power, so that a pure sine at the frequency results in 1.0
+        let norm = energy * self.block.len() as f64 / 2.0;
+        let ratio = |g: &Goertzel| g.power(&self.block) / norm;
+
+        let rows = self.dtmf_rows.each_ref().map(ratio);
+        let columns = self.dtmf_columns.each_ref().map(ratio);
+
+        let digit = dtmf_digit(&rows, &columns);
+
+        if let Some(digit) = self.dtmf.update(digit) {
+            if self.dtmf.count == DTMF_MIN_BLOCKS {
+                self.detected.push_back(Tone::Dtmf(digit));
+            }
+        }
+
+        let fax_tone = if ratio(&self.cng) > FAX_MIN_ENERGY_RATIO {
+            Some(Tone::FaxCng)
+        } else if ratio(&self.ced) > FAX_MIN_ENERGY_RATIO {
+            Some(Tone::FaxCed)
+        } else {
+            None
+        };
+
+        if let Some(tone) = self.fax.update(fax_tone) {
+            let min_blocks = match tone {
+                Tone::FaxCng => CNG_MIN_BLOCKS,
+                _ => CED_MIN_BLOCKS,
+            };
+
+            if self.fax.count == min_blocks {
+                self.detected.push_back(tone);
+            }
+        }
+    }
+}
+
+/// Returns the DTMF digit if both a row and column frequency clearly dominate the block
+fn dtmf_digit(rows: &[f64; 4], columns: &[f64; 4]) -> Option<char> {
+    let (row, row_ratio) = strongest(rows);
+    let (column, column_ratio) = strongest(columns);
+
+    if row_ratio + column_ratio < DTMF_MIN_ENERGY_RATIO {
+        return None;
+    }
+
+    if row_ratio > column_ratio * DTMF_MAX_TWIST || column_ratio > row_ratio * DTMF_MAX_TWIST {
+        return None;
+    }
+
+    Some(DTMF_DIGITS[row][column])
+}
+
+fn strongest(ratios: &[f64; 4]) -> (usize, f64) {
+    ratios
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .expect("ratios is not empty")
+}
+
+/// Tracks for how many consecutive blocks a tone has been present
+#[derive(Debug)]
+struct ToneState<T> {
+    current: Option<T>,
+    count: u32,
+}
+
+impl<T> Default for ToneState<T> {
+    fn default() -> Self {
+        Self {
+            current: None,
+            count: 0,
+        }
+    }
+}
+
+impl<T: Copy + PartialEq> ToneState<T> {
+    fn update(&mut self, tone: Option<T>) -> Option<T> {
+        if tone.is_some() && tone == self.current {
+            self.count += 1;
+        } else {
+            self.current = tone;
+            self.count = u32::from(tone.is_some());
+        }
+
+        self.current
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Goertzel {
+    coeff: f64,
+}
+
+impl Goertzel {
+    pub(crate) fn new(frequency: f64, sample_rate: u32) -> Self {
+        Self {
+            coeff: 2.0 * (2.0 * PI * frequency / f64::from(sample_rate)).cos(),
+        }
+    }
+
+    pub(crate) fn power(&self, samples: &[f64]) -> f64 {
+        let mut s1 = 0.0;
+        let mut s2 = 0.0;
+
+        for &sample in samples {
+            let s = sample + self.coeff * s1 - s2;
+            s2 = s1;
+            s1 = s;
+        }
+
+        s1 * s1 + s2 * s2 - self.coeff * s1 * s2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequencies: &[f64], sample_rate: u32, duration_ms: u32) -> Vec<i16> {
+        let len = sample_rate * duration_ms / 1000;
+        let amplitude = 0.4 / frequencies.len() as f64;
+
+        (0..len)
+            .map(|i| {
+                let t = f64::from(i) / f64::from(sample_rate);
+
+                let v: f64 = frequencies
+                    .iter()
+                    .map(|f| amplitude * (2.0 * PI * f * t).sin())
+                    .sum();
+
+                (v * f64::from(i16::MAX)) as i16
+            })
+            .collect()
+    }
+
+    fn detect(detector: &mut ToneDetector, samples: &[i16]) -> Vec<Tone> {
+        detector.process_samples(samples);
+
+        std::iter::from_fn(|| detector.pop_tone()).collect()
+    }
+
+    #[test]
+    fn dtmf_digits() {
+        let mut detector = ToneDetector::new(8000);
+
+        for (row, frequencies) in DTMF_DIGITS.iter().enumerate() {
+            for (column, &digit) in frequencies.iter().enumerate() {
+                let samples = tone(&[DTMF_ROWS[row], DTMF_COLUMNS[column]], 8000, 100);
+
+                assert_eq!(detect(&mut detector, &samples), [Tone::Dtmf(digit)]);
+                assert!(detect(&mut detector, &[0; 400]).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn dtmf_repeated_digit() {
+        let mut detector = ToneDetector::new(16000);
+
+        let mut samples = tone(&[697.0, 1209.0], 16000, 80);
+        samples.extend_from_slice(&[0; 800]);
+        samples.extend(tone(&[697.0, 1209.0], 16000, 80));
+
+        assert_eq!(
+            detect(&mut detector, &samples),
+            [Tone::Dtmf('1'), Tone::Dtmf('1')]
+        );
+    }
+
+    #[test]
+    fn single_frequency_is_not_dtmf() {
+        let mut detector = ToneDetector::new(8000);
+
+        assert!(detect(&mut detector, &tone(&[697.0], 8000, 200)).is_empty());
+        assert!(detect(&mut detector, &tone(&[440.0, 350.0], 8000, 200)).is_empty());
+    }
+
+    #[test]
+    fn fax_tones() {
+        let mut detector = ToneDetector::new(8000);
+
+        assert_eq!(
+            detect(&mut detector, &tone(&[CNG_FREQUENCY], 8000, 500)),
+            [Tone::FaxCng]
+        );
+        assert!(detect(&mut detector, &[0; 8000]).is_empty());
+
+        assert!(detect(&mut detector, &tone(&[CED_FREQUENCY], 8000, 300)).is_empty());
+        assert_eq!(
+            detect(&mut detector, &tone(&[CED_FREQUENCY], 8000, 2000)),
+            [Tone::FaxCed]
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{DePayloader, Payloader};
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// Highest 15 bit picture ID, picture IDs wrap around to 0 after it
+const MAX_PICTURE_ID: u16 = 0x7FFF;
+
+/// VP8 payload descriptor, prepended to the VP8 data in every RTP packet (RFC 7741 section 4.2)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Vp8PayloadDescriptor {
+    /// The frame is not used as reference for other frames and can be discarded
+    pub non_reference: bool,
+
+    /// The packet starts a VP8 partition
+    pub start_of_partition: bool,
+
+    /// Index of the partition the packet belongs to (0-7)
+    pub partition_index: u8,
+
+    /// 7 or 15 bit running index of the frames
+    pub picture_id: Option<u16>,
+
+    /// Running index of the frames of the base temporal layer
+    pub tl0_pic_idx: Option<u8>,
+
+    /// Temporal layer index and the layer sync bit
+    pub temporal_layer: Option<(u8, bool)>,
+
+    /// Running index of the keyframes
+    pub key_idx: Option<u8>,
+}
+
+impl Vp8PayloadDescriptor {
+    /// Parse the descriptor at the start of a RTP payload, returns it with its length
+    pub fn parse(payload: &[u8]) -> Option<(Self, usize)> {
+        let mut bytes = payload.iter().copied();
+        let mut len = 0;
+        let mut next = || {
+            len += 1;
+            bytes.next()
+        };
+
+        let first = next()?;
+
+        let mut descriptor = Self {
+            non_reference: first & 0x20 != 0,
+            start_of_partition: first & 0x10 != 0,
+            partition_index: first & 0x07,
+            ..Self::default()
+        };
+
+        if first & 0x80 == 0 {
+            return Some((descriptor, len));
+        }
+
+        let extension = next()?;
+
+        if extension & 0x80 != 0 {
+            let picture_id = next()?;
+
+            descriptor.picture_id = Some(if picture_id & 0x80 != 0 {
+                u16::from_be_bytes([picture_id & 0x7F, next()?])
+            } else {
+                u16::from(picture_id)
+            });
+        }
+
+        if extension & 0x40 != 0 {
+            descriptor.tl0_pic_idx = Some(next()?);
+        }
+
+        if extension & 0x30 != 0 {
+            let tid_keyidx = next()?;
+
+            if extension & 0x20 != 0 {
+                descriptor.temporal_layer = Some((tid_keyidx >> 6, tid_keyidx & 0x20 != 0));
+            }
+
+            if extension & 0x10 != 0 {
+                descriptor.key_idx = Some(tid_keyidx & 0x1F);
+            }
+        }
+
+        Some((descriptor, len))
+    }
+
+    /// Write the descriptor, the picture ID is always written using 15 bits
+    pub fn write(&self, buf: &mut BytesMut) {
+        let extension = u8::from(self.picture_id.is_some()) << 7
+            | u8::from(self.tl0_pic_idx.is_some()) << 6
+            | u8::from(self.temporal_layer.is_some()) << 5
+            | u8::from(self.key_idx.is_some()) << 4;
+
+        buf.put_u8(
+            u8::from(extension != 0) << 7
+                | u8::from(self.non_reference) << 5
+                | u8::from(self.start_of_partition) << 4
+                | (self.partition_index & 0x07),
+        );
+
+        if extension == 0 {
+            return;
+        }
+
+        buf.put_u8(extension);
+
+        if let Some(picture_id) = self.picture_id {
+            buf.put_u16(0x8000 | (picture_id & MAX_PICTURE_ID));
+        }
+
+        if let Some(tl0_pic_idx) = self.tl0_pic_idx {
+            buf.put_u8(tl0_pic_idx);
+        }
+
+        if self.temporal_layer.is_some() || self.key_idx.is_some() {
+            let (tid, layer_sync) = self.temporal_layer.unwrap_or_default();
+
+            buf.put_u8(
+                (tid & 0x03) << 6 | u8::from(layer_sync) << 5 | (self.key_idx.unwrap_or(0) & 0x1F),
+            );
+        }
+    }
+}
+
+/// Returns if the VP8 frame is a keyframe, using the inverse keyframe flag of its frame tag
+pub fn vp8_is_keyframe(frame: &[u8]) -> bool {
+    frame.first().is_some_and(|tag| tag & 0x01 == 0)
+}
+
+/// Returns if the RTP payload contains the start of a VP8 keyframe
+pub fn vp8_payload_is_keyframe(payload: &[u8]) -> bool {
+    let Some((descriptor, len)) = Vp8PayloadDescriptor::parse(payload) else {
+        return false;
+    };
+
+    descriptor.start_of_partition
+        && descriptor.partition_index == 0
+        && vp8_is_keyframe(&payload[len..])
+}
+
+/// Payloader of VP8 frames (RFC 7741)
+///
+/// Every packet carries a 15 bit picture ID, increased with every frame. The frame is split into packets
+/// without regard of its partitions, the first packet is flagged as start of partition 0.
+#[derive(Debug)]
+pub struct Vp8Payloader {
+    picture_id: u16,
+}
+
+impl Vp8Payloader {
+    /// Create a payloader starting with the given picture ID, which should be random
+    pub fn new(picture_id: u16) -> Self {
+        Self {
+            picture_id: picture_id & MAX_PICTURE_ID,
+        }
+    }
+}
+
+impl Payloader for Vp8Payloader {
+    fn payload(&mut self, frame: &Bytes, max_size: usize) -> impl Iterator<Item = Bytes> + '_ {
+        let mut descriptor = Vp8PayloadDescriptor {
+            picture_id: Some(self.picture_id),
+            ..Vp8PayloadDescriptor::default()
+        };
+
+        self.picture_id = (self.picture_id + 1) & MAX_PICTURE_ID;
+
+        // Payload descriptor with the extension byte and 15 bit picture ID
+        let header_len = 4;
+        let chunk_size = max_size.saturating_sub(header_len).max(1);
+
+        let mut packets = vec![];
+
+        for (i, chunk) in frame.chunks(chunk_size).enumerate() {
+            descriptor.start_of_partition = i == 0;
+
+            let mut packet = BytesMut::with_capacity(header_len + chunk.len());
+            descriptor.write(&mut packet);
+            packet.put_slice(chunk);
+
+            packets.push(packet.freeze());
+        }
+
+        packets.into_iter()
+    }
+}
+
+/// Depayloader of VP8 RTP packets (RFC 7741), returning complete frames
+///
+/// A frame is returned once the first packet of the next frame arrives, or earlier by calling
+/// [`end_frame`](Self::end_frame) when receiving a packet with the RTP marker bit set.
+///
+/// Packets must be given in sequence number order. A frame is dropped if its first packet is missing or packets
+/// of another picture ID are received in between.
+#[derive(Debug, Default)]
+pub struct Vp8DePayloader {
+    frame: Option<BytesMut>,
+    picture_id: Option<u16>,
+}
+
+impl Vp8DePayloader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the frame received so far, to be called after the packet with the RTP marker bit
+    pub fn end_frame(&mut self) -> Option<Bytes> {
+        self.picture_id = None;
+        self.frame.take().map(BytesMut::freeze)
+    }
+}
+
+impl DePayloader for Vp8DePayloader {
+    fn depayload(&mut self, payload: &Bytes) -> Option<Bytes> {
+        let (descriptor, len) = Vp8PayloadDescriptor::parse(payload)?;
+        let data = &payload[len..];
+
+        if descriptor.start_of_partition && descriptor.partition_index == 0 {
+            let previous = self.end_frame();
+
+            self.frame = Some(BytesMut::from(data));
+            self.picture_id = descriptor.picture_id;
+
+            return previous;
+        }
+
+        if self.frame.is_some() && descriptor.picture_id != self.picture_id {
+            // Lost the end of the frame and the start of the next
+            self.end_frame();
+            return None;
+        }
+
+        self.frame.as_mut()?.put_slice(data);
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptor() {
+        let descriptor = Vp8PayloadDescriptor {
+            non_reference: true,
+            start_of_partition: true,
+            partition_index: 1,
+            picture_id: Some(0x1234),
+            tl0_pic_idx: Some(7),
+            temporal_layer: Some((2, true)),
+            key_idx: Some(3),
+        };
+
+        let mut buf = BytesMut::new();
+        descriptor.write(&mut buf);
+
+        assert_eq!(&buf[..], &[0xB1, 0xF0, 0x92, 0x34, 7, 0xA3]);
+        assert_eq!(Vp8PayloadDescriptor::parse(&buf), Some((descriptor, 6)));
+
+        // 7 bit picture ID
+        assert_eq!(
+            Vp8PayloadDescriptor::parse(&[0x90, 0x80, 0x05, 0xAA]),
+            Some((
+                Vp8PayloadDescriptor {
+                    start_of_partition: true,
+                    picture_id: Some(5),
+                    ..Vp8PayloadDescriptor::default()
+                },
+                3
+            ))
+        );
+
+        assert_eq!(Vp8PayloadDescriptor::parse(&[0x80, 0x80]), None);
+    }
+
+    #[test]
+    fn payload() {
+        let frame = Bytes::from_static(&[0x10, 1, 2, 3, 4, 5, 6]);
+
+        let mut payloader = Vp8Payloader::new(MAX_PICTURE_ID);
+        let packets: Vec<Bytes> = payloader.payload(&frame, 8).collect();
+
+        assert_eq!(
+            packets,
+            [
+                &[0x90, 0x80, 0xFF, 0xFF, 0x10, 1, 2, 3][..],
+                &[0x80, 0x80, 0xFF, 0xFF, 4, 5, 6],
+            ]
+        );
+
+        // Picture ID wraps around
+        let packets: Vec<Bytes> = payloader.payload(&frame, 100).collect();
+        assert_eq!(&packets[0][..4], &[0x90, 0x80, 0x80, 0x00]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let keyframe = Bytes::from_static(&[0x10, 0x02, 0x00, 0x9D, 0x01, 0x2A, 1, 2, 3]);
+        let interframe = Bytes::from_static(&[0x11, 4, 5, 6, 7]);
+
+        let mut payloader = Vp8Payloader::new(100);
+        let mut depayloader = Vp8DePayloader::new();
+
+        let packets: Vec<Bytes> = payloader.payload(&keyframe, 8).collect();
+        assert!(vp8_payload_is_keyframe(&packets[0]));
+        assert!(!vp8_payload_is_keyframe(&packets[1]));
+
+        for packet in &packets {
+            assert!(depayloader.depayload(packet).is_none());
+        }
+
+        let packets: Vec<Bytes> = payloader.payload(&interframe, 8).collect();
+        assert!(!vp8_payload_is_keyframe(&packets[0]));
+
+        // The keyframe is returned once the next frame starts
+        let received = depayloader.depayload(&packets[0]).unwrap();
+        assert_eq!(received, keyframe);
+        assert!(vp8_is_keyframe(&received));
+
+        for packet in &packets[1..] {
+            assert!(depayloader.depayload(packet).is_none());
+        }
+
+        assert_eq!(depayloader.end_frame().unwrap(), interframe);
+    }
+
+    #[test]
+    fn missing_start() {
+        let frame = Bytes::from_static(&[0x11, 1, 2, 3, 4, 5, 6]);
+
+        let mut payloader = Vp8Payloader::new(0);
+        let mut depayloader = Vp8DePayloader::new();
+
+        let packets: Vec<Bytes> = payloader.payload(&frame, 8).collect();
+
+        assert!(depayloader.depayload(&packets[1]).is_none());
+        assert!(depayloader.end_frame().is_none());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::G711;
+use std::fmt;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_ALAW: u16 = 6;
+const FORMAT_ULAW: u16 = 7;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Error returned by [`WavAudio::parse`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WavError {
+    /// The data is not a RIFF WAVE file
+    NotWave,
+    /// A required chunk is missing or truncated
+    MissingChunk(&'static str),
+    /// The sample format is not supported
+    Unsupported { format: u16, bits_per_sample: u16 },
+}
+
+impl fmt::Display for WavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WavError::NotWave => f.write_str("not a RIFF WAVE file"),
+            WavError::MissingChunk(chunk) => write!(f, "missing or truncated {chunk} chunk"),
+            WavError::Unsupported {
+                format,
+                bits_per_sample,
+            } => write!(
+                f,
+                "unsupported WAV format {format:#06x} with {bits_per_sample} bits per sample"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WavError {}
+
+/// Decoded audio of a WAV file
+///
+/// Supports 8 & 16 bit PCM, A-law and μ-law encoded files with any number of channels and sample rate.
+#[derive(Debug, Clone)]
+pub struct WavAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Interleaved 16 bit PCM samples of all channels
+    pub samples: Vec<i16>,
+}
+
+impl WavAudio {
+    pub fn parse(data: &[u8]) -> Result<Self, WavError> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(WavError::NotWave);
+        }
+
+        let mut format = None;
+        let mut samples = None;
+
+        let mut chunks = &data[12..];
+
+        while chunks.len() >= 8 {
+            let id = &chunks[0..4];
+            let len = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+
+            // Files written while recording may have a too large data chunk length
+            let body = &chunks[8..(8 + len).min(chunks.len())];
+
+            match id {
+                b"fmt " => format = Some(Format::parse(body)?),
+                b"data" => samples = Some(body),
+                _ => {}
+            }
+
+            // Chunks are padded to an even length
+            let next = 8 + len + (len & 1);
+            chunks = chunks.get(next..).unwrap_or_default();
+        }
+
+        let format = format.ok_or(WavError::MissingChunk("fmt"))?;
+        let data = samples.ok_or(WavError::MissingChunk("data"))?;
+
+        let samples = match (format.format, format.bits_per_sample) {
+            (FORMAT_PCM, 16) => data
+                .chunks_exact(2)
+                .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+                .collect(),
+            (FORMAT_PCM, 8) => data
+                .iter()
+                .map(|sample| (i16::from(*sample) - 128) << 8)
+                .collect(),
+            (FORMAT_ALAW, 8) => {
+                let mut samples = Vec::with_capacity(data.len());
+                G711::Pcma.decode(data, &mut samples);
+                samples
+            }
+            (FORMAT_ULAW, 8) => {
+                let mut samples = Vec::with_capacity(data.len());
+                G711::Pcmu.decode(data, &mut samples);
+                samples
+            }
+            (format, bits_per_sample) => {
+                return Err(WavError::Unsupported {
+                    format,
+                    bits_per_sample,
+                })
+            }
+        };
+
+        Ok(Self {
+            sample_rate: format.sample_rate,
+            channels: format.channels.max(1),
+            samples,
+        })
+    }
+
+    /// Returns the audio mixed down to a single channel and resampled to the given sample rate
+    ///
+    /// Uses linear interpolation, which is good enough for speech prompts.
+    pub fn to_mono(&self, sample_rate: u32) -> Vec<i16> {
+        let channels = usize::from(self.channels);
+
+        let mono: Vec<i16> = self
+            .samples
+            .chunks_exact(channels)
+            .map(|frame| {
+                let sum: i32 = frame.iter().copied().map(i32::from).sum();
+                (sum / channels as i32) as i16
+            })
+            .collect();
+
+        if self.sample_rate == sample_rate || mono.is_empty() || self.sample_rate == 0 {
+            return mono;
+        }
+
+        let len =
+            (mono.len() as u64 * u64::from(sample_rate) / u64::from(self.sample_rate)) as usize;
+
+        (0..len)
+            .map(|i| {
+                // Position of the output sample in the input, as 32.32 fixed point number
+                let position =
+                    ((i as u128) << 32) * u128::from(self.sample_rate) / u128::from(sample_rate);
+                let index = (position >> 32) as usize;
+                let fraction = (position & 0xFFFF_FFFF) as i64;
+
+                let a = i64::from(mono[index]);
+                let b = i64::from(*mono.get(index + 1).unwrap_or(&mono[index]));
+
+                (a + (((b - a) * fraction) >> 32)) as i16
+            })
+            .collect()
+    }
+}
+
+struct Format {
+    format: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+}
+
+impl Format {
+    fn parse(fmt: &[u8]) -> Result<Self, WavError> {
+        if fmt.len() < 16 {
+            return Err(WavError::MissingChunk("fmt"));
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes([fmt[i], fmt[i + 1]]);
+
+        let mut format = u16_at(0);
+
+        // The actual format is the start of the sub format GUID
+        if format == FORMAT_EXTENSIBLE {
+            if fmt.len() < 26 {
+                return Err(WavError::MissingChunk("fmt"));
+            }
+
+            format = u16_at(24);
+        }
+
+        Ok(Self {
+            format,
+            channels: u16_at(2),
+            sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+            bits_per_sample: u16_at(14),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(format: u16, channels: u16, sample_rate: u32, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut wav = vec![];
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&format.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * u32::from(channels * bits / 8)).to_le_bytes());
+        wav.extend_from_slice(&(channels * bits / 8).to_le_bytes());
+        wav.extend_from_slice(&bits.to_le_bytes());
+        wav.extend_from_slice(b"LIST\x03\x00\x00\x00abc\x00");
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(data);
+        wav
+    }
+
+    #[test]
+    fn parse_stereo_pcm() {
+        let data: Vec<u8> = [100i16, 300, -100, -300]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+
+        let audio = WavAudio::parse(&wav(FORMAT_PCM, 2, 16000, 16, &data)).unwrap();
+
+        assert_eq!(audio.sample_rate, 16000);
+        assert_eq!(audio.samples, [100, 300, -100, -300]);
+        assert_eq!(audio.to_mono(16000), [200, -200]);
+        assert_eq!(audio.to_mono(8000), [200]);
+        assert_eq!(audio.to_mono(32000), [200, 0, -200, -200]);
+    }
+
+    #[test]
+    fn parse_ulaw() {
+        let audio = WavAudio::parse(&wav(FORMAT_ULAW, 1, 8000, 8, &[0xFF, 0xFF])).unwrap();
+
+        assert_eq!(audio.samples, [0, 0]);
+    }
+
+    #[test]
+    fn unsupported() {
+        assert_eq!(
+            WavAudio::parse(&wav(3, 1, 8000, 32, &[0; 8])).unwrap_err(),
+            WavError::Unsupported {
+                format: 3,
+                bits_per_sample: 32
+            }
+        );
+        assert_eq!(WavAudio::parse(b"RIFF").unwrap_err(), WavError::NotWave);
+    }
+}
@@ -0,0 +1,77 @@
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use std::fmt;
+
+/// Content attribute value (`a=content`), describes what a media stream is used for
+///
+/// [RFC4796](https://www.rfc-editor.org/rfc/rfc4796.html)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    /// Presentation slides, or screen sharing in general
+    Slides,
+    /// Image from the speaker
+    Speaker,
+    /// Sign language
+    SignLanguage,
+    /// Main media stream, e.g. the camera
+    Main,
+    /// Alternative media stream
+    Alt,
+    Other(BytesStr),
+}
+
+impl Content {
+    /// Parse the comma separated list of content values
+    pub fn parse_list(src: &Bytes, i: &str) -> Vec<Self> {
+        i.split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| match value {
+                "slides" => Self::Slides,
+                "speaker" => Self::Speaker,
+                "sl" => Self::SignLanguage,
+                "main" => Self::Main,
+                "alt" => Self::Alt,
+                _ => Self::Other(BytesStr::from_parse(src, value)),
+            })
+            .collect()
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Slides => "slides",
+            Self::Speaker => "speaker",
+            Self::SignLanguage => "sl",
+            Self::Main => "main",
+            Self::Alt => "alt",
+            Self::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn content() {
+        let input = BytesStr::from_static("slides, main,x-custom");
+
+        let content = Content::parse_list(input.as_ref(), &input);
+
+        assert_eq!(
+            content,
+            [
+                Content::Slides,
+                Content::Main,
+                Content::Other(BytesStr::from_static("x-custom"))
+            ]
+        );
+    }
+}
@@ -87,7 +87,7 @@ impl fmt::Display for Fingerprint {
         let mut iter = self.fingerprint.iter();
 
         if let Some(b) = iter.next() {
-            write!(f, "{b:2X}")?;
+            write!(f, "{b:02X}")?;
 
             for b in iter {
                 write!(f, ":{b:02X}")?;
@@ -123,4 +123,14 @@ mod test {
 
         assert_eq!(fingerprint.to_string(), input.as_str());
     }
+
+    #[test]
+    fn fingerprint_print_leading_zero() {
+        let fingerprint = Fingerprint {
+            algorithm: FingerprintAlgorithm::SHA384,
+            fingerprint: vec![0x05, 0xAB, 0x00],
+        };
+
+        assert_eq!(fingerprint.to_string(), "SHA-384 05:AB:00");
+    }
 }
@@ -3,6 +3,7 @@ use bytesstr::BytesStr;
 use std::fmt;
 
 mod candidate;
+mod content;
 mod crypto;
 mod direction;
 mod extmap;
@@ -11,11 +12,13 @@ mod fmtp;
 mod group;
 mod ice;
 mod rtcp;
+mod rtcp_fb;
 mod rtpmap;
 mod setup;
 mod ssrc;
 
 pub use candidate::{IceCandidate, InvalidCandidateParamError, UntaggedAddress};
+pub use content::Content;
 pub use crypto::{SrtpCrypto, SrtpFecOrder, SrtpKeyingMaterial, SrtpSessionParam, SrtpSuite};
 pub use direction::Direction;
 pub use extmap::ExtMap;
@@ -24,6 +27,7 @@ pub use fmtp::Fmtp;
 pub use group::Group;
 pub use ice::{IceOptions, IcePassword, IceUsernameFragment};
 pub use rtcp::Rtcp;
+pub use rtcp_fb::RtcpFeedback;
 pub use rtpmap::RtpMap;
 pub use setup::Setup;
 pub use ssrc::{SourceAttribute, Ssrc};
@@ -0,0 +1,129 @@
+//! RTCP feedback capability attribute (`a=rtcp-fb:...`)
+
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use internal::IResult;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while, take_while1};
+use nom::character::complete::digit1;
+use nom::combinator::{map, map_res, opt};
+use nom::error::context;
+use nom::sequence::{preceded, tuple};
+use std::fmt;
+use std::str::FromStr;
+
+/// RTCP feedback capability attribute (`a=rtcp-fb`)
+///
+/// Announces a type of RTCP feedback message supported for a payload type or all payload types
+///
+/// [RFC4585](https://www.rfc-editor.org/rfc/rfc4585.html#section-4.2)
+#[derive(Debug, Clone)]
+pub struct RtcpFeedback {
+    /// The format the feedback is supported for, `None` for all formats (`*`)
+    pub format: Option<u8>,
+
+    /// Feedback type, e.g. `nack` or `ccm`
+    pub kind: BytesStr,
+
+    /// Optional parameter of the feedback type, e.g. `pli` in `nack pli`
+    pub param: Option<BytesStr>,
+}
+
+impl RtcpFeedback {
+    pub fn parse<'i>(src: &Bytes, i: &'i str) -> IResult<&'i str, Self> {
+        context(
+            "parsing rtcp-fb",
+            map(
+                tuple((
+                    // format or wildcard
+                    alt((
+                        map(tag("*"), |_| None),
+                        map(map_res(digit1, FromStr::from_str), Some),
+                    )),
+                    // feedback type
+                    preceded(
+                        take_while1(char::is_whitespace),
+                        take_while1(|c: char| !c.is_whitespace()),
+                    ),
+                    // optional parameter
+                    opt(preceded(
+                        take_while1(char::is_whitespace),
+                        |rem: &'i str| Ok(("", rem)),
+                    )),
+                    take_while(char::is_whitespace),
+                )),
+                |(format, kind, param, _)| RtcpFeedback {
+                    format,
+                    kind: BytesStr::from_parse(src, kind),
+                    param: param
+                        .map(str::trim)
+                        .filter(|param| !param.is_empty())
+                        .map(|param| BytesStr::from_parse(src, param)),
+                },
+            ),
+        )(i)
+    }
+
+    /// Returns if the feedback applies to the given format
+    pub fn applies_to(&self, format: u8) -> bool {
+        self.format.is_none_or(|f| f == format)
+    }
+}
+
+impl fmt::Display for RtcpFeedback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.format {
+            Some(format) => write!(f, "{format} {}", self.kind)?,
+            None => write!(f, "* {}", self.kind)?,
+        }
+
+        if let Some(param) = &self.param {
+            write!(f, " {param}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rtcp_fb() {
+        let input = BytesStr::from_static("96 nack pli");
+
+        let (rem, rtcp_fb) = RtcpFeedback::parse(input.as_ref(), &input).unwrap();
+
+        assert!(rem.is_empty());
+
+        assert_eq!(rtcp_fb.format, Some(96));
+        assert_eq!(rtcp_fb.kind, "nack");
+        assert_eq!(rtcp_fb.param.unwrap(), "pli");
+    }
+
+    #[test]
+    fn rtcp_fb_wildcard() {
+        let input = BytesStr::from_static("* nack");
+
+        let (rem, rtcp_fb) = RtcpFeedback::parse(input.as_ref(), &input).unwrap();
+
+        assert!(rem.is_empty());
+
+        assert_eq!(rtcp_fb.format, None);
+        assert_eq!(rtcp_fb.kind, "nack");
+        assert!(rtcp_fb.param.is_none());
+        assert!(rtcp_fb.applies_to(111));
+    }
+
+    #[test]
+    fn rtcp_fb_print() {
+        let rtcp_fb = RtcpFeedback {
+            format: Some(96),
+            kind: "ccm".into(),
+            param: Some("fir".into()),
+        };
+
+        assert_eq!(rtcp_fb.to_string(), "96 ccm fir");
+    }
+}
@@ -66,6 +66,12 @@ pub enum TransportProtocol {
     /// DTLS-SRTP with [RFC5124](https://www.rfc-editor.org/rfc/rfc5124.html)
     UdpTlsRtpSavpf,
 
+    /// RTP over TCP [RFC4571](https://www.rfc-editor.org/rfc/rfc4571.html)
+    TcpRtpAvp,
+
+    /// RTP over TCP with [RFC4585](https://www.rfc-editor.org/rfc/rfc4585.html)
+    TcpRtpAvpf,
+
     /// Other unknown
     Other(BytesStr),
 }
@@ -74,6 +80,8 @@ impl TransportProtocol {
     pub fn parse(src: &Bytes) -> impl Fn(&str) -> IResult<&str, Self> + '_ {
         move |i| {
             alt((
+                map(tag("TCP/RTP/AVPF"), |_| TransportProtocol::TcpRtpAvpf),
+                map(tag("TCP/RTP/AVP"), |_| TransportProtocol::TcpRtpAvp),
                 map(tag("UDP/TLS/RTP/SAVPF"), |_| {
                     TransportProtocol::UdpTlsRtpSavpf
                 }),
@@ -103,6 +111,8 @@ impl fmt::Display for TransportProtocol {
             TransportProtocol::RtpSavpf => f.write_str("RTP/SAVPF"),
             TransportProtocol::UdpTlsRtpSavp => f.write_str("UDP/TLS/RTP/SAVP"),
             TransportProtocol::UdpTlsRtpSavpf => f.write_str("UDP/TLS/RTP/SAVPF"),
+            TransportProtocol::TcpRtpAvp => f.write_str("TCP/RTP/AVP"),
+            TransportProtocol::TcpRtpAvpf => f.write_str("TCP/RTP/AVPF"),
             TransportProtocol::Other(str) => f.write_str(str),
         }
     }
@@ -183,4 +193,18 @@ mod test {
 
         assert!(rem.is_empty());
     }
+
+    #[test]
+    fn media_tcp() {
+        let input = BytesStr::from_static("audio 9 TCP/RTP/AVP 0 8");
+
+        let (rem, media) = Media::parse(input.as_ref(), &input).unwrap();
+
+        assert_eq!(media.port, 9);
+        assert_eq!(media.proto, TransportProtocol::TcpRtpAvp);
+        assert_eq!(media.proto.to_string(), "TCP/RTP/AVP");
+        assert_eq!(media.fmts, [0, 8]);
+
+        assert!(rem.is_empty());
+    }
 }
@@ -2,8 +2,8 @@ use crate::connection::Connection;
 use crate::media::Media;
 use crate::{bandwidth::Bandwidth, Rtcp};
 use crate::{
-    Direction, ExtMap, Fingerprint, Fmtp, IceCandidate, IcePassword, IceUsernameFragment,
-    MediaType, RtpMap, Setup, SrtpCrypto, Ssrc, TransportProtocol, UnknownAttribute,
+    Content, Direction, ExtMap, Fingerprint, Fmtp, IceCandidate, IcePassword, IceUsernameFragment,
+    MediaType, RtcpFeedback, RtpMap, Setup, SrtpCrypto, Ssrc, TransportProtocol, UnknownAttribute,
 };
 use bytesstr::BytesStr;
 use std::fmt::{self, Debug};
@@ -31,15 +31,25 @@ pub struct MediaDescription {
     /// rtcp-mux attribute
     pub rtcp_mux: bool,
 
+    /// rtcp-mux-only attribute ([RFC8858](https://www.rfc-editor.org/rfc/rfc8858.html)), the offerer requires
+    /// RTCP to be multiplexed and has no separate RTCP port
+    pub rtcp_mux_only: bool,
+
     /// Media ID (a=mid)
     pub mid: Option<BytesStr>,
 
+    /// Content of the media (a=content), e.g. to distinguish screen sharing from the camera
+    pub content: Vec<Content>,
+
     /// RTP Payload mappings
     pub rtpmap: Vec<RtpMap>,
 
     /// RTP encoding parameters
     pub fmtp: Vec<Fmtp>,
 
+    /// Supported RTCP feedback messages (a=rtcp-fb)
+    pub rtcp_fb: Vec<RtcpFeedback>,
+
     /// ICE username fragment
     pub ice_ufrag: Option<IceUsernameFragment>,
 
@@ -96,10 +106,24 @@ impl fmt::Display for MediaDescription {
             write!(f, "a=rtcp-mux\r\n")?;
         }
 
+        if self.rtcp_mux_only {
+            write!(f, "a=rtcp-mux-only\r\n")?;
+        }
+
         if let Some(mid) = &self.mid {
             write!(f, "a=mid:{}\r\n", mid)?;
         }
 
+        if let Some((first, rest)) = self.content.split_first() {
+            write!(f, "a=content:{first}")?;
+
+            for content in rest {
+                write!(f, ",{content}")?;
+            }
+
+            write!(f, "\r\n")?;
+        }
+
         for rtpmap in &self.rtpmap {
             write!(f, "a=rtpmap:{}\r\n", rtpmap)?;
         }
@@ -108,6 +132,10 @@ impl fmt::Display for MediaDescription {
             write!(f, "a=fmtp:{}\r\n", fmtp)?;
         }
 
+        for rtcp_fb in &self.rtcp_fb {
+            write!(f, "a=rtcp-fb:{rtcp_fb}\r\n")?;
+        }
+
         if let Some(ufrag) = &self.ice_ufrag {
             write!(f, "a=ice-ufrag:{}\r\n", ufrag.ufrag)?;
         }
@@ -172,9 +200,12 @@ impl MediaDescription {
             direction: Direction::Inactive,
             rtcp: None,
             rtcp_mux: false,
+            rtcp_mux_only: false,
             mid: None,
+            content: vec![],
             rtpmap: vec![],
             fmtp: vec![],
+            rtcp_fb: vec![],
             ice_ufrag: None,
             ice_pwd: None,
             ice_candidates: vec![],
@@ -1,7 +1,7 @@
 use crate::{
-    Bandwidth, Connection, Direction, ExtMap, Fingerprint, Fmtp, Group, IceCandidate, IceOptions,
-    IcePassword, IceUsernameFragment, Media, MediaDescription, Origin, Rtcp, RtpMap,
-    SessionDescription, Setup, SrtpCrypto, Ssrc, Time, UnknownAttribute,
+    Bandwidth, Connection, Content, Direction, ExtMap, Fingerprint, Fmtp, Group, IceCandidate,
+    IceOptions, IcePassword, IceUsernameFragment, Media, MediaDescription, Origin, Rtcp,
+    RtcpFeedback, RtpMap, SessionDescription, Setup, SrtpCrypto, Ssrc, Time, UnknownAttribute,
 };
 use bytesstr::BytesStr;
 use internal::verbose_error_to_owned;
@@ -102,9 +102,12 @@ impl Parser {
                     direction: self.direction,
                     rtcp: None,
                     rtcp_mux: false,
+                    rtcp_mux_only: false,
                     mid: None,
+                    content: vec![],
                     rtpmap: vec![],
                     fmtp: vec![],
+                    rtcp_fb: vec![],
                     ice_ufrag: None,
                     ice_pwd: None,
                     ice_candidates: vec![],
@@ -167,6 +170,11 @@ impl Parser {
 
                 // TODO error here ?
             }
+            "content" => {
+                if let Some(media_description) = self.media_descriptions.last_mut() {
+                    media_description.content = Content::parse_list(src.as_ref(), value);
+                }
+            }
             "rtpmap" => {
                 let (_, rtpmap) = RtpMap::parse(src.as_ref(), value).finish()?;
 
@@ -185,6 +193,13 @@ impl Parser {
 
                 // TODO error here ?
             }
+            "rtcp-fb" => {
+                let (_, rtcp_fb) = RtcpFeedback::parse(src.as_ref(), value).finish()?;
+
+                if let Some(media_description) = self.media_descriptions.last_mut() {
+                    media_description.rtcp_fb.push(rtcp_fb);
+                }
+            }
             "ice-lite" => {
                 self.ice_lite = true;
             }
@@ -312,6 +327,11 @@ impl Parser {
                     media_description.rtcp_mux = true;
                 }
             }
+            "rtcp-mux-only" => {
+                if let Some(media_description) = self.media_descriptions.last_mut() {
+                    media_description.rtcp_mux_only = true;
+                }
+            }
             "end-of-candidates" => {
                 if let Some(media_description) = self.media_descriptions.last_mut() {
                     media_description.ice_end_of_candidates = true;
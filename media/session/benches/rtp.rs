@@ -0,0 +1,325 @@
+//! Per packet cost of sending and receiving RTP through a [`SdpSession`]
+//!
+//! Two sessions are connected in memory, without any sockets. `send_rtp` measures [`SdpSession::send_rtp`] until
+//! the [`Event::SendData`] is popped, `receive_rtp` measures [`SdpSession::receive`] until the [`Event::ReceiveRTP`]
+//! is popped. `allocations` prints the number of allocator calls per sent and received packet. Results are tracked
+//! in `benches/README.md`.
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use ezk_session::{
+    Codec, Codecs, Direction, Event, LocalMediaId, MediaId, MediaType, Options, SdpSession,
+    TransportChange, TransportId, TransportType,
+};
+use ice::{Component, ReceivedPkt};
+use rtp::{RtpExtensions, RtpPacket, RtpTimestamp, SequenceNumber, Ssrc};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Counts allocations and reallocations, deallocations are free to the hot path
+struct CountingAllocator;
+
+static ALLOCATOR_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATOR_CALLS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATOR_CALLS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Average number of allocator calls of `f` over many packets
+fn allocator_calls(mut f: impl FnMut(u16)) -> f64 {
+    const PACKETS: u16 = 1000;
+
+    let before = ALLOCATOR_CALLS.load(Ordering::Relaxed);
+
+    for sequence_number in 0..PACKETS {
+        f(sequence_number);
+    }
+
+    (ALLOCATOR_CALLS.load(Ordering::Relaxed) - before) as f64 / f64::from(PACKETS)
+}
+
+struct Peer {
+    session: SdpSession,
+    ip: IpAddr,
+    local_media_id: LocalMediaId,
+    next_port: u16,
+    /// Local port of every transport
+    ports: Vec<(TransportId, u16)>,
+    media_id: Option<MediaId>,
+}
+
+impl Peer {
+    fn new(ip: Ipv4Addr, transport: TransportType) -> Self {
+        let ip = IpAddr::V4(ip);
+
+        let mut session = SdpSession::new(
+            ip,
+            Options {
+                offer_transport: transport,
+                offer_ice: false,
+                ..Options::default()
+            },
+        );
+
+        let local_media_id = session
+            .add_local_media(
+                Codecs::new(MediaType::Audio).with_codec(Codec::PCMU),
+                1,
+                Direction::SendRecv,
+            )
+            .unwrap();
+
+        Self {
+            session,
+            ip,
+            local_media_id,
+            next_port: 10000,
+            ports: vec![],
+            media_id: None,
+        }
+    }
+
+    fn apply_transport_changes(&mut self) {
+        for change in self.session.transport_changes() {
+            let (transport_id, rtcp) = match change {
+                TransportChange::CreateSocket(transport_id) => (transport_id, false),
+                TransportChange::CreateSocketPair(transport_id) => (transport_id, true),
+                _ => continue,
+            };
+
+            let rtp_port = self.next_port;
+            let rtcp_port = rtcp.then_some(rtp_port + 1);
+            self.next_port += 2;
+
+            self.session
+                .set_transport_ports(transport_id, &[self.ip], rtp_port, rtcp_port);
+            self.ports.push((transport_id, rtp_port));
+        }
+    }
+
+    fn transport_id(&self, port: u16) -> TransportId {
+        self.ports
+            .iter()
+            .find(|(_, p)| *p == port || *p + 1 == port)
+            .map(|(transport_id, _)| *transport_id)
+            .expect("unknown port")
+    }
+
+    fn receive(&mut self, data: Vec<u8>, source: IpAddr, target: SocketAddr) {
+        let transport_id = self.transport_id(target.port());
+
+        self.session.receive(
+            transport_id,
+            ReceivedPkt {
+                data,
+                source: SocketAddr::new(source, 0),
+                destination: target,
+                component: Component::Rtp,
+            },
+        );
+    }
+
+    /// Pop all events, returns the data to send to the peer
+    fn pop_events(&mut self) -> Vec<(Bytes, SocketAddr)> {
+        let mut outgoing = vec![];
+
+        self.session.poll(Instant::now());
+
+        while let Some(event) = self.session.pop_event() {
+            match event {
+                Event::MediaAdded(media_added) => self.media_id = Some(media_added.id),
+                Event::SendData { data, target, .. } => outgoing.push((data, target)),
+                _ => {}
+            }
+        }
+
+        outgoing
+    }
+}
+
+/// Create two sessions which negotiated a single audio stream using the given transport
+fn connected_pair(transport: TransportType) -> (Peer, Peer) {
+    let mut offerer = Peer::new(Ipv4Addr::new(127, 0, 0, 1), transport);
+    let mut answerer = Peer::new(Ipv4Addr::new(127, 0, 0, 2), transport);
+
+    offerer
+        .session
+        .add_media(offerer.local_media_id, Direction::SendRecv);
+    offerer.apply_transport_changes();
+
+    let offer = offerer.session.create_sdp_offer();
+
+    let state = answerer.session.receive_sdp_offer(offer).unwrap();
+    answerer.apply_transport_changes();
+    let answer = answerer.session.create_sdp_answer(state);
+
+    offerer.session.receive_sdp_answer(answer);
+    offerer.apply_transport_changes();
+
+    // Exchange packets until the transports are connected (DTLS handshake)
+    loop {
+        let to_answerer = offerer.pop_events();
+        let to_offerer = answerer.pop_events();
+
+        if to_answerer.is_empty() && to_offerer.is_empty() {
+            break;
+        }
+
+        for (data, target) in to_answerer {
+            answerer.receive(data.into(), offerer.ip, target);
+        }
+
+        for (data, target) in to_offerer {
+            offerer.receive(data.into(), answerer.ip, target);
+        }
+    }
+
+    let answerer_media_id = answerer.media_id.expect("media must be negotiated");
+    answerer
+        .session
+        .set_jitter_buffer_bypass(answerer_media_id, true);
+
+    (offerer, answerer)
+}
+
+fn packet(sequence_number: u16) -> RtpPacket {
+    RtpPacket {
+        pt: 0,
+        marker: false,
+        sequence_number: SequenceNumber(sequence_number),
+        ssrc: Ssrc(0),
+        timestamp: RtpTimestamp(u32::from(sequence_number) * 160),
+        extensions: RtpExtensions::default(),
+        payload: Bytes::from_static(&[0xFF; 160]),
+        padding: None,
+    }
+}
+
+/// Send a packet and return the data of the resulting `SendData` event
+fn send(peer: &mut Peer, sequence_number: u16) -> (Bytes, SocketAddr) {
+    let media_id = peer.media_id.unwrap();
+
+    peer.session.send_rtp(media_id, packet(sequence_number));
+
+    loop {
+        match peer.session.pop_event() {
+            Some(Event::SendData { data, target, .. }) => return (data, target),
+            Some(_) => continue,
+            None => panic!("no SendData event"),
+        }
+    }
+}
+
+const TRANSPORTS: [(&str, TransportType); 3] = [
+    ("rtp", TransportType::Rtp),
+    ("sdes-srtp", TransportType::SdesSrtp),
+    ("dtls-srtp", TransportType::DtlsSrtp),
+];
+
+fn send_rtp(c: &mut Criterion) {
+    let mut group = c.benchmark_group("send_rtp");
+
+    for (name, transport) in TRANSPORTS {
+        let (mut offerer, _answerer) = connected_pair(transport);
+        let mut sequence_number = 0u16;
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                sequence_number = sequence_number.wrapping_add(1);
+                black_box(send(&mut offerer, sequence_number))
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn receive_rtp(c: &mut Criterion) {
+    let mut group = c.benchmark_group("receive_rtp");
+
+    for (name, transport) in TRANSPORTS {
+        let (mut offerer, mut answerer) = connected_pair(transport);
+        let source = offerer.ip;
+        let mut sequence_number = 0u16;
+
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    sequence_number = sequence_number.wrapping_add(1);
+                    let (data, target) = send(&mut offerer, sequence_number);
+                    (Vec::from(data), target)
+                },
+                |(data, target)| {
+                    answerer.receive(data, source, target);
+                    answerer.session.poll(Instant::now());
+
+                    loop {
+                        match answerer.session.pop_event() {
+                            Some(Event::ReceiveRTP { packet, .. }) => return black_box(packet),
+                            Some(_) => continue,
+                            None => panic!("no ReceiveRTP event"),
+                        }
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn allocations(_: &mut Criterion) {
+    for (name, transport) in TRANSPORTS {
+        let (mut offerer, mut answerer) = connected_pair(transport);
+        let source = offerer.ip;
+
+        let send = allocator_calls(|sequence_number| {
+            black_box(send(&mut offerer, sequence_number));
+        });
+
+        // Only count the receiving side, the packets are sent up front
+        let mut packets: Vec<_> = (0..1000)
+            .map(|sequence_number| {
+                let (data, target) = self::send(&mut offerer, 1000 + sequence_number);
+                (Vec::from(data), target)
+            })
+            .collect();
+        packets.reverse();
+
+        let receive = allocator_calls(|_| {
+            let (data, target) = packets.pop().unwrap();
+            answerer.receive(data, source, target);
+
+            while let Some(event) = answerer.session.pop_event() {
+                if let Event::ReceiveRTP { packet, .. } = event {
+                    black_box(packet);
+                    break;
+                }
+            }
+        });
+
+        println!("allocations/{name}: {send:.2} per sent packet, {receive:.2} per received packet");
+    }
+}
+
+criterion_group!(benches, send_rtp, receive_rtp, allocations);
+criterion_main!(benches);
@@ -0,0 +1,354 @@
+use super::socket::{bind_udp, Socket};
+use bytes::Bytes;
+use futures_util::future::join_all;
+use std::{
+    collections::VecDeque,
+    future::poll_fn,
+    io,
+    mem::MaybeUninit,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
+use stun_types::{
+    attributes::{Fingerprint, XorMappedAddress},
+    Class, Message, MessageBuilder, Method, TransactionId,
+};
+use tokio::{io::ReadBuf, time::timeout_at};
+
+/// Number of STUN binding requests sent per socket before giving up
+const BINDING_ATTEMPTS: u32 = 3;
+/// Time to wait for the response to the first binding request, doubled with every retransmission
+const INITIAL_RTO: Duration = Duration::from_millis(500);
+
+/// Pool of UDP sockets bound ahead of time, together with the server reflexive address a STUN server discovered for
+/// each of them
+///
+/// Gathering server reflexive ICE candidates requires a roundtrip to the STUN server, which delays creating the SDP
+/// offer of a call when the server is slow or far away. Sockets prepared using [`prepare`](Self::prepare) (e.g.
+/// while idle, or concurrently to resolving the SIP target of a call) are used by all
+/// [`AsyncSdpSession`](super::AsyncSdpSession)s the cache was set on using
+/// [`set_candidate_cache`](super::AsyncSdpSession::set_candidate_cache). Sessions bind new sockets once the cache
+/// is empty.
+///
+/// NATs drop mappings which haven't been used for a while, cached sockets are discarded once they are older than
+/// the cache's TTL.
+#[derive(Clone)]
+pub struct CandidateCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    ttl: Duration,
+    sockets: VecDeque<CachedSocket>,
+}
+
+pub(crate) struct CachedSocket {
+    pub(crate) socket: Socket,
+    pub(crate) reflexive: ReflexiveAddr,
+    expires_at: Instant,
+}
+
+/// Server reflexive address discovered for a cached socket
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ReflexiveAddr {
+    /// STUN server which discovered the address
+    pub(crate) server: SocketAddr,
+    /// Local address the STUN response was received on
+    pub(crate) base: SocketAddr,
+    pub(crate) addr: SocketAddr,
+}
+
+impl CandidateCache {
+    /// Create an empty cache, discarding sockets after the given time to live
+    ///
+    /// The TTL should be below the time NATs keep unused UDP mappings, which is usually at least 30 seconds.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                ttl,
+                sockets: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Returns the number of sockets in the cache which haven't expired yet
+    pub fn len(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove_expired(Instant::now());
+        inner.sockets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bind `count` sockets to the local IP address and discover their server reflexive addresses using the STUN
+    /// `server`
+    ///
+    /// The IP address must be the one sessions bind their sockets to, the unspecified address (`0.0.0.0` or `::`
+    /// for dual-stack sessions) unless [`set_local_ip`](super::AsyncSdpSession::set_local_ip) is used. Sockets
+    /// the server doesn't respond for are not added to the cache. The server should also be added to the sessions
+    /// using [`add_stun_server`](super::AsyncSdpSession::add_stun_server), otherwise they still send their own
+    /// binding requests.
+    pub async fn prepare(
+        &self,
+        local_ip: IpAddr,
+        server: SocketAddr,
+        count: usize,
+    ) -> io::Result<()> {
+        let sockets = (0..count)
+            .map(|_| bind_udp(SocketAddr::new(local_ip, 0)).map(Socket::new))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let discovered = join_all(sockets.into_iter().map(|socket| discover(socket, server))).await;
+
+        let mut inner = self.inner.lock().unwrap();
+        let expires_at = Instant::now() + inner.ttl;
+
+        for result in discovered {
+            if let Some((socket, reflexive)) = result? {
+                inner.sockets.push_back(CachedSocket {
+                    socket,
+                    reflexive,
+                    expires_at,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Take a socket bound to the given local IP address out of the cache
+    pub(crate) fn take(&self, local_ip: IpAddr) -> Option<CachedSocket> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove_expired(Instant::now());
+
+        let position = inner
+            .sockets
+            .iter()
+            .position(|cached| cached.socket.local_addr().ip() == local_ip)?;
+
+        inner.sockets.remove(position)
+    }
+}
+
+impl Inner {
+    fn remove_expired(&mut self, now: Instant) {
+        self.sockets.retain(|cached| cached.expires_at > now);
+    }
+}
+
+/// Send STUN binding requests to the server until it responds with the socket's server reflexive address
+async fn discover(
+    mut socket: Socket,
+    server: SocketAddr,
+) -> io::Result<Option<(Socket, ReflexiveAddr)>> {
+    let transaction_id = TransactionId::random();
+
+    let mut builder = MessageBuilder::new(Class::Request, Method::Binding, transaction_id);
+    builder.add_attr(Fingerprint);
+    let request = Bytes::from(builder.finish());
+
+    let mut buf = vec![MaybeUninit::uninit(); 1500];
+    let mut rto = INITIAL_RTO;
+
+    for _ in 0..BINDING_ATTEMPTS {
+        socket.enqueue(request.clone(), None, server);
+
+        let retransmit_at = Instant::now() + rto;
+        rto *= 2;
+
+        loop {
+            let mut read_buf = ReadBuf::uninit(&mut buf);
+
+            let received = poll_fn(|cx| {
+                if let Some(e) = socket.send_pending(cx) {
+                    return Poll::Ready(Err(e));
+                }
+
+                socket.poll_recv_from(cx, &mut read_buf)
+            });
+
+            let Ok(result) = timeout_at(retransmit_at.into(), received).await else {
+                break;
+            };
+
+            let (destination, source) = result?;
+
+            if source != server {
+                continue;
+            }
+
+            let Ok(mut response) = Message::parse(read_buf.filled()) else {
+                continue;
+            };
+
+            if response.transaction_id() != transaction_id || response.class() != Class::Success {
+                continue;
+            }
+
+            let Some(Ok(XorMappedAddress(addr))) = response.attribute::<XorMappedAddress>() else {
+                continue;
+            };
+
+            let reflexive = ReflexiveAddr {
+                server,
+                base: destination,
+                addr,
+            };
+
+            return Ok(Some((socket, reflexive)));
+        }
+    }
+
+    log::debug!("STUN server {server} didn't respond, not caching socket");
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsyncSdpSession, Codec, Codecs, Direction, MediaType, Options};
+    use tokio::{net::UdpSocket, time::timeout};
+
+    const REFLEXIVE_IP: [u8; 4] = [203, 0, 113, 7];
+
+    /// Answer all binding requests with a reflexive address using the port of the request, returns the server's
+    /// address and the number of requests received so far
+    async fn stun_server() -> (SocketAddr, Arc<Mutex<usize>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(0));
+
+        tokio::spawn({
+            let requests = requests.clone();
+
+            async move {
+                let mut buf = vec![0; 1500];
+
+                loop {
+                    let (len, source) = socket.recv_from(&mut buf).await.unwrap();
+                    let request = Message::parse(&buf[..len]).unwrap();
+                    *requests.lock().unwrap() += 1;
+
+                    let mut response = MessageBuilder::new(
+                        Class::Success,
+                        Method::Binding,
+                        request.transaction_id(),
+                    );
+                    response.add_attr(XorMappedAddress(SocketAddr::from((
+                        REFLEXIVE_IP,
+                        source.port(),
+                    ))));
+
+                    socket.send_to(&response.finish(), source).await.unwrap();
+                }
+            }
+        });
+
+        (addr, requests)
+    }
+
+    #[tokio::test]
+    async fn prepare() {
+        let (server, requests) = stun_server().await;
+        let local_ip = "127.0.0.1".parse().unwrap();
+
+        let cache = CandidateCache::new(Duration::from_secs(30));
+        cache.prepare(local_ip, server, 2).await.unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(*requests.lock().unwrap(), 2);
+
+        let cached = cache.take(local_ip).unwrap();
+        let port = cached.socket.local_addr().port();
+        assert_eq!(cached.reflexive.server, server);
+        assert_eq!(cached.reflexive.base, SocketAddr::new(local_ip, port));
+        assert_eq!(
+            cached.reflexive.addr,
+            SocketAddr::from((REFLEXIVE_IP, port))
+        );
+
+        // Only sockets bound to the requested address are taken
+        assert!(cache.take("0.0.0.0".parse().unwrap()).is_none());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn expired() {
+        let (server, _) = stun_server().await;
+        let local_ip = "127.0.0.1".parse().unwrap();
+
+        let cache = CandidateCache::new(Duration::ZERO);
+        cache.prepare(local_ip, server, 1).await.unwrap();
+
+        assert!(cache.is_empty());
+        assert!(cache.take(local_ip).is_none());
+    }
+
+    #[tokio::test]
+    async fn unreachable_server() {
+        let unreachable = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server = unreachable.local_addr().unwrap();
+
+        let cache = CandidateCache::new(Duration::from_secs(30));
+        cache
+            .prepare("127.0.0.1".parse().unwrap(), server, 1)
+            .await
+            .unwrap();
+
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn session_uses_cached_sockets() {
+        let (server, requests) = stun_server().await;
+
+        let cache = CandidateCache::new(Duration::from_secs(30));
+        cache
+            .prepare("0.0.0.0".parse().unwrap(), server, 2)
+            .await
+            .unwrap();
+        let prepared_requests = *requests.lock().unwrap();
+
+        let mut session = AsyncSdpSession::new(
+            "127.0.0.1".parse().unwrap(),
+            Options {
+                offer_ice: true,
+                ..Options::lan()
+            },
+        );
+        session.add_stun_server(server);
+        session.set_candidate_cache(cache.clone());
+
+        let audio = session
+            .add_local_media(
+                Codecs::new(MediaType::Audio).with_codec(Codec::PCMU),
+                1,
+                Direction::SendRecv,
+            )
+            .unwrap();
+        session.add_media(audio, Direction::SendRecv);
+
+        let offer = timeout(Duration::from_secs(1), session.create_sdp_offer())
+            .await
+            .expect("gathering must not wait for the STUN server")
+            .unwrap();
+
+        // The server reflexive candidates were known before, the STUN server isn't contacted again
+        assert_eq!(*requests.lock().unwrap(), prepared_requests);
+
+        let srflx: Vec<_> = offer.media_descriptions[0]
+            .ice_candidates
+            .iter()
+            .filter(|candidate| candidate.typ == "srflx")
+            .collect();
+        assert!(!srflx.is_empty());
+        assert!(srflx
+            .iter()
+            .all(|candidate| candidate.address.to_string() == "203.0.113.7"));
+    }
+}
@@ -1,26 +1,47 @@
 use crate::{
     events::{
-        IceConnectionStateChanged, MediaAdded, MediaChanged, TransportChange,
-        TransportConnectionStateChanged,
+        AnsweringMachineDetected, AudioLevelChanged, BandwidthEstimated, BitrateEstimated,
+        CallProgressDetected, CodecChanged, CodecDownshiftRequested, DtmfReceived,
+        FingerprintMismatch, IceConnectionStateChanged, KeyframeRequested, MediaAdded,
+        MediaChanged, NewLocalCandidate, PromptFinished, PromptStarted, QualityAlertChanged,
+        ReferencePictureIndicated, RemoteMediaEnded, SrtpRekeyed, TargetBitrateChanged,
+        ToneDetected, TransportChange, TransportConnectionStateChanged, TransportMigrated,
+        TransportSendFailed, UnexpectedDirectionRtpReceived, UnexpectedPayloadTypeReceived,
     },
-    Codecs, Error, Event, LocalMediaId, MediaId, Options, ReceivedPkt, TransportId,
+    Codec, Codecs, DtmfError, Error, Event, KeyframeRecovery, LocalMediaId, MediaId, MediaInfo,
+    MulticastGroup, Options, ReceiveDirectionEnforcement, ReceivedPkt, RtpTap, TransportId,
+    TransportInfo, TurnCredentials, TurnUri, UnexpectedPayloadTypePolicy,
 };
+use candidate_cache::ReflexiveAddr;
 use ice::{Component, IceGatheringState};
-use rtp::RtpPacket;
-use sdp_types::{Direction, SessionDescription};
-use socket::Socket;
+use rtp::{
+    AnsweringMachineDetector, BitrateAllocation, CongestionController, DownshiftConfig, DtmfSender,
+    JitterBufferConfig, JitterBufferStats, PacketFeedback, ProbeConfig, PromptId, PromptMode,
+    QualityThreshold, RtpPacket, TelephoneEvent,
+};
+use sdp_types::{Direction, IceCandidate, SessionDescription};
+use socket::{bind_udp, Socket};
 use std::{
     collections::{HashMap, VecDeque},
     future::{pending, poll_fn},
     io::{self},
     mem::MaybeUninit,
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     task::Poll,
-    time::Instant,
+    time::{Duration, Instant},
+};
+use tcp::FramedTcpSocket;
+use tokio::{
+    io::ReadBuf,
+    net::{TcpListener, TcpSocket, UdpSocket},
+    select, time,
 };
-use tokio::{io::ReadBuf, net::UdpSocket, select, time::sleep_until};
 
+mod candidate_cache;
 mod socket;
+mod tcp;
+
+pub use candidate_cache::CandidateCache;
 
 /// Session event returned by [`AsyncSdpSession::run`]
 #[derive(Debug)]
@@ -35,19 +56,77 @@ pub enum AsyncEvent {
     IceConnectionState(IceConnectionStateChanged),
     /// See [`TransportConnectionStateChanged`]
     TransportConnectionState(TransportConnectionStateChanged),
+    /// See [`TransportMigrated`]
+    TransportMigrated(TransportMigrated),
+    /// See [`TransportSendFailed`], the socket is rebound automatically if possible
+    TransportSendFailed(TransportSendFailed),
+    /// See [`SrtpRekeyed`]
+    SrtpRekeyed(SrtpRekeyed),
+    /// See [`FingerprintMismatch`]
+    FingerprintMismatch(FingerprintMismatch),
+    /// See [`NewLocalCandidate`]
+    NewLocalCandidate(NewLocalCandidate),
 
     /// Receive RTP on a media
     ReceiveRTP {
         media_id: MediaId,
         packet: RtpPacket,
+        /// The packet's payload type wasn't negotiated for the media, see [`Event::ReceiveRTP`]
+        unexpected_payload_type: bool,
     },
+
+    /// See [`AudioLevelChanged`]
+    AudioLevel(AudioLevelChanged),
+    /// See [`ToneDetected`]
+    ToneDetected(ToneDetected),
+    /// See [`DtmfReceived`]
+    DtmfReceived(DtmfReceived),
+    /// See [`CallProgressDetected`]
+    CallProgress(CallProgressDetected),
+    /// See [`AnsweringMachineDetected`]
+    AnsweringMachine(AnsweringMachineDetected),
+    /// See [`PromptStarted`]
+    PromptStarted(PromptStarted),
+
+    /// See [`PromptFinished`]
+    PromptFinished(PromptFinished),
+    /// See [`BandwidthEstimated`]
+    BandwidthEstimate(BandwidthEstimated),
+    /// See [`BitrateEstimated`]
+    BitrateEstimate(BitrateEstimated),
+    /// See [`TargetBitrateChanged`]
+    TargetBitrate(TargetBitrateChanged),
+    /// See [`CodecDownshiftRequested`]
+    CodecDownshift(CodecDownshiftRequested),
+    /// See [`QualityAlertChanged`]
+    QualityAlert(QualityAlertChanged),
+    /// See [`CodecChanged`]
+    CodecChanged(CodecChanged),
+    /// See [`KeyframeRequested`]
+    KeyframeRequest(KeyframeRequested),
+    /// See [`ReferencePictureIndicated`]
+    ReferencePictureIndication(ReferencePictureIndicated),
+    /// See [`UnexpectedPayloadTypeReceived`]
+    UnexpectedPayloadType(UnexpectedPayloadTypeReceived),
+    /// See [`UnexpectedDirectionRtpReceived`]
+    UnexpectedDirectionRtp(UnexpectedDirectionRtpReceived),
+    /// See [`RemoteMediaEnded`]
+    RemoteMediaEnded(RemoteMediaEnded),
 }
 
 pub struct AsyncSdpSession {
     state: super::SdpSession,
     sockets: HashMap<(TransportId, Component), Socket>,
-    timeout: Option<Instant>,
+    tcp_sockets: HashMap<TransportId, FramedTcpSocket>,
+    /// Measured with tokio's clock, the session's timers are driven by tokio's timers
+    timeout: Option<time::Instant>,
     ips: Vec<IpAddr>,
+    /// Local IP address to bind media sockets to, all interfaces if not set
+    bind_ip: Option<IpAddr>,
+    /// Set by [`pause`](Self::pause), sockets and timers are not polled
+    paused: bool,
+    /// Sockets bound ahead of time, see [`set_candidate_cache`](Self::set_candidate_cache)
+    candidate_cache: Option<CandidateCache>,
 
     buf: Vec<MaybeUninit<u8>>,
 
@@ -59,12 +138,12 @@ impl AsyncSdpSession {
         Self {
             state: super::SdpSession::new(address, options),
             sockets: HashMap::new(),
-            timeout: Some(Instant::now()), // poll immediately
-            ips: local_ip_address::linux::list_afinet_netifas()
-                .unwrap()
-                .into_iter()
-                .map(|(_, addr)| addr)
-                .collect(),
+            tcp_sockets: HashMap::new(),
+            timeout: Some(time::Instant::now()), // poll immediately
+            ips: local_ips(),
+            bind_ip: None,
+            paused: false,
+            candidate_cache: None,
 
             buf: vec![MaybeUninit::uninit(); 65535],
 
@@ -72,18 +151,227 @@ impl AsyncSdpSession {
         }
     }
 
+    /// Create a session using both IPv4 and IPv6
+    ///
+    /// Media sockets accept both address families and ICE host candidates of both are gathered. Offers use the IPv4
+    /// address as connection address, transports of a peer using IPv6 use the IPv6 address, see
+    /// [`SdpSession::set_dual_stack_address`](crate::SdpSession::set_dual_stack_address).
+    pub fn new_dual_stack(ipv4: Ipv4Addr, ipv6: Ipv6Addr, options: Options) -> Self {
+        let mut this = Self::new(IpAddr::V4(ipv4), options);
+        this.state.set_dual_stack_address(IpAddr::V6(ipv6));
+        this
+    }
+
+    /// Bind all media sockets created after this to the given local IP address, instead of all interfaces
+    ///
+    /// Used on multi-homed hosts (e.g. VPN and LAN) to send a call's media over a specific network interface. Only
+    /// this address is used for ICE host candidates. The address passed to [`new`](Self::new) should usually be
+    /// the same, as it is the address put into the SDP.
+    pub fn set_local_ip(&mut self, ip: IpAddr) {
+        self.bind_ip = Some(ip);
+        self.ips = vec![ip];
+    }
+
+    /// Use sockets bound ahead of time from the given cache for new transports, skipping the STUN roundtrip to gather
+    /// their server reflexive candidates
+    ///
+    /// See [`CandidateCache`] for details, the cache can be shared by any number of sessions.
+    pub fn set_candidate_cache(&mut self, cache: CandidateCache) {
+        self.candidate_cache = Some(cache);
+    }
+
+    /// Move all media to new sockets on the given local IP address without interrupting it, e.g. after the network
+    /// interface changed
+    ///
+    /// See [`SdpSession::migrate_transports`](crate::SdpSession::migrate_transports). The new sockets are bound to
+    /// the address if [`set_local_ip`](Self::set_local_ip) was used, otherwise the addresses of all interfaces are
+    /// looked up again for ICE host candidates.
+    pub fn migrate_transports(&mut self, address: IpAddr) {
+        if self.bind_ip.is_some() {
+            self.set_local_ip(address);
+        } else {
+            self.ips = local_ips();
+        }
+
+        self.state.migrate_transports(address);
+    }
+
+    /// [`SdpSession::rekey_srtp`](crate::SdpSession::rekey_srtp)
+    pub fn rekey_srtp(&mut self) {
+        self.state.rekey_srtp()
+    }
+
+    /// Suspend all media, e.g. before the system goes to sleep
+    ///
+    /// Timers are stopped and the sockets are no longer read from or written to, packets which haven't been sent
+    /// yet and all RTP sent while paused are dropped. [`run`](Self::run) only returns events which were already
+    /// pending until the session is resumed using [`resume`](Self::resume). Creating SDP offers and answers fails
+    /// with [`Error::Paused`] while ICE candidates are still being gathered, as gathering doesn't progress while
+    /// paused.
+    pub fn pause(&mut self) {
+        self.paused = true;
+
+        for socket in self.sockets.values_mut() {
+            socket.discard_pending();
+        }
+    }
+
+    /// Continue all media after [`pause`](Self::pause), returns if the local address of the media is still
+    /// available
+    ///
+    /// If the address is gone (e.g. the system woke up in another network) the media must be moved to a new
+    /// address using [`migrate_transports`](Self::migrate_transports), which also restarts ICE with new candidates,
+    /// and a new SDP offer must be sent to the peer.
+    ///
+    /// With `ice_restart` the media is moved to new transports on the same address, e.g. because the NAT bindings
+    /// may have expired while suspended. A new SDP offer must be sent to the peer as well.
+    pub fn resume(&mut self, ice_restart: bool) -> bool {
+        self.paused = false;
+
+        // Timers which expired while paused fire once instead of catching up
+        let now = Instant::now();
+        self.state.skip_expired_timers(now);
+        self.timeout = self.state.timeout().map(|d| time::Instant::now() + d);
+
+        let ips = local_ips();
+        let available = ips.contains(&self.bind_ip.unwrap_or(self.state.address));
+
+        if self.bind_ip.is_none() {
+            self.ips = ips;
+        }
+
+        if ice_restart && available {
+            self.migrate_transports(self.state.address);
+        }
+
+        available
+    }
+
+    /// Returns if the session is paused, see [`pause`](Self::pause)
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Address to bind media sockets to, a dual-stack socket is used if the session has an IPv6 address
+    fn bind_addr(&self) -> SocketAddr {
+        let uses_ipv6 = self.state.address.is_ipv6()
+            || self
+                .state
+                .dual_stack_address
+                .is_some_and(|address| address.is_ipv6());
+
+        let unspecified = if uses_ipv6 {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        } else {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        };
+
+        SocketAddr::new(self.bind_ip.unwrap_or(unspecified), 0)
+    }
+
+    /// Addresses to use for ICE host candidates, which the media sockets can receive on
+    fn host_ips(&self) -> Vec<IpAddr> {
+        let bind_addr = self.bind_addr();
+
+        self.ips
+            .iter()
+            .copied()
+            .filter(|ip| bind_addr.is_ipv6() || ip.is_ipv4())
+            .collect()
+    }
+
     /// Add a stun server to use to setup ICE
     pub fn add_stun_server(&mut self, server: SocketAddr) {
         self.state.add_stun_server(server);
     }
 
+    /// Add a TURN server to allocate relayed candidates from for ICE, e.g. `turn:turn.example.org`
+    ///
+    /// The URI's host is resolved using the system's resolver. Only TURN over UDP is supported, `turns` URIs and
+    /// `transport=tcp` are rejected.
+    pub async fn add_turn_server(
+        &mut self,
+        uri: &TurnUri,
+        credentials: TurnCredentials,
+    ) -> io::Result<()> {
+        if !uri.is_supported() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only TURN over UDP is supported",
+            ));
+        }
+
+        let bind_addr = self.bind_addr();
+
+        let server = tokio::net::lookup_host((uri.host.as_str(), uri.port))
+            .await?
+            .find(|server| bind_addr.is_ipv6() || server.is_ipv4())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no usable address for TURN server {}", uri.host),
+                )
+            })?;
+
+        self.state.add_turn_server(server, credentials);
+
+        Ok(())
+    }
+
+    /// [`SdpSession::add_remote_candidate`](crate::SdpSession::add_remote_candidate)
+    pub fn add_remote_candidate(&mut self, transport_id: TransportId, candidate: &IceCandidate) {
+        self.state.add_remote_candidate(transport_id, candidate);
+    }
+
     /// Returns if any media already configured
     pub fn has_media(&self) -> bool {
         self.state.has_media()
     }
 
-    pub fn send_rtp(&mut self, media_id: MediaId, packet: RtpPacket) {
-        self.state.send_rtp(media_id, packet);
+    /// [`SdpSession::medias`](crate::SdpSession::medias)
+    pub fn medias(&self) -> impl Iterator<Item = MediaInfo> + '_ {
+        self.state.medias()
+    }
+
+    /// [`SdpSession::transports`](crate::SdpSession::transports)
+    pub fn transports(&self) -> impl Iterator<Item = TransportInfo> + '_ {
+        self.state.transports()
+    }
+
+    /// [`SdpSession::send_rtp`](crate::SdpSession::send_rtp)
+    pub fn send_rtp(&mut self, media_id: MediaId, packet: RtpPacket) -> bool {
+        self.state.send_rtp(media_id, packet)
+    }
+
+    /// [`SdpSession::send_dtmf`](crate::SdpSession::send_dtmf)
+    pub fn send_dtmf(
+        &mut self,
+        media_id: MediaId,
+        packet: RtpPacket,
+        event: TelephoneEvent,
+    ) -> Result<(), DtmfError> {
+        self.state.send_dtmf(media_id, packet, event)
+    }
+
+    /// [`SdpSession::dtmf_sender`](crate::SdpSession::dtmf_sender)
+    pub fn dtmf_sender(
+        &self,
+        media_id: MediaId,
+        event: u8,
+        duration: Duration,
+    ) -> Result<DtmfSender, DtmfError> {
+        self.state.dtmf_sender(media_id, event, duration)
+    }
+
+    /// [`SdpSession::send_flash_hook`](crate::SdpSession::send_flash_hook)
+    pub fn send_flash_hook(
+        &mut self,
+        media_id: MediaId,
+        packet: RtpPacket,
+        duration: u16,
+        end: bool,
+    ) -> Result<(), DtmfError> {
+        self.state.send_flash_hook(media_id, packet, duration, end)
     }
 
     /// Register codecs for a media type with a limit of how many media session by can be created
@@ -102,6 +390,257 @@ impl AsyncSdpSession {
         self.state.add_media(local_media_id, direction)
     }
 
+    /// Request a new media session for screen sharing, see
+    /// [`SdpSession::add_screenshare_media`](crate::SdpSession::add_screenshare_media)
+    pub fn add_screenshare_media(
+        &mut self,
+        local_media_id: LocalMediaId,
+        direction: Direction,
+    ) -> MediaId {
+        self.state.add_screenshare_media(local_media_id, direction)
+    }
+
+    /// Request a new media session which sends to a multicast group, see
+    /// [`SdpSession::add_multicast_media`](crate::SdpSession::add_multicast_media)
+    pub fn add_multicast_media(
+        &mut self,
+        local_media_id: LocalMediaId,
+        group: MulticastGroup,
+    ) -> MediaId {
+        self.state.add_multicast_media(local_media_id, group)
+    }
+
+    /// Request a new media session which sends RTP over TCP, see
+    /// [`SdpSession::add_tcp_media`](crate::SdpSession::add_tcp_media)
+    pub fn add_tcp_media(&mut self, local_media_id: LocalMediaId, direction: Direction) -> MediaId {
+        self.state.add_tcp_media(local_media_id, direction)
+    }
+
+    /// Mark the media as deleted
+    ///
+    /// The actual deletion will be performed with the next SDP exchange
+    pub fn remove_media(&mut self, media_id: MediaId) {
+        self.state.remove_media(media_id);
+    }
+
+    pub fn update_media(&mut self, media_id: MediaId, new_direction: Direction) {
+        self.state.update_media(media_id, new_direction);
+    }
+
+    /// Deliver received RTP packets of the media immediately in arrival order, skipping the jitter buffer
+    pub fn set_jitter_buffer_bypass(&mut self, media_id: MediaId, bypass: bool) {
+        self.state.set_jitter_buffer_bypass(media_id, bypass);
+    }
+
+    /// [`SdpSession::set_jitter_buffer_config`](crate::SdpSession::set_jitter_buffer_config)
+    pub fn set_jitter_buffer_config(&mut self, media_id: MediaId, config: JitterBufferConfig) {
+        self.state.set_jitter_buffer_config(media_id, config);
+    }
+
+    /// [`SdpSession::jitter_buffer_stats`](crate::SdpSession::jitter_buffer_stats)
+    pub fn jitter_buffer_stats(&self, media_id: MediaId) -> Option<JitterBufferStats> {
+        self.state.jitter_buffer_stats(media_id)
+    }
+
+    /// Emit periodic audio level reports for the media, see
+    /// [`SdpSession::set_audio_level_interval`](crate::SdpSession::set_audio_level_interval)
+    pub fn set_audio_level_interval(&mut self, media_id: MediaId, interval: Option<Duration>) {
+        self.state.set_audio_level_interval(media_id, interval);
+    }
+
+    /// Detect inband DTMF and fax tones in the decoded audio of the media, see
+    /// [`SdpSession::set_tone_detection`](crate::SdpSession::set_tone_detection)
+    pub fn set_tone_detection(&mut self, media_id: MediaId, enabled: bool) {
+        self.state.set_tone_detection(media_id, enabled);
+    }
+
+    /// Detect call progress tones in the decoded audio of the media, see
+    /// [`SdpSession::set_call_progress_analysis`](crate::SdpSession::set_call_progress_analysis)
+    pub fn set_call_progress_analysis(&mut self, media_id: MediaId, enabled: bool) {
+        self.state.set_call_progress_analysis(media_id, enabled);
+    }
+
+    /// [`SdpSession::set_answering_machine_detection`](crate::SdpSession::set_answering_machine_detection)
+    pub fn set_answering_machine_detection(
+        &mut self,
+        media_id: MediaId,
+        detector: Option<Box<dyn AnsweringMachineDetector>>,
+    ) {
+        self.state
+            .set_answering_machine_detection(media_id, detector);
+    }
+
+    /// [`SdpSession::set_rtp_tap`](crate::SdpSession::set_rtp_tap)
+    pub fn set_rtp_tap(&mut self, media_id: MediaId, tap: Option<Box<dyn RtpTap>>) {
+        self.state.set_rtp_tap(media_id, tap);
+    }
+
+    /// [`SdpSession::play_prompt`](crate::SdpSession::play_prompt)
+    pub fn play_prompt(
+        &mut self,
+        media_id: MediaId,
+        samples: Vec<i16>,
+        mode: PromptMode,
+    ) -> Option<PromptId> {
+        self.state.play_prompt(media_id, samples, mode)
+    }
+
+    /// [`SdpSession::stop_prompts`](crate::SdpSession::stop_prompts)
+    pub fn stop_prompts(&mut self, media_id: MediaId) {
+        self.state.stop_prompts(media_id);
+    }
+
+    /// [`SdpSession::process_outgoing_audio_samples`](crate::SdpSession::process_outgoing_audio_samples)
+    pub fn process_outgoing_audio_samples(
+        &mut self,
+        media_id: MediaId,
+        samples: &mut [i16],
+        timestamp: u32,
+    ) {
+        self.state
+            .process_outgoing_audio_samples(media_id, samples, timestamp);
+    }
+
+    /// [`SdpSession::set_bandwidth_probing`](crate::SdpSession::set_bandwidth_probing)
+    pub fn set_bandwidth_probing(&mut self, media_id: MediaId, config: Option<ProbeConfig>) {
+        self.state.set_bandwidth_probing(media_id, config);
+    }
+
+    /// [`SdpSession::set_keyframe_recovery`](crate::SdpSession::set_keyframe_recovery)
+    pub fn set_keyframe_recovery(&mut self, media_id: MediaId, recovery: KeyframeRecovery) {
+        self.state.set_keyframe_recovery(media_id, recovery);
+    }
+
+    /// [`SdpSession::send_reference_picture_indication`](crate::SdpSession::send_reference_picture_indication)
+    pub fn send_reference_picture_indication(&mut self, media_id: MediaId, data: &[u8]) {
+        self.state.send_reference_picture_indication(media_id, data);
+    }
+
+    /// [`SdpSession::set_bitrate_allocation`](crate::SdpSession::set_bitrate_allocation)
+    pub fn set_bitrate_allocation(
+        &mut self,
+        media_id: MediaId,
+        allocation: Option<BitrateAllocation>,
+    ) {
+        self.state.set_bitrate_allocation(media_id, allocation);
+    }
+
+    /// [`SdpSession::set_bandwidth_estimate`](crate::SdpSession::set_bandwidth_estimate)
+    pub fn set_bandwidth_estimate(&mut self, transport_id: TransportId, bitrate: u32) {
+        self.state.set_bandwidth_estimate(transport_id, bitrate);
+    }
+
+    /// [`SdpSession::set_congestion_controller`](crate::SdpSession::set_congestion_controller)
+    pub fn set_congestion_controller(
+        &mut self,
+        transport_id: TransportId,
+        controller: Option<Box<dyn CongestionController>>,
+    ) {
+        self.state
+            .set_congestion_controller(transport_id, controller);
+    }
+
+    /// [`SdpSession::set_codec_downshift`](crate::SdpSession::set_codec_downshift)
+    pub fn set_codec_downshift(
+        &mut self,
+        media_id: MediaId,
+        downshift: Option<(Codec, DownshiftConfig)>,
+    ) {
+        self.state.set_codec_downshift(media_id, downshift);
+    }
+
+    /// [`SdpSession::set_quality_thresholds`](crate::SdpSession::set_quality_thresholds)
+    pub fn set_quality_thresholds(&mut self, media_id: MediaId, thresholds: Vec<QualityThreshold>) {
+        self.state.set_quality_thresholds(media_id, thresholds);
+    }
+
+    /// [`SdpSession::set_unexpected_payload_type_policy`](crate::SdpSession::set_unexpected_payload_type_policy)
+    pub fn set_unexpected_payload_type_policy(
+        &mut self,
+        media_id: MediaId,
+        policy: UnexpectedPayloadTypePolicy,
+    ) {
+        self.state
+            .set_unexpected_payload_type_policy(media_id, policy);
+    }
+
+    /// [`SdpSession::unexpected_payload_type_packets`](crate::SdpSession::unexpected_payload_type_packets)
+    pub fn unexpected_payload_type_packets(&self, media_id: MediaId) -> u64 {
+        self.state.unexpected_payload_type_packets(media_id)
+    }
+
+    /// [`SdpSession::set_receive_direction_enforcement`](crate::SdpSession::set_receive_direction_enforcement)
+    pub fn set_receive_direction_enforcement(
+        &mut self,
+        media_id: MediaId,
+        enforcement: ReceiveDirectionEnforcement,
+    ) {
+        self.state
+            .set_receive_direction_enforcement(media_id, enforcement);
+    }
+
+    /// [`SdpSession::unexpected_direction_packets`](crate::SdpSession::unexpected_direction_packets)
+    pub fn unexpected_direction_packets(&self, media_id: MediaId) -> u64 {
+        self.state.unexpected_direction_packets(media_id)
+    }
+
+    /// [`SdpSession::receive_packet_feedback`](crate::SdpSession::receive_packet_feedback)
+    pub fn receive_packet_feedback(
+        &mut self,
+        transport_id: TransportId,
+        feedback: &[PacketFeedback],
+    ) {
+        self.state.receive_packet_feedback(transport_id, feedback);
+    }
+
+    /// [`SdpSession::end_to_end_latency`](crate::SdpSession::end_to_end_latency)
+    pub fn end_to_end_latency(&self, media_id: MediaId) -> Option<Duration> {
+        self.state.end_to_end_latency(media_id)
+    }
+
+    /// Feed decoded audio samples of the media into its audio level meter and tone detector
+    pub fn process_audio_samples(&mut self, media_id: MediaId, samples: &[i16]) {
+        self.state.process_audio_samples(media_id, samples);
+    }
+
+    /// Start a batch of changes, see [`SdpSession::begin_changes`](crate::SdpSession::begin_changes)
+    pub fn begin_changes(&mut self) {
+        self.state.begin_changes();
+    }
+
+    /// Commit the current batch of changes, see [`SdpSession::commit_changes`](crate::SdpSession::commit_changes)
+    pub fn commit_changes(&mut self) {
+        self.state.commit_changes();
+    }
+
+    /// Discard pending changes, see [`SdpSession::rollback_changes`](crate::SdpSession::rollback_changes)
+    pub async fn rollback_changes(&mut self) -> Result<(), Error> {
+        self.state.rollback_changes();
+
+        self.handle_transport_changes().await?;
+
+        Ok(())
+    }
+
+    /// Override the direction of the media for the next SDP offer only
+    ///
+    /// See [`SdpSession::override_media_direction`](crate::SdpSession::override_media_direction)
+    pub fn override_media_direction(&mut self, media_id: MediaId, direction: Direction) {
+        self.state.override_media_direction(media_id, direction);
+    }
+
+    /// Create all sockets required by pending changes and gather ICE candidates for them
+    ///
+    /// This is implicitly done by [`create_sdp_offer`](Self::create_sdp_offer), but can be started earlier and run
+    /// concurrently to other work required to set up a call (e.g. resolving the SIP target using
+    /// `Endpoint::select_transport`), to reduce the time it takes to create the offer when using slow STUN servers.
+    /// Sockets of a [`CandidateCache`] set using [`set_candidate_cache`](Self::set_candidate_cache) don't require
+    /// contacting the STUN server at all.
+    pub async fn gather_candidates(&mut self) -> Result<(), Error> {
+        self.handle_transport_changes().await?;
+        self.run_until_all_candidates_are_gathered().await
+    }
+
     pub async fn create_sdp_offer(&mut self) -> Result<SessionDescription, crate::Error> {
         self.handle_transport_changes().await?;
         self.run_until_all_candidates_are_gathered().await?;
@@ -128,45 +667,159 @@ impl AsyncSdpSession {
         Ok(())
     }
 
+    /// Take a socket out of the candidate cache, or bind a new one if there is none
+    fn udp_socket(&self) -> io::Result<(Socket, Option<ReflexiveAddr>)> {
+        let bind_addr = self.bind_addr();
+
+        if let Some(cached) = self
+            .candidate_cache
+            .as_ref()
+            .and_then(|cache| cache.take(bind_addr.ip()))
+        {
+            return Ok((cached.socket, Some(cached.reflexive)));
+        }
+
+        Ok((Socket::new(bind_udp(bind_addr)?), None))
+    }
+
+    fn add_reflexive_addr(
+        &mut self,
+        transport_id: TransportId,
+        component: Component,
+        reflexive: Option<ReflexiveAddr>,
+    ) {
+        if let Some(ReflexiveAddr { server, base, addr }) = reflexive {
+            self.state
+                .add_transport_reflexive_addr(transport_id, component, server, base, addr);
+        }
+    }
+
     async fn handle_transport_changes(&mut self) -> io::Result<()> {
         for change in self.state.transport_changes() {
             match change {
                 TransportChange::CreateSocket(transport_id) => {
-                    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+                    let (socket, reflexive) = self.udp_socket()?;
 
                     self.state.set_transport_ports(
                         transport_id,
-                        &self.ips,
-                        socket.local_addr()?.port(),
+                        &self.host_ips(),
+                        socket.local_addr().port(),
                         None,
                     );
+                    self.add_reflexive_addr(transport_id, Component::Rtp, reflexive);
 
-                    self.sockets
-                        .insert((transport_id, Component::Rtp), Socket::new(socket));
+                    self.sockets.insert((transport_id, Component::Rtp), socket);
                 }
                 TransportChange::CreateSocketPair(transport_id) => {
-                    let rtp_socket = UdpSocket::bind("0.0.0.0:0").await?;
-                    let rtcp_socket = UdpSocket::bind("0.0.0.0:0").await?;
+                    let (rtp_socket, rtp_reflexive) = self.udp_socket()?;
+                    let (rtcp_socket, rtcp_reflexive) = self.udp_socket()?;
+
+                    self.state.set_transport_ports(
+                        transport_id,
+                        &self.host_ips(),
+                        rtp_socket.local_addr().port(),
+                        Some(rtcp_socket.local_addr().port()),
+                    );
+                    self.add_reflexive_addr(transport_id, Component::Rtp, rtp_reflexive);
+                    self.add_reflexive_addr(transport_id, Component::Rtcp, rtcp_reflexive);
+
+                    self.sockets
+                        .insert((transport_id, Component::Rtp), rtp_socket);
+                    self.sockets
+                        .insert((transport_id, Component::Rtcp), rtcp_socket);
+                }
+                TransportChange::CreateMulticastSocket(transport_id, group) => {
+                    let socket = match group.address {
+                        IpAddr::V4(_) => UdpSocket::bind("0.0.0.0:0").await?,
+                        IpAddr::V6(_) => UdpSocket::bind("[::]:0").await?,
+                    };
+
+                    if group.address.is_ipv4() {
+                        socket.set_multicast_ttl_v4(u32::from(group.ttl))?;
+                    }
 
                     self.state.set_transport_ports(
                         transport_id,
-                        &self.ips,
-                        rtp_socket.local_addr()?.port(),
-                        Some(rtcp_socket.local_addr()?.port()),
+                        &[],
+                        socket.local_addr()?.port(),
+                        None,
                     );
 
                     self.sockets
-                        .insert((transport_id, Component::Rtp), Socket::new(rtp_socket));
+                        .insert((transport_id, Component::Rtp), Socket::new(socket));
+                }
+                TransportChange::JoinMulticastGroup(transport_id, group, _source) => {
+                    // Source filtering is done by the session
+                    let socket = match group.address {
+                        IpAddr::V4(address) => {
+                            let socket =
+                                UdpSocket::bind((Ipv4Addr::UNSPECIFIED, group.port)).await?;
+                            socket.join_multicast_v4(address, Ipv4Addr::UNSPECIFIED)?;
+                            socket
+                        }
+                        IpAddr::V6(address) => {
+                            let socket =
+                                UdpSocket::bind((Ipv6Addr::UNSPECIFIED, group.port)).await?;
+                            socket.join_multicast_v6(&address, 0)?;
+                            socket
+                        }
+                    };
+
+                    self.state
+                        .set_transport_ports(transport_id, &[], group.port, None);
+
                     self.sockets
-                        .insert((transport_id, Component::Rtcp), Socket::new(rtcp_socket));
+                        .insert((transport_id, Component::Rtp), Socket::new(socket));
+                }
+                TransportChange::CreateTcpListener(transport_id) => {
+                    let listener = TcpListener::bind(self.bind_addr()).await?;
+
+                    self.state.set_transport_ports(
+                        transport_id,
+                        &[],
+                        listener.local_addr()?.port(),
+                        None,
+                    );
+
+                    self.tcp_sockets
+                        .insert(transport_id, FramedTcpSocket::listen(listener));
+                }
+                TransportChange::ConnectTcp(transport_id, remote) => {
+                    let socket = match remote {
+                        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+                        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+                    };
+
+                    if let Some(ip) = self.bind_ip {
+                        socket.bind(SocketAddr::new(ip, 0))?;
+                    }
+
+                    let stream = socket.connect(remote).await?;
+
+                    self.state.set_transport_ports(
+                        transport_id,
+                        &[],
+                        stream.local_addr()?.port(),
+                        None,
+                    );
+
+                    self.tcp_sockets
+                        .insert(transport_id, FramedTcpSocket::connected(stream)?);
                 }
                 TransportChange::Remove(transport_id) => {
                     self.sockets.remove(&(transport_id, Component::Rtp));
                     self.sockets.remove(&(transport_id, Component::Rtcp));
+                    self.tcp_sockets.remove(&transport_id);
                 }
                 TransportChange::RemoveRtcpSocket(transport_id) => {
                     self.sockets.remove(&(transport_id, Component::Rtcp));
                 }
+                TransportChange::RebindSocket(transport_id, component) => {
+                    if let Some(socket) = self.sockets.remove(&(transport_id, component)) {
+                        self.sockets
+                            .insert((transport_id, component), socket.rebind().await?);
+                    }
+                }
             }
         }
 
@@ -188,6 +841,19 @@ impl AsyncSdpSession {
                 Event::TransportConnectionState(event) => self
                     .events
                     .push_back(AsyncEvent::TransportConnectionState(event)),
+                Event::TransportMigrated(event) => {
+                    self.events.push_back(AsyncEvent::TransportMigrated(event))
+                }
+                Event::TransportSendFailed(event) => self
+                    .events
+                    .push_back(AsyncEvent::TransportSendFailed(event)),
+                Event::SrtpRekeyed(event) => self.events.push_back(AsyncEvent::SrtpRekeyed(event)),
+                Event::FingerprintMismatch(event) => self
+                    .events
+                    .push_back(AsyncEvent::FingerprintMismatch(event)),
+                Event::NewLocalCandidate(event) => {
+                    self.events.push_back(AsyncEvent::NewLocalCandidate(event))
+                }
                 Event::SendData {
                     transport_id,
                     component,
@@ -195,15 +861,79 @@ impl AsyncSdpSession {
                     source,
                     target,
                 } => {
+                    if self.paused {
+                        continue;
+                    }
+
                     if let Some(socket) = self.sockets.get_mut(&(transport_id, component)) {
                         socket.enqueue(data, source, target);
+                    } else if let Some(socket) = self.tcp_sockets.get_mut(&transport_id) {
+                        socket.enqueue(&data);
                     } else {
                         log::error!("SdpSession tried to send packet using a non existent socket");
                     }
                 }
-                Event::ReceiveRTP { media_id, packet } => self
+                Event::ReceiveRTP {
+                    media_id,
+                    packet,
+                    unexpected_payload_type,
+                } => self.events.push_back(AsyncEvent::ReceiveRTP {
+                    media_id,
+                    packet,
+                    unexpected_payload_type,
+                }),
+                Event::AudioLevel(event) => self.events.push_back(AsyncEvent::AudioLevel(event)),
+                Event::ToneDetected(event) => {
+                    self.events.push_back(AsyncEvent::ToneDetected(event))
+                }
+                Event::DtmfReceived(event) => {
+                    self.events.push_back(AsyncEvent::DtmfReceived(event))
+                }
+                Event::CallProgress(event) => {
+                    self.events.push_back(AsyncEvent::CallProgress(event))
+                }
+                Event::AnsweringMachine(event) => {
+                    self.events.push_back(AsyncEvent::AnsweringMachine(event))
+                }
+                Event::PromptStarted(event) => {
+                    self.events.push_back(AsyncEvent::PromptStarted(event))
+                }
+                Event::PromptFinished(event) => {
+                    self.events.push_back(AsyncEvent::PromptFinished(event))
+                }
+                Event::BandwidthEstimate(event) => {
+                    self.events.push_back(AsyncEvent::BandwidthEstimate(event))
+                }
+                Event::BitrateEstimate(event) => {
+                    self.events.push_back(AsyncEvent::BitrateEstimate(event))
+                }
+                Event::TargetBitrate(event) => {
+                    self.events.push_back(AsyncEvent::TargetBitrate(event))
+                }
+                Event::CodecDownshift(event) => {
+                    self.events.push_back(AsyncEvent::CodecDownshift(event))
+                }
+                Event::QualityAlert(event) => {
+                    self.events.push_back(AsyncEvent::QualityAlert(event))
+                }
+                Event::CodecChanged(event) => {
+                    self.events.push_back(AsyncEvent::CodecChanged(event))
+                }
+                Event::KeyframeRequest(event) => {
+                    self.events.push_back(AsyncEvent::KeyframeRequest(event))
+                }
+                Event::ReferencePictureIndication(event) => self
+                    .events
+                    .push_back(AsyncEvent::ReferencePictureIndication(event)),
+                Event::UnexpectedPayloadType(event) => self
+                    .events
+                    .push_back(AsyncEvent::UnexpectedPayloadType(event)),
+                Event::UnexpectedDirectionRtp(event) => self
                     .events
-                    .push_back(AsyncEvent::ReceiveRTP { media_id, packet }),
+                    .push_back(AsyncEvent::UnexpectedDirectionRtp(event)),
+                Event::RemoteMediaEnded(event) => {
+                    self.events.push_back(AsyncEvent::RemoteMediaEnded(event))
+                }
             }
         }
 
@@ -211,10 +941,19 @@ impl AsyncSdpSession {
     }
 
     async fn run_until_all_candidates_are_gathered(&mut self) -> Result<(), crate::Error> {
+        // Candidates are signaled as they are gathered when using trickle ICE
+        if self.state.options.trickle_ice {
+            return Ok(());
+        }
+
         while !matches!(
             self.state.ice_gathering_state(),
             None | Some(IceGatheringState::Complete)
         ) {
+            if self.paused {
+                return Err(Error::Paused);
+            }
+
             self.step().await?;
             self.handle_events()?;
         }
@@ -230,15 +969,28 @@ impl AsyncSdpSession {
 
             self.step().await?;
             self.handle_events().unwrap();
+
+            // Completed transport migrations remove the old transport
+            self.handle_transport_changes().await?;
         }
     }
 
     async fn step(&mut self) -> Result<(), Error> {
+        if self.paused {
+            return pending().await;
+        }
+
         let mut buf = ReadBuf::uninit(&mut self.buf);
 
         select! {
-            (socket_id, result) = poll_sockets(&mut self.sockets, &mut buf) => {
-                let (dst, source) = result?;
+            (socket_id, result) = poll_sockets(&mut self.sockets, &mut self.tcp_sockets, &mut buf) => {
+                let (dst, source) = match result {
+                    SocketEvent::Received(result) => result?,
+                    SocketEvent::SendFailed(e) => {
+                        self.state.report_send_failure(socket_id.0, socket_id.1, e);
+                        return Ok(());
+                    }
+                };
 
                 let pkt = ReceivedPkt {
                     data: buf.filled().to_vec(),
@@ -248,7 +1000,7 @@ impl AsyncSdpSession {
                 };
 
                 self.state.receive(socket_id.0, pkt);
-                self.timeout = self.state.timeout().map(|d| Instant::now() + d);
+                self.timeout = self.state.timeout().map(|d| time::Instant::now() + d);
 
                 buf.set_filled(0);
 
@@ -256,33 +1008,60 @@ impl AsyncSdpSession {
             }
             _ = timeout(self.timeout) => {
                 self.state.poll(Instant::now());
-                self.timeout = self.state.timeout().map(|d| Instant::now() + d);
+                self.timeout = self.state.timeout().map(|d| time::Instant::now() + d);
                 Ok(())
             }
         }
     }
 }
 
-async fn timeout(instant: Option<Instant>) {
+fn local_ips() -> Vec<IpAddr> {
+    local_ip_address::linux::list_afinet_netifas()
+        .unwrap()
+        .into_iter()
+        .map(|(_, addr)| addr)
+        .collect()
+}
+
+async fn timeout(instant: Option<time::Instant>) {
     match instant {
-        Some(instant) => sleep_until(instant.into()).await,
+        Some(instant) => time::sleep_until(instant).await,
         None => pending().await,
     }
 }
 
+enum SocketEvent {
+    /// Received a packet, contains the local and remote address
+    Received(Result<(SocketAddr, SocketAddr), io::Error>),
+    /// Sending on the socket failed persistently
+    SendFailed(io::Error),
+}
+
 async fn poll_sockets(
     sockets: &mut HashMap<(TransportId, Component), Socket>,
+    tcp_sockets: &mut HashMap<TransportId, FramedTcpSocket>,
     buf: &mut ReadBuf<'_>,
-) -> (
-    (TransportId, Component),
-    Result<(SocketAddr, SocketAddr), io::Error>,
-) {
+) -> ((TransportId, Component), SocketEvent) {
     poll_fn(|cx| {
         for (socket_id, socket) in sockets.iter_mut() {
+            if let Some(e) = socket.send_pending(cx) {
+                return Poll::Ready((*socket_id, SocketEvent::SendFailed(e)));
+            }
+
+            if let Poll::Ready(result) = socket.poll_recv_from(cx, buf) {
+                return Poll::Ready((*socket_id, SocketEvent::Received(result)));
+            }
+        }
+
+        // RTCP is always multiplexed on TCP connections
+        for (transport_id, socket) in tcp_sockets.iter_mut() {
             socket.send_pending(cx);
 
             if let Poll::Ready(result) = socket.poll_recv_from(cx, buf) {
-                return Poll::Ready((*socket_id, result));
+                return Poll::Ready((
+                    (*transport_id, Component::Rtp),
+                    SocketEvent::Received(result),
+                ));
             }
         }
 
@@ -290,3 +1069,94 @@ async fn poll_sockets(
     })
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdp_types::MediaType;
+    use tokio::time::timeout;
+
+    fn session(options: Options) -> (AsyncSdpSession, LocalMediaId) {
+        let mut session = AsyncSdpSession::new("127.0.0.1".parse().unwrap(), options);
+        let audio = session
+            .add_local_media(
+                Codecs::new(MediaType::Audio).with_codec(Codec::PCMU),
+                1,
+                Direction::SendRecv,
+            )
+            .unwrap();
+
+        (session, audio)
+    }
+
+    fn offered_port(offer: &SessionDescription) -> u16 {
+        offer.media_descriptions[0].media.port
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn paused_session_is_idle() {
+        let stun_server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let (mut session, audio) = session(Options {
+            offer_ice: true,
+            ..Options::lan()
+        });
+        session.add_stun_server(stun_server.local_addr().unwrap());
+        session.add_media(audio, Direction::SendRecv);
+
+        // Gathering candidates would never complete
+        session.pause();
+        let offer = timeout(Duration::from_secs(60), session.create_sdp_offer()).await;
+        assert!(matches!(offer, Ok(Err(Error::Paused))));
+
+        assert!(timeout(Duration::from_secs(60), session.run())
+            .await
+            .is_err());
+        assert!(stun_server.try_recv_from(&mut [0; 1500]).is_err());
+
+        // Gathering continues after resuming
+        assert!(session.resume(false));
+        assert!(!session.is_paused());
+
+        let mut buf = [0; 1500];
+        select! {
+            result = stun_server.recv_from(&mut buf) => assert!(result.is_ok()),
+            result = async { loop { session.run().await?; } } => {
+                let result: Result<(), Error> = result;
+                panic!("session stopped: {result:?}");
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn paused_session_without_ice_creates_offer() {
+        let (mut session, audio) = session(Options::lan());
+        session.add_media(audio, Direction::SendRecv);
+
+        session.pause();
+        assert!(session.create_sdp_offer().await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resume_with_ice_restart() {
+        let (mut a, audio) = session(Options::lan());
+        let (mut b, _) = session(Options::lan());
+        a.add_media(audio, Direction::SendRecv);
+
+        let offer = a.create_sdp_offer().await.unwrap();
+        let port = offered_port(&offer);
+        let answer = b.receive_sdp_offer(offer).await.unwrap();
+        a.receive_sdp_answer(answer).await.unwrap();
+
+        // Resuming keeps the transport
+        a.pause();
+        assert!(a.resume(false));
+        assert_eq!(offered_port(&a.create_sdp_offer().await.unwrap()), port);
+
+        // Restarting moves the media to a new transport, which is offered
+        a.pause();
+        assert!(a.resume(true));
+        assert_eq!(a.transports().count(), 2);
+        assert_ne!(offered_port(&a.create_sdp_offer().await.unwrap()), port);
+    }
+}
@@ -1,9 +1,11 @@
+use bytes::Bytes;
 use futures_util::ready;
 use quinn_udp::{RecvMeta, Transmit, UdpSockRef, UdpSocketState};
+use socket2::{Domain, Protocol, Type};
 use std::{
     collections::VecDeque,
     io::{self, IoSliceMut},
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
     task::{Context, Poll},
 };
 use tokio::{
@@ -11,11 +13,16 @@ use tokio::{
     net::UdpSocket,
 };
 
+/// Number of packets in a row which must fail to send before the failure is considered persistent
+const PERSISTENT_SEND_FAILURES: u32 = 50;
+
 pub(crate) struct Socket {
     state: UdpSocketState,
     socket: UdpSocket,
     local_addr: SocketAddr,
-    to_send: VecDeque<(Vec<u8>, Option<IpAddr>, SocketAddr)>,
+    to_send: VecDeque<(Bytes, Option<IpAddr>, SocketAddr)>,
+    /// Number of packets which failed to send since the last successful send
+    send_failures: u32,
 }
 
 impl Socket {
@@ -26,10 +33,33 @@ impl Socket {
             socket,
             local_addr,
             to_send: VecDeque::new(),
+            send_failures: 0,
         }
     }
 
-    pub(crate) fn enqueue(&mut self, data: Vec<u8>, source: Option<IpAddr>, target: SocketAddr) {
+    pub(crate) fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Replace the underlying socket with a new one bound to the same local address, keeping unsent packets
+    pub(crate) async fn rebind(self) -> io::Result<Self> {
+        let Self {
+            socket,
+            local_addr,
+            to_send,
+            ..
+        } = self;
+
+        // Close the old socket first, so its address can be bound again
+        drop(socket);
+
+        let mut socket = Self::new(bind_udp(local_addr)?);
+        socket.to_send = to_send;
+
+        Ok(socket)
+    }
+
+    pub(crate) fn enqueue(&mut self, data: Bytes, source: Option<IpAddr>, target: SocketAddr) {
         self.to_send.push_back((data, source, target));
 
         if self.to_send.len() > 100 {
@@ -39,22 +69,30 @@ impl Socket {
         }
     }
 
-    pub(crate) fn send_pending(&mut self, cx: &mut Context<'_>) {
+    /// Drop all packets which haven't been sent yet
+    pub(crate) fn discard_pending(&mut self) {
+        self.to_send.clear();
+    }
+
+    /// Send queued packets until the socket would block
+    ///
+    /// Packets which fail to send are dropped, returns the error once sending failed persistently.
+    pub(crate) fn send_pending(&mut self, cx: &mut Context<'_>) -> Option<io::Error> {
         'outer: while let Some((data, source, target)) = self.to_send.front() {
             // Loop makes sure that the waker is registered with the runtime,
             // if poll_send_ready returns Ready but send returns WouldBlock
             loop {
                 if self.socket.poll_send_ready(cx).is_pending() {
-                    return;
+                    return None;
                 }
 
                 let result = self.socket.try_io(Interest::WRITABLE, || {
                     let udp_ref = UdpSockRef::from(&self.socket);
 
-                    self.state.send(
+                    self.state.try_send(
                         udp_ref,
                         &Transmit {
-                            destination: *target,
+                            destination: to_socket_family(self.local_addr, *target),
                             ecn: None,
                             contents: data,
                             segment_size: None,
@@ -63,13 +101,30 @@ impl Socket {
                     )
                 });
 
-                // Only return WouldBlock
-                if result.is_ok() {
-                    self.to_send.pop_front();
-                    continue 'outer;
+                match result {
+                    Ok(()) => {
+                        self.send_failures = 0;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => {
+                        log::debug!("Failed to send packet to {target}, {e}");
+
+                        self.send_failures += 1;
+
+                        if self.send_failures == PERSISTENT_SEND_FAILURES {
+                            self.to_send.pop_front();
+                            self.send_failures = 0;
+                            return Some(e);
+                        }
+                    }
                 }
+
+                self.to_send.pop_front();
+                continue 'outer;
             }
         }
+
+        None
     }
 
     pub(crate) fn poll_recv_from(
@@ -91,12 +146,13 @@ impl Socket {
 
                 buf.set_filled(meta[0].len);
 
+                // Dual-stack sockets receive IPv4 packets from IPv4-mapped IPv6 addresses
                 Ok((
                     meta[0]
                         .dst_ip
-                        .map(|ip| SocketAddr::new(ip, self.local_addr.port()))
+                        .map(|ip| SocketAddr::new(ip.to_canonical(), self.local_addr.port()))
                         .unwrap_or(self.local_addr),
-                    meta[0].addr,
+                    SocketAddr::new(meta[0].addr.ip().to_canonical(), meta[0].addr.port()),
                 ))
             });
 
@@ -106,3 +162,29 @@ impl Socket {
         }
     }
 }
+
+/// Bind a UDP socket to the given address
+///
+/// Sockets bound to the unspecified IPv6 address are dual-stack, accepting both IPv4 & IPv6 traffic.
+pub(crate) fn bind_udp(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = socket2::Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+
+    if addr.ip() == Ipv6Addr::UNSPECIFIED {
+        socket.set_only_v6(false)?;
+    }
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+/// IPv4 targets must be addressed using their IPv4-mapped IPv6 address on dual-stack sockets
+fn to_socket_family(local_addr: SocketAddr, target: SocketAddr) -> SocketAddr {
+    match target.ip() {
+        IpAddr::V4(ip) if local_addr.is_ipv6() => {
+            SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), target.port())
+        }
+        _ => target,
+    }
+}
@@ -0,0 +1,160 @@
+use futures_util::ready;
+use std::{
+    io,
+    net::SocketAddr,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::ReadBuf,
+    net::{TcpListener, TcpStream},
+};
+
+/// Maximum number of buffered bytes waiting to be sent
+const MAX_SEND_BUFFER: usize = 256 * 1024;
+
+/// Socket sending and receiving RTP & RTCP over a TCP connection, framing each packet by prefixing it with its
+/// length ([RFC4571](https://www.rfc-editor.org/rfc/rfc4571.html))
+pub(crate) struct FramedTcpSocket {
+    state: State,
+    /// Received data which does not form a complete frame yet
+    recv_buf: Vec<u8>,
+    /// Framed data waiting to be sent
+    send_buf: Vec<u8>,
+}
+
+enum State {
+    /// Waiting for the peer to connect
+    Listening(TcpListener),
+    Connected {
+        stream: TcpStream,
+        local_addr: SocketAddr,
+        peer_addr: SocketAddr,
+    },
+    /// The connection was closed by the peer
+    Closed,
+}
+
+impl FramedTcpSocket {
+    pub(crate) fn listen(listener: TcpListener) -> Self {
+        Self::new(State::Listening(listener))
+    }
+
+    pub(crate) fn connected(stream: TcpStream) -> io::Result<Self> {
+        Ok(Self::new(State::Connected {
+            local_addr: stream.local_addr()?,
+            peer_addr: stream.peer_addr()?,
+            stream,
+        }))
+    }
+
+    fn new(state: State) -> Self {
+        Self {
+            state,
+            recv_buf: Vec::new(),
+            send_buf: Vec::new(),
+        }
+    }
+
+    pub(crate) fn enqueue(&mut self, data: &[u8]) {
+        let Ok(len) = u16::try_from(data.len()) else {
+            log::warn!("packet too large to be framed, dropping it");
+            return;
+        };
+
+        if self.send_buf.len() + data.len() > MAX_SEND_BUFFER {
+            log::warn!("send buffer too large, dropping packet");
+            return;
+        }
+
+        encode_frame(len, data, &mut self.send_buf);
+    }
+
+    pub(crate) fn send_pending(&mut self, cx: &mut Context<'_>) {
+        let State::Connected { stream, .. } = &mut self.state else {
+            return;
+        };
+
+        while !self.send_buf.is_empty() {
+            if stream.poll_write_ready(cx).is_pending() {
+                return;
+            }
+
+            match stream.try_write(&self.send_buf) {
+                Ok(n) => {
+                    self.send_buf.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    log::warn!("failed to send on TCP connection, {e}");
+                    self.send_buf.clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn poll_recv_from(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<(SocketAddr, SocketAddr)>> {
+        loop {
+            match &mut self.state {
+                State::Listening(listener) => {
+                    let (stream, peer_addr) = ready!(listener.poll_accept(cx))?;
+
+                    // Only a single connection is accepted, the listener is closed afterwards
+                    self.state = State::Connected {
+                        local_addr: stream.local_addr()?,
+                        peer_addr,
+                        stream,
+                    };
+                }
+                State::Connected {
+                    stream,
+                    local_addr,
+                    peer_addr,
+                } => {
+                    if let Some(frame) = decode_frame(&mut self.recv_buf) {
+                        buf.put_slice(&frame);
+                        return Poll::Ready(Ok((*local_addr, *peer_addr)));
+                    }
+
+                    ready!(stream.poll_read_ready(cx))?;
+
+                    let mut chunk = [0u8; 4096];
+
+                    match stream.try_read(&mut chunk) {
+                        Ok(0) => {
+                            log::debug!("TCP connection closed by peer");
+                            self.state = State::Closed;
+                        }
+                        Ok(n) => self.recv_buf.extend_from_slice(&chunk[..n]),
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(e) => return Poll::Ready(Err(e)),
+                    }
+                }
+                State::Closed => return Poll::Pending,
+            }
+        }
+    }
+}
+
+fn encode_frame(len: u16, data: &[u8], buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Remove the first complete frame from the buffer and return its content
+fn decode_frame(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let len = usize::from(u16::from_be_bytes([*buf.first()?, *buf.get(1)?]));
+
+    if buf.len() < 2 + len {
+        return None;
+    }
+
+    let frame = buf[2..2 + len].to_vec();
+    buf.drain(..2 + len);
+
+    Some(frame)
+}
@@ -0,0 +1,63 @@
+use crate::transport::{supported_fingerprint_algorithms, SDES_SRTP_SUITES};
+use crate::{Codec, SrtpBackend, SrtpProfile, TransportType};
+use sdp_types::{FingerprintAlgorithm, SrtpSuite};
+
+/// Features supported by this build of the crate, returned by [`capabilities`]
+///
+/// Media is encoded and decoded by the application, so the codecs listed are the ones with builtin SDP
+/// definitions. Other codecs can still be negotiated using [`Codec::new`].
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// Codecs with builtin definitions, e.g. [`Codec::PCMU`]
+    pub codecs: Vec<Codec>,
+    /// Telephone-event (RFC 4733) can be negotiated, see [`Codecs::allow_dtmf`](crate::Codecs::allow_dtmf)
+    pub telephone_event: bool,
+
+    /// Transport types which can be offered and answered
+    pub transport_types: Vec<TransportType>,
+    /// ICE is supported
+    pub ice: bool,
+    /// RTP over TCP (RFC 4571) is supported
+    pub rtp_over_tcp: bool,
+    /// Sending to and receiving from multicast groups is supported
+    pub multicast: bool,
+
+    /// SRTP backends enabled by cargo features
+    pub srtp_backends: Vec<SrtpBackend>,
+    /// SRTP protection profiles which can be negotiated using DTLS-SRTP
+    pub dtls_srtp_profiles: Vec<SrtpProfile>,
+    /// Crypto suites which can be negotiated using SDES-SRTP
+    pub sdes_srtp_suites: Vec<SrtpSuite>,
+    /// Hash algorithms of DTLS certificate fingerprints which can be created and verified
+    pub dtls_fingerprint_algorithms: Vec<FingerprintAlgorithm>,
+}
+
+/// Report the capabilities of this build, e.g. to build codec offers and feature toggles from them
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        codecs: vec![
+            Codec::PCMU,
+            Codec::PCMA,
+            Codec::G722,
+            Codec::OPUS,
+            Codec::H264,
+            Codec::H265,
+            Codec::VP8,
+            Codec::VP9,
+            Codec::AV1,
+        ],
+        telephone_event: true,
+        transport_types: vec![
+            TransportType::Rtp,
+            TransportType::SdesSrtp,
+            TransportType::DtlsSrtp,
+        ],
+        ice: true,
+        rtp_over_tcp: true,
+        multicast: true,
+        srtp_backends: SrtpBackend::available(),
+        dtls_srtp_profiles: SrtpProfile::ALL.to_vec(),
+        sdes_srtp_suites: SDES_SRTP_SUITES.to_vec(),
+        dtls_fingerprint_algorithms: supported_fingerprint_algorithms(),
+    }
+}
@@ -1,3 +1,4 @@
+use rtp::{JitterBufferConfig, TelephoneEvents, FLASH_HOOK};
 use sdp_types::MediaType;
 use std::borrow::Cow;
 
@@ -12,6 +13,24 @@ pub struct NegotiatedCodec {
     pub recv_fmtp: Option<String>,
 }
 
+/// Negotiated RFC 4733 telephone-event payload type, used to send DTMF digits and the flash-hook signal
+#[derive(Debug, Clone)]
+pub struct NegotiatedDtmf {
+    pub send_pt: u8,
+    pub recv_pt: u8,
+    pub clock_rate: u32,
+    /// Events the peer is able to receive, from the fmtp of its telephone-event payload type
+    pub events: TelephoneEvents,
+}
+
+/// Negotiated RFC 4588 retransmission payload type of the codec, used to answer the peer's generic NACKs
+/// (RFC 4585)
+#[derive(Debug, Clone)]
+pub struct NegotiatedRtx {
+    pub send_pt: u8,
+    pub recv_pt: u8,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Codec {
     /// Either set by the codec itself if it's static, or assigned later when added to a session
@@ -30,6 +49,7 @@ impl Codec {
     pub const OPUS: Self = Self::new("OPUS", 48_000).with_channels(2);
 
     pub const H264: Self = Self::new("H264", 90_000);
+    pub const H265: Self = Self::new("H265", 90_000);
     pub const VP8: Self = Self::new("VP8", 90_000);
     pub const VP9: Self = Self::new("VP9", 90_000);
     pub const AV1: Self = Self::new("AV1", 90_000);
@@ -73,13 +93,38 @@ impl Codec {
         self
     }
 
-    pub fn with_fmtp(mut self, fmtp: String) {
+    pub fn with_fmtp(mut self, fmtp: String) -> Self {
         self.fmtp = Some(fmtp);
+        self
+    }
+
+    /// RFC 4733 telephone-event payload type offered alongside codecs with the given clock rate
+    ///
+    /// Announces support for receiving DTMF digits and the flash-hook signal.
+    pub(crate) fn telephone_event(clock_rate: u32) -> Self {
+        Self::new("telephone-event", clock_rate)
+            .with_fmtp(TelephoneEvents::range(0, FLASH_HOOK).to_string())
+    }
+
+    /// RFC 4588 retransmission payload type offered alongside the codec with payload type `apt`
+    pub(crate) fn rtx(clock_rate: u32, apt: u8) -> Self {
+        Self::new("rtx", clock_rate).with_fmtp(format!("apt={apt}"))
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Sample rate of the decoded audio
+    ///
+    /// This is the clock rate, except for G.722 which is sampled at 16kHz but uses an RTP clock rate of 8kHz.
+    pub(crate) fn sample_rate(&self) -> u32 {
+        if self.name.eq_ignore_ascii_case("G722") {
+            16_000
+        } else {
+            self.clock_rate
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -87,6 +132,9 @@ pub struct Codecs {
     pub(crate) media_type: MediaType,
     pub(crate) codecs: Vec<Codec>,
     pub(crate) allow_dtmf: bool,
+    pub(crate) allow_rtx: bool,
+    pub(crate) allow_remb: bool,
+    pub(crate) jitter_buffer: JitterBufferConfig,
 }
 
 impl Codecs {
@@ -95,14 +143,42 @@ impl Codecs {
             media_type,
             codecs: vec![],
             allow_dtmf: false,
+            allow_rtx: false,
+            allow_remb: false,
+            jitter_buffer: JitterBufferConfig::default(),
         }
     }
 
+    /// Offer and accept the telephone-event payload type (RFC 4733) for each clock rate of the audio codecs
     pub fn allow_dtmf(mut self, dtmf: bool) -> Self {
         self.allow_dtmf = dtmf;
         self
     }
 
+    /// Offer and accept a retransmission payload type (RFC 4588) for each video codec, requesting lost packets
+    /// using generic NACKs (RFC 4585)
+    pub fn allow_rtx(mut self, rtx: bool) -> Self {
+        self.allow_rtx = rtx;
+        self
+    }
+
+    /// Offer and accept receiver estimated maximum bitrate feedback (`goog-remb`) for the codecs
+    ///
+    /// The bitrate available for the received media is estimated from the delay variation of the packets and
+    /// reported to the peer. Estimates reported by the peer are emitted as
+    /// [`Event::BitrateEstimate`](crate::Event::BitrateEstimate).
+    pub fn allow_remb(mut self, remb: bool) -> Self {
+        self.allow_remb = remb;
+        self
+    }
+
+    /// Set how long the received packets of media using these codecs are buffered, see
+    /// [`SdpSession::set_jitter_buffer_config`](crate::SdpSession::set_jitter_buffer_config)
+    pub fn with_jitter_buffer(mut self, config: JitterBufferConfig) -> Self {
+        self.jitter_buffer = config;
+        self
+    }
+
     pub fn with_codec(mut self, codec: Codec) -> Self {
         self.add_codec(codec);
         self
@@ -1,8 +1,19 @@
-use crate::{codecs::NegotiatedCodec, LocalMediaId, MediaId, TransportId};
+use crate::{
+    codecs::{NegotiatedCodec, NegotiatedDtmf},
+    LocalMediaId, MediaId, MulticastGroup, SrtpProfile, TransportId,
+};
+use bytes::Bytes;
 use ice::{Component, IceConnectionState, IceGatheringState};
-use rtp::RtpPacket;
-use sdp_types::Direction;
-use std::net::{IpAddr, SocketAddr};
+use rtp::{
+    AnsweringMachineVerdict, AudioLevelReport, CallProgressTone, PromptId, QualityMetric,
+    RtpPacket, Ssrc, Tone,
+};
+use sdp_types::{Content, Direction, IceCandidate};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
 /// New media line was added to the session
 #[derive(Debug)]
@@ -12,6 +23,10 @@ pub struct MediaAdded {
     pub local_media_id: LocalMediaId,
     pub direction: Direction,
     pub codec: NegotiatedCodec,
+    /// Telephone-event payload type, if DTMF is allowed and the peer supports it at the codec's clock rate
+    pub dtmf: Option<NegotiatedDtmf>,
+    /// Content the peer declared for the media, e.g. [`Content::Slides`] for screen sharing
+    pub content: Vec<Content>,
 }
 
 /// Existing media has changed
@@ -20,6 +35,9 @@ pub struct MediaChanged {
     pub id: MediaId,
     pub old_direction: Direction,
     pub new_direction: Direction,
+    /// The peer put the media on hold, either by offering `sendonly`/`inactive` or using the legacy RFC 2543
+    /// convention of a `0.0.0.0` or `::` connection address. Sending RTP is paused while on hold.
+    pub hold: bool,
 }
 
 /// The gathering state of the ICE agent used by the transport changed state
@@ -50,6 +68,245 @@ pub struct TransportConnectionStateChanged {
     pub transport_id: TransportId,
     pub old: TransportConnectionState,
     pub new: TransportConnectionState,
+    /// The SRTP protection profile negotiated using DTLS, set when a DTLS-SRTP transport is connected
+    pub srtp_profile: Option<SrtpProfile>,
+}
+
+/// A media was switched to a new transport after it connected, see
+/// [`SdpSession::migrate_transports`](crate::SdpSession::migrate_transports)
+///
+/// Media is sent using the new transport from now on. The old transport is removed once no media uses it anymore.
+#[derive(Debug)]
+pub struct TransportMigrated {
+    pub media_id: MediaId,
+    pub old_transport_id: TransportId,
+    pub new_transport_id: TransportId,
+}
+
+/// Sending on a socket of a transport failed persistently, reported using
+/// [`SdpSession::report_send_failure`](crate::SdpSession::report_send_failure)
+///
+/// This usually means the route to the peer is gone, e.g. after a VPN was connected or disconnected.
+#[derive(Debug)]
+pub struct TransportSendFailed {
+    pub transport_id: TransportId,
+    pub component: Component,
+    pub error: io::Error,
+    /// Rebinding the socket was requested using [`TransportChange::RebindSocket`]
+    pub rebinding: bool,
+}
+
+/// The peer's DTLS certificate did not match the pinned fingerprints, see
+/// [`Options::dtls_pinned_fingerprints`](crate::Options::dtls_pinned_fingerprints)
+///
+/// The transport fails, which is reported using [`Event::TransportConnectionState`].
+#[derive(Debug)]
+pub struct FingerprintMismatch {
+    pub transport_id: TransportId,
+}
+
+/// The ICE agent of a transport discovered a new local candidate
+///
+/// Used for trickle ICE (see [`Options::trickle_ice`](crate::Options::trickle_ice)), the candidate must be sent to
+/// the peer out-of-band, together with the mid of the media using the transport. This also reports the candidates
+/// which are already part of the SDP.
+#[derive(Debug)]
+pub struct NewLocalCandidate {
+    pub transport_id: TransportId,
+    pub candidate: IceCandidate,
+}
+
+/// The keys of a SDES-SRTP transport were replaced, see [`SdpSession::rekey_srtp`](crate::SdpSession::rekey_srtp)
+#[derive(Debug)]
+pub struct SrtpRekeyed {
+    pub transport_id: TransportId,
+    /// The new keys were requested using [`SdpSession::rekey_srtp`](crate::SdpSession::rekey_srtp), otherwise the
+    /// peer offered a new key
+    pub initiated_locally: bool,
+}
+
+/// Periodic audio level report of a media, enabled using
+/// [`SdpSession::set_audio_level_interval`](crate::SdpSession::set_audio_level_interval)
+#[derive(Debug)]
+pub struct AudioLevelChanged {
+    pub media_id: MediaId,
+    pub report: AudioLevelReport,
+}
+
+/// A tone was detected in the decoded audio of a media, enabled using
+/// [`SdpSession::set_tone_detection`](crate::SdpSession::set_tone_detection)
+#[derive(Debug)]
+pub struct ToneDetected {
+    pub media_id: MediaId,
+    pub tone: Tone,
+}
+
+/// A DTMF digit was received as RFC 4733 telephone-event using the negotiated payload type
+///
+/// Reported once the event has ended. The telephone-event packets of DTMF digits are not delivered as
+/// [`Event::ReceiveRTP`], other events (e.g. flash-hook) are.
+#[derive(Debug)]
+pub struct DtmfReceived {
+    pub media_id: MediaId,
+    /// The digit, `0-9`, `*`, `#` or `A-D`
+    pub digit: char,
+    pub duration: Duration,
+}
+
+/// A call progress tone was detected in the decoded audio of a media, enabled using
+/// [`SdpSession::set_call_progress_analysis`](crate::SdpSession::set_call_progress_analysis)
+#[derive(Debug)]
+pub struct CallProgressDetected {
+    pub media_id: MediaId,
+    pub tone: CallProgressTone,
+}
+
+/// Answering machine detection of a media made a decision, enabled using
+/// [`SdpSession::set_answering_machine_detection`](crate::SdpSession::set_answering_machine_detection)
+#[derive(Debug)]
+pub struct AnsweringMachineDetected {
+    pub media_id: MediaId,
+    pub verdict: AnsweringMachineVerdict,
+}
+
+/// A prompt queued using [`SdpSession::play_prompt`](crate::SdpSession::play_prompt) started playing
+///
+/// Can be used to duck other outgoing audio until the matching [`PromptFinished`].
+#[derive(Debug)]
+pub struct PromptStarted {
+    pub media_id: MediaId,
+    pub prompt_id: PromptId,
+    /// RTP timestamp of the prompt's first sample
+    pub timestamp: u32,
+}
+
+/// A prompt queued using [`SdpSession::play_prompt`](crate::SdpSession::play_prompt) finished playing
+#[derive(Debug)]
+pub struct PromptFinished {
+    pub media_id: MediaId,
+    pub prompt_id: PromptId,
+    /// RTP timestamp right after the prompt's last sample
+    pub timestamp: u32,
+}
+
+/// Startup bandwidth probing of a media finished, enabled using
+/// [`SdpSession::set_bandwidth_probing`](crate::SdpSession::set_bandwidth_probing)
+#[derive(Debug)]
+pub struct BandwidthEstimated {
+    pub media_id: MediaId,
+    /// Estimated available bandwidth in bits per second, `None` if the peer didn't report on the probes
+    pub bitrate: Option<u32>,
+}
+
+/// The peer reported the bitrate it estimates to be available on a transport using REMB feedback, see
+/// [`Codecs::allow_remb`](crate::Codecs::allow_remb)
+///
+/// The estimate is also used as the transport's bandwidth estimate, see
+/// [`SdpSession::set_bandwidth_estimate`](crate::SdpSession::set_bandwidth_estimate).
+#[derive(Debug)]
+pub struct BitrateEstimated {
+    pub transport_id: TransportId,
+    /// Estimated available bitrate in bits per second
+    pub bitrate: u32,
+}
+
+/// The target bitrate of a media's encoder changed, see
+/// [`SdpSession::set_bitrate_allocation`](crate::SdpSession::set_bitrate_allocation)
+#[derive(Debug)]
+pub struct TargetBitrateChanged {
+    pub media_id: MediaId,
+    /// Bitrate in bits per second, 0 if the media's minimum bitrate cannot be satisfied
+    pub bitrate: u32,
+}
+
+/// Sustained loss changed and the media should switch codecs, see
+/// [`SdpSession::set_codec_downshift`](crate::SdpSession::set_codec_downshift)
+///
+/// The codec change is included in the next SDP offer, which must be created and sent to the peer.
+#[derive(Debug)]
+pub struct CodecDownshiftRequested {
+    pub media_id: MediaId,
+    /// `true` when switching to the robust codec, `false` when switching back to the original codec
+    pub downshift: bool,
+}
+
+/// A call quality alert of a media was raised or cleared, see
+/// [`SdpSession::set_quality_thresholds`](crate::SdpSession::set_quality_thresholds)
+#[derive(Debug)]
+pub struct QualityAlertChanged {
+    pub media_id: MediaId,
+    pub metric: QualityMetric,
+    /// `true` if the alert was raised, `false` if it was cleared
+    pub active: bool,
+    /// Value of the metric which caused the change
+    pub value: f32,
+}
+
+/// The negotiated codec of an existing media changed
+#[derive(Debug)]
+pub struct CodecChanged {
+    pub media_id: MediaId,
+    pub codec: NegotiatedCodec,
+    /// Telephone-event payload type matching the clock rate of the new codec, if any
+    pub dtmf: Option<NegotiatedDtmf>,
+}
+
+/// The peer requested a keyframe for a media it receives, using RTCP PLI or FIR
+#[derive(Debug)]
+pub struct KeyframeRequested {
+    pub media_id: MediaId,
+    /// How the media's encoder should recover, see
+    /// [`SdpSession::set_keyframe_recovery`](crate::SdpSession::set_keyframe_recovery)
+    pub recovery: KeyframeRecovery,
+}
+
+/// The peer indicated a correctly decoded reference picture using RTCP RPSI, which can be used as long-term
+/// reference frame to recover from losses
+#[derive(Debug)]
+pub struct ReferencePictureIndicated {
+    pub media_id: MediaId,
+    pub payload_type: u8,
+    /// Codec specific identification of the reference picture
+    pub data: Bytes,
+}
+
+/// Received RTP with a payload type which wasn't negotiated for the media, e.g. because the peer switched codecs
+/// without renegotiating, see
+/// [`SdpSession::set_unexpected_payload_type_policy`](crate::SdpSession::set_unexpected_payload_type_policy)
+///
+/// Emitted once per payload type until the media's codec changes.
+#[derive(Debug)]
+pub struct UnexpectedPayloadTypeReceived {
+    pub media_id: MediaId,
+    pub payload_type: u8,
+    /// Payload type of the media's negotiated codec
+    pub expected_payload_type: u8,
+    /// How packets with the payload type are handled
+    pub policy: UnexpectedPayloadTypePolicy,
+    /// A switch to the codec of the payload type is included in the next SDP offer, which must be created and sent
+    /// to the peer
+    pub renegotiate: bool,
+}
+
+/// Received RTP on a media which was negotiated without receiving (`sendonly` or `inactive` from the local
+/// perspective), only emitted using [`ReceiveDirectionEnforcement::Report`], see
+/// [`SdpSession::set_receive_direction_enforcement`](crate::SdpSession::set_receive_direction_enforcement)
+#[derive(Debug)]
+pub struct UnexpectedDirectionRtpReceived {
+    pub media_id: MediaId,
+    /// Negotiated direction of the media from the local perspective
+    pub direction: Direction,
+    pub packet: RtpPacket,
+}
+
+/// The peer ended a stream of a media by sending a RTCP BYE for its ssrc
+///
+/// Packets of the stream still waiting in the jitter buffer are discarded, decoders of the stream can be
+/// torn down.
+#[derive(Debug)]
+pub struct RemoteMediaEnded {
+    pub media_id: MediaId,
+    pub ssrc: Ssrc,
 }
 
 /// Session event returned by [`SdpSession::pop_event`](crate::SdpSession::pop_event)
@@ -67,12 +324,22 @@ pub enum Event {
     IceConnectionState(IceConnectionStateChanged),
     /// See [`TransportConnectionStateChanged`]
     TransportConnectionState(TransportConnectionStateChanged),
+    /// See [`TransportMigrated`]
+    TransportMigrated(TransportMigrated),
+    /// See [`TransportSendFailed`]
+    TransportSendFailed(TransportSendFailed),
+    /// See [`SrtpRekeyed`]
+    SrtpRekeyed(SrtpRekeyed),
+    /// See [`FingerprintMismatch`]
+    FingerprintMismatch(FingerprintMismatch),
+    /// See [`NewLocalCandidate`]
+    NewLocalCandidate(NewLocalCandidate),
 
     /// Send data
     SendData {
         transport_id: TransportId,
         component: Component,
-        data: Vec<u8>,
+        data: Bytes,
         /// The local IP address to use to send the data
         source: Option<IpAddr>,
         target: SocketAddr,
@@ -82,7 +349,113 @@ pub enum Event {
     ReceiveRTP {
         media_id: MediaId,
         packet: RtpPacket,
+        /// The packet's payload type wasn't negotiated for the media, only delivered using
+        /// [`UnexpectedPayloadTypePolicy::Deliver`]
+        unexpected_payload_type: bool,
     },
+
+    /// See [`AudioLevelChanged`]
+    AudioLevel(AudioLevelChanged),
+
+    /// See [`ToneDetected`]
+    ToneDetected(ToneDetected),
+
+    /// See [`DtmfReceived`]
+    DtmfReceived(DtmfReceived),
+
+    /// See [`CallProgressDetected`]
+    CallProgress(CallProgressDetected),
+
+    /// See [`AnsweringMachineDetected`]
+    AnsweringMachine(AnsweringMachineDetected),
+
+    /// See [`PromptStarted`]
+    PromptStarted(PromptStarted),
+
+    /// See [`PromptFinished`]
+    PromptFinished(PromptFinished),
+
+    /// See [`BandwidthEstimated`]
+    BandwidthEstimate(BandwidthEstimated),
+
+    /// See [`BitrateEstimated`]
+    BitrateEstimate(BitrateEstimated),
+
+    /// See [`TargetBitrateChanged`]
+    TargetBitrate(TargetBitrateChanged),
+
+    /// See [`CodecDownshiftRequested`]
+    CodecDownshift(CodecDownshiftRequested),
+
+    /// See [`QualityAlertChanged`]
+    QualityAlert(QualityAlertChanged),
+
+    /// See [`CodecChanged`]
+    CodecChanged(CodecChanged),
+
+    /// See [`KeyframeRequested`]
+    KeyframeRequest(KeyframeRequested),
+
+    /// See [`ReferencePictureIndicated`]
+    ReferencePictureIndication(ReferencePictureIndicated),
+
+    /// See [`UnexpectedPayloadTypeReceived`]
+    UnexpectedPayloadType(UnexpectedPayloadTypeReceived),
+
+    /// See [`UnexpectedDirectionRtpReceived`]
+    UnexpectedDirectionRtp(UnexpectedDirectionRtpReceived),
+
+    /// See [`RemoteMediaEnded`]
+    RemoteMediaEnded(RemoteMediaEnded),
+}
+
+/// How an encoder should answer a keyframe request
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeyframeRecovery {
+    /// Encode a full IDR frame
+    #[default]
+    Idr,
+
+    /// Refresh the picture over the next frames using periodic intra macroblocks (intra-refresh), which avoids
+    /// the bitrate spike of a full IDR frame
+    IntraRefresh,
+
+    /// Encode a frame which only references the long-term reference frame last acknowledged by the peer, see
+    /// [`ReferencePictureIndicated`]
+    ///
+    /// Requests are reported as [`KeyframeRecovery::Idr`] while the peer hasn't acknowledged any reference yet.
+    LongTermReference,
+}
+
+/// How received RTP packets with a payload type which wasn't negotiated for the media are handled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnexpectedPayloadTypePolicy {
+    /// Drop the packets
+    #[default]
+    Drop,
+
+    /// Deliver the packets, tagged using the `unexpected_payload_type` field of [`Event::ReceiveRTP`]
+    Deliver,
+
+    /// Drop the packets and switch the media to the payload type's codec with the next SDP offer
+    ///
+    /// The codec must be one of the media's local codecs, otherwise the packets are only dropped.
+    Renegotiate,
+}
+
+/// How received RTP packets of a media which was negotiated without receiving (`sendonly` or `inactive` from the
+/// local perspective) are handled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiveDirectionEnforcement {
+    /// Deliver the packets like any other using [`Event::ReceiveRTP`]
+    Deliver,
+
+    /// Drop the packets
+    #[default]
+    Drop,
+
+    /// Emit the packets using [`Event::UnexpectedDirectionRtp`] instead of [`Event::ReceiveRTP`]
+    Report,
 }
 
 /// Connection state of a transport
@@ -127,6 +500,35 @@ pub enum TransportChange {
     Remove(TransportId),
     /// Remove the RTCP socket of the given transport.
     RemoveRtcpSocket(TransportId),
+    /// Request a UDP socket to send to the given multicast group. The multicast TTL of the socket must be set to
+    /// the group's TTL. RTCP is sent from the same socket.
+    ///
+    /// The port of the socket must be reported using [`SdpSession::set_transport_ports`](super::SdpSession::set_transport_ports)
+    CreateMulticastSocket(TransportId, MulticastGroup),
+    /// Request a UDP socket bound to the port of the multicast group, which must join the group to receive from it.
+    /// RTCP is sent from the same socket.
+    ///
+    /// If a source is set, only packets from this source are of interest (source-specific multicast). Packets from
+    /// other sources are discarded by the session, but can already be filtered by the socket.
+    ///
+    /// The port of the socket must be reported using [`SdpSession::set_transport_ports`](super::SdpSession::set_transport_ports)
+    JoinMulticastGroup(TransportId, MulticastGroup, Option<IpAddr>),
+    /// Request a TCP listener which accepts a single connection from the peer. RTP and RTCP are sent over the
+    /// connection, each packet prefixed with its length ([RFC4571](https://www.rfc-editor.org/rfc/rfc4571.html)).
+    ///
+    /// The port of the listener must be reported using [`SdpSession::set_transport_ports`](super::SdpSession::set_transport_ports)
+    CreateTcpListener(TransportId),
+    /// Request a TCP connection to the given address of the peer. RTP and RTCP are sent over the connection,
+    /// each packet prefixed with its length ([RFC4571](https://www.rfc-editor.org/rfc/rfc4571.html)).
+    ///
+    /// The local port of the connection must be reported using [`SdpSession::set_transport_ports`](super::SdpSession::set_transport_ports)
+    ConnectTcp(TransportId, SocketAddr),
+    /// Replace the UDP socket of the transport's component with a new socket bound to the same local address.
+    /// Packets which haven't been sent yet should be sent using the new socket.
+    ///
+    /// Requested after sending failed persistently, a new socket picks up changes in routing which the old one
+    /// may not recover from.
+    RebindSocket(TransportId, Component),
 }
 
 // TODO; can this be removed because it too complex for something so simple
@@ -149,6 +551,26 @@ impl<'a> TransportRequiredChanges<'a> {
             .push(TransportChange::CreateSocketPair(self.id))
     }
 
+    pub(crate) fn require_multicast_socket(&mut self, group: MulticastGroup) {
+        self.changes
+            .push(TransportChange::CreateMulticastSocket(self.id, group));
+    }
+
+    pub(crate) fn join_multicast_group(&mut self, group: MulticastGroup, source: Option<IpAddr>) {
+        self.changes
+            .push(TransportChange::JoinMulticastGroup(self.id, group, source));
+    }
+
+    pub(crate) fn require_tcp_listener(&mut self) {
+        self.changes
+            .push(TransportChange::CreateTcpListener(self.id));
+    }
+
+    pub(crate) fn connect_tcp(&mut self, remote: SocketAddr) {
+        self.changes
+            .push(TransportChange::ConnectTcp(self.id, remote));
+    }
+
     pub(crate) fn remove_rtcp_socket(&mut self) {
         self.changes
             .push(TransportChange::RemoveRtcpSocket(self.id));
@@ -0,0 +1,50 @@
+use crate::{
+    LocalMediaId, MediaId, NegotiatedDtmf, TransportConnectionState, TransportId, TransportType,
+};
+use bytesstr::BytesStr;
+use ice::IceConnectionState;
+use sdp_types::{Direction, MediaType};
+use std::borrow::Cow;
+use std::net::SocketAddr;
+
+/// Information about an active media, returned by [`SdpSession::medias`](crate::SdpSession::medias)
+#[derive(Debug, Clone)]
+pub struct MediaInfo {
+    pub id: MediaId,
+    pub local_media_id: LocalMediaId,
+    pub media_type: MediaType,
+    pub mid: Option<BytesStr>,
+    /// Negotiated direction from the local perspective
+    pub direction: Direction,
+    /// The peer put the media on hold
+    pub remote_hold: bool,
+    /// Transport the media is sent with
+    pub transport_id: TransportId,
+    /// Transport the media is migrating to, see
+    /// [`SdpSession::migrate_transports`](crate::SdpSession::migrate_transports)
+    pub migrate_to: Option<TransportId>,
+    /// Payload type of the negotiated codec
+    pub payload_type: u8,
+    /// Name of the negotiated codec
+    pub codec_name: Cow<'static, str>,
+    pub clock_rate: u32,
+    /// Negotiated telephone-event payload type, if any
+    pub dtmf: Option<NegotiatedDtmf>,
+}
+
+/// Information about a transport, returned by [`SdpSession::transports`](crate::SdpSession::transports)
+#[derive(Debug, Clone)]
+pub struct TransportInfo {
+    pub id: TransportId,
+    pub transport_type: TransportType,
+    /// Connection state of the transport, `None` while it is still being negotiated
+    pub connection_state: Option<TransportConnectionState>,
+    /// Connection state of the transport's ICE agent, if it uses ICE
+    pub ice_connection_state: Option<IceConnectionState>,
+    pub local_rtp_port: Option<u16>,
+    pub local_rtcp_port: Option<u16>,
+    /// Address RTP is sent to, `None` while the transport is still being negotiated
+    pub remote_rtp_address: Option<SocketAddr>,
+    /// Active media using the transport or migrating to it
+    pub media: Vec<MediaId>,
+}
@@ -1,19 +1,33 @@
 #![warn(unreachable_pub)]
 
 use ::rtp::{
-    rtcp_types::{Compound, Packet as RtcpPacket},
-    RtpPacket, RtpSession,
+    rtcp_types::{
+        Compound, Fir, Nack, Packet as RtcpPacket, PayloadFeedback, Pli, ReportBlock, Rpsi,
+        RtcpPacketWriterExt, TransportFeedback,
+    },
+    AnsweringMachineDetector, AudioLevelMeter, BandwidthProber, BitrateAllocation,
+    BitrateAllocator, CallProgressDetector, CodecDownshiftPolicy, CongestionController,
+    DownshiftConfig, DownshiftDecision, DtmfReceiver, DtmfSender, JitterBufferConfig,
+    JitterBufferStats, NackConfig, NackGenerator, NtpTimestamp, PacketFeedback, ProbeConfig,
+    PromptId, PromptMode, PromptPlayer, QualityMonitor, QualitySample, QualityThreshold, Remb,
+    RembEstimator, RtpPacket, RtpSession, RtxSender, SequenceNumber, Ssrc, TelephoneEvent,
+    ToneDetector, FLASH_HOOK,
 };
 use bytes::Bytes;
 use bytesstr::BytesStr;
 use events::{
-    IceConnectionStateChanged, IceGatheringStateChanged, TransportChange,
-    TransportConnectionStateChanged, TransportRequiredChanges,
+    AnsweringMachineDetected, AudioLevelChanged, BandwidthEstimated, BitrateEstimated,
+    CallProgressDetected, CodecDownshiftRequested, DtmfReceived, FingerprintMismatch,
+    IceConnectionStateChanged, IceGatheringStateChanged, KeyframeRequested, NewLocalCandidate,
+    PromptFinished, PromptStarted, QualityAlertChanged, ReferencePictureIndicated,
+    RemoteMediaEnded, TargetBitrateChanged, ToneDetected, TransportConnectionStateChanged,
+    TransportMigrated, TransportRequiredChanges, TransportSendFailed,
+    UnexpectedDirectionRtpReceived, UnexpectedPayloadTypeReceived,
 };
 use ice::{Component, IceAgent, IceConnectionState, IceGatheringState, ReceivedPkt};
 use local_media::LocalMedia;
-use sdp_types::MediaDescription;
-use slotmap::SlotMap;
+use sdp_types::{MediaDescription, TransportProtocol};
+use slotmap::{SecondaryMap, SlotMap};
 use std::{
     cmp::min,
     collections::VecDeque,
@@ -22,24 +36,43 @@ use std::{
     time::{Duration, Instant},
 };
 use transport::{
-    ReceivedPacket, SessionTransportState, Transport, TransportBuilder, TransportEvent,
+    ReceivedPacket, SdesSrtpRekey, SessionTransportState, TcpSetup, Transport, TransportBuilder,
+    TransportEvent,
 };
 
 mod async_wrapper;
+mod capabilities;
 mod codecs;
 mod events;
+mod info;
 mod local_media;
 mod options;
 mod rtp;
 mod sdp;
+mod srtp;
+mod tap;
 mod transport;
 
-pub use async_wrapper::{AsyncEvent, AsyncSdpSession};
-pub use codecs::{Codec, Codecs, NegotiatedCodec};
-pub use events::{Event, TransportConnectionState};
-pub use options::{BundlePolicy, Options, RtcpMuxPolicy, TransportType};
+pub use async_wrapper::{AsyncEvent, AsyncSdpSession, CandidateCache};
+pub use capabilities::{capabilities, Capabilities};
+pub use codecs::{Codec, Codecs, NegotiatedCodec, NegotiatedDtmf, NegotiatedRtx};
+pub use events::{
+    Event, KeyframeRecovery, ReceiveDirectionEnforcement, TransportChange,
+    TransportConnectionState, UnexpectedPayloadTypePolicy,
+};
+pub use ice::{InvalidTurnUri, TurnCredentials, TurnTransport, TurnUri};
+pub use info::{MediaInfo, TransportInfo};
+pub use options::{
+    AddressMapping, BundlePolicy, Options, RtcpMuxPolicy, SrtpProfile, TransportType,
+};
 pub use sdp::SdpAnswerState;
-pub use sdp_types::{Direction, MediaType, ParseSessionDescriptionError, SessionDescription};
+pub use sdp_types::{
+    Content, Direction, Fingerprint, FingerprintAlgorithm, IceCandidate, MediaType,
+    ParseSessionDescriptionError, SessionDescription, SrtpSuite,
+};
+pub use srtp::SrtpBackend;
+pub use tap::{RtpDumpTap, RtpTap, TapDirection, TappedRtpPacket};
+pub use transport::{DtlsCertificate, MulticastGroup};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct MediaId(u32);
@@ -50,6 +83,35 @@ impl MediaId {
         self.0 += 1;
         id
     }
+
+    /// Identification tag (`a=mid`) of the media, encoded using all alphanumeric characters if `short`
+    ///
+    /// Short mids keep the SDP and the RTP mid header extension small, the first 62 media only require a single
+    /// character.
+    fn mid(self, short: bool) -> String {
+        const ALPHABET: &[u8; 62] =
+            b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+        if !short {
+            return self.0.to_string();
+        }
+
+        let mut mid = Vec::new();
+        let mut n = self.0 as usize;
+
+        loop {
+            mid.push(ALPHABET[n % ALPHABET.len()]);
+            n /= ALPHABET.len();
+
+            if n == 0 {
+                break;
+            }
+        }
+
+        mid.reverse();
+
+        String::from_utf8(mid).expect("alphabet is ascii")
+    }
 }
 
 slotmap::new_key_type! {
@@ -61,6 +123,21 @@ slotmap::new_key_type! {
 pub enum Error {
     #[error(transparent)]
     Io(#[from] io::Error),
+    /// The operation requires the session to run, but it is paused, see
+    /// [`AsyncSdpSession::pause`](crate::AsyncSdpSession::pause)
+    #[error("session is paused")]
+    Paused,
+}
+
+/// Error returned when sending a telephone-event, see [`SdpSession::send_dtmf`]
+#[derive(Debug, thiserror::Error)]
+pub enum DtmfError {
+    #[error("telephone-event was not negotiated for the media")]
+    NotNegotiated,
+    #[error("event {0} is not a DTMF digit")]
+    NotADigit(u8),
+    #[error("peer does not support telephone-event {0}")]
+    Unsupported(u8),
 }
 
 pub struct SdpSession {
@@ -71,6 +148,8 @@ pub struct SdpSession {
 
     // Local ip address to use
     address: IpAddr,
+    /// Local address of the other IP family, see [`SdpSession::set_dual_stack_address`]
+    dual_stack_address: Option<IpAddr>,
 
     /// State shared between transports
     transport_state: SessionTransportState,
@@ -86,9 +165,19 @@ pub struct SdpSession {
 
     // Transports
     transports: SlotMap<TransportId, TransportEntry>,
+    /// Bandwidth estimate of each transport in bits per second, split across its media by their bitrate allocation
+    bandwidth_estimates: SecondaryMap<TransportId, u32>,
+    /// Congestion controllers producing the bandwidth estimate of a transport from packet feedback
+    congestion_controllers: SecondaryMap<TransportId, Box<dyn CongestionController>>,
 
     /// Pending changes which will be (maybe partially) applied once the offer/answer exchange has been completed
     pending_changes: Vec<PendingChange>,
+    /// Index into `pending_changes` where the currently open batch of changes starts
+    ///
+    /// Changes after this index are not included in SDP offers until the batch is committed
+    batch_start: Option<usize>,
+    /// Direction overrides which are only used for the next SDP offer
+    direction_overrides: Vec<(MediaId, Direction)>,
     transport_changes: Vec<TransportChange>,
     events: VecDeque<Event>,
 }
@@ -116,13 +205,13 @@ impl TransportEntry {
         }
     }
 
-    #[track_caller]
-    fn unwrap(&self) -> &Transport {
+    /// Whether RTCP is multiplexed with RTP, a transport which is not yet negotiated always offers rtcp-mux
+    fn rtcp_mux(&self) -> bool {
         match self {
-            TransportEntry::Transport(transport) => transport,
-            TransportEntry::TransportBuilder(..) => {
-                panic!("Tried to access incomplete transport")
+            TransportEntry::Transport(transport) => {
+                transport.remote_rtp_address == transport.remote_rtcp_address
             }
+            TransportEntry::TransportBuilder(..) => true,
         }
     }
 
@@ -136,6 +225,47 @@ impl TransportEntry {
         }
     }
 
+    fn local_ports(&self) -> (Option<u16>, Option<u16>) {
+        match self {
+            TransportEntry::Transport(transport) => {
+                (transport.local_rtp_port, transport.local_rtcp_port)
+            }
+            TransportEntry::TransportBuilder(transport_builder) => (
+                transport_builder.local_rtp_port,
+                transport_builder.local_rtcp_port,
+            ),
+        }
+    }
+
+    fn multicast(&self) -> Option<&MulticastGroup> {
+        match self {
+            TransportEntry::Transport(transport) => transport.multicast.as_ref(),
+            TransportEntry::TransportBuilder(transport_builder) => {
+                transport_builder.multicast.as_ref()
+            }
+        }
+    }
+
+    fn tcp(&self) -> Option<TcpSetup> {
+        match self {
+            TransportEntry::Transport(transport) => transport.tcp,
+            TransportEntry::TransportBuilder(transport_builder) => transport_builder.tcp,
+        }
+    }
+
+    /// Multicast and TCP transports are never bundled
+    fn can_bundle(&self) -> bool {
+        self.multicast().is_none() && self.tcp().is_none()
+    }
+
+    fn sdp_type(&self, avpf: bool) -> TransportProtocol {
+        match (self.tcp(), avpf) {
+            (Some(_), true) => TransportProtocol::TcpRtpAvpf,
+            (Some(_), false) => TransportProtocol::TcpRtpAvp,
+            (None, avpf) => self.type_().sdp_type(avpf),
+        }
+    }
+
     fn ice_agent(&self) -> Option<&IceAgent> {
         match self {
             TransportEntry::Transport(transport) => transport.ice_agent.as_ref(),
@@ -172,19 +302,180 @@ struct ActiveMedia {
 
     /// Optional mid, this is only Some if both offer and answer have the mid attribute set
     mid: Option<BytesStr>,
+    /// Content of the media included in SDP, e.g. to flag screen sharing
+    content: Vec<Content>,
 
     /// SDP Send/Recv direction
     direction: DirectionBools,
+    /// Direction to offer in the next SDP offer, set after a one-shot direction override was negotiated
+    restore_direction: Option<DirectionBools>,
+    /// The peer put the media on hold, RTP is not sent
+    remote_hold: bool,
 
     /// Which transport is used by this media
     transport: TransportId,
+    /// Transport the media switches to once it is connected, set while a migration is in progress
+    migrate_to: Option<TransportId>,
 
     /// Which codec is negotiated
     codec_pt: u8,
     codec: Codec,
+    /// Telephone-event payload type, if negotiated
+    dtmf: Option<NegotiatedDtmf>,
+    /// Reassembles received telephone-events into DTMF digits
+    dtmf_receiver: DtmfReceiver,
+    /// Retransmissions of lost packets, if RTX and generic NACKs are negotiated
+    rtx: Option<Retransmission>,
+    /// Estimates the bitrate available for the received media to report it to the peer, if REMB is negotiated
+    remb: Option<RembEstimator>,
+
+    /// Audio level metering, if enabled
+    audio_level: Option<AudioLevelMonitor>,
+    /// Inband DTMF and fax tone detection, if enabled
+    tone_detector: Option<ToneDetector>,
+    /// Ringback, busy and SIT detection, if enabled
+    call_progress: Option<CallProgressDetector>,
+    /// Answering machine detection, if enabled and not yet decided
+    answering_machine: Option<Box<dyn AnsweringMachineDetector>>,
+
+    /// Receives copies of sent & received RTP packets, see [`SdpSession::set_rtp_tap`]
+    rtp_tap: Option<Box<dyn RtpTap>>,
+    /// Prompts inserted into the outgoing audio
+    prompt_player: PromptPlayer,
+    /// Startup bandwidth probing, if enabled and not yet finished
+    bandwidth_prober: Option<BandwidthProber>,
+    /// Priority and bitrate limits used to split the transport's bandwidth estimate, if set
+    bitrate_allocation: Option<BitrateAllocation>,
+    /// Last target bitrate emitted for the media
+    target_bitrate: Option<u32>,
+
+    /// How to answer picture loss indications
+    keyframe_recovery: KeyframeRecovery,
+    /// Sequence number of the last handled FIR, to ignore retransmissions
+    last_fir_sequence: Option<u8>,
+    /// The peer acknowledged a reference picture using RPSI
+    reference_acked: bool,
+
+    /// Switching to a more robust codec under sustained loss, if enabled
+    codec_downshift: Option<CodecDownshift>,
+    /// Call quality alerting, if thresholds are registered
+    quality_monitor: Option<QualityMonitor>,
+
+    /// How to handle received RTP with a payload type other than `codec_pt`
+    unexpected_pt_policy: UnexpectedPayloadTypePolicy,
+    /// Number of received RTP packets with a payload type other than `codec_pt`
+    unexpected_pt_packets: u64,
+    /// Unexpected payload types already reported since the codec was negotiated
+    reported_unexpected_pts: Vec<u8>,
+
+    /// How to handle received RTP while the media is negotiated without receiving
+    receive_direction_enforcement: ReceiveDirectionEnforcement,
+    /// Number of RTP packets received while the media is negotiated without receiving
+    unexpected_direction_packets: u64,
+}
+
+struct CodecDownshift {
+    policy: CodecDownshiftPolicy,
+    /// Codec and payload type to switch to under sustained loss
+    robust: (Codec, u8),
+    /// Codec and payload type to switch back to when the loss is low again
+    original: (Codec, u8),
+}
+
+struct Retransmission {
+    negotiated: NegotiatedRtx,
+    /// Keeps the sent packets to answer the peer's NACKs
+    sender: RtxSender,
+    /// Requests lost received packets
+    nack_generator: NackGenerator,
+}
+
+impl Retransmission {
+    fn new(negotiated: NegotiatedRtx, buffer_size: Option<usize>) -> Self {
+        let mut sender = RtxSender::new(
+            negotiated.send_pt,
+            Ssrc(rand::random()),
+            SequenceNumber(rand::random()),
+        );
+
+        if let Some(buffer_size) = buffer_size {
+            sender.set_capacity(buffer_size);
+        }
+
+        Self {
+            negotiated,
+            sender,
+            nack_generator: NackGenerator::new(NackConfig::default()),
+        }
+    }
+}
+
+struct AudioLevelMonitor {
+    meter: AudioLevelMeter,
+    interval: Duration,
+    next_report: Instant,
 }
 
 impl ActiveMedia {
+    /// Returns if the payload type is negotiated for the media, either by the codec, as telephone-event or for
+    /// retransmissions
+    fn is_negotiated_pt(&self, pt: u8) -> bool {
+        self.codec_pt == pt
+            || self.dtmf.as_ref().is_some_and(|dtmf| dtmf.recv_pt == pt)
+            || self
+                .rtx
+                .as_ref()
+                .is_some_and(|rtx| rtx.negotiated.recv_pt == pt)
+    }
+
+    /// Handle a received RTP packet with a payload type other than the negotiated one, returns if the packet must
+    /// be delivered
+    fn receive_unexpected_payload_type(
+        &mut self,
+        pt: u8,
+        local_media: &SlotMap<LocalMediaId, LocalMedia>,
+        pending_changes: &mut Vec<PendingChange>,
+        events: &mut VecDeque<Event>,
+    ) -> bool {
+        self.unexpected_pt_packets += 1;
+
+        if !self.reported_unexpected_pts.contains(&pt) {
+            self.reported_unexpected_pts.push(pt);
+
+            log::debug!(
+                "Received RTP with unexpected payload type {pt} for {:?}, expected {}",
+                self.id,
+                self.codec_pt
+            );
+
+            let codec = local_media[self.local_media_id]
+                .codecs
+                .codecs
+                .iter()
+                .find(|c| c.pt == Some(pt));
+
+            let renegotiate = match (self.unexpected_pt_policy, codec) {
+                (UnexpectedPayloadTypePolicy::Renegotiate, Some(codec)) => {
+                    pending_changes.push(PendingChange::ChangeCodec(self.id, codec.clone(), pt));
+                    true
+                }
+                _ => false,
+            };
+
+            events.push_back(Event::UnexpectedPayloadType(
+                UnexpectedPayloadTypeReceived {
+                    media_id: self.id,
+                    payload_type: pt,
+                    expected_payload_type: self.codec_pt,
+                    policy: self.unexpected_pt_policy,
+                    renegotiate,
+                },
+            ));
+        }
+
+        self.unexpected_pt_policy == UnexpectedPayloadTypePolicy::Deliver
+    }
+
     fn matches(
         &self,
         transports: &SlotMap<TransportId, TransportEntry>,
@@ -210,6 +501,28 @@ enum PendingChange {
     AddMedia(PendingMedia),
     RemoveMedia(MediaId),
     ChangeDirection(MediaId, Direction),
+    ChangeCodec(MediaId, Codec, u8),
+    MigrateTransports(TransportMigration),
+    /// New keys offered for the SDES-SRTP transports, see [`SdpSession::rekey_srtp`]
+    RekeySrtp(Vec<(TransportId, SdesSrtpRekey)>),
+}
+
+/// Requested migration of all media to new transports, see [`SdpSession::migrate_transports`]
+struct TransportMigration {
+    /// Local address of the new transports
+    address: IpAddr,
+    /// Pairs of the transport in use and the transport replacing it
+    transports: Vec<(TransportId, TransportId)>,
+}
+
+impl TransportMigration {
+    /// Returns the transport replacing the given transport
+    fn new_transport(&self, old_transport_id: TransportId) -> Option<TransportId> {
+        self.transports
+            .iter()
+            .find(|(old, _)| *old == old_transport_id)
+            .map(|(_, new)| *new)
+    }
 }
 
 struct PendingMedia {
@@ -218,6 +531,7 @@ struct PendingMedia {
     media_type: MediaType,
     mid: String,
     direction: Direction,
+    content: Vec<Content>,
     use_avpf: bool,
     /// Transport to use when not bundling
     standalone_transport: Option<TransportId>,
@@ -241,41 +555,54 @@ impl PendingMedia {
 
         if let Some(standalone_transport) = self.standalone_transport {
             // TODO: some sip endpoints push back on the AVPF offer and set AVP in their answer so we might need to match that here as well and adjust
-            if transports[standalone_transport]
-                .type_()
-                .sdp_type(self.use_avpf)
-                == desc.media.proto
-            {
+            if transports[standalone_transport].sdp_type(self.use_avpf) == desc.media.proto {
                 return true;
             }
         }
 
-        transports[self.bundle_transport]
-            .type_()
-            .sdp_type(self.use_avpf)
-            == desc.media.proto
+        transports[self.bundle_transport].sdp_type(self.use_avpf) == desc.media.proto
     }
 }
 
 impl SdpSession {
     pub fn new(address: IpAddr, options: Options) -> Self {
         SdpSession {
+            transport_state: SessionTransportState::new(
+                options.dtls_srtp_profiles.clone(),
+                options.dtls_fingerprint_algorithms.clone(),
+                options.dtls_certificate.clone(),
+                &options.dtls_pinned_fingerprints,
+                options.srtp_backend,
+            ),
             options,
             id: u64::from(rand::random::<u16>()),
             version: u64::from(rand::random::<u16>()),
             address,
-            transport_state: SessionTransportState::default(),
+            dual_stack_address: None,
             next_pt: 96,
             local_media: SlotMap::with_key(),
             next_media_id: MediaId(0),
             state: Vec::new(),
             transports: SlotMap::with_key(),
+            bandwidth_estimates: SecondaryMap::new(),
+            congestion_controllers: SecondaryMap::new(),
             pending_changes: Vec::new(),
+            batch_start: None,
+            direction_overrides: Vec::new(),
             transport_changes: Vec::new(),
             events: VecDeque::new(),
         }
     }
 
+    /// Set the local address of the other IP family, for hosts using both IPv4 & IPv6
+    ///
+    /// The address passed to [`new`](Self::new) is used in offers. Transports of a peer using the address family
+    /// of the dual-stack address use it as connection address instead, so answering an IPv6 offer uses the IPv6
+    /// address. The sockets of the session's transports must be able to receive on both addresses.
+    pub fn set_dual_stack_address(&mut self, address: IpAddr) {
+        self.dual_stack_address = Some(address);
+    }
+
     /// Add a stun server to use for ICE
     pub fn add_stun_server(&mut self, server: SocketAddr) {
         self.transport_state.add_stun_server(server);
@@ -296,6 +623,40 @@ impl SdpSession {
         }
     }
 
+    /// Add a TURN server to allocate relayed candidates from for ICE
+    pub fn add_turn_server(&mut self, server: SocketAddr, credentials: TurnCredentials) {
+        self.transport_state
+            .add_turn_server(server, credentials.clone());
+
+        for transport in self.transports.values_mut() {
+            match transport {
+                TransportEntry::Transport(transport) => {
+                    if let Some(ice_agent) = &mut transport.ice_agent {
+                        ice_agent.add_turn_server(server, credentials.clone());
+                    }
+                }
+                TransportEntry::TransportBuilder(transport_builder) => {
+                    if let Some(ice_agent) = &mut transport_builder.ice_agent {
+                        ice_agent.add_turn_server(server, credentials.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Add a remote ICE candidate of the transport, which was received out-of-band using trickle ICE
+    ///
+    /// Candidates can be added before the SDP answer was received, candidates of transports without ICE are ignored.
+    pub fn add_remote_candidate(&mut self, transport_id: TransportId, candidate: &IceCandidate) {
+        let Some(transport) = self.transports.get_mut(transport_id) else {
+            return;
+        };
+
+        if let Some(ice_agent) = transport.ice_agent_mut() {
+            ice_agent.add_remote_candidate(candidate);
+        }
+    }
+
     pub fn has_media(&self) -> bool {
         let has_pending_media = self
             .pending_changes
@@ -305,6 +666,55 @@ impl SdpSession {
         (!self.state.is_empty()) || has_pending_media
     }
 
+    /// Iterate over the active media of the session, media which hasn't been negotiated yet is not included
+    pub fn medias(&self) -> impl Iterator<Item = MediaInfo> + '_ {
+        self.state.iter().map(|media| MediaInfo {
+            id: media.id,
+            local_media_id: media.local_media_id,
+            media_type: media.media_type,
+            mid: media.mid.clone(),
+            direction: media.direction.into(),
+            remote_hold: media.remote_hold,
+            transport_id: media.transport,
+            migrate_to: media.migrate_to,
+            payload_type: media.codec_pt,
+            codec_name: media.codec.name.clone(),
+            clock_rate: media.codec.clock_rate,
+            dtmf: media.dtmf.clone(),
+        })
+    }
+
+    /// Iterate over the transports of the session, including the ones which are still being negotiated
+    pub fn transports(&self) -> impl Iterator<Item = TransportInfo> + '_ {
+        self.transports.iter().map(|(id, entry)| {
+            let (local_rtp_port, local_rtcp_port) = entry.local_ports();
+
+            let (connection_state, remote_rtp_address) = match entry {
+                TransportEntry::Transport(transport) => (
+                    Some(transport.connection_state()),
+                    Some(transport.remote_rtp_address),
+                ),
+                TransportEntry::TransportBuilder(..) => (None, None),
+            };
+
+            TransportInfo {
+                id,
+                transport_type: entry.type_(),
+                connection_state,
+                ice_connection_state: entry.ice_agent().map(|agent| agent.connection_state()),
+                local_rtp_port,
+                local_rtcp_port,
+                remote_rtp_address,
+                media: self
+                    .state
+                    .iter()
+                    .filter(|media| media.transport == id || media.migrate_to == Some(id))
+                    .map(|media| media.id)
+                    .collect(),
+            }
+        })
+    }
+
     /// Register codecs for a media type with a limit of how many media session by can be created
     ///
     /// Returns `None` if no more payload type numbers are available
@@ -332,8 +742,49 @@ impl SdpSession {
             }
         }
 
+        let mut dtmf: Vec<Codec> = vec![];
+
+        if codecs.allow_dtmf && codecs.media_type == MediaType::Audio {
+            for codec in &codecs.codecs {
+                if dtmf.iter().any(|d| d.clock_rate == codec.clock_rate) {
+                    continue;
+                }
+
+                let mut telephone_event = Codec::telephone_event(codec.clock_rate);
+                telephone_event.pt = Some(self.next_pt);
+                dtmf.push(telephone_event);
+
+                self.next_pt += 1;
+
+                if self.next_pt > 127 {
+                    self.next_pt = prev_next_pt;
+                    return None;
+                }
+            }
+        }
+
+        let mut rtx: Vec<Codec> = vec![];
+
+        if codecs.allow_rtx && codecs.media_type == MediaType::Video {
+            for codec in &codecs.codecs {
+                let mut rtx_codec =
+                    Codec::rtx(codec.clock_rate, codec.pt.expect("pt was assigned above"));
+                rtx_codec.pt = Some(self.next_pt);
+                rtx.push(rtx_codec);
+
+                self.next_pt += 1;
+
+                if self.next_pt > 127 {
+                    self.next_pt = prev_next_pt;
+                    return None;
+                }
+            }
+        }
+
         Some(self.local_media.insert(LocalMedia {
             codecs,
+            dtmf,
+            rtx,
             limit,
             use_count: 0,
             direction: direction.into(),
@@ -344,10 +795,11 @@ impl SdpSession {
     pub fn add_media(&mut self, local_media_id: LocalMediaId, direction: Direction) -> MediaId {
         let media_id = self.next_media_id.step();
 
-        // Find out which type of transport to use for this media
+        // Find out which type of transport to use for this media, ignoring transports which are never bundled
         let transport_type = self
             .transports
             .values()
+            .filter(|t| t.can_bundle())
             .map(|t| t.type_())
             .max()
             .unwrap_or(self.options.offer_transport);
@@ -356,7 +808,7 @@ impl SdpSession {
         let bundle_transport_id = self
             .transports
             .iter()
-            .find(|(_, t)| t.type_() == transport_type)
+            .find(|(_, t)| t.can_bundle() && t.type_() == transport_type)
             .map(|(id, _)| id);
 
         let (standalone_transport, bundle_transport) = match self.options.bundle_policy {
@@ -401,8 +853,9 @@ impl SdpSession {
                 id: media_id,
                 local_media_id,
                 media_type: self.local_media[local_media_id].codecs.media_type,
-                mid: media_id.0.to_string(),
+                mid: media_id.mid(self.options.compact_sdp),
                 direction,
+                content: vec![],
                 use_avpf: self.options.offer_avpf,
                 standalone_transport,
                 bundle_transport,
@@ -411,6 +864,88 @@ impl SdpSession {
         media_id
     }
 
+    /// Request a new media session which sends to a multicast group, e.g. for paging or intercom systems
+    ///
+    /// The media is always offered as `sendonly` using plain RTP on its own transport, without ICE or rtcp-mux.
+    /// Every receiver of the offer is expected to join the multicast group.
+    pub fn add_multicast_media(
+        &mut self,
+        local_media_id: LocalMediaId,
+        group: MulticastGroup,
+    ) -> MediaId {
+        let media_id = self.next_media_id.step();
+
+        let transport_id = self.transports.insert_with_key(|id| {
+            TransportEntry::TransportBuilder(TransportBuilder::new_multicast(
+                TransportRequiredChanges::new(id, &mut self.transport_changes),
+                group,
+            ))
+        });
+
+        self.pending_changes
+            .push(PendingChange::AddMedia(PendingMedia {
+                id: media_id,
+                local_media_id,
+                media_type: self.local_media[local_media_id].codecs.media_type,
+                mid: media_id.mid(self.options.compact_sdp),
+                direction: Direction::SendOnly,
+                content: vec![],
+                use_avpf: false,
+                standalone_transport: Some(transport_id),
+                bundle_transport: transport_id,
+            }));
+
+        media_id
+    }
+
+    /// Request a new media session which sends RTP over TCP ([RFC4571](https://www.rfc-editor.org/rfc/rfc4571.html)),
+    /// for networks where UDP is blocked entirely
+    ///
+    /// The media is offered using plain RTP on its own transport without ICE, RTCP is multiplexed over the same
+    /// connection. The local side waits for the peer to connect (`a=setup:passive`).
+    pub fn add_tcp_media(&mut self, local_media_id: LocalMediaId, direction: Direction) -> MediaId {
+        let media_id = self.next_media_id.step();
+
+        let transport_id = self.transports.insert_with_key(|id| {
+            TransportEntry::TransportBuilder(TransportBuilder::new_tcp(
+                TransportRequiredChanges::new(id, &mut self.transport_changes),
+            ))
+        });
+
+        self.pending_changes
+            .push(PendingChange::AddMedia(PendingMedia {
+                id: media_id,
+                local_media_id,
+                media_type: self.local_media[local_media_id].codecs.media_type,
+                mid: media_id.mid(self.options.compact_sdp),
+                direction,
+                content: vec![],
+                use_avpf: self.options.offer_avpf,
+                standalone_transport: Some(transport_id),
+                bundle_transport: transport_id,
+            }));
+
+        media_id
+    }
+
+    /// Request a new media session for screen sharing
+    ///
+    /// The media is flagged using `a=content:slides` ([RFC4796](https://www.rfc-editor.org/rfc/rfc4796.html)),
+    /// so the peer can distinguish it from the camera when both are sent as video.
+    pub fn add_screenshare_media(
+        &mut self,
+        local_media_id: LocalMediaId,
+        direction: Direction,
+    ) -> MediaId {
+        let media_id = self.add_media(local_media_id, direction);
+
+        if let Some(PendingChange::AddMedia(pending_media)) = self.pending_changes.last_mut() {
+            pending_media.content = vec![Content::Slides];
+        }
+
+        media_id
+    }
+
     /// Mark the media as deleted
     ///
     /// The actual deletion will be performed with the next SDP exchange
@@ -428,92 +963,1249 @@ impl SdpSession {
         }
     }
 
-    /// Returns an list all pending transport changes
-    pub fn transport_changes(&mut self) -> Vec<TransportChange> {
-        std::mem::take(&mut self.transport_changes)
-    }
+    /// Move all media to new transports using the given local address, e.g. after the network interface changed
+    ///
+    /// The new transports are created alongside the current ones (make-before-break) and offered with the next
+    /// SDP offer. Media keeps using its current transport until the new transport is connected, then it is
+    /// switched over keeping its RTP session, and the old transport is removed. A
+    /// [`TransportMigrated`](events::TransportMigrated) event is emitted for every switched media.
+    ///
+    /// A previously requested migration which has not been completed by an SDP exchange is replaced. SDP offers of
+    /// the peer moving media to a new address are handled the same way, answering with a new transport.
+    pub fn migrate_transports(&mut self, address: IpAddr) {
+        self.pending_changes
+            .retain(|change| !matches!(change, PendingChange::MigrateTransports(..)));
+        self.remove_unused_transports();
 
-    /// Set the RTP/RTCP ports of a transport
-    pub fn set_transport_ports(
-        &mut self,
-        transport_id: TransportId,
-        ip_addrs: &[IpAddr],
-        rtp_port: u16,
-        rtcp_port: Option<u16>,
-    ) {
-        let transport = &mut self.transports[transport_id];
+        let mut transports = vec![];
 
-        match transport {
-            TransportEntry::Transport(transport) => {
-                transport.local_rtp_port = Some(rtp_port);
-                transport.local_rtcp_port = rtcp_port;
-            }
-            TransportEntry::TransportBuilder(transport_builder) => {
-                transport_builder.local_rtp_port = Some(rtp_port);
-                transport_builder.local_rtcp_port = rtcp_port;
+        for old_transport_id in self.state.iter().map(|media| media.transport) {
+            if transports.iter().any(|(old, _)| *old == old_transport_id) {
+                continue;
             }
-        };
 
-        if let Some(ice_agent) = transport.ice_agent_mut() {
-            for ip in ip_addrs {
-                ice_agent.add_host_addr(Component::Rtp, SocketAddr::new(*ip, rtp_port));
+            let old_transport = &self.transports[old_transport_id];
+            let multicast = old_transport.multicast().copied();
+            let tcp = old_transport.tcp().is_some();
+            let transport_type = old_transport.type_();
 
-                if let Some(rtcp_port) = rtcp_port {
-                    ice_agent.add_host_addr(Component::Rtcp, SocketAddr::new(*ip, rtcp_port));
-                }
-            }
+            let new_transport_id = self.transports.insert_with_key(|id| {
+                let required_changes =
+                    TransportRequiredChanges::new(id, &mut self.transport_changes);
+
+                let transport_builder = if let Some(group) = multicast {
+                    TransportBuilder::new_multicast(required_changes, group)
+                } else if tcp {
+                    TransportBuilder::new_tcp(required_changes)
+                } else {
+                    TransportBuilder::new(
+                        &mut self.transport_state,
+                        required_changes,
+                        transport_type,
+                        self.options.rtcp_mux_policy,
+                        self.options.offer_ice,
+                    )
+                };
+
+                TransportEntry::TransportBuilder(transport_builder)
+            });
+
+            transports.push((old_transport_id, new_transport_id));
+        }
+
+        if !transports.is_empty() {
+            self.pending_changes
+                .push(PendingChange::MigrateTransports(TransportMigration {
+                    address,
+                    transports,
+                }));
         }
     }
 
-    /// Returns a duration after which [`poll`](Self::poll) must be called
-    pub fn timeout(&self) -> Option<Duration> {
-        let now = Instant::now();
+    /// Rotate the keys of all SDES-SRTP transports with the next SDP offer
+    ///
+    /// A new local key is offered for every transport, keeping the negotiated crypto suite. The new keys are used
+    /// once the answer is received, together with the key the peer answered with. A [`SrtpRekeyed`](events::SrtpRekeyed) event is
+    /// emitted for every transport once its keys were replaced. If the offer is rejected the current keys stay in
+    /// use, the request is discarded using [`rollback_changes`](Self::rollback_changes).
+    ///
+    /// Offers of the peer containing a new key are handled the same way, answering with a new local key. DTLS-SRTP
+    /// transports are not re-keyed.
+    pub fn rekey_srtp(&mut self) {
+        self.pending_changes
+            .retain(|change| !matches!(change, PendingChange::RekeySrtp(..)));
 
-        let mut timeout = None;
+        let mut rekeys: Vec<(TransportId, SdesSrtpRekey)> = vec![];
 
-        for transport in self.transports.values() {
-            match transport {
-                TransportEntry::Transport(transport) => {
-                    timeout = opt_min(timeout, transport.timeout(now))
-                }
-                TransportEntry::TransportBuilder(transport_builder) => {
-                    timeout = opt_min(timeout, transport_builder.timeout(now))
+        for media in &self.state {
+            if rekeys.iter().any(|(id, _)| *id == media.transport) {
+                continue;
+            }
+
+            if let TransportEntry::Transport(transport) = &self.transports[media.transport] {
+                if let Some(rekey) = transport.create_srtp_rekey() {
+                    rekeys.push((media.transport, rekey));
                 }
             }
         }
 
-        for media in self.state.iter() {
-            timeout = opt_min(timeout, media.rtp_session.pop_rtp_after(None));
-
-            let rtcp_send_timeout = media
-                .next_rtcp
-                .checked_duration_since(now)
-                .unwrap_or_default();
-            timeout = opt_min(timeout, Some(rtcp_send_timeout))
+        if !rekeys.is_empty() {
+            self.pending_changes.push(PendingChange::RekeySrtp(rekeys));
         }
-
-        timeout
     }
 
-    /// Poll for new events. Call [`pop_event`](Self::pop_event) to handle them.
-    pub fn poll(&mut self, now: Instant) {
-        for transport in &mut self.transports.values_mut() {
-            match transport {
-                TransportEntry::Transport(transport) => {
-                    transport.poll(now);
-                }
-                TransportEntry::TransportBuilder(transport_builder) => {
-                    transport_builder.poll(now);
+    /// Switch media to the transport it is migrating to once that is connected, see [`migrate_transports`](Self::migrate_transports)
+    fn complete_transport_migrations(&mut self) {
+        let mut changed = false;
+
+        for media in &mut self.state {
+            let Some(new_transport_id) = media.migrate_to else {
+                continue;
+            };
+
+            let TransportEntry::Transport(new_transport) = &self.transports[new_transport_id]
+            else {
+                continue;
+            };
+
+            match new_transport.connection_state() {
+                TransportConnectionState::Connected => {}
+                TransportConnectionState::Failed => {
+                    log::warn!(
+                        "Failed to migrate {:?}, new transport failed to connect",
+                        media.id
+                    );
+
+                    media.migrate_to = None;
+                    changed = true;
+                    continue;
                 }
+                TransportConnectionState::New | TransportConnectionState::Connecting => continue,
             }
-        }
 
-        for media in self.state.iter_mut() {
-            if let Some(rtp_packet) = media.rtp_session.pop_rtp(None) {
-                self.events.push_back(Event::ReceiveRTP {
+            let old_transport_id = std::mem::replace(&mut media.transport, new_transport_id);
+            media.migrate_to = None;
+            changed = true;
+
+            // Keep the bandwidth estimation of the media running on the new transport
+            if let Some(controller) = self.congestion_controllers.remove(old_transport_id) {
+                self.congestion_controllers
+                    .insert(new_transport_id, controller);
+            }
+
+            if let Some(bitrate) = self.bandwidth_estimates.remove(old_transport_id) {
+                self.bandwidth_estimates.insert(new_transport_id, bitrate);
+            }
+
+            self.events
+                .push_back(Event::TransportMigrated(TransportMigrated {
                     media_id: media.id,
-                    packet: rtp_packet,
-                });
+                    old_transport_id,
+                    new_transport_id,
+                }));
+        }
+
+        if changed {
+            self.remove_unused_transports();
+        }
+    }
+
+    /// Deliver received RTP packets of the media immediately in arrival order, skipping the jitter buffer
+    ///
+    /// Useful when forwarding the media, where buffering should only happen at the final receiver.
+    pub fn set_jitter_buffer_bypass(&mut self, media_id: MediaId, bypass: bool) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.rtp_session.set_jitter_buffer_bypass(bypass);
+        }
+    }
+
+    /// Set how long received RTP packets of the media are buffered, replacing the configuration set using
+    /// [`Codecs::with_jitter_buffer`]
+    pub fn set_jitter_buffer_config(&mut self, media_id: MediaId, config: JitterBufferConfig) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.rtp_session.set_jitter_buffer_config(config);
+        }
+    }
+
+    /// Statistics of the jitter buffer of the media, `None` if no RTP was received yet
+    pub fn jitter_buffer_stats(&self, media_id: MediaId) -> Option<JitterBufferStats> {
+        let media = self.state.iter().find(|m| m.id == media_id)?;
+
+        let ssrc = media.rtp_session.remote_ssrc().next()?;
+
+        media.rtp_session.jitter_buffer_stats(ssrc)
+    }
+
+    /// Emit [`Event::AudioLevel`] for the media every `interval`, `None` disables the audio level reports
+    ///
+    /// Levels are taken from the audio level header extension of received packets, when the peer doesn't send it
+    /// decoded samples must be provided using [`process_audio_samples`](Self::process_audio_samples).
+    pub fn set_audio_level_interval(&mut self, media_id: MediaId, interval: Option<Duration>) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.audio_level = interval.map(|interval| AudioLevelMonitor {
+                meter: AudioLevelMeter::default(),
+                interval,
+                next_report: Instant::now() + interval,
+            });
+        }
+    }
+
+    /// Detect inband DTMF and fax tones (CNG/CED) in the decoded audio of the media
+    ///
+    /// Detected tones are emitted as [`Event::ToneDetected`]. The decoded samples must be provided using
+    /// [`process_audio_samples`](Self::process_audio_samples).
+    pub fn set_tone_detection(&mut self, media_id: MediaId, enabled: bool) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.tone_detector = enabled.then(|| ToneDetector::new(media.codec.sample_rate()));
+        }
+    }
+
+    /// Detect call progress tones (ringback, busy, special information tones) in the decoded audio of the media
+    ///
+    /// Intended to supervise the early media of outbound calls, e.g. to tell a ringing destination from a busy or
+    /// disconnected number when the network doesn't signal it. Detected tones are emitted as
+    /// [`Event::CallProgress`]. The decoded samples must be provided using
+    /// [`process_audio_samples`](Self::process_audio_samples).
+    pub fn set_call_progress_analysis(&mut self, media_id: MediaId, enabled: bool) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.call_progress =
+                enabled.then(|| CallProgressDetector::new(media.codec.sample_rate()));
+        }
+    }
+
+    /// Run answering machine detection (AMD) on the decoded audio of the media
+    ///
+    /// Should be enabled once an outbound call is answered. The detector is fed until it returns a verdict, which
+    /// is emitted as [`Event::AnsweringMachine`]. The decoded samples must be provided using
+    /// [`process_audio_samples`](Self::process_audio_samples).
+    ///
+    /// [`EnergyAnsweringMachineDetector`](rtp::EnergyAnsweringMachineDetector) can be used as a simple default,
+    /// created with the sample rate of the media's negotiated codec.
+    pub fn set_answering_machine_detection(
+        &mut self,
+        media_id: MediaId,
+        detector: Option<Box<dyn AnsweringMachineDetector>>,
+    ) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.answering_machine = detector;
+        }
+    }
+
+    /// Pass copies of the RTP packets sent and received by the media to the tap, e.g. to record the call
+    ///
+    /// Packets are tapped unencrypted, the media path is not affected. [`RtpDumpTap`] writes the packets of one
+    /// direction to an rtpdump file. `None` removes the tap.
+    pub fn set_rtp_tap(&mut self, media_id: MediaId, tap: Option<Box<dyn RtpTap>>) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.rtp_tap = tap;
+        }
+    }
+
+    /// Probe the available bandwidth by sending padding packets right after the media's transport connected
+    ///
+    /// The probes are sent on a separate SSRC before the actual media ramps up. The estimate is emitted as
+    /// [`Event::BandwidthEstimate`] once the peer reported on the probes in a RTCP receiver report and can be used
+    /// to choose the initial video bitrate. `None` disables probing, if it hasn't finished yet.
+    pub fn set_bandwidth_probing(&mut self, media_id: MediaId, config: Option<ProbeConfig>) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.bandwidth_prober = config
+                .map(|config| BandwidthProber::new(Ssrc(rand::random()), media.codec_pt, config));
+        }
+    }
+
+    /// Set how the media's encoder should answer picture loss indications (PLI) of the peer
+    ///
+    /// Keyframe requests are emitted as [`Event::KeyframeRequest`]. Full intra requests (FIR) always ask for an
+    /// IDR frame, since they require an immediate decoder refresh point.
+    pub fn set_keyframe_recovery(&mut self, media_id: MediaId, recovery: KeyframeRecovery) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.keyframe_recovery = recovery;
+        }
+    }
+
+    /// Set the priority and bitrate limits of the media's encoder
+    ///
+    /// The bandwidth estimate of the media's transport is split across all its media with an allocation and
+    /// the result is emitted as [`Event::TargetBitrate`] for every media whose target bitrate changed.
+    pub fn set_bitrate_allocation(
+        &mut self,
+        media_id: MediaId,
+        allocation: Option<BitrateAllocation>,
+    ) {
+        let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) else {
+            return;
+        };
+
+        media.bitrate_allocation = allocation;
+
+        if allocation.is_none() {
+            media.target_bitrate = None;
+        }
+
+        let transport_id = media.transport;
+        self.allocate_bitrate(transport_id);
+    }
+
+    /// Switch the audio media to a more robust codec when the peer reports sustained high loss using RTCP, and
+    /// back to the original codec when the conditions improve, `None` disables it
+    ///
+    /// The robust codec (e.g. Opus with a low bitrate and inband FEC, or PCMU instead of G.722) must be part of
+    /// the media's local codecs. Whenever the codec should be switched [`Event::CodecDownshift`] is emitted and the
+    /// switch is included in the next SDP offer. Once the peer accepted it [`Event::CodecChanged`] is emitted.
+    pub fn set_codec_downshift(
+        &mut self,
+        media_id: MediaId,
+        downshift: Option<(Codec, DownshiftConfig)>,
+    ) {
+        let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) else {
+            return;
+        };
+
+        let Some((codec, config)) = downshift else {
+            media.codec_downshift = None;
+            return;
+        };
+
+        let robust = self.local_media[media.local_media_id]
+            .codecs
+            .codecs
+            .iter()
+            .find(|c| {
+                c.name.eq_ignore_ascii_case(&codec.name)
+                    && c.clock_rate == codec.clock_rate
+                    && c.fmtp == codec.fmtp
+            });
+
+        let Some(robust) = robust else {
+            log::warn!(
+                "Cannot enable codec downshift to {}, codec is not part of the local media",
+                codec.name
+            );
+            return;
+        };
+
+        media.codec_downshift = Some(CodecDownshift {
+            policy: CodecDownshiftPolicy::new(config),
+            robust: (
+                robust.clone(),
+                robust.pt.expect("pt is set when added to session"),
+            ),
+            original: (media.codec.clone(), media.codec_pt),
+        });
+    }
+
+    /// Set how received RTP packets with a payload type which wasn't negotiated for the media are handled, the
+    /// default is [`UnexpectedPayloadTypePolicy::Drop`]
+    ///
+    /// [`Event::UnexpectedPayloadType`] is emitted for every such payload type received. The packets are counted
+    /// regardless of the policy, see [`unexpected_payload_type_packets`](Self::unexpected_payload_type_packets).
+    pub fn set_unexpected_payload_type_policy(
+        &mut self,
+        media_id: MediaId,
+        policy: UnexpectedPayloadTypePolicy,
+    ) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.unexpected_pt_policy = policy;
+        }
+    }
+
+    /// Number of RTP packets received for the media with a payload type which wasn't negotiated
+    pub fn unexpected_payload_type_packets(&self, media_id: MediaId) -> u64 {
+        self.state
+            .iter()
+            .find(|m| m.id == media_id)
+            .map_or(0, |m| m.unexpected_pt_packets)
+    }
+
+    /// Set how received RTP packets are handled while the media is negotiated without receiving (`sendonly` or
+    /// `inactive` from the local perspective), the default is [`ReceiveDirectionEnforcement::Drop`]
+    ///
+    /// The packets are counted regardless of the enforcement, see
+    /// [`unexpected_direction_packets`](Self::unexpected_direction_packets).
+    pub fn set_receive_direction_enforcement(
+        &mut self,
+        media_id: MediaId,
+        enforcement: ReceiveDirectionEnforcement,
+    ) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.receive_direction_enforcement = enforcement;
+        }
+    }
+
+    /// Number of RTP packets received for the media while it was negotiated without receiving
+    pub fn unexpected_direction_packets(&self, media_id: MediaId) -> u64 {
+        self.state
+            .iter()
+            .find(|m| m.id == media_id)
+            .map_or(0, |m| m.unexpected_direction_packets)
+    }
+
+    /// Set the bandwidth estimate of a transport in bits per second
+    ///
+    /// The estimate is split across the media using the transport, see
+    /// [`set_bitrate_allocation`](Self::set_bitrate_allocation). It is also set when startup bandwidth probing
+    /// finished with an estimate.
+    pub fn set_bandwidth_estimate(&mut self, transport_id: TransportId, bitrate: u32) {
+        if !self.transports.contains_key(transport_id) {
+            return;
+        }
+
+        self.bandwidth_estimates.insert(transport_id, bitrate);
+        self.allocate_bitrate(transport_id);
+    }
+
+    /// Set the congestion controller of a transport, e.g. [`GccController`](::rtp::GccController)
+    ///
+    /// Feedback about the packets sent on the transport must be passed using
+    /// [`receive_packet_feedback`](Self::receive_packet_feedback). `None` removes the controller.
+    pub fn set_congestion_controller(
+        &mut self,
+        transport_id: TransportId,
+        controller: Option<Box<dyn CongestionController>>,
+    ) {
+        if !self.transports.contains_key(transport_id) {
+            return;
+        }
+
+        match controller {
+            Some(controller) => {
+                self.congestion_controllers.insert(transport_id, controller);
+            }
+            None => {
+                self.congestion_controllers.remove(transport_id);
+            }
+        }
+    }
+
+    /// Pass feedback about packets sent on a transport, e.g. from transport-wide congestion control feedback,
+    /// to its congestion controller
+    ///
+    /// The resulting target bitrate is used as the bandwidth estimate of the transport, see
+    /// [`set_bandwidth_estimate`](Self::set_bandwidth_estimate).
+    pub fn receive_packet_feedback(
+        &mut self,
+        transport_id: TransportId,
+        feedback: &[PacketFeedback],
+    ) {
+        let Some(controller) = self.congestion_controllers.get_mut(transport_id) else {
+            return;
+        };
+
+        controller.on_feedback(Instant::now(), feedback);
+
+        let bitrate = controller.target_bitrate();
+        self.set_bandwidth_estimate(transport_id, bitrate);
+    }
+
+    fn allocate_bitrate(&mut self, transport_id: TransportId) {
+        let Some(&bitrate) = self.bandwidth_estimates.get(transport_id) else {
+            return;
+        };
+
+        let mut allocator = BitrateAllocator::new();
+
+        for media in self.state.iter().filter(|m| m.transport == transport_id) {
+            if let Some(allocation) = media.bitrate_allocation {
+                allocator.set(media.id, allocation);
+            }
+        }
+
+        for (media_id, bitrate) in allocator.allocate(bitrate) {
+            let media = self
+                .state
+                .iter_mut()
+                .find(|m| m.id == media_id)
+                .expect("allocator only contains existing media");
+
+            if media.target_bitrate != Some(bitrate) {
+                media.target_bitrate = Some(bitrate);
+
+                self.events
+                    .push_back(Event::TargetBitrate(TargetBitrateChanged {
+                        media_id,
+                        bitrate,
+                    }));
+            }
+        }
+    }
+
+    /// Register call quality thresholds for the media, which are evaluated using the RTCP reports the peer sends
+    /// about the media's outgoing stream
+    ///
+    /// [`Event::QualityAlert`] is emitted whenever an alert is raised or cleared. An empty list removes all
+    /// thresholds.
+    pub fn set_quality_thresholds(&mut self, media_id: MediaId, thresholds: Vec<QualityThreshold>) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.quality_monitor =
+                (!thresholds.is_empty()).then(|| QualityMonitor::new(thresholds));
+        }
+    }
+
+    /// Feed a report block about a media's outgoing stream to its quality monitor
+    fn report_quality(&mut self, report_block: &ReportBlock<'_>) {
+        let Some(media) = self
+            .state
+            .iter_mut()
+            .find(|m| m.rtp_session.ssrc().0 == report_block.ssrc())
+        else {
+            return;
+        };
+
+        let Some(monitor) = &mut media.quality_monitor else {
+            return;
+        };
+
+        // RTT = A - LSR - DLSR (RFC 3550 Section 6.4.1), unknown until the peer received a sender report
+        let rtt = match report_block.last_sender_report_timestamp() {
+            0 => None,
+            lsr => NtpTimestamp::now()
+                .to_fixed_u32()
+                .wrapping_sub(lsr)
+                .checked_sub(report_block.delay_since_last_sender_report_timestamp())
+                .map(|rtt| rtt as f32 * 1000.0 / 65536.0),
+        };
+
+        let sample = QualitySample {
+            loss: f32::from(report_block.fraction_lost()) * 100.0 / 256.0,
+            jitter: report_block.interarrival_jitter() as f32 * 1000.0
+                / media.codec.clock_rate as f32,
+            rtt,
+        };
+
+        let media_id = media.id;
+
+        for alert in monitor.report(Instant::now(), sample) {
+            self.events
+                .push_back(Event::QualityAlert(QualityAlertChanged {
+                    media_id,
+                    metric: alert.metric,
+                    active: alert.active,
+                    value: alert.value,
+                }));
+        }
+    }
+
+    /// Feed the loss reported about a media's outgoing stream to its codec downshift policy
+    fn report_loss(&mut self, ssrc: u32, fraction_lost: f32) {
+        let Some(media) = self
+            .state
+            .iter_mut()
+            .find(|m| m.rtp_session.ssrc().0 == ssrc)
+        else {
+            return;
+        };
+
+        let Some(downshift) = &mut media.codec_downshift else {
+            return;
+        };
+
+        let Some(decision) = downshift.policy.report_loss(Instant::now(), fraction_lost) else {
+            return;
+        };
+
+        let (codec, pt) = match decision {
+            DownshiftDecision::Downshift => downshift.robust.clone(),
+            DownshiftDecision::Upshift => downshift.original.clone(),
+        };
+
+        let media_id = media.id;
+
+        // A later codec change of the same media takes precedence over earlier ones which weren't offered yet
+        self.pending_changes
+            .push(PendingChange::ChangeCodec(media_id, codec, pt));
+
+        self.events
+            .push_back(Event::CodecDownshift(CodecDownshiftRequested {
+                media_id,
+                downshift: decision == DownshiftDecision::Downshift,
+            }));
+    }
+
+    /// End the remote streams of the ssrcs contained in received RTCP BYE packets
+    fn receive_bye(&mut self, ssrcs: Vec<Ssrc>) {
+        for ssrc in ssrcs {
+            let Some(media_id) = self
+                .state
+                .iter_mut()
+                .find_map(|m| m.rtp_session.remove_remote_ssrc(ssrc).then_some(m.id))
+            else {
+                log::debug!("Received BYE for unknown ssrc {:#x}", ssrc.0);
+                continue;
+            };
+
+            log::debug!("Remote ended stream {:#x} of {media_id:?}", ssrc.0);
+
+            self.events
+                .push_back(Event::RemoteMediaEnded(RemoteMediaEnded { media_id, ssrc }));
+        }
+    }
+
+    /// Use the bitrate estimated by the peer as bandwidth estimate of the transport
+    fn receive_remb(&mut self, transport_id: TransportId, remb: Remb) {
+        let bitrate = u32::try_from(remb.bitrate).unwrap_or(u32::MAX);
+
+        log::debug!("Peer estimated {bitrate}bps available on {transport_id:?}");
+
+        self.events
+            .push_back(Event::BitrateEstimate(BitrateEstimated {
+                transport_id,
+                bitrate,
+            }));
+
+        self.set_bandwidth_estimate(transport_id, bitrate);
+    }
+
+    /// Answer a generic NACK of the peer by retransmitting the requested packets
+    fn receive_nack(&mut self, feedback: &TransportFeedback<'_>) {
+        let Ok(nack) = feedback.parse_fci::<Nack>() else {
+            return;
+        };
+
+        let Some(media) = self
+            .state
+            .iter_mut()
+            .find(|m| m.rtp_session.ssrc().0 == feedback.media_ssrc())
+        else {
+            return;
+        };
+
+        let Some(rtx) = &mut media.rtx else {
+            log::debug!("Ignoring NACK for {:?} without negotiated RTX", media.id);
+            return;
+        };
+
+        let transport = self.transports[media.transport].unwrap_mut();
+
+        for sequence_number in nack.entries() {
+            if let Some(packet) = rtx.sender.retransmit(SequenceNumber(sequence_number)) {
+                transport.send_rtp(packet);
+            }
+        }
+    }
+
+    fn receive_keyframe_request(&mut self, feedback: &PayloadFeedback<'_>) {
+        if feedback.parse_fci::<Pli>().is_ok() {
+            let Some(media) = self
+                .state
+                .iter()
+                .find(|m| m.rtp_session.ssrc().0 == feedback.media_ssrc())
+            else {
+                return;
+            };
+
+            let recovery = match media.keyframe_recovery {
+                KeyframeRecovery::LongTermReference if !media.reference_acked => {
+                    KeyframeRecovery::Idr
+                }
+                recovery => recovery,
+            };
+
+            self.events
+                .push_back(Event::KeyframeRequest(KeyframeRequested {
+                    media_id: media.id,
+                    recovery,
+                }));
+        } else if let Ok(rpsi) = feedback.parse_fci::<Rpsi>() {
+            let Some(media) = self
+                .state
+                .iter_mut()
+                .find(|m| m.rtp_session.ssrc().0 == feedback.media_ssrc())
+            else {
+                return;
+            };
+
+            media.reference_acked = true;
+
+            self.events.push_back(Event::ReferencePictureIndication(
+                ReferencePictureIndicated {
+                    media_id: media.id,
+                    payload_type: rpsi.payload_type(),
+                    data: Bytes::copy_from_slice(rpsi.bit_string().0),
+                },
+            ));
+        } else if let Ok(fir) = feedback.parse_fci::<Fir>() {
+            // The FIR's media ssrc field is unused, the requested ssrcs are in its entries
+            for entry in fir.entries() {
+                let Some(media) = self
+                    .state
+                    .iter_mut()
+                    .find(|m| m.rtp_session.ssrc().0 == entry.ssrc())
+                else {
+                    continue;
+                };
+
+                // Retransmitted FIRs carry the same sequence number
+                if media.last_fir_sequence == Some(entry.sequence()) {
+                    continue;
+                }
+
+                media.last_fir_sequence = Some(entry.sequence());
+
+                self.events
+                    .push_back(Event::KeyframeRequest(KeyframeRequested {
+                        media_id: media.id,
+                        recovery: KeyframeRecovery::Idr,
+                    }));
+            }
+        }
+    }
+
+    /// Tell the peer that the given reference picture of the received media was decoded correctly, using RTCP RPSI
+    ///
+    /// `data` is the codec specific identification of the picture. The peer's encoder can then use it as long-term
+    /// reference to recover from losses instead of sending an IDR frame. The feedback is sent as reduced-size RTCP
+    /// packet ([RFC5506](https://www.rfc-editor.org/rfc/rfc5506.html)).
+    pub fn send_reference_picture_indication(&mut self, media_id: MediaId, data: &[u8]) {
+        let Some(media) = self.state.iter().find(|m| m.id == media_id) else {
+            return;
+        };
+
+        let Some(remote_ssrc) = media.rtp_session.remote_ssrc().next() else {
+            log::debug!("Cannot send RPSI before receiving RTP on media {media_id:?}");
+            return;
+        };
+
+        let transport = self.transports[media.transport].unwrap_mut();
+
+        if transport.connection_state() != TransportConnectionState::Connected {
+            return;
+        }
+
+        let rpsi = Rpsi::builder()
+            .payload_type(media.codec_pt)
+            .native_data(data, 0);
+
+        let feedback = PayloadFeedback::builder(&rpsi)
+            .sender_ssrc(media.rtp_session.ssrc().0)
+            .media_ssrc(remote_ssrc.0);
+
+        let mut encode_buf = vec![0u8; 1500];
+
+        match feedback.write_into(&mut encode_buf) {
+            Ok(len) => {
+                encode_buf.truncate(len);
+                transport.send_rtcp(encode_buf);
+            }
+            Err(e) => log::warn!("Failed to write RTCP RPSI packet, {e:?}"),
+        }
+    }
+
+    /// Feed decoded mono audio samples of the media into its audio level meter and tone detectors
+    ///
+    /// Does nothing if neither audio level reports, tone detection, call progress analysis nor answering machine
+    /// detection are enabled for the media.
+    pub fn process_audio_samples(&mut self, media_id: MediaId, samples: &[i16]) {
+        let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) else {
+            return;
+        };
+
+        if let Some(monitor) = &mut media.audio_level {
+            monitor.meter.process_samples(samples);
+        }
+
+        if let Some(tone_detector) = &mut media.tone_detector {
+            tone_detector.process_samples(samples);
+
+            while let Some(tone) = tone_detector.pop_tone() {
+                self.events
+                    .push_back(Event::ToneDetected(ToneDetected { media_id, tone }));
+            }
+        }
+
+        if let Some(call_progress) = &mut media.call_progress {
+            call_progress.process_samples(samples);
+
+            while let Some(tone) = call_progress.pop_tone() {
+                self.events
+                    .push_back(Event::CallProgress(CallProgressDetected { media_id, tone }));
+            }
+        }
+
+        if let Some(detector) = &mut media.answering_machine {
+            if let Some(verdict) = detector.process_samples(samples) {
+                media.answering_machine = None;
+
+                self.events
+                    .push_back(Event::AnsweringMachine(AnsweringMachineDetected {
+                        media_id,
+                        verdict,
+                    }));
+            }
+        }
+    }
+
+    /// Insert an audio prompt (e.g. "this call is recorded") into the outgoing audio of the media
+    ///
+    /// The prompt consists of decoded mono samples with the sample rate of the media's negotiated codec. It is
+    /// either mixed with or replaces the audio passed to
+    /// [`process_outgoing_audio_samples`](Self::process_outgoing_audio_samples) before it is encoded. Multiple
+    /// prompts are played one after another, [`Event::PromptStarted`] is emitted once a prompt starts playing and
+    /// [`Event::PromptFinished`] once it played completely.
+    ///
+    /// Returns `None` if the media doesn't exist.
+    pub fn play_prompt(
+        &mut self,
+        media_id: MediaId,
+        samples: Vec<i16>,
+        mode: PromptMode,
+    ) -> Option<PromptId> {
+        let media = self.state.iter_mut().find(|m| m.id == media_id)?;
+
+        Some(media.prompt_player.play(samples, mode))
+    }
+
+    /// Stop all prompts of the media, started using [`play_prompt`](Self::play_prompt)
+    ///
+    /// Stopped prompts are not reported using [`Event::PromptFinished`].
+    pub fn stop_prompts(&mut self, media_id: MediaId) {
+        if let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) {
+            media.prompt_player.stop();
+        }
+    }
+
+    /// Insert the playing prompts into the decoded mono audio samples which are about to be encoded and sent
+    ///
+    /// `timestamp` is the RTP timestamp the first sample will be sent with, it is used to report the exact start
+    /// and end of prompts in [`Event::PromptStarted`] and [`Event::PromptFinished`].
+    ///
+    /// Does nothing if no prompt is playing.
+    pub fn process_outgoing_audio_samples(
+        &mut self,
+        media_id: MediaId,
+        samples: &mut [i16],
+        timestamp: u32,
+    ) {
+        let Some(media) = self.state.iter_mut().find(|m| m.id == media_id) else {
+            return;
+        };
+
+        media.prompt_player.process_samples(samples);
+
+        let clock_rate = u64::from(media.codec.clock_rate);
+        let sample_rate = u64::from(media.codec.sample_rate());
+        let timestamp_at = |offset: usize| {
+            timestamp.wrapping_add((offset as u64 * clock_rate / sample_rate) as u32)
+        };
+
+        while let Some((prompt_id, offset)) = media.prompt_player.pop_started() {
+            self.events.push_back(Event::PromptStarted(PromptStarted {
+                media_id,
+                prompt_id,
+                timestamp: timestamp_at(offset),
+            }));
+        }
+
+        while let Some((prompt_id, offset)) = media.prompt_player.pop_finished() {
+            self.events.push_back(Event::PromptFinished(PromptFinished {
+                media_id,
+                prompt_id,
+                timestamp: timestamp_at(offset),
+            }));
+        }
+    }
+
+    /// Estimated end-to-end latency of the received media, from capture at the peer until reception
+    ///
+    /// Requires the peer to send the abs-capture-time header extension. Returns `None` if no estimate is available.
+    pub fn end_to_end_latency(&self, media_id: MediaId) -> Option<Duration> {
+        let media = self.state.iter().find(|m| m.id == media_id)?;
+
+        media
+            .rtp_session
+            .remote_ssrc()
+            .filter_map(|ssrc| media.rtp_session.end_to_end_latency(ssrc))
+            .max()
+    }
+
+    /// Start a batch of changes
+    ///
+    /// All calls to [`add_media`](Self::add_media), [`remove_media`](Self::remove_media) and
+    /// [`update_media`](Self::update_media) made after this are not included in any SDP offer until the batch is
+    /// committed using [`commit_changes`](Self::commit_changes). Use [`rollback_changes`](Self::rollback_changes)
+    /// to discard the batch instead.
+    ///
+    /// Does nothing if a batch has already been started.
+    pub fn begin_changes(&mut self) {
+        if self.batch_start.is_none() {
+            self.batch_start = Some(self.pending_changes.len());
+        }
+    }
+
+    /// Commit all changes made since [`begin_changes`](Self::begin_changes), they will be applied together with the
+    /// next SDP offer.
+    pub fn commit_changes(&mut self) {
+        self.batch_start = None;
+    }
+
+    /// Discard pending changes
+    ///
+    /// If a batch was started using [`begin_changes`](Self::begin_changes) only the changes of that batch are
+    /// discarded. Otherwise all pending changes are discarded, which must be done when the peer rejected the
+    /// SDP offer containing them (e.g. the re-INVITE was answered with an error response).
+    pub fn rollback_changes(&mut self) {
+        if let Some(batch_start) = self.batch_start.take() {
+            self.pending_changes.truncate(batch_start);
+        } else {
+            self.pending_changes.clear();
+            self.direction_overrides.clear();
+        }
+
+        self.remove_unused_transports();
+    }
+
+    /// Returns the changes which are to be included in the next SDP offer
+    fn committed_changes(&self) -> &[PendingChange] {
+        let end = self.batch_start.unwrap_or(self.pending_changes.len());
+
+        &self.pending_changes[..end]
+    }
+
+    /// Returns the migration which is to be included in the next SDP offer
+    fn committed_migration(&self) -> Option<&TransportMigration> {
+        self.committed_changes().iter().rev().find_map(|c| match c {
+            PendingChange::MigrateTransports(migration) => Some(migration),
+            _ => None,
+        })
+    }
+
+    /// Returns the new keys which are to be offered in the next SDP offer
+    fn committed_srtp_rekeys(&self) -> &[(TransportId, SdesSrtpRekey)] {
+        self.committed_changes()
+            .iter()
+            .rev()
+            .find_map(|c| match c {
+                PendingChange::RekeySrtp(rekeys) => Some(rekeys.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Override the direction of the media for the next SDP offer only
+    ///
+    /// Unlike [`update_media`](Self::update_media) this does not replace the media's direction permanently.
+    /// The direction the media had before is offered again with the offer following the next one
+    /// (e.g. to put the media temporarily on hold with `sendonly`).
+    pub fn override_media_direction(&mut self, media_id: MediaId, direction: Direction) {
+        if self.state.iter().any(|e| e.id == media_id) {
+            self.direction_overrides.retain(|(id, _)| *id != media_id);
+            self.direction_overrides.push((media_id, direction));
+        }
+    }
+
+    /// Returns an list all pending transport changes
+    pub fn transport_changes(&mut self) -> Vec<TransportChange> {
+        std::mem::take(&mut self.transport_changes)
+    }
+
+    /// Request new sockets for a transport, bound to the same local ports
+    ///
+    /// Useful when sending fails because the routing changed, e.g. after a VPN was connected. Only UDP sockets
+    /// of negotiated unicast transports are rebound, the changes are returned by
+    /// [`transport_changes`](Self::transport_changes).
+    pub fn rebind_transport(&mut self, transport_id: TransportId) {
+        for component in [Component::Rtp, Component::Rtcp] {
+            self.rebind_socket(transport_id, component);
+        }
+    }
+
+    /// Report that sending on a socket of a transport failed persistently
+    ///
+    /// Emits a [`TransportSendFailed`] event and requests rebinding the socket if possible, see
+    /// [`rebind_transport`](Self::rebind_transport).
+    pub fn report_send_failure(
+        &mut self,
+        transport_id: TransportId,
+        component: Component,
+        error: io::Error,
+    ) {
+        log::warn!("Sending on {transport_id:?} {component:?} failed persistently, {error}");
+
+        let rebinding = self.rebind_socket(transport_id, component);
+
+        self.events
+            .push_back(Event::TransportSendFailed(TransportSendFailed {
+                transport_id,
+                component,
+                error,
+                rebinding,
+            }));
+    }
+
+    /// Request rebinding a socket of a transport, returns if rebinding is possible
+    fn rebind_socket(&mut self, transport_id: TransportId, component: Component) -> bool {
+        let Some(TransportEntry::Transport(transport)) = self.transports.get(transport_id) else {
+            return false;
+        };
+
+        // Multicast memberships and TCP connections can't be moved to a new socket
+        if transport.multicast.is_some() || transport.tcp.is_some() {
+            return false;
+        }
+
+        if component == Component::Rtcp && transport.local_rtcp_port.is_none() {
+            return false;
+        }
+
+        let already_requested = self.transport_changes.iter().any(|change| {
+            matches!(change, TransportChange::RebindSocket(id, c) if *id == transport_id && *c == component)
+        });
+
+        if !already_requested {
+            self.transport_changes
+                .push(TransportChange::RebindSocket(transport_id, component));
+        }
+
+        true
+    }
+
+    /// Set the RTP/RTCP ports of a transport
+    pub fn set_transport_ports(
+        &mut self,
+        transport_id: TransportId,
+        ip_addrs: &[IpAddr],
+        rtp_port: u16,
+        rtcp_port: Option<u16>,
+    ) {
+        // The transport may have been removed before its sockets were created (e.g. by rolling back the media
+        // using it), the socket is released again with the `TransportChange::Remove` queued after this change
+        let Some(transport) = self.transports.get_mut(transport_id) else {
+            return;
+        };
+
+        match transport {
+            TransportEntry::Transport(transport) => {
+                transport.local_rtp_port = Some(rtp_port);
+                transport.local_rtcp_port = rtcp_port;
+            }
+            TransportEntry::TransportBuilder(transport_builder) => {
+                transport_builder.local_rtp_port = Some(rtp_port);
+                transport_builder.local_rtcp_port = rtcp_port;
+            }
+        };
+
+        if let Some(ice_agent) = transport.ice_agent_mut() {
+            let mut add_addr = |component, addr| {
+                ice_agent.add_host_addr(component, addr);
+
+                for mapping in &self.options.address_mappings {
+                    if let Some(mapped) = mapping.map(addr) {
+                        ice_agent.add_mapped_addr(component, addr, mapped);
+                    }
+                }
+            };
+
+            for ip in ip_addrs {
+                add_addr(Component::Rtp, SocketAddr::new(*ip, rtp_port));
+
+                if let Some(rtcp_port) = rtcp_port {
+                    add_addr(Component::Rtcp, SocketAddr::new(*ip, rtcp_port));
+                }
+            }
+        }
+    }
+
+    /// Add a server reflexive address which was discovered using the STUN `server` for the socket of a transport
+    /// before it was handed to the session, e.g. when the socket was bound ahead of time
+    ///
+    /// Must be called after [`set_transport_ports`](Self::set_transport_ports). If the STUN server was added to the
+    /// session, no binding request is sent for the component and gathering the candidates completes right away.
+    /// Does nothing if the transport doesn't use ICE.
+    pub fn add_transport_reflexive_addr(
+        &mut self,
+        transport_id: TransportId,
+        component: Component,
+        server: SocketAddr,
+        base: SocketAddr,
+        addr: SocketAddr,
+    ) {
+        let Some(ice_agent) = self
+            .transports
+            .get_mut(transport_id)
+            .and_then(TransportEntry::ice_agent_mut)
+        else {
+            return;
+        };
+
+        ice_agent.add_server_reflexive_addr(component, server, base, addr);
+    }
+
+    /// Address to use in SDP, the public address of the local address if a mapping exists
+    fn advertised_address(&self, local_ip: IpAddr) -> IpAddr {
+        self.options
+            .address_mappings
+            .iter()
+            .find(|mapping| mapping.local_ip == local_ip)
+            .map_or(local_ip, |mapping| mapping.public_ip)
+    }
+
+    /// Port to use in SDP for the given local port
+    fn advertised_port(&self, local_ip: IpAddr, port: u16) -> u16 {
+        let local = SocketAddr::new(local_ip, port);
+
+        self.options
+            .address_mappings
+            .iter()
+            .find_map(|mapping| mapping.map(local))
+            .map_or(port, |public| public.port())
+    }
+
+    /// Returns a duration after which [`poll`](Self::poll) must be called
+    pub fn timeout(&self) -> Option<Duration> {
+        let now = Instant::now();
+
+        let mut timeout = None;
+
+        for transport in self.transports.values() {
+            match transport {
+                TransportEntry::Transport(transport) => {
+                    timeout = opt_min(timeout, transport.timeout(now))
+                }
+                TransportEntry::TransportBuilder(transport_builder) => {
+                    timeout = opt_min(timeout, transport_builder.timeout(now))
+                }
+            }
+        }
+
+        for media in self.state.iter() {
+            timeout = opt_min(timeout, media.rtp_session.pop_rtp_after(None));
+
+            let rtcp_send_timeout = media
+                .next_rtcp
+                .checked_duration_since(now)
+                .unwrap_or_default();
+            timeout = opt_min(timeout, Some(rtcp_send_timeout));
+
+            if let Some(monitor) = &media.audio_level {
+                let report_timeout = monitor
+                    .next_report
+                    .checked_duration_since(now)
+                    .unwrap_or_default();
+                timeout = opt_min(timeout, Some(report_timeout));
+            }
+
+            if let Some(prober) = &media.bandwidth_prober {
+                timeout = opt_min(timeout, prober.timeout(now));
+            }
+
+            if let Some(rtx) = &media.rtx {
+                timeout = opt_min(timeout, rtx.nack_generator.timeout(now));
+            }
+
+            if let Some(remb) = &media.remb {
+                timeout = opt_min(timeout, remb.timeout(now));
+            }
+        }
+
+        timeout
+    }
+
+    /// Move all timers which expired before `now` to `now`, e.g. after the system was suspended
+    ///
+    /// The next [`poll`](Self::poll) then fires them once, instead of catching up on every missed RTCP report and
+    /// ICE retransmission.
+    pub fn skip_expired_timers(&mut self, now: Instant) {
+        for transport in self.transports.values_mut() {
+            if let Some(ice_agent) = transport.ice_agent_mut() {
+                ice_agent.skip_expired_timers(now);
+            }
+        }
+
+        for media in &mut self.state {
+            media.next_rtcp = media.next_rtcp.max(now);
+        }
+    }
+
+    /// Poll for new events. Call [`pop_event`](Self::pop_event) to handle them.
+    pub fn poll(&mut self, now: Instant) {
+        let mut estimates = vec![];
+
+        for transport in &mut self.transports.values_mut() {
+            match transport {
+                TransportEntry::Transport(transport) => {
+                    transport.poll(now);
+                }
+                TransportEntry::TransportBuilder(transport_builder) => {
+                    transport_builder.poll(now);
+                }
+            }
+        }
+
+        for media in self.state.iter_mut() {
+            if let Some(rtp_packet) = media.rtp_session.pop_rtp(None) {
+                // Telephone-events of DTMF digits are reported once they have ended
+                let is_dtmf = receive_dtmf(media, &rtp_packet, &mut self.events);
+
+                // Padding-only packets (e.g. bandwidth probes) carry no media
+                let is_padding = rtp_packet.payload.is_empty() && rtp_packet.padding.is_some();
+
+                if !is_dtmf && !is_padding {
+                    self.events.push_back(Event::ReceiveRTP {
+                        media_id: media.id,
+                        unexpected_payload_type: !media.is_negotiated_pt(rtp_packet.pt),
+                        packet: rtp_packet,
+                    });
+                }
+            }
+
+            if let Some(monitor) = &mut media.audio_level {
+                if monitor.next_report <= now {
+                    monitor.next_report = now + monitor.interval;
+
+                    if let Some(report) = monitor.meter.report() {
+                        self.events.push_back(Event::AudioLevel(AudioLevelChanged {
+                            media_id: media.id,
+                            report,
+                        }));
+                    }
+                }
+            }
+
+            if let Some(prober) = &mut media.bandwidth_prober {
+                let transport = self.transports[media.transport].unwrap_mut();
+
+                if transport.connection_state() == TransportConnectionState::Connected {
+                    prober.start(now);
+
+                    while let Some(mut packet) = prober.poll(now) {
+                        packet.extensions.mid =
+                            media.mid.as_ref().map(AsRef::<Bytes>::as_ref).cloned();
+
+                        transport.send_rtp(packet);
+                    }
+                }
+
+                if prober.is_finished() {
+                    self.events
+                        .push_back(Event::BandwidthEstimate(BandwidthEstimated {
+                            media_id: media.id,
+                            bitrate: prober.estimate(),
+                        }));
+
+                    if let Some(bitrate) = prober.estimate() {
+                        estimates.push((media.transport, bitrate));
+                    }
+
+                    media.bandwidth_prober = None;
+                }
+            }
+
+            if let Some(rtx) = &mut media.rtx {
+                let missing = rtx.nack_generator.poll(now);
+                let transport = self.transports[media.transport].unwrap_mut();
+
+                if !missing.is_empty()
+                    && transport.connection_state() == TransportConnectionState::Connected
+                {
+                    send_nack(transport, &media.rtp_session, &missing);
+                }
+            }
+
+            if let Some(bitrate) = media.remb.as_mut().and_then(|remb| remb.poll(now)) {
+                let transport = self.transports[media.transport].unwrap_mut();
+
+                if transport.connection_state() == TransportConnectionState::Connected {
+                    send_remb(transport, &media.rtp_session, bitrate);
+                }
             }
 
             // TODO: only emit rtcp if the media's transport state is connected
@@ -529,6 +2221,12 @@ impl SdpSession {
                 send_rtcp_report(transport, media);
             }
         }
+
+        for (transport_id, bitrate) in estimates {
+            self.set_bandwidth_estimate(transport_id, bitrate);
+        }
+
+        self.complete_transport_migrations();
     }
 
     /// Returns the next event to process. Must be called until it return None.
@@ -560,12 +2258,17 @@ impl SdpSession {
                         new,
                     }))
                 }
-                TransportEvent::TransportConnectionState { old, new } => {
+                TransportEvent::TransportConnectionState {
+                    old,
+                    new,
+                    srtp_profile,
+                } => {
                     return Some(Event::TransportConnectionState(
                         TransportConnectionStateChanged {
                             transport_id,
                             old,
                             new,
+                            srtp_profile,
                         },
                     ))
                 }
@@ -575,13 +2278,25 @@ impl SdpSession {
                     source,
                     target,
                 } => {
+                    // Packets are allocated with their exact size, converting them doesn't allocate again
                     return Some(Event::SendData {
                         transport_id,
                         component,
-                        data,
+                        data: Bytes::from(data),
                         source,
                         target,
-                    })
+                    });
+                }
+                TransportEvent::FingerprintMismatch => {
+                    return Some(Event::FingerprintMismatch(FingerprintMismatch {
+                        transport_id,
+                    }))
+                }
+                TransportEvent::NewLocalCandidate { candidate } => {
+                    return Some(Event::NewLocalCandidate(NewLocalCandidate {
+                        transport_id,
+                        candidate,
+                    }))
                 }
             }
         }
@@ -598,7 +2313,18 @@ impl SdpSession {
             }
         };
 
-        match transport.receive(pkt) {
+        let received = transport.receive(pkt);
+
+        // The packet may have completed the connection of a transport some media is migrating to
+        if self
+            .state
+            .iter()
+            .any(|m| m.migrate_to == Some(transport_id))
+        {
+            self.complete_transport_migrations();
+        }
+
+        match received {
             ReceivedPacket::Rtp(packet) => {
                 // Find the matching media using the mid field
                 let entry = self
@@ -613,14 +2339,105 @@ impl SdpSession {
                 // Try to find the correct media using the payload type
                 let entry = if let Some(entry) = entry {
                     Some(entry)
+                } else if let Some(index) = self
+                    .state
+                    .iter()
+                    .position(|m| m.transport == transport_id && m.is_negotiated_pt(packet.pt))
+                {
+                    Some(&mut self.state[index])
                 } else {
-                    self.state
+                    // Without bundling the packet belongs to the only media using the transport, even if the
+                    // payload type doesn't match
+                    let mut media = self
+                        .state
                         .iter_mut()
-                        .filter(|m| m.transport == transport_id)
-                        .find(|e| e.codec_pt == packet.pt)
+                        .filter(|m| m.transport == transport_id);
+
+                    media.next().filter(|_| media.next().is_none())
                 };
 
                 if let Some(entry) = entry {
+                    if !entry.direction.recv {
+                        entry.unexpected_direction_packets += 1;
+
+                        match entry.receive_direction_enforcement {
+                            ReceiveDirectionEnforcement::Deliver => {}
+                            ReceiveDirectionEnforcement::Drop => return,
+                            ReceiveDirectionEnforcement::Report => {
+                                self.events.push_back(Event::UnexpectedDirectionRtp(
+                                    UnexpectedDirectionRtpReceived {
+                                        media_id: entry.id,
+                                        direction: entry.direction.into(),
+                                        packet,
+                                    },
+                                ));
+                                return;
+                            }
+                        }
+                    }
+
+                    // Retransmitted packets are handled like the original
+                    let packet = match &entry.rtx {
+                        Some(rtx) if rtx.negotiated.recv_pt == packet.pt => {
+                            let original =
+                                entry.rtp_session.remote_ssrc().next().and_then(|ssrc| {
+                                    ::rtp::rtx_decode(&packet, entry.codec_pt, ssrc)
+                                });
+
+                            let Some(original) = original else {
+                                log::debug!("Dropping RTX packet without a known original stream");
+                                return;
+                            };
+
+                            original
+                        }
+                        _ => packet,
+                    };
+
+                    if let Some(tap) = &mut entry.rtp_tap {
+                        tap.tap(TappedRtpPacket {
+                            media_id: entry.id,
+                            direction: TapDirection::Inbound,
+                            timestamp: Instant::now(),
+                            packet: packet.clone(),
+                        });
+                    }
+
+                    // Only track the sequence numbers of the media stream itself, not e.g. bandwidth probes
+                    let is_media_stream = entry
+                        .rtp_session
+                        .remote_ssrc()
+                        .next()
+                        .is_none_or(|ssrc| ssrc == packet.ssrc);
+
+                    if let Some(rtx) = entry.rtx.as_mut().filter(|_| is_media_stream) {
+                        rtx.nack_generator
+                            .receive(packet.sequence_number, Instant::now());
+                    }
+
+                    if let Some(remb) = entry.remb.as_mut().filter(|_| is_media_stream) {
+                        remb.receive(Instant::now(), packet.timestamp, packet.payload.len());
+                    }
+
+                    if !entry.is_negotiated_pt(packet.pt)
+                        && !entry.receive_unexpected_payload_type(
+                            packet.pt,
+                            &self.local_media,
+                            &mut self.pending_changes,
+                            &mut self.events,
+                        )
+                    {
+                        return;
+                    }
+
+                    if let Some((monitor, level)) = entry
+                        .audio_level
+                        .as_mut()
+                        .zip(packet.extensions.audio_level)
+                    {
+                        monitor.meter.process_audio_level(level);
+                    }
+
                     entry.rtp_session.recv_rtp(packet);
                 } else {
                     log::warn!("Failed to find media for RTP packet ssrc={:?}", packet.ssrc);
@@ -648,49 +2465,61 @@ impl SdpSession {
                     return;
                 }
 
-                // Find out what kind of rtcp packet this is
-                let ssrc = match &packets[0] {
-                    RtcpPacket::App(..) => {
-                        // ignore
-                        log::debug!("ignoring app RTCP packet");
-                        return;
-                    }
-                    RtcpPacket::Bye(..) => {
-                        // TODO: implement bye handling
-                        log::warn!("ignoring BYE RTCP packet");
-                        return;
-                    }
-                    RtcpPacket::Rr(receiver_report) => receiver_report.ssrc(),
-                    RtcpPacket::Sdes(..) => {
-                        // what
-                        log::debug!("ignoring invalid RTCP packet");
-                        return;
-                    }
-                    RtcpPacket::Sr(sender_report) => sender_report.ssrc(),
-                    RtcpPacket::TransportFeedback(transport_feedback) => {
-                        transport_feedback.sender_ssrc()
+                // Hand the report blocks about bandwidth probes to their prober
+                let report_blocks = packets.iter().flat_map(|packet| match packet {
+                    RtcpPacket::Rr(receiver_report) => receiver_report.report_blocks().collect(),
+                    RtcpPacket::Sr(sender_report) => sender_report.report_blocks().collect(),
+                    _ => vec![],
+                });
+
+                for report_block in report_blocks {
+                    self.report_loss(
+                        report_block.ssrc(),
+                        f32::from(report_block.fraction_lost()) / 256.0,
+                    );
+                    self.report_quality(&report_block);
+
+                    let prober = self
+                        .state
+                        .iter_mut()
+                        .filter_map(|m| m.bandwidth_prober.as_mut())
+                        .find(|prober| prober.ssrc().0 == report_block.ssrc());
+
+                    if let Some(prober) = prober {
+                        prober.receive_report(
+                            report_block.extended_sequence_number(),
+                            report_block.cumulative_lost(),
+                        );
                     }
-                    RtcpPacket::PayloadFeedback(payload_feedback) => payload_feedback.sender_ssrc(),
-                    RtcpPacket::Unknown(..) => {
-                        log::debug!("ignoring unknown RTCP packet");
-                        return;
+                }
+
+                if let Some(remb) = Remb::find_in_compound(&pkt_data) {
+                    self.receive_remb(transport_id, remb);
+                }
+
+                for packet in &packets {
+                    match packet {
+                        RtcpPacket::PayloadFeedback(feedback) => {
+                            self.receive_keyframe_request(feedback)
+                        }
+                        RtcpPacket::TransportFeedback(feedback) => self.receive_nack(feedback),
+                        _ => {}
                     }
-                };
+                }
 
-                let media = self
-                    .state
-                    .iter_mut()
-                    .find(|e| e.rtp_session.remote_ssrc().any(|r_ssrc| r_ssrc.0 == ssrc));
+                let bye_ssrcs: Vec<Ssrc> = packets
+                    .iter()
+                    .filter_map(|packet| match packet {
+                        RtcpPacket::Bye(bye) => Some(bye.ssrcs().map(Ssrc).collect::<Vec<_>>()),
+                        _ => None,
+                    })
+                    .flatten()
+                    .collect();
 
-                let Some(media) = media else {
-                    log::warn!("Failed to find media for incoming RTCP packet");
-                    return;
-                };
+                self.forward_rtcp(packets);
 
-                for packet in packets {
-                    // TODO: handle the RTCP packets properly
-                    media.rtp_session.recv_rtcp(packet);
-                }
+                // End the streams only after the reports preceding the BYE have been handled
+                self.receive_bye(bye_ssrcs);
             }
             ReceivedPacket::TransportSpecific => {
                 // ignore
@@ -698,8 +2527,64 @@ impl SdpSession {
         }
     }
 
-    pub fn send_rtp(&mut self, media_id: MediaId, mut packet: RtpPacket) {
+    /// Hand the RTCP packets to the RTP session of the media they belong to
+    fn forward_rtcp(&mut self, packets: Vec<RtcpPacket<'_>>) {
+        // Find out what kind of rtcp packet this is
+        let ssrc = match &packets[0] {
+            RtcpPacket::App(..) => {
+                // ignore
+                log::debug!("ignoring app RTCP packet");
+                return;
+            }
+            RtcpPacket::Bye(..) => {
+                // handled by receive_bye
+                return;
+            }
+            RtcpPacket::Rr(receiver_report) => receiver_report.ssrc(),
+            RtcpPacket::Sdes(..) => {
+                // what
+                log::debug!("ignoring invalid RTCP packet");
+                return;
+            }
+            RtcpPacket::Sr(sender_report) => sender_report.ssrc(),
+            RtcpPacket::TransportFeedback(transport_feedback) => transport_feedback.sender_ssrc(),
+            RtcpPacket::PayloadFeedback(payload_feedback) => payload_feedback.sender_ssrc(),
+            RtcpPacket::Unknown(..) => {
+                log::debug!("ignoring unknown RTCP packet");
+                return;
+            }
+        };
+
+        let media = self
+            .state
+            .iter_mut()
+            .find(|e| e.rtp_session.remote_ssrc().any(|r_ssrc| r_ssrc.0 == ssrc));
+
+        let Some(media) = media else {
+            log::warn!("Failed to find media for incoming RTCP packet");
+            return;
+        };
+
+        for packet in packets {
+            // TODO: handle the RTCP packets properly
+            media.rtp_session.recv_rtcp(packet);
+        }
+    }
+
+    /// Send a RTP packet of the media
+    ///
+    /// To allow the peer to measure the end-to-end latency set `packet.extensions.abs_capture_time` to the time
+    /// the frame was captured, it is only sent if the header extension was negotiated.
+    ///
+    /// Packets are dropped while the peer put the media on hold, see [`MediaChanged::hold`](events::MediaChanged::hold).
+    /// Returns `false` if the packet was dropped.
+    pub fn send_rtp(&mut self, media_id: MediaId, mut packet: RtpPacket) -> bool {
         let media = self.state.iter_mut().find(|m| m.id == media_id).unwrap();
+
+        if media.remote_hold {
+            return false;
+        }
+
         let transport = self.transports[media.transport].unwrap_mut();
 
         packet.ssrc = media.rtp_session.ssrc();
@@ -708,7 +2593,116 @@ impl SdpSession {
         // Tell the RTP session that a packet is being sent
         media.rtp_session.send_rtp(&packet);
 
+        if let Some(rtx) = &mut media.rtx {
+            rtx.sender.push_sent(&packet);
+        }
+
+        if let Some(tap) = &mut media.rtp_tap {
+            tap.tap(TappedRtpPacket {
+                media_id,
+                direction: TapDirection::Outbound,
+                timestamp: Instant::now(),
+                packet: packet.clone(),
+            });
+        }
+
         transport.send_rtp(packet);
+
+        true
+    }
+
+    /// Send a DTMF digit (telephone-event `0-15`) using the negotiated telephone-event payload type (RFC 4733)
+    ///
+    /// The payload type and payload of the packet are set from `event`. The sequence number and timestamp must be
+    /// set like for [`send_rtp`](Self::send_rtp), all packets of an event share the timestamp of its start.
+    ///
+    /// Events the peer didn't announce in its telephone-event fmtp are refused, the flash-hook signal must be sent
+    /// using [`send_flash_hook`](Self::send_flash_hook).
+    pub fn send_dtmf(
+        &mut self,
+        media_id: MediaId,
+        packet: RtpPacket,
+        event: TelephoneEvent,
+    ) -> Result<(), DtmfError> {
+        if event.event > 15 {
+            return Err(DtmfError::NotADigit(event.event));
+        }
+
+        self.send_telephone_event(media_id, packet, event)
+    }
+
+    /// Create a [`DtmfSender`] generating all packets of a DTMF digit (telephone-event `0-15`) of the given duration
+    ///
+    /// The sender uses the negotiated telephone-event payload type and clock rate. Once
+    /// [started](DtmfSender::start) with the current timestamp of the media, the packets it returns must be given
+    /// a sequence number and sent using [`send_rtp`](Self::send_rtp).
+    pub fn dtmf_sender(
+        &self,
+        media_id: MediaId,
+        event: u8,
+        duration: Duration,
+    ) -> Result<DtmfSender, DtmfError> {
+        if event > 15 {
+            return Err(DtmfError::NotADigit(event));
+        }
+
+        let media = self.state.iter().find(|m| m.id == media_id).unwrap();
+
+        let dtmf = media.dtmf.as_ref().ok_or(DtmfError::NotNegotiated)?;
+
+        if !dtmf.events.contains(event) {
+            return Err(DtmfError::Unsupported(event));
+        }
+
+        Ok(DtmfSender::new(
+            dtmf.send_pt,
+            dtmf.clock_rate,
+            event,
+            duration,
+        ))
+    }
+
+    /// Send the flash-hook signal (telephone-event 16), used by PBXs e.g. to start a transfer
+    ///
+    /// Works like [`send_dtmf`](Self::send_dtmf), `duration` is in units of the telephone-event's clock rate.
+    /// Refused if the peer didn't announce support for the flash-hook event.
+    pub fn send_flash_hook(
+        &mut self,
+        media_id: MediaId,
+        packet: RtpPacket,
+        duration: u16,
+        end: bool,
+    ) -> Result<(), DtmfError> {
+        let event = TelephoneEvent {
+            event: FLASH_HOOK,
+            end,
+            volume: 0,
+            duration,
+        };
+
+        self.send_telephone_event(media_id, packet, event)
+    }
+
+    fn send_telephone_event(
+        &mut self,
+        media_id: MediaId,
+        mut packet: RtpPacket,
+        event: TelephoneEvent,
+    ) -> Result<(), DtmfError> {
+        let media = self.state.iter().find(|m| m.id == media_id).unwrap();
+
+        let dtmf = media.dtmf.as_ref().ok_or(DtmfError::NotNegotiated)?;
+
+        if !dtmf.events.contains(event.event) {
+            return Err(DtmfError::Unsupported(event.event));
+        }
+
+        packet.pt = dtmf.send_pt;
+        packet.payload = event.to_bytes();
+
+        self.send_rtp(media_id, packet);
+
+        Ok(())
     }
 
     /// Returns the cumulative gathering state of all ice agents
@@ -745,6 +2739,46 @@ fn send_rtcp_report(transport: &mut Transport, media: &mut ActiveMedia) {
     transport.send_rtcp(encode_buf);
 }
 
+/// Request the missing packets from the peer using a generic NACK
+fn send_nack(transport: &mut Transport, rtp_session: &RtpSession, missing: &[SequenceNumber]) {
+    let Some(remote_ssrc) = rtp_session.remote_ssrc().next() else {
+        return;
+    };
+
+    let nack = missing
+        .iter()
+        .fold(Nack::builder(), |nack, sequence_number| {
+            nack.add_rtp_sequence(sequence_number.0)
+        });
+
+    let feedback = TransportFeedback::builder_owned(nack)
+        .sender_ssrc(rtp_session.ssrc().0)
+        .media_ssrc(remote_ssrc.0);
+
+    let mut encode_buf = vec![0u8; 1500];
+
+    match feedback.write_into(&mut encode_buf) {
+        Ok(len) => {
+            encode_buf.truncate(len);
+            transport.send_rtcp(encode_buf);
+        }
+        Err(e) => log::warn!("Failed to write RTCP NACK packet, {e:?}"),
+    }
+}
+
+fn send_remb(transport: &mut Transport, rtp_session: &RtpSession, bitrate: u32) {
+    let remb = Remb {
+        bitrate: u64::from(bitrate),
+        ssrcs: rtp_session.remote_ssrc().map(|ssrc| ssrc.0).collect(),
+    };
+
+    if remb.ssrcs.is_empty() {
+        return;
+    }
+
+    transport.send_rtcp(remb.to_vec(rtp_session.ssrc().0));
+}
+
 // i'm too lazy to work with the direction type, so using this as a cop out
 #[derive(Debug, Clone, Copy, PartialEq)]
 struct DirectionBools {
@@ -776,6 +2810,41 @@ impl From<Direction> for DirectionBools {
     }
 }
 
+/// Handle a received telephone-event packet of a DTMF digit, returns `false` if the packet is something else
+fn receive_dtmf(media: &mut ActiveMedia, packet: &RtpPacket, events: &mut VecDeque<Event>) -> bool {
+    let Some(dtmf) = &media.dtmf else {
+        return false;
+    };
+
+    if packet.pt != dtmf.recv_pt {
+        return false;
+    }
+
+    let Some(event) = TelephoneEvent::parse(&packet.payload) else {
+        return false;
+    };
+
+    if event.digit().is_none() {
+        return false;
+    }
+
+    for event in media.dtmf_receiver.receive(packet.timestamp, event) {
+        let Some(digit) = event.digit() else {
+            continue;
+        };
+
+        events.push_back(Event::DtmfReceived(DtmfReceived {
+            media_id: media.id,
+            digit,
+            duration: Duration::from_secs_f64(
+                f64::from(event.duration) / f64::from(dtmf.clock_rate),
+            ),
+        }));
+    }
+
+    true
+}
+
 fn opt_min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
     match (a, b) {
         (None, None) => None,
@@ -1,8 +1,13 @@
-use crate::{Codec, Codecs, DirectionBools};
+use crate::{Codec, Codecs, DirectionBools, NegotiatedDtmf, NegotiatedRtx};
+use rtp::TelephoneEvents;
 use sdp_types::{Direction, MediaDescription};
 
 pub(super) struct LocalMedia {
     pub(super) codecs: Codecs,
+    /// Telephone-event payload types offered if DTMF is allowed, one for each clock rate of the codecs
+    pub(super) dtmf: Vec<Codec>,
+    /// Retransmission payload types offered if RTX is allowed, one for each codec
+    pub(super) rtx: Vec<Codec>,
     pub(super) limit: u32,
     pub(super) direction: DirectionBools,
     pub(super) use_count: u32,
@@ -82,4 +87,85 @@ impl LocalMedia {
 
         None
     }
+
+    /// Find the peer's telephone-event payload type matching the clock rate of the chosen codec
+    pub(super) fn choose_dtmf(
+        &self,
+        codec: &Codec,
+        desc: &MediaDescription,
+    ) -> Option<NegotiatedDtmf> {
+        if self.dtmf.is_empty() {
+            return None;
+        }
+
+        let rtpmap = desc.rtpmap.iter().find(|rtpmap| {
+            rtpmap.encoding.eq_ignore_ascii_case("telephone-event")
+                && rtpmap.clock_rate == codec.clock_rate
+                && desc.media.fmts.contains(&rtpmap.payload)
+        })?;
+
+        // Without fmtp the peer supports the DTMF digits (RFC 4733 section 2.4.1)
+        let events = match desc.fmtp.iter().find(|f| f.format == rtpmap.payload) {
+            Some(fmtp) => TelephoneEvents::parse(&fmtp.params).unwrap_or_else(|| {
+                log::warn!(
+                    "Invalid telephone-event fmtp {:?}, assuming DTMF digits only",
+                    fmtp.params
+                );
+                TelephoneEvents::default()
+            }),
+            None => TelephoneEvents::default(),
+        };
+
+        Some(NegotiatedDtmf {
+            send_pt: rtpmap.payload,
+            recv_pt: rtpmap.payload,
+            clock_rate: codec.clock_rate,
+            events,
+        })
+    }
+
+    /// Find the peer's retransmission payload type for the chosen codec, only used if the peer also supports
+    /// generic NACKs for the codec
+    pub(super) fn choose_rtx(
+        &self,
+        codec_pt: u8,
+        desc: &MediaDescription,
+    ) -> Option<NegotiatedRtx> {
+        if self.rtx.is_empty() {
+            return None;
+        }
+
+        let nack = desc.rtcp_fb.iter().any(|rtcp_fb| {
+            rtcp_fb.applies_to(codec_pt) && rtcp_fb.kind == "nack" && rtcp_fb.param.is_none()
+        });
+
+        if !nack {
+            return None;
+        }
+
+        let apt = format!("apt={codec_pt}");
+
+        let rtpmap = desc.rtpmap.iter().find(|rtpmap| {
+            rtpmap.encoding.eq_ignore_ascii_case("rtx")
+                && desc.media.fmts.contains(&rtpmap.payload)
+                && desc.fmtp.iter().any(|fmtp| {
+                    fmtp.format == rtpmap.payload
+                        && fmtp.params.split(';').any(|param| param.trim() == apt)
+                })
+        })?;
+
+        Some(NegotiatedRtx {
+            send_pt: rtpmap.payload,
+            recv_pt: rtpmap.payload,
+        })
+    }
+
+    /// Returns if both sides support REMB feedback for the chosen codec
+    pub(super) fn choose_remb(&self, codec_pt: u8, desc: &MediaDescription) -> bool {
+        self.codecs.allow_remb
+            && desc
+                .rtcp_fb
+                .iter()
+                .any(|rtcp_fb| rtcp_fb.applies_to(codec_pt) && rtcp_fb.kind == "goog-remb")
+    }
 }
@@ -1,4 +1,7 @@
-use sdp_types::TransportProtocol;
+use crate::{DtlsCertificate, SrtpBackend};
+use sdp_types::{Fingerprint, FingerprintAlgorithm, TransportProtocol};
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
 
 #[derive(Debug, Default, Clone)]
 pub struct Options {
@@ -6,12 +9,135 @@ pub struct Options {
     pub offer_transport: TransportType,
     /// Use ICE when making an offer
     pub offer_ice: bool,
+    /// Signal ICE candidates incrementally using trickle ICE (RFC 8838)
+    ///
+    /// SDP offers and answers include `a=ice-options:trickle` and are created without waiting for all candidates to
+    /// be gathered. Gathered candidates are reported using [`Event::NewLocalCandidate`](crate::Event::NewLocalCandidate),
+    /// candidates the peer trickled are added using
+    /// [`SdpSession::add_remote_candidate`](crate::SdpSession::add_remote_candidate).
+    pub trickle_ice: bool,
     /// Offer the extended RTP profile for RTCP-based feedback
     pub offer_avpf: bool,
     /// Policy when negotiating RTP & RTCP multiplexing over the same UDP socket
     pub rtcp_mux_policy: RtcpMuxPolicy,
     /// Policy to use when offering bundled media over a single transport
     pub bundle_policy: BundlePolicy,
+    /// Minimize the size of generated SDP by omitting redundant attributes
+    ///
+    /// Removes rtpmap attributes for static payload types, rtpmap & fmtp attributes of unused formats
+    /// and rtcp attributes which only repeat the default port. Locally added media is identified using short
+    /// mids. Useful to keep SIP messages small when offering many codecs.
+    pub compact_sdp: bool,
+    /// Static NAT mappings of local media addresses to public ones
+    ///
+    /// The public address of the session's local address is used in the generated SDP. Mapped addresses are
+    /// also added as server reflexive ICE candidates. Useful when running behind a static NAT without STUN.
+    pub address_mappings: Vec<AddressMapping>,
+    /// SRTP protection profiles offered in the DTLS handshake of DTLS-SRTP transports, in order of preference
+    ///
+    /// All supported profiles are offered if empty. The negotiated profile is reported with
+    /// [`Event::TransportConnectionState`](crate::Event::TransportConnectionState) once the transport is connected.
+    pub dtls_srtp_profiles: Vec<SrtpProfile>,
+    /// Number of recently sent RTP packets kept per media to answer the peer's NACKs, if RTX is negotiated
+    ///
+    /// Uses [`DEFAULT_RTX_BUFFER_SIZE`](::rtp::DEFAULT_RTX_BUFFER_SIZE) if not set.
+    pub rtx_buffer_size: Option<usize>,
+    /// Hash algorithms of the local DTLS certificate fingerprints included in SDP, one fingerprint attribute is
+    /// added per algorithm
+    ///
+    /// Only SHA-256 is used if empty, unsupported algorithms are ignored. The remote certificate is always verified
+    /// using the strongest supported algorithm the peer included a fingerprint of (RFC 8122 Section 5).
+    pub dtls_fingerprint_algorithms: Vec<FingerprintAlgorithm>,
+    /// Certificate used in the DTLS handshake of DTLS-SRTP transports
+    ///
+    /// A self-signed certificate is generated if not set.
+    pub dtls_certificate: Option<DtlsCertificate>,
+    /// Fingerprints the peer's DTLS certificate must match in addition to the fingerprints of its SDP
+    ///
+    /// Not checked if empty. A certificate matching none of them fails the transport and emits
+    /// [`Event::FingerprintMismatch`](crate::Event::FingerprintMismatch).
+    pub dtls_pinned_fingerprints: Vec<Fingerprint>,
+    /// Implementation used to protect SDES-SRTP & DTLS-SRTP media, available backends depend on the enabled
+    /// cargo features
+    pub srtp_backend: SrtpBackend,
+}
+
+impl Options {
+    /// Options for calls inside a trusted local network without NAT, e.g. intercoms or a local test PBX
+    ///
+    /// Offers plain RTP without ICE or RTCP feedback, which is supported by virtually every SIP user agent.
+    pub fn lan() -> Self {
+        Self {
+            offer_transport: TransportType::Rtp,
+            offer_ice: false,
+            offer_avpf: false,
+            ..Self::default()
+        }
+    }
+
+    /// Options for receive-only endpoints handling many concurrent sessions, e.g. announcement players or
+    /// recording servers
+    ///
+    /// Offers plain RTP without ICE or RTCP feedback and keeps the generated SDP compact. Offers are answered
+    /// with whatever transport the peer requested. Local media should be added using [`Direction::RecvOnly`].
+    ///
+    /// [`Direction::RecvOnly`]: crate::Direction::RecvOnly
+    pub fn receive_only() -> Self {
+        Self {
+            compact_sdp: true,
+            ..Self::lan()
+        }
+    }
+}
+
+/// Mapping of a local IP address and port range to a public one, see [`Options::address_mappings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressMapping {
+    pub local_ip: IpAddr,
+    pub local_ports: RangeInclusive<u16>,
+
+    pub public_ip: IpAddr,
+    /// Public port of the first port in `local_ports`, the rest of the range is mapped consecutively
+    pub public_ports_start: u16,
+}
+
+impl AddressMapping {
+    /// Map all ports of the local IP address to the same ports of the public IP address
+    pub fn new(local_ip: IpAddr, public_ip: IpAddr) -> Self {
+        Self {
+            local_ip,
+            local_ports: 0..=u16::MAX,
+            public_ip,
+            public_ports_start: 0,
+        }
+    }
+
+    /// Map the local port range to the public port range starting at `public_ports_start`
+    pub fn with_ports(
+        local_ip: IpAddr,
+        local_ports: RangeInclusive<u16>,
+        public_ip: IpAddr,
+        public_ports_start: u16,
+    ) -> Self {
+        Self {
+            local_ip,
+            local_ports,
+            public_ip,
+            public_ports_start,
+        }
+    }
+
+    /// Returns the public address of the local address, if it's covered by this mapping
+    pub fn map(&self, local: SocketAddr) -> Option<SocketAddr> {
+        if local.ip() != self.local_ip || !self.local_ports.contains(&local.port()) {
+            return None;
+        }
+
+        let offset = local.port() - self.local_ports.start();
+        let port = self.public_ports_start.checked_add(offset)?;
+
+        Some(SocketAddr::new(self.public_ip, port))
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -43,6 +169,39 @@ impl TransportType {
     }
 }
 
+/// SRTP protection profile negotiated using DTLS-SRTP (RFC 5764, RFC 7714)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SrtpProfile {
+    Aes128CmSha1_80,
+    Aes128CmSha1_32,
+    AeadAes128Gcm,
+    AeadAes256Gcm,
+}
+
+impl SrtpProfile {
+    /// All profiles in the default order of preference
+    pub(crate) const ALL: [Self; 4] = [
+        Self::Aes128CmSha1_80,
+        Self::Aes128CmSha1_32,
+        Self::AeadAes128Gcm,
+        Self::AeadAes256Gcm,
+    ];
+
+    /// Name of the profile as used by OpenSSL
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Aes128CmSha1_80 => "SRTP_AES128_CM_SHA1_80",
+            Self::Aes128CmSha1_32 => "SRTP_AES128_CM_SHA1_32",
+            Self::AeadAes128Gcm => "SRTP_AEAD_AES_128_GCM",
+            Self::AeadAes256Gcm => "SRTP_AEAD_AES_256_GCM",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|profile| profile.name() == name)
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum RtcpMuxPolicy {
     /// Offer multiplexing RTCP on the RTP port,
@@ -50,6 +209,9 @@ pub enum RtcpMuxPolicy {
     #[default]
     Negotiate,
     /// Require RTCP muxing, fail if the peer doesn't support it.
+    ///
+    /// Offers `a=rtcp-mux-only` ([RFC8858](https://www.rfc-editor.org/rfc/rfc8858.html)), no separate RTCP
+    /// socket is ever requested.
     Require,
 }
 
@@ -3,6 +3,9 @@ use rtp::RtpExtensionIds;
 use sdp_types::{Direction, ExtMap, MediaDescription, SessionDescription};
 
 const RTP_MID_HDREXT: &str = "urn:ietf:params:rtp-hdrext:sdes:mid";
+const RTP_AUDIO_LEVEL_HDREXT: &str = "urn:ietf:params:rtp-hdrext:ssrc-audio-level";
+const RTP_ABS_CAPTURE_TIME_HDREXT: &str =
+    "http://www.webrtc.org/experiments/rtp-hdrext/abs-capture-time";
 
 pub(crate) trait RtpExtensionIdsExt {
     fn offer() -> Self;
@@ -12,7 +15,11 @@ pub(crate) trait RtpExtensionIdsExt {
 
 impl RtpExtensionIdsExt for RtpExtensionIds {
     fn offer() -> Self {
-        RtpExtensionIds { mid: Some(1) }
+        RtpExtensionIds {
+            mid: Some(1),
+            audio_level: Some(2),
+            abs_capture_time: Some(3),
+        }
     }
 
     fn from_sdp(session_desc: &SessionDescription, media_desc: &MediaDescription) -> Self {
@@ -22,6 +29,14 @@ impl RtpExtensionIdsExt for RtpExtensionIds {
                     .iter()
                     .find(|extmap| extmap.uri == RTP_MID_HDREXT)
                     .map(|extmap| extmap.id),
+                audio_level: v
+                    .iter()
+                    .find(|extmap| extmap.uri == RTP_AUDIO_LEVEL_HDREXT)
+                    .map(|extmap| extmap.id),
+                abs_capture_time: v
+                    .iter()
+                    .find(|extmap| extmap.uri == RTP_ABS_CAPTURE_TIME_HDREXT)
+                    .map(|extmap| extmap.id),
             }
         }
 
@@ -30,6 +45,8 @@ impl RtpExtensionIdsExt for RtpExtensionIds {
 
         Self {
             mid: b.mid.or(a.mid),
+            audio_level: b.audio_level.or(a.audio_level),
+            abs_capture_time: b.abs_capture_time.or(a.abs_capture_time),
         }
     }
 
@@ -44,6 +61,22 @@ impl RtpExtensionIdsExt for RtpExtensionIds {
             });
         }
 
+        if let Some(audio_level_id) = self.audio_level {
+            extmap.push(ExtMap {
+                id: audio_level_id,
+                uri: BytesStr::from_static(RTP_AUDIO_LEVEL_HDREXT),
+                direction: Direction::SendRecv,
+            });
+        }
+
+        if let Some(abs_capture_time_id) = self.abs_capture_time {
+            extmap.push(ExtMap {
+                id: abs_capture_time_id,
+                uri: BytesStr::from_static(RTP_ABS_CAPTURE_TIME_HDREXT),
+                direction: Direction::SendRecv,
+            });
+        }
+
         extmap
     }
 }
@@ -1,19 +1,24 @@
 use crate::codecs::NegotiatedCodec;
-use crate::events::{MediaAdded, MediaChanged, TransportChange, TransportRequiredChanges};
-use crate::transport::{Transport, TransportBuilder};
+use crate::events::{
+    CodecChanged, MediaAdded, MediaChanged, SrtpRekeyed, TransportChange, TransportRequiredChanges,
+};
+use crate::transport::{SdesSrtpRekey, Transport, TransportBuilder};
 use crate::{
-    ActiveMedia, DirectionBools, Error, Event, MediaId, PendingChange, SdpSession, TransportEntry,
-    TransportId,
+    ActiveMedia, Codec, DirectionBools, Error, Event, KeyframeRecovery, MediaId, PendingChange,
+    ReceiveDirectionEnforcement, Retransmission, RtcpMuxPolicy, SdpSession, TransportEntry,
+    TransportId, UnexpectedPayloadTypePolicy,
 };
 use bytesstr::BytesStr;
-use rtp::{RtpSession, Ssrc};
+use rtp::{DtmfReceiver, GccConfig, PromptPlayer, RembEstimator, RtpSession, Ssrc};
 use sdp_types::{
     Connection, Direction, Fmtp, Group, IceOptions, IcePassword, IceUsernameFragment, Media,
-    MediaDescription, MediaType, Origin, Rtcp, RtpMap, SessionDescription, Time, TransportProtocol,
+    MediaDescription, MediaType, Origin, Rtcp, RtcpFeedback, RtpMap, SessionDescription,
+    TaggedAddress, Time, TransportProtocol,
 };
 use std::{
     collections::HashMap,
-    mem::replace,
+    mem::{replace, take},
+    net::IpAddr,
     time::{Duration, Instant},
 };
 
@@ -39,13 +44,20 @@ impl SdpSession {
     /// The actual answer can be created using [`create_sdp_answer`](Self::create_sdp_answer).
     pub fn receive_sdp_offer(
         &mut self,
-        offer: SessionDescription,
+        mut offer: SessionDescription,
     ) -> Result<SdpAnswerState, Error> {
+        // rtcp-mux-only implies rtcp-mux, even if the offerer didn't include both as required by RFC 8858
+        for remote_media_desc in &mut offer.media_descriptions {
+            remote_media_desc.rtcp_mux |= remote_media_desc.rtcp_mux_only;
+        }
+
         let mut new_state = vec![];
         let mut response = vec![];
+        // Transports replaced by a new one in this offer, shared by all media bundled on them
+        let mut migrated = vec![];
 
         for (mline, remote_media_desc) in offer.media_descriptions.iter().enumerate() {
-            let requested_direction: DirectionBools = remote_media_desc.direction.flipped().into();
+            let (requested_direction, hold) = requested_direction(&offer, remote_media_desc);
 
             // First thing: Search the current state for an entry that matches this description - and update accordingly
             let matched_position = self
@@ -54,8 +66,40 @@ impl SdpSession {
                 .position(|media| media.matches(&self.transports, remote_media_desc));
 
             if let Some(position) = matched_position {
-                self.update_active_media(requested_direction, self.state[position].id);
-                let media = self.state.remove(position);
+                self.update_active_media(requested_direction, hold, self.state[position].id);
+                self.follow_offered_codec(remote_media_desc, self.state[position].id);
+                let mut media = self.state.remove(position);
+                self.migrate_transport_from_offer(
+                    &mut migrated,
+                    &offer,
+                    remote_media_desc,
+                    &mut media,
+                )?;
+
+                // RTCP can't be multiplexed on a transport which was negotiated with a separate RTCP port, the
+                // m-line must be rejected if the peer requires it (RFC 8858)
+                let transport = media.migrate_to.unwrap_or(media.transport);
+
+                if remote_media_desc.rtcp_mux_only && !self.transports[transport].rtcp_mux() {
+                    self.local_media[media.local_media_id].use_count -= 1;
+                    self.events.push_back(Event::MediaRemoved(media.id));
+
+                    response.push(SdpResponseEntry::Rejected {
+                        media_type: remote_media_desc.media.media_type,
+                        mid: remote_media_desc.mid.clone(),
+                    });
+
+                    log::debug!(
+                        "Rejecting mline={mline}, rtcp-mux-only on a transport without rtcp-mux"
+                    );
+                    continue;
+                }
+
+                // A transport which is being replaced gets its keys from the new one
+                if media.migrate_to.is_none() {
+                    self.receive_srtp_offer(media.transport, remote_media_desc)?;
+                }
+
                 response.push(SdpResponseEntry::Active(media.id));
                 new_state.push(media);
                 continue;
@@ -82,11 +126,16 @@ impl SdpSession {
 
             let media_id = self.next_media_id.step();
 
+            let dtmf = self.local_media[local_media_id].choose_dtmf(&codec, remote_media_desc);
+            let rtx = self.local_media[local_media_id].choose_rtx(codec_pt, remote_media_desc);
+            let remb = self.local_media[local_media_id].choose_remb(codec_pt, remote_media_desc);
+
             // Get or create transport for the m-line
             let transport = self.get_or_create_transport(&new_state, &offer, remote_media_desc)?;
 
             let Some(transport) = transport else {
                 // No transport was found or created, reject media
+                self.local_media[local_media_id].use_count -= 1;
                 response.push(SdpResponseEntry::Rejected {
                     media_type: remote_media_desc.media.media_type,
                     mid: remote_media_desc.mid.clone(),
@@ -96,6 +145,20 @@ impl SdpSession {
                 continue;
             };
 
+            // A BUNDLE group may put the media on a transport negotiated with a separate RTCP port
+            if remote_media_desc.rtcp_mux_only && !self.transports[transport].rtcp_mux() {
+                self.local_media[local_media_id].use_count -= 1;
+                response.push(SdpResponseEntry::Rejected {
+                    media_type: remote_media_desc.media.media_type,
+                    mid: remote_media_desc.mid.clone(),
+                });
+
+                log::debug!(
+                    "Rejecting mline={mline}, rtcp-mux-only on a transport without rtcp-mux"
+                );
+                continue;
+            }
+
             let recv_fmtp = remote_media_desc
                 .fmtp
                 .iter()
@@ -116,6 +179,8 @@ impl SdpSession {
                     send_fmtp: codec.fmtp.clone(),
                     recv_fmtp,
                 },
+                dtmf: dtmf.clone(),
+                content: remote_media_desc.content.clone(),
             }));
 
             response.push(SdpResponseEntry::Active(media_id));
@@ -123,12 +188,42 @@ impl SdpSession {
                 id: media_id,
                 local_media_id,
                 media_type: remote_media_desc.media.media_type,
-                rtp_session: RtpSession::new(Ssrc(rand::random()), codec.clock_rate),
+                rtp_session: RtpSession::new(Ssrc(rand::random()), codec.clock_rate)
+                    .with_jitter_buffer_config(
+                        self.local_media[local_media_id].codecs.jitter_buffer,
+                    ),
                 avpf: is_avpf(&remote_media_desc.media.proto),
                 next_rtcp: Instant::now() + Duration::from_secs(5),
                 rtcp_interval: rtcp_interval(remote_media_desc.media.media_type),
                 mid: remote_media_desc.mid.clone(),
+                content: remote_media_desc.content.clone(),
                 direction: negotiated_direction,
+                restore_direction: None,
+                remote_hold: hold,
+                audio_level: None,
+                tone_detector: None,
+                dtmf_receiver: DtmfReceiver::new(),
+                rtx: rtx.map(|rtx| Retransmission::new(rtx, self.options.rtx_buffer_size)),
+                remb: remb.then(|| RembEstimator::new(codec.clock_rate, GccConfig::default())),
+                call_progress: None,
+                prompt_player: PromptPlayer::new(),
+                answering_machine: None,
+                rtp_tap: None,
+                bandwidth_prober: None,
+                bitrate_allocation: None,
+                target_bitrate: None,
+                keyframe_recovery: KeyframeRecovery::default(),
+                last_fir_sequence: None,
+                reference_acked: false,
+                codec_downshift: None,
+                dtmf,
+                quality_monitor: None,
+                unexpected_pt_policy: UnexpectedPayloadTypePolicy::default(),
+                unexpected_pt_packets: 0,
+                reported_unexpected_pts: Vec::new(),
+                receive_direction_enforcement: ReceiveDirectionEnforcement::default(),
+                unexpected_direction_packets: 0,
+                migrate_to: None,
                 transport,
                 codec_pt,
                 codec,
@@ -145,22 +240,73 @@ impl SdpSession {
 
         self.remove_unused_transports();
 
+        // Transports which are connected right away (e.g. plain RTP without ICE) can be switched to immediately
+        self.complete_transport_migrations();
+
         Ok(SdpAnswerState(response))
     }
 
+    /// Create a new transport for the media if the peer moved it to a new transport (make-before-break)
+    ///
+    /// The media keeps using its current transport until the new one is connected.
+    fn migrate_transport_from_offer(
+        &mut self,
+        migrated: &mut Vec<(TransportId, TransportId)>,
+        offer: &SessionDescription,
+        remote_media_desc: &MediaDescription,
+        media: &mut ActiveMedia,
+    ) -> Result<(), Error> {
+        let current_transport_id = media.migrate_to.unwrap_or(media.transport);
+
+        if let Some((_, new_transport_id)) = migrated
+            .iter()
+            .find(|(old, _)| *old == current_transport_id)
+        {
+            media.migrate_to = Some(*new_transport_id);
+            return Ok(());
+        }
+
+        let TransportEntry::Transport(transport) = &self.transports[current_transport_id] else {
+            return Ok(());
+        };
+
+        if !transport.is_moved_by_offer(offer, remote_media_desc) {
+            return Ok(());
+        }
+
+        if let Some(new_transport_id) =
+            self.create_transport_from_offer(offer, remote_media_desc)?
+        {
+            migrated.push((current_transport_id, new_transport_id));
+            media.migrate_to = Some(new_transport_id);
+        } else {
+            log::warn!(
+                "Peer moved {:?} to an unsupported transport, keeping the current one",
+                media.id
+            );
+        }
+
+        Ok(())
+    }
+
     /// Remove all transports that are not being used anymore
-    fn remove_unused_transports(&mut self) {
+    pub(crate) fn remove_unused_transports(&mut self) {
         self.transports.retain(|id, _| {
-            // Is the transport in use by active media?
-            let in_use_by_active = self.state.iter().any(|media| media.transport == id);
+            // Is the transport in use by active media, or is media migrating to it?
+            let in_use_by_active = self
+                .state
+                .iter()
+                .any(|media| media.transport == id || media.migrate_to == Some(id));
 
             // Is the transport in use by any pending changes?
-            let in_use_by_pending = self.pending_changes.iter().any(|change| {
-                if let PendingChange::AddMedia(add_media) = change {
+            let in_use_by_pending = self.pending_changes.iter().any(|change| match change {
+                PendingChange::AddMedia(add_media) => {
                     add_media.bundle_transport == id || add_media.standalone_transport == Some(id)
-                } else {
-                    false
                 }
+                PendingChange::MigrateTransports(migration) => {
+                    migration.transports.iter().any(|(_, new)| *new == id)
+                }
+                _ => false,
             });
 
             if in_use_by_active || in_use_by_pending {
@@ -172,22 +318,136 @@ impl SdpSession {
         });
     }
 
-    fn update_active_media(&mut self, requested_direction: DirectionBools, media_id: MediaId) {
+    fn update_active_media(
+        &mut self,
+        requested_direction: DirectionBools,
+        hold: bool,
+        media_id: MediaId,
+    ) {
         let media = self
             .state
             .iter_mut()
             .find(|m| m.id == media_id)
             .expect("media_id must be valid");
 
-        if media.direction != requested_direction {
+        if media.direction != requested_direction || media.remote_hold != hold {
             self.events.push_back(Event::MediaChanged(MediaChanged {
                 id: media_id,
                 old_direction: media.direction.into(),
                 new_direction: requested_direction.into(),
+                hold,
             }));
 
             media.direction = requested_direction;
+            media.remote_hold = hold;
+        }
+    }
+
+    /// Apply the last codec change of the media which was part of the offer, if the peer accepted it
+    fn update_active_media_codec(
+        &mut self,
+        committed_changes: &[PendingChange],
+        remote_media_desc: &MediaDescription,
+        media_id: MediaId,
+    ) {
+        let Some((codec, codec_pt)) = committed_changes.iter().rev().find_map(|c| match c {
+            PendingChange::ChangeCodec(id, codec, pt) if *id == media_id => Some((codec, *pt)),
+            _ => None,
+        }) else {
+            return;
+        };
+
+        if !remote_media_desc.media.fmts.contains(&codec_pt) {
+            log::warn!(
+                "Peer rejected codec change to {} for {media_id:?}",
+                codec.name
+            );
+            return;
+        }
+
+        self.switch_active_media_codec(media_id, codec, codec_pt, remote_media_desc);
+    }
+
+    /// Answer a re-offer which no longer contains the media's codec using one of the offered codecs
+    fn follow_offered_codec(&mut self, remote_media_desc: &MediaDescription, media_id: MediaId) {
+        let media = self
+            .state
+            .iter()
+            .find(|m| m.id == media_id)
+            .expect("media_id must be valid");
+
+        if remote_media_desc.media.fmts.contains(&media.codec_pt) {
+            return;
+        }
+
+        let Some((codec, codec_pt, _)) =
+            self.local_media[media.local_media_id].choose_codec_from_answer(remote_media_desc)
+        else {
+            log::warn!("Peer offered none of the local codecs for {media_id:?}");
+            return;
+        };
+
+        self.switch_active_media_codec(media_id, &codec, codec_pt, remote_media_desc);
+    }
+
+    fn switch_active_media_codec(
+        &mut self,
+        media_id: MediaId,
+        codec: &Codec,
+        codec_pt: u8,
+        remote_media_desc: &MediaDescription,
+    ) {
+        let media = self
+            .state
+            .iter_mut()
+            .find(|m| m.id == media_id)
+            .expect("media_id must be valid");
+
+        if media.codec_pt == codec_pt {
+            return;
+        }
+
+        let dtmf = self.local_media[media.local_media_id].choose_dtmf(codec, remote_media_desc);
+
+        // The RTP session's timestamps depend on the clock rate, keep the SSRC so the peer sees the same stream
+        if media.codec.clock_rate != codec.clock_rate {
+            let mut rtp_session = RtpSession::new(media.rtp_session.ssrc(), codec.clock_rate)
+                .with_jitter_buffer_config(media.rtp_session.jitter_buffer_config());
+            rtp_session.set_jitter_buffer_bypass(media.rtp_session.jitter_buffer_bypass());
+
+            media.rtp_session = rtp_session;
         }
+
+        media.codec = codec.clone();
+        media.codec_pt = codec_pt;
+        media.dtmf = dtmf.clone();
+        media.rtx = self.local_media[media.local_media_id]
+            .choose_rtx(codec_pt, remote_media_desc)
+            .map(|rtx| Retransmission::new(rtx, self.options.rtx_buffer_size));
+        media.remb = self.local_media[media.local_media_id]
+            .choose_remb(codec_pt, remote_media_desc)
+            .then(|| RembEstimator::new(codec.clock_rate, GccConfig::default()));
+        media.reported_unexpected_pts.clear();
+
+        let recv_fmtp = remote_media_desc
+            .fmtp
+            .iter()
+            .find(|f| f.format == codec_pt)
+            .map(|f| f.params.to_string());
+
+        self.events.push_back(Event::CodecChanged(CodecChanged {
+            media_id,
+            codec: NegotiatedCodec {
+                send_pt: codec_pt,
+                recv_pt: codec_pt,
+                name: codec.name.clone(),
+                clock_rate: codec.clock_rate,
+                channels: codec.channels,
+                send_fmtp: codec.fmtp.clone(),
+                recv_fmtp,
+            },
+            dtmf,
+        }));
     }
 
     /// Get or create a transport for the given media description
@@ -208,6 +468,17 @@ impl SdpSession {
             return Ok(Some(id));
         }
 
+        self.create_transport_from_offer(session_desc, remote_media_desc)
+    }
+
+    /// Create a transport for the given media description
+    ///
+    /// If the transport type is unknown or cannot be created Ok(None) is returned.
+    fn create_transport_from_offer(
+        &mut self,
+        session_desc: &SessionDescription,
+        remote_media_desc: &MediaDescription,
+    ) -> Result<Option<TransportId>, Error> {
         // TODO: this is very messy, create_from_offer return Ok(None) if the transport is not supported
         let maybe_transport_id =
             self.transports
@@ -271,7 +542,16 @@ impl SdpSession {
                 }
             };
 
-            media_descriptions.push(self.media_description_for_active(active, None));
+            // Answer using the transport the media is migrating to, if the peer moved it
+            let transport_id = active.migrate_to.unwrap_or(active.transport);
+
+            media_descriptions.push(self.media_description_for_active(
+                active,
+                &self.transports[transport_id],
+                self.address,
+                None,
+                None,
+            ));
         }
 
         let mut sess_desc = SessionDescription {
@@ -279,11 +559,11 @@ impl SdpSession {
                 username: "-".into(),
                 session_id: self.id.to_string().into(),
                 session_version: self.version.to_string().into(),
-                address: self.address.into(),
+                address: self.advertised_address(self.address).into(),
             },
             name: "-".into(),
             connection: Some(Connection {
-                address: self.address.into(),
+                address: self.advertised_address(self.address).into(),
                 ttl: None,
                 num: None,
             }),
@@ -294,7 +574,7 @@ impl SdpSession {
             extmap: vec![],
             extmap_allow_mixed: true,
             ice_lite: false,
-            ice_options: IceOptions::default(),
+            ice_options: self.ice_options(),
             ice_ufrag: None,
             ice_pwd: None,
             setup: None,
@@ -317,23 +597,38 @@ impl SdpSession {
             });
         }
 
+        if self.options.compact_sdp {
+            compact_session_description(&mut sess_desc);
+        }
+
         sess_desc
     }
 
     pub fn create_sdp_offer(&self) -> SessionDescription {
         let mut media_descriptions = vec![];
 
+        // Media which is to be migrated is offered on the new transports and address
+        let migration = self.committed_migration();
+        let address = migration.map_or(self.address, |migration| migration.address);
+
         // Put the current media sessions in the offer
-        for media in &self.state {
-            let mut override_direction = None;
+        'next_media: for media in &self.state {
+            let mut override_direction = media.restore_direction.map(Direction::from);
+            let mut override_codec = None;
 
             // Apply requested changes
-            for change in &self.pending_changes {
+            for change in self.committed_changes() {
                 match change {
-                    PendingChange::AddMedia(..) => {}
+                    PendingChange::AddMedia(..)
+                    | PendingChange::MigrateTransports(..)
+                    | PendingChange::RekeySrtp(..) => {}
                     PendingChange::RemoveMedia(media_id) => {
                         if media.id == *media_id {
-                            continue;
+                            // Removed media must still be offered, but with the port set to zero
+                            let mut desc = MediaDescription::rejected(media.media_type);
+                            desc.mid = media.mid.clone();
+                            media_descriptions.push(desc);
+                            continue 'next_media;
                         }
                     }
                     PendingChange::ChangeDirection(media_id, direction) => {
@@ -341,14 +636,53 @@ impl SdpSession {
                             override_direction = Some(*direction);
                         }
                     }
+                    PendingChange::ChangeCodec(media_id, codec, pt) => {
+                        if media.id == *media_id {
+                            override_codec = Some((codec, *pt));
+                        }
+                    }
                 }
             }
 
-            media_descriptions.push(self.media_description_for_active(media, override_direction));
+            // One-shot overrides take precedence over any other change
+            if let Some((_, direction)) = self
+                .direction_overrides
+                .iter()
+                .find(|(media_id, _)| *media_id == media.id)
+            {
+                override_direction = Some(*direction);
+            }
+
+            let transport_id = migration
+                .and_then(|migration| migration.new_transport(media.transport))
+                .unwrap_or(media.transport);
+
+            let mut media_desc = self.media_description_for_active(
+                media,
+                &self.transports[transport_id],
+                address,
+                override_direction,
+                override_codec,
+            );
+
+            // Offer the new key instead of the current one when re-keying
+            if let Some((_, rekey)) = self
+                .committed_srtp_rekeys()
+                .iter()
+                .find(|(id, _)| *id == transport_id)
+            {
+                media_desc.crypto = vec![rekey.crypto().clone()];
+            }
+
+            // Keep requiring muxing in subsequent offers, rtcp-mux-only is never part of an answer (RFC 8858)
+            media_desc.rtcp_mux_only =
+                media_desc.rtcp_mux && self.options.rtcp_mux_policy == RtcpMuxPolicy::Require;
+
+            media_descriptions.push(media_desc);
         }
 
         // Add all pending added media
-        for change in &self.pending_changes {
+        for change in self.committed_changes() {
             let PendingChange::AddMedia(pending_media) = change else {
                 continue;
             };
@@ -358,21 +692,19 @@ impl SdpSession {
                 .standalone_transport
                 .unwrap_or(pending_media.bundle_transport)];
 
-            let (local_rtp_port, local_rtcp_port) = match &transport {
-                TransportEntry::Transport(transport) => {
-                    (transport.local_rtp_port, transport.local_rtcp_port)
-                }
-                TransportEntry::TransportBuilder(transport_builder) => (
-                    transport_builder.local_rtp_port,
-                    transport_builder.local_rtcp_port,
-                ),
-            };
+            let (local_rtp_port, local_rtcp_port) = transport.local_ports();
 
             let mut rtpmap = vec![];
             let mut fmtp = vec![];
             let mut fmts = vec![];
 
-            for codec in &local_media.codecs.codecs {
+            for codec in local_media
+                .codecs
+                .codecs
+                .iter()
+                .chain(&local_media.dtmf)
+                .chain(&local_media.rtx)
+            {
                 let pt = codec.pt.expect("pt is set when adding the codec");
 
                 fmts.push(pt);
@@ -393,10 +725,28 @@ impl SdpSession {
                 }
             }
 
+            // Generic NACKs are requested for every codec with a retransmission payload type
+            let mut rtcp_fb = vec![];
+
+            for codec in &local_media.codecs.codecs {
+                let pt = codec.pt.expect("pt is set when adding the codec");
+
+                if !local_media.rtx.is_empty() {
+                    rtcp_fb.push(nack_feedback(pt));
+                }
+
+                if local_media.codecs.allow_remb {
+                    rtcp_fb.push(remb_feedback(pt));
+                }
+            }
+
             let mut media_desc = MediaDescription {
                 media: Media {
                     media_type: local_media.codecs.media_type,
-                    port: local_rtp_port.expect("rtp port not set for transport"),
+                    port: self.advertised_port(
+                        address,
+                        local_rtp_port.expect("rtp port not set for transport"),
+                    ),
                     ports_num: None,
                     proto: transport.type_().sdp_type(pending_media.use_avpf),
                     fmts,
@@ -405,14 +755,18 @@ impl SdpSession {
                 bandwidth: vec![],
                 direction: pending_media.direction,
                 rtcp: local_rtcp_port.map(|port| Rtcp {
-                    port,
+                    port: self.advertised_port(address, port),
                     address: None,
                 }),
                 // always offer rtcp-mux
                 rtcp_mux: true,
+                // no RTCP port is allocated when muxing is required (RFC 8858)
+                rtcp_mux_only: self.options.rtcp_mux_policy == RtcpMuxPolicy::Require,
                 mid: Some(pending_media.mid.as_str().into()),
+                content: pending_media.content.clone(),
                 rtpmap,
                 fmtp,
+                rtcp_fb,
                 ice_ufrag: None,
                 ice_pwd: None,
                 ice_candidates: vec![],
@@ -436,11 +790,11 @@ impl SdpSession {
                 username: "-".into(),
                 session_id: self.id.to_string().into(),
                 session_version: self.version.to_string().into(),
-                address: self.address.into(),
+                address: self.advertised_address(address).into(),
             },
             name: "-".into(),
             connection: Some(Connection {
-                address: self.address.into(),
+                address: self.advertised_address(address).into(),
                 ttl: None,
                 num: None,
             }),
@@ -451,7 +805,7 @@ impl SdpSession {
             extmap: vec![],
             extmap_allow_mixed: true,
             ice_lite: false,
-            ice_options: IceOptions::default(),
+            ice_options: self.ice_options(),
             ice_ufrag: None,
             ice_pwd: None,
             setup: None,
@@ -474,25 +828,153 @@ impl SdpSession {
             });
         }
 
+        if self.options.compact_sdp {
+            compact_session_description(&mut sess_desc);
+        }
+
+        sess_desc
+    }
+
+    /// Create a SDP describing the capabilities of the session, e.g. for the response to an OPTIONS request
+    /// ([RFC3261 Section 11.2](https://www.rfc-editor.org/rfc/rfc3261#section-11.2))
+    ///
+    /// Contains a media description with the port set to zero for each local media, listing its codecs
+    /// ([RFC3264 Section 9](https://www.rfc-editor.org/rfc/rfc3264#section-9)). Doesn't modify the session.
+    pub fn create_sdp_capabilities(&self) -> SessionDescription {
+        let media_descriptions = self
+            .local_media
+            .values()
+            .map(|local_media| {
+                let mut media_desc = MediaDescription::rejected(local_media.codecs.media_type);
+
+                media_desc.media.proto = self
+                    .options
+                    .offer_transport
+                    .sdp_type(self.options.offer_avpf);
+                media_desc.direction = Direction::SendRecv;
+
+                for codec in local_media
+                    .codecs
+                    .codecs
+                    .iter()
+                    .chain(&local_media.dtmf)
+                    .chain(&local_media.rtx)
+                {
+                    let pt = codec.pt.expect("pt is set when adding the codec");
+
+                    media_desc.media.fmts.push(pt);
+
+                    media_desc.rtpmap.push(RtpMap {
+                        payload: pt,
+                        encoding: codec.name.as_ref().into(),
+                        clock_rate: codec.clock_rate,
+                        params: codec.channels.map(|c| c.to_string().into()),
+                    });
+
+                    if let Some(param) = &codec.fmtp {
+                        media_desc.fmtp.push(Fmtp {
+                            format: pt,
+                            params: param.as_str().into(),
+                        });
+                    }
+                }
+
+                media_desc
+            })
+            .collect();
+
+        let mut sess_desc = SessionDescription {
+            origin: Origin {
+                username: "-".into(),
+                session_id: self.id.to_string().into(),
+                session_version: self.version.to_string().into(),
+                address: self.advertised_address(self.address).into(),
+            },
+            name: "-".into(),
+            connection: Some(Connection {
+                address: self.advertised_address(self.address).into(),
+                ttl: None,
+                num: None,
+            }),
+            bandwidth: vec![],
+            time: Time { start: 0, stop: 0 },
+            direction: Direction::SendRecv,
+            group: vec![],
+            extmap: vec![],
+            extmap_allow_mixed: false,
+            ice_lite: false,
+            ice_options: IceOptions::default(),
+            ice_ufrag: None,
+            ice_pwd: None,
+            setup: None,
+            fingerprint: vec![],
+            attributes: vec![],
+            media_descriptions,
+        };
+
+        if self.options.compact_sdp {
+            compact_session_description(&mut sess_desc);
+        }
+
         sess_desc
     }
 
     /// Receive a SDP answer after sending an offer.
     pub fn receive_sdp_answer(&mut self, answer: SessionDescription) {
+        // Remember the direction of media which used a one-shot override in this offer, to restore it in the next one.
+        // Media which had its direction restored in this offer doesn't need to be restored again.
+        let direction_overrides = take(&mut self.direction_overrides);
+
+        // Take all changes which were included in the offer, changes of a batch that was not yet committed are kept
+        let committed_len = self.committed_changes().len();
+        let committed_changes: Vec<PendingChange> =
+            self.pending_changes.drain(..committed_len).collect();
+        if let Some(batch_start) = &mut self.batch_start {
+            *batch_start = 0;
+        }
+
+        let migration = committed_changes.iter().rev().find_map(|c| match c {
+            PendingChange::MigrateTransports(migration) => Some(migration),
+            _ => None,
+        });
+
+        let srtp_rekeys = committed_changes
+            .iter()
+            .rev()
+            .find_map(|c| match c {
+                PendingChange::RekeySrtp(rekeys) => Some(rekeys.as_slice()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut rekeyed_transports = vec![];
+
+        for media in &mut self.state {
+            let overridden = direction_overrides
+                .iter()
+                .any(|(media_id, _)| *media_id == media.id);
+
+            media.restore_direction = if overridden {
+                Some(media.restore_direction.unwrap_or(media.direction))
+            } else {
+                None
+            };
+        }
+
         'next_media_desc: for (mline, remote_media_desc) in
             answer.media_descriptions.iter().enumerate()
         {
-            // Skip any rejected answers
-            if remote_media_desc.direction == Direction::Inactive {
+            // Skip any rejected answers, an inactive answer with a port puts the media on hold
+            if remote_media_desc.media.port == 0
+                && remote_media_desc.direction == Direction::Inactive
+            {
                 continue;
             }
 
-            let requested_direction: DirectionBools = remote_media_desc.direction.flipped().into();
+            let (requested_direction, hold) = requested_direction(&answer, remote_media_desc);
 
             // Try to match an active media session, while filtering out media that is to be deleted
             for media in &mut self.state {
-                let pending_removal = self
-                    .pending_changes
+                let pending_removal = committed_changes
                     .iter()
                     .any(|c| matches!(c, PendingChange::RemoveMedia(id) if *id == media.id));
 
@@ -505,13 +987,33 @@ impl SdpSession {
                     // // TODO: update media
                     // let _ = requested_direction;
                     let media_id = media.id;
-                    self.update_active_media(requested_direction, media_id);
+                    let transport_id = media.transport;
+
+                    // Start connecting the new transport, the media switches over once it is connected
+                    if let Some(new_transport_id) =
+                        migration.and_then(|migration| migration.new_transport(media.transport))
+                    {
+                        media.migrate_to = Some(new_transport_id);
+                        self.build_transport(new_transport_id, &answer, remote_media_desc);
+                    }
+
+                    // Switch to the new keys once per transport, bundled media shares them
+                    if let Some((_, rekey)) = srtp_rekeys.iter().find(|(id, _)| *id == transport_id)
+                    {
+                        if !rekeyed_transports.contains(&transport_id) {
+                            rekeyed_transports.push(transport_id);
+                            self.complete_srtp_rekey(transport_id, rekey, remote_media_desc);
+                        }
+                    }
+
+                    self.update_active_media(requested_direction, hold, media_id);
+                    self.update_active_media_codec(&committed_changes, remote_media_desc, media_id);
                     continue 'next_media_desc;
                 }
             }
 
             // Try to match a new media session
-            for pending_change in &self.pending_changes {
+            for pending_change in &committed_changes {
                 let PendingChange::AddMedia(pending_media) = pending_change else {
                     continue;
                 };
@@ -533,26 +1035,22 @@ impl SdpSession {
                     pending_media.standalone_transport.unwrap()
                 };
 
-                // Build transport if necessary
-                if let TransportEntry::TransportBuilder(transport_builder) =
-                    &mut self.transports[transport_id]
-                {
-                    let transport_builder =
-                        replace(transport_builder, TransportBuilder::placeholder());
-
-                    let transport = transport_builder.build_from_answer(
-                        &mut self.transport_state,
-                        TransportRequiredChanges::new(transport_id, &mut self.transport_changes),
-                        &answer,
-                        remote_media_desc,
-                    );
-
-                    self.transports[transport_id] = TransportEntry::Transport(transport);
-                }
-
-                let (codec, codec_pt, direction) = self.local_media[pending_media.local_media_id]
+                let Some((codec, codec_pt, direction)) = self.local_media
+                    [pending_media.local_media_id]
                     .choose_codec_from_answer(remote_media_desc)
-                    .unwrap();
+                else {
+                    // Nobody would send or receive on the new media, same as rejecting it
+                    log::debug!("Ignoring mline={mline}, inactive answer to new media");
+                    continue 'next_media_desc;
+                };
+
+                self.build_transport(transport_id, &answer, remote_media_desc);
+                let dtmf = self.local_media[pending_media.local_media_id]
+                    .choose_dtmf(&codec, remote_media_desc);
+                let rtx = self.local_media[pending_media.local_media_id]
+                    .choose_rtx(codec_pt, remote_media_desc);
+                let remb = self.local_media[pending_media.local_media_id]
+                    .choose_remb(codec_pt, remote_media_desc);
 
                 let recv_fmtp = remote_media_desc
                     .fmtp
@@ -574,18 +1072,52 @@ impl SdpSession {
                         send_fmtp: codec.fmtp.clone(),
                         recv_fmtp,
                     },
+                    dtmf: dtmf.clone(),
+                    content: remote_media_desc.content.clone(),
                 }));
 
                 self.state.push(ActiveMedia {
                     id: pending_media.id,
                     local_media_id: pending_media.local_media_id,
                     media_type: pending_media.media_type,
-                    rtp_session: RtpSession::new(Ssrc(rand::random()), codec.clock_rate),
+                    rtp_session: RtpSession::new(Ssrc(rand::random()), codec.clock_rate)
+                        .with_jitter_buffer_config(
+                            self.local_media[pending_media.local_media_id]
+                                .codecs
+                                .jitter_buffer,
+                        ),
                     avpf: pending_media.use_avpf,
                     next_rtcp: Instant::now() + Duration::from_secs(5),
                     rtcp_interval: rtcp_interval(pending_media.media_type),
                     mid: remote_media_desc.mid.clone(),
+                    content: pending_media.content.clone(),
                     direction,
+                    restore_direction: None,
+                    remote_hold: hold,
+                    audio_level: None,
+                    tone_detector: None,
+                    dtmf_receiver: DtmfReceiver::new(),
+                    rtx: rtx.map(|rtx| Retransmission::new(rtx, self.options.rtx_buffer_size)),
+                    remb: remb.then(|| RembEstimator::new(codec.clock_rate, GccConfig::default())),
+                    call_progress: None,
+                    prompt_player: PromptPlayer::new(),
+                    answering_machine: None,
+                    rtp_tap: None,
+                    bandwidth_prober: None,
+                    bitrate_allocation: None,
+                    target_bitrate: None,
+                    keyframe_recovery: KeyframeRecovery::default(),
+                    last_fir_sequence: None,
+                    reference_acked: false,
+                    codec_downshift: None,
+                    dtmf,
+                    quality_monitor: None,
+                    unexpected_pt_policy: UnexpectedPayloadTypePolicy::default(),
+                    unexpected_pt_packets: 0,
+                    reported_unexpected_pts: Vec::new(),
+                    receive_direction_enforcement: ReceiveDirectionEnforcement::default(),
+                    unexpected_direction_packets: 0,
+                    migrate_to: None,
                     transport: transport_id,
                     codec_pt,
                     codec,
@@ -598,52 +1130,226 @@ impl SdpSession {
             log::warn!("Failed to match mline={mline} to any offered media");
         }
 
-        self.pending_changes.clear();
+        // Apply all removals which were part of the offer
+        for change in &committed_changes {
+            let PendingChange::RemoveMedia(media_id) = change else {
+                continue;
+            };
+
+            if let Some(position) = self.state.iter().position(|m| m.id == *media_id) {
+                let media = self.state.remove(position);
+
+                let local_media = &mut self.local_media[media.local_media_id];
+                local_media.use_count = local_media.use_count.saturating_sub(1);
+
+                self.events.push_back(Event::MediaRemoved(media.id));
+            }
+        }
+
+        if let Some(migration) = migration {
+            self.address = migration.address;
+        }
+
         self.remove_unused_transports();
+
+        // Transports which are connected right away (e.g. plain RTP without ICE) can be switched to immediately
+        self.complete_transport_migrations();
+    }
+
+    /// Build the transport from the answer, if it has not been built already
+    fn build_transport(
+        &mut self,
+        transport_id: TransportId,
+        answer: &SessionDescription,
+        remote_media_desc: &MediaDescription,
+    ) {
+        if let TransportEntry::TransportBuilder(transport_builder) =
+            &mut self.transports[transport_id]
+        {
+            let transport_builder = replace(transport_builder, TransportBuilder::placeholder());
+
+            let transport = transport_builder.build_from_answer(
+                &mut self.transport_state,
+                TransportRequiredChanges::new(transport_id, &mut self.transport_changes),
+                answer,
+                remote_media_desc,
+            );
+
+            self.transports[transport_id] = TransportEntry::Transport(transport);
+        }
+    }
+
+    /// Switch a transport to the keys offered for re-keying and the key the peer answered with
+    fn complete_srtp_rekey(
+        &mut self,
+        transport_id: TransportId,
+        rekey: &SdesSrtpRekey,
+        remote_media_desc: &MediaDescription,
+    ) {
+        let TransportEntry::Transport(transport) = &mut self.transports[transport_id] else {
+            return;
+        };
+
+        match transport.complete_srtp_rekey(&self.transport_state, rekey, remote_media_desc) {
+            Ok(()) => self.events.push_back(Event::SrtpRekeyed(SrtpRekeyed {
+                transport_id,
+                initiated_locally: true,
+            })),
+            Err(e) => {
+                log::warn!("Failed to re-key {transport_id:?}, keeping the current keys, {e}")
+            }
+        }
+    }
+
+    /// Replace the keys of a transport if the peer offered a new key
+    fn receive_srtp_offer(
+        &mut self,
+        transport_id: TransportId,
+        remote_media_desc: &MediaDescription,
+    ) -> Result<(), Error> {
+        let TransportEntry::Transport(transport) = &mut self.transports[transport_id] else {
+            return Ok(());
+        };
+
+        if transport.receive_srtp_offer(&self.transport_state, remote_media_desc)? {
+            self.events.push_back(Event::SrtpRekeyed(SrtpRekeyed {
+                transport_id,
+                initiated_locally: false,
+            }));
+        }
+
+        Ok(())
     }
 
     fn media_description_for_active(
         &self,
         active: &ActiveMedia,
+        transport: &TransportEntry,
+        local_ip: IpAddr,
         override_direction: Option<Direction>,
+        override_codec: Option<(&Codec, u8)>,
     ) -> MediaDescription {
-        let rtpmap = RtpMap {
-            payload: active.codec_pt,
-            encoding: active.codec.name.as_ref().into(),
-            clock_rate: active.codec.clock_rate,
-            params: Default::default(),
-        };
+        let (codec, codec_pt) = override_codec.unwrap_or((&active.codec, active.codec_pt));
 
-        let fmtp = active.codec.fmtp.as_ref().map(|param| Fmtp {
-            format: active.codec_pt,
-            params: param.as_str().into(),
-        });
+        // Telephone-event must use the clock rate of the codec, offer the local one when changing the codec
+        let dtmf = match override_codec {
+            Some((codec, _)) => self.local_media[active.local_media_id]
+                .dtmf
+                .iter()
+                .find(|dtmf| dtmf.clock_rate == codec.clock_rate)
+                .map(|dtmf| {
+                    (
+                        dtmf.clone(),
+                        dtmf.pt.expect("pt is set when adding the codec"),
+                    )
+                }),
+            None => active
+                .dtmf
+                .as_ref()
+                .map(|dtmf| (Codec::telephone_event(dtmf.clock_rate), dtmf.send_pt)),
+        };
 
-        let transport = self.transports[active.transport].unwrap();
+        // Retransmissions are bound to the payload type of the codec
+        let rtx = match override_codec {
+            Some(_) => {
+                let apt = Codec::rtx(codec.clock_rate, codec_pt);
 
-        let mut media_desc = MediaDescription {
-            media: Media {
-                media_type: active.media_type,
-                port: transport
-                    .local_rtp_port
-                    .expect("Did not set port for RTP socket"),
-                ports_num: None,
-                proto: transport.type_().sdp_type(active.avpf),
-                fmts: vec![active.codec_pt],
-            },
-            connection: None,
-            bandwidth: vec![],
-            direction: override_direction.unwrap_or(active.direction.into()),
-            rtcp: transport.local_rtcp_port.map(|port| Rtcp {
-                port,
-                address: None,
+                self.local_media[active.local_media_id]
+                    .rtx
+                    .iter()
+                    .find(|rtx| rtx.fmtp == apt.fmtp)
+                    .map(|rtx| {
+                        (
+                            rtx.clone(),
+                            rtx.pt.expect("pt is set when adding the codec"),
+                        )
+                    })
+            }
+            None => active.rtx.as_ref().map(|rtx| {
+                (
+                    Codec::rtx(codec.clock_rate, codec_pt),
+                    rtx.negotiated.send_pt,
+                )
             }),
-            rtcp_mux: transport.remote_rtp_address == transport.remote_rtcp_address,
-            mid: active.mid.clone(),
-            rtpmap: vec![rtpmap],
-            fmtp: fmtp.into_iter().collect(),
-            ice_ufrag: None,
-            ice_pwd: None,
+        };
+
+        let remb = match override_codec {
+            Some(_) => self.local_media[active.local_media_id].codecs.allow_remb,
+            None => active.remb.is_some(),
+        };
+
+        let rtcp_fb = rtx
+            .as_ref()
+            .map(|_| nack_feedback(codec_pt))
+            .into_iter()
+            .chain(remb.then(|| remb_feedback(codec_pt)))
+            .collect();
+
+        let mut fmts = vec![];
+        let mut rtpmap = vec![];
+        let mut fmtp = vec![];
+
+        for (codec, pt) in [(codec, codec_pt)]
+            .into_iter()
+            .chain(dtmf.as_ref().map(|(dtmf, pt)| (dtmf, *pt)))
+            .chain(rtx.as_ref().map(|(rtx, pt)| (rtx, *pt)))
+        {
+            fmts.push(pt);
+
+            rtpmap.push(RtpMap {
+                payload: pt,
+                encoding: codec.name.as_ref().into(),
+                clock_rate: codec.clock_rate,
+                params: Default::default(),
+            });
+
+            if let Some(param) = &codec.fmtp {
+                fmtp.push(Fmtp {
+                    format: pt,
+                    params: param.as_str().into(),
+                });
+            }
+        }
+
+        let (local_rtp_port, local_rtcp_port) = transport.local_ports();
+
+        // Use the address of the family the peer uses, if it differs from the session level address
+        let address = self.local_address_for(transport, local_ip);
+        let connection = (address != local_ip).then(|| Connection {
+            address: self.advertised_address(address).into(),
+            ttl: None,
+            num: None,
+        });
+
+        let rtcp_mux = transport.rtcp_mux();
+
+        let mut media_desc = MediaDescription {
+            media: Media {
+                media_type: active.media_type,
+                port: self.advertised_port(
+                    address,
+                    local_rtp_port.expect("Did not set port for RTP socket"),
+                ),
+                ports_num: None,
+                proto: transport.type_().sdp_type(active.avpf),
+                fmts,
+            },
+            connection,
+            bandwidth: vec![],
+            direction: override_direction.unwrap_or(active.direction.into()),
+            rtcp: local_rtcp_port.map(|port| Rtcp {
+                port: self.advertised_port(address, port),
+                address: None,
+            }),
+            rtcp_mux,
+            rtcp_mux_only: false,
+            mid: active.mid.clone(),
+            content: active.content.clone(),
+            rtpmap,
+            fmtp,
+            rtcp_fb,
+            ice_ufrag: None,
+            ice_pwd: None,
             ice_candidates: vec![],
             ice_end_of_candidates: false,
             crypto: vec![],
@@ -660,10 +1366,44 @@ impl SdpSession {
         media_desc
     }
 
+    /// Local address to use for the transport, the dual-stack address if the peer uses its address family
+    fn local_address_for(&self, transport: &TransportEntry, address: IpAddr) -> IpAddr {
+        let TransportEntry::Transport(transport) = transport else {
+            return address;
+        };
+
+        let remote_is_ipv6 = transport.remote_rtp_address.is_ipv6();
+
+        match self.dual_stack_address {
+            Some(dual_stack_address)
+                if address.is_ipv6() != remote_is_ipv6
+                    && dual_stack_address.is_ipv6() == remote_is_ipv6 =>
+            {
+                dual_stack_address
+            }
+            _ => address,
+        }
+    }
+
+    /// Session level ICE options, announcing trickle ICE support if enabled
+    fn ice_options(&self) -> IceOptions {
+        let mut ice_options = IceOptions::default();
+
+        if self.options.trickle_ice {
+            ice_options.options.push("trickle".into());
+        }
+
+        ice_options
+    }
+
     fn build_bundle_groups(&self, include_pending_changes: bool) -> Vec<Group> {
         let mut bundle_groups: HashMap<TransportId, Vec<BytesStr>> = HashMap::new();
 
         for media in &self.state {
+            if !self.transports[media.transport].can_bundle() {
+                continue;
+            }
+
             if let Some(mid) = media.mid.clone() {
                 bundle_groups.entry(media.transport).or_default().push(mid);
             }
@@ -672,6 +1412,10 @@ impl SdpSession {
         if include_pending_changes {
             for change in &self.pending_changes {
                 if let PendingChange::AddMedia(pending_media) = change {
+                    if !self.transports[pending_media.bundle_transport].can_bundle() {
+                        continue;
+                    }
+
                     bundle_groups
                         .entry(pending_media.bundle_transport)
                         .or_default()
@@ -691,15 +1435,108 @@ impl SdpSession {
     }
 }
 
+/// Remove all attributes from the session description which are redundant or unused
+fn compact_session_description(sess_desc: &mut SessionDescription) {
+    for media_desc in &mut sess_desc.media_descriptions {
+        let fmts = &media_desc.media.fmts;
+
+        // Static payload types have an implicit mapping, drop everything that isn't offered
+        media_desc
+            .rtpmap
+            .retain(|rtpmap| rtpmap.payload >= 96 && fmts.contains(&rtpmap.payload));
+        media_desc.fmtp.retain(|fmtp| fmts.contains(&fmtp.format));
+        media_desc
+            .rtcp_fb
+            .retain(|rtcp_fb| rtcp_fb.format.is_none_or(|format| fmts.contains(&format)));
+
+        // The rtcp attribute is only required if RTCP doesn't use the next higher port
+        if let Some(rtcp) = &media_desc.rtcp {
+            let is_default_port = rtcp.port == media_desc.media.port.wrapping_add(1);
+            let is_muxed_port = media_desc.rtcp_mux && rtcp.port == media_desc.media.port;
+
+            if rtcp.address.is_none() && (is_default_port || is_muxed_port) {
+                media_desc.rtcp = None;
+            }
+        }
+    }
+
+    let uses_extmap = !sess_desc.extmap.is_empty()
+        || sess_desc
+            .media_descriptions
+            .iter()
+            .any(|media_desc| !media_desc.extmap.is_empty());
+
+    if !uses_extmap {
+        sess_desc.extmap_allow_mixed = false;
+    }
+}
+
+/// Returns the direction the peer requests from the local perspective and if it put the media on hold
+///
+/// The media is on hold if the peer doesn't want to receive any media, either using `a=sendonly`/`a=inactive` or
+/// the legacy unspecified connection address.
+fn requested_direction(
+    session_desc: &SessionDescription,
+    media_desc: &MediaDescription,
+) -> (DirectionBools, bool) {
+    let mut requested_direction: DirectionBools = media_desc.direction.flipped().into();
+
+    if is_legacy_hold(session_desc, media_desc) {
+        requested_direction.send = false;
+    }
+
+    (requested_direction, !requested_direction.send)
+}
+
+/// Returns if the media description uses the unspecified address `0.0.0.0` or `::` to put the media on hold
+/// (RFC 2543)
+fn is_legacy_hold(session_desc: &SessionDescription, media_desc: &MediaDescription) -> bool {
+    let connection = media_desc
+        .connection
+        .as_ref()
+        .or(session_desc.connection.as_ref());
+
+    match connection {
+        Some(Connection {
+            address: TaggedAddress::IP4(ip),
+            ..
+        }) => ip.is_unspecified(),
+        Some(Connection {
+            address: TaggedAddress::IP6(ip),
+            ..
+        }) => ip.is_unspecified(),
+        _ => false,
+    }
+}
+
+/// Generic NACK feedback (`a=rtcp-fb:<pt> nack`) for the payload type
+fn nack_feedback(pt: u8) -> RtcpFeedback {
+    RtcpFeedback {
+        format: Some(pt),
+        kind: BytesStr::from_static("nack"),
+        param: None,
+    }
+}
+
+fn remb_feedback(pt: u8) -> RtcpFeedback {
+    RtcpFeedback {
+        format: Some(pt),
+        kind: BytesStr::from_static("goog-remb"),
+        param: None,
+    }
+}
+
 fn is_avpf(t: &TransportProtocol) -> bool {
     match t {
         TransportProtocol::RtpAvpf
         | TransportProtocol::RtpSavpf
-        | TransportProtocol::UdpTlsRtpSavpf => true,
+        | TransportProtocol::UdpTlsRtpSavpf
+        | TransportProtocol::TcpRtpAvpf => true,
         TransportProtocol::Unspecified
         | TransportProtocol::RtpAvp
         | TransportProtocol::RtpSavp
         | TransportProtocol::UdpTlsRtpSavp
+        | TransportProtocol::TcpRtpAvp
         | TransportProtocol::Other(..) => false,
     }
 }
@@ -710,3 +1547,1191 @@ fn rtcp_interval(media_type: MediaType) -> Duration {
         _ => Duration::from_secs(5),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codecs, LocalMediaId, Options, UnexpectedPayloadTypeReceived};
+    use ice::{Component, ReceivedPkt};
+    use rtp::rtcp_types::{
+        Fir, PayloadFeedback, PayloadFeedbackBuilder, Pli, Rpsi, RtcpPacketWriterExt,
+    };
+
+    fn session(options: Options) -> (SdpSession, LocalMediaId) {
+        let mut session = SdpSession::new("127.0.0.1".parse().unwrap(), options);
+
+        let audio = session
+            .add_local_media(
+                Codecs::new(MediaType::Audio)
+                    .with_codec(Codec::OPUS)
+                    .with_codec(Codec::PCMU),
+                2,
+                Direction::SendRecv,
+            )
+            .unwrap();
+
+        (session, audio)
+    }
+
+    fn handle_transport_changes(session: &mut SdpSession) {
+        for change in session.transport_changes() {
+            match change {
+                TransportChange::CreateSocket(transport_id) => {
+                    session.set_transport_ports(transport_id, &[], 10000, None);
+                }
+                TransportChange::CreateSocketPair(transport_id) => {
+                    session.set_transport_ports(transport_id, &[], 10000, Some(10001));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn create_offer(session: &mut SdpSession) -> SessionDescription {
+        handle_transport_changes(session);
+        session.create_sdp_offer()
+    }
+
+    /// Complete an offer/answer exchange, returns the offer
+    fn negotiate(offerer: &mut SdpSession, answerer: &mut SdpSession) -> SessionDescription {
+        let offer = create_offer(offerer);
+
+        let state = answerer.receive_sdp_offer(offer.clone()).unwrap();
+        handle_transport_changes(answerer);
+        let answer = answerer.create_sdp_answer(state);
+
+        offerer.receive_sdp_answer(answer);
+        handle_transport_changes(offerer);
+
+        offer
+    }
+
+    /// Two sessions which negotiated a single audio media, returns the id of the media in the first session
+    fn established() -> (SdpSession, SdpSession, MediaId) {
+        let (mut a, audio) = session(Options::lan());
+        let (mut b, _) = session(Options::lan());
+
+        let media_id = a.add_media(audio, Direction::SendRecv);
+        negotiate(&mut a, &mut b);
+
+        (a, b, media_id)
+    }
+
+    fn directions(offer: &SessionDescription) -> Vec<Direction> {
+        offer
+            .media_descriptions
+            .iter()
+            .map(|media_desc| media_desc.direction)
+            .collect()
+    }
+
+    fn offer(options: Options) -> SessionDescription {
+        let (mut session, audio) = session(options);
+        session.add_media(audio, Direction::SendRecv);
+
+        create_offer(&mut session)
+    }
+
+    #[test]
+    fn short_mids() {
+        assert_eq!(MediaId(0).mid(true), "0");
+        assert_eq!(MediaId(10).mid(true), "a");
+        assert_eq!(MediaId(61).mid(true), "Z");
+        assert_eq!(MediaId(62).mid(true), "10");
+        assert_eq!(MediaId(62 * 62).mid(true), "100");
+
+        assert_eq!(MediaId(62).mid(false), "62");
+    }
+
+    #[test]
+    fn compact_offer() {
+        let full = offer(Options::lan());
+        let compact = offer(Options {
+            compact_sdp: true,
+            ..Options::lan()
+        });
+
+        assert!(compact.to_string().len() < full.to_string().len());
+
+        let full = &full.media_descriptions[0];
+        let compact = &compact.media_descriptions[0];
+
+        assert_eq!(full.media.fmts, compact.media.fmts);
+        assert_eq!(compact.mid.as_deref(), Some("0"));
+
+        // Static payload types don't require a rtpmap attribute
+        assert!(full.rtpmap.iter().any(|rtpmap| rtpmap.payload == 0));
+        assert!(compact.rtpmap.iter().all(|rtpmap| rtpmap.payload >= 96));
+        assert!(compact
+            .rtpmap
+            .iter()
+            .any(|rtpmap| rtpmap.encoding.eq_ignore_ascii_case("opus")));
+
+        // RTCP uses the default port, or is multiplexed over the RTP port
+        assert!(compact.rtcp.is_none());
+    }
+
+    #[test]
+    fn compact_session_description_strips_redundant_attributes() {
+        let mut sess_desc = SessionDescription::parse(&BytesStr::from_static(
+            "v=0\r\n\
+             o=- 1 1 IN IP4 127.0.0.1\r\n\
+             s=-\r\n\
+             c=IN IP4 127.0.0.1\r\n\
+             t=0 0\r\n\
+             a=extmap-allow-mixed\r\n\
+             m=audio 10000 RTP/AVPF 96 0\r\n\
+             a=rtcp:10001\r\n\
+             a=rtpmap:96 opus/48000/2\r\n\
+             a=rtpmap:97 telephone-event/8000\r\n\
+             a=rtpmap:0 PCMU/8000\r\n\
+             a=fmtp:96 useinbandfec=1\r\n\
+             a=fmtp:97 0-16\r\n\
+             a=rtcp-fb:96 nack\r\n\
+             a=rtcp-fb:97 nack\r\n\
+             a=rtcp-fb:* ccm fir\r\n\
+             m=audio 20000 RTP/AVP 0\r\n\
+             a=rtcp:20001 IN IP4 192.168.0.1\r\n",
+        ))
+        .unwrap();
+
+        compact_session_description(&mut sess_desc);
+
+        assert!(!sess_desc.extmap_allow_mixed);
+
+        let audio = &sess_desc.media_descriptions[0];
+        assert!(audio.rtcp.is_none());
+        assert_eq!(
+            audio
+                .rtpmap
+                .iter()
+                .map(|rtpmap| rtpmap.payload)
+                .collect::<Vec<_>>(),
+            [96]
+        );
+        assert_eq!(
+            audio
+                .fmtp
+                .iter()
+                .map(|fmtp| fmtp.format)
+                .collect::<Vec<_>>(),
+            [96]
+        );
+        assert_eq!(
+            audio
+                .rtcp_fb
+                .iter()
+                .map(|rtcp_fb| rtcp_fb.format)
+                .collect::<Vec<_>>(),
+            [Some(96), None]
+        );
+
+        // The rtcp attribute is required if it contains an address
+        assert!(sess_desc.media_descriptions[1].rtcp.is_some());
+    }
+
+    #[test]
+    fn direction_override_is_restored() {
+        let (mut a, mut b, media_id) = established();
+
+        a.override_media_direction(media_id, Direction::SendOnly);
+
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(directions(&offer), [Direction::SendOnly]);
+        assert_eq!(a.medias().next().unwrap().direction, Direction::SendOnly);
+
+        // The offer following the overridden one restores the previous direction
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(directions(&offer), [Direction::SendRecv]);
+        assert_eq!(a.medias().next().unwrap().direction, Direction::SendRecv);
+
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(directions(&offer), [Direction::SendRecv]);
+    }
+
+    #[test]
+    fn repeated_direction_override_restores_original_direction() {
+        let (mut a, mut b, media_id) = established();
+
+        a.override_media_direction(media_id, Direction::SendOnly);
+        negotiate(&mut a, &mut b);
+
+        a.override_media_direction(media_id, Direction::Inactive);
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(directions(&offer), [Direction::Inactive]);
+
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(directions(&offer), [Direction::SendRecv]);
+    }
+
+    #[test]
+    fn direction_override_takes_precedence_over_update() {
+        let (mut a, mut b, media_id) = established();
+
+        a.update_media(media_id, Direction::RecvOnly);
+        a.override_media_direction(media_id, Direction::Inactive);
+
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(directions(&offer), [Direction::Inactive]);
+    }
+
+    #[test]
+    fn rollback_discards_direction_override() {
+        let (mut a, _b, media_id) = established();
+
+        a.override_media_direction(media_id, Direction::SendOnly);
+        a.rollback_changes();
+
+        assert_eq!(directions(&create_offer(&mut a)), [Direction::SendRecv]);
+    }
+
+    #[test]
+    fn batched_changes_are_offered_once_committed() {
+        let (mut a, mut b, media_id) = established();
+        let audio = a.medias().next().unwrap().local_media_id;
+
+        a.begin_changes();
+        a.update_media(media_id, Direction::RecvOnly);
+        a.add_media(audio, Direction::SendRecv);
+
+        assert_eq!(directions(&create_offer(&mut a)), [Direction::SendRecv]);
+
+        a.commit_changes();
+
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(
+            directions(&offer),
+            [Direction::RecvOnly, Direction::SendRecv]
+        );
+        assert_eq!(a.medias().count(), 2);
+    }
+
+    #[test]
+    fn uncommitted_batch_survives_answer() {
+        let (mut a, mut b, media_id) = established();
+
+        a.update_media(media_id, Direction::SendOnly);
+        let offer = create_offer(&mut a);
+
+        // Changes made while the offer is pending are kept for the next one
+        a.begin_changes();
+        a.update_media(media_id, Direction::Inactive);
+
+        let state = b.receive_sdp_offer(offer).unwrap();
+        handle_transport_changes(&mut b);
+        a.receive_sdp_answer(b.create_sdp_answer(state));
+
+        assert_eq!(a.medias().next().unwrap().direction, Direction::SendOnly);
+        assert_eq!(directions(&create_offer(&mut a)), [Direction::SendOnly]);
+
+        a.commit_changes();
+
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(directions(&offer), [Direction::Inactive]);
+    }
+
+    #[test]
+    fn rollback_batch() {
+        let (mut a, _b, media_id) = established();
+        let audio = a.medias().next().unwrap().local_media_id;
+
+        a.update_media(media_id, Direction::SendOnly);
+
+        a.begin_changes();
+        a.remove_media(media_id);
+        a.add_media(audio, Direction::SendRecv);
+        a.rollback_changes();
+
+        // Only the changes of the batch are discarded
+        let offer = create_offer(&mut a);
+        assert_eq!(directions(&offer), [Direction::SendOnly]);
+        assert_ne!(offer.media_descriptions[0].media.port, 0);
+
+        // Without a batch all pending changes are discarded
+        a.rollback_changes();
+        assert_eq!(directions(&create_offer(&mut a)), [Direction::SendRecv]);
+    }
+
+    #[test]
+    fn rollback_removes_unused_transports() {
+        let (mut a, _b, _) = established();
+        let audio = a.medias().next().unwrap().local_media_id;
+
+        a.add_media(audio, Direction::SendRecv);
+        create_offer(&mut a);
+        assert_eq!(a.transports.len(), 2);
+
+        a.rollback_changes();
+        assert_eq!(a.transports.len(), 1);
+    }
+
+    #[test]
+    fn removed_media_is_offered_with_port_zero() {
+        let (mut a, mut b, media_id) = established();
+        let audio = a.medias().next().unwrap().local_media_id;
+
+        let second = a.add_media(audio, Direction::SendRecv);
+        negotiate(&mut a, &mut b);
+        while a.pop_event().is_some() {}
+
+        a.remove_media(media_id);
+
+        // The m-line of removed media is kept in the offer but rejected (RFC 3264 section 8.2)
+        let offer = negotiate(&mut a, &mut b);
+        assert_eq!(offer.media_descriptions.len(), 2);
+
+        let removed = &offer.media_descriptions[0];
+        assert_eq!(removed.media.port, 0);
+        assert_eq!(removed.mid.as_deref(), Some("0"));
+        assert_ne!(offer.media_descriptions[1].media.port, 0);
+
+        assert!(std::iter::from_fn(|| a.pop_event())
+            .any(|event| matches!(event, Event::MediaRemoved(id) if id == media_id)));
+        assert_eq!(
+            a.medias().map(|media| media.id).collect::<Vec<_>>(),
+            [second]
+        );
+    }
+
+    fn require_rtcp_mux() -> Options {
+        Options {
+            rtcp_mux_policy: RtcpMuxPolicy::Require,
+            ..Options::lan()
+        }
+    }
+
+    /// Receive the offer and create the answer
+    fn answer(answerer: &mut SdpSession, offer: SessionDescription) -> SessionDescription {
+        let state = answerer.receive_sdp_offer(offer).unwrap();
+        handle_transport_changes(answerer);
+        answerer.create_sdp_answer(state)
+    }
+
+    #[test]
+    fn rtcp_mux_only_offer_skips_rtcp_socket() {
+        let (mut session, audio) = session(require_rtcp_mux());
+        session.add_media(audio, Direction::SendRecv);
+
+        let changes = session.transport_changes();
+        assert!(changes
+            .iter()
+            .any(|change| matches!(change, TransportChange::CreateSocket(..))));
+        assert!(!changes
+            .iter()
+            .any(|change| matches!(change, TransportChange::CreateSocketPair(..))));
+
+        for change in changes {
+            if let TransportChange::CreateSocket(transport_id) = change {
+                session.set_transport_ports(transport_id, &[], 10000, None);
+            }
+        }
+
+        let required = session.create_sdp_offer();
+        let audio = &required.media_descriptions[0];
+        assert!(audio.rtcp_mux);
+        assert!(audio.rtcp_mux_only);
+        assert!(audio.rtcp.is_none());
+
+        // Without requiring it, rtcp-mux is offered together with a RTCP port as fallback
+        let negotiated = offer(Options::lan());
+        let audio = &negotiated.media_descriptions[0];
+        assert!(audio.rtcp_mux);
+        assert!(!audio.rtcp_mux_only);
+        assert!(audio.rtcp.is_some());
+    }
+
+    #[test]
+    fn rtcp_mux_only_is_kept_in_subsequent_offers() {
+        let (mut a, audio) = session(require_rtcp_mux());
+        let (mut b, _) = session(Options::lan());
+
+        a.add_media(audio, Direction::SendRecv);
+        let offer = negotiate(&mut a, &mut b);
+        assert!(offer.media_descriptions[0].rtcp_mux_only);
+
+        let offer = negotiate(&mut a, &mut b);
+        assert!(offer.media_descriptions[0].rtcp_mux);
+        assert!(offer.media_descriptions[0].rtcp_mux_only);
+    }
+
+    #[test]
+    fn rtcp_mux_only_is_not_part_of_answer() {
+        let (mut b, _) = session(Options::lan());
+
+        let answer = answer(&mut b, offer(require_rtcp_mux()));
+
+        let audio = &answer.media_descriptions[0];
+        assert_ne!(audio.media.port, 0);
+        assert!(audio.rtcp_mux);
+        assert!(!audio.rtcp_mux_only);
+        assert!(audio.rtcp.is_none());
+    }
+
+    #[test]
+    fn rtcp_mux_only_implies_rtcp_mux() {
+        let (mut b, _) = session(Options::lan());
+
+        let mut offer = offer(require_rtcp_mux());
+        offer.media_descriptions[0].rtcp_mux = false;
+
+        let state = b.receive_sdp_offer(offer).unwrap();
+        let changes = b.transport_changes();
+        assert!(!changes
+            .iter()
+            .any(|change| matches!(change, TransportChange::CreateSocketPair(..))));
+
+        for change in changes {
+            if let TransportChange::CreateSocket(transport_id) = change {
+                b.set_transport_ports(transport_id, &[], 10000, None);
+            }
+        }
+
+        let answer = b.create_sdp_answer(state);
+        assert!(answer.media_descriptions[0].rtcp_mux);
+    }
+
+    /// Two sessions which negotiated a single audio media with a separate RTCP port, because the peer didn't offer
+    /// rtcp-mux
+    fn established_without_rtcp_mux() -> (SdpSession, SdpSession) {
+        let (mut a, audio) = session(Options::lan());
+        let (mut b, _) = session(Options::lan());
+        a.add_media(audio, Direction::SendRecv);
+
+        let mut offer = create_offer(&mut a);
+        offer.media_descriptions[0].rtcp_mux = false;
+        let answer = answer(&mut b, offer);
+        assert!(!answer.media_descriptions[0].rtcp_mux);
+        a.receive_sdp_answer(answer);
+        handle_transport_changes(&mut a);
+
+        while b.pop_event().is_some() {}
+
+        (a, b)
+    }
+
+    #[test]
+    fn reject_rtcp_mux_only_on_transport_without_rtcp_mux() {
+        let (mut a, mut b) = established_without_rtcp_mux();
+        let media_id = b.medias().next().unwrap().id;
+
+        // The peer requires muxing on the same transport
+        let mut reoffer = create_offer(&mut a);
+        reoffer.media_descriptions[0].rtcp_mux = true;
+        reoffer.media_descriptions[0].rtcp_mux_only = true;
+        let rejected = answer(&mut b, reoffer);
+
+        assert_eq!(rejected.media_descriptions[0].media.port, 0);
+        assert!(std::iter::from_fn(|| b.pop_event())
+            .any(|event| matches!(event, Event::MediaRemoved(id) if id == media_id)));
+        assert_eq!(b.medias().count(), 0);
+
+        // The local media can be used again
+        let accepted = answer(&mut b, offer(require_rtcp_mux()));
+        assert_ne!(accepted.media_descriptions[0].media.port, 0);
+    }
+
+    #[test]
+    fn reject_rtcp_mux_only_bundled_on_transport_without_rtcp_mux() {
+        let (mut a, mut b) = established_without_rtcp_mux();
+
+        // Add a second m-line requiring muxing, bundled on the existing transport
+        let mut reoffer = create_offer(&mut a);
+        let mut bundled = reoffer.media_descriptions[0].clone();
+        bundled.mid = Some("1".into());
+        bundled.rtcp_mux = true;
+        bundled.rtcp_mux_only = true;
+        reoffer.media_descriptions.push(bundled);
+        reoffer.group = vec![Group {
+            typ: "BUNDLE".into(),
+            mids: vec!["0".into(), "1".into()],
+        }];
+
+        let answer = answer(&mut b, reoffer);
+
+        assert_ne!(answer.media_descriptions[0].media.port, 0);
+        assert_eq!(answer.media_descriptions[1].media.port, 0);
+        assert_eq!(b.medias().count(), 1);
+    }
+
+    #[test]
+    fn reject_rtcp_mux_only_with_multicast() {
+        let (mut b, _) = session(Options::lan());
+
+        let offer = SessionDescription::parse(&BytesStr::from_static(
+            "v=0\r\n\
+             o=- 1 1 IN IP4 192.168.0.1\r\n\
+             s=-\r\n\
+             c=IN IP4 224.2.1.1/16\r\n\
+             t=0 0\r\n\
+             m=audio 30000 RTP/AVP 0\r\n\
+             a=rtcp-mux\r\n\
+             a=rtcp-mux-only\r\n",
+        ))
+        .unwrap();
+
+        let answer = answer(&mut b, offer);
+        assert_eq!(answer.media_descriptions[0].media.port, 0);
+    }
+
+    /// The ways a peer can put the media on hold
+    fn hold_variants() -> [fn(&mut SessionDescription); 4] {
+        fn set_connection(sess_desc: &mut SessionDescription, address: TaggedAddress) {
+            let connection = Connection {
+                address,
+                ttl: None,
+                num: None,
+            };
+
+            sess_desc.connection = Some(connection.clone());
+            sess_desc.media_descriptions[0].connection = Some(connection);
+        }
+
+        [
+            |sess_desc| sess_desc.media_descriptions[0].direction = Direction::SendOnly,
+            |sess_desc| sess_desc.media_descriptions[0].direction = Direction::Inactive,
+            |sess_desc| {
+                set_connection(
+                    sess_desc,
+                    TaggedAddress::IP4(std::net::Ipv4Addr::UNSPECIFIED),
+                )
+            },
+            |sess_desc| {
+                set_connection(
+                    sess_desc,
+                    TaggedAddress::IP6(std::net::Ipv6Addr::UNSPECIFIED),
+                )
+            },
+        ]
+    }
+
+    fn rtp_packet() -> rtp::RtpPacket {
+        rtp::RtpPacket {
+            pt: 96,
+            marker: false,
+            sequence_number: rtp::SequenceNumber(0),
+            ssrc: Ssrc(0),
+            timestamp: rtp::RtpTimestamp(0),
+            extensions: rtp::RtpExtensions::default(),
+            payload: bytes::Bytes::from_static(&[0; 160]),
+            padding: None,
+        }
+    }
+
+    fn remote_hold(session: &SdpSession) -> bool {
+        session.medias().next().unwrap().remote_hold
+    }
+
+    fn hold_events(session: &mut SdpSession) -> Vec<bool> {
+        std::iter::from_fn(|| session.pop_event())
+            .filter_map(|event| match event {
+                Event::MediaChanged(MediaChanged { hold, .. }) => Some(hold),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn hold_in_offer() {
+        for put_on_hold in hold_variants() {
+            // Initial offer
+            let (mut b, _) = session(Options::lan());
+            let mut hold_offer = offer(Options::lan());
+            put_on_hold(&mut hold_offer);
+            let inactive = hold_offer.media_descriptions[0].direction == Direction::Inactive;
+            let hold_answer = answer(&mut b, hold_offer);
+
+            if inactive {
+                // New media nobody sends or receives on is rejected
+                assert_eq!(hold_answer.media_descriptions[0].media.port, 0);
+            } else {
+                assert!(remote_hold(&b));
+            }
+
+            // Re-offer of established media
+            let (mut a, mut b, _) = established();
+            while b.pop_event().is_some() {}
+            assert!(!remote_hold(&b));
+
+            let mut reoffer = create_offer(&mut a);
+            put_on_hold(&mut reoffer);
+            answer(&mut b, reoffer);
+            assert!(remote_hold(&b));
+            assert_eq!(hold_events(&mut b), [true]);
+
+            // RTP isn't sent while on hold
+            let media_id = b.medias().next().unwrap().id;
+            assert!(!b.send_rtp(media_id, rtp_packet()));
+
+            // Resume
+            negotiate(&mut a, &mut b);
+            assert!(!remote_hold(&b));
+            assert_eq!(hold_events(&mut b), [false]);
+            assert!(b.send_rtp(media_id, rtp_packet()));
+        }
+    }
+
+    #[test]
+    fn hold_in_answer() {
+        for put_on_hold in hold_variants() {
+            // Answer to the initial offer
+            let (mut a, audio) = session(Options::lan());
+            let (mut b, _) = session(Options::lan());
+            a.add_media(audio, Direction::SendRecv);
+            let offer = create_offer(&mut a);
+            let mut hold_answer = answer(&mut b, offer);
+            put_on_hold(&mut hold_answer);
+            let inactive = hold_answer.media_descriptions[0].direction == Direction::Inactive;
+            a.receive_sdp_answer(hold_answer);
+
+            if inactive {
+                assert!(a.medias().next().is_none());
+            } else {
+                assert!(remote_hold(&a));
+            }
+
+            // Answer to a re-offer of established media
+            let (mut a, mut b, media_id) = established();
+            while a.pop_event().is_some() {}
+            assert!(!remote_hold(&a));
+
+            let reoffer = create_offer(&mut a);
+            let mut hold_answer = answer(&mut b, reoffer);
+            put_on_hold(&mut hold_answer);
+            a.receive_sdp_answer(hold_answer);
+            assert!(remote_hold(&a));
+            assert_eq!(hold_events(&mut a), [true]);
+
+            // Resume, the negotiated direction is offered unless changed
+            a.update_media(media_id, Direction::SendRecv);
+            negotiate(&mut a, &mut b);
+            assert!(!remote_hold(&a));
+            assert_eq!(hold_events(&mut a), [false]);
+        }
+    }
+
+    #[test]
+    fn rejected_answer_is_skipped() {
+        let (mut a, mut b, _) = established();
+        while a.pop_event().is_some() {}
+
+        let reoffer = create_offer(&mut a);
+        let mut rejected = answer(&mut b, reoffer);
+        rejected.media_descriptions[0] = MediaDescription::rejected(MediaType::Audio);
+        a.receive_sdp_answer(rejected);
+
+        assert!(!remote_hold(&a));
+        assert!(hold_events(&mut a).is_empty());
+    }
+
+    /// Send an RTP packet with the payload type from the first media of `from` to `to`, returns the events of `to`
+    fn transfer_rtp(from: &mut SdpSession, to: &mut SdpSession, pt: u8, seq: u16) -> Vec<Event> {
+        while from.pop_event().is_some() {}
+
+        let media_id = from.medias().next().unwrap().id;
+        let packet = rtp::RtpPacket {
+            pt,
+            sequence_number: rtp::SequenceNumber(seq),
+            timestamp: rtp::RtpTimestamp(u32::from(seq) * 160),
+            ..rtp_packet()
+        };
+        assert!(from.send_rtp(media_id, packet));
+
+        let transport_id = to.medias().next().unwrap().transport_id;
+
+        while let Some(event) = from.pop_event() {
+            if let Event::SendData {
+                component,
+                data,
+                target,
+                ..
+            } = event
+            {
+                to.receive(
+                    transport_id,
+                    ReceivedPkt {
+                        data: data.to_vec(),
+                        source: "127.0.0.1:10000".parse().unwrap(),
+                        destination: target,
+                        component,
+                    },
+                );
+            }
+        }
+
+        to.poll(Instant::now());
+        std::iter::from_fn(|| to.pop_event()).collect()
+    }
+
+    /// The `unexpected_payload_type` flags of the received RTP packets
+    fn received_rtp(events: &[Event]) -> Vec<bool> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::ReceiveRTP {
+                    unexpected_payload_type,
+                    ..
+                } => Some(*unexpected_payload_type),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn unexpected_payload_types(events: Vec<Event>) -> Vec<UnexpectedPayloadTypeReceived> {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::UnexpectedPayloadType(unexpected) => Some(unexpected),
+                _ => None,
+            })
+            .collect()
+    }
+
+    const PCMU_PT: u8 = 0;
+    const PCMA_PT: u8 = 8;
+
+    #[test]
+    fn unexpected_payload_type_is_dropped_by_default() {
+        let (mut a, mut b, media_id) = established();
+        let opus_pt = a.medias().next().unwrap().payload_type;
+        assert_ne!(opus_pt, PCMU_PT);
+
+        let events = transfer_rtp(&mut b, &mut a, opus_pt, 0);
+        assert_eq!(received_rtp(&events), [false]);
+        assert!(unexpected_payload_types(events).is_empty());
+
+        let events = transfer_rtp(&mut b, &mut a, PCMU_PT, 1);
+        assert!(received_rtp(&events).is_empty());
+        let unexpected = unexpected_payload_types(events);
+        assert_eq!(unexpected.len(), 1);
+        assert_eq!(unexpected[0].media_id, media_id);
+        assert_eq!(unexpected[0].payload_type, PCMU_PT);
+        assert_eq!(unexpected[0].expected_payload_type, opus_pt);
+        assert_eq!(unexpected[0].policy, UnexpectedPayloadTypePolicy::Drop);
+        assert!(!unexpected[0].renegotiate);
+
+        // Reported once per payload type, but every packet is counted
+        let events = transfer_rtp(&mut b, &mut a, PCMU_PT, 2);
+        assert!(received_rtp(&events).is_empty());
+        assert!(unexpected_payload_types(events).is_empty());
+
+        let events = transfer_rtp(&mut b, &mut a, PCMA_PT, 3);
+        assert_eq!(unexpected_payload_types(events).len(), 1);
+
+        transfer_rtp(&mut b, &mut a, opus_pt, 4);
+        assert_eq!(a.unexpected_payload_type_packets(media_id), 3);
+    }
+
+    #[test]
+    fn unexpected_payload_type_is_delivered() {
+        let (mut a, mut b, media_id) = established();
+        a.set_unexpected_payload_type_policy(media_id, UnexpectedPayloadTypePolicy::Deliver);
+
+        let events = transfer_rtp(&mut b, &mut a, PCMU_PT, 0);
+        assert_eq!(received_rtp(&events), [true]);
+        let unexpected = unexpected_payload_types(events);
+        assert_eq!(unexpected.len(), 1);
+        assert_eq!(unexpected[0].policy, UnexpectedPayloadTypePolicy::Deliver);
+        assert!(!unexpected[0].renegotiate);
+
+        let events = transfer_rtp(&mut b, &mut a, PCMU_PT, 1);
+        assert_eq!(received_rtp(&events), [true]);
+        assert_eq!(a.unexpected_payload_type_packets(media_id), 2);
+    }
+
+    #[test]
+    fn unexpected_payload_type_is_renegotiated() {
+        let (mut a, mut b, media_id) = established();
+        let opus_pt = a.medias().next().unwrap().payload_type;
+        a.set_unexpected_payload_type_policy(media_id, UnexpectedPayloadTypePolicy::Renegotiate);
+
+        // PCMA isn't one of the local codecs, there is nothing to switch to
+        let events = transfer_rtp(&mut b, &mut a, PCMA_PT, 0);
+        let unexpected = unexpected_payload_types(events);
+        assert_eq!(unexpected.len(), 1);
+        assert!(!unexpected[0].renegotiate);
+
+        let events = transfer_rtp(&mut b, &mut a, PCMU_PT, 1);
+        assert!(received_rtp(&events).is_empty());
+        let unexpected = unexpected_payload_types(events);
+        assert_eq!(unexpected.len(), 1);
+        assert_eq!(
+            unexpected[0].policy,
+            UnexpectedPayloadTypePolicy::Renegotiate
+        );
+        assert!(unexpected[0].renegotiate);
+
+        // The next offer switches to the codec the peer is sending
+        negotiate(&mut a, &mut b);
+        let media = a.medias().next().unwrap();
+        assert_eq!(media.payload_type, PCMU_PT);
+        assert_eq!(media.codec_name, "PCMU");
+
+        let events = transfer_rtp(&mut b, &mut a, PCMU_PT, 2);
+        assert_eq!(received_rtp(&events), [false]);
+
+        let events = transfer_rtp(&mut b, &mut a, opus_pt, 3);
+        assert!(received_rtp(&events).is_empty());
+        assert_eq!(unexpected_payload_types(events).len(), 1);
+        assert_eq!(a.unexpected_payload_type_packets(media_id), 3);
+    }
+
+    /// Receive an RTP packet from a peer which ignores the negotiated direction, returns the events of `to`
+    fn receive_rtp(to: &mut SdpSession, seq: u16) -> Vec<Event> {
+        let packet = rtp::RtpPacket {
+            pt: to.medias().next().unwrap().payload_type,
+            sequence_number: rtp::SequenceNumber(seq),
+            timestamp: rtp::RtpTimestamp(u32::from(seq) * 160),
+            ..rtp_packet()
+        };
+
+        receive_packet(to, packet.to_vec(rtp::RtpExtensionIds::default()))
+    }
+
+    /// Receive a packet on the transport of the first media of `to`, returns the events of `to`
+    fn receive_packet(to: &mut SdpSession, data: Vec<u8>) -> Vec<Event> {
+        while to.pop_event().is_some() {}
+
+        let transport_id = to.medias().next().unwrap().transport_id;
+        to.receive(
+            transport_id,
+            ReceivedPkt {
+                data,
+                source: "127.0.0.1:10000".parse().unwrap(),
+                destination: "127.0.0.1:10000".parse().unwrap(),
+                component: Component::Rtp,
+            },
+        );
+
+        to.poll(Instant::now());
+        std::iter::from_fn(|| to.pop_event()).collect()
+    }
+
+    fn unexpected_direction_rtp(events: &[Event]) -> Vec<Direction> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::UnexpectedDirectionRtp(unexpected) => Some(unexpected.direction),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn receive_direction_enforcement() {
+        let enforcements = [
+            None,
+            Some(ReceiveDirectionEnforcement::Deliver),
+            Some(ReceiveDirectionEnforcement::Drop),
+            Some(ReceiveDirectionEnforcement::Report),
+        ];
+
+        let directions = [
+            Direction::SendRecv,
+            Direction::RecvOnly,
+            Direction::SendOnly,
+            Direction::Inactive,
+        ];
+
+        for enforcement in enforcements {
+            for direction in directions {
+                let (mut a, mut b, media_id) = established();
+
+                if let Some(enforcement) = enforcement {
+                    a.set_receive_direction_enforcement(media_id, enforcement);
+                }
+
+                a.update_media(media_id, direction);
+                negotiate(&mut a, &mut b);
+                assert_eq!(a.medias().next().unwrap().direction, direction);
+
+                let events = receive_rtp(&mut a, 0);
+                let receiving = matches!(direction, Direction::SendRecv | Direction::RecvOnly);
+
+                // Packets are dropped by default
+                let (delivered, reported) =
+                    match enforcement.unwrap_or(ReceiveDirectionEnforcement::Drop) {
+                        _ if receiving => (true, false),
+                        ReceiveDirectionEnforcement::Deliver => (true, false),
+                        ReceiveDirectionEnforcement::Drop => (false, false),
+                        ReceiveDirectionEnforcement::Report => (false, true),
+                    };
+
+                assert_eq!(
+                    received_rtp(&events).len(),
+                    usize::from(delivered),
+                    "{enforcement:?} {direction:?}"
+                );
+                assert_eq!(
+                    unexpected_direction_rtp(&events),
+                    if reported { vec![direction] } else { vec![] },
+                    "{enforcement:?} {direction:?}"
+                );
+                assert_eq!(
+                    a.unexpected_direction_packets(media_id),
+                    u64::from(!receiving),
+                    "{enforcement:?} {direction:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn receive_direction_enforcement_on_hold() {
+        for put_on_hold in hold_variants() {
+            let (mut a, mut b, _) = established();
+            let media_id = b.medias().next().unwrap().id;
+
+            let mut reoffer = create_offer(&mut a);
+            put_on_hold(&mut reoffer);
+            let inactive = reoffer.media_descriptions[0].direction == Direction::Inactive;
+            answer(&mut b, reoffer);
+            assert!(remote_hold(&b));
+
+            // Only an inactive hold stops receiving, the peer keeps sending otherwise
+            let events = receive_rtp(&mut b, 0);
+            assert_eq!(received_rtp(&events).len(), usize::from(!inactive));
+            assert_eq!(
+                b.unexpected_direction_packets(media_id),
+                u64::from(inactive)
+            );
+
+            b.set_receive_direction_enforcement(media_id, ReceiveDirectionEnforcement::Report);
+            let events = receive_rtp(&mut b, 1);
+            assert_eq!(
+                unexpected_direction_rtp(&events),
+                if inactive {
+                    vec![Direction::Inactive]
+                } else {
+                    vec![]
+                }
+            );
+
+            // Resuming receives again
+            negotiate(&mut a, &mut b);
+            assert!(!remote_hold(&b));
+            let events = receive_rtp(&mut b, 2);
+            assert_eq!(received_rtp(&events).len(), 1);
+            assert!(unexpected_direction_rtp(&events).is_empty());
+            assert_eq!(
+                b.unexpected_direction_packets(media_id),
+                u64::from(inactive) * 2
+            );
+        }
+    }
+
+    fn local_ssrc(session: &SdpSession) -> u32 {
+        session.state[0].rtp_session.ssrc().0
+    }
+
+    /// Receive an RTCP payload-specific feedback packet, returns the events of `to`
+    fn receive_feedback(to: &mut SdpSession, feedback: PayloadFeedbackBuilder<'_>) -> Vec<Event> {
+        let mut data = vec![0; 1500];
+        let len = feedback.write_into(&mut data).unwrap();
+        data.truncate(len);
+
+        receive_packet(to, data)
+    }
+
+    fn pli(media_ssrc: u32) -> PayloadFeedbackBuilder<'static> {
+        PayloadFeedback::builder_owned(Pli::builder()).media_ssrc(media_ssrc)
+    }
+
+    fn fir(entries: &[(u32, u8)]) -> PayloadFeedbackBuilder<'static> {
+        let fir = entries
+            .iter()
+            .fold(Fir::builder(), |fir, &(ssrc, sequence)| {
+                fir.add_ssrc(ssrc, sequence)
+            });
+
+        PayloadFeedback::builder_owned(fir)
+    }
+
+    fn keyframe_requests(events: &[Event]) -> Vec<(MediaId, KeyframeRecovery)> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::KeyframeRequest(request) => Some((request.media_id, request.recovery)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pli_requests_keyframe() {
+        let (mut a, _b, media_id) = established();
+        let ssrc = local_ssrc(&a);
+
+        let events = receive_feedback(&mut a, pli(ssrc));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::Idr)]
+        );
+
+        // Every PLI is a new request
+        let events = receive_feedback(&mut a, pli(ssrc));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::Idr)]
+        );
+
+        a.set_keyframe_recovery(media_id, KeyframeRecovery::IntraRefresh);
+        let events = receive_feedback(&mut a, pli(ssrc));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::IntraRefresh)]
+        );
+
+        // PLIs for other streams are ignored
+        let events = receive_feedback(&mut a, pli(ssrc.wrapping_add(1)));
+        assert!(keyframe_requests(&events).is_empty());
+    }
+
+    #[test]
+    fn fir_requests_keyframe_once_per_sequence_number() {
+        let (mut a, _b, media_id) = established();
+        let ssrc = local_ssrc(&a);
+
+        // FIRs always request an IDR frame
+        a.set_keyframe_recovery(media_id, KeyframeRecovery::IntraRefresh);
+
+        let events = receive_feedback(&mut a, fir(&[(ssrc, 1)]));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::Idr)]
+        );
+
+        // A retransmission of the same request
+        let events = receive_feedback(&mut a, fir(&[(ssrc, 1)]));
+        assert!(keyframe_requests(&events).is_empty());
+
+        let events = receive_feedback(&mut a, fir(&[(ssrc, 2)]));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::Idr)]
+        );
+
+        let events = receive_feedback(&mut a, fir(&[(ssrc, 2)]));
+        assert!(keyframe_requests(&events).is_empty());
+
+        // Entries for other streams are ignored
+        let events = receive_feedback(&mut a, fir(&[(ssrc.wrapping_add(1), 3), (ssrc, 3)]));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::Idr)]
+        );
+    }
+
+    fn rpsi(
+        media_ssrc: u32,
+        payload_type: u8,
+        data: &'static [u8],
+    ) -> PayloadFeedbackBuilder<'static> {
+        let rpsi = Rpsi::builder()
+            .payload_type(payload_type)
+            .native_data(data, 0);
+
+        PayloadFeedback::builder_owned(rpsi).media_ssrc(media_ssrc)
+    }
+
+    fn reference_picture_indications(events: &[Event]) -> Vec<(MediaId, u8, &[u8])> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::ReferencePictureIndication(indication) => Some((
+                    indication.media_id,
+                    indication.payload_type,
+                    &indication.data[..],
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rpsi_enables_long_term_reference_recovery() {
+        let (mut a, _b, media_id) = established();
+        let ssrc = local_ssrc(&a);
+        let pt = a.medias().next().unwrap().payload_type;
+        a.set_keyframe_recovery(media_id, KeyframeRecovery::LongTermReference);
+
+        // No reference was acknowledged yet
+        let events = receive_feedback(&mut a, pli(ssrc));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::Idr)]
+        );
+
+        let events = receive_feedback(&mut a, rpsi(ssrc, pt, &[1, 2, 3, 4, 5, 6]));
+        assert_eq!(
+            reference_picture_indications(&events),
+            [(media_id, pt, &[1, 2, 3, 4, 5, 6][..])]
+        );
+        assert!(keyframe_requests(&events).is_empty());
+
+        let events = receive_feedback(&mut a, pli(ssrc));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::LongTermReference)]
+        );
+
+        // FIRs still request an IDR frame once per sequence number
+        let events = receive_feedback(&mut a, fir(&[(ssrc, 7)]));
+        assert_eq!(
+            keyframe_requests(&events),
+            [(media_id, KeyframeRecovery::Idr)]
+        );
+
+        let events = receive_feedback(&mut a, fir(&[(ssrc, 7)]));
+        assert!(keyframe_requests(&events).is_empty());
+
+        // RPSIs for other streams are ignored
+        let events = receive_feedback(&mut a, rpsi(ssrc.wrapping_add(1), pt, &[7, 8]));
+        assert!(reference_picture_indications(&events).is_empty());
+    }
+
+    #[test]
+    fn fir_sequence_number_wraps() {
+        let (mut a, _b, media_id) = established();
+        let ssrc = local_ssrc(&a);
+
+        for sequence in [254, 255, 0, 1] {
+            let events = receive_feedback(&mut a, fir(&[(ssrc, sequence)]));
+            assert_eq!(
+                keyframe_requests(&events),
+                [(media_id, KeyframeRecovery::Idr)]
+            );
+        }
+
+        let events = receive_feedback(&mut a, fir(&[(ssrc, 1)]));
+        assert!(keyframe_requests(&events).is_empty());
+    }
+
+    #[test]
+    fn send_reference_picture_indication() {
+        let (mut a, mut b, media_id) = established();
+        let b_media_id = b.medias().next().unwrap().id;
+        let pt = a.medias().next().unwrap().payload_type;
+
+        // Nothing to acknowledge before receiving RTP
+        b.send_reference_picture_indication(b_media_id, &[1, 2, 3, 4, 5, 6]);
+        assert!(!std::iter::from_fn(|| b.pop_event())
+            .any(|event| matches!(event, Event::SendData { .. })));
+
+        transfer_rtp(&mut a, &mut b, pt, 0);
+        b.send_reference_picture_indication(b_media_id, &[1, 2, 3, 4, 5, 6]);
+
+        let sent: Vec<_> = std::iter::from_fn(|| b.pop_event())
+            .filter_map(|event| match event {
+                Event::SendData { data, .. } => Some(data),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sent.len(), 1);
+
+        let events = receive_packet(&mut a, sent[0].to_vec());
+        assert_eq!(
+            reference_picture_indications(&events),
+            [(media_id, pt, &[1, 2, 3, 4, 5, 6][..])]
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use super::native::AesCtr;
+use aes::{Aes128, Aes256};
+use ctr::cipher::{InnerIvInit, KeyInit, StreamCipher};
+use ctr::{Ctr128BE, CtrCore};
+use std::io;
+
+/// AES counter mode of the RustCrypto `aes` & `ctr` crates
+///
+/// The expanded AES key is cloned into a new CTR instance for every packet.
+pub(super) enum RustAesCtr {
+    Aes128(Box<Aes128>),
+    Aes256(Box<Aes256>),
+}
+
+impl AesCtr for RustAesCtr {
+    fn new(key: &[u8]) -> io::Result<Self> {
+        match key.len() {
+            16 => Aes128::new_from_slice(key)
+                .map(|aes| Self::Aes128(Box::new(aes)))
+                .ok(),
+            32 => Aes256::new_from_slice(key)
+                .map(|aes| Self::Aes256(Box::new(aes)))
+                .ok(),
+            _ => None,
+        }
+        .ok_or_else(|| io::Error::other("invalid AES key length"))
+    }
+
+    fn apply_keystream(&mut self, iv: &[u8; 16], data: &mut [u8]) -> io::Result<()> {
+        match self {
+            Self::Aes128(aes) => {
+                Ctr128BE::<Aes128>::from_core(CtrCore::inner_iv_init((**aes).clone(), iv.into()))
+                    .apply_keystream(data)
+            }
+            Self::Aes256(aes) => {
+                Ctr128BE::<Aes256>::from_core(CtrCore::inner_iv_init((**aes).clone(), iv.into()))
+                    .apply_keystream(data)
+            }
+        }
+
+        Ok(())
+    }
+}
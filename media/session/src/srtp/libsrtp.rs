@@ -0,0 +1,71 @@
+use super::{SrtpCipher, SrtpDirection, SrtpSession};
+use ::srtp::session::SessionRef;
+use ::srtp::{CryptoPolicy, Session, StreamPolicy};
+use std::io;
+
+pub(super) fn create_session(
+    cipher: SrtpCipher,
+    direction: SrtpDirection,
+    key_and_salt: &[u8],
+) -> io::Result<Box<dyn SrtpSession>> {
+    let policy = StreamPolicy {
+        rtp: rtp_policy(cipher),
+        rtcp: rtcp_policy(cipher),
+        key: key_and_salt,
+        ..Default::default()
+    };
+
+    let session = match direction {
+        SrtpDirection::Inbound => Session::with_inbound_template(policy),
+        SrtpDirection::Outbound => Session::with_outbound_template(policy),
+    }
+    .map_err(io::Error::other)?;
+
+    Ok(Box::new(session))
+}
+
+fn rtp_policy(cipher: SrtpCipher) -> CryptoPolicy {
+    match cipher {
+        SrtpCipher::AesCm128HmacSha1_80 => CryptoPolicy::aes_cm_128_hmac_sha1_80(),
+        SrtpCipher::AesCm128HmacSha1_32 => CryptoPolicy::aes_cm_128_hmac_sha1_32(),
+        SrtpCipher::AesCm192HmacSha1_80 => CryptoPolicy::aes_cm_192_hmac_sha1_80(),
+        SrtpCipher::AesCm192HmacSha1_32 => CryptoPolicy::aes_cm_192_hmac_sha1_32(),
+        SrtpCipher::AesCm256HmacSha1_80 => CryptoPolicy::aes_cm_256_hmac_sha1_80(),
+        SrtpCipher::AesCm256HmacSha1_32 => CryptoPolicy::aes_cm_256_hmac_sha1_32(),
+        SrtpCipher::AeadAes128Gcm => CryptoPolicy::aes_gcm_128_16_auth(),
+        SrtpCipher::AeadAes256Gcm => CryptoPolicy::aes_gcm_256_16_auth(),
+    }
+}
+
+/// SRTCP always uses 80 bit authentication tags (RFC 4568 Section 6.2)
+fn rtcp_policy(cipher: SrtpCipher) -> CryptoPolicy {
+    match cipher {
+        SrtpCipher::AesCm128HmacSha1_32 => CryptoPolicy::aes_cm_128_hmac_sha1_80(),
+        SrtpCipher::AesCm192HmacSha1_32 => CryptoPolicy::aes_cm_192_hmac_sha1_80(),
+        SrtpCipher::AesCm256HmacSha1_32 => CryptoPolicy::aes_cm_256_hmac_sha1_80(),
+        cipher => rtp_policy(cipher),
+    }
+}
+
+impl SrtpSession for Session {
+    fn protect(&mut self, packet: &mut Vec<u8>) -> io::Result<()> {
+        SessionRef::protect(self, packet).map_err(io::Error::other)
+    }
+
+    fn protect_rtcp(&mut self, packet: &mut Vec<u8>) -> io::Result<()> {
+        SessionRef::protect_rtcp(self, packet).map_err(io::Error::other)
+    }
+
+    fn unprotect(&mut self, packet: &mut Vec<u8>) -> io::Result<()> {
+        SessionRef::unprotect(self, packet).map_err(io::Error::other)
+    }
+
+    fn unprotect_rtcp(&mut self, packet: &mut Vec<u8>) -> io::Result<()> {
+        SessionRef::unprotect_rtcp(self, packet).map_err(io::Error::other)
+    }
+
+    fn max_trailer_len(&self) -> usize {
+        // libsrtp reserves room for the largest possible trailer, regardless of the crypto policy
+        ::srtp::sys::SRTP_MAX_TRAILER_LEN as usize
+    }
+}
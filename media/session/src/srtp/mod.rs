@@ -0,0 +1,270 @@
+//! SRTP protection of RTP & RTCP packets with exchangeable crypto backends
+//!
+//! The transports only use the [`SrtpSession`] trait, the implementation behind it is chosen by the
+//! [`SrtpBackend`] set in [`Options::srtp_backend`](crate::Options::srtp_backend). Which backends are available
+//! depends on the enabled cargo features:
+//!
+//! - `libsrtp` (default): [libsrtp](https://github.com/cisco/libsrtp), built from source which requires libclang
+//! - `openssl-srtp`: native implementation using the AES & HMAC primitives of OpenSSL, e.g. for FIPS deployments
+//! - `ring-srtp`: native implementation using [ring](https://github.com/briansmith/ring) for HMAC-SHA1 & AES-GCM,
+//!   AES-CM is taken from the RustCrypto crates as ring does not expose it
+//! - `rust-srtp`: native implementation using the pure Rust [RustCrypto](https://github.com/RustCrypto) crates
+//!
+//! AES-192-CM is never offered or accepted in SDP, the key derivation of the native backends does not match
+//! libsrtp's for it.
+
+use crate::SrtpProfile;
+use sdp_types::SrtpSuite;
+use std::io;
+
+#[cfg(any(feature = "ring-srtp", feature = "rust-srtp"))]
+mod aes_ctr;
+#[cfg(feature = "libsrtp")]
+mod libsrtp;
+#[cfg(any(feature = "openssl-srtp", feature = "ring-srtp", feature = "rust-srtp"))]
+mod native;
+#[cfg(feature = "openssl-srtp")]
+mod openssl;
+#[cfg(feature = "ring-srtp")]
+mod ring;
+#[cfg(feature = "rust-srtp")]
+mod rust_crypto;
+
+#[cfg(not(any(
+    feature = "libsrtp",
+    feature = "openssl-srtp",
+    feature = "ring-srtp",
+    feature = "rust-srtp"
+)))]
+compile_error!(
+    "at least one SRTP backend feature must be enabled: libsrtp, openssl-srtp, ring-srtp or rust-srtp"
+);
+
+/// Implementation used to protect and unprotect SRTP packets
+///
+/// Only backends enabled by their cargo feature are available. The default is the first available one of
+/// `Libsrtp`, `OpenSsl`, `Ring` and `RustCrypto`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SrtpBackend {
+    /// libsrtp, requires the `libsrtp` feature
+    #[cfg(feature = "libsrtp")]
+    #[default]
+    Libsrtp,
+    /// AES & HMAC primitives of OpenSSL, requires the `openssl-srtp` feature
+    #[cfg(feature = "openssl-srtp")]
+    #[cfg_attr(not(feature = "libsrtp"), default)]
+    OpenSsl,
+    /// HMAC & AES-GCM primitives of ring, requires the `ring-srtp` feature
+    #[cfg(feature = "ring-srtp")]
+    #[cfg_attr(not(any(feature = "libsrtp", feature = "openssl-srtp")), default)]
+    Ring,
+    /// Pure Rust RustCrypto crates, requires the `rust-srtp` feature
+    #[cfg(feature = "rust-srtp")]
+    #[cfg_attr(
+        not(any(feature = "libsrtp", feature = "openssl-srtp", feature = "ring-srtp")),
+        default
+    )]
+    RustCrypto,
+}
+
+impl SrtpBackend {
+    /// All backends enabled by cargo features
+    pub(crate) fn available() -> Vec<Self> {
+        vec![
+            #[cfg(feature = "libsrtp")]
+            Self::Libsrtp,
+            #[cfg(feature = "openssl-srtp")]
+            Self::OpenSsl,
+            #[cfg(feature = "ring-srtp")]
+            Self::Ring,
+            #[cfg(feature = "rust-srtp")]
+            Self::RustCrypto,
+        ]
+    }
+
+    /// Create a session protecting (outbound) or unprotecting (inbound) packets using the given master key & salt
+    #[cfg_attr(not(feature = "libsrtp"), allow(unused_variables))]
+    pub(crate) fn create_session(
+        self,
+        cipher: SrtpCipher,
+        direction: SrtpDirection,
+        key_and_salt: &[u8],
+    ) -> io::Result<Box<dyn SrtpSession>> {
+        if key_and_salt.len() != cipher.key_len() + cipher.salt_len() {
+            return Err(io::Error::other(format!(
+                "invalid SRTP master key length {} for {cipher:?}",
+                key_and_salt.len()
+            )));
+        }
+
+        match self {
+            #[cfg(feature = "libsrtp")]
+            Self::Libsrtp => libsrtp::create_session(cipher, direction, key_and_salt),
+            #[cfg(feature = "openssl-srtp")]
+            Self::OpenSsl => native::create_session::<openssl::OpenSsl>(cipher, key_and_salt),
+            #[cfg(feature = "ring-srtp")]
+            Self::Ring => native::create_session::<ring::Ring>(cipher, key_and_salt),
+            #[cfg(feature = "rust-srtp")]
+            Self::RustCrypto => {
+                native::create_session::<rust_crypto::RustCrypto>(cipher, key_and_salt)
+            }
+        }
+    }
+}
+
+/// Protects or unprotects RTP & RTCP packets of a single direction of a transport
+pub(crate) trait SrtpSession: Send {
+    /// Encrypt and authenticate an RTP packet in place, appending the authentication tag
+    fn protect(&mut self, packet: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Encrypt and authenticate an RTCP packet in place, appending the SRTCP index and authentication tag
+    fn protect_rtcp(&mut self, packet: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Authenticate and decrypt an SRTP packet in place, removing the authentication tag
+    fn unprotect(&mut self, packet: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Authenticate and decrypt an SRTCP packet in place, removing the SRTCP index and authentication tag
+    fn unprotect_rtcp(&mut self, packet: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Number of bytes [`protect`](SrtpSession::protect) or [`protect_rtcp`](SrtpSession::protect_rtcp) may append
+    /// to a packet
+    fn max_trailer_len(&self) -> usize;
+
+    /// Number of bytes to reserve for [`protect`](SrtpSession::protect) to append to a RTP packet
+    fn rtp_trailer_len(&self) -> usize {
+        self.max_trailer_len()
+    }
+}
+
+/// Inbound & outbound session of a transport
+pub(crate) type SrtpSessions = (Box<dyn SrtpSession>, Box<dyn SrtpSession>);
+
+/// Whether a [`SrtpSession`] is used to protect or unprotect packets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SrtpDirection {
+    Inbound,
+    Outbound,
+}
+
+/// Crypto suite of an SRTP session, negotiated using SDES or DTLS-SRTP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SrtpCipher {
+    AesCm128HmacSha1_80,
+    AesCm128HmacSha1_32,
+    AesCm192HmacSha1_80,
+    AesCm192HmacSha1_32,
+    AesCm256HmacSha1_80,
+    AesCm256HmacSha1_32,
+    AeadAes128Gcm,
+    AeadAes256Gcm,
+}
+
+impl SrtpCipher {
+    pub(crate) fn from_sdes_suite(suite: &SrtpSuite) -> Option<Self> {
+        match suite {
+            SrtpSuite::AES_CM_128_HMAC_SHA1_80 => Some(Self::AesCm128HmacSha1_80),
+            SrtpSuite::AES_CM_128_HMAC_SHA1_32 => Some(Self::AesCm128HmacSha1_32),
+            SrtpSuite::AES_192_CM_HMAC_SHA1_80 => Some(Self::AesCm192HmacSha1_80),
+            SrtpSuite::AES_192_CM_HMAC_SHA1_32 => Some(Self::AesCm192HmacSha1_32),
+            SrtpSuite::AES_256_CM_HMAC_SHA1_80 => Some(Self::AesCm256HmacSha1_80),
+            SrtpSuite::AES_256_CM_HMAC_SHA1_32 => Some(Self::AesCm256HmacSha1_32),
+            SrtpSuite::AEAD_AES_128_GCM => Some(Self::AeadAes128Gcm),
+            SrtpSuite::AEAD_AES_256_GCM => Some(Self::AeadAes256Gcm),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn from_dtls_profile(profile: SrtpProfile) -> Self {
+        match profile {
+            SrtpProfile::Aes128CmSha1_80 => Self::AesCm128HmacSha1_80,
+            SrtpProfile::Aes128CmSha1_32 => Self::AesCm128HmacSha1_32,
+            SrtpProfile::AeadAes128Gcm => Self::AeadAes128Gcm,
+            SrtpProfile::AeadAes256Gcm => Self::AeadAes256Gcm,
+        }
+    }
+
+    /// Length of the master key in bytes
+    pub(crate) fn key_len(&self) -> usize {
+        match self {
+            Self::AesCm128HmacSha1_80 | Self::AesCm128HmacSha1_32 | Self::AeadAes128Gcm => 16,
+            Self::AesCm192HmacSha1_80 | Self::AesCm192HmacSha1_32 => 24,
+            Self::AesCm256HmacSha1_80 | Self::AesCm256HmacSha1_32 | Self::AeadAes256Gcm => 32,
+        }
+    }
+
+    /// Length of the master salt in bytes
+    pub(crate) fn salt_len(&self) -> usize {
+        if self.is_aead() {
+            12
+        } else {
+            14
+        }
+    }
+
+    pub(crate) fn is_aead(&self) -> bool {
+        matches!(self, Self::AeadAes128Gcm | Self::AeadAes256Gcm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every suite implemented by all backends
+    const CIPHERS: [SrtpCipher; 6] = [
+        SrtpCipher::AesCm128HmacSha1_80,
+        SrtpCipher::AesCm128HmacSha1_32,
+        SrtpCipher::AesCm256HmacSha1_80,
+        SrtpCipher::AesCm256HmacSha1_32,
+        SrtpCipher::AeadAes128Gcm,
+        SrtpCipher::AeadAes256Gcm,
+    ];
+
+    #[test]
+    fn backends_interoperate() {
+        let rtp = [
+            &[
+                0x80, 0x00, 0x12, 0x34, 0, 0, 0x03, 0x20, 0xde, 0xad, 0xbe, 0xef,
+            ][..],
+            &[0x55; 160],
+        ]
+        .concat();
+        let rtcp = [
+            &[0x81, 0xc9, 0x00, 0x07, 0xde, 0xad, 0xbe, 0xef][..],
+            &[0x66; 24],
+        ]
+        .concat();
+
+        for cipher in CIPHERS {
+            let key: Vec<u8> = (0..cipher.key_len() + cipher.salt_len())
+                .map(|i| (i * 7) as u8)
+                .collect();
+
+            for sender in SrtpBackend::available() {
+                for receiver in SrtpBackend::available() {
+                    let mut outbound = sender
+                        .create_session(cipher, SrtpDirection::Outbound, &key)
+                        .unwrap();
+                    let mut inbound = receiver
+                        .create_session(cipher, SrtpDirection::Inbound, &key)
+                        .unwrap();
+
+                    let mut packet = rtp.clone();
+                    outbound.protect(&mut packet).unwrap();
+                    inbound.unprotect(&mut packet).unwrap_or_else(|e| {
+                        panic!("{cipher:?} RTP from {sender:?} to {receiver:?}: {e}")
+                    });
+                    assert_eq!(packet, rtp);
+
+                    let mut packet = rtcp.clone();
+                    outbound.protect_rtcp(&mut packet).unwrap();
+                    assert!(packet.len() - rtcp.len() <= outbound.max_trailer_len());
+                    inbound.unprotect_rtcp(&mut packet).unwrap_or_else(|e| {
+                        panic!("{cipher:?} RTCP from {sender:?} to {receiver:?}: {e}")
+                    });
+                    assert_eq!(packet, rtcp);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,865 @@
+//! SRTP ([RFC3711](https://www.rfc-editor.org/rfc/rfc3711.html)) and AES-GCM SRTP
+//! ([RFC7714](https://www.rfc-editor.org/rfc/rfc7714.html)) implemented on top of the primitives of a [`Crypto`]
+//! backend
+
+use super::{SrtpCipher, SrtpSession};
+use std::collections::HashMap;
+use std::io;
+
+/// Cryptographic primitives required to implement SRTP
+pub(super) trait Crypto: 'static {
+    type AesCtr: AesCtr;
+    type HmacSha1: HmacSha1;
+    type AesGcm: AesGcm;
+}
+
+/// AES in counter mode, as used by the SRTP key derivation and AES-CM ciphers
+pub(super) trait AesCtr: Send + Sized {
+    fn new(key: &[u8]) -> io::Result<Self>;
+
+    /// XOR `data` with the keystream starting at the counter block `iv`
+    fn apply_keystream(&mut self, iv: &[u8; 16], data: &mut [u8]) -> io::Result<()>;
+}
+
+pub(super) trait HmacSha1: Send + Sized {
+    fn new(key: &[u8]) -> io::Result<Self>;
+
+    fn sign(&mut self, data: &[u8]) -> io::Result<[u8; 20]>;
+}
+
+/// AES-GCM with a 12 byte nonce and 16 byte authentication tag
+pub(super) trait AesGcm: Send + Sized {
+    fn new(key: &[u8]) -> io::Result<Self>;
+
+    /// Encrypt `data` in place, returns the authentication tag
+    fn seal(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut [u8]) -> io::Result<[u8; 16]>;
+
+    /// Verify the authentication tag and decrypt `data` in place
+    fn open(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut [u8], tag: &[u8])
+        -> io::Result<()>;
+}
+
+// Key derivation labels (RFC 3711 Section 4.3.1)
+const LABEL_RTP_ENCRYPTION: u8 = 0x00;
+const LABEL_RTP_AUTH: u8 = 0x01;
+const LABEL_RTP_SALT: u8 = 0x02;
+const LABEL_RTCP_ENCRYPTION: u8 = 0x03;
+const LABEL_RTCP_AUTH: u8 = 0x04;
+const LABEL_RTCP_SALT: u8 = 0x05;
+
+const AUTH_KEY_LEN: usize = 20;
+const GCM_TAG_LEN: usize = 16;
+const SRTCP_INDEX_LEN: usize = 4;
+const SRTCP_E_FLAG: u32 = 0x8000_0000;
+const RTP_HEADER_LEN: usize = 12;
+const RTCP_HEADER_LEN: usize = 8;
+const REPLAY_WINDOW_SIZE: u64 = 64;
+
+impl SrtpCipher {
+    /// Length of the authentication tag appended to RTP packets
+    fn rtp_tag_len(&self) -> usize {
+        match self {
+            Self::AesCm128HmacSha1_80 | Self::AesCm192HmacSha1_80 | Self::AesCm256HmacSha1_80 => 10,
+            Self::AesCm128HmacSha1_32 | Self::AesCm192HmacSha1_32 | Self::AesCm256HmacSha1_32 => 4,
+            Self::AeadAes128Gcm | Self::AeadAes256Gcm => 16,
+        }
+    }
+
+    /// Length of the authentication tag appended to RTCP packets, the 32 bit suites still use 80 bit tags for RTCP
+    fn rtcp_tag_len(&self) -> usize {
+        if self.is_aead() {
+            16
+        } else {
+            10
+        }
+    }
+}
+
+pub(super) fn create_session<C: Crypto>(
+    cipher: SrtpCipher,
+    key_and_salt: &[u8],
+) -> io::Result<Box<dyn SrtpSession>> {
+    let (master_key, master_salt) = key_and_salt.split_at(cipher.key_len());
+
+    // The AES-CM PRF uses a 112 bit salt, the shorter salt of the GCM ciphers is padded with zeros
+    let mut salt = [0u8; 14];
+    salt[..master_salt.len()].copy_from_slice(master_salt);
+
+    let mut prf = C::AesCtr::new(master_key)?;
+
+    Ok(Box::new(NativeSession::<C> {
+        cipher,
+        rtp: Keys::derive(
+            &mut prf,
+            &salt,
+            cipher,
+            [LABEL_RTP_ENCRYPTION, LABEL_RTP_AUTH, LABEL_RTP_SALT],
+        )?,
+        rtcp: Keys::derive(
+            &mut prf,
+            &salt,
+            cipher,
+            [LABEL_RTCP_ENCRYPTION, LABEL_RTCP_AUTH, LABEL_RTCP_SALT],
+        )?,
+        streams: HashMap::new(),
+    }))
+}
+
+/// Session keys of either RTP or RTCP
+enum Keys<C: Crypto> {
+    AesCm {
+        aes: C::AesCtr,
+        hmac: C::HmacSha1,
+        salt: [u8; 14],
+    },
+    AesGcm {
+        aes: C::AesGcm,
+        salt: [u8; 12],
+    },
+}
+
+impl<C: Crypto> Keys<C> {
+    fn derive(
+        prf: &mut C::AesCtr,
+        master_salt: &[u8; 14],
+        cipher: SrtpCipher,
+        [encryption_label, auth_label, salt_label]: [u8; 3],
+    ) -> io::Result<Self> {
+        let mut key = [0u8; 32];
+        let key = &mut key[..cipher.key_len()];
+        derive_key(prf, master_salt, encryption_label, key)?;
+
+        if cipher.is_aead() {
+            let mut salt = [0u8; 12];
+            derive_key(prf, master_salt, salt_label, &mut salt)?;
+
+            Ok(Self::AesGcm {
+                aes: C::AesGcm::new(key)?,
+                salt,
+            })
+        } else {
+            let mut auth_key = [0u8; AUTH_KEY_LEN];
+            derive_key(prf, master_salt, auth_label, &mut auth_key)?;
+
+            let mut salt = [0u8; 14];
+            derive_key(prf, master_salt, salt_label, &mut salt)?;
+
+            Ok(Self::AesCm {
+                aes: C::AesCtr::new(key)?,
+                hmac: C::HmacSha1::new(&auth_key)?,
+                salt,
+            })
+        }
+    }
+}
+
+/// AES-CM PRF with a key derivation rate of zero (RFC 3711 Section 4.3)
+fn derive_key<A: AesCtr>(
+    prf: &mut A,
+    master_salt: &[u8; 14],
+    label: u8,
+    out: &mut [u8],
+) -> io::Result<()> {
+    let mut iv = [0u8; 16];
+    iv[..14].copy_from_slice(master_salt);
+    iv[7] ^= label;
+
+    out.fill(0);
+    prf.apply_keystream(&iv, out)
+}
+
+struct NativeSession<C: Crypto> {
+    cipher: SrtpCipher,
+    rtp: Keys<C>,
+    rtcp: Keys<C>,
+    streams: HashMap<u32, Stream>,
+}
+
+/// Per SSRC state
+#[derive(Default)]
+struct Stream {
+    /// Packet indices of received RTP packets, or the index of the last sent RTP packet
+    rtp: ReplayWindow,
+    /// Indices of received SRTCP packets
+    rtcp: ReplayWindow,
+    /// Index of the next sent SRTCP packet
+    next_srtcp_index: u32,
+}
+
+impl<C: Crypto> SrtpSession for NativeSession<C> {
+    fn protect(&mut self, packet: &mut Vec<u8>) -> io::Result<()> {
+        let header_len = rtp_header_len(packet)?;
+        let ssrc = read_u32(&packet[8..]);
+        let sequence_number = u16::from_be_bytes([packet[2], packet[3]]);
+
+        let stream = self.streams.entry(ssrc).or_default();
+        let index = stream.rtp.estimate_index(sequence_number);
+        stream.rtp.update(index);
+
+        let roc = (index >> 16) as u32;
+
+        match &mut self.rtp {
+            Keys::AesCm { aes, hmac, salt } => {
+                aes.apply_keystream(&aes_cm_iv(salt, ssrc, index), &mut packet[header_len..])?;
+
+                // The authenticated portion is the packet followed by the ROC
+                let len = packet.len();
+                packet.extend_from_slice(&roc.to_be_bytes());
+                let tag = hmac.sign(packet)?;
+                packet.truncate(len);
+                packet.extend_from_slice(&tag[..self.cipher.rtp_tag_len()]);
+            }
+            Keys::AesGcm { aes, salt } => {
+                let nonce = rtp_gcm_nonce(salt, ssrc, roc, sequence_number);
+
+                let (header, payload) = packet.split_at_mut(header_len);
+                let tag = aes.seal(&nonce, header, payload)?;
+                packet.extend_from_slice(&tag);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn protect_rtcp(&mut self, packet: &mut Vec<u8>) -> io::Result<()> {
+        if packet.len() < RTCP_HEADER_LEN {
+            return Err(invalid("RTCP packet too short"));
+        }
+
+        let ssrc = read_u32(&packet[4..]);
+
+        let stream = self.streams.entry(ssrc).or_default();
+        let index = stream.next_srtcp_index;
+        stream.next_srtcp_index = (index + 1) & !SRTCP_E_FLAG;
+
+        let e_index = (index | SRTCP_E_FLAG).to_be_bytes();
+
+        match &mut self.rtcp {
+            Keys::AesCm { aes, hmac, salt } => {
+                aes.apply_keystream(
+                    &aes_cm_iv(salt, ssrc, u64::from(index)),
+                    &mut packet[RTCP_HEADER_LEN..],
+                )?;
+
+                packet.extend_from_slice(&e_index);
+                let tag = hmac.sign(packet)?;
+                packet.extend_from_slice(&tag[..self.cipher.rtcp_tag_len()]);
+            }
+            Keys::AesGcm { aes, salt } => {
+                let nonce = rtcp_gcm_nonce(salt, ssrc, index);
+                let aad = rtcp_gcm_aad(&packet[..RTCP_HEADER_LEN], e_index);
+
+                let tag = aes.seal(&nonce, &aad, &mut packet[RTCP_HEADER_LEN..])?;
+                packet.extend_from_slice(&tag);
+                packet.extend_from_slice(&e_index);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unprotect(&mut self, packet: &mut Vec<u8>) -> io::Result<()> {
+        if packet.len() < RTP_HEADER_LEN {
+            return Err(invalid("SRTP packet too short"));
+        }
+
+        let ssrc = read_u32(&packet[8..]);
+
+        // Only keep the state of new streams once a packet was authenticated
+        let known = self.streams.contains_key(&ssrc);
+        let stream = self.streams.entry(ssrc).or_default();
+
+        let result = unprotect_rtp(&mut self.rtp, self.cipher, stream, ssrc, packet);
+
+        if result.is_err() && !known {
+            self.streams.remove(&ssrc);
+        }
+
+        result
+    }
+
+    fn unprotect_rtcp(&mut self, packet: &mut Vec<u8>) -> io::Result<()> {
+        if packet.len() < RTCP_HEADER_LEN {
+            return Err(invalid("SRTCP packet too short"));
+        }
+
+        let ssrc = read_u32(&packet[4..]);
+
+        let known = self.streams.contains_key(&ssrc);
+        let stream = self.streams.entry(ssrc).or_default();
+
+        let result = unprotect_rtcp(&mut self.rtcp, self.cipher, stream, ssrc, packet);
+
+        if result.is_err() && !known {
+            self.streams.remove(&ssrc);
+        }
+
+        result
+    }
+
+    fn max_trailer_len(&self) -> usize {
+        self.cipher
+            .rtp_tag_len()
+            .max(SRTCP_INDEX_LEN + self.cipher.rtcp_tag_len())
+    }
+
+    fn rtp_trailer_len(&self) -> usize {
+        self.cipher.rtp_tag_len()
+    }
+}
+
+fn unprotect_rtp<C: Crypto>(
+    keys: &mut Keys<C>,
+    cipher: SrtpCipher,
+    stream: &mut Stream,
+    ssrc: u32,
+    packet: &mut Vec<u8>,
+) -> io::Result<()> {
+    let tag_len = cipher.rtp_tag_len();
+
+    if packet.len() < RTP_HEADER_LEN + tag_len {
+        return Err(invalid("SRTP packet too short"));
+    }
+
+    let auth_len = packet.len() - tag_len;
+    let header_len = rtp_header_len(&packet[..auth_len])?;
+    let sequence_number = u16::from_be_bytes([packet[2], packet[3]]);
+
+    let index = stream.rtp.estimate_index(sequence_number);
+    stream.rtp.check(index)?;
+
+    let roc = (index >> 16) as u32;
+
+    let mut tag = [0u8; GCM_TAG_LEN];
+    let tag = &mut tag[..tag_len];
+    tag.copy_from_slice(&packet[auth_len..]);
+    packet.truncate(auth_len);
+
+    match keys {
+        Keys::AesCm { aes, hmac, salt } => {
+            packet.extend_from_slice(&roc.to_be_bytes());
+            let expected_tag = hmac.sign(packet)?;
+            packet.truncate(auth_len);
+
+            if !constant_time_eq(&expected_tag[..tag_len], tag) {
+                return Err(invalid("SRTP authentication failed"));
+            }
+
+            aes.apply_keystream(&aes_cm_iv(salt, ssrc, index), &mut packet[header_len..])?;
+        }
+        Keys::AesGcm { aes, salt } => {
+            let nonce = rtp_gcm_nonce(salt, ssrc, roc, sequence_number);
+
+            let (header, payload) = packet.split_at_mut(header_len);
+            aes.open(&nonce, header, payload, tag)?;
+        }
+    }
+
+    stream.rtp.update(index);
+
+    Ok(())
+}
+
+fn unprotect_rtcp<C: Crypto>(
+    keys: &mut Keys<C>,
+    cipher: SrtpCipher,
+    stream: &mut Stream,
+    ssrc: u32,
+    packet: &mut Vec<u8>,
+) -> io::Result<()> {
+    let tag_len = cipher.rtcp_tag_len();
+
+    if packet.len() < RTCP_HEADER_LEN + SRTCP_INDEX_LEN + tag_len {
+        return Err(invalid("SRTCP packet too short"));
+    }
+
+    let mut tag = [0u8; GCM_TAG_LEN];
+    let tag = &mut tag[..tag_len];
+
+    let e_index = match keys {
+        // header || ciphertext || E || index || tag
+        Keys::AesCm { .. } => {
+            let auth_len = packet.len() - tag_len;
+            tag.copy_from_slice(&packet[auth_len..]);
+            packet.truncate(auth_len);
+            read_u32(&packet[auth_len - SRTCP_INDEX_LEN..])
+        }
+        // header || ciphertext || tag || E || index
+        Keys::AesGcm { .. } => {
+            let e_index = read_u32(&packet[packet.len() - SRTCP_INDEX_LEN..]);
+            packet.truncate(packet.len() - SRTCP_INDEX_LEN);
+
+            let payload_len = packet.len() - tag_len;
+            tag.copy_from_slice(&packet[payload_len..]);
+            packet.truncate(payload_len);
+            e_index
+        }
+    };
+
+    if e_index & SRTCP_E_FLAG == 0 {
+        return Err(invalid("unencrypted SRTCP packets are not supported"));
+    }
+
+    let index = e_index & !SRTCP_E_FLAG;
+    stream.rtcp.check(u64::from(index))?;
+
+    match keys {
+        Keys::AesCm { aes, hmac, salt } => {
+            // The E flag & index are part of the authenticated portion
+            let expected_tag = hmac.sign(packet)?;
+            packet.truncate(packet.len() - SRTCP_INDEX_LEN);
+
+            if !constant_time_eq(&expected_tag[..tag_len], tag) {
+                return Err(invalid("SRTCP authentication failed"));
+            }
+
+            aes.apply_keystream(
+                &aes_cm_iv(salt, ssrc, u64::from(index)),
+                &mut packet[RTCP_HEADER_LEN..],
+            )?;
+        }
+        Keys::AesGcm { aes, salt } => {
+            let nonce = rtcp_gcm_nonce(salt, ssrc, index);
+            let aad = rtcp_gcm_aad(&packet[..RTCP_HEADER_LEN], e_index.to_be_bytes());
+
+            aes.open(&nonce, &aad, &mut packet[RTCP_HEADER_LEN..], tag)?;
+        }
+    }
+
+    stream.rtcp.update(u64::from(index));
+
+    Ok(())
+}
+
+/// Sliding window of received packet indices, used to detect replayed packets (RFC 3711 Section 3.3.2)
+#[derive(Default)]
+struct ReplayWindow {
+    highest: Option<u64>,
+    /// Bit `n` is set if the packet with the index `highest - n` was received
+    received: u64,
+}
+
+impl ReplayWindow {
+    /// Estimate the packet index of an RTP packet from its sequence number (RFC 3711 Appendix A)
+    fn estimate_index(&self, sequence_number: u16) -> u64 {
+        let Some(highest) = self.highest else {
+            return u64::from(sequence_number);
+        };
+
+        let roc = highest >> 16;
+        let highest_sequence_number = highest as u16;
+
+        let roc = if highest_sequence_number < 0x8000 {
+            if sequence_number > highest_sequence_number
+                && sequence_number - highest_sequence_number > 0x8000
+            {
+                roc.saturating_sub(1)
+            } else {
+                roc
+            }
+        } else if highest_sequence_number - 0x8000 > sequence_number {
+            roc + 1
+        } else {
+            roc
+        };
+
+        (roc << 16) | u64::from(sequence_number)
+    }
+
+    fn check(&self, index: u64) -> io::Result<()> {
+        let Some(highest) = self.highest else {
+            return Ok(());
+        };
+
+        if index > highest {
+            return Ok(());
+        }
+
+        let delta = highest - index;
+
+        if delta >= REPLAY_WINDOW_SIZE {
+            Err(invalid("packet index is too old"))
+        } else if self.received & (1 << delta) != 0 {
+            Err(invalid("replayed packet"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn update(&mut self, index: u64) {
+        match self.highest {
+            Some(highest) if index <= highest => {
+                let delta = highest - index;
+
+                if delta < REPLAY_WINDOW_SIZE {
+                    self.received |= 1 << delta;
+                }
+            }
+            Some(highest) => {
+                let shift = index - highest;
+
+                self.received = if shift < REPLAY_WINDOW_SIZE {
+                    (self.received << shift) | 1
+                } else {
+                    1
+                };
+                self.highest = Some(index);
+            }
+            None => {
+                self.received = 1;
+                self.highest = Some(index);
+            }
+        }
+    }
+}
+
+fn rtp_header_len(packet: &[u8]) -> io::Result<usize> {
+    if packet.len() < RTP_HEADER_LEN {
+        return Err(invalid("RTP packet too short"));
+    }
+
+    let csrc_count = usize::from(packet[0] & 0x0F);
+    let mut len = RTP_HEADER_LEN + csrc_count * 4;
+
+    if packet[0] & 0x10 != 0 {
+        if packet.len() < len + 4 {
+            return Err(invalid("RTP packet too short"));
+        }
+
+        let extension_len = usize::from(u16::from_be_bytes([packet[len + 2], packet[len + 3]]));
+        len += 4 + extension_len * 4;
+    }
+
+    if packet.len() < len {
+        return Err(invalid("RTP packet too short"));
+    }
+
+    Ok(len)
+}
+
+/// IV of the AES-CM cipher (RFC 3711 Section 4.1.1)
+fn aes_cm_iv(salt: &[u8; 14], ssrc: u32, index: u64) -> [u8; 16] {
+    let mut iv = [0u8; 16];
+    iv[..14].copy_from_slice(salt);
+
+    xor(&mut iv[4..8], &ssrc.to_be_bytes());
+    xor(&mut iv[8..14], &index.to_be_bytes()[2..]);
+
+    iv
+}
+
+/// Nonce of AES-GCM SRTP (RFC 7714 Section 8.1)
+fn rtp_gcm_nonce(salt: &[u8; 12], ssrc: u32, roc: u32, sequence_number: u16) -> [u8; 12] {
+    let mut nonce = *salt;
+
+    xor(&mut nonce[2..6], &ssrc.to_be_bytes());
+    xor(&mut nonce[6..10], &roc.to_be_bytes());
+    xor(&mut nonce[10..], &sequence_number.to_be_bytes());
+
+    nonce
+}
+
+/// Nonce of AES-GCM SRTCP (RFC 7714 Section 9.1)
+fn rtcp_gcm_nonce(salt: &[u8; 12], ssrc: u32, index: u32) -> [u8; 12] {
+    let mut nonce = *salt;
+
+    xor(&mut nonce[2..6], &ssrc.to_be_bytes());
+    xor(&mut nonce[8..], &index.to_be_bytes());
+
+    nonce
+}
+
+/// Additional authenticated data of AES-GCM SRTCP, the header followed by the E flag & SRTCP index
+fn rtcp_gcm_aad(header: &[u8], e_index: [u8; 4]) -> [u8; RTCP_HEADER_LEN + SRTCP_INDEX_LEN] {
+    let mut aad = [0u8; RTCP_HEADER_LEN + SRTCP_INDEX_LEN];
+    aad[..RTCP_HEADER_LEN].copy_from_slice(header);
+    aad[RTCP_HEADER_LEN..].copy_from_slice(&e_index);
+    aad
+}
+
+fn xor(dst: &mut [u8], src: &[u8]) {
+    for (dst, src) in dst.iter_mut().zip(src) {
+        *dst ^= src;
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn derive<C: Crypto>(master_key: &str, master_salt: &str, label: u8, len: usize) -> Vec<u8> {
+        let mut prf = C::AesCtr::new(&hex(master_key)).unwrap();
+        let salt: [u8; 14] = hex(master_salt).try_into().unwrap();
+
+        let mut out = vec![0u8; len];
+        derive_key(&mut prf, &salt, label, &mut out).unwrap();
+        out
+    }
+
+    /// RFC 3711 Appendix B.3
+    fn key_derivation_aes_128<C: Crypto>() {
+        let master_key = "E1F97A0D3E018BE0D64FA32C06DE4139";
+        let master_salt = "0EC675AD498AFEEBB6960B3AABE6";
+
+        assert_eq!(
+            derive::<C>(master_key, master_salt, LABEL_RTP_ENCRYPTION, 16),
+            hex("C61E7A93744F39EE10734AFE3FF7A087")
+        );
+        assert_eq!(
+            derive::<C>(master_key, master_salt, LABEL_RTP_SALT, 14),
+            hex("30CBBC08863D8C85D49DB34A9AE1")
+        );
+        assert_eq!(
+            derive::<C>(master_key, master_salt, LABEL_RTP_AUTH, AUTH_KEY_LEN),
+            hex("CEBE321F6FF7716B6FD4AB49AF256A156D38BAA4")
+        );
+    }
+
+    /// RFC 6188 Section 7.2
+    fn key_derivation_aes_256<C: Crypto>() {
+        let master_key = "f0f04914b513f2763a1b1fa130f10e2998f6f6e43e4309d1e622a0e332b9f1b6";
+        let master_salt = "3b04803de51ee7c96423ab5b78d2";
+
+        assert_eq!(
+            derive::<C>(master_key, master_salt, LABEL_RTP_ENCRYPTION, 32),
+            hex("5ba1064e30ec51613cad926c5a28ef731ec7fb397f70a960653caf06554cd8c4")
+        );
+        assert_eq!(
+            derive::<C>(master_key, master_salt, LABEL_RTP_SALT, 14),
+            hex("fa31791685ca444a9e07c6c64e93")
+        );
+        assert_eq!(
+            derive::<C>(master_key, master_salt, LABEL_RTP_AUTH, AUTH_KEY_LEN),
+            hex("fd9c32d39ed5fbb5a9dc96b30818454d1313dc05")
+        );
+    }
+
+    /// RFC 3711 Appendix B.2
+    fn aes_cm_keystream<C: Crypto>() {
+        let mut aes = C::AesCtr::new(&hex("2B7E151628AED2A6ABF7158809CF4F3C")).unwrap();
+        let salt: [u8; 14] = hex("F0F1F2F3F4F5F6F7F8F9FAFBFCFD").try_into().unwrap();
+
+        let mut keystream = [0u8; 48];
+        aes.apply_keystream(&aes_cm_iv(&salt, 0, 0), &mut keystream)
+            .unwrap();
+
+        assert_eq!(
+            keystream[..],
+            hex("E03EAD0935C95E80E166B16DD92B4EB4
+                 D23513162B02D0F72A43A2FE4A5F97AB
+                 41E95B3BB0A2E8DD477901E4FCA894C0")
+        );
+    }
+
+    fn gcm_session<C: Crypto>(cipher: SrtpCipher, key: &str, salt: &str) -> NativeSession<C> {
+        let keys = || Keys::AesGcm {
+            aes: C::AesGcm::new(&hex(key)).unwrap(),
+            salt: hex(salt).try_into().unwrap(),
+        };
+
+        NativeSession {
+            cipher,
+            rtp: keys(),
+            rtcp: keys(),
+            streams: HashMap::new(),
+        }
+    }
+
+    /// RFC 7714 Section 16.1.1 & 16.2.1
+    fn aes_gcm_rtp<C: Crypto>() {
+        let header = hex("8040f17b8041f8d35501a0b2");
+        let plaintext =
+            hex("47616c6c696120657374206f6d6e697320646976697361 20696e207061727465732074726573");
+
+        let vectors = [
+            (
+                SrtpCipher::AeadAes128Gcm,
+                "000102030405060708090a0b0c0d0e0f",
+                "f24de3a3fb34de6cacba861c9d7e4bcabe633bd50d294e6f42a5f47a51c7d19b36de3adf8833
+                 899d7f27beb16a9152cf765ee4390cce",
+            ),
+            (
+                SrtpCipher::AeadAes256Gcm,
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "32b1de78a822fe12ef9f78fa332e33aab18012389a58e2f3b50b2a0276ffae0f1ba63799b87b
+                 7aa3db36dfffd6b0f9bb7878d7a76c13",
+            ),
+        ];
+
+        for (cipher, key, expected) in vectors {
+            let mut outbound = gcm_session::<C>(cipher, key, "517569642070726f2071756f");
+            let mut inbound = gcm_session::<C>(cipher, key, "517569642070726f2071756f");
+
+            let mut packet = [&header[..], &plaintext[..]].concat();
+            outbound.protect(&mut packet).unwrap();
+            assert_eq!(packet[..12], header[..]);
+            assert_eq!(packet[12..], hex(expected)[..]);
+
+            inbound.unprotect(&mut packet).unwrap();
+            assert_eq!(packet, [&header[..], &plaintext[..]].concat());
+        }
+    }
+
+    const CIPHERS: [SrtpCipher; 6] = [
+        SrtpCipher::AesCm128HmacSha1_80,
+        SrtpCipher::AesCm128HmacSha1_32,
+        SrtpCipher::AesCm256HmacSha1_80,
+        SrtpCipher::AesCm256HmacSha1_32,
+        SrtpCipher::AeadAes128Gcm,
+        SrtpCipher::AeadAes256Gcm,
+    ];
+
+    fn master_key(cipher: SrtpCipher) -> Vec<u8> {
+        (0..cipher.key_len() + cipher.salt_len())
+            .map(|i| i as u8)
+            .collect()
+    }
+
+    fn rtp_packet(sequence_number: u16) -> Vec<u8> {
+        let mut packet = hex("80000000 00000000 deadbeef");
+        packet[2..4].copy_from_slice(&sequence_number.to_be_bytes());
+        packet.extend_from_slice(&[0xAB; 160]);
+        packet
+    }
+
+    fn rtcp_packet() -> Vec<u8> {
+        // Receiver report with one report block
+        let mut packet = hex("81c90007 deadbeef");
+        packet.extend_from_slice(&[0xCD; 24]);
+        packet
+    }
+
+    fn roundtrip<C: Crypto>() {
+        for cipher in CIPHERS {
+            let key = master_key(cipher);
+            let mut outbound = create_session::<C>(cipher, &key).unwrap();
+            let mut inbound = create_session::<C>(cipher, &key).unwrap();
+
+            // Crosses a sequence number rollover
+            for sequence_number in [0xFFFE, 0xFFFF, 0, 1] {
+                let plain = rtp_packet(sequence_number);
+                let mut packet = plain.clone();
+
+                outbound.protect(&mut packet).unwrap();
+                assert_ne!(packet[12..plain.len()], plain[12..]);
+                assert_eq!(packet.len(), plain.len() + cipher.rtp_tag_len());
+                assert!(packet.len() - plain.len() <= outbound.max_trailer_len());
+                assert_eq!(packet.len() - plain.len(), outbound.rtp_trailer_len());
+
+                inbound.unprotect(&mut packet).unwrap();
+                assert_eq!(packet, plain, "{cipher:?}");
+            }
+
+            for _ in 0..2 {
+                let plain = rtcp_packet();
+                let mut packet = plain.clone();
+
+                outbound.protect_rtcp(&mut packet).unwrap();
+                assert_eq!(
+                    packet.len(),
+                    plain.len() + SRTCP_INDEX_LEN + cipher.rtcp_tag_len()
+                );
+                assert!(packet.len() - plain.len() <= outbound.max_trailer_len());
+
+                inbound.unprotect_rtcp(&mut packet).unwrap();
+                assert_eq!(packet, plain, "{cipher:?}");
+            }
+        }
+    }
+
+    fn rejects_tampered_and_replayed<C: Crypto>() {
+        for cipher in CIPHERS {
+            let key = master_key(cipher);
+            let mut outbound = create_session::<C>(cipher, &key).unwrap();
+            let mut inbound = create_session::<C>(cipher, &key).unwrap();
+
+            let mut packet = rtp_packet(100);
+            outbound.protect(&mut packet).unwrap();
+
+            let mut tampered = packet.clone();
+            tampered[20] ^= 1;
+            assert!(inbound.unprotect(&mut tampered).is_err(), "{cipher:?}");
+
+            let mut replayed = packet.clone();
+            inbound.unprotect(&mut packet).unwrap();
+            assert!(inbound.unprotect(&mut replayed).is_err(), "{cipher:?}");
+
+            let mut packet = rtcp_packet();
+            outbound.protect_rtcp(&mut packet).unwrap();
+
+            let mut tampered = packet.clone();
+            tampered[10] ^= 1;
+            assert!(inbound.unprotect_rtcp(&mut tampered).is_err(), "{cipher:?}");
+
+            let mut replayed = packet.clone();
+            inbound.unprotect_rtcp(&mut packet).unwrap();
+            assert!(inbound.unprotect_rtcp(&mut replayed).is_err(), "{cipher:?}");
+        }
+    }
+
+    macro_rules! crypto_tests {
+        ($($feature:literal => $name:ident: $crypto:ty,)*) => {$(
+            #[cfg(feature = $feature)]
+            mod $name {
+                type C = $crypto;
+
+                #[test]
+                fn key_derivation_aes_128() {
+                    super::key_derivation_aes_128::<C>();
+                }
+
+                #[test]
+                fn key_derivation_aes_256() {
+                    super::key_derivation_aes_256::<C>();
+                }
+
+                #[test]
+                fn aes_cm_keystream() {
+                    super::aes_cm_keystream::<C>();
+                }
+
+                #[test]
+                fn aes_gcm_rtp() {
+                    super::aes_gcm_rtp::<C>();
+                }
+
+                #[test]
+                fn roundtrip() {
+                    super::roundtrip::<C>();
+                }
+
+                #[test]
+                fn rejects_tampered_and_replayed() {
+                    super::rejects_tampered_and_replayed::<C>();
+                }
+            }
+        )*};
+    }
+
+    crypto_tests! {
+        "openssl-srtp" => openssl: crate::srtp::openssl::OpenSsl,
+        "ring-srtp" => ring: crate::srtp::ring::Ring,
+        "rust-srtp" => rust_crypto: crate::srtp::rust_crypto::RustCrypto,
+    }
+}
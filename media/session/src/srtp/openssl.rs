@@ -0,0 +1,138 @@
+use super::native::{AesCtr, AesGcm, Crypto, HmacSha1};
+use openssl::cipher::{Cipher, CipherRef};
+use openssl::cipher_ctx::CipherCtx;
+use openssl::hash::{hash, Hasher, MessageDigest};
+use std::io;
+
+/// Primitives of OpenSSL's EVP interface, which are provided by the FIPS module if it is configured
+pub(super) struct OpenSsl;
+
+impl Crypto for OpenSsl {
+    type AesCtr = OpenSslAesCtr;
+    type HmacSha1 = OpenSslHmacSha1;
+    type AesGcm = OpenSslAesGcm;
+}
+
+pub(super) struct OpenSslAesCtr {
+    ctx: CipherCtx,
+}
+
+impl AesCtr for OpenSslAesCtr {
+    fn new(key: &[u8]) -> io::Result<Self> {
+        let cipher = match key.len() {
+            16 => Cipher::aes_128_ctr(),
+            32 => Cipher::aes_256_ctr(),
+            _ => return Err(io::Error::other("invalid AES key length")),
+        };
+
+        let mut ctx = CipherCtx::new()?;
+        ctx.encrypt_init(Some(cipher), Some(key), None)?;
+
+        Ok(Self { ctx })
+    }
+
+    fn apply_keystream(&mut self, iv: &[u8; 16], data: &mut [u8]) -> io::Result<()> {
+        self.ctx.encrypt_init(None, None, Some(iv))?;
+        self.ctx.cipher_update_inplace(data, data.len())?;
+
+        Ok(())
+    }
+}
+
+/// HMAC-SHA1 with the inner & outer hash states of the key precomputed
+///
+/// Creating a new OpenSSL HMAC context for every packet is expensive, cloning a digest context is not.
+pub(super) struct OpenSslHmacSha1 {
+    inner: Hasher,
+    outer: Hasher,
+}
+
+impl HmacSha1 for OpenSslHmacSha1 {
+    fn new(key: &[u8]) -> io::Result<Self> {
+        const BLOCK_LEN: usize = 64;
+
+        let mut block = [0u8; BLOCK_LEN];
+
+        if key.len() > BLOCK_LEN {
+            let digest = hash(MessageDigest::sha1(), key)?;
+            block[..digest.len()].copy_from_slice(&digest);
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Hasher::new(MessageDigest::sha1())?;
+        inner.update(&block.map(|b| b ^ 0x36))?;
+
+        let mut outer = Hasher::new(MessageDigest::sha1())?;
+        outer.update(&block.map(|b| b ^ 0x5C))?;
+
+        Ok(Self { inner, outer })
+    }
+
+    fn sign(&mut self, data: &[u8]) -> io::Result<[u8; 20]> {
+        let mut inner = self.inner.clone();
+        inner.update(data)?;
+
+        let mut outer = self.outer.clone();
+        outer.update(&inner.finish()?)?;
+
+        let mut mac = [0u8; 20];
+        mac.copy_from_slice(&outer.finish()?);
+
+        Ok(mac)
+    }
+}
+
+pub(super) struct OpenSslAesGcm {
+    ctx: CipherCtx,
+    cipher: &'static CipherRef,
+    key: Vec<u8>,
+}
+
+impl AesGcm for OpenSslAesGcm {
+    fn new(key: &[u8]) -> io::Result<Self> {
+        let cipher = match key.len() {
+            16 => Cipher::aes_128_gcm(),
+            32 => Cipher::aes_256_gcm(),
+            _ => return Err(io::Error::other("invalid AES-GCM key length")),
+        };
+
+        Ok(Self {
+            ctx: CipherCtx::new()?,
+            cipher,
+            key: key.to_vec(),
+        })
+    }
+
+    fn seal(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut [u8]) -> io::Result<[u8; 16]> {
+        self.ctx
+            .encrypt_init(Some(self.cipher), Some(&self.key), Some(nonce))?;
+        self.ctx.cipher_update(aad, None)?;
+        self.ctx.cipher_update_inplace(data, data.len())?;
+        self.ctx.cipher_final(&mut [0u8; 16])?;
+
+        let mut tag = [0u8; 16];
+        self.ctx.tag(&mut tag)?;
+
+        Ok(tag)
+    }
+
+    fn open(
+        &mut self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> io::Result<()> {
+        self.ctx
+            .decrypt_init(Some(self.cipher), Some(&self.key), Some(nonce))?;
+        self.ctx.set_tag(tag)?;
+        self.ctx.cipher_update(aad, None)?;
+        self.ctx.cipher_update_inplace(data, data.len())?;
+        self.ctx
+            .cipher_final(&mut [0u8; 16])
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "authentication failed"))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use super::aes_ctr::RustAesCtr;
+use super::native::{AesGcm, Crypto, HmacSha1};
+use ring::aead::{Aad, LessSafeKey, Nonce, Tag, UnboundKey, AES_128_GCM, AES_256_GCM};
+use ring::hmac;
+use std::io;
+
+/// HMAC-SHA1 & AES-GCM of ring
+///
+/// ring does not expose AES in counter mode, which SRTP needs for the key derivation and the AES-CM ciphers, so
+/// that part is provided by the RustCrypto `aes` & `ctr` crates.
+pub(super) struct Ring;
+
+impl Crypto for Ring {
+    type AesCtr = RustAesCtr;
+    type HmacSha1 = RingHmacSha1;
+    type AesGcm = RingAesGcm;
+}
+
+pub(super) struct RingHmacSha1 {
+    key: hmac::Key,
+}
+
+impl HmacSha1 for RingHmacSha1 {
+    fn new(key: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            key: hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key),
+        })
+    }
+
+    fn sign(&mut self, data: &[u8]) -> io::Result<[u8; 20]> {
+        let mut mac = [0u8; 20];
+        mac.copy_from_slice(hmac::sign(&self.key, data).as_ref());
+
+        Ok(mac)
+    }
+}
+
+pub(super) struct RingAesGcm {
+    key: LessSafeKey,
+}
+
+impl AesGcm for RingAesGcm {
+    fn new(key: &[u8]) -> io::Result<Self> {
+        let algorithm = match key.len() {
+            16 => &AES_128_GCM,
+            32 => &AES_256_GCM,
+            _ => return Err(io::Error::other("invalid AES-GCM key length")),
+        };
+
+        let key =
+            UnboundKey::new(algorithm, key).map_err(|_| io::Error::other("invalid AES-GCM key"))?;
+
+        Ok(Self {
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    fn seal(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut [u8]) -> io::Result<[u8; 16]> {
+        let tag = self
+            .key
+            .seal_in_place_separate_tag(Nonce::assume_unique_for_key(*nonce), Aad::from(aad), data)
+            .map_err(|_| io::Error::other("AES-GCM encryption failed"))?;
+
+        let mut out = [0u8; 16];
+        out.copy_from_slice(tag.as_ref());
+
+        Ok(out)
+    }
+
+    fn open(
+        &mut self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> io::Result<()> {
+        let authentication_failed =
+            || io::Error::new(io::ErrorKind::InvalidData, "authentication failed");
+
+        let tag = Tag::try_from(tag).map_err(|_| authentication_failed())?;
+
+        self.key
+            .open_in_place_separate_tag(
+                Nonce::assume_unique_for_key(*nonce),
+                Aad::from(aad),
+                tag,
+                data,
+                0..,
+            )
+            .map_err(|_| authentication_failed())?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use super::aes_ctr::RustAesCtr;
+use super::native::{AesGcm, Crypto, HmacSha1};
+use aes_gcm::aead::AeadInPlace;
+use aes_gcm::{Aes128Gcm, Aes256Gcm, KeyInit, Nonce, Tag};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::io;
+
+/// Pure Rust primitives of the RustCrypto project
+pub(super) struct RustCrypto;
+
+impl Crypto for RustCrypto {
+    type AesCtr = RustAesCtr;
+    type HmacSha1 = RustHmacSha1;
+    type AesGcm = RustAesGcm;
+}
+
+pub(super) struct RustHmacSha1 {
+    mac: Hmac<Sha1>,
+}
+
+impl HmacSha1 for RustHmacSha1 {
+    fn new(key: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            mac: <Hmac<Sha1> as Mac>::new_from_slice(key).map_err(io::Error::other)?,
+        })
+    }
+
+    fn sign(&mut self, data: &[u8]) -> io::Result<[u8; 20]> {
+        let mut mac = self.mac.clone();
+        mac.update(data);
+
+        Ok(mac.finalize().into_bytes().into())
+    }
+}
+
+pub(super) enum RustAesGcm {
+    Aes128(Box<Aes128Gcm>),
+    Aes256(Box<Aes256Gcm>),
+}
+
+impl AesGcm for RustAesGcm {
+    fn new(key: &[u8]) -> io::Result<Self> {
+        let invalid_key = |_| io::Error::other("invalid AES-GCM key length");
+
+        match key.len() {
+            16 => Aes128Gcm::new_from_slice(key).map(|aes| Self::Aes128(Box::new(aes))),
+            _ => Aes256Gcm::new_from_slice(key).map(|aes| Self::Aes256(Box::new(aes))),
+        }
+        .map_err(invalid_key)
+    }
+
+    fn seal(&mut self, nonce: &[u8; 12], aad: &[u8], data: &mut [u8]) -> io::Result<[u8; 16]> {
+        let nonce = Nonce::from_slice(nonce);
+
+        let tag = match self {
+            Self::Aes128(aes) => aes.encrypt_in_place_detached(nonce, aad, data),
+            Self::Aes256(aes) => aes.encrypt_in_place_detached(nonce, aad, data),
+        }
+        .map_err(|_| io::Error::other("AES-GCM encryption failed"))?;
+
+        Ok(tag.into())
+    }
+
+    fn open(
+        &mut self,
+        nonce: &[u8; 12],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> io::Result<()> {
+        let nonce = Nonce::from_slice(nonce);
+        let tag = Tag::from_slice(tag);
+
+        match self {
+            Self::Aes128(aes) => aes.decrypt_in_place_detached(nonce, aad, data, tag),
+            Self::Aes256(aes) => aes.decrypt_in_place_detached(nonce, aad, data, tag),
+        }
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "authentication failed"))
+    }
+}
@@ -0,0 +1,82 @@
+use crate::MediaId;
+use rtp::{RtpDumpWriter, RtpExtensionIds, RtpPacket};
+use std::io::Write;
+use std::time::Instant;
+
+/// Direction of a packet passed to an [`RtpTap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapDirection {
+    /// Received from the peer
+    Inbound,
+    /// Sent to the peer
+    Outbound,
+}
+
+/// Copy of an RTP packet of a media, passed to the [`RtpTap`] of the media
+#[derive(Debug, Clone)]
+pub struct TappedRtpPacket {
+    pub media_id: MediaId,
+    pub direction: TapDirection,
+    /// When the packet was received or sent
+    pub timestamp: Instant,
+    /// The unencrypted packet, retransmissions are resolved to the original packet
+    pub packet: RtpPacket,
+}
+
+/// Receives copies of the RTP packets sent and received by a media, e.g. for call recording, see
+/// [`SdpSession::set_rtp_tap`](crate::SdpSession::set_rtp_tap)
+///
+/// Implemented for channel senders, so the packets can be handled on another task or thread.
+pub trait RtpTap: Send + 'static {
+    /// Handle a copy of a packet, must not block
+    fn tap(&mut self, packet: TappedRtpPacket);
+}
+
+impl RtpTap for std::sync::mpsc::Sender<TappedRtpPacket> {
+    fn tap(&mut self, packet: TappedRtpPacket) {
+        // The receiver may have stopped recording
+        let _ = self.send(packet);
+    }
+}
+
+impl RtpTap for tokio::sync::mpsc::UnboundedSender<TappedRtpPacket> {
+    fn tap(&mut self, packet: TappedRtpPacket) {
+        let _ = self.send(packet);
+    }
+}
+
+/// [`RtpTap`] writing the packets of one direction to an rtpdump file
+///
+/// Header extensions are not written. Recording stops after the first write error.
+pub struct RtpDumpTap<W> {
+    direction: TapDirection,
+    writer: Option<RtpDumpWriter<W>>,
+}
+
+impl<W: Write + Send + 'static> RtpDumpTap<W> {
+    pub fn new(direction: TapDirection, writer: RtpDumpWriter<W>) -> Self {
+        Self {
+            direction,
+            writer: Some(writer),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> RtpTap for RtpDumpTap<W> {
+    fn tap(&mut self, packet: TappedRtpPacket) {
+        if packet.direction != self.direction {
+            return;
+        }
+
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+
+        let bytes = packet.packet.to_vec(RtpExtensionIds::default());
+
+        if let Err(e) = writer.write_rtp(packet.timestamp, &bytes) {
+            log::warn!("Failed to write rtpdump of {:?}, {e}", packet.media_id);
+            self.writer = None;
+        }
+    }
+}
@@ -1,9 +1,9 @@
 use super::{
-    dtls_srtp::{to_openssl_digest, DtlsSetup, DtlsSrtpSession},
+    dtls_srtp::{strongest_fingerprints, DtlsSetup, DtlsSrtpSession},
     resolve_rtp_and_rtcp_address,
     sdes_srtp::{self, SdesSrtpOffer},
-    IceAgent, ReceivedPacket, SessionTransportState, Transport, TransportEvent, TransportKind,
-    TransportRequiredChanges,
+    IceAgent, MulticastGroup, ReceivedPacket, SessionTransportState, TcpSetup, Transport,
+    TransportEvent, TransportKind, TransportRequiredChanges,
 };
 use crate::{
     events::TransportConnectionState, rtp::extensions::RtpExtensionIdsExt, ReceivedPkt,
@@ -28,6 +28,12 @@ pub(crate) struct TransportBuilder {
 
     pub(crate) ice_agent: Option<IceAgent>,
 
+    /// Set if the transport sends to a multicast group
+    pub(crate) multicast: Option<MulticastGroup>,
+
+    /// Set if RTP is sent over a TCP connection
+    pub(crate) tcp: Option<TcpSetup>,
+
     // Backlog of messages received before the SDP answer has been received
     backlog: Vec<ReceivedPkt>,
 }
@@ -45,10 +51,36 @@ impl TransportBuilder {
             local_rtcp_port: None,
             kind: TransportBuilderKind::Rtp,
             ice_agent: None,
+            multicast: None,
+            tcp: None,
             backlog: vec![],
         }
     }
 
+    /// Create a plain RTP transport sending to a multicast group, which never uses ICE or rtcp-mux
+    pub(crate) fn new_multicast(
+        mut required_changes: TransportRequiredChanges<'_>,
+        group: MulticastGroup,
+    ) -> Self {
+        required_changes.require_multicast_socket(group);
+
+        Self {
+            multicast: Some(group),
+            ..Self::placeholder()
+        }
+    }
+
+    /// Create a plain RTP over TCP transport which accepts the connection of the peer, never uses ICE and
+    /// always multiplexes RTCP
+    pub(crate) fn new_tcp(mut required_changes: TransportRequiredChanges<'_>) -> Self {
+        required_changes.require_tcp_listener();
+
+        Self {
+            tcp: Some(TcpSetup::Passive),
+            ..Self::placeholder()
+        }
+    }
+
     pub(crate) fn new(
         state: &mut SessionTransportState,
         mut required_changes: TransportRequiredChanges<'_>,
@@ -67,7 +99,7 @@ impl TransportBuilder {
                 TransportBuilderKind::SdesSrtp(sdes_srtp::SdesSrtpOffer::new())
             }
             TransportType::DtlsSrtp => TransportBuilderKind::DtlsSrtp {
-                fingerprint: vec![state.dtls_fingerprint()],
+                fingerprint: state.dtls_fingerprints(),
             },
         };
 
@@ -78,9 +110,7 @@ impl TransportBuilder {
                 matches!(rtcp_mux_policy, RtcpMuxPolicy::Require),
             );
 
-            for server in &state.stun_servers {
-                ice_agent.add_stun_server(*server);
-            }
+            state.add_ice_servers(&mut ice_agent);
 
             Some(ice_agent)
         } else {
@@ -91,6 +121,8 @@ impl TransportBuilder {
             local_rtp_port: None,
             local_rtcp_port: None,
             ice_agent,
+            multicast: None,
+            tcp: None,
             kind,
             backlog: vec![],
         }
@@ -119,6 +151,14 @@ impl TransportBuilder {
                 pwd: ice_agent.credentials().pwd.clone().into(),
             });
         }
+
+        if let Some(group) = &self.multicast {
+            group.populate_desc(desc);
+        }
+
+        if let Some(setup) = &self.tcp {
+            setup.populate_desc(desc);
+        }
     }
 
     pub(crate) fn type_(&self) -> TransportType {
@@ -159,6 +199,9 @@ impl TransportBuilder {
                 source,
                 target,
             }),
+            IceEvent::NewLocalCandidate { candidate } => {
+                Some(TransportEvent::NewLocalCandidate { candidate })
+            }
         }
     }
 
@@ -168,8 +211,10 @@ impl TransportBuilder {
         }
     }
 
-    pub(crate) fn receive(&mut self, pkt: ReceivedPkt) {
+    pub(crate) fn receive(&mut self, mut pkt: ReceivedPkt) {
         if let Some(ice_agent) = &mut self.ice_agent {
+            pkt = ice_agent.unwrap_relayed(pkt);
+
             if matches!(is_stun_message(&pkt.data), IsStunMessageInfo::Yes { .. }) {
                 ice_agent.receive(pkt);
                 return;
@@ -192,8 +237,12 @@ impl TransportBuilder {
         session_desc: &SessionDescription,
         remote_media_desc: &MediaDescription,
     ) -> Transport {
-        let (remote_rtp_address, remote_rtcp_address) =
-            resolve_rtp_and_rtcp_address(session_desc, remote_media_desc).unwrap();
+        let (remote_rtp_address, remote_rtcp_address) = if let Some(group) = &self.multicast {
+            // The answer must contain the same multicast group, no need to resolve anything
+            (group.rtp_address(), group.rtcp_address())
+        } else {
+            resolve_rtp_and_rtcp_address(session_desc, remote_media_desc).unwrap()
+        };
 
         // Remove RTCP socket if the answer has rtcp-mux set
         if remote_media_desc.rtcp_mux && self.local_rtcp_port.is_some() {
@@ -236,27 +285,38 @@ impl TransportBuilder {
                 local_rtcp_port: self.local_rtcp_port,
                 remote_rtp_address,
                 remote_rtcp_address,
-                rtcp_mux: remote_media_desc.rtcp_mux,
+                signalled_rtp_address: remote_rtp_address,
+                rtcp_mux: (remote_media_desc.rtcp_mux && self.multicast.is_none())
+                    || self.tcp.is_some(),
                 ice_agent,
+                multicast: self.multicast,
+                multicast_source: None,
+                tcp: self.tcp,
                 negotiated_extension_ids: receive_extension_ids,
                 connection_state: TransportConnectionState::New,
                 kind: TransportKind::Rtp,
                 events: VecDeque::new(),
             },
             TransportBuilderKind::SdesSrtp(offer) => {
-                let (crypto, inbound, outbound) = offer.receive_answer(&remote_media_desc.crypto);
+                let (crypto, (inbound, outbound)) = offer
+                    .receive_answer(state.srtp_backend, &remote_media_desc.crypto)
+                    .unwrap();
 
                 Transport {
                     local_rtp_port: self.local_rtp_port,
                     local_rtcp_port: self.local_rtcp_port,
                     remote_rtp_address,
                     remote_rtcp_address,
+                    signalled_rtp_address: remote_rtp_address,
                     rtcp_mux: remote_media_desc.rtcp_mux,
                     ice_agent,
+                    multicast: None,
+                    multicast_source: None,
+                    tcp: None,
                     negotiated_extension_ids: receive_extension_ids,
                     connection_state: TransportConnectionState::New,
                     kind: TransportKind::SdesSrtp {
-                        crypto: vec![crypto],
+                        crypto,
                         inbound,
                         outbound,
                     },
@@ -270,24 +330,35 @@ impl TransportBuilder {
                     _ => panic!("missing or invalid setup attribute"),
                 };
 
-                let remote_fingerprints: Vec<_> = session_desc
-                    .fingerprint
-                    .iter()
-                    .chain(remote_media_desc.fingerprint.iter())
-                    .filter_map(|e| Some((to_openssl_digest(&e.algorithm)?, e.fingerprint.clone())))
-                    .collect();
-
-                let dtls =
-                    DtlsSrtpSession::new(state.ssl_context(), remote_fingerprints.clone(), setup)
-                        .unwrap();
+                let remote_fingerprints = strongest_fingerprints(
+                    session_desc
+                        .fingerprint
+                        .iter()
+                        .chain(remote_media_desc.fingerprint.iter()),
+                );
+
+                let srtp_backend = state.srtp_backend;
+                let pinned_fingerprints = state.dtls_pinned_fingerprints.clone();
+                let dtls = DtlsSrtpSession::new(
+                    state.ssl_context(),
+                    remote_fingerprints.clone(),
+                    pinned_fingerprints,
+                    setup,
+                    srtp_backend,
+                )
+                .unwrap();
 
                 Transport {
                     local_rtp_port: self.local_rtp_port,
                     local_rtcp_port: self.local_rtcp_port,
                     remote_rtp_address,
                     remote_rtcp_address,
+                    signalled_rtp_address: remote_rtp_address,
                     rtcp_mux: remote_media_desc.rtcp_mux,
                     ice_agent,
+                    multicast: None,
+                    multicast_source: None,
+                    tcp: None,
                     negotiated_extension_ids: receive_extension_ids,
                     connection_state: TransportConnectionState::New,
                     kind: TransportKind::DtlsSrtp {
@@ -1,3 +1,5 @@
+use crate::srtp::{SrtpBackend, SrtpCipher, SrtpDirection, SrtpSessions};
+use crate::SrtpProfile;
 use openssl::{
     asn1::Asn1Time,
     bn::{BigNum, MsbOption},
@@ -11,14 +13,60 @@ use openssl::{
         X509NameBuilder, X509,
     },
 };
-use sdp_types::FingerprintAlgorithm;
-use srtp::openssl::Config;
+use sdp_types::{Fingerprint, FingerprintAlgorithm};
 use std::{
     collections::VecDeque,
+    fmt,
     io::{self, Cursor, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::Duration,
 };
 
+/// Certificate and private key used in the DTLS handshake of DTLS-SRTP transports, see
+/// [`Options::dtls_certificate`](crate::Options::dtls_certificate)
+#[derive(Clone)]
+pub struct DtlsCertificate {
+    certificate: X509,
+    private_key: PKey<Private>,
+}
+
+impl DtlsCertificate {
+    /// Use the certificate and its private key, fails if the key doesn't belong to the certificate
+    pub fn new(certificate: X509, private_key: PKey<Private>) -> io::Result<Self> {
+        let public_key = certificate.public_key().map_err(io::Error::other)?;
+
+        if !public_key.public_eq(&private_key) {
+            return Err(io::Error::other(
+                "private key does not match the certificate",
+            ));
+        }
+
+        Ok(Self {
+            certificate,
+            private_key,
+        })
+    }
+
+    /// Read the certificate and its private key from PEM
+    pub fn from_pem(certificate: &[u8], private_key: &[u8]) -> io::Result<Self> {
+        let certificate = X509::from_pem(certificate).map_err(io::Error::other)?;
+        let private_key = PKey::private_key_from_pem(private_key).map_err(io::Error::other)?;
+
+        Self::new(certificate, private_key)
+    }
+}
+
+impl fmt::Debug for DtlsCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DtlsCertificate")
+            .field("subject", &self.certificate.subject_name())
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub(crate) enum DtlsSetup {
     Accept,
@@ -33,20 +81,33 @@ pub(crate) enum DtlsState {
     Failed,
 }
 
+/// Label of the keying material exporter used to derive the SRTP master keys (RFC 5764 Section 4.2)
+const SRTP_EXPORTER_LABEL: &str = "EXTRACTOR-dtls_srtp";
+
 pub(crate) struct DtlsSrtpSession {
     stream: SslStream<IoQueue>,
     state: DtlsState,
+    srtp_backend: SrtpBackend,
+    /// Set when the peer's certificate didn't match the pinned fingerprints
+    pin_mismatch: Arc<AtomicBool>,
 }
 
 impl DtlsSrtpSession {
+    /// Create a DTLS session verifying the peer's certificate using the fingerprints of the SDP, and the pinned
+    /// fingerprints if there are any
     pub(crate) fn new(
         ssl_context: &SslContext,
         fingerprints: Vec<(MessageDigest, Vec<u8>)>,
+        pinned_fingerprints: Vec<(MessageDigest, Vec<u8>)>,
         setup: DtlsSetup,
+        srtp_backend: SrtpBackend,
     ) -> io::Result<Self> {
         let mut ssl = Ssl::new(ssl_context)?;
         ssl.set_mtu(1200)?;
 
+        let pin_mismatch = Arc::new(AtomicBool::new(false));
+        let pin_mismatch_ = pin_mismatch.clone();
+
         ssl.set_verify_callback(
             SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT,
             move |_, x509_store| {
@@ -54,17 +115,18 @@ impl DtlsSrtpSession {
                     return false;
                 };
 
-                for (digest, fingerprint) in &fingerprints {
-                    let Ok(peer_fingerprint) = certificate.digest(*digest) else {
-                        continue;
-                    };
+                if !matches_any(certificate, &fingerprints) {
+                    return false;
+                }
 
-                    if peer_fingerprint.as_ref() == fingerprint {
-                        return true;
-                    }
+                if !pinned_fingerprints.is_empty()
+                    && !matches_any(certificate, &pinned_fingerprints)
+                {
+                    pin_mismatch_.store(true, Ordering::Relaxed);
+                    return false;
                 }
 
-                false
+                true
             },
         );
 
@@ -80,6 +142,8 @@ impl DtlsSrtpSession {
                 DtlsSetup::Accept => DtlsState::Accepting,
                 DtlsSetup::Connect => DtlsState::Connecting,
             },
+            srtp_backend,
+            pin_mismatch,
         };
 
         // Put initial handshake into the IoQueue
@@ -92,6 +156,11 @@ impl DtlsSrtpSession {
         self.state
     }
 
+    /// Returns if the handshake failed because the peer's certificate didn't match the pinned fingerprints
+    pub(crate) fn pin_mismatch(&self) -> bool {
+        self.pin_mismatch.load(Ordering::Relaxed)
+    }
+
     // TODO: if event_timeout is ever merged, use it
     // #[cfg(openssl320)]
     // pub(crate) fn timeout(&self) -> Option<Duration> {
@@ -109,18 +178,15 @@ impl DtlsSrtpSession {
     }
 
     pub(crate) fn receive(&mut self, data: Vec<u8>) {
+        if matches!(self.state, DtlsState::Failed) {
+            return;
+        }
+
         assert!(self.stream.get_mut().to_read.is_none());
         self.stream.get_mut().to_read = Some(Cursor::new(data));
     }
 
-    pub(crate) fn handshake(
-        &mut self,
-    ) -> io::Result<
-        Option<(
-            srtp::openssl::InboundSession,
-            srtp::openssl::OutboundSession,
-        )>,
-    > {
+    pub(crate) fn handshake(&mut self) -> io::Result<Option<SrtpSessions>> {
         let result = match self.state {
             DtlsState::Connecting => self.stream.connect(),
             DtlsState::Accepting => self.stream.accept(),
@@ -138,16 +204,57 @@ impl DtlsSrtpSession {
                 return Ok(None);
             } else {
                 self.state = DtlsState::Failed;
+                self.stream.get_mut().to_read = None;
                 return Err(io::Error::other(e));
             }
         }
 
         self.state = DtlsState::Connected;
 
-        let (inbound, outbound) =
-            srtp::openssl::session_pair(self.stream.ssl(), Config::default()).unwrap();
+        self.srtp_sessions().map(Some)
+    }
+
+    /// Create the inbound & outbound SRTP sessions using the keying material exported from the DTLS session
+    fn srtp_sessions(&self) -> io::Result<SrtpSessions> {
+        let profile = self
+            .srtp_profile()
+            .ok_or_else(|| io::Error::other("no SRTP profile negotiated"))?;
+
+        let cipher = SrtpCipher::from_dtls_profile(profile);
+        let key_len = cipher.key_len();
+        let salt_len = cipher.salt_len();
+
+        // client key || server key || client salt || server salt
+        let mut material = vec![0u8; 2 * (key_len + salt_len)];
+        self.stream
+            .ssl()
+            .export_keying_material(&mut material, SRTP_EXPORTER_LABEL, None)?;
+
+        let (keys, salts) = material.split_at(2 * key_len);
+        let client_key = [&keys[..key_len], &salts[..salt_len]].concat();
+        let server_key = [&keys[key_len..], &salts[salt_len..]].concat();
+
+        let (inbound_key, outbound_key) = if self.stream.ssl().is_server() {
+            (client_key, server_key)
+        } else {
+            (server_key, client_key)
+        };
+
+        let inbound =
+            self.srtp_backend
+                .create_session(cipher, SrtpDirection::Inbound, &inbound_key)?;
+        let outbound =
+            self.srtp_backend
+                .create_session(cipher, SrtpDirection::Outbound, &outbound_key)?;
 
-        Ok(Some((inbound, outbound)))
+        Ok((inbound, outbound))
+    }
+
+    /// The negotiated SRTP protection profile, available once connected
+    pub(crate) fn srtp_profile(&self) -> Option<SrtpProfile> {
+        let profile = self.stream.ssl().selected_srtp_profile()?;
+
+        SrtpProfile::from_name(profile.name())
     }
 
     pub(crate) fn pop_to_send(&mut self) -> Option<Vec<u8>> {
@@ -187,6 +294,33 @@ impl Write for IoQueue {
     }
 }
 
+fn matches_any(
+    certificate: &openssl::x509::X509Ref,
+    fingerprints: &[(MessageDigest, Vec<u8>)],
+) -> bool {
+    fingerprints.iter().any(|(digest, fingerprint)| {
+        certificate
+            .digest(*digest)
+            .is_ok_and(|peer_fingerprint| peer_fingerprint.as_ref() == fingerprint)
+    })
+}
+
+/// Fingerprint hash algorithms which can be used to create and verify certificate fingerprints
+pub(crate) fn supported_fingerprint_algorithms() -> Vec<FingerprintAlgorithm> {
+    [
+        FingerprintAlgorithm::SHA1,
+        FingerprintAlgorithm::SHA224,
+        FingerprintAlgorithm::SHA256,
+        FingerprintAlgorithm::SHA384,
+        FingerprintAlgorithm::SHA512,
+        FingerprintAlgorithm::MD5,
+        FingerprintAlgorithm::MD2,
+    ]
+    .into_iter()
+    .filter(|algo| to_openssl_digest(algo).is_some())
+    .collect()
+}
+
 pub(super) fn to_openssl_digest(algo: &FingerprintAlgorithm) -> Option<MessageDigest> {
     match algo {
         FingerprintAlgorithm::SHA1 => Some(MessageDigest::sha1()),
@@ -200,12 +334,75 @@ pub(super) fn to_openssl_digest(algo: &FingerprintAlgorithm) -> Option<MessageDi
     }
 }
 
-pub(super) fn make_ssl_context() -> SslContext {
-    let (cert, pkey) = make_ca_cert().unwrap();
+/// Strength of a fingerprint's hash algorithm, higher is stronger
+fn digest_strength(algo: &FingerprintAlgorithm) -> u8 {
+    match algo {
+        FingerprintAlgorithm::SHA512 => 6,
+        FingerprintAlgorithm::SHA384 => 5,
+        FingerprintAlgorithm::SHA256 => 4,
+        FingerprintAlgorithm::SHA224 => 3,
+        FingerprintAlgorithm::SHA1 => 2,
+        FingerprintAlgorithm::MD5 => 1,
+        FingerprintAlgorithm::MD2 | FingerprintAlgorithm::Other(..) => 0,
+    }
+}
+
+/// Select the remote fingerprints to verify the peer's certificate with
+///
+/// Only fingerprints using the strongest supported hash algorithm are used (RFC 8122 Section 5), so a peer
+/// cannot be impersonated using a weaker fingerprint it also included.
+pub(super) fn strongest_fingerprints<'a>(
+    fingerprints: impl Iterator<Item = &'a Fingerprint> + Clone,
+) -> Vec<(MessageDigest, Vec<u8>)> {
+    let Some(strongest) = fingerprints
+        .clone()
+        .filter(|f| to_openssl_digest(&f.algorithm).is_some())
+        .max_by_key(|f| digest_strength(&f.algorithm))
+        .map(|f| f.algorithm.clone())
+    else {
+        return vec![];
+    };
+
+    fingerprints
+        .filter(|f| f.algorithm == strongest)
+        .filter_map(|f| Some((to_openssl_digest(&f.algorithm)?, f.fingerprint.clone())))
+        .collect()
+}
+
+/// Convert the fingerprints to the digests used to verify certificates, ignoring unsupported algorithms
+pub(super) fn to_openssl_fingerprints(
+    fingerprints: &[Fingerprint],
+) -> Vec<(MessageDigest, Vec<u8>)> {
+    fingerprints
+        .iter()
+        .filter_map(|f| Some((to_openssl_digest(&f.algorithm)?, f.fingerprint.clone())))
+        .collect()
+}
+
+/// Create the SSL context using the given certificate, or a generated self-signed one
+pub(super) fn make_ssl_context(
+    profiles: &[SrtpProfile],
+    certificate: Option<&DtlsCertificate>,
+) -> SslContext {
+    let (cert, pkey) = match certificate {
+        Some(certificate) => (
+            certificate.certificate.clone(),
+            certificate.private_key.clone(),
+        ),
+        None => make_ca_cert().unwrap(),
+    };
 
     let mut ctx = SslAcceptor::mozilla_modern(SslMethod::dtls()).unwrap();
-    ctx.set_tlsext_use_srtp(srtp::openssl::SRTP_PROFILE_NAMES)
-        .unwrap();
+
+    let profiles = if profiles.is_empty() {
+        &SrtpProfile::ALL[..]
+    } else {
+        profiles
+    };
+
+    let profiles: Vec<&str> = profiles.iter().map(SrtpProfile::name).collect();
+    ctx.set_tlsext_use_srtp(&profiles.join(":")).unwrap();
+
     ctx.set_private_key(&pkey).unwrap();
     ctx.set_certificate(&cert).unwrap();
     ctx.check_private_key().unwrap();
@@ -2,18 +2,21 @@ use crate::{
     events::{TransportConnectionState, TransportRequiredChanges},
     opt_min,
     rtp::extensions::RtpExtensionIdsExt,
-    Error, TransportType,
+    srtp::{SrtpBackend, SrtpSession, SrtpSessions},
+    Error, SrtpProfile, TransportType,
 };
-use dtls_srtp::{make_ssl_context, DtlsSetup, DtlsSrtpSession, DtlsState};
+use dtls_srtp::{make_ssl_context, to_openssl_fingerprints, DtlsSetup, DtlsSrtpSession, DtlsState};
 use ice::{
     Component, IceAgent, IceConnectionState, IceCredentials, IceEvent, IceGatheringState,
-    ReceivedPkt,
+    ReceivedPkt, TurnCredentials,
 };
-use openssl::{hash::MessageDigest, ssl::SslContext};
+use openssl::hash::MessageDigest;
+use openssl::ssl::SslContext;
 use rtp::{RtpExtensionIds, RtpPacket};
+use sdes_srtp::NegotiatedCrypto;
 use sdp_types::{
-    Connection, Fingerprint, FingerprintAlgorithm, MediaDescription, SessionDescription, Setup,
-    SrtpCrypto, TaggedAddress, TransportProtocol,
+    Connection, Fingerprint, FingerprintAlgorithm, IceCandidate, MediaDescription,
+    SessionDescription, Setup, TaggedAddress, TransportProtocol,
 };
 use std::{
     collections::VecDeque,
@@ -28,38 +31,172 @@ mod packet_kind;
 mod sdes_srtp;
 
 pub(crate) use builder::TransportBuilder;
+pub(crate) use dtls_srtp::supported_fingerprint_algorithms;
+pub use dtls_srtp::DtlsCertificate;
 pub(crate) use packet_kind::PacketKind;
+pub(crate) use sdes_srtp::{SdesSrtpRekey, SUITES as SDES_SRTP_SUITES};
+
+/// Multicast group used to send media to multiple receivers at once, e.g. for paging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MulticastGroup {
+    /// Multicast address of the group
+    pub address: IpAddr,
+    /// RTP port, RTCP is sent to the next higher port
+    pub port: u16,
+    /// Multicast TTL, only used for IPv4
+    pub ttl: u8,
+}
+
+impl MulticastGroup {
+    fn rtp_address(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    fn rtcp_address(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port + 1)
+    }
+
+    /// Replace the transport address attributes of the media description with the multicast group
+    fn populate_desc(&self, desc: &mut MediaDescription) {
+        desc.media.port = self.port;
+        desc.connection = Some(Connection {
+            address: self.address.into(),
+            ttl: self.address.is_ipv4().then_some(u32::from(self.ttl)),
+            num: None,
+        });
+        desc.rtcp = None;
+        desc.rtcp_mux = false;
+        desc.rtcp_mux_only = false;
+    }
+}
+
+/// Role of the local side of an RTP over TCP transport ([RFC4571](https://www.rfc-editor.org/rfc/rfc4571.html)),
+/// negotiated using the `setup` attribute ([RFC4145](https://www.rfc-editor.org/rfc/rfc4145.html))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TcpSetup {
+    /// Connect to the peer
+    Active,
+    /// Accept the connection of the peer
+    Passive,
+}
+
+impl TcpSetup {
+    /// Choose the local role from the remote media description, returns `None` if the peer refuses to connect
+    fn from_remote(remote_media_desc: &MediaDescription) -> Option<Self> {
+        match remote_media_desc.setup {
+            // The peer is active if the attribute is missing
+            Some(Setup::Active) | None => Some(Self::Passive),
+            Some(Setup::Passive | Setup::ActPass) => Some(Self::Active),
+            Some(Setup::HoldConn) => None,
+        }
+    }
+
+    /// Set the TCP specific fields of the media description, RTCP is always multiplexed on the connection
+    fn populate_desc(&self, desc: &mut MediaDescription) {
+        desc.media.proto = match desc.media.proto {
+            TransportProtocol::RtpAvpf => TransportProtocol::TcpRtpAvpf,
+            _ => TransportProtocol::TcpRtpAvp,
+        };
+
+        match self {
+            Self::Active => {
+                // The port of the active side is irrelevant, use the discard port
+                desc.media.port = 9;
+                desc.setup = Some(Setup::Active);
+            }
+            Self::Passive => desc.setup = Some(Setup::Passive),
+        }
+
+        desc.rtcp = None;
+        desc.rtcp_mux = true;
+    }
+}
+
+fn is_tcp(proto: &TransportProtocol) -> bool {
+    matches!(
+        proto,
+        TransportProtocol::TcpRtpAvp | TransportProtocol::TcpRtpAvpf
+    )
+}
 
 #[derive(Default)]
 pub(crate) struct SessionTransportState {
     ssl_context: Option<openssl::ssl::SslContext>,
     ice_credentials: Option<IceCredentials>,
     stun_servers: Vec<SocketAddr>,
+    turn_servers: Vec<(SocketAddr, TurnCredentials)>,
+    dtls_srtp_profiles: Vec<SrtpProfile>,
+    dtls_fingerprint_algorithms: Vec<FingerprintAlgorithm>,
+    dtls_certificate: Option<DtlsCertificate>,
+    dtls_pinned_fingerprints: Vec<(MessageDigest, Vec<u8>)>,
+    srtp_backend: SrtpBackend,
 }
 
 impl SessionTransportState {
+    pub(crate) fn new(
+        dtls_srtp_profiles: Vec<SrtpProfile>,
+        dtls_fingerprint_algorithms: Vec<FingerprintAlgorithm>,
+        dtls_certificate: Option<DtlsCertificate>,
+        dtls_pinned_fingerprints: &[Fingerprint],
+        srtp_backend: SrtpBackend,
+    ) -> Self {
+        Self {
+            dtls_srtp_profiles,
+            dtls_fingerprint_algorithms,
+            dtls_certificate,
+            dtls_pinned_fingerprints: to_openssl_fingerprints(dtls_pinned_fingerprints),
+            srtp_backend,
+            ..Self::default()
+        }
+    }
+
     pub(crate) fn add_stun_server(&mut self, server: SocketAddr) {
         self.stun_servers.push(server);
     }
 
-    fn ssl_context(&mut self) -> &mut SslContext {
-        self.ssl_context.get_or_insert_with(make_ssl_context)
+    pub(crate) fn add_turn_server(&mut self, server: SocketAddr, credentials: TurnCredentials) {
+        self.turn_servers.push((server, credentials));
     }
 
-    fn dtls_fingerprint(&mut self) -> Fingerprint {
-        let ctx = self.ssl_context();
+    /// Add the configured STUN & TURN servers to a new ICE agent
+    fn add_ice_servers(&self, ice_agent: &mut IceAgent) {
+        for server in &self.stun_servers {
+            ice_agent.add_stun_server(*server);
+        }
 
-        Fingerprint {
-            algorithm: FingerprintAlgorithm::SHA256,
-            fingerprint: ctx
-                .certificate()
-                .unwrap()
-                .digest(MessageDigest::sha256())
-                .unwrap()
-                .to_vec(),
+        for (server, credentials) in &self.turn_servers {
+            ice_agent.add_turn_server(*server, credentials.clone());
         }
     }
 
+    fn ssl_context(&mut self) -> &mut SslContext {
+        self.ssl_context.get_or_insert_with(|| {
+            make_ssl_context(&self.dtls_srtp_profiles, self.dtls_certificate.as_ref())
+        })
+    }
+
+    fn dtls_fingerprints(&mut self) -> Vec<Fingerprint> {
+        let algorithms = if self.dtls_fingerprint_algorithms.is_empty() {
+            vec![FingerprintAlgorithm::SHA256]
+        } else {
+            self.dtls_fingerprint_algorithms.clone()
+        };
+
+        let certificate = self.ssl_context().certificate().unwrap();
+
+        algorithms
+            .into_iter()
+            .filter_map(|algorithm| {
+                let digest = dtls_srtp::to_openssl_digest(&algorithm)?;
+
+                Some(Fingerprint {
+                    fingerprint: certificate.digest(digest).unwrap().to_vec(),
+                    algorithm,
+                })
+            })
+            .collect()
+    }
+
     fn ice_credentials(&mut self) -> IceCredentials {
         self.ice_credentials
             .get_or_insert_with(IceCredentials::random)
@@ -79,6 +216,7 @@ pub(crate) enum TransportEvent {
     TransportConnectionState {
         old: TransportConnectionState,
         new: TransportConnectionState,
+        srtp_profile: Option<SrtpProfile>,
     },
     SendData {
         component: Component,
@@ -86,6 +224,10 @@ pub(crate) enum TransportEvent {
         source: Option<IpAddr>,
         target: SocketAddr,
     },
+    FingerprintMismatch,
+    NewLocalCandidate {
+        candidate: IceCandidate,
+    },
 }
 
 pub(crate) struct Transport {
@@ -94,11 +236,21 @@ pub(crate) struct Transport {
 
     pub(crate) remote_rtp_address: SocketAddr,
     pub(crate) remote_rtcp_address: SocketAddr,
+    /// Remote RTP address as signalled in SDP, `remote_rtp_address` may be changed by ICE
+    signalled_rtp_address: SocketAddr,
 
     rtcp_mux: bool,
 
     pub(crate) ice_agent: Option<IceAgent>,
 
+    /// Set if the transport sends to or receives from a multicast group
+    pub(crate) multicast: Option<MulticastGroup>,
+    /// Only accept packets from this source, set when receiving from a multicast group with a source filter
+    multicast_source: Option<IpAddr>,
+
+    /// Set if RTP is sent over a TCP connection
+    pub(crate) tcp: Option<TcpSetup>,
+
     /// The receiving extension ids
     negotiated_extension_ids: RtpExtensionIds,
 
@@ -111,10 +263,10 @@ pub(crate) struct Transport {
 enum TransportKind {
     Rtp,
     SdesSrtp {
-        /// Local crypto attribute
-        crypto: Vec<SrtpCrypto>,
-        inbound: srtp::Session,
-        outbound: srtp::Session,
+        /// Crypto attributes of the keys in use
+        crypto: NegotiatedCrypto,
+        inbound: Box<dyn SrtpSession>,
+        outbound: Box<dyn SrtpSession>,
     },
     DtlsSrtp {
         /// Local DTLS certificate fingerprint attribute
@@ -122,7 +274,7 @@ enum TransportKind {
         setup: Setup,
 
         dtls: DtlsSrtpSession,
-        srtp: Option<(srtp::Session, srtp::Session)>,
+        srtp: Option<SrtpSessions>,
     },
 }
 
@@ -133,15 +285,44 @@ impl Transport {
         session_desc: &SessionDescription,
         remote_media_desc: &MediaDescription,
     ) -> Result<Option<Self>, Error> {
-        if remote_media_desc.rtcp_mux {
-            required_changes.require_socket();
+        let multicast = multicast_group(session_desc, remote_media_desc);
+
+        let tcp_setup = if is_tcp(&remote_media_desc.media.proto) {
+            let Some(setup) = TcpSetup::from_remote(remote_media_desc) else {
+                return Ok(None);
+            };
+
+            Some(setup)
         } else {
-            required_changes.require_socket_pair();
-        }
+            None
+        };
 
         let (remote_rtp_address, remote_rtcp_address) =
             resolve_rtp_and_rtcp_address(session_desc, remote_media_desc).unwrap();
 
+        if let Some((group, source)) = multicast {
+            // DTLS cannot be used with multicast, RTCP is never multiplexed with multicast (RFC 8858)
+            if remote_media_desc.rtcp_mux_only
+                || matches!(
+                    remote_media_desc.media.proto,
+                    TransportProtocol::UdpTlsRtpSavp | TransportProtocol::UdpTlsRtpSavpf
+                )
+            {
+                return Ok(None);
+            }
+
+            required_changes.join_multicast_group(group, source);
+        } else if let Some(setup) = tcp_setup {
+            match setup {
+                TcpSetup::Active => required_changes.connect_tcp(remote_rtp_address),
+                TcpSetup::Passive => required_changes.require_tcp_listener(),
+            }
+        } else if remote_media_desc.rtcp_mux {
+            required_changes.require_socket();
+        } else {
+            required_changes.require_socket_pair();
+        }
+
         let ice_ufrag = session_desc
             .ice_ufrag
             .as_ref()
@@ -152,7 +333,10 @@ impl Transport {
             .as_ref()
             .or(remote_media_desc.ice_pwd.as_ref());
 
-        let ice_agent = if let Some((ufrag, pwd)) = ice_ufrag.zip(ice_pwd) {
+        let ice_agent = if let Some((ufrag, pwd)) = ice_ufrag
+            .zip(ice_pwd)
+            .filter(|_| multicast.is_none() && tcp_setup.is_none())
+        {
             let mut ice_agent = IceAgent::new_from_answer(
                 state.ice_credentials(),
                 IceCredentials {
@@ -163,9 +347,7 @@ impl Transport {
                 remote_media_desc.rtcp_mux,
             );
 
-            for server in &state.stun_servers {
-                ice_agent.add_stun_server(*server);
-            }
+            state.add_ice_servers(&mut ice_agent);
 
             for candidate in &remote_media_desc.ice_candidates {
                 ice_agent.add_remote_candidate(candidate);
@@ -179,29 +361,40 @@ impl Transport {
         let receive_extension_ids = RtpExtensionIds::from_sdp(session_desc, remote_media_desc);
 
         let mut transport = match &remote_media_desc.media.proto {
-            TransportProtocol::RtpAvp | TransportProtocol::RtpAvpf => Transport {
+            TransportProtocol::RtpAvp
+            | TransportProtocol::RtpAvpf
+            | TransportProtocol::TcpRtpAvp
+            | TransportProtocol::TcpRtpAvpf => Transport {
                 local_rtp_port: None,
                 local_rtcp_port: None,
                 remote_rtp_address,
                 remote_rtcp_address,
+                signalled_rtp_address: remote_rtp_address,
                 rtcp_mux: remote_media_desc.rtcp_mux,
                 ice_agent,
+                multicast: None,
+                multicast_source: None,
+                tcp: None,
                 negotiated_extension_ids: receive_extension_ids,
                 connection_state: TransportConnectionState::New,
                 kind: TransportKind::Rtp,
                 events: VecDeque::new(),
             },
             TransportProtocol::RtpSavp | TransportProtocol::RtpSavpf => {
-                let (crypto, inbound, outbound) =
-                    sdes_srtp::negotiate_from_offer(&remote_media_desc.crypto)?;
+                let (crypto, (inbound, outbound)) =
+                    sdes_srtp::negotiate_from_offer(state.srtp_backend, &remote_media_desc.crypto)?;
 
                 Transport {
                     local_rtp_port: None,
                     local_rtcp_port: None,
                     remote_rtp_address,
                     remote_rtcp_address,
+                    signalled_rtp_address: remote_rtp_address,
                     rtcp_mux: remote_media_desc.rtcp_mux,
                     ice_agent,
+                    multicast: None,
+                    multicast_source: None,
+                    tcp: None,
                     negotiated_extension_ids: receive_extension_ids,
                     connection_state: TransportConnectionState::New,
                     kind: TransportKind::SdesSrtp {
@@ -226,6 +419,18 @@ impl Transport {
             _ => return Ok(None),
         };
 
+        if let Some((group, source)) = multicast {
+            transport.rtcp_mux = false;
+            transport.multicast = Some(group);
+            transport.multicast_source = source;
+        }
+
+        if let Some(setup) = tcp_setup {
+            transport.remote_rtcp_address = remote_rtp_address;
+            transport.rtcp_mux = true;
+            transport.tcp = Some(setup);
+        }
+
         // RTP & SDES-SRTP transport are instantly set to the connected state if ICE is not used
         if matches!(
             transport.kind,
@@ -238,6 +443,28 @@ impl Transport {
         Ok(Some(transport))
     }
 
+    /// Returns if the peer moved the media to a new transport, by offering a different address than before
+    ///
+    /// Offers using an unspecified address to put the media on hold don't move it. Multicast groups are never moved.
+    pub(crate) fn is_moved_by_offer(
+        &self,
+        session_desc: &SessionDescription,
+        remote_media_desc: &MediaDescription,
+    ) -> bool {
+        if self.multicast.is_some() {
+            return false;
+        }
+
+        let Ok((remote_rtp_address, _)) =
+            resolve_rtp_and_rtcp_address(session_desc, remote_media_desc)
+        else {
+            return false;
+        };
+
+        !remote_rtp_address.ip().is_unspecified()
+            && remote_rtp_address != self.signalled_rtp_address
+    }
+
     pub(crate) fn dtls_srtp_from_offer(
         state: &mut SessionTransportState,
         session_desc: &SessionDescription,
@@ -260,31 +487,38 @@ impl Transport {
             }
         };
 
-        let remote_fingerprints: Vec<_> = session_desc
-            .fingerprint
-            .iter()
-            .chain(remote_media_desc.fingerprint.iter())
-            .filter_map(|e| {
-                Some((
-                    dtls_srtp::to_openssl_digest(&e.algorithm)?,
-                    e.fingerprint.clone(),
-                ))
-            })
-            .collect();
+        let remote_fingerprints = dtls_srtp::strongest_fingerprints(
+            session_desc
+                .fingerprint
+                .iter()
+                .chain(remote_media_desc.fingerprint.iter()),
+        );
 
-        let dtls = DtlsSrtpSession::new(state.ssl_context(), remote_fingerprints.clone(), setup)?;
+        let srtp_backend = state.srtp_backend;
+        let pinned_fingerprints = state.dtls_pinned_fingerprints.clone();
+        let dtls = DtlsSrtpSession::new(
+            state.ssl_context(),
+            remote_fingerprints.clone(),
+            pinned_fingerprints,
+            setup,
+            srtp_backend,
+        )?;
 
         Ok(Transport {
             local_rtp_port: None,
             local_rtcp_port: None,
             remote_rtp_address,
             remote_rtcp_address,
+            signalled_rtp_address: remote_rtp_address,
             rtcp_mux: remote_media_desc.rtcp_mux,
             ice_agent,
+            multicast: None,
+            multicast_source: None,
+            tcp: None,
             negotiated_extension_ids: receive_extension_ids,
             connection_state: TransportConnectionState::New,
             kind: TransportKind::DtlsSrtp {
-                fingerprint: vec![state.dtls_fingerprint()],
+                fingerprint: state.dtls_fingerprints(),
                 setup: match setup {
                     DtlsSetup::Accept => Setup::Passive,
                     DtlsSetup::Connect => Setup::Active,
@@ -311,7 +545,7 @@ impl Transport {
         match &self.kind {
             TransportKind::Rtp => {}
             TransportKind::SdesSrtp { crypto, .. } => {
-                desc.crypto.extend_from_slice(crypto);
+                desc.crypto.push(crypto.local.clone());
             }
             TransportKind::DtlsSrtp {
                 fingerprint, setup, ..
@@ -330,6 +564,83 @@ impl Transport {
                 pwd: ice_agent.credentials().pwd.clone().into(),
             });
         }
+
+        if let Some(group) = &self.multicast {
+            group.populate_desc(desc);
+        }
+
+        if let Some(setup) = &self.tcp {
+            setup.populate_desc(desc);
+        }
+    }
+
+    /// Create new keys to offer for re-keying, returns `None` if the transport doesn't use SDES-SRTP
+    pub(crate) fn create_srtp_rekey(&self) -> Option<SdesSrtpRekey> {
+        match &self.kind {
+            TransportKind::SdesSrtp { crypto, .. } => Some(SdesSrtpRekey::new(&crypto.local)),
+            TransportKind::Rtp | TransportKind::DtlsSrtp { .. } => None,
+        }
+    }
+
+    /// Switch to the keys offered using [`create_srtp_rekey`](Self::create_srtp_rekey) and the peer's answer
+    pub(crate) fn complete_srtp_rekey(
+        &mut self,
+        state: &SessionTransportState,
+        rekey: &SdesSrtpRekey,
+        remote_media_desc: &MediaDescription,
+    ) -> io::Result<()> {
+        let TransportKind::SdesSrtp {
+            crypto,
+            inbound,
+            outbound,
+        } = &mut self.kind
+        else {
+            return Err(io::Error::other("transport doesn't use SDES-SRTP"));
+        };
+
+        let (negotiated, (new_inbound, new_outbound)) =
+            rekey.receive_answer(state.srtp_backend, &remote_media_desc.crypto)?;
+
+        *crypto = negotiated;
+        *inbound = new_inbound;
+        *outbound = new_outbound;
+
+        Ok(())
+    }
+
+    /// Switch to new keys if the peer offered a different key than the one in use, returns if the keys changed
+    ///
+    /// A new local key is created as well, to be sent in the answer.
+    pub(crate) fn receive_srtp_offer(
+        &mut self,
+        state: &SessionTransportState,
+        remote_media_desc: &MediaDescription,
+    ) -> io::Result<bool> {
+        let TransportKind::SdesSrtp {
+            crypto,
+            inbound,
+            outbound,
+        } = &mut self.kind
+        else {
+            return Ok(false);
+        };
+
+        if remote_media_desc
+            .crypto
+            .iter()
+            .any(|remote| sdes_srtp::is_same_key(remote, &crypto.remote))
+        {
+            return Ok(false);
+        }
+
+        let (negotiated, (new_inbound, new_outbound)) =
+            sdes_srtp::negotiate_from_offer(state.srtp_backend, &remote_media_desc.crypto)?;
+
+        *crypto = negotiated;
+        *inbound = new_inbound;
+        *outbound = new_outbound;
+
+        Ok(true)
     }
 
     pub(crate) fn timeout(&self, now: Instant) -> Option<Duration> {
@@ -372,6 +683,9 @@ impl Transport {
                         target,
                     })
                 }
+                IceEvent::NewLocalCandidate { candidate } => {
+                    return Some(TransportEvent::NewLocalCandidate { candidate })
+                }
             }
         }
 
@@ -381,17 +695,42 @@ impl Transport {
         ) {
             if let TransportKind::DtlsSrtp { dtls, .. } = &mut self.kind {
                 if let Some(data) = dtls.pop_to_send() {
-                    return Some(TransportEvent::SendData {
+                    return Some(self.relay(TransportEvent::SendData {
                         component: Component::Rtp,
                         data,
                         source: None,
                         target: self.remote_rtp_address,
-                    });
+                    }));
                 }
             }
         }
 
-        self.events.pop_front()
+        self.events.pop_front().map(|event| self.relay(event))
+    }
+
+    /// Wrap outgoing data for the TURN server, if ICE selected a relayed candidate to send it from
+    fn relay(&self, event: TransportEvent) -> TransportEvent {
+        match (event, &self.ice_agent) {
+            (
+                TransportEvent::SendData {
+                    component,
+                    data,
+                    source,
+                    target,
+                },
+                Some(ice_agent),
+            ) => {
+                let (data, target) = ice_agent.wrap_relayed(component, data, target);
+
+                TransportEvent::SendData {
+                    component,
+                    data,
+                    source,
+                    target,
+                }
+            }
+            (event, _) => event,
+        }
     }
 
     pub(crate) fn poll(&mut self, now: Instant) {
@@ -457,6 +796,18 @@ impl Transport {
     }
 
     pub(crate) fn receive(&mut self, mut pkt: ReceivedPkt) -> ReceivedPacket {
+        if self
+            .multicast_source
+            .is_some_and(|source| source != pkt.source.ip())
+        {
+            return ReceivedPacket::TransportSpecific;
+        }
+
+        // Data relayed by a TURN server is handled as if it was received from the peer directly
+        if let Some(ice_agent) = &self.ice_agent {
+            pkt = ice_agent.unwrap_relayed(pkt);
+        }
+
         match PacketKind::identify(&pkt.data) {
             PacketKind::Rtp => {
                 // Handle incoming RTP packet
@@ -466,7 +817,10 @@ impl Transport {
                     ..
                 } = &mut self.kind
                 {
-                    inbound.unprotect(&mut pkt.data).unwrap();
+                    if let Err(e) = inbound.unprotect(&mut pkt.data) {
+                        log::debug!("Failed to unprotect SRTP packet, {e}");
+                        return ReceivedPacket::TransportSpecific;
+                    }
                 }
 
                 match RtpPacket::parse(self.negotiated_extension_ids, pkt.data) {
@@ -485,7 +839,10 @@ impl Transport {
                     ..
                 } = &mut self.kind
                 {
-                    inbound.unprotect_rtcp(&mut pkt.data).unwrap();
+                    if let Err(e) = inbound.unprotect_rtcp(&mut pkt.data) {
+                        log::debug!("Failed to unprotect SRTCP packet, {e}");
+                        return ReceivedPacket::TransportSpecific;
+                    }
                 }
 
                 ReceivedPacket::Rtcp(pkt.data)
@@ -506,8 +863,16 @@ impl Transport {
                 if let TransportKind::DtlsSrtp { dtls, srtp, .. } = &mut self.kind {
                     dtls.receive(pkt.data.clone());
 
-                    if let Some((inbound, outbound)) = dtls.handshake().unwrap() {
-                        *srtp = Some((inbound.into_session(), outbound.into_session()));
+                    match dtls.handshake() {
+                        Ok(Some(sessions)) => *srtp = Some(sessions),
+                        Ok(None) => {}
+                        Err(e) => {
+                            log::warn!("DTLS handshake failed, {e}");
+
+                            if dtls.pin_mismatch() {
+                                self.events.push_back(TransportEvent::FingerprintMismatch);
+                            }
+                        }
                     }
 
                     while let Some(data) = dtls.pop_to_send() {
@@ -530,7 +895,17 @@ impl Transport {
     }
 
     pub(crate) fn send_rtp(&mut self, packet: RtpPacket) {
-        let mut packet = packet.to_vec(self.negotiated_extension_ids);
+        // Leave room for the SRTP trailer so protecting the packet doesn't reallocate it
+        let trailer_len = match &self.kind {
+            TransportKind::SdesSrtp { outbound, .. }
+            | TransportKind::DtlsSrtp {
+                srtp: Some((_, outbound)),
+                ..
+            } => outbound.rtp_trailer_len(),
+            _ => 0,
+        };
+
+        let mut packet = packet.to_vec_with_trailer(self.negotiated_extension_ids, trailer_len);
 
         match &mut self.kind {
             TransportKind::DtlsSrtp { srtp: None, .. } => {
@@ -569,7 +944,8 @@ impl Transport {
             _ => (),
         }
 
-        let component = if self.rtcp_mux {
+        // Multicast transports have no RTCP socket and send RTCP from the RTP socket
+        let component = if self.rtcp_mux || self.local_rtcp_port.is_none() {
             Component::Rtp
         } else {
             Component::Rtcp
@@ -586,10 +962,20 @@ impl Transport {
     // Set the a new connection state and emit an event if the state differs from the old one
     fn set_connection_state(&mut self, new: TransportConnectionState) {
         if self.connection_state != new {
+            let srtp_profile = match &self.kind {
+                TransportKind::DtlsSrtp { dtls, .. }
+                    if new == TransportConnectionState::Connected =>
+                {
+                    dtls.srtp_profile()
+                }
+                _ => None,
+            };
+
             self.events
                 .push_back(TransportEvent::TransportConnectionState {
                     old: self.connection_state,
                     new,
+                    srtp_profile,
                 });
 
             self.connection_state = new;
@@ -609,6 +995,78 @@ pub(crate) enum ReceivedPacket {
     TransportSpecific,
 }
 
+/// Returns the multicast group and optional source if the media description uses a multicast connection address
+fn multicast_group(
+    session_desc: &SessionDescription,
+    media_desc: &MediaDescription,
+) -> Option<(MulticastGroup, Option<IpAddr>)> {
+    let connection = media_desc
+        .connection
+        .as_ref()
+        .or(session_desc.connection.as_ref())?;
+
+    let address = match &connection.address {
+        TaggedAddress::IP4(address) => IpAddr::V4(*address),
+        TaggedAddress::IP6(address) => IpAddr::V6(*address),
+        TaggedAddress::IP4FQDN(..) | TaggedAddress::IP6FQDN(..) => return None,
+    };
+
+    if !address.is_multicast() {
+        return None;
+    }
+
+    let group = MulticastGroup {
+        address,
+        port: media_desc.media.port,
+        ttl: connection
+            .ttl
+            .map_or(1, |ttl| ttl.min(u32::from(u8::MAX)) as u8),
+    };
+
+    // Media level source filters replace session level ones
+    let source_filters = if media_desc
+        .attributes
+        .iter()
+        .any(|attr| attr.name == "source-filter")
+    {
+        &media_desc.attributes
+    } else {
+        &session_desc.attributes
+    };
+
+    let source = source_filters
+        .iter()
+        .filter(|attr| attr.name == "source-filter")
+        .filter_map(|attr| attr.value.as_ref())
+        .find_map(|value| parse_source_filter(value, address));
+
+    Some((group, source))
+}
+
+/// Parse the first source address of an inclusive source filter for the given destination address
+/// ([RFC4570](https://www.rfc-editor.org/rfc/rfc4570.html))
+///
+/// `incl IN IP4 <dest-address> <src-address>...`
+fn parse_source_filter(value: &str, destination: IpAddr) -> Option<IpAddr> {
+    let mut parts = value.split_ascii_whitespace();
+
+    let (Some("incl"), Some("IN"), Some(_), Some(dest_address), Some(src_address)) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return None;
+    };
+
+    if dest_address != "*" && dest_address.parse::<IpAddr>().ok()? != destination {
+        return None;
+    }
+
+    src_address.parse().ok()
+}
+
 fn resolve_rtp_and_rtcp_address(
     remote_session_description: &SessionDescription,
     remote_media_description: &MediaDescription,
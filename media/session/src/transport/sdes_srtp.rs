@@ -1,72 +1,49 @@
+use crate::srtp::{SrtpBackend, SrtpCipher, SrtpDirection, SrtpSession};
 use base64::{prelude::BASE64_STANDARD, Engine};
 use rand::RngCore;
 use sdp_types::{
     SrtpCrypto, SrtpKeyingMaterial,
     SrtpSuite::{self, *},
 };
-use srtp::CryptoPolicy;
 use std::io;
 
+type SrtpSessions = (Box<dyn SrtpSession>, Box<dyn SrtpSession>);
+
+/// Local and remote crypto attribute in use by an SDES-SRTP transport
+#[derive(Debug, Clone)]
+pub(super) struct NegotiatedCrypto {
+    pub(super) local: SrtpCrypto,
+    pub(super) remote: SrtpCrypto,
+}
+
 pub(super) fn negotiate_from_offer(
+    backend: SrtpBackend,
     remote_crypto: &[SrtpCrypto],
-) -> io::Result<(Vec<SrtpCrypto>, srtp::Session, srtp::Session)> {
-    let choice1 = remote_crypto
-        .iter()
-        .find(|c| c.suite == AES_256_CM_HMAC_SHA1_80 && !c.keys.is_empty());
-    let choice2 = remote_crypto
+) -> io::Result<(NegotiatedCrypto, SrtpSessions)> {
+    let crypto = SUITES
         .iter()
-        .find(|c| c.suite == AES_256_CM_HMAC_SHA1_32 && !c.keys.is_empty());
-    let choice3 = remote_crypto
-        .iter()
-        .find(|c| c.suite == AES_CM_128_HMAC_SHA1_80 && !c.keys.is_empty());
-    let choice4 = remote_crypto
-        .iter()
-        .find(|c| c.suite == AES_CM_128_HMAC_SHA1_32 && !c.keys.is_empty());
-
-    let crypto = choice1
-        .or(choice2)
-        .or(choice3)
-        .or(choice4)
+        .find_map(|suite| {
+            remote_crypto
+                .iter()
+                .find(|c| c.suite == *suite && !c.keys.is_empty())
+        })
         .ok_or_else(|| io::Error::other("No compatible srtp suite found"))?;
 
     let recv_key = BASE64_STANDARD
         .decode(&crypto.keys[0].key_and_salt)
         .map_err(io::Error::other)?;
 
-    let suite = srtp_suite_to_policy(&crypto.suite).unwrap();
+    let cipher = SrtpCipher::from_sdes_suite(&crypto.suite).unwrap();
+    let send_key = random_key(cipher);
 
-    let mut send_key = vec![0u8; suite.key_len()];
-    rand::rng().fill_bytes(&mut send_key);
-
-    let inbound = srtp::Session::with_inbound_template(srtp::StreamPolicy {
-        rtp: suite,
-        rtcp: suite,
-        key: &recv_key,
-        ..Default::default()
-    })
-    .unwrap();
-
-    let outbound = srtp::Session::with_outbound_template(srtp::StreamPolicy {
-        rtp: suite,
-        rtcp: suite,
-        key: &send_key,
-        ..Default::default()
-    })
-    .unwrap();
+    let sessions = create_sessions(backend, cipher, &recv_key, &send_key)?;
 
     Ok((
-        vec![SrtpCrypto {
-            tag: crypto.tag,
-            suite: crypto.suite.clone(),
-            keys: vec![SrtpKeyingMaterial {
-                key_and_salt: BASE64_STANDARD.encode(&send_key).into(),
-                lifetime: None,
-                mki: None,
-            }],
-            params: vec![],
-        }],
-        inbound,
-        outbound,
+        NegotiatedCrypto {
+            local: local_crypto(crypto.tag, crypto.suite.clone(), &send_key),
+            remote: crypto.clone(),
+        },
+        sessions,
     ))
 }
 
@@ -74,22 +51,25 @@ pub(super) struct SdesSrtpOffer {
     keys: Vec<(SrtpSuite, Vec<u8>)>,
 }
 
+/// Crypto suites offered and accepted, in order of preference
+///
+/// AES-192-CM is left out, the native SRTP backends derive its keys differently than libsrtp.
+pub(crate) const SUITES: [SrtpSuite; 4] = [
+    AES_256_CM_HMAC_SHA1_80,
+    AES_256_CM_HMAC_SHA1_32,
+    AES_CM_128_HMAC_SHA1_80,
+    AES_CM_128_HMAC_SHA1_32,
+];
+
 impl SdesSrtpOffer {
     pub(super) fn new() -> Self {
         let mut keys = vec![];
 
-        for suite in [
-            AES_256_CM_HMAC_SHA1_80,
-            AES_256_CM_HMAC_SHA1_32,
-            AES_CM_128_HMAC_SHA1_80,
-            AES_CM_128_HMAC_SHA1_32,
-        ] {
-            let policy = srtp_suite_to_policy(&suite).expect("only using known working suites");
+        for suite in SUITES {
+            let cipher =
+                SrtpCipher::from_sdes_suite(&suite).expect("only using known working suites");
 
-            let mut send_key = vec![0u8; policy.key_len()];
-            rand::rng().fill_bytes(&mut send_key);
-
-            keys.push((suite, send_key));
+            keys.push((suite, random_key(cipher)));
         }
 
         Self { keys }
@@ -114,8 +94,9 @@ impl SdesSrtpOffer {
 
     pub(super) fn receive_answer(
         self,
+        backend: SrtpBackend,
         remote_crypto: &[SrtpCrypto],
-    ) -> (SrtpCrypto, srtp::Session, srtp::Session) {
+    ) -> io::Result<(NegotiatedCrypto, SrtpSessions)> {
         for (tag, (suite, send_key)) in self.keys.into_iter().enumerate() {
             let tag = tag as u32 + 1;
 
@@ -130,53 +111,159 @@ impl SdesSrtpOffer {
 
                 let recv_key = BASE64_STANDARD
                     .decode(&crypto.keys[0].key_and_salt)
-                    .unwrap();
-
-                let crypto_attr = SrtpCrypto {
-                    tag,
-                    suite: suite.clone(),
-                    keys: vec![SrtpKeyingMaterial {
-                        key_and_salt: BASE64_STANDARD.encode(&send_key).into(),
-                        lifetime: None,
-                        mki: None,
-                    }],
-                    params: vec![],
+                    .map_err(io::Error::other)?;
+
+                let cipher = SrtpCipher::from_sdes_suite(&suite).unwrap();
+                let sessions = create_sessions(backend, cipher, &recv_key, &send_key)?;
+
+                let negotiated = NegotiatedCrypto {
+                    local: local_crypto(tag, suite, &send_key),
+                    remote: crypto.clone(),
                 };
 
-                let suite = srtp_suite_to_policy(&suite).unwrap();
-                let inbound = srtp::Session::with_inbound_template(srtp::StreamPolicy {
-                    rtp: suite,
-                    rtcp: suite,
-                    key: &recv_key,
-                    ..Default::default()
-                })
-                .unwrap();
-                let outbound = srtp::Session::with_outbound_template(srtp::StreamPolicy {
-                    rtp: suite,
-                    rtcp: suite,
-                    key: &send_key,
-                    ..Default::default()
-                })
-                .unwrap();
-
-                return (crypto_attr, inbound, outbound);
+                return Ok((negotiated, sessions));
             }
         }
 
-        todo!("error, no suitable crypto attribute in response")
+        Err(io::Error::other("No suitable crypto attribute in answer"))
     }
 }
 
-fn srtp_suite_to_policy(suite: &SrtpSuite) -> Option<CryptoPolicy> {
-    match suite {
-        SrtpSuite::AES_CM_128_HMAC_SHA1_80 => Some(CryptoPolicy::aes_cm_128_hmac_sha1_80()),
-        SrtpSuite::AES_CM_128_HMAC_SHA1_32 => Some(CryptoPolicy::aes_cm_128_hmac_sha1_32()),
-        SrtpSuite::AES_192_CM_HMAC_SHA1_80 => Some(CryptoPolicy::aes_cm_192_hmac_sha1_80()),
-        SrtpSuite::AES_192_CM_HMAC_SHA1_32 => Some(CryptoPolicy::aes_cm_192_hmac_sha1_32()),
-        SrtpSuite::AES_256_CM_HMAC_SHA1_80 => Some(CryptoPolicy::aes_cm_256_hmac_sha1_80()),
-        SrtpSuite::AES_256_CM_HMAC_SHA1_32 => Some(CryptoPolicy::aes_cm_256_hmac_sha1_32()),
-        SrtpSuite::AEAD_AES_128_GCM => Some(CryptoPolicy::aes_gcm_128_16_auth()),
-        SrtpSuite::AEAD_AES_256_GCM => Some(CryptoPolicy::aes_gcm_256_16_auth()),
-        _ => None,
+/// New local key offered to rotate the keys of an established SDES-SRTP transport
+///
+/// The negotiated tag & suite are kept, only the keys change.
+#[derive(Debug, Clone)]
+pub(crate) struct SdesSrtpRekey {
+    crypto: SrtpCrypto,
+    send_key: Vec<u8>,
+}
+
+impl SdesSrtpRekey {
+    pub(super) fn new(current: &SrtpCrypto) -> Self {
+        let cipher =
+            SrtpCipher::from_sdes_suite(&current.suite).expect("suite was negotiated before");
+        let send_key = random_key(cipher);
+
+        Self {
+            crypto: local_crypto(current.tag, current.suite.clone(), &send_key),
+            send_key,
+        }
+    }
+
+    /// The crypto attribute to offer
+    pub(crate) fn crypto(&self) -> &SrtpCrypto {
+        &self.crypto
+    }
+
+    /// Create the new SRTP sessions from the peer's answer, which may or may not contain a new key as well
+    pub(super) fn receive_answer(
+        &self,
+        backend: SrtpBackend,
+        remote_crypto: &[SrtpCrypto],
+    ) -> io::Result<(NegotiatedCrypto, SrtpSessions)> {
+        let crypto = remote_crypto
+            .iter()
+            .find(|c| {
+                c.tag == self.crypto.tag && c.suite == self.crypto.suite && !c.keys.is_empty()
+            })
+            .ok_or_else(|| io::Error::other("No matching crypto attribute in re-keying answer"))?;
+
+        let recv_key = BASE64_STANDARD
+            .decode(&crypto.keys[0].key_and_salt)
+            .map_err(io::Error::other)?;
+
+        let cipher = SrtpCipher::from_sdes_suite(&crypto.suite).unwrap();
+        let sessions = create_sessions(backend, cipher, &recv_key, &self.send_key)?;
+
+        Ok((
+            NegotiatedCrypto {
+                local: self.crypto.clone(),
+                remote: crypto.clone(),
+            },
+            sessions,
+        ))
+    }
+}
+
+fn local_crypto(tag: u32, suite: SrtpSuite, send_key: &[u8]) -> SrtpCrypto {
+    SrtpCrypto {
+        tag,
+        suite,
+        keys: vec![SrtpKeyingMaterial {
+            key_and_salt: BASE64_STANDARD.encode(send_key).into(),
+            lifetime: None,
+            mki: None,
+        }],
+        params: vec![],
+    }
+}
+
+/// Returns if both attributes describe the same key
+pub(super) fn is_same_key(a: &SrtpCrypto, b: &SrtpCrypto) -> bool {
+    a.tag == b.tag
+        && a.suite == b.suite
+        && a.keys.first().map(|k| &k.key_and_salt) == b.keys.first().map(|k| &k.key_and_salt)
+}
+
+/// Create a random master key & salt for the cipher
+fn random_key(cipher: SrtpCipher) -> Vec<u8> {
+    let mut key = vec![0u8; cipher.key_len() + cipher.salt_len()];
+    rand::rng().fill_bytes(&mut key);
+    key
+}
+
+fn create_sessions(
+    backend: SrtpBackend,
+    cipher: SrtpCipher,
+    recv_key: &[u8],
+    send_key: &[u8],
+) -> io::Result<SrtpSessions> {
+    let inbound = backend.create_session(cipher, SrtpDirection::Inbound, recv_key)?;
+    let outbound = backend.create_session(cipher, SrtpDirection::Outbound, send_key)?;
+
+    Ok((inbound, outbound))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote_crypto(tag: u32, suite: SrtpSuite) -> SrtpCrypto {
+        let cipher = SrtpCipher::from_sdes_suite(&suite).unwrap();
+
+        local_crypto(tag, suite, &random_key(cipher))
+    }
+
+    #[test]
+    fn offer_excludes_aes_192() {
+        let mut crypto = vec![];
+        SdesSrtpOffer::new().extend_crypto(&mut crypto);
+
+        let suites: Vec<_> = crypto.into_iter().map(|c| c.suite).collect();
+        assert_eq!(suites, SUITES);
+    }
+
+    #[test]
+    fn answer_skips_offered_aes_192() {
+        let offer = [
+            remote_crypto(1, AES_192_CM_HMAC_SHA1_80),
+            remote_crypto(2, AES_192_CM_HMAC_SHA1_32),
+            remote_crypto(3, AES_CM_128_HMAC_SHA1_32),
+        ];
+
+        let (negotiated, _) = negotiate_from_offer(SrtpBackend::default(), &offer).unwrap();
+        assert_eq!(negotiated.remote.tag, 3);
+        assert_eq!(negotiated.local.suite, AES_CM_128_HMAC_SHA1_32);
+
+        assert!(negotiate_from_offer(SrtpBackend::default(), &offer[..2]).is_err());
+    }
+
+    #[test]
+    fn offer_rejects_aes_192_answer() {
+        let answer = [remote_crypto(1, AES_192_CM_HMAC_SHA1_80)];
+
+        assert!(SdesSrtpOffer::new()
+            .receive_answer(SrtpBackend::default(), &answer)
+            .is_err());
     }
 }
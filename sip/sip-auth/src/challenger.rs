@@ -0,0 +1,562 @@
+use crate::digest::{hash_md5, hash_sha256, hash_sha512_trunc256, HashFn};
+use crate::RequestParts;
+use bytesstr::BytesStr;
+use sip_types::header::typed::{
+    Algorithm, AlgorithmValue, AuthChallenge, AuthResponse, DigestChallenge, DigestResponse,
+    QopOption, Username,
+};
+use sip_types::uri::SipUri;
+use sip_types::{Headers, Name, StatusCode};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Looks up the credentials of users authenticated by a [`DigestChallenger`]
+pub trait CredentialVerifier: Send + Sync + 'static {
+    /// Returns the password of `username` in `realm`, `None` if the user is unknown
+    fn password(&self, realm: &str, username: &str) -> Option<Vec<u8>>;
+}
+
+impl<F> CredentialVerifier for F
+where
+    F: Fn(&str, &str) -> Option<Vec<u8>> + Send + Sync + 'static,
+{
+    fn password(&self, realm: &str, username: &str) -> Option<Vec<u8>> {
+        self(realm, username)
+    }
+}
+
+/// Result of [`DigestChallenger::verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// The request carries valid credentials of the contained user
+    Authorized(BytesStr),
+
+    /// The request must be rejected with a challenge, see [`DigestChallenger::challenge`]
+    ///
+    /// `stale` is set when the credentials were valid but the nonce expired.
+    Unauthorized { stale: bool },
+}
+
+/// Challenges incoming requests (e.g. INVITE or REGISTER) using Digest authentication
+///
+/// Nonces are derived from the time they were created and a secret, so no state has to be kept for issued
+/// challenges. To detect replayed credentials the nonce-counts used with each nonce are remembered until the
+/// nonce expires.
+pub struct DigestChallenger<V> {
+    realm: BytesStr,
+    verifier: V,
+    secret: String,
+    used_nonces: Mutex<HashMap<BytesStr, UsedNonce>>,
+
+    /// Algorithm offered in challenges. Is SHA-256 by default
+    pub algorithm: AlgorithmValue,
+    /// How long a nonce can be used until the client is challenged again with `stale=true`. Is 5 minutes by default
+    pub nonce_lifetime: Duration,
+    /// Challenge as proxy using 407 responses and the `Proxy-Authenticate` header. Is false by default
+    pub is_proxy: bool,
+}
+
+impl<V: CredentialVerifier> DigestChallenger<V> {
+    pub fn new(realm: impl Into<BytesStr>, verifier: V) -> Self {
+        Self {
+            realm: realm.into(),
+            verifier,
+            secret: uuid::Uuid::new_v4().simple().to_string(),
+            used_nonces: Mutex::new(HashMap::new()),
+            algorithm: AlgorithmValue::SHA256,
+            nonce_lifetime: Duration::from_secs(300),
+            is_proxy: false,
+        }
+    }
+
+    /// Status code of the response carrying the challenge
+    pub fn status_code(&self) -> StatusCode {
+        if self.is_proxy {
+            StatusCode::PROXY_AUTHENTICATION_REQUIRED
+        } else {
+            StatusCode::UNAUTHORIZED
+        }
+    }
+
+    /// Add a new challenge to the headers of a response rejecting an unauthorized request
+    pub fn challenge(&self, response_headers: &mut Headers, stale: bool) {
+        let name = if self.is_proxy {
+            Name::PROXY_AUTHENTICATE
+        } else {
+            Name::WWW_AUTHENTICATE
+        };
+
+        let challenge = DigestChallenge {
+            realm: self.realm.clone(),
+            domain: None,
+            nonce: self.create_nonce(unix_time()).into(),
+            opaque: None,
+            stale,
+            algorithm: Algorithm::AlgorithmValue(self.algorithm.clone()),
+            qop: vec![QopOption::Auth],
+            userhash: false,
+            other: vec![],
+        };
+
+        response_headers.insert_type(name, &AuthChallenge::Digest(challenge));
+    }
+
+    /// Verify the credentials of an incoming request
+    pub fn verify(&self, request: RequestParts<'_>) -> Verification {
+        let name = if self.is_proxy {
+            Name::PROXY_AUTHORIZATION
+        } else {
+            Name::AUTHORIZATION
+        };
+
+        let responses = match request.headers.try_get::<Vec<AuthResponse>>(name) {
+            Some(Ok(responses)) => responses,
+            Some(Err(e)) => {
+                log::debug!("Failed to parse authorization header, {e}");
+                return Verification::Unauthorized { stale: false };
+            }
+            None => return Verification::Unauthorized { stale: false },
+        };
+
+        let response = responses.into_iter().find_map(|response| match response {
+            AuthResponse::Digest(response) if response.realm == self.realm => Some(response),
+            _ => None,
+        });
+
+        let Some(response) = response else {
+            return Verification::Unauthorized { stale: false };
+        };
+
+        let Username::Username(username) = &response.username else {
+            log::debug!("Rejecting digest response with non ASCII username");
+            return Verification::Unauthorized { stale: false };
+        };
+
+        if !self.verify_response(request, &response, username) {
+            return Verification::Unauthorized { stale: false };
+        }
+
+        // Only report an expired nonce as stale when the credentials were correct (RFC 2617 Section 3.2.1)
+        match self.nonce_age(&response.nonce) {
+            Some(age) if age < self.nonce_lifetime => {}
+            _ => return Verification::Unauthorized { stale: true },
+        }
+
+        if !self.use_nonce_count(&response) {
+            log::debug!("Rejecting replayed digest response of {username}");
+            return Verification::Unauthorized { stale: false };
+        }
+
+        Verification::Authorized(username.clone())
+    }
+
+    /// Remember the nonce-count of a verified response, returns false if it was used with the nonce before
+    ///
+    /// Responses without qop carry no nonce-count, their nonce can only be used once.
+    fn use_nonce_count(&self, response: &DigestResponse) -> bool {
+        let nc = response.qop_response.as_ref().map_or(0, |qop| qop.nc);
+        let now = unix_time();
+
+        let mut used_nonces = self.used_nonces.lock().unwrap();
+
+        // Forget about expired nonces, they are rejected as stale anyway
+        used_nonces.retain(|_, used| {
+            Duration::from_secs(now.saturating_sub(used.created)) < self.nonce_lifetime
+        });
+
+        let used = used_nonces
+            .entry(response.nonce.clone())
+            .or_insert_with(|| UsedNonce {
+                created: nonce_timestamp(&response.nonce).unwrap_or(now),
+                nonce_counts: HashSet::new(),
+            });
+
+        used.nonce_counts.insert(nc)
+    }
+
+    fn verify_response(
+        &self,
+        request: RequestParts<'_>,
+        response: &DigestResponse,
+        username: &str,
+    ) -> bool {
+        let hash = match &response.algorithm {
+            Algorithm::AlgorithmValue(algorithm) if *algorithm == self.algorithm => match algorithm
+            {
+                AlgorithmValue::MD5 => hash_md5 as HashFn,
+                AlgorithmValue::SHA256 => hash_sha256,
+                AlgorithmValue::SHA512256 => hash_sha512_trunc256,
+                _ => return false,
+            },
+            _ => return false,
+        };
+
+        // The credentials are only valid for the request-URI they were created for (RFC 2617 Section 3.2.2.5)
+        match response.uri.parse::<SipUri>() {
+            Ok(uri) if uri.compare(&request.line.uri) => {}
+            _ => {
+                log::debug!("Rejecting digest response for uri {}", response.uri);
+                return false;
+            }
+        }
+
+        let Some(password) = self.verifier.password(&self.realm, username) else {
+            return false;
+        };
+
+        let ha1 = hash(
+            [
+                format!("{}:{}:", username, self.realm).as_bytes(),
+                &password,
+            ]
+            .concat()
+            .as_slice(),
+        );
+
+        let Some(qop_response) = &response.qop_response else {
+            let ha2 = hash(format!("{}:{}", request.line.method, response.uri).as_bytes());
+            let expected = hash(format!("{}:{}:{}", ha1, response.nonce, ha2).as_bytes());
+
+            return constant_time_eq(response.response.as_bytes(), expected.as_bytes());
+        };
+
+        // The nonce-count starts at 1, it is only 0 if it is missing in a response without qop
+        if qop_response.nc == 0 {
+            return false;
+        }
+
+        let ha2 = match qop_response.qop {
+            QopOption::Auth => hash(format!("{}:{}", request.line.method, response.uri).as_bytes()),
+            QopOption::AuthInt => hash(
+                format!(
+                    "{}:{}:{}",
+                    request.line.method,
+                    response.uri,
+                    hash(request.body)
+                )
+                .as_bytes(),
+            ),
+            QopOption::Other(_) => return false,
+        };
+
+        // The nonce-count is hex encoded, accept implementations printing it in either case
+        [
+            format!("{:08x}", qop_response.nc),
+            format!("{:08X}", qop_response.nc),
+        ]
+        .iter()
+        .any(|nc| {
+            let expected = hash(
+                format!(
+                    "{}:{}:{}:{}:{}:{}",
+                    ha1, response.nonce, nc, qop_response.cnonce, qop_response.qop, ha2
+                )
+                .as_bytes(),
+            );
+
+            constant_time_eq(response.response.as_bytes(), expected.as_bytes())
+        })
+    }
+
+    fn create_nonce(&self, timestamp: u64) -> String {
+        format!(
+            "{:016x}{}",
+            timestamp,
+            hash_sha256(format!("{}:{}", timestamp, self.secret).as_bytes())
+        )
+    }
+
+    /// Returns the age of a nonce created by this challenger
+    fn nonce_age(&self, nonce: &str) -> Option<Duration> {
+        let timestamp = nonce_timestamp(nonce)?;
+
+        if self.create_nonce(timestamp) != nonce {
+            return None;
+        }
+
+        Some(Duration::from_secs(unix_time().saturating_sub(timestamp)))
+    }
+}
+
+/// Nonce-counts received with a nonce
+struct UsedNonce {
+    created: u64,
+    nonce_counts: HashSet<u32>,
+}
+
+fn nonce_timestamp(nonce: &str) -> Option<u64> {
+    u64::from_str_radix(nonce.get(..16)?, 16).ok()
+}
+
+/// Compare two responses without leaking the position of the first difference through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        ClientAuthenticator, DigestAuthenticator, DigestCredentials, DigestUser, ResponseParts,
+    };
+    use sip_types::{
+        msg::{RequestLine, StatusLine},
+        Method,
+    };
+
+    fn challenger() -> DigestChallenger<impl CredentialVerifier> {
+        DigestChallenger::new("example.org", |_: &str, username: &str| {
+            (username == "alice").then(|| b"secret".to_vec())
+        })
+    }
+
+    /// Let a client answer the challenge and return the headers of its authorized request
+    fn authorize(
+        challenger: &DigestChallenger<impl CredentialVerifier>,
+        line: &RequestLine,
+        password: &str,
+    ) -> Headers {
+        let mut headers = Headers::new();
+        authenticator(challenger, line, password).authorize_request(line, &mut headers, &[]);
+        headers
+    }
+
+    /// Create a client which answered the challenge of `challenger`
+    fn authenticator(
+        challenger: &DigestChallenger<impl CredentialVerifier>,
+        line: &RequestLine,
+        password: &str,
+    ) -> DigestAuthenticator {
+        let mut challenge_headers = Headers::new();
+        challenger.challenge(&mut challenge_headers, false);
+
+        let mut credentials = DigestCredentials::new();
+        credentials.set_default(DigestUser::new("alice", password));
+        let mut authenticator = DigestAuthenticator::new(credentials);
+
+        authenticator
+            .handle_rejection(
+                RequestParts {
+                    line,
+                    headers: &Headers::new(),
+                    body: &[],
+                },
+                ResponseParts {
+                    line: &StatusLine {
+                        code: challenger.status_code(),
+                        reason: None,
+                    },
+                    headers: &challenge_headers,
+                    body: &[],
+                },
+            )
+            .unwrap();
+
+        authenticator
+    }
+
+    fn invite() -> RequestLine {
+        RequestLine {
+            method: Method::INVITE,
+            uri: "sip:bob@example.org".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn unauthorized_without_credentials() {
+        let line = invite();
+
+        let verification = challenger().verify(RequestParts {
+            line: &line,
+            headers: &Headers::new(),
+            body: &[],
+        });
+
+        assert_eq!(verification, Verification::Unauthorized { stale: false });
+    }
+
+    #[test]
+    fn authorized_with_valid_credentials() {
+        let challenger = challenger();
+        let line = invite();
+        let headers = authorize(&challenger, &line, "secret");
+
+        let verification = challenger.verify(RequestParts {
+            line: &line,
+            headers: &headers,
+            body: &[],
+        });
+
+        assert_eq!(verification, Verification::Authorized("alice".into()));
+    }
+
+    #[test]
+    fn unauthorized_with_wrong_password() {
+        let challenger = challenger();
+        let line = invite();
+        let headers = authorize(&challenger, &line, "wrong");
+
+        let verification = challenger.verify(RequestParts {
+            line: &line,
+            headers: &headers,
+            body: &[],
+        });
+
+        assert_eq!(verification, Verification::Unauthorized { stale: false });
+    }
+
+    #[test]
+    fn stale_nonce() {
+        let mut challenger = challenger();
+        challenger.nonce_lifetime = Duration::ZERO;
+
+        let line = invite();
+        let headers = authorize(&challenger, &line, "secret");
+
+        let verification = challenger.verify(RequestParts {
+            line: &line,
+            headers: &headers,
+            body: &[],
+        });
+
+        assert_eq!(verification, Verification::Unauthorized { stale: true });
+    }
+
+    fn verify(
+        challenger: &DigestChallenger<impl CredentialVerifier>,
+        line: &RequestLine,
+        headers: &Headers,
+    ) -> Verification {
+        challenger.verify(RequestParts {
+            line,
+            headers,
+            body: &[],
+        })
+    }
+
+    fn edit_response(headers: &mut Headers, edit: impl FnOnce(&mut DigestResponse)) {
+        headers
+            .edit(Name::AUTHORIZATION, |responses: &mut Vec<AuthResponse>| {
+                let AuthResponse::Digest(response) = &mut responses[0] else {
+                    panic!("expected digest response");
+                };
+
+                edit(response);
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn replayed_response() {
+        let challenger = challenger();
+        let line = invite();
+        let headers = authorize(&challenger, &line, "secret");
+
+        assert_eq!(
+            verify(&challenger, &line, &headers),
+            Verification::Authorized("alice".into())
+        );
+        assert_eq!(
+            verify(&challenger, &line, &headers),
+            Verification::Unauthorized { stale: false }
+        );
+    }
+
+    #[test]
+    fn increasing_nonce_count() {
+        let challenger = challenger();
+        let line = invite();
+        let mut authenticator = authenticator(&challenger, &line, "secret");
+
+        for _ in 0..3 {
+            let mut headers = Headers::new();
+            authenticator.authorize_request(&line, &mut headers, &[]);
+
+            assert_eq!(
+                verify(&challenger, &line, &headers),
+                Verification::Authorized("alice".into())
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_uri() {
+        let challenger = challenger();
+        let line = invite();
+        let headers = authorize(&challenger, &line, "secret");
+
+        // Same method, different target
+        let other = RequestLine {
+            method: Method::INVITE,
+            uri: "sip:carol@example.org".parse().unwrap(),
+        };
+
+        assert_eq!(
+            verify(&challenger, &other, &headers),
+            Verification::Unauthorized { stale: false }
+        );
+
+        // Changing the uri parameter to match invalidates the response
+        let mut headers = headers;
+        edit_response(&mut headers, |response| {
+            response.uri = "sip:carol@example.org".into()
+        });
+
+        assert_eq!(
+            verify(&challenger, &other, &headers),
+            Verification::Unauthorized { stale: false }
+        );
+    }
+
+    #[test]
+    fn wrong_nonce_count() {
+        let challenger = challenger();
+        let line = invite();
+        let headers = authorize(&challenger, &line, "secret");
+
+        for nc in [0, 2] {
+            let mut headers = headers.clone();
+            edit_response(&mut headers, |response| {
+                response.qop_response.as_mut().unwrap().nc = nc;
+            });
+
+            assert_eq!(
+                verify(&challenger, &line, &headers),
+                Verification::Unauthorized { stale: false }
+            );
+        }
+
+        assert_eq!(
+            verify(&challenger, &line, &headers),
+            Verification::Authorized("alice".into())
+        );
+    }
+
+    #[test]
+    fn stale_nonce_of_replayed_response() {
+        let mut challenger = challenger();
+        let line = invite();
+        let headers = authorize(&challenger, &line, "secret");
+
+        assert_eq!(
+            verify(&challenger, &line, &headers),
+            Verification::Authorized("alice".into())
+        );
+
+        // Once the nonce expires the client is asked to retry with a new one
+        challenger.nonce_lifetime = Duration::ZERO;
+
+        assert_eq!(
+            verify(&challenger, &line, &headers),
+            Verification::Unauthorized { stale: true }
+        );
+    }
+}
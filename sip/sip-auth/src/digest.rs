@@ -6,8 +6,10 @@ use sip_types::header::typed::{
     QopResponse, Username,
 };
 use sip_types::header::HeaderError;
+use sip_types::msg::RequestLine;
 use sip_types::print::{AppendCtx, PrintCtx, UriContext};
 use sip_types::{Headers, Name};
+use std::cmp::Reverse;
 use std::collections::HashMap;
 
 #[derive(Debug, thiserror::Error)]
@@ -101,14 +103,17 @@ pub struct DigestAuthenticator {
 
 struct QopEntry {
     ha1: String,
-    ha2: String,
     hash: HashFn,
 }
 
 /// Contains a list of authentication challenges that want to authenticate the same realm.
 ///
-/// As each realm may only be authenticated once per request, only the topmost supported challenge will
-/// be used for authentication. (See RFC8760 Section 2.4)
+/// As each realm may only be authenticated once per request, only one supported challenge will be used for
+/// authentication.
+///
+/// RFC8760 Section 2.4 requires using the topmost supported challenge. This deliberately deviates from it and
+/// prefers the challenge with the strongest algorithm, as servers often list MD5 first for compatibility. Challenges
+/// with equally strong algorithms are tried in the order they were received.
 struct ChallengedRealm {
     realm: BytesStr,
     challenges: Vec<(bool, AuthChallenge)>,
@@ -131,7 +136,7 @@ pub(crate) struct ResponseEntry {
 impl ClientAuthenticator for DigestAuthenticator {
     type Error = DigestError;
 
-    fn authorize_request(&mut self, request_headers: &mut Headers) {
+    fn authorize_request(&mut self, line: &RequestLine, headers: &mut Headers, body: &[u8]) {
         for response in &mut self.responses {
             let name = if response.is_proxy {
                 Name::PROXY_AUTHORIZATION
@@ -139,44 +144,53 @@ impl ClientAuthenticator for DigestAuthenticator {
                 Name::AUTHORIZATION
             };
 
-            // nc is already correct
-            if response.use_count > 0 {
+            // qop response needs its nonce-count incremented and response re-calculated for the request
+            if let Some(qop_response) = &mut response.header.qop_response {
+                // nc is already correct for the first use
+                if response.use_count > 0 {
+                    qop_response.nc += 1;
+                }
+
                 let digest_realm = &response.header.realm;
 
-                // qop response needs its nonce-count incremented and response re-calculated
-                if let Some(qop_response) = &mut response.header.qop_response {
-                    qop_response.nc += 1;
+                let (_, qop_entry) = self
+                    .qop_responses
+                    .iter()
+                    .find(|(realm, _)| realm == digest_realm)
+                    .expect("qop_entry must be some");
 
-                    let (_, qop_entry) = self
-                        .qop_responses
-                        .iter_mut()
-                        .find(|(realm, _)| realm == digest_realm)
-                        .expect("qop_entry must be some");
-
-                    match qop_response.qop {
-                        QopOption::Auth | QopOption::AuthInt => {
-                            let hash = (qop_entry.hash)(
-                                format!(
-                                    "{}:{}:{:08X}:{}:auth:{}",
-                                    qop_entry.ha1,
-                                    response.header.nonce,
-                                    qop_response.nc,
-                                    qop_response.cnonce,
-                                    qop_entry.ha2
-                                )
-                                .as_bytes(),
-                            );
-
-                            response.header.response = hash.into();
-                        }
-                        QopOption::Other(_) => unreachable!(),
-                    };
-                }
+                let hash = qop_entry.hash;
+
+                let ha2 = match &qop_response.qop {
+                    QopOption::Auth => {
+                        hash(format!("{}:{}", line.method, response.header.uri).as_bytes())
+                    }
+                    QopOption::AuthInt => hash(
+                        format!("{}:{}:{}", line.method, response.header.uri, hash(body))
+                            .as_bytes(),
+                    ),
+                    QopOption::Other(_) => unreachable!(),
+                };
+
+                let response_hash = hash(
+                    format!(
+                        "{}:{}:{:08X}:{}:{}:{}",
+                        qop_entry.ha1,
+                        response.header.nonce,
+                        qop_response.nc,
+                        qop_response.cnonce,
+                        qop_response.qop,
+                        ha2
+                    )
+                    .as_bytes(),
+                );
+
+                response.header.response = response_hash.into();
             }
 
             response.use_count += 1;
 
-            request_headers.insert_type(name, &response.header);
+            headers.insert_type(name, &response.header);
         }
     }
 
@@ -192,7 +206,12 @@ impl ClientAuthenticator for DigestAuthenticator {
 
         let mut failed_realms = vec![];
 
-        'outer: for challenged_realm in challenged_realms {
+        'outer: for mut challenged_realm in challenged_realms {
+            // Try the strongest algorithm first, challenges using equally strong algorithms keep their order
+            challenged_realm
+                .challenges
+                .sort_by_key(|(_, challenge)| Reverse(algorithm_strength(challenge)));
+
             for (is_proxy, challenge) in challenged_realm.challenges {
                 let AuthChallenge::Digest(challenge) = challenge else {
                     continue;
@@ -430,7 +449,7 @@ impl DigestAuthenticator {
                     .as_bytes(),
                 );
 
-                self.save_qop_response(challenge.realm.clone(), ha1, ha2, hash);
+                self.save_qop_response(challenge.realm.clone(), ha1, hash);
 
                 let qop_response = QopResponse {
                     qop: QopOption::AuthInt,
@@ -453,7 +472,7 @@ impl DigestAuthenticator {
                     .as_bytes(),
                 );
 
-                self.save_qop_response(challenge.realm.clone(), ha1, ha2, hash);
+                self.save_qop_response(challenge.realm.clone(), ha1, hash);
 
                 let qop_response = QopResponse {
                     qop: QopOption::Auth,
@@ -500,14 +519,8 @@ impl DigestAuthenticator {
         })
     }
 
-    fn save_qop_response(
-        &mut self,
-        challenge_realm: BytesStr,
-        ha1: String,
-        ha2: String,
-        hash: HashFn,
-    ) {
-        let qop_entry = QopEntry { ha1, ha2, hash };
+    fn save_qop_response(&mut self, challenge_realm: BytesStr, ha1: String, hash: HashFn) {
+        let qop_entry = QopEntry { ha1, hash };
 
         if let Some((_, old_qop_entry)) = self
             .qop_responses
@@ -522,23 +535,42 @@ impl DigestAuthenticator {
     }
 }
 
-fn hash_md5(i: &[u8]) -> String {
+pub(crate) fn hash_md5(i: &[u8]) -> String {
     format!("{:x}", md5::compute(i))
 }
 
-fn hash_sha256(i: &[u8]) -> String {
+pub(crate) fn hash_sha256(i: &[u8]) -> String {
     let mut hasher = sha2::Sha256::new();
     hasher.update(i);
     format!("{:x}", hasher.finalize())
 }
 
-fn hash_sha512_trunc256(i: &[u8]) -> String {
+pub(crate) fn hash_sha512_trunc256(i: &[u8]) -> String {
     let mut hasher = sha2::Sha512_256::new();
     hasher.update(i);
     format!("{:x}", hasher.finalize())
 }
 
-type HashFn = fn(&[u8]) -> String;
+pub(crate) type HashFn = fn(&[u8]) -> String;
+
+/// Rank the challenge's algorithm, higher is stronger
+fn algorithm_strength(challenge: &AuthChallenge) -> u8 {
+    let AuthChallenge::Digest(challenge) = challenge else {
+        return 0;
+    };
+
+    let algorithm = match &challenge.algorithm {
+        Algorithm::AkaNamespace((_, av)) => av,
+        Algorithm::AlgorithmValue(av) => av,
+    };
+
+    match algorithm {
+        AlgorithmValue::SHA512256 | AlgorithmValue::SHA512256Sess => 3,
+        AlgorithmValue::SHA256 | AlgorithmValue::SHA256Sess => 2,
+        AlgorithmValue::MD5 | AlgorithmValue::MD5Sess => 1,
+        AlgorithmValue::Other(_) => 0,
+    }
+}
 
 #[cfg(test)]
 mod test {
@@ -603,7 +635,7 @@ mod test {
             .unwrap();
 
         let mut response_headers = Headers::new();
-        authenticator.authorize_request(&mut response_headers);
+        authenticator.authorize_request(&line, &mut response_headers, &[]);
 
         let authorization = response_headers
             .get::<AuthResponse>(Name::AUTHORIZATION)
@@ -684,7 +716,7 @@ mod test {
             .unwrap();
 
         let mut response_headers = Headers::new();
-        authenticator.authorize_request(&mut response_headers);
+        authenticator.authorize_request(&line, &mut response_headers, &[]);
 
         let response = response_headers
             .get::<AuthResponse>(Name::AUTHORIZATION)
@@ -720,7 +752,7 @@ mod test {
         };
 
         let mut response_headers = Headers::new();
-        authenticator.authorize_request(&mut response_headers);
+        authenticator.authorize_request(&line, &mut response_headers, &[]);
 
         let response = response_headers
             .get::<AuthResponse>(Name::AUTHORIZATION)
@@ -756,4 +788,162 @@ mod test {
             _ => panic!("Expected digest"),
         }
     }
+
+    fn challenge(algorithm: AlgorithmValue, qop: Vec<QopOption>) -> AuthChallenge {
+        AuthChallenge::Digest(DigestChallenge {
+            realm: "example.org".into(),
+            domain: None,
+            nonce: "YWmh5GFpoLjiTDCA1hTSSygkgdj99aHE".into(),
+            opaque: None,
+            stale: false,
+            algorithm: Algorithm::AlgorithmValue(algorithm),
+            qop,
+            userhash: false,
+            other: vec![],
+        })
+    }
+
+    fn register() -> RequestLine {
+        RequestLine {
+            method: Method::REGISTER,
+            uri: "sip:example.org".parse::<SipUri>().unwrap(),
+        }
+    }
+
+    fn reject(authenticator: &mut DigestAuthenticator, headers: &Headers, body: &[u8]) {
+        let line = register();
+
+        authenticator
+            .handle_rejection(
+                RequestParts {
+                    line: &line,
+                    headers: &Headers::new(),
+                    body,
+                },
+                ResponseParts {
+                    line: &StatusLine {
+                        code: StatusCode::UNAUTHORIZED,
+                        reason: None,
+                    },
+                    headers,
+                    body: &[],
+                },
+            )
+            .unwrap();
+    }
+
+    fn authorize(authenticator: &mut DigestAuthenticator, body: &[u8]) -> DigestResponse {
+        let mut headers = Headers::new();
+        authenticator.authorize_request(&register(), &mut headers, body);
+
+        match headers.get::<AuthResponse>(Name::AUTHORIZATION).unwrap() {
+            AuthResponse::Digest(response) => response,
+            _ => panic!("Expected digest"),
+        }
+    }
+
+    #[test]
+    fn strongest_algorithm() {
+        let mut authenticator = test_authenticator();
+
+        let mut headers = Headers::new();
+
+        for algorithm in [
+            AlgorithmValue::MD5,
+            AlgorithmValue::SHA512256,
+            AlgorithmValue::SHA256,
+        ] {
+            headers.insert_type(
+                Name::WWW_AUTHENTICATE,
+                &challenge(algorithm, vec![QopOption::Auth]),
+            );
+        }
+
+        reject(&mut authenticator, &headers, &[]);
+
+        let response = authorize(&mut authenticator, &[]);
+        assert_eq!(
+            response.algorithm,
+            Algorithm::AlgorithmValue(AlgorithmValue::SHA512256)
+        );
+    }
+
+    #[test]
+    fn auth_int_sha256() {
+        let mut authenticator = test_authenticator();
+
+        let mut headers = Headers::new();
+        headers.insert_type(
+            Name::WWW_AUTHENTICATE,
+            &challenge(AlgorithmValue::SHA256, vec![QopOption::AuthInt]),
+        );
+
+        reject(&mut authenticator, &headers, b"v=0\r\n");
+
+        let ha1 = hash_sha256(b"user123:example.org:password123");
+
+        // Each request's response protects its own body
+        let bodies: [&[u8]; 3] = [b"v=0\r\n", b"v=0\r\ns=-\r\n", b""];
+
+        for (nc, body) in (1..).zip(bodies) {
+            let response = authorize(&mut authenticator, body);
+            let qop_response = response.qop_response.unwrap();
+
+            assert_eq!(qop_response.qop, QopOption::AuthInt);
+            assert_eq!(qop_response.nc, nc);
+
+            let ha2 =
+                hash_sha256(format!("REGISTER:sip:example.org:{}", hash_sha256(body)).as_bytes());
+
+            let expected = hash_sha256(
+                format!(
+                    "{ha1}:YWmh5GFpoLjiTDCA1hTSSygkgdj99aHE:{nc:08X}:{}:auth-int:{ha2}",
+                    qop_response.cnonce
+                )
+                .as_bytes(),
+            );
+
+            assert_eq!(response.response, expected.as_str());
+        }
+    }
+
+    #[test]
+    fn auth_reused_for_other_method() {
+        let mut authenticator = test_authenticator();
+
+        let mut headers = Headers::new();
+        headers.insert_type(
+            Name::WWW_AUTHENTICATE,
+            &challenge(AlgorithmValue::MD5, vec![QopOption::Auth]),
+        );
+
+        reject(&mut authenticator, &headers, &[]);
+        authorize(&mut authenticator, &[]);
+
+        let line = RequestLine {
+            method: Method::OPTIONS,
+            uri: "sip:example.org".parse::<SipUri>().unwrap(),
+        };
+
+        let mut headers = Headers::new();
+        authenticator.authorize_request(&line, &mut headers, &[]);
+
+        let AuthResponse::Digest(response) = headers.get(Name::AUTHORIZATION).unwrap() else {
+            panic!("Expected digest");
+        };
+        let qop_response = response.qop_response.unwrap();
+
+        let ha1 = hash_md5(b"user123:example.org:password123");
+        let ha2 = hash_md5(b"OPTIONS:sip:example.org");
+
+        let expected = hash_md5(
+            format!(
+                "{ha1}:YWmh5GFpoLjiTDCA1hTSSygkgdj99aHE:00000002:{}:auth:{ha2}",
+                qop_response.cnonce
+            )
+            .as_bytes(),
+        );
+
+        assert_eq!(response.response, expected.as_str());
+    }
 }
@@ -3,8 +3,10 @@ use sip_types::Headers;
 use std::error::Error;
 use std::fmt::Debug;
 
+mod challenger;
 mod digest;
 
+pub use challenger::{CredentialVerifier, DigestChallenger, Verification};
 pub use digest::{DigestAuthenticator, DigestCredentials, DigestError, DigestUser};
 
 /// SIP request authenticator
@@ -13,8 +15,9 @@ pub trait ClientAuthenticator {
 
     /// Modify a request's header to add the required authorization
     ///
-    /// Implementations like Digest will do nothing here before receiving a rejection response
-    fn authorize_request(&mut self, request: &mut Headers);
+    /// Implementations like Digest will do nothing here before receiving a rejection response. The request line and
+    /// body are required to calculate responses which protect them, e.g. Digest with `qop=auth-int`.
+    fn authorize_request(&mut self, line: &RequestLine, headers: &mut Headers, body: &[u8]);
 
     /// Handle a rejection request
     ///
@@ -0,0 +1,244 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// Range of IP addresses in CIDR notation, e.g. `192.168.0.0/16` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid IP network {0:?}")]
+pub struct InvalidIpNetwork(String);
+
+impl IpNetwork {
+    /// Create a network from an address and prefix length, returns `None` if the prefix length is too long
+    ///
+    /// IPv4-mapped IPv6 networks (`::ffff:0:0/96` and longer prefixes) are converted to the IPv4 network.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        let max_len = match addr {
+            IpAddr::V4(..) => 32,
+            IpAddr::V6(..) => 128,
+        };
+
+        if prefix_len > max_len {
+            return None;
+        }
+
+        if let IpAddr::V6(v6) = addr {
+            if let Some(v4) = v6.to_ipv4_mapped().filter(|_| prefix_len >= 96) {
+                return Some(Self {
+                    addr: IpAddr::V4(v4),
+                    prefix_len: prefix_len - 96,
+                });
+            }
+        }
+
+        Some(Self { addr, prefix_len })
+    }
+
+    /// Returns if the address is part of the network
+    ///
+    /// IPv4-mapped IPv6 addresses are treated as IPv4 addresses, other addresses are never part of a network of the
+    /// other address family.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => prefix_matches(
+                u128::from(u32::from(network)) << 96,
+                u128::from(u32::from(ip)) << 96,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(network), u128::from(ip), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: u128, ip: u128, prefix_len: u8) -> bool {
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(prefix_len))
+        .unwrap_or(0);
+
+    network & mask == ip & mask
+}
+
+impl From<IpAddr> for IpNetwork {
+    fn from(addr: IpAddr) -> Self {
+        let addr = addr.to_canonical();
+
+        let prefix_len = match addr {
+            IpAddr::V4(..) => 32,
+            IpAddr::V6(..) => 128,
+        };
+
+        Self { addr, prefix_len }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = InvalidIpNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidIpNetwork(s.into());
+
+        let Some((addr, prefix_len)) = s.split_once('/') else {
+            return s.parse::<IpAddr>().map(Self::from).map_err(|_| invalid());
+        };
+
+        let addr = addr.parse().map_err(|_| invalid())?;
+        let prefix_len = prefix_len.parse().map_err(|_| invalid())?;
+
+        Self::new(addr, prefix_len).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Allow and deny lists of IP networks, evaluated by the [`Endpoint`](crate::Endpoint) for every received message
+/// before any processing
+///
+/// A source is rejected if it is part of a denied network. If any allowed networks are set, sources outside of
+/// them are rejected as well. Messages of rejected sources are dropped without a response.
+#[derive(Debug, Default, Clone)]
+pub struct AccessControl {
+    allow: Vec<IpNetwork>,
+    deny: Vec<IpNetwork>,
+}
+
+impl AccessControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accept messages from the given network (and any other allowed network)
+    pub fn allow(mut self, network: IpNetwork) -> Self {
+        self.allow.push(network);
+        self
+    }
+
+    /// Reject all messages from the given network, takes precedence over allowed networks
+    pub fn deny(mut self, network: IpNetwork) -> Self {
+        self.deny.push(network);
+        self
+    }
+
+    /// Returns if messages from the given address are accepted
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|network| network.contains(ip)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|network| network.contains(ip))
+    }
+}
+
+/// A message was dropped because its source was rejected by the endpoint's [`AccessControl`]
+#[derive(Debug, Clone)]
+pub struct RejectedSource {
+    pub source: SocketAddr,
+    /// Total number of messages rejected by the endpoint, including this one
+    pub rejected_count: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let valid = [
+            ("0.0.0.0/0", "0.0.0.0/0"),
+            ("10.0.0.0/8", "10.0.0.0/8"),
+            ("10.1.2.3/32", "10.1.2.3/32"),
+            ("10.1.2.3", "10.1.2.3/32"),
+            ("::/0", "::/0"),
+            ("2001:db8::/32", "2001:db8::/32"),
+            ("2001:db8::1/128", "2001:db8::1/128"),
+            ("2001:db8::1", "2001:db8::1/128"),
+            ("::ffff:10.0.0.0/104", "10.0.0.0/8"),
+            ("::ffff:10.1.2.3/128", "10.1.2.3/32"),
+            ("::ffff:10.1.2.3", "10.1.2.3/32"),
+            ("::ffff:0.0.0.0/96", "0.0.0.0/0"),
+            // Shorter prefixes include addresses which aren't IPv4-mapped
+            ("::ffff:0.0.0.0/80", "::ffff:0.0.0.0/80"),
+        ];
+
+        for (input, expected) in valid {
+            let network: IpNetwork = input.parse().unwrap();
+            assert_eq!(network.to_string(), expected, "{input}");
+        }
+
+        let invalid = [
+            "",
+            "/8",
+            "10.0.0.0/",
+            "10.0.0.0/33",
+            "10.0.0.0/-1",
+            "10.0.0.0/8/8",
+            "10.0.0.0/a",
+            "::/129",
+            "::ffff:10.0.0.0/129",
+            "example.org/8",
+        ];
+
+        for input in invalid {
+            assert!(input.parse::<IpNetwork>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn contains() {
+        let cases = [
+            ("0.0.0.0/0", "192.0.2.1", true),
+            ("0.0.0.0/0", "::ffff:192.0.2.1", true),
+            ("0.0.0.0/0", "2001:db8::1", false),
+            ("0.0.0.0/0", "::1", false),
+            ("10.0.0.0/8", "10.255.0.1", true),
+            ("10.0.0.0/8", "11.0.0.1", false),
+            ("10.0.0.0/8", "::ffff:10.0.0.1", true),
+            ("10.1.2.3/32", "10.1.2.3", true),
+            ("10.1.2.3/32", "10.1.2.4", false),
+            ("::/0", "2001:db8::1", true),
+            ("::/0", "192.0.2.1", false),
+            ("::/0", "::ffff:192.0.2.1", false),
+            ("2001:db8::/32", "2001:db8:ffff::1", true),
+            ("2001:db8::/32", "2001:db9::1", false),
+            ("2001:db8::1/128", "2001:db8::1", true),
+            ("2001:db8::1/128", "2001:db8::2", false),
+            ("::ffff:10.0.0.0/104", "10.0.0.1", true),
+            ("::ffff:10.0.0.0/104", "::ffff:10.0.0.1", true),
+            ("::ffff:10.0.0.0/104", "11.0.0.1", false),
+        ];
+
+        for (network, ip, expected) in cases {
+            let network: IpNetwork = network.parse().unwrap();
+            let ip: IpAddr = ip.parse().unwrap();
+
+            assert_eq!(network.contains(ip), expected, "{network} contains {ip}");
+        }
+    }
+
+    #[test]
+    fn access_control() {
+        let acl = AccessControl::new()
+            .allow("10.0.0.0/8".parse().unwrap())
+            .allow("2001:db8::/32".parse().unwrap())
+            .deny("::ffff:10.0.0.0/120".parse().unwrap());
+
+        assert!(acl.is_allowed("10.1.0.1".parse().unwrap()));
+        assert!(acl.is_allowed("2001:db8::1".parse().unwrap()));
+        assert!(!acl.is_allowed("10.0.0.1".parse().unwrap()));
+        assert!(!acl.is_allowed("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(!acl.is_allowed("192.0.2.1".parse().unwrap()));
+
+        assert!(AccessControl::new().is_allowed("192.0.2.1".parse().unwrap()));
+    }
+}
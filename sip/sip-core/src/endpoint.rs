@@ -2,27 +2,37 @@ use crate::transaction::{ClientInvTsx, ClientTsx, ServerInvTsx, ServerTsx, TsxKe
 use crate::transaction::{Transactions, TsxMessage};
 use crate::transport::{
     Direction, Factory, OutgoingParts, OutgoingRequest, OutgoingResponse, ReceivedMessage,
-    TargetTransportInfo, TpHandle, Transports, TransportsBuilder,
+    TargetTransportInfo, TpHandle, TransportSwitch, Transports, TransportsBuilder,
+};
+use crate::{
+    AccessControl, BaseHeaders, IncomingRequest, Layer, MayTake, RejectedSource, Request, Response,
+    Result, StunError,
 };
-use crate::{BaseHeaders, IncomingRequest, Layer, MayTake, Request, Response, Result, StunError};
 use bytes::{Bytes, BytesMut};
 use bytesstr::BytesStr;
-use sip_types::header::typed::{Accept, Allow, Supported, Via};
+use parking_lot::Mutex;
+use sip_types::header::typed::{Accept, Allow, Routing, Supported, Via};
 use sip_types::host::{Host, HostPort};
 use sip_types::msg::{MessageLine, StatusLine};
 use sip_types::print::{AppendCtx, BytesPrint, PrintCtx};
+use sip_types::uri::params::Param;
 use sip_types::uri::SipUri;
 use sip_types::{Headers, Method, Name, StatusCode};
 use std::any::type_name;
 use std::fmt::Write;
 use std::mem::take;
 use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::{fmt, io};
 use stun_types::Message;
 use tokio::sync::broadcast;
 use tracing::Instrument;
 
+/// Size in bytes above which requests must be sent using a congestion controlled transport
+/// if possible (RFC 3261 Section 18.1.1)
+const MAX_UNRELIABLE_REQUEST_SIZE: usize = 1300;
+
 /// The endpoint is the centerpiece of the sip stack. It contains all information about the
 /// application and a stack of layered modules which build the logic of SIP applications and
 /// its extensions.
@@ -49,6 +59,15 @@ struct Inner {
     supported: Vec<Supported>,
     user_agent: Option<BytesStr>,
 
+    /// Methods claimed at runtime using [`Endpoint::claim_method`]
+    claimed_methods: Mutex<Vec<Method>>,
+    reject_unsupported_methods: bool,
+
+    access_control: Option<AccessControl>,
+    rejected_count: AtomicU64,
+    rejected_sources: broadcast::Sender<RejectedSource>,
+
+    transport_switches: broadcast::Sender<TransportSwitch>,
     transports: Transports,
     transactions: Transactions,
 
@@ -70,6 +89,15 @@ impl Endpoint {
         ClientInvTsx::send(self.clone(), request, target).await
     }
 
+    /// Sends a CANCEL request for the INVITE of the given transaction and return a [`ClientTsx`] which MUST be
+    /// used to drive the transaction
+    ///
+    /// The CANCEL is sent to the same destination using the INVITE's Via header, so the peer can match it to the
+    /// INVITE transaction (RFC 3261 Section 9.1).
+    pub async fn send_cancel(&self, request: Request, invite: &ClientInvTsx) -> Result<ClientTsx> {
+        ClientTsx::send_cancel(self.clone(), request, invite.request()).await
+    }
+
     /// Sends a request and return a [`ClientTsx`] which MUST be used to drive the transaction
     pub async fn send_request(
         &self,
@@ -96,6 +124,79 @@ impl Endpoint {
         &self.inner.allow
     }
 
+    /// Returns the ALLOW headers of this endpoint including all methods claimed using
+    /// [`claim_method`](Self::claim_method)
+    pub fn allow_header(&self) -> Vec<Allow> {
+        let mut allow = self.inner.allow.clone();
+
+        for method in self.inner.claimed_methods.lock().iter() {
+            if !allow.iter().any(|Allow(allowed)| allowed == method) {
+                allow.push(Allow(method.clone()));
+            }
+        }
+
+        allow
+    }
+
+    /// Claim a method at runtime, which is then treated as if it was part of the endpoint's ALLOW capabilities
+    ///
+    /// If enabled using [`EndpointBuilder::reject_unsupported_methods`], requests with a method that is neither
+    /// allowed nor claimed are responded to with `405 Method Not Allowed` if no layer handles them.
+    pub fn claim_method(&self, method: Method) {
+        let mut claimed_methods = self.inner.claimed_methods.lock();
+
+        if !claimed_methods.contains(&method) {
+            claimed_methods.push(method);
+        }
+    }
+
+    /// Release a method previously claimed using [`claim_method`](Self::claim_method)
+    pub fn release_method(&self, method: &Method) {
+        self.inner.claimed_methods.lock().retain(|m| m != method);
+    }
+
+    /// Returns if the method is part of the ALLOW capabilities or was claimed using
+    /// [`claim_method`](Self::claim_method)
+    pub fn is_method_allowed(&self, method: &Method) -> bool {
+        self.inner
+            .allow
+            .iter()
+            .any(|Allow(allowed)| allowed == method)
+            || self.inner.claimed_methods.lock().contains(method)
+    }
+
+    /// Create a `405 Method Not Allowed` response including the ALLOW headers, if the request's method is not
+    /// allowed by the endpoint
+    ///
+    /// A `501 Not Implemented` response is created instead if the endpoint doesn't allow any method, as a `405`
+    /// must list the allowed methods. Returns `None` if the method is allowed, the request is an ACK or CANCEL, or
+    /// responding to unsupported methods wasn't enabled using [`EndpointBuilder::reject_unsupported_methods`].
+    pub fn create_method_not_allowed_response(
+        &self,
+        request: &IncomingRequest,
+    ) -> Option<OutgoingResponse> {
+        let method = &request.line.method;
+
+        if !self.inner.reject_unsupported_methods
+            || *method == Method::ACK
+            || *method == Method::CANCEL
+            || self.is_method_allowed(method)
+        {
+            return None;
+        }
+
+        let allow = self.allow_header();
+
+        if allow.is_empty() {
+            return Some(self.create_response(request, StatusCode::NOT_IMPLMENTED, None));
+        }
+
+        let mut response = self.create_response(request, StatusCode::METHOD_NOT_ALLOWED, None);
+        response.msg.headers.insert_named(&allow);
+
+        Some(response)
+    }
+
     /// Returns all SUPPORTED headers this endpoint supports
     pub fn supported(&self) -> &Vec<Supported> {
         &self.inner.supported
@@ -108,11 +209,16 @@ impl Endpoint {
         tsx_key: &TsxKey,
         via_host_port: Option<HostPort>,
     ) -> Via {
-        Via::new(
+        let mut via = Via::new(
             transport.name(),
             via_host_port.unwrap_or_else(|| transport.sent_by().into()),
             tsx_key.branch().clone(),
-        )
+        );
+
+        // Ask the peer to report the source port the request was received from (RFC 3581)
+        via.params.push(Param::name("rport"));
+
+        via
     }
 
     /// Try to find or create a suitable transport for a given uri and return a non-empty list
@@ -121,20 +227,55 @@ impl Endpoint {
         self.transports().select(self, uri).await
     }
 
+    /// Like [`select_transport`](Self::select_transport) but only selects transports bound to the given local IP
+    /// address, e.g. to send a call's requests over a specific network interface on multi-homed hosts
+    ///
+    /// The result can be stored in [`TargetTransportInfo::transport`] to use it for all requests of a dialog.
+    pub async fn select_transport_from(
+        &self,
+        uri: &SipUri,
+        local_ip: IpAddr,
+    ) -> Result<(TpHandle, SocketAddr)> {
+        self.transports().select_from(self, uri, local_ip).await
+    }
+
+    /// Resolve the URI and select the transport used to send requests to it, unless the target has one already
+    ///
+    /// Done implicitly when sending the first request, but can be started earlier to run concurrently to other
+    /// work, e.g. gathering ICE candidates for the SDP offer. The servers resolved for the URI are kept in the
+    /// target to [fail over](Self::failover) to.
+    pub async fn resolve_target(
+        &self,
+        uri: &SipUri,
+        target: &mut TargetTransportInfo,
+    ) -> Result<()> {
+        if target.transport.is_some() {
+            return Ok(());
+        }
+
+        let (transport, destination, failover) =
+            self.transports().select_failover(self, uri).await?;
+        target.transport = Some((transport, destination));
+        target.failover = Some(failover);
+
+        Ok(())
+    }
+
     /// Takes a request and converts it into an `Outgoing`.
     /// To do so it calculates the destination and retrieves a suitable transport
+    ///
+    /// Requests with a preloaded route set, e.g. to use an outbound proxy, are sent to the first `Route`.
     pub async fn create_outgoing(
         &self,
         request: Request,
         target: &mut TargetTransportInfo,
     ) -> Result<OutgoingRequest> {
-        let (transport, destination) = if let Some((transport, destination)) = &target.transport {
-            (transport.clone(), *destination)
-        } else {
-            let (transport, destination) = self.select_transport(&request.line.uri).await?;
-            target.transport = Some((transport.clone(), destination));
-            (transport, destination)
-        };
+        self.resolve_target(&next_hop(&request), target).await?;
+
+        let (transport, destination) = target
+            .transport
+            .clone()
+            .expect("resolve_target must set the transport");
 
         Ok(OutgoingRequest {
             msg: request,
@@ -146,8 +287,129 @@ impl Endpoint {
         })
     }
 
+    /// Switch the target to the next server resolved for the URI it was created for (RFC 3263 Section 4.3)
+    ///
+    /// Must be called when the server in use didn't respond to a request or responded with
+    /// `503 Service Unavailable`, then the request must be sent again using a new transaction. Servers are tried
+    /// in the order of their NAPTR and SRV records. Returns `false` if no server is left, or the target's
+    /// transport wasn't selected by the endpoint.
+    pub async fn failover(&self, target: &mut TargetTransportInfo) -> bool {
+        let Some(failover) = &mut target.failover else {
+            return false;
+        };
+
+        let Some((transport, destination)) = self.transports().select_next(self, failover).await
+        else {
+            target.failover = None;
+            return false;
+        };
+
+        log::debug!("Failing over to {destination} using {transport}");
+
+        target.transport = Some((transport, destination));
+
+        true
+    }
+
+    /// Internal: Used by the client transactions to create the outgoing request including
+    /// the transaction's Via header.
+    ///
+    /// Requests which exceed [`MAX_UNRELIABLE_REQUEST_SIZE`] are switched to a reliable transport
+    /// if one can be found, as required by RFC 3261 Section 18.1.1. Otherwise the request
+    /// is sent using the originally selected transport.
+    pub(crate) async fn create_outgoing_tsx_request(
+        &self,
+        request: Request,
+        target: &mut TargetTransportInfo,
+        tsx_key: &TsxKey,
+    ) -> Result<OutgoingRequest> {
+        let mut request = self.create_outgoing(request, target).await?;
+
+        let via = self.create_via(
+            &request.parts.transport,
+            tsx_key,
+            target.via_host_port.clone(),
+        );
+
+        request.msg.headers.insert_named_front(&via);
+
+        if request.parts.transport.reliable() {
+            return Ok(request);
+        }
+
+        self.print_outgoing_request(&mut request)?;
+
+        if request.parts.buffer.len() <= MAX_UNRELIABLE_REQUEST_SIZE {
+            return Ok(request);
+        }
+
+        match self
+            .transports()
+            .select_reliable(self, &next_hop(&request.msg))
+            .await
+        {
+            Ok((transport, destination)) => {
+                log::info!(
+                    "Request of {} bytes exceeds the UDP size limit, switching transport from {} to {}",
+                    request.parts.buffer.len(),
+                    request.parts.transport,
+                    transport
+                );
+
+                // Nobody may be subscribed, ignore the error
+                let _ = self.inner.transport_switches.send(TransportSwitch {
+                    request_size: request.parts.buffer.len(),
+                    from: request.parts.transport.clone(),
+                    to: transport.clone(),
+                    destination,
+                });
+
+                let via = self.create_via(&transport, tsx_key, target.via_host_port.clone());
+
+                request
+                    .msg
+                    .headers
+                    .edit(Name::VIA, |vias: &mut Vec<Via>| vias[0] = via)?;
+
+                target.transport = Some((transport.clone(), destination));
+
+                request.parts = OutgoingParts {
+                    transport,
+                    destination,
+                    buffer: Default::default(),
+                };
+            }
+            Err(e) => {
+                log::debug!(
+                    "No reliable transport available for request of {} bytes, sending it via {}, {e}",
+                    request.parts.buffer.len(),
+                    request.parts.transport
+                );
+            }
+        }
+
+        Ok(request)
+    }
+
     /// Print the request to its buffer (if needed) and send it via the transport
     pub async fn send_outgoing_request(&self, message: &mut OutgoingRequest) -> io::Result<()> {
+        self.print_outgoing_request(message)?;
+
+        log::trace!(
+            "Sending request to {:?}\n{:?}",
+            &message.parts.destination,
+            BytesPrint(&message.parts.buffer)
+        );
+
+        message
+            .parts
+            .transport
+            .send(&message.parts.buffer, message.parts.destination)
+            .await
+    }
+
+    /// Print the request to its buffer, if it hasn't been printed already
+    fn print_outgoing_request(&self, message: &mut OutgoingRequest) -> io::Result<()> {
         // Append the endpoints configured user agent, if there isn't one already
         if let Some(user_agent) = &self.inner.user_agent {
             if !message.msg.headers.contains(&Name::USER_AGENT) {
@@ -176,7 +438,7 @@ impl Endpoint {
             )
             .map_err(|e| {
                 // wrap
-                io::Error::new(io::ErrorKind::Other, e)
+                io::Error::other(e)
             })?;
 
             buffer.extend_from_slice(&message.msg.body);
@@ -184,17 +446,7 @@ impl Endpoint {
             message.parts.buffer = buffer.freeze();
         }
 
-        log::trace!(
-            "Sending request to {:?}\n{:?}",
-            &message.parts.destination,
-            BytesPrint(&message.parts.buffer)
-        );
-
-        message
-            .parts
-            .transport
-            .send(&message.parts.buffer, message.parts.destination)
-            .await
+        Ok(())
     }
 
     /// Print the request to its buffer (if needed) and send it via the transport
@@ -220,7 +472,7 @@ impl Endpoint {
             )
             .map_err(|e| {
                 // wrap
-                io::Error::new(io::ErrorKind::Other, e)
+                io::Error::other(e)
             })?;
 
             buffer.extend_from_slice(&message.msg.body);
@@ -306,6 +558,47 @@ impl Endpoint {
         }
     }
 
+    /// Returns the number of messages dropped because their source was rejected by the endpoint's
+    /// [`AccessControl`]
+    pub fn rejected_count(&self) -> u64 {
+        self.inner.rejected_count.load(Ordering::Relaxed)
+    }
+
+    /// Receive an event for every message dropped because its source was rejected by the endpoint's
+    /// [`AccessControl`]
+    pub fn subscribe_rejected_sources(&self) -> broadcast::Receiver<RejectedSource> {
+        self.inner.rejected_sources.subscribe()
+    }
+
+    /// Receive an event for every request sent using a reliable transport instead of the one selected for it,
+    /// because it was too large for an unreliable transport
+    pub fn subscribe_transport_switches(&self) -> broadcast::Receiver<TransportSwitch> {
+        self.inner.transport_switches.subscribe()
+    }
+
+    /// Returns if the source is accepted by the endpoint's [`AccessControl`], counts and reports it otherwise
+    fn check_access(&self, source: SocketAddr) -> bool {
+        let Some(access_control) = &self.inner.access_control else {
+            return true;
+        };
+
+        if access_control.is_allowed(source.ip()) {
+            return true;
+        }
+
+        let rejected_count = self.inner.rejected_count.fetch_add(1, Ordering::Relaxed) + 1;
+
+        log::debug!("Dropping message from rejected source {source}");
+
+        // Nobody may be subscribed, ignore the error
+        let _ = self.inner.rejected_sources.send(RejectedSource {
+            source,
+            rejected_count,
+        });
+
+        false
+    }
+
     /// Pass a received message to the endpoint for further processing
     ///
     /// Spawns a task internally which will let every registered layer have a look at the message
@@ -316,6 +609,10 @@ impl Endpoint {
 
     #[tracing::instrument(level = "debug", skip(self, message), fields(%message))]
     async fn do_receive(self, mut message: ReceivedMessage) {
+        if !self.check_access(message.tp_info.source) {
+            return;
+        }
+
         log::trace!(
             "Received from {}: \n{:?}",
             message.tp_info.source,
@@ -430,11 +727,15 @@ impl Endpoint {
             return Ok(());
         }
 
-        let response = self.create_response(
-            &request,
-            StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST,
-            None,
-        );
+        let response = self
+            .create_method_not_allowed_response(&request)
+            .unwrap_or_else(|| {
+                self.create_response(
+                    &request,
+                    StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST,
+                    None,
+                )
+            });
 
         if request.line.method == Method::INVITE {
             let tsx = self.create_server_inv_tsx(&mut request);
@@ -489,6 +790,17 @@ impl Endpoint {
     }
 }
 
+/// URI the next hop of a request is resolved from, the first `Route` of a preloaded route set or the request URI
+/// (RFC 3261 Section 8.1.2)
+fn next_hop(request: &Request) -> SipUri {
+    request
+        .headers
+        .get::<Vec<Routing>>(Name::ROUTE)
+        .ok()
+        .and_then(|route_set| route_set.into_iter().next())
+        .map_or_else(|| request.line.uri.clone(), |route| route.uri.uri)
+}
+
 fn add_received_rport(via: &mut Via, source: SocketAddr) {
     let source_host: Host = source.ip().into();
 
@@ -510,6 +822,8 @@ pub struct EndpointBuilder {
     allow: Vec<Allow>,
     supported: Vec<Supported>,
     user_agent: Option<BytesStr>,
+    reject_unsupported_methods: bool,
+    access_control: Option<AccessControl>,
 
     transports: TransportsBuilder,
     layer: Vec<Box<dyn Layer>>,
@@ -531,6 +845,8 @@ impl EndpointBuilder {
             allow: vec![],
             supported: vec![],
             user_agent: None,
+            reject_unsupported_methods: false,
+            access_control: None,
             transports: Default::default(),
             layer: Default::default(),
         }
@@ -544,9 +860,11 @@ impl EndpointBuilder {
         self.accept.push(accepted.into())
     }
 
-    /// Add an ALLOW header to the endpoints capabilities
+    /// Add an ALLOW header to the endpoints capabilities, methods which are already allowed are ignored
     pub fn add_allow(&mut self, allowed: Method) {
-        self.allow.push(Allow(allowed))
+        if !self.allow.iter().any(|Allow(method)| *method == allowed) {
+            self.allow.push(Allow(allowed))
+        }
     }
 
     /// Add an SUPPORTED header to the endpoints capabilities
@@ -565,6 +883,27 @@ impl EndpointBuilder {
         self.user_agent = Some(user_agent.into())
     }
 
+    /// Respond to unhandled requests whose method is not allowed with `405 Method Not Allowed` instead of
+    /// `481 Call/Transaction Does Not Exist`. Disabled by default.
+    ///
+    /// The allowed methods are the ones added by layers using [`add_allow`](Self::add_allow) and claimed at runtime
+    /// using [`Endpoint::claim_method`]. Enable it only if all methods the application handles are allowed this way.
+    ///
+    /// See [`Endpoint::create_method_not_allowed_response`]
+    pub fn reject_unsupported_methods(&mut self, enabled: bool) -> &mut Self {
+        self.reject_unsupported_methods = enabled;
+        self
+    }
+
+    /// Drop messages from sources rejected by the given allow and deny lists before processing them
+    ///
+    /// Rejected messages are counted and reported, see [`Endpoint::rejected_count`] and
+    /// [`Endpoint::subscribe_rejected_sources`].
+    pub fn set_access_control(&mut self, access_control: AccessControl) -> &mut Self {
+        self.access_control = Some(access_control);
+        self
+    }
+
     /// Add an unmanaged transport to the endpoint which will never vanish or break (e.g. UDP)
     pub fn add_unmanaged_transport(&mut self, transport: TpHandle) -> &mut Self {
         self.transports.insert_unmanaged(transport);
@@ -616,6 +955,12 @@ impl EndpointBuilder {
             allow: take(&mut self.allow),
             supported: take(&mut self.supported),
             user_agent: take(&mut self.user_agent),
+            claimed_methods: Mutex::new(vec![]),
+            reject_unsupported_methods: self.reject_unsupported_methods,
+            access_control: take(&mut self.access_control),
+            rejected_count: AtomicU64::new(0),
+            rejected_sources: broadcast::channel(16).0,
+            transport_switches: broadcast::channel(16).0,
             transports: self.transports.build(),
             transactions: Default::default(),
             layer,
@@ -11,6 +11,8 @@ pub enum Error {
     Header(#[from] HeaderError),
     #[error("request timed out")]
     RequestTimedOut,
+    #[error("request cancelled")]
+    Cancelled,
 }
 
 #[derive(Debug, thiserror::Error)]
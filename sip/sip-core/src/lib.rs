@@ -19,11 +19,13 @@ use transport::MessageTpInfo;
 
 #[macro_use]
 mod error;
+mod acl;
 mod endpoint;
 mod may_take;
 pub mod transaction;
 pub mod transport;
 
+pub use acl::{AccessControl, InvalidIpNetwork, IpNetwork, RejectedSource};
 pub use endpoint::Endpoint;
 pub use endpoint::EndpointBuilder;
 pub use error::{Error, Result, StunError};
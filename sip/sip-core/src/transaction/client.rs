@@ -3,11 +3,12 @@ use super::key::TsxKey;
 use super::{TsxRegistration, TsxResponse};
 use crate::error::Error;
 use crate::transaction::consts::T4;
-use crate::transport::{OutgoingRequest, TargetTransportInfo};
+use crate::transport::{OutgoingParts, OutgoingRequest, TargetTransportInfo};
 use crate::{Endpoint, Request, Result};
+use sip_types::header::typed::Via;
 use sip_types::{CodeKind, Method};
-use std::time::Instant;
-use tokio::time::{timeout, timeout_at};
+use tokio::time::{timeout, timeout_at, Instant};
+use tokio_util::sync::CancellationToken;
 
 /// Client non-INVITE transaction. Used to receive responses to a sent request.
 ///
@@ -47,17 +48,59 @@ impl ClientTsx {
             method
         );
 
-        let mut request = endpoint.create_outgoing(request, target).await?;
-
         let registration = TsxRegistration::create(endpoint, TsxKey::client(&method));
 
-        let via = registration.endpoint.create_via(
-            &request.parts.transport,
-            &registration.tsx_key,
-            target.via_host_port.clone(),
+        let mut request = registration
+            .endpoint
+            .create_outgoing_tsx_request(request, target, &registration.tsx_key)
+            .await?;
+
+        registration
+            .endpoint
+            .send_outgoing_request(&mut request)
+            .await?;
+
+        let timeout = Instant::now() + T1 * 64;
+
+        Ok(Self {
+            registration: Some(registration),
+            request,
+            timeout,
+            state: State::Init,
+        })
+    }
+
+    /// Internal: Used by [Endpoint::send_cancel]
+    pub(crate) async fn send_cancel(
+        endpoint: Endpoint,
+        mut request: Request,
+        invite: &OutgoingRequest,
+    ) -> Result<Self> {
+        assert_eq!(
+            request.line.method,
+            Method::CANCEL,
+            "tried to create cancel transaction from {} request",
+            request.line.method
+        );
+
+        let via: Via = invite.msg.headers.get_named()?;
+        let branch = via.params.get_val("branch").cloned().unwrap_or_default();
+
+        let registration = TsxRegistration::create(
+            endpoint,
+            TsxKey::client_with_branch(&Method::CANCEL, branch),
         );
 
-        request.msg.headers.insert_named_front(&via);
+        request.headers.insert_named_front(&via);
+
+        let mut request = OutgoingRequest {
+            msg: request,
+            parts: OutgoingParts {
+                buffer: Default::default(),
+                ..invite.parts.clone()
+            },
+        };
+
         registration
             .endpoint
             .send_outgoing_request(&mut request)
@@ -98,7 +141,7 @@ impl ClientTsx {
                 loop {
                     let receive = timeout(T2, registration.receive_response());
 
-                    match timeout_at(self.timeout.into(), receive).await {
+                    match timeout_at(self.timeout, receive).await {
                         Ok(Ok(msg)) => return self.handle_msg(msg),
                         Ok(Err(_)) => {
                             // retransmit
@@ -112,7 +155,7 @@ impl ClientTsx {
                 }
             }
             State::Init | State::Proceeding => {
-                match timeout_at(self.timeout.into(), registration.receive_response()).await {
+                match timeout_at(self.timeout, registration.receive_response()).await {
                     Ok(msg) => self.handle_msg(msg),
                     Err(_) => Err(Error::RequestTimedOut),
                 }
@@ -138,6 +181,21 @@ impl ClientTsx {
         }
     }
 
+    /// Calls [`ClientTsx::receive_final`] until the `cancellation` token is cancelled, returning
+    /// [`Error::Cancelled`] then.
+    ///
+    /// Non-INVITE requests cannot be cancelled using CANCEL, instead the transaction is abandoned and the
+    /// request is no longer retransmitted.
+    pub async fn receive_final_cancellable(
+        &mut self,
+        cancellation: &CancellationToken,
+    ) -> Result<TsxResponse> {
+        tokio::select! {
+            response = self.receive_final() => response,
+            _ = cancellation.cancelled() => Err(Error::Cancelled),
+        }
+    }
+
     fn handle_msg(&mut self, response: TsxResponse) -> Result<TsxResponse> {
         match response.line.code.kind() {
             CodeKind::Provisional => {
@@ -155,10 +213,7 @@ impl ClientTsx {
                     tokio::spawn(async move {
                         let timeout = Instant::now() + T4;
 
-                        while timeout_at(timeout.into(), registration.receive())
-                            .await
-                            .is_ok()
-                        {
+                        while timeout_at(timeout, registration.receive()).await.is_ok() {
                             // toss incoming messages, just keep registration alive
                         }
                     });
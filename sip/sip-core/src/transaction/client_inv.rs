@@ -10,8 +10,8 @@ use sip_types::header::typed::{CSeq, MaxForwards};
 use sip_types::header::HeaderError;
 use sip_types::msg::RequestLine;
 use sip_types::{CodeKind, Headers, Method, Name};
-use std::time::{Duration, Instant};
-use tokio::time::{timeout, timeout_at};
+use std::time::Duration;
+use tokio::time::{timeout, timeout_at, Instant};
 
 /// Client INVITE transaction. Used to receives responses to a INVITE request.
 ///
@@ -57,17 +57,13 @@ impl ClientInvTsx {
             request.line.method
         );
 
-        let mut request = endpoint.create_outgoing(request, target).await?;
-
         let registration = TsxRegistration::create(endpoint, TsxKey::client(&Method::INVITE));
 
-        let via = registration.endpoint.create_via(
-            &request.parts.transport,
-            &registration.tsx_key,
-            target.via_host_port.clone(),
-        );
+        let mut request = registration
+            .endpoint
+            .create_outgoing_tsx_request(request, target, &registration.tsx_key)
+            .await?;
 
-        request.msg.headers.insert_named_front(&via);
         registration
             .endpoint
             .send_outgoing_request(&mut request)
@@ -110,7 +106,7 @@ impl ClientInvTsx {
                 loop {
                     let receive = timeout(n, registration.receive_response());
 
-                    match timeout_at(self.timeout.into(), receive).await {
+                    match timeout_at(self.timeout, receive).await {
                         Ok(Ok(msg)) => return self.handle_msg(msg).await,
                         Ok(Err(_)) => {
                             // retransmit
@@ -126,13 +122,13 @@ impl ClientInvTsx {
                 }
             }
             State::Init | State::Proceeding => {
-                match timeout_at(self.timeout.into(), registration.receive_response()).await {
+                match timeout_at(self.timeout, registration.receive_response()).await {
                     Ok(msg) => self.handle_msg(msg).await,
                     Err(_) => Err(Error::RequestTimedOut),
                 }
             }
             State::Accepted => {
-                match timeout_at(self.timeout.into(), registration.receive_response()).await {
+                match timeout_at(self.timeout, registration.receive_response()).await {
                     Ok(msg) => Ok(Some(msg)),
                     Err(_) => {
                         self.state = State::Terminated;
@@ -172,10 +168,7 @@ impl ClientInvTsx {
                     tokio::spawn(async move {
                         let timeout = Instant::now() + Duration::from_secs(32);
 
-                        while timeout_at(timeout.into(), registration.receive())
-                            .await
-                            .is_ok()
-                        {
+                        while timeout_at(timeout, registration.receive()).await.is_ok() {
                             registration
                                 .endpoint
                                 .send_outgoing_request(&mut ack)
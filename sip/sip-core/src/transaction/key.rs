@@ -97,6 +97,15 @@ impl TsxKey {
         }))
     }
 
+    /// Create a client key using an existing branch, e.g. of the INVITE a CANCEL request belongs to
+    pub(crate) fn client_with_branch(method: &Method, branch: BytesStr) -> Self {
+        TsxKey(Repr::RFC3261(Rfc3261 {
+            role: Role::Client,
+            branch,
+            method: filter_method(method),
+        }))
+    }
+
     #[inline]
     pub fn branch(&self) -> &BytesStr {
         match &self.0 {
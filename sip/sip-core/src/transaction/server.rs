@@ -3,8 +3,7 @@ use super::TsxRegistration;
 use crate::transport::OutgoingResponse;
 use crate::{IncomingRequest, Result};
 use sip_types::{CodeKind, Method};
-use std::time::Instant;
-use tokio::time::timeout_at;
+use tokio::time::{timeout_at, Instant};
 
 /// Server transaction. Used to respond to the incoming request.
 ///
@@ -70,7 +69,7 @@ impl ServerTsx {
         let abandon = Instant::now() + T1 * 64;
 
         tokio::spawn(async move {
-            while let Ok(msg) = timeout_at(abandon.into(), self.registration.receive()).await {
+            while let Ok(msg) = timeout_at(abandon, self.registration.receive()).await {
                 if msg.line.is_request() {
                     if let Err(e) = self
                         .registration
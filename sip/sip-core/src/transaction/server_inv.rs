@@ -6,8 +6,7 @@ use crate::{IncomingRequest, Result};
 use sip_types::msg::MessageLine;
 use sip_types::{CodeKind, Method};
 use std::io;
-use std::time::Instant;
-use tokio::time::timeout_at;
+use tokio::time::{timeout_at, Instant};
 
 /// Server INVITE transaction. Used to respond to the incoming request.
 ///
@@ -105,7 +104,7 @@ impl ServerInvTsx {
 
         // wait for ack and retransmit if necessary
         loop {
-            match timeout_at(retransmit.into(), self.registration.receive()).await {
+            match timeout_at(retransmit, self.registration.receive()).await {
                 Ok(inc_msg) => {
                     // two things are allowed to happen here
                     // 1 - the transaction receives a retransmission of the initial invite
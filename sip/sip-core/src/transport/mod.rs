@@ -12,7 +12,7 @@ use sip_types::Headers;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
 use std::mem::take;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::Deref;
 use std::sync::Arc;
 use std::time::SystemTime;
@@ -186,6 +186,16 @@ pub struct TargetTransportInfo {
     /// requests to. If not set the request-uri
     /// will be used to populate there accordingly.
     pub transport: Option<(TpHandle, SocketAddr)>,
+
+    /// Servers which remain to fail over to, see [`Endpoint::failover`]
+    pub(crate) failover: Option<Failover>,
+}
+
+/// The URI a [`TargetTransportInfo`] was resolved from and its servers which haven't been tried yet
+#[derive(Debug, Clone)]
+pub(crate) struct Failover {
+    uri: SipUri,
+    servers: Vec<ServerEntry>,
 }
 
 /// Transport related info for a message
@@ -273,6 +283,20 @@ pub struct OutgoingParts {
     pub buffer: Bytes,
 }
 
+/// A request was sent using a reliable transport instead of the one selected for it, because it exceeded the size
+/// limit of unreliable transports (RFC 3261 Section 18.1.1)
+#[derive(Debug, Clone)]
+pub struct TransportSwitch {
+    /// Size of the printed request in bytes
+    pub request_size: usize,
+    /// Unreliable transport originally selected for the request
+    pub from: TpHandle,
+    /// Reliable transport the request is sent with instead
+    pub to: TpHandle,
+    /// Address the request is sent to
+    pub destination: SocketAddr,
+}
+
 /// Key used to identify and store transports
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct TpKey {
@@ -306,24 +330,20 @@ pub(crate) struct Transports {
 }
 
 impl Transports {
-    async fn resolve_host_port(&self, host: &Host, port: u16) -> io::Result<Vec<ServerEntry>> {
-        match host {
+    async fn resolve_uri(&self, uri: &SipUri) -> io::Result<Vec<ServerEntry>> {
+        let default_port = if uri.sips { 5061 } else { 5060 };
+        let port = uri.host_port.port.unwrap_or(default_port);
+
+        match &uri.host_port.host {
             Host::IP6(ip) => Ok(vec![ServerEntry::from((*ip, port))]),
             Host::IP4(ip) => Ok(vec![ServerEntry::from((*ip, port))]),
-            Host::Name(name) => resolver::resolve_host(&self.dns_resolver, name, port).await,
+            Host::Name(name) => {
+                resolver::resolve_host(&self.dns_resolver, name, uri.host_port.port, default_port)
+                    .await
+            }
         }
     }
 
-    async fn resolve_uri(&self, uri: &SipUri) -> io::Result<Vec<ServerEntry>> {
-        let port = match uri.host_port.port {
-            Some(port) => port,
-            None if uri.sips => 5061,
-            None => 5060,
-        };
-
-        self.resolve_host_port(&uri.host_port.host, port).await
-    }
-
     /// Will try to find or create a suitable transport the given Uri
     #[tracing::instrument(name = "select_transport", level = "trace", skip(self, endpoint))]
     pub(crate) async fn select(
@@ -331,36 +351,139 @@ impl Transports {
         endpoint: &Endpoint,
         uri: &SipUri,
     ) -> Result<(TpHandle, SocketAddr)> {
+        self.select_with(endpoint, uri, false, None)
+            .await
+            .map(|(transport, address, _)| (transport, address))
+    }
+
+    /// Like [`Transports::select`] but also returns the remaining servers of the URI to fail over to
+    pub(crate) async fn select_failover(
+        &self,
+        endpoint: &Endpoint,
+        uri: &SipUri,
+    ) -> Result<(TpHandle, SocketAddr, Failover)> {
+        self.select_with(endpoint, uri, false, None).await
+    }
+
+    /// Select a transport for the next server of a failover, returns `None` once no server is left
+    pub(crate) async fn select_next(
+        &self,
+        endpoint: &Endpoint,
+        failover: &mut Failover,
+    ) -> Option<(TpHandle, SocketAddr)> {
+        self.select_server(endpoint, &failover.uri, &mut failover.servers, false, None)
+            .await
+    }
+
+    /// Like [`Transports::select`] but only considers transports bound to the given local IP address
+    #[tracing::instrument(name = "select_transport_from", level = "trace", skip(self, endpoint))]
+    pub(crate) async fn select_from(
+        &self,
+        endpoint: &Endpoint,
+        uri: &SipUri,
+        local_ip: IpAddr,
+    ) -> Result<(TpHandle, SocketAddr)> {
+        self.select_with(endpoint, uri, false, Some(local_ip))
+            .await
+            .map(|(transport, address, _)| (transport, address))
+    }
+
+    /// Like [`Transports::select`] but only considers reliable transports.
+    ///
+    /// The URI's transport parameter and UDP only server entries are ignored, as this is used to
+    /// switch to a congestion controlled transport when a request is too large to be sent via UDP.
+    #[tracing::instrument(
+        name = "select_reliable_transport",
+        level = "trace",
+        skip(self, endpoint)
+    )]
+    pub(crate) async fn select_reliable(
+        &self,
+        endpoint: &Endpoint,
+        uri: &SipUri,
+    ) -> Result<(TpHandle, SocketAddr)> {
+        self.select_with(endpoint, uri, true, None)
+            .await
+            .map(|(transport, address, _)| (transport, address))
+    }
+
+    async fn select_with(
+        &self,
+        endpoint: &Endpoint,
+        uri: &SipUri,
+        reliable_only: bool,
+        local_ip: Option<IpAddr>,
+    ) -> Result<(TpHandle, SocketAddr, Failover)> {
         log::trace!("select transport for {:?}", uri);
 
         // Resolve host_port to possible remote addresses
-        let servers = self.resolve_uri(uri).await?;
+        let mut servers = self.resolve_uri(uri).await?;
+
+        let Some((transport, address)) = self
+            .select_server(endpoint, uri, &mut servers, reliable_only, local_ip)
+            .await
+        else {
+            return Err(io::Error::other(format!("Failed to select transport for {uri:?}")).into());
+        };
+
+        let failover = Failover {
+            uri: uri.clone(),
+            servers,
+        };
+
+        Ok((transport, address, failover))
+    }
+
+    /// Select a transport for the first usable server, removes it and all servers before it from `servers`
+    async fn select_server(
+        &self,
+        endpoint: &Endpoint,
+        uri: &SipUri,
+        servers: &mut Vec<ServerEntry>,
+        reliable_only: bool,
+        local_ip: Option<IpAddr>,
+    ) -> Option<(TpHandle, SocketAddr)> {
+        let local_ip_matches = |tp: &TpHandle| local_ip.is_none_or(|ip| tp.bound().ip() == ip);
+
+        while !servers.is_empty() {
+            let server = servers.remove(0);
+
+            if reliable_only && matches!(server.transport, Some(resolver::Transport::Udp)) {
+                continue;
+            }
 
-        for server in servers {
             // Search unmanaged ones (connectionless, e.g. udp)
-            if let Some(transport) = self.find_matching_unmanaged_transport(uri, &server) {
+            if let Some(transport) = self
+                .find_matching_unmanaged_transport(uri, &server)
+                .filter(|tp| !reliable_only || tp.reliable())
+                .filter(|tp| local_ip_matches(tp))
+            {
                 log::trace!("selected connectionless: {}", transport);
 
-                return Ok((transport.clone(), server.address));
+                return Some((transport.clone(), server.address));
             }
 
             // Search managed idling transports (connections, e.g. tcp / tls)
-            if let Some(found) = self.find_matching_idling_transport(uri, &server) {
-                return Ok((found, server.address));
+            if let Some(found) =
+                self.find_matching_idling_transport(uri, &server, reliable_only, local_ip)
+            {
+                return Some((found, server.address));
             }
 
             // No existing transport found, try and connect a new one
 
-            if let Some(found) = self.connect(endpoint, uri, &server).await {
-                return Ok((found, server.address));
+            if let Some(found) = self.connect(endpoint, uri, &server, reliable_only).await {
+                if local_ip_matches(&found) {
+                    return Some((found, server.address));
+                }
+
+                log::debug!(
+                    "connected transport {found} is not bound to {local_ip:?}, ignoring it"
+                );
             }
         }
 
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to select transport for {uri:?}"),
-        )
-        .into())
+        None
     }
 
     fn find_matching_unmanaged_transport(
@@ -394,6 +517,8 @@ impl Transports {
         &self,
         uri: &SipUri,
         server: &ServerEntry,
+        ignore_transport_param: bool,
+        local_ip: Option<IpAddr>,
     ) -> Option<TpHandle> {
         // TODO: do something about this lock
         let mut transports = self.transports.lock();
@@ -416,14 +541,20 @@ impl Transports {
                 continue;
             }
 
+            if local_ip.is_some_and(|ip| managed.transport.bound().ip() != ip) {
+                continue;
+            }
+
             // Check if the transport security is sufficient
-            if !uri.sips || managed.transport.secure() {
+            if uri.sips && !managed.transport.secure() {
                 continue;
             }
 
             // Check if the transport's name matches the transport parameter
             if let Some(transport_param) = uri.uri_params.get_val("transport") {
-                if !managed.transport.matches_transport_param(transport_param) {
+                if !ignore_transport_param
+                    && !managed.transport.matches_transport_param(transport_param)
+                {
                     continue;
                 }
             }
@@ -445,6 +576,7 @@ impl Transports {
         endpoint: &Endpoint,
         uri: &SipUri,
         server: &ServerEntry,
+        ignore_transport_param: bool,
     ) -> Option<TpHandle> {
         // Try to build new transport with a factory
         for factory in self.factories.iter() {
@@ -454,13 +586,13 @@ impl Transports {
                 }
             }
 
-            if !uri.sips || factory.secure() {
+            if uri.sips && !factory.secure() {
                 continue;
             }
 
             // Check if the transport's name matches the transport parameter
             if let Some(transport_param) = uri.uri_params.get_val("transport") {
-                if !factory.matches_transport_param(transport_param) {
+                if !ignore_transport_param && !factory.matches_transport_param(transport_param) {
                     continue;
                 }
             }
@@ -615,3 +747,62 @@ impl TransportsBuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::udp::Udp;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    async fn endpoint() -> Endpoint {
+        let mut builder = Endpoint::builder();
+        Udp::spawn(&mut builder, (Ipv4Addr::LOCALHOST, 0))
+            .await
+            .unwrap();
+        builder.build()
+    }
+
+    fn destination(target: &TargetTransportInfo) -> Option<SocketAddr> {
+        target.transport.as_ref().map(|(_, address)| *address)
+    }
+
+    #[tokio::test]
+    async fn failover_without_servers() {
+        let endpoint = endpoint().await;
+
+        let mut target = TargetTransportInfo::default();
+        assert!(!endpoint.failover(&mut target).await);
+        assert!(target.transport.is_none());
+    }
+
+    #[tokio::test]
+    async fn failover_tries_remaining_servers_in_order() {
+        let endpoint = endpoint().await;
+
+        let first = SocketAddr::from((Ipv4Addr::LOCALHOST, 5070));
+        let second = SocketAddr::from((Ipv4Addr::LOCALHOST, 5080));
+
+        let mut target = TargetTransportInfo {
+            failover: Some(Failover {
+                uri: "sip:example.org".parse().unwrap(),
+                servers: vec![
+                    // No IPv6 transport exists, must be skipped
+                    ServerEntry::from((Ipv6Addr::LOCALHOST, 5060)),
+                    ServerEntry::from(first),
+                    ServerEntry::from(second),
+                ],
+            }),
+            ..Default::default()
+        };
+
+        assert!(endpoint.failover(&mut target).await);
+        assert_eq!(destination(&target), Some(first));
+
+        assert!(endpoint.failover(&mut target).await);
+        assert_eq!(destination(&target), Some(second));
+
+        assert!(!endpoint.failover(&mut target).await);
+        assert!(target.failover.is_none());
+        assert_eq!(destination(&target), Some(second));
+    }
+}
@@ -1,7 +1,9 @@
 use super::streaming::{
     StreamingFactory, StreamingListener, StreamingListenerBuilder, StreamingTransport,
 };
+use sha2::{Digest, Sha256};
 use sip_types::uri::SipUri;
+use std::collections::HashMap;
 use std::io;
 use std::net::SocketAddr;
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
@@ -33,6 +35,150 @@ impl StreamingFactory for TlsConnector {
     }
 }
 
+/// How the certificate of a TLS server is verified
+#[derive(Clone)]
+pub enum ServerVerification {
+    /// Verify the certificate chain using the system's root certificates
+    SystemRoots,
+    /// Verify the certificate chain using only the given CA certificates
+    CustomCa(Vec<native_tls::Certificate>),
+    /// Only accept server certificates whose SHA-256 fingerprint of the DER encoding is in the list.
+    /// The certificate chain and hostname are not verified.
+    ///
+    /// native-tls can only check the fingerprint after the handshake, so no client identity is presented to
+    /// pinned servers.
+    PinnedFingerprints(Vec<[u8; 32]>),
+}
+
+/// TLS settings used when connecting to a SIP server
+#[derive(Clone)]
+pub struct TlsClientPolicy {
+    pub verification: ServerVerification,
+    /// Client certificate presented to the server for mutual authentication, unless the server is verified using
+    /// [`ServerVerification::PinnedFingerprints`]
+    pub identity: Option<native_tls::Identity>,
+}
+
+impl TlsClientPolicy {
+    pub fn new(verification: ServerVerification) -> Self {
+        Self {
+            verification,
+            identity: None,
+        }
+    }
+
+    pub fn with_identity(mut self, identity: native_tls::Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    fn build(&self) -> Result<PolicyConnector, native_tls::Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+
+        let pinned = match &self.verification {
+            ServerVerification::SystemRoots => None,
+            ServerVerification::CustomCa(certificates) => {
+                builder.disable_built_in_roots(true);
+
+                for certificate in certificates {
+                    builder.add_root_certificate(certificate.clone());
+                }
+
+                None
+            }
+            ServerVerification::PinnedFingerprints(fingerprints) => {
+                // The fingerprint is checked after the handshake instead
+                builder.danger_accept_invalid_certs(true);
+
+                Some(fingerprints.clone())
+            }
+        };
+
+        if let Some(identity) = &self.identity {
+            if pinned.is_some() {
+                // The identity would be sent to the server before its certificate was checked
+                log::warn!("Not presenting the client identity to a server verified by pinned fingerprints");
+            } else {
+                builder.identity(identity.clone());
+            }
+        }
+
+        Ok(PolicyConnector {
+            connector: TlsConnector::from(builder.build()?),
+            pinned,
+        })
+    }
+}
+
+struct PolicyConnector {
+    connector: TlsConnector,
+    pinned: Option<Vec<[u8; 32]>>,
+}
+
+/// TLS connector applying a [`TlsClientPolicy`] depending on the server's host, e.g. to present a different
+/// client certificate to each registrar or proxy
+pub struct TlsPolicyConnector {
+    default: PolicyConnector,
+    hosts: HashMap<String, PolicyConnector>,
+}
+
+impl TlsPolicyConnector {
+    /// Create a connector using the `default` policy for all hosts without their own policy
+    pub fn new(default: TlsClientPolicy) -> Result<Self, native_tls::Error> {
+        Ok(Self {
+            default: default.build()?,
+            hosts: HashMap::new(),
+        })
+    }
+
+    /// Use the `policy` when connecting to the given host, as it appears in the SIP URI
+    pub fn with_host_policy(
+        mut self,
+        host: impl Into<String>,
+        policy: TlsClientPolicy,
+    ) -> Result<Self, native_tls::Error> {
+        self.hosts.insert(host.into(), policy.build()?);
+        Ok(self)
+    }
+}
+
+#[async_trait::async_trait]
+impl StreamingFactory for TlsPolicyConnector {
+    type Transport = TlsStream<TcpStream>;
+
+    async fn connect<A: ToSocketAddrs + Send>(
+        &self,
+        uri: &SipUri,
+        addr: SocketAddr,
+    ) -> io::Result<Self::Transport> {
+        let domain = uri.host_port.host.to_string();
+
+        let policy = self.hosts.get(&domain).unwrap_or(&self.default);
+
+        let stream = StreamingFactory::connect::<A>(&policy.connector, uri, addr).await?;
+
+        if let Some(pinned) = &policy.pinned {
+            let certificate = stream
+                .get_ref()
+                .peer_certificate()
+                .map_err(native_tls_err_to_io_err)?
+                .ok_or_else(|| io::Error::other("server did not present a certificate"))?;
+
+            let fingerprint: [u8; 32] =
+                Sha256::digest(certificate.to_der().map_err(native_tls_err_to_io_err)?).into();
+
+            if !pinned.contains(&fingerprint) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "server certificate does not match any pinned fingerprint",
+                ));
+            }
+        }
+
+        Ok(stream)
+    }
+}
+
 // ==== Listener
 
 #[async_trait::async_trait]
@@ -97,5 +243,5 @@ impl StreamingTransport for TlsStream<TcpStream> {
 }
 
 fn native_tls_err_to_io_err(e: native_tls::Error) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, e)
+    io::Error::other(e)
 }
@@ -3,11 +3,12 @@ use hickory_resolver::proto::rr::{RData, RecordType};
 use hickory_resolver::ResolveError;
 use hickory_resolver::{Name, TokioResolver};
 use multimap::MultiMap;
+use rand::Rng;
 use std::io;
 use std::net::{IpAddr, SocketAddr};
 
 #[derive(Debug, Clone, Copy)]
-pub(super) struct ServerEntry {
+pub(crate) struct ServerEntry {
     pub(super) address: SocketAddr,
     pub(super) transport: Option<Transport>,
 }
@@ -66,11 +67,16 @@ impl Transport {
     }
 }
 
-#[tracing::instrument(err, skip(dns_resolver, uri_port))]
+/// Resolve a hostname to the servers to try in order (RFC 3263 Section 4)
+///
+/// NAPTR and SRV records are only used if the URI has no explicit port, otherwise only A/AAAA records are resolved
+/// and used with that port.
+#[tracing::instrument(err, skip(dns_resolver, uri_port, default_port))]
 pub(super) async fn resolve_host(
     dns_resolver: &TokioResolver,
     name: &str,
-    uri_port: u16,
+    uri_port: Option<u16>,
+    default_port: u16,
 ) -> io::Result<Vec<ServerEntry>> {
     log::debug!("Resolving hostname {:?}", name);
 
@@ -78,6 +84,18 @@ pub(super) async fn resolve_host(
 
     let mut entries: Vec<ServerEntry> = vec![];
 
+    if let Some(port) = uri_port {
+        resolve_a_records(dns_resolver, name.clone(), None, port, &mut entries).await?;
+
+        if entries.is_empty() {
+            return Err(io::Error::other(format!(
+                "No DNS records for host '{name}' found"
+            )));
+        }
+
+        return Ok(entries);
+    }
+
     // First find NAPTR DNS records
     resolve_naptr_records(dns_resolver, name.clone(), &mut entries).await?;
 
@@ -99,14 +117,13 @@ pub(super) async fn resolve_host(
 
     // Neither NAPTR nor SRV entries exist - just resolve A/AAAA records
     if entries.is_empty() {
-        resolve_a_records(dns_resolver, name.clone(), None, uri_port, &mut entries).await?;
+        resolve_a_records(dns_resolver, name.clone(), None, default_port, &mut entries).await?;
     }
 
     if entries.is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("No DNS records for host '{name}' found"),
-        ));
+        return Err(io::Error::other(format!(
+            "No DNS records for host '{name}' found"
+        )));
     }
 
     Ok(entries)
@@ -205,15 +222,14 @@ async fn resolve_srv_records(
         return Ok(());
     };
 
-    // Order SRV records by priority
-    let mut srv_records: Vec<&SRV> = lookup
+    let srv_records: Vec<&SRV> = lookup
         .record_iter()
         .filter_map(|record| match record.data() {
             RData::SRV(srv) => Some(srv),
             _ => None,
         })
         .collect();
-    srv_records.sort_unstable_by_key(|srv| srv.priority());
+    let srv_records = order_srv_records(srv_records);
 
     log::debug!("Got {} SRV records for \"{name}\"", srv_records.len());
 
@@ -244,6 +260,45 @@ async fn resolve_srv_records(
     Ok(())
 }
 
+/// Order SRV records by priority, records of the same priority are ordered randomly using their weight
+/// (RFC 2782)
+fn order_srv_records(mut records: Vec<&SRV>) -> Vec<&SRV> {
+    records.sort_by_key(|srv| srv.priority());
+
+    let mut ordered = Vec::with_capacity(records.len());
+    let mut rng = rand::rng();
+
+    for group in records.chunk_by(|a, b| a.priority() == b.priority()) {
+        let mut group = group.to_vec();
+
+        // Records with weight 0 come first, which gives them a very small chance of being selected before others
+        group.sort_by_key(|srv| srv.weight() != 0);
+
+        while !group.is_empty() {
+            let total: u32 = group.iter().map(|srv| u32::from(srv.weight())).sum();
+            let mut pick = rng.random_range(0..=total);
+
+            let index = group
+                .iter()
+                .position(|srv| {
+                    let weight = u32::from(srv.weight());
+
+                    if pick <= weight {
+                        true
+                    } else {
+                        pick -= weight;
+                        false
+                    }
+                })
+                .unwrap_or(group.len() - 1);
+
+            ordered.push(group.remove(index));
+        }
+    }
+
+    ordered
+}
+
 async fn resolve_a_records(
     dns_resolver: &TokioResolver,
     name: Name,
@@ -279,3 +334,110 @@ fn filter_no_records<T>(e: Result<T, ResolveError>) -> Result<Option<T>, Resolve
         Err(e) => Err(e),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+    use hickory_resolver::name_server::TokioConnectionProvider;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    fn srv(priority: u16, weight: u16, port: u16) -> SRV {
+        SRV::new(
+            priority,
+            weight,
+            port,
+            Name::from_ascii("sip.example.org.").unwrap(),
+        )
+    }
+
+    fn ports(records: &[SRV]) -> Vec<u16> {
+        order_srv_records(records.iter().collect())
+            .iter()
+            .map(|srv| srv.port())
+            .collect()
+    }
+
+    #[test]
+    fn srv_ordered_by_priority() {
+        let records = [srv(20, 0, 2), srv(30, 10, 3), srv(10, 50, 1)];
+
+        for _ in 0..100 {
+            assert_eq!(ports(&records), [1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn srv_same_priority_contains_all() {
+        let records = [srv(10, 0, 1), srv(10, 5, 2), srv(10, 0, 3), srv(20, 0, 4)];
+
+        for _ in 0..100 {
+            let mut ordered = ports(&records);
+
+            assert_eq!(ordered.pop(), Some(4));
+
+            ordered.sort_unstable();
+            assert_eq!(ordered, [1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn srv_same_priority_ordered_by_weight() {
+        let records = [srv(10, 10, 1), srv(10, 90, 2)];
+
+        let heavy_first = (0..10_000).filter(|_| ports(&records)[0] == 2).count();
+
+        assert!((8_500..9_500).contains(&heavy_first), "{heavy_first}");
+    }
+
+    #[test]
+    fn srv_zero_weight_rarely_first() {
+        let records = [srv(10, 0, 1), srv(10, 100, 2)];
+
+        let zero_first = (0..10_000).filter(|_| ports(&records)[0] == 1).count();
+
+        assert!(zero_first < 500, "{zero_first}");
+    }
+
+    /// Resolver whose name server doesn't exist, so only locally known names can be resolved
+    fn offline_resolver() -> TokioResolver {
+        let config = ResolverConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], 1, true),
+        );
+
+        let mut opts = ResolverOpts::default();
+        opts.timeout = Duration::from_millis(100);
+        opts.attempts = 0;
+
+        TokioResolver::builder_with_config(config, TokioConnectionProvider::default())
+            .with_options(opts)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn explicit_port_only_resolves_a_records() {
+        let entries = resolve_host(&offline_resolver(), "localhost", Some(5080), 5060)
+            .await
+            .unwrap();
+
+        assert!(!entries.is_empty());
+
+        for entry in entries {
+            assert!(entry.address.ip().is_loopback());
+            assert_eq!(entry.address.port(), 5080);
+            assert!(entry.transport.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn explicit_port_without_a_records() {
+        assert!(
+            resolve_host(&offline_resolver(), "sip.invalid", Some(5080), 5060)
+                .await
+                .is_err()
+        );
+    }
+}
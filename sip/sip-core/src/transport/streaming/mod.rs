@@ -28,11 +28,17 @@ pub trait StreamingListenerBuilder: Sized + Send + Sync + 'static {
         addr: A,
     ) -> io::Result<(Self::StreamingListener, SocketAddr)>;
 
+    /// Address advertised as sent-by address of accepted connections, instead of the local address
+    fn advertised(&self) -> Option<SocketAddr> {
+        None
+    }
+
     async fn spawn<A: ToSocketAddrs + Send>(
         self,
         endpoint: &mut EndpointBuilder,
         addr: A,
     ) -> io::Result<()> {
+        let advertised = self.advertised();
         let (listener, bound) = self.bind(addr).await?;
 
         log::info!(
@@ -41,7 +47,7 @@ pub trait StreamingListenerBuilder: Sized + Send + Sync + 'static {
             bound
         );
 
-        tokio::spawn(task_accept(endpoint.subscribe(), listener));
+        tokio::spawn(task_accept(endpoint.subscribe(), listener, advertised));
 
         Ok(())
     }
@@ -79,6 +85,7 @@ pub trait StreamingListener: Send + Sync {
 
 pub struct StreamingWrite<T> {
     bound: SocketAddr,
+    sent_by: SocketAddr,
     remote: SocketAddr,
     incoming: bool,
 
@@ -89,6 +96,7 @@ impl<T: StreamingTransport> fmt::Debug for StreamingWrite<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("StreamingWrite")
             .field("bound", &self.bound)
+            .field("sent_by", &self.sent_by)
             .field("remote", &self.remote)
             .field("incoming", &self.incoming)
             .finish()
@@ -127,7 +135,7 @@ where
     }
 
     fn sent_by(&self) -> SocketAddr {
-        self.bound
+        self.sent_by
     }
 
     fn direction(&self) -> Direction {
@@ -181,6 +189,7 @@ where
 
         let transport = StreamingWrite {
             bound: local,
+            sent_by: local,
             remote,
             write_half: write_half.clone(),
             incoming: false,
@@ -204,8 +213,11 @@ where
     }
 }
 
-async fn task_accept<I>(mut endpoint: broadcast::Receiver<Endpoint>, mut incoming: I)
-where
+async fn task_accept<I>(
+    mut endpoint: broadcast::Receiver<Endpoint>,
+    mut incoming: I,
+    advertised: Option<SocketAddr>,
+) where
     I: StreamingListener,
 {
     let endpoint = match endpoint.recv().await.ok() {
@@ -232,6 +244,7 @@ where
 
                 let transport = StreamingWrite {
                     bound: local,
+                    sent_by: advertised.unwrap_or(local),
                     remote,
                     write_half: write_half.clone(),
                     incoming: true,
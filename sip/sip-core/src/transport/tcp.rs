@@ -55,12 +55,22 @@ impl StreamingFactory for TcpConnector {
 #[derive(Default)]
 pub struct TcpListener {
     _priv: (),
+    advertised: Option<SocketAddr>,
 }
 
 impl TcpListener {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Advertise the given address as sent-by address of accepted connections, e.g. the public address of a
+    /// static (1:1) NAT
+    pub fn new_with_advertised(advertised: SocketAddr) -> Self {
+        Self {
+            advertised: Some(advertised),
+            ..Self::default()
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -77,6 +87,10 @@ impl StreamingListenerBuilder for TcpListener {
 
         Ok((listener, bound))
     }
+
+    fn advertised(&self) -> Option<SocketAddr> {
+        self.advertised
+    }
 }
 
 #[async_trait::async_trait]
@@ -9,10 +9,13 @@ use tokio::sync::broadcast;
 
 const UDP: &str = "UDP";
 const MAX_MSG_SIZE: usize = u16::MAX as usize;
+/// Maximum number of datagrams handled per wakeup of the receive task, before yielding to other tasks
+const RECV_BATCH: usize = 64;
 
 #[derive(Debug)]
 struct Inner {
     bound: SocketAddr,
+    sent_by: SocketAddr,
     socket: UdpSocket,
 }
 
@@ -28,16 +31,52 @@ impl fmt::Display for Udp {
 }
 
 impl Udp {
+    /// Bind a UDP socket to the given address and add it to the endpoint
+    ///
+    /// Can be called multiple times to listen on several sockets. Outgoing requests use the first transport
+    /// matching the destination's address family, unless a transport is chosen explicitly.
     pub async fn spawn<A>(builder: &mut EndpointBuilder, addr: A) -> io::Result<TpHandle>
+    where
+        A: ToSocketAddrs,
+    {
+        Self::spawn_inner(builder, addr, None).await
+    }
+
+    /// Like [`Udp::spawn`] but advertises the given address in the Via header instead of the bound address
+    ///
+    /// Used when the endpoint is behind a static (1:1) NAT, where the local address differs from the address peers
+    /// can reach it at. The advertised address is available as the transport's `sent_by` address to build the
+    /// Contact header.
+    pub async fn spawn_with_advertised<A>(
+        builder: &mut EndpointBuilder,
+        addr: A,
+        advertised: SocketAddr,
+    ) -> io::Result<TpHandle>
+    where
+        A: ToSocketAddrs,
+    {
+        Self::spawn_inner(builder, addr, Some(advertised)).await
+    }
+
+    async fn spawn_inner<A>(
+        builder: &mut EndpointBuilder,
+        addr: A,
+        advertised: Option<SocketAddr>,
+    ) -> io::Result<TpHandle>
     where
         A: ToSocketAddrs,
     {
         let socket = UdpSocket::bind(addr).await?;
         let bound = socket.local_addr()?;
+        let sent_by = advertised.unwrap_or(bound);
 
-        log::info!("Bound UDP to {}", bound);
+        log::info!("Bound UDP to {}, advertised as {}", bound, sent_by);
 
-        let inner = Arc::new(Inner { bound, socket });
+        let inner = Arc::new(Inner {
+            bound,
+            sent_by,
+            socket,
+        });
 
         let handle = TpHandle::new(Udp {
             inner: inner.clone(),
@@ -70,7 +109,7 @@ impl Transport for Udp {
     }
 
     fn sent_by(&self) -> SocketAddr {
-        self.inner.bound
+        self.inner.sent_by
     }
 
     fn direction(&self) -> Direction {
@@ -95,11 +134,29 @@ async fn receive_task(
     let mut buffer = vec![0u8; MAX_MSG_SIZE];
 
     loop {
-        let result = inner.socket.recv_from(&mut buffer).await;
-
-        if let Err(e) = handle_msg(&endpoint, &inner, &handle, result, &buffer).await {
+        if let Err(e) = inner.socket.readable().await {
             log::error!("UDP recv error {:?}", e);
+            continue;
         }
+
+        // Drain all datagrams queued on the socket, instead of waiting for readiness once per datagram. Under load
+        // (e.g. thousands of registrations refreshed at once) this handles a burst of messages with a single wakeup.
+        for _ in 0..RECV_BATCH {
+            let (len, remote) = match inner.socket.try_recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::error!("UDP recv error {:?}", e);
+                    continue;
+                }
+            };
+
+            if let Err(e) = handle_msg(&endpoint, &inner, &handle, remote, &buffer[..len]).await {
+                log::error!("UDP recv error {:?}", e);
+            }
+        }
+
+        tokio::task::yield_now().await;
     }
 }
 
@@ -107,13 +164,9 @@ async fn handle_msg(
     endpoint: &Endpoint,
     inner: &Inner,
     handle: &TpHandle,
-    result: io::Result<(usize, SocketAddr)>,
+    remote: SocketAddr,
     bytes: &[u8],
 ) -> Result<()> {
-    let (len, remote) = result?;
-
-    let bytes = &bytes[..len];
-
     match parse_complete(bytes) {
         Ok(CompleteItem::KeepAliveRequest) => {
             inner.socket.send_to(b"\r\n", remote).await?;
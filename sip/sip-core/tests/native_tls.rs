@@ -0,0 +1,163 @@
+#![cfg(all(
+    feature = "tls-native-tls",
+    not(any(target_os = "windows", target_vendor = "apple"))
+))]
+
+use ezk_sip_core::transport::native_tls::{
+    ServerVerification, TlsClientPolicy, TlsPolicyConnector,
+};
+use ezk_sip_core::transport::streaming::StreamingFactory;
+use openssl::asn1::Asn1Time;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode};
+use openssl::x509::{X509Name, X509};
+use sip_types::uri::SipUri;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::mpsc;
+use std::thread;
+use tokio_native_tls::native_tls::{Certificate, Identity};
+
+/// Self-signed certificate and its private key
+fn self_signed(name: &str) -> (X509, PKey<Private>) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut subject = X509Name::builder().unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let subject = subject.build();
+
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_issuer_name(&subject).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+    (builder.build(), key)
+}
+
+fn identity(name: &str) -> Identity {
+    let (certificate, key) = self_signed(name);
+
+    Identity::from_pkcs8(
+        &certificate.to_pem().unwrap(),
+        &key.private_key_to_pem_pkcs8().unwrap(),
+    )
+    .unwrap()
+}
+
+fn fingerprint(certificate: &X509) -> [u8; 32] {
+    certificate
+        .digest(MessageDigest::sha256())
+        .unwrap()
+        .as_ref()
+        .try_into()
+        .unwrap()
+}
+
+/// Accept a single TLS connection, reports if the client presented a certificate
+fn server(certificate: &X509, key: &PKey<Private>) -> (SocketAddr, mpsc::Receiver<bool>) {
+    let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+    acceptor.set_certificate(certificate).unwrap();
+    acceptor.set_private_key(key).unwrap();
+    // Request a client certificate, but accept any or none
+    acceptor.set_verify_callback(SslVerifyMode::PEER, |_, _| true);
+    let acceptor = acceptor.build();
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+
+        if let Ok(stream) = acceptor.accept(stream) {
+            let _ = tx.send(stream.ssl().peer_certificate().is_some());
+        }
+    });
+
+    (address, rx)
+}
+
+async fn connect(policy: TlsClientPolicy, address: SocketAddr) -> std::io::Result<()> {
+    let connector = TlsPolicyConnector::new(policy).unwrap();
+    let uri: SipUri = "sips:sip.example.org".parse().unwrap();
+
+    StreamingFactory::connect::<SocketAddr>(&connector, &uri, address)
+        .await
+        .map(drop)
+}
+
+#[tokio::test]
+async fn pinned_fingerprint() {
+    let (certificate, key) = self_signed("sip.example.org");
+    let (address, presented) = server(&certificate, &key);
+
+    let policy = TlsClientPolicy::new(ServerVerification::PinnedFingerprints(vec![
+        [0; 32],
+        fingerprint(&certificate),
+    ]));
+
+    connect(policy, address).await.unwrap();
+    assert!(!presented.recv().unwrap());
+}
+
+#[tokio::test]
+async fn pinned_fingerprint_mismatch() {
+    let (certificate, key) = self_signed("sip.example.org");
+    let (address, _presented) = server(&certificate, &key);
+
+    let (other, _) = self_signed("sip.example.org");
+    let policy = TlsClientPolicy::new(ServerVerification::PinnedFingerprints(vec![fingerprint(
+        &other,
+    )]));
+
+    assert!(connect(policy, address).await.is_err());
+}
+
+#[tokio::test]
+async fn identity_not_presented_to_pinned_server() {
+    let (certificate, key) = self_signed("sip.example.org");
+    let (address, presented) = server(&certificate, &key);
+
+    let policy = TlsClientPolicy::new(ServerVerification::PinnedFingerprints(vec![fingerprint(
+        &certificate,
+    )]))
+    .with_identity(identity("alice"));
+
+    connect(policy, address).await.unwrap();
+    assert!(!presented.recv().unwrap());
+}
+
+#[tokio::test]
+async fn identity_presented_to_verified_server() {
+    let (certificate, key) = self_signed("sip.example.org");
+    let (address, presented) = server(&certificate, &key);
+
+    let ca = Certificate::from_der(&certificate.to_der().unwrap()).unwrap();
+    let policy = TlsClientPolicy::new(ServerVerification::CustomCa(vec![ca]))
+        .with_identity(identity("alice"));
+
+    connect(policy, address).await.unwrap();
+    assert!(presented.recv().unwrap());
+}
+
+#[tokio::test]
+async fn untrusted_server() {
+    let (certificate, key) = self_signed("sip.example.org");
+    let (address, _presented) = server(&certificate, &key);
+
+    let (other, _) = self_signed("sip.example.org");
+    let ca = Certificate::from_der(&other.to_der().unwrap()).unwrap();
+    let policy = TlsClientPolicy::new(ServerVerification::CustomCa(vec![ca]));
+
+    assert!(connect(policy, address).await.is_err());
+}
@@ -0,0 +1,160 @@
+use bytes::Bytes;
+use ezk_sip_core::transport::streaming::StreamingListenerBuilder;
+use ezk_sip_core::transport::tcp::{TcpConnector, TcpListener};
+use ezk_sip_core::transport::udp::Udp;
+use ezk_sip_core::transport::TargetTransportInfo;
+use ezk_sip_core::{Endpoint, IncomingRequest, Layer, MayTake, Request};
+use sip_types::header::typed::{CSeq, CallID, FromTo};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{Method, Name, StatusCode};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+const LOCAL_IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+/// Name of the transport and body length of a request received by the server
+type Received = (&'static str, usize);
+
+/// Answers every OPTIONS request and reports the transport it was received with
+struct Responder {
+    received: mpsc::UnboundedSender<Received>,
+}
+
+#[async_trait::async_trait]
+impl Layer for Responder {
+    fn name(&self) -> &'static str {
+        "responder"
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::OPTIONS {
+            return;
+        }
+
+        let mut request = request.take();
+        let tsx = endpoint.create_server_tsx(&mut request);
+
+        let _ = self
+            .received
+            .send((request.tp_info.transport.name(), request.body.len()));
+
+        let response = endpoint.create_response(&request, StatusCode::OK, None);
+        tsx.respond(response).await.unwrap();
+    }
+}
+
+/// Spawn an endpoint listening on UDP & TCP on the same port
+async fn server() -> (Endpoint, SocketAddr, mpsc::UnboundedReceiver<Received>) {
+    let (received, rx) = mpsc::unbounded_channel();
+
+    let mut builder = Endpoint::builder();
+    builder.add_layer(Responder { received });
+
+    let udp = Udp::spawn(&mut builder, (LOCAL_IP, 0)).await.unwrap();
+    TcpListener::new()
+        .spawn(&mut builder, udp.bound())
+        .await
+        .unwrap();
+
+    (builder.build(), udp.bound(), rx)
+}
+
+async fn client() -> Endpoint {
+    let mut builder = Endpoint::builder();
+
+    Udp::spawn(&mut builder, (LOCAL_IP, 0)).await.unwrap();
+    builder.add_transport_factory(Arc::new(TcpConnector::new()));
+
+    builder.build()
+}
+
+fn options(server: SocketAddr, body_len: usize) -> Request {
+    let uri: SipUri = format!("sip:{server}").parse().unwrap();
+    let from: SipUri = "sip:alice@example.org".parse().unwrap();
+
+    let mut request = Request::new(Method::OPTIONS, uri.clone());
+    request.headers.insert_type(
+        Name::FROM,
+        &FromTo::new(NameAddr::uri(from), Some("from-tag".into())),
+    );
+    request
+        .headers
+        .insert_type(Name::TO, &FromTo::new(NameAddr::uri(uri), None));
+    request.headers.insert_named(&CallID::new("transport-test"));
+    request.headers.insert_named(&CSeq::new(1, Method::OPTIONS));
+    request.body = Bytes::from(vec![b'a'; body_len]);
+    request
+}
+
+async fn send(endpoint: &Endpoint, request: Request) -> TargetTransportInfo {
+    let mut target = TargetTransportInfo::default();
+    let mut tsx = endpoint.send_request(request, &mut target).await.unwrap();
+
+    let response = timeout(Duration::from_secs(5), tsx.receive_final())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(response.line.code, StatusCode::OK);
+
+    target
+}
+
+#[tokio::test]
+async fn small_request_uses_udp() {
+    let (_server, address, mut received) = server().await;
+    let client = client().await;
+    let mut switches = client.subscribe_transport_switches();
+
+    send(&client, options(address, 100)).await;
+
+    assert_eq!(received.recv().await, Some(("UDP", 100)));
+    assert!(switches.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn oversized_request_switches_to_tcp() {
+    let (_server, address, mut received) = server().await;
+    let client = client().await;
+    let mut switches = client.subscribe_transport_switches();
+
+    let target = send(&client, options(address, 2000)).await;
+
+    assert_eq!(received.recv().await, Some(("TCP", 2000)));
+
+    let switch = switches.try_recv().unwrap();
+    assert!(switch.request_size > 2000);
+    assert_eq!(switch.from.name(), "UDP");
+    assert_eq!(switch.to.name(), "TCP");
+    assert_eq!(switch.destination, address);
+
+    // Later requests to the same target reuse the reliable transport
+    let (transport, destination) = target.transport.unwrap();
+    assert_eq!(transport.name(), "TCP");
+    assert_eq!(destination, address);
+}
+
+#[tokio::test]
+async fn transport_param_connects_tcp() {
+    let (_server, address, mut received) = server().await;
+    let client = client().await;
+
+    let mut request = options(address, 100);
+    request.line.uri.uri_params.push_or_edit("transport", "tcp");
+
+    send(&client, request).await;
+
+    assert_eq!(received.recv().await, Some(("TCP", 100)));
+}
+
+#[tokio::test]
+async fn sips_requires_secure_transport() {
+    let (_server, address, _received) = server().await;
+    let client = client().await;
+
+    let uri: SipUri = format!("sips:{address}").parse().unwrap();
+
+    assert!(client.select_transport(&uri).await.is_err());
+}
@@ -0,0 +1,158 @@
+use ezk_sip_core::transport::udp::Udp;
+use ezk_sip_core::transport::TargetTransportInfo;
+use ezk_sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake, Request};
+use sip_types::header::typed::{Allow, CSeq, CallID, FromTo};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{Method, Name, StatusCode};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::time::timeout;
+
+const LOCAL_IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+/// Answers every MESSAGE request, if the method was claimed at runtime
+struct Messages;
+
+#[async_trait::async_trait]
+impl Layer for Messages {
+    fn name(&self) -> &'static str {
+        "messages"
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::MESSAGE || !endpoint.is_method_allowed(&Method::MESSAGE) {
+            return;
+        }
+
+        let mut request = request.take();
+        let tsx = endpoint.create_server_tsx(&mut request);
+
+        let response = endpoint.create_response(&request, StatusCode::OK, None);
+        tsx.respond(response).await.unwrap();
+    }
+}
+
+async fn server(configure: impl FnOnce(&mut EndpointBuilder)) -> (Endpoint, SocketAddr) {
+    let mut builder = Endpoint::builder();
+    builder.add_layer(Messages);
+    configure(&mut builder);
+
+    let udp = Udp::spawn(&mut builder, (LOCAL_IP, 0)).await.unwrap();
+
+    (builder.build(), udp.bound())
+}
+
+async fn client() -> Endpoint {
+    let mut builder = Endpoint::builder();
+    Udp::spawn(&mut builder, (LOCAL_IP, 0)).await.unwrap();
+    builder.build()
+}
+
+fn request(method: Method, server: SocketAddr) -> Request {
+    let uri: SipUri = format!("sip:{server}").parse().unwrap();
+    let from: SipUri = "sip:alice@example.org".parse().unwrap();
+
+    let mut request = Request::new(method.clone(), uri.clone());
+    request.headers.insert_type(
+        Name::FROM,
+        &FromTo::new(NameAddr::uri(from), Some("from-tag".into())),
+    );
+    request
+        .headers
+        .insert_type(Name::TO, &FromTo::new(NameAddr::uri(uri), None));
+    request
+        .headers
+        .insert_named(&CallID::new("unsupported-methods-test"));
+    request.headers.insert_named(&CSeq::new(1, method));
+    request
+}
+
+/// Send a request to the server and return the code & ALLOW headers of the response
+async fn send(method: Method, server: SocketAddr) -> (StatusCode, Vec<Allow>) {
+    let client = client().await;
+
+    let mut target = TargetTransportInfo::default();
+    let mut tsx = client
+        .send_request(request(method, server), &mut target)
+        .await
+        .unwrap();
+
+    let response = timeout(Duration::from_secs(5), tsx.receive_final())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let allow = response.headers.get_named().unwrap_or_default();
+
+    (response.line.code, allow)
+}
+
+#[tokio::test]
+async fn disabled_by_default() {
+    let (_server, address) = server(|builder| builder.add_allow(Method::OPTIONS)).await;
+
+    let (code, allow) = send(Method::INFO, address).await;
+    assert_eq!(code, StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST);
+    assert!(allow.is_empty());
+}
+
+#[tokio::test]
+async fn method_not_allowed() {
+    let (server, address) = server(|builder| {
+        builder.reject_unsupported_methods(true);
+        builder.add_allow(Method::OPTIONS);
+        builder.add_allow(Method::INFO);
+    })
+    .await;
+    server.claim_method(Method::MESSAGE);
+
+    let (code, allow) = send(Method::SUBSCRIBE, address).await;
+    assert_eq!(code, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(
+        allow,
+        [
+            Allow(Method::OPTIONS),
+            Allow(Method::INFO),
+            Allow(Method::MESSAGE)
+        ]
+    );
+
+    // Allowed methods which no layer handles are not rejected
+    let (code, _) = send(Method::INFO, address).await;
+    assert_eq!(code, StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST);
+}
+
+#[tokio::test]
+async fn not_implemented_without_allowed_methods() {
+    let (_server, address) = server(|builder| {
+        builder.reject_unsupported_methods(true);
+    })
+    .await;
+
+    let (code, allow) = send(Method::SUBSCRIBE, address).await;
+    assert_eq!(code, StatusCode::NOT_IMPLMENTED);
+    assert!(allow.is_empty());
+}
+
+#[tokio::test]
+async fn claimed_method_reaches_layer() {
+    let (server, address) = server(|builder| {
+        builder.reject_unsupported_methods(true);
+        builder.add_allow(Method::OPTIONS);
+    })
+    .await;
+
+    let (code, _) = send(Method::MESSAGE, address).await;
+    assert_eq!(code, StatusCode::METHOD_NOT_ALLOWED);
+
+    server.claim_method(Method::MESSAGE);
+
+    let (code, _) = send(Method::MESSAGE, address).await;
+    assert_eq!(code, StatusCode::OK);
+
+    server.release_method(&Method::MESSAGE);
+
+    let (code, allow) = send(Method::MESSAGE, address).await;
+    assert_eq!(code, StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(allow, [Allow(Method::OPTIONS)]);
+}
@@ -141,6 +141,10 @@ codes! {
     /// 200 OK
     [200 => OK, "OK"];
 
+    /// [[RFC6665, Section 8.3.1](https://tools.ietf.org/html/rfc6665#section-8.3.1)]
+    /// 202 Accepted
+    [202 => ACCEPTED, "Accepted"];
+
     // ==== REDIRECTION 3XX ====
 
     /// [[RFC3621, Section 21.3.1](https://tools.ietf.org/html/rfc3261#section-21.3.1)]
@@ -315,6 +319,10 @@ codes! {
     /// 513 Message Too Large
     [513 => MESSAGE_TOO_LARGE, "Message Too Large"];
 
+    /// [[RFC8599, Section 4.1.3](https://datatracker.ietf.org/doc/html/rfc8599#section-4.1.3)]
+    /// 555 Push Notification Service Not Supported
+    [555 => PUSH_NOTIFICATION_SERVICE_NOT_SUPPORTED, "Push Notification Service Not Supported"];
+
     // ==== GLOBAL FAILURE 6XX ====
 
     /// [[RFC3621, Section 21.6.1](https://tools.ietf.org/html/rfc3261#section-21.6.1)]
@@ -181,6 +181,9 @@ header_names! {
     /// [[RFC3621, Section 20.19](https://tools.ietf.org/html/rfc3261#section-20.19)]
     "Expires",              Expires,            ["expires"],                EXPIRES;
 
+    /// [[RFC6809, Section 6](https://datatracker.ietf.org/doc/html/rfc6809#section-6)]
+    "Feature-Caps",         FeatureCaps,        ["feature-caps"],           FEATURE_CAPS;
+
     /// [[RFC3621, Section 20.20](https://tools.ietf.org/html/rfc3261#section-20.20)]
     "From",                 From,               ["from", "f"],              FROM;
 
@@ -238,6 +241,9 @@ header_names! {
     /// [[RFC3621, Section 20.30](https://tools.ietf.org/html/rfc3261#section-20.30)]
     "Record-Route",         RecordRoute,        ["record-route"],           RECORD_ROUTE;
 
+    /// [[RFC3515, Section 2.1](https://datatracker.ietf.org/doc/html/rfc3515#section-2.1)]
+    "Refer-To",             ReferTo,            ["refer-to", "r"],          REFER_TO;
+
     /// [[RFC3891, Section 6.1](https://datatracker.ietf.org/doc/html/rfc3891#section-6.1)]
     "Replaces",             Replaces,           ["replaces"],               REPLACES;
 
@@ -0,0 +1,106 @@
+use crate::header::headers::OneOrMore;
+use crate::header::name::Name;
+use crate::header::{ConstNamed, ExtendValues, HeaderParse};
+use crate::print::PrintCtx;
+use crate::uri::params::{Params, CPS};
+use bytes::Bytes;
+use internal::IResult;
+use nom::bytes::complete::tag;
+use nom::combinator::map;
+use nom::sequence::preceded;
+use std::fmt;
+
+/// `Feature-Caps` header ([RFC6809](https://datatracker.ietf.org/doc/html/rfc6809)), contains only one value.
+/// To get all values use [`Vec`].
+///
+/// The feature capability indicators (e.g. `+sip.pns`) are stored as params.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureCaps {
+    pub params: Params<CPS>,
+}
+
+impl FeatureCaps {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    impl_with_params!(params, with_key_param, with_value_param);
+}
+
+impl ConstNamed for FeatureCaps {
+    const NAME: Name = Name::FEATURE_CAPS;
+}
+
+impl HeaderParse for FeatureCaps {
+    fn parse<'i>(src: &'i Bytes, i: &'i str) -> IResult<&'i str, Self> {
+        map(preceded(tag("*"), Params::<CPS>::parse(src)), |params| {
+            FeatureCaps { params }
+        })(i)
+    }
+}
+
+impl ExtendValues for FeatureCaps {
+    fn extend_values(&self, _: PrintCtx<'_>, values: &mut OneOrMore) {
+        let value = match values {
+            OneOrMore::One(value) => value,
+            OneOrMore::More(values) => values.last_mut().expect("empty OneOrMore::More variant"),
+        };
+
+        *value = format!("{}, {}", value, self).into();
+    }
+
+    fn create_values(&self, _: PrintCtx<'_>) -> OneOrMore {
+        OneOrMore::One(self.to_string().into())
+    }
+}
+
+impl fmt::Display for FeatureCaps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "*{}", self.params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Headers;
+    use bytesstr::BytesStr;
+
+    #[test]
+    fn feature_caps() {
+        let input = BytesStr::from_static("*;+sip.pns=\"apns\";+sip.pnsreg=\"121\"");
+
+        let (rem, feature_caps) = FeatureCaps::parse(input.as_ref(), &input).unwrap();
+
+        assert!(rem.is_empty());
+
+        assert_eq!(feature_caps.params.get_val("+sip.pns").unwrap(), "apns");
+        assert_eq!(feature_caps.params.get_val("+sip.pnsreg").unwrap(), "121");
+    }
+
+    #[test]
+    fn feature_caps_multiple() {
+        let mut headers = Headers::new();
+        headers.insert(
+            Name::FEATURE_CAPS,
+            "*;+sip.pns=\"fcm\", *;+sip.pnsreg=\"60\"",
+        );
+
+        let feature_caps: Vec<FeatureCaps> = headers.get_named().unwrap();
+
+        assert_eq!(feature_caps.len(), 2);
+        assert_eq!(feature_caps[0].params.get_val("+sip.pns").unwrap(), "fcm");
+        assert_eq!(feature_caps[1].params.get_val("+sip.pnsreg").unwrap(), "60");
+    }
+
+    #[test]
+    fn feature_caps_print() {
+        let feature_caps = FeatureCaps::new().with_key_param("+sip.pnspurr");
+
+        let mut headers = Headers::new();
+        headers.insert_named(&feature_caps);
+
+        assert_eq!(headers.to_string(), "Feature-Caps: *;+sip.pnspurr\r\n");
+    }
+}
@@ -11,9 +11,11 @@ mod cseq;
 mod event;
 mod expires;
 mod extensions;
+mod feature_caps;
 mod from_to;
 mod max_fwd;
 mod prack;
+mod refer_to;
 mod replaces;
 mod retry_after;
 mod routing;
@@ -32,9 +34,11 @@ pub use cseq::CSeq;
 pub use event::Event;
 pub use expires::{Expires, MinExpires};
 pub use extensions::{Require, Supported, Unsupported};
+pub use feature_caps::FeatureCaps;
 pub use from_to::FromTo;
 pub use max_fwd::MaxForwards;
 pub use prack::{RAck, RSeq};
+pub use refer_to::ReferTo;
 pub use replaces::Replaces;
 pub use retry_after::RetryAfter;
 pub use routing::Routing;
@@ -0,0 +1,104 @@
+//! [RFC3515](https://datatracker.ietf.org/doc/html/rfc3515)
+
+use crate::header::headers::OneOrMore;
+use crate::header::name::Name;
+use crate::header::{ConstNamed, ExtendValues, HeaderParse};
+use crate::print::{AppendCtx, Print, PrintCtx};
+use crate::uri::params::{Params, CPS};
+use crate::uri::NameAddr;
+use bytes::Bytes;
+use internal::IResult;
+use nom::combinator::map;
+use nom::sequence::tuple;
+use std::fmt;
+
+/// `Refer-To` header
+///
+/// Unlike most other headers containing URIs, the URI may contain headers (e.g. `?Replaces=...`) which are meant
+/// to be added to the request sent to it.
+#[derive(Debug, Clone)]
+pub struct ReferTo {
+    pub uri: NameAddr,
+    pub params: Params<CPS>,
+}
+
+impl ReferTo {
+    #[inline]
+    pub fn new(uri: NameAddr) -> ReferTo {
+        ReferTo {
+            uri,
+            params: Params::new(),
+        }
+    }
+
+    impl_with_params!(params, with_key_param, with_value_param);
+}
+
+impl ConstNamed for ReferTo {
+    const NAME: Name = Name::REFER_TO;
+}
+
+impl HeaderParse for ReferTo {
+    fn parse<'i>(src: &'i Bytes, i: &'i str) -> IResult<&'i str, Self> {
+        map(
+            tuple((NameAddr::parse_no_params(src), Params::<CPS>::parse(src))),
+            |(uri, params)| ReferTo { uri, params },
+        )(i)
+    }
+}
+
+impl ExtendValues for ReferTo {
+    fn extend_values(&self, ctx: PrintCtx<'_>, values: &mut OneOrMore) {
+        *values = self.create_values(ctx)
+    }
+
+    fn create_values(&self, ctx: PrintCtx<'_>) -> OneOrMore {
+        OneOrMore::One(self.print_ctx(ctx).to_string().into())
+    }
+}
+
+impl Print for ReferTo {
+    fn print(&self, f: &mut fmt::Formatter<'_>, ctx: PrintCtx<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.uri.print_ctx(ctx), self.params)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::uri::SipUri;
+    use crate::Headers;
+
+    #[test]
+    fn print_refer_to() {
+        let mut uri: SipUri = "sip:bob@example.org".parse().unwrap();
+        uri.header_params
+            .push_or_edit("Replaces", "call@host;to-tag=1;from-tag=2");
+
+        let mut headers = Headers::new();
+        headers.insert_named(&ReferTo::new(NameAddr::uri(uri)));
+        let headers = headers.to_string();
+
+        assert_eq!(
+            headers,
+            "Refer-To: <sip:bob@example.org?Replaces=call%40host%3Bto-tag%3D1%3Bfrom-tag%3D2>\r\n"
+        );
+    }
+
+    #[test]
+    fn parse_refer_to() {
+        let mut headers = Headers::new();
+        headers.insert(
+            Name::REFER_TO,
+            "<sip:bob@example.org?Replaces=call%40host%3Bto-tag%3D1%3Bfrom-tag%3D2>",
+        );
+
+        let refer_to: ReferTo = headers.get_named().unwrap();
+
+        assert!(refer_to.params.is_empty());
+        assert_eq!(
+            refer_to.uri.uri.header_params.get_val("Replaces").unwrap(),
+            "call@host;to-tag=1;from-tag=2"
+        );
+    }
+}
@@ -115,9 +115,9 @@ impl<S: ParamsSpec> Params<S> {
                 opt(map(
                     ws((
                         tag(S::FIRST_DELIMITER),
-                        Param::do_parse(src, S::CHAR_SPEC),
+                        Param::do_parse(src, parse_char::<S>),
                         many0(map(
-                            ws((tag(S::DELIMITER), Param::do_parse(src, S::CHAR_SPEC))),
+                            ws((tag(S::DELIMITER), Param::do_parse(src, parse_char::<S>))),
                             |(_, param)| param,
                         )),
                     )),
@@ -203,6 +203,11 @@ pub trait ParamsSpec {
     const ENCODE_SET: fn() -> &'static AsciiSet;
 }
 
+/// Characters accepted when parsing params of the spec, which always includes percent-encoded characters
+fn parse_char<S: ParamsSpec>(c: char) -> bool {
+    c == '%' || (S::CHAR_SPEC)(c)
+}
+
 /// Header Param Specification in uris (?SomeHeader=SomeValue&SomeOtherHeader=SomeOtherValue)
 pub enum HPS {}
 
@@ -411,6 +416,29 @@ mod test {
         );
     }
 
+    #[test]
+    fn header_params_decode() {
+        let src = BytesStr::from_static("?Replaces=abc%40host%3Bto-tag%3D1%3Bfrom-tag%3D2");
+        let (rem, params) = Params::<HPS>::parse(src.as_ref())(&src).unwrap();
+
+        assert!(rem.is_empty());
+
+        assert_eq!(
+            params.get_val("Replaces").unwrap(),
+            "abc@host;to-tag=1;from-tag=2"
+        );
+        assert_eq!(
+            params.to_string(),
+            "?Replaces=abc%40host%3Bto-tag%3D1%3Bfrom-tag%3D2"
+        );
+
+        let src = BytesStr::from_static("?emoji=%F0%9F%98%80");
+        let (rem, params) = Params::<HPS>::parse(src.as_ref())(&src).unwrap();
+
+        assert!(rem.is_empty());
+        assert_eq!(params.get_val("emoji").unwrap(), "😀");
+    }
+
     #[test]
     fn header_params_print() {
         let params = Params::<HPS>::new()
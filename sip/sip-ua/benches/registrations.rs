@@ -0,0 +1,131 @@
+//! Time until every registration of a [`RegistrationSet`] is accepted by a registrar
+//!
+//! Client and registrar are two endpoints connected over UDP on the loopback interface, the registrar accepts every
+//! REGISTER request. `register/N` inserts N registrations into a new set and runs it until N
+//! [`RegistrationEvent::Registered`] events are returned. Results are tracked in `benches/README.md`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ezk_sip_ua::register::{Registration, RegistrationEvent, RegistrationSet};
+use sip_core::transport::udp::Udp;
+use sip_core::{Endpoint, IncomingRequest, Layer, MayTake};
+use sip_types::header::typed::{Contact, Expires};
+use sip_types::uri::{NameAddr, SipUri, SipUriUserPart};
+use sip_types::{Method, StatusCode};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+const LOCAL_IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Number of REGISTER requests in flight at once
+const MAX_IN_FLIGHT: usize = 64;
+
+/// Accepts every REGISTER request
+struct Registrar;
+
+#[async_trait::async_trait]
+impl Layer for Registrar {
+    fn name(&self) -> &'static str {
+        "registrar"
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::REGISTER {
+            return;
+        }
+
+        let mut request = request.take();
+        let tsx = endpoint.create_server_tsx(&mut request);
+
+        let mut response = endpoint.create_response(&request, StatusCode::OK, None);
+        response.msg.headers.insert_named(&Expires(3600));
+
+        let _ = tsx.respond(response).await;
+    }
+}
+
+struct Setup {
+    client: Endpoint,
+    client_uri: SipUri,
+    registrar_uri: SipUri,
+    _registrar: Endpoint,
+}
+
+async fn setup() -> Setup {
+    let mut builder = Endpoint::builder();
+    builder.add_layer(Registrar);
+    let udp = Udp::spawn(&mut builder, (LOCAL_IP, 0)).await.unwrap();
+    let registrar_uri = format!("sip:{}", udp.bound()).parse().unwrap();
+    let registrar = builder.build();
+
+    let mut builder = Endpoint::builder();
+    let udp = Udp::spawn(&mut builder, (LOCAL_IP, 0)).await.unwrap();
+    let client_uri = format!("sip:{}", udp.bound()).parse().unwrap();
+    let client = builder.build();
+
+    Setup {
+        client,
+        client_uri,
+        registrar_uri,
+        _registrar: registrar,
+    }
+}
+
+async fn register_all(setup: &Setup, count: usize) -> Duration {
+    let mut set = RegistrationSet::new(setup.client.clone(), MAX_IN_FLIGHT);
+
+    for i in 0..count {
+        let id: SipUri = format!("sip:user{i}@example.org").parse().unwrap();
+        let mut contact = setup.client_uri.clone();
+        contact.user_part = SipUriUserPart::User(format!("user{i}").into());
+
+        set.insert(Registration::new(
+            NameAddr::uri(id),
+            Contact::new(NameAddr::uri(contact)),
+            setup.registrar_uri.clone(),
+            Duration::from_secs(3600),
+        ));
+    }
+
+    let start = Instant::now();
+    let mut registered = 0;
+
+    while registered < count {
+        match set.run().await {
+            RegistrationEvent::Registered { .. } => registered += 1,
+            event => panic!("registration failed, {event:?}"),
+        }
+    }
+
+    start.elapsed()
+}
+
+fn register(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let setup = runtime.block_on(setup());
+
+    let mut group = c.benchmark_group("register");
+    group.sample_size(10);
+
+    for count in [100, 1000, 5000] {
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut total = Duration::ZERO;
+
+                    for _ in 0..iters {
+                        total += register_all(&setup, count).await;
+                    }
+
+                    total
+                })
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, register);
+criterion_main!(benches);
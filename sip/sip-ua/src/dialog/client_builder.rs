@@ -5,7 +5,7 @@ use bytes::Bytes;
 use sip_core::transaction::TsxResponse;
 use sip_core::transport::TargetTransportInfo;
 use sip_core::{Endpoint, Request};
-use sip_types::header::typed::{CSeq, CallID, Contact, FromTo, MaxForwards};
+use sip_types::header::typed::{CSeq, CallID, Contact, FromTo, MaxForwards, Routing};
 use sip_types::header::HeaderError;
 use sip_types::msg::RequestLine;
 use sip_types::uri::{NameAddr, SipUri};
@@ -21,6 +21,8 @@ pub struct ClientDialogBuilder {
     pub local_contact: Contact,
     pub call_id: CallID,
     pub target: SipUri,
+    /// Preloaded route set of the requests sent before the dialog is created, e.g. an outbound proxy
+    pub route_set: Vec<Routing>,
     pub secure: bool,
     pub target_tp_info: TargetTransportInfo,
 }
@@ -41,6 +43,7 @@ impl ClientDialogBuilder {
             call_id: CallID(random_string()),
             secure: target.sips,
             target,
+            route_set: vec![],
             target_tp_info: TargetTransportInfo::default(),
         }
     }
@@ -58,6 +61,10 @@ impl ClientDialogBuilder {
         });
         headers.insert_named(&self.local_contact);
 
+        if !self.route_set.is_empty() {
+            headers.insert_type(Name::ROUTE, &self.route_set);
+        }
+
         Request {
             line: RequestLine {
                 method,
@@ -68,6 +75,13 @@ impl ClientDialogBuilder {
         }
     }
 
+    /// URI of the next hop of the requests, the first entry of the route set or the target
+    pub fn next_hop(&self) -> &SipUri {
+        self.route_set
+            .first()
+            .map_or(&self.target, |route| &route.uri.uri)
+    }
+
     pub fn create_dialog_from_response(
         &mut self,
         response: &TsxResponse,
@@ -1,5 +1,6 @@
 use bytesstr::BytesStr;
 use sip_core::IncomingRequest;
+use sip_types::header::typed::Replaces;
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct DialogKey {
@@ -9,6 +10,26 @@ pub struct DialogKey {
 }
 
 impl DialogKey {
+    /// Create a `Replaces` header which a third party can send to the peer of this dialog, to replace the dialog
+    /// with its own (e.g. for an attended transfer)
+    ///
+    /// Returns `None` if the dialog has no peer tag yet.
+    pub fn to_replaces(&self) -> Option<Replaces> {
+        Some(Replaces {
+            call_id: self.call_id.clone(),
+            from_tag: self.local_tag.clone(),
+            to_tag: self.peer_tag.clone()?,
+            early_only: false,
+        })
+    }
+
+    /// Returns if the `Replaces` header received in a request refers to this dialog
+    pub fn matches_replaces(&self, replaces: &Replaces) -> bool {
+        self.call_id == replaces.call_id
+            && self.local_tag == replaces.to_tag
+            && self.peer_tag.as_ref() == Some(&replaces.from_tag)
+    }
+
     pub(crate) fn from_incoming(request: &IncomingRequest) -> Option<Self> {
         let base_headers = &request.base_headers;
         Some(Self {
@@ -30,6 +30,10 @@ impl DialogEntry {
             usages: Default::default(),
         }
     }
+
+    pub(super) fn next_peer_cseq(&self) -> Option<u32> {
+        self.next_peer_cseq
+    }
 }
 
 #[derive(Default)]
@@ -158,7 +162,9 @@ impl DialogLayer {
             return Ok(());
         }
 
-        let response = endpoint.create_response(&request, StatusCode::NOT_FOUND, None);
+        let response = endpoint
+            .create_method_not_allowed_response(&request)
+            .unwrap_or_else(|| endpoint.create_response(&request, StatusCode::NOT_FOUND, None));
 
         if request.line.method == Method::INVITE {
             let tsx = endpoint.create_server_inv_tsx(&mut request);
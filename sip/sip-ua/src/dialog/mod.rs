@@ -12,10 +12,12 @@ use tokio::sync::Mutex;
 mod client_builder;
 mod key;
 mod layer;
+mod snapshot;
 
 pub use client_builder::ClientDialogBuilder;
 pub use key::DialogKey;
 pub use layer::{register_usage, DialogLayer, Usage, UsageGuard};
+pub use snapshot::DialogSnapshot;
 
 #[derive(Debug)]
 pub struct Dialog {
@@ -113,12 +115,16 @@ impl Dialog {
     }
 
     pub fn create_request(&self, method: Method) -> Request {
+        let cseq = self.local_cseq.fetch_add(1, Ordering::Relaxed);
+
+        self.create_request_with_cseq(method, cseq)
+    }
+
+    /// Create a request using the given CSeq number, without consuming one of the dialog
+    pub(crate) fn create_request_with_cseq(&self, method: Method, cseq: u32) -> Request {
         let mut request = Request::new(method.clone(), self.peer_contact.uri.uri.clone());
 
-        let cseq = CSeq::new(
-            self.local_cseq.fetch_add(1, Ordering::Relaxed),
-            method.clone(),
-        );
+        let cseq = CSeq::new(cseq, method.clone());
 
         request.headers.insert_type(Name::FROM, &self.local_fromto);
         request.headers.insert_type(Name::TO, &self.peer_fromto);
@@ -158,8 +164,10 @@ impl Dialog {
                 }
             }
 
-            if let 180..=189 | 200..=299 | 405 = code {
-                response.msg.headers.insert_named(self.endpoint.allowed());
+            let allow = self.endpoint.allow_header();
+
+            if matches!(code, 180..=189 | 200..=299 | 405) && !allow.is_empty() {
+                response.msg.headers.insert_named(&allow);
             }
 
             if let 200..=299 = code {
@@ -170,7 +178,9 @@ impl Dialog {
                     })?;
                 }
 
-                response.msg.headers.insert_named(self.endpoint.supported());
+                if !self.endpoint.supported().is_empty() {
+                    response.msg.headers.insert_named(self.endpoint.supported());
+                }
             }
         }
 
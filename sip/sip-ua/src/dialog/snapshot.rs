@@ -0,0 +1,300 @@
+use super::layer::DialogEntry;
+use super::{Dialog, DialogLayer};
+use bytes::Bytes;
+use sip_core::Endpoint;
+use sip_types::header::typed::{CallID, Contact, FromTo, Routing, SessionExpires};
+use sip_types::header::HeaderError;
+use sip_types::msg::Line;
+use sip_types::{Headers, Name};
+use std::fmt;
+use std::sync::atomic::Ordering;
+
+const PEER_CONTACT: Name = Name::custom("Peer-Contact", &["peer-contact"]);
+const LOCAL_CSEQ: Name = Name::custom("Local-CSeq", &["local-cseq"]);
+const PEER_CSEQ: Name = Name::custom("Peer-CSeq", &["peer-cseq"]);
+const SECURE: Name = Name::custom("Secure", &["secure"]);
+
+/// State of a [`Dialog`] required to resume it, e.g. after restarting the process
+///
+/// Created using [`Dialog::snapshot`] and turned back into a dialog using [`Dialog::restore`].
+/// It can be persisted in its printed form, which is a list of SIP headers, and read back using
+/// [`DialogSnapshot::parse`].
+#[derive(Debug, Clone)]
+pub struct DialogSnapshot {
+    /// The next CSeq number used for requests sent inside the dialog
+    pub local_cseq: u32,
+
+    /// CSeq number of the last request received inside the dialog
+    pub peer_cseq: Option<u32>,
+
+    pub local_fromto: FromTo,
+    pub peer_fromto: FromTo,
+    pub local_contact: Contact,
+    pub peer_contact: Contact,
+    pub call_id: CallID,
+    pub route_set: Vec<Routing>,
+    pub secure: bool,
+
+    /// Session timer of the INVITE session using the dialog, set by
+    /// [`InviteSession::snapshot`](crate::invite::session::InviteSession::snapshot)
+    pub session_expires: Option<SessionExpires>,
+}
+
+impl DialogSnapshot {
+    /// Parse a snapshot from its printed form
+    pub fn parse(input: &str) -> Result<Self, HeaderError> {
+        // Parsed values reference the source buffer
+        let src = Bytes::copy_from_slice(input.as_bytes());
+        let input = std::str::from_utf8(&src).expect("copied from str");
+
+        let mut headers = Headers::new();
+
+        for line in input
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty())
+        {
+            let (_, line) = Line::parse(&src, line).map_err(|_| {
+                HeaderError::malformed_adhoc(
+                    Name::unknown(line.to_owned().into()),
+                    "invalid header line",
+                )
+            })?;
+
+            headers.insert(line.name, line.value);
+        }
+
+        Self::from_headers(&headers)
+    }
+
+    /// Read a snapshot from the headers created by [`DialogSnapshot::to_headers`]
+    pub fn from_headers(headers: &Headers) -> Result<Self, HeaderError> {
+        let route_set = match headers.get(Name::ROUTE) {
+            Ok(route_set) => route_set,
+            Err(e) if e.is_missing() => vec![],
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            local_cseq: get_number(headers, LOCAL_CSEQ)?
+                .ok_or_else(|| HeaderError::missing(LOCAL_CSEQ))?,
+            peer_cseq: get_number(headers, PEER_CSEQ)?,
+            local_fromto: headers.get(Name::FROM)?,
+            peer_fromto: headers.get(Name::TO)?,
+            local_contact: headers.get(Name::CONTACT)?,
+            peer_contact: headers.get(PEER_CONTACT)?,
+            call_id: headers.get_named()?,
+            route_set,
+            secure: get_number(headers, SECURE)?.is_some_and(|secure| secure != 0),
+            session_expires: headers.try_get_named().transpose()?,
+        })
+    }
+
+    /// Create headers containing the snapshot
+    pub fn to_headers(&self) -> Headers {
+        let mut headers = Headers::new();
+
+        headers.insert_named(&self.call_id);
+        headers.insert_type(Name::FROM, &self.local_fromto);
+        headers.insert_type(Name::TO, &self.peer_fromto);
+        headers.insert_type(Name::CONTACT, &self.local_contact);
+        headers.insert_type(PEER_CONTACT, &self.peer_contact);
+
+        if !self.route_set.is_empty() {
+            headers.insert_type(Name::ROUTE, &self.route_set);
+        }
+
+        headers.insert(LOCAL_CSEQ, self.local_cseq.to_string());
+
+        if let Some(peer_cseq) = self.peer_cseq {
+            headers.insert(PEER_CSEQ, peer_cseq.to_string());
+        }
+
+        headers.insert(SECURE, u8::from(self.secure).to_string());
+
+        if let Some(session_expires) = &self.session_expires {
+            headers.insert_named(session_expires);
+        }
+
+        headers
+    }
+}
+
+impl fmt::Display for DialogSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_headers().fmt(f)
+    }
+}
+
+fn get_number(headers: &Headers, name: Name) -> Result<Option<u32>, HeaderError> {
+    let Some((_, value)) = headers.iter().find(|(n, _)| **n == name) else {
+        return Ok(None);
+    };
+
+    value
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| HeaderError::malformed_adhoc(name, "expected a number"))
+}
+
+impl Dialog {
+    /// Capture the state of the dialog, to be able to [`restore`](Dialog::restore) it later
+    pub fn snapshot(&self) -> DialogSnapshot {
+        let peer_cseq = self
+            .endpoint
+            .layer::<DialogLayer>()
+            .dialogs
+            .lock()
+            .get(&self.key())
+            .and_then(DialogEntry::next_peer_cseq)
+            .map(|next_peer_cseq| next_peer_cseq - 1);
+
+        DialogSnapshot {
+            local_cseq: self.local_cseq.load(Ordering::Relaxed),
+            peer_cseq,
+            local_fromto: self.local_fromto.clone(),
+            peer_fromto: self.peer_fromto.clone(),
+            local_contact: self.local_contact.clone(),
+            peer_contact: self.peer_contact.clone(),
+            call_id: self.call_id.clone(),
+            route_set: self.route_set.clone(),
+            secure: self.secure,
+            session_expires: None,
+        }
+    }
+
+    /// Re-create a dialog from a snapshot taken before, e.g. by a previous instance of the process
+    ///
+    /// The dialog is registered with the endpoint's [`DialogLayer`] and can be used to send requests right away.
+    /// The transport to the peer is resolved again from its contact.
+    pub fn restore(endpoint: Endpoint, snapshot: DialogSnapshot) -> Self {
+        let dialog = Self {
+            endpoint,
+            local_cseq: snapshot.local_cseq.into(),
+            local_fromto: snapshot.local_fromto,
+            peer_fromto: snapshot.peer_fromto,
+            local_contact: snapshot.local_contact,
+            peer_contact: snapshot.peer_contact,
+            call_id: snapshot.call_id,
+            route_set: snapshot.route_set,
+            secure: snapshot.secure,
+            target_tp_info: Default::default(),
+        };
+
+        let entry = DialogEntry::new(snapshot.peer_cseq);
+        dialog
+            .endpoint
+            .layer::<DialogLayer>()
+            .dialogs
+            .lock()
+            .insert(dialog.key(), entry);
+
+        dialog
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sip_types::header::typed::Refresher;
+    use sip_types::uri::{NameAddr, SipUri};
+
+    fn name_addr(uri: &str) -> NameAddr {
+        NameAddr::uri(uri.parse::<SipUri>().unwrap())
+    }
+
+    fn snapshot() -> DialogSnapshot {
+        DialogSnapshot {
+            local_cseq: 42,
+            peer_cseq: Some(7),
+            local_fromto: FromTo::new(name_addr("sip:alice@example.org"), Some("local".into())),
+            peer_fromto: FromTo::new(name_addr("sip:bob@example.com"), Some("peer".into())),
+            local_contact: Contact::new(name_addr("sip:alice@192.0.2.1:5060")),
+            peer_contact: Contact::new(name_addr("sips:bob@198.51.100.2:5061;transport=tcp")),
+            call_id: CallID::new("snapshot-call-id"),
+            route_set: vec![
+                Routing {
+                    uri: name_addr("sip:proxy1.example.org;lr"),
+                    params: Default::default(),
+                },
+                Routing {
+                    uri: name_addr("sip:proxy2.example.org;lr"),
+                    params: Default::default(),
+                },
+            ],
+            secure: true,
+            session_expires: Some(SessionExpires {
+                delta_secs: 1800,
+                refresher: Refresher::Uas,
+            }),
+        }
+    }
+
+    #[test]
+    fn print_parse() {
+        let snapshot = snapshot();
+        let printed = snapshot.to_string();
+
+        let parsed = DialogSnapshot::parse(&printed).unwrap();
+
+        assert_eq!(parsed.local_cseq, 42);
+        assert_eq!(parsed.peer_cseq, Some(7));
+        assert_eq!(parsed.local_fromto.tag.as_deref(), Some("local"));
+        assert_eq!(parsed.peer_fromto.tag.as_deref(), Some("peer"));
+        assert!(parsed.peer_contact.uri.uri.sips);
+        assert_eq!(parsed.peer_contact.uri.uri.host_port.port, Some(5061));
+        assert_eq!(
+            parsed
+                .peer_contact
+                .uri
+                .uri
+                .uri_params
+                .get_val("transport")
+                .map(|t| t.as_str()),
+            Some("tcp")
+        );
+        assert_eq!(parsed.call_id, snapshot.call_id);
+        assert_eq!(parsed.route_set.len(), 2);
+        assert!(parsed.secure);
+
+        let session_expires = parsed.session_expires.unwrap();
+        assert_eq!(session_expires.delta_secs, 1800);
+        assert_eq!(session_expires.refresher, Refresher::Uas);
+
+        assert_eq!(parsed.to_string(), printed);
+    }
+
+    #[test]
+    fn print_parse_optional() {
+        let mut snapshot = snapshot();
+        snapshot.peer_cseq = None;
+        snapshot.route_set = vec![];
+        snapshot.secure = false;
+        snapshot.session_expires = None;
+
+        let parsed = DialogSnapshot::parse(&snapshot.to_string()).unwrap();
+
+        assert_eq!(parsed.peer_cseq, None);
+        assert!(parsed.route_set.is_empty());
+        assert!(!parsed.secure);
+        assert!(parsed.session_expires.is_none());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let printed = snapshot().to_string();
+
+        let without_cseq: String = printed
+            .lines()
+            .filter(|line| !line.starts_with("Local-CSeq"))
+            .map(|line| format!("{line}\r\n"))
+            .collect();
+        assert!(DialogSnapshot::parse(&without_cseq).is_err());
+
+        let invalid_cseq = printed.replace("Local-CSeq: 42", "Local-CSeq: abc");
+        assert!(DialogSnapshot::parse(&invalid_cseq).is_err());
+
+        assert!(DialogSnapshot::parse("not a header").is_err());
+    }
+}
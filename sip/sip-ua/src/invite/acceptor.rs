@@ -5,16 +5,19 @@ use crate::dialog::{register_usage, Dialog, UsageGuard};
 use crate::invite::session::Role;
 use crate::invite::{InviteSessionState, InviteUsage};
 use crate::util::random_sequence_number;
+use bytes::Bytes;
 use bytesstr::BytesStr;
 use parking_lot as pl;
 use sip_core::transaction::consts::T1;
+use sip_core::transaction::ServerTsx;
 use sip_core::transport::OutgoingResponse;
 use sip_core::{Endpoint, IncomingRequest, Result};
-use sip_types::header::typed::{RSeq, Require, Supported};
+use sip_types::header::typed::{ContentType, RSeq, Require, Supported};
 use sip_types::{Method, StatusCode};
 use std::sync::Arc;
 use tokio::sync::{mpsc, oneshot, Mutex, Notify};
 use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -23,12 +26,21 @@ pub enum Error {
 
     #[error("peer cancelled its request")]
     RequestTerminated,
+
+    #[error("cancelled by the application")]
+    Cancelled,
 }
 
 #[derive(Debug, thiserror::Error)]
 #[error("invite got cancelled")]
 pub struct Cancelled;
 
+/// UPDATE request received before the INVITE was answered, see [`InviteAcceptor::receive_update`]
+pub struct EarlyUpdate {
+    pub update: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
 pub struct InviteAcceptor {
     endpoint: Endpoint,
     inner: Arc<Inner>,
@@ -37,8 +49,14 @@ pub struct InviteAcceptor {
     cancelled: bool,
     usage_guard: Option<UsageGuard>,
 
+    /// UPDATE requests received in the early dialog
+    updates: mpsc::Receiver<IncomingRequest>,
+
     /// Configuration for `timer` extension
     timer_config: AcceptorTimerConfig,
+
+    /// Application level cancellation, see [`InviteAcceptor::set_cancellation`]
+    cancellation: Option<CancellationToken>,
 }
 
 impl Drop for InviteAcceptor {
@@ -74,6 +92,7 @@ impl InviteAcceptor {
 
         let peer_supports_timer = supported.iter().any(|ext| ext.0 == "timer");
         let peer_supports_100rel = supported.iter().any(|ext| ext.0 == "100rel");
+        let peer_allows_update = super::allows_update(&invite.headers);
 
         // ==== register acceptor usage to dialog
 
@@ -84,6 +103,7 @@ impl InviteAcceptor {
             branch: invite.tsx_key.branch().clone(),
         };
         let cancelled_notify = Arc::new(Notify::new());
+        let (update_sink, updates) = mpsc::channel(4);
 
         // Create Inner shared state
         let tsx = endpoint.create_server_inv_tsx(&mut invite);
@@ -93,9 +113,11 @@ impl InviteAcceptor {
                 tsx,
                 invite,
                 cancelled_notify: cancelled_notify.clone(),
+                update_sink,
             }),
             peer_supports_timer,
             peer_supports_100rel,
+            peer_allows_update,
             awaited_ack: pl::Mutex::new(None),
             awaited_prack: pl::Mutex::new(None),
         });
@@ -125,10 +147,29 @@ impl InviteAcceptor {
             cancellable_key,
             cancelled_notify,
             cancelled: false,
+            updates,
             timer_config: AcceptorTimerConfig::default(),
+            cancellation: None,
         }
     }
 
+    /// Give up accepting the INVITE once the token is cancelled, e.g. when an application level timeout expired
+    ///
+    /// Waiting for the PRACK of a reliable provisional response is aborted and its retransmissions stop.
+    /// Once cancelled, [`respond_success`](Self::respond_success) no longer accepts the INVITE and responds with
+    /// `480 Temporarily Unavailable` instead. Both return [`Error::Cancelled`].
+    ///
+    /// Use [`cancel_at`](crate::util::cancel_at) to give up at a deadline.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
     /// Configure the `timer` extension
     pub fn timer_config(&mut self) -> &mut AcceptorTimerConfig {
         &mut self.timer_config
@@ -144,6 +185,40 @@ impl InviteAcceptor {
         self.cancelled = true;
     }
 
+    /// Receive an UPDATE request sent by the peer in the early dialog (RFC 3311)
+    ///
+    /// Respond to it using its transaction and a response created with
+    /// [`create_update_response`](Self::create_update_response). Returns `None` once the INVITE was answered or
+    /// cancelled, so waiting for UPDATE requests can replace waiting for [`cancelled`](Self::cancelled) while
+    /// the INVITE wasn't answered yet.
+    pub async fn receive_update(&mut self) -> Option<EarlyUpdate> {
+        let mut update = self.updates.recv().await?;
+        let transaction = self.endpoint.create_server_tsx(&mut update);
+
+        Some(EarlyUpdate {
+            update,
+            transaction,
+        })
+    }
+
+    /// Create a response to an UPDATE request received using [`receive_update`](Self::receive_update)
+    pub async fn create_update_response(
+        &self,
+        update: &IncomingRequest,
+        code: StatusCode,
+        reason: Option<BytesStr>,
+    ) -> Result<OutgoingResponse, Error> {
+        let state = self.inner.state.lock().await;
+
+        if let InviteSessionState::UasProvisional { dialog, .. } = &*state {
+            dialog
+                .create_response(update, code, reason)
+                .map_err(Error::Core)
+        } else {
+            Err(Error::RequestTerminated)
+        }
+    }
+
     pub fn peer_supports_100rel(&self) -> bool {
         self.inner.peer_supports_100rel
     }
@@ -219,8 +294,18 @@ impl InviteAcceptor {
             let mut prack = None;
             let mut delta = T1;
 
+            let cancellation = self.cancellation.clone().unwrap_or_default();
+
             for _ in 1..6 {
-                match timeout(delta, &mut prack_recv).await {
+                let received = tokio::select! {
+                    received = timeout(delta, &mut prack_recv) => received,
+                    _ = cancellation.cancelled() => {
+                        self.inner.awaited_prack.lock().take();
+                        return Err(Error::Cancelled);
+                    }
+                };
+
+                match received {
                     Ok(res) => {
                         // Unwrap is safe as no other function sets `awaiting_prack`
                         // which means the channel will not be dropped
@@ -245,6 +330,16 @@ impl InviteAcceptor {
         mut self,
         mut response: OutgoingResponse,
     ) -> Result<(InviteSession, IncomingRequest), Error> {
+        if self.is_cancelled() {
+            let response = self
+                .create_response(StatusCode::TEMPORARILY_UNAVAILABLE, None)
+                .await?;
+
+            self.respond_failure(response).await?;
+
+            return Err(Error::Cancelled);
+        }
+
         // Lock the state over the duration of the responding process and
         // while waiting for the ACK. This avoids handling of other
         // requests that assume a completed session.
@@ -297,6 +392,25 @@ impl InviteAcceptor {
         }
     }
 
+    /// Accept the INVITE right away with a `200 OK` carrying the given SDP answer
+    ///
+    /// Skips any provisional responses, useful for endpoints accepting many sessions in bulk like
+    /// announcement players or recording servers.
+    pub async fn accept_with_sdp(
+        self,
+        sdp: impl Into<Bytes>,
+    ) -> Result<(InviteSession, IncomingRequest), Error> {
+        let mut response = self.create_response(StatusCode::OK, None).await?;
+
+        response
+            .msg
+            .headers
+            .insert_named(&ContentType("application/sdp".into()));
+        response.msg.body = sdp.into();
+
+        self.respond_success(response).await
+    }
+
     pub async fn respond_failure(self, response: OutgoingResponse) -> Result<(), Error> {
         if let Some((_, transaction, _)) = self.inner.state.lock().await.set_cancelled() {
             transaction
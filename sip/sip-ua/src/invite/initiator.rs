@@ -11,15 +11,17 @@ use parking_lot as pl;
 use sip_core::transaction::{ClientInvTsx, TsxResponse};
 use sip_core::transport::OutgoingRequest;
 use sip_core::{Endpoint, Error, Request};
-use sip_types::header::typed::{Contact, RSeq, Refresher, Supported};
+use sip_types::header::typed::{Contact, RSeq, Refresher, Replaces, Require, Routing, Supported};
 use sip_types::header::HeaderError;
 use sip_types::uri::{NameAddr, SipUri};
 use sip_types::{Method, Name, StatusCode};
 use std::collections::HashMap;
 use std::future::poll_fn;
+use std::net::IpAddr;
 use std::sync::Arc;
 use std::task::{ready, Context, Poll};
 use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
 pub enum Response {
@@ -52,6 +54,22 @@ pub struct InviteInitiator {
     pub support_100rel: bool,
 
     pub timer_config: InitiatorTimerConfig,
+
+    /// Dialog to replace with the new session, e.g. to pick up a call ringing at another user agent
+    pub replaces: Option<Replaces>,
+
+    cancellation: Cancellation,
+    /// A CANCEL may only be sent after receiving a provisional response
+    provisional_received: bool,
+}
+
+#[derive(Debug)]
+enum Cancellation {
+    None,
+    Armed(CancellationToken),
+    /// The token was cancelled, but the CANCEL request was not sent yet
+    Requested,
+    Sent,
 }
 
 impl InviteInitiator {
@@ -75,9 +93,27 @@ impl InviteInitiator {
                 refresher: Refresher::Unspecified,
                 expires_secs_min: 90,
             },
+            replaces: None,
+            cancellation: Cancellation::None,
+            provisional_received: false,
         }
     }
 
+    /// Create an initiator which picks up the call of a dialog monitored using the dialog event package
+    ///
+    /// `target` must be the URI of the user agent the dialog belongs to.
+    pub fn pickup(
+        endpoint: Endpoint,
+        local_addr: NameAddr,
+        local_contact: Contact,
+        target: SipUri,
+        dialog_info: &DialogInfo,
+    ) -> Self {
+        let mut initiator = Self::new(endpoint, local_addr, local_contact, target);
+        initiator.replaces = Some(dialog_info.replaces());
+        initiator
+    }
+
     pub fn create_invite(&mut self) -> Request {
         let mut request = self.dialog_builder.create_request(Method::INVITE);
 
@@ -90,9 +126,52 @@ impl InviteInitiator {
             self.timer_config.populate_request(&mut request);
         }
 
+        if let Some(replaces) = &self.replaces {
+            request.headers.insert_named(replaces);
+            request
+                .headers
+                .insert_named(&Require(BytesStr::from_static("replaces")));
+        }
+
         request
     }
 
+    /// Send the INVITE via the given route set, e.g. a single `Route` to an outbound proxy
+    ///
+    /// The route set of the dialog is taken from the `Record-Route` headers of the response as usual.
+    pub fn set_route_set(&mut self, route_set: Vec<Routing>) {
+        self.dialog_builder.route_set = route_set;
+    }
+
+    /// Send the INVITE and all following requests of the call from the given local IP address
+    ///
+    /// Must be called before [`send_invite`](Self::send_invite). Selects a transport bound to the address, see
+    /// [`Endpoint::select_transport_from`].
+    pub async fn set_local_ip(&mut self, local_ip: IpAddr) -> Result<(), sip_core::Error> {
+        let transport = self
+            .dialog_builder
+            .endpoint
+            .select_transport_from(self.dialog_builder.next_hop(), local_ip)
+            .await?;
+
+        self.dialog_builder.target_tp_info.transport = Some(transport);
+
+        Ok(())
+    }
+
+    /// Resolve the target and select the transport used to send the INVITE, e.g. concurrently to creating the SDP
+    /// offer
+    ///
+    /// Otherwise this is done by [`send_invite`](Self::send_invite), see [`Endpoint::resolve_target`].
+    pub async fn resolve(&mut self) -> Result<(), sip_core::Error> {
+        let next_hop = self.dialog_builder.next_hop().clone();
+
+        self.dialog_builder
+            .endpoint
+            .resolve_target(&next_hop, &mut self.dialog_builder.target_tp_info)
+            .await
+    }
+
     pub async fn send_invite(&mut self, request: Request) -> Result<(), sip_core::Error> {
         let transaction = self
             .dialog_builder
@@ -105,12 +184,28 @@ impl InviteInitiator {
         Ok(())
     }
 
+    /// Send the following INVITE to the next server resolved for the target, after the current one didn't respond
+    /// or responded with `503 Service Unavailable`
+    ///
+    /// Returns `false` if there is none left, see [`Endpoint::failover`].
+    pub async fn failover(&mut self) -> bool {
+        self.dialog_builder
+            .endpoint
+            .failover(&mut self.dialog_builder.target_tp_info)
+            .await
+    }
+
     pub async fn cancel(mut self) -> Result<(), sip_core::Error> {
         let request = self.dialog_builder.create_request(Method::CANCEL);
 
+        let transaction = self
+            .transaction
+            .as_ref()
+            .expect("must send invite before calling cancel");
+
         self.dialog_builder
             .endpoint
-            .send_request(request, &mut self.dialog_builder.target_tp_info)
+            .send_cancel(request, transaction)
             .await?
             .receive_final()
             .await?;
@@ -131,6 +226,44 @@ impl InviteInitiator {
         }
     }
 
+    /// Cancel the INVITE once the token is cancelled, e.g. when an application level timeout expired
+    ///
+    /// The cancellation is handled while calling [`receive`](Self::receive), which sends a CANCEL request as soon
+    /// as a provisional response has been received and then keeps returning the INVITE's responses. Usually the
+    /// peer responds with `487 Request Terminated`, but if it accepted the call before receiving the CANCEL a
+    /// session is still returned, which must be terminated by the application.
+    ///
+    /// Use [`cancel_at`](crate::util::cancel_at) to cancel the INVITE at a deadline.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = Cancellation::Armed(token);
+    }
+
+    async fn send_cancel(&mut self) -> Result<(), Error> {
+        let request = self.dialog_builder.create_request(Method::CANCEL);
+
+        let invite = self
+            .transaction
+            .as_ref()
+            .expect("must send invite before calling receive");
+
+        let mut transaction = self
+            .dialog_builder
+            .endpoint
+            .send_cancel(request, invite)
+            .await?;
+
+        self.cancellation = Cancellation::Sent;
+
+        // The outcome is reported through the INVITE's final response, only keep the transaction alive
+        tokio::spawn(async move {
+            if let Err(e) = transaction.receive_final().await {
+                log::warn!("Failed to receive response to CANCEL request, {e}");
+            }
+        });
+
+        Ok(())
+    }
+
     pub fn transaction(&self) -> Option<&ClientInvTsx> {
         self.transaction.as_ref()
     }
@@ -149,19 +282,38 @@ impl InviteInitiator {
     }
 
     pub async fn receive(&mut self) -> Result<Response, Error> {
-        let transaction = self
-            .transaction
-            .as_mut()
-            .expect("must send invite before calling receive");
-
         loop {
-            let response = match transaction.receive().await? {
-                Some(response) => response,
-                None => return Ok(Response::Finished),
+            if matches!(self.cancellation, Cancellation::Requested) && self.provisional_received {
+                self.send_cancel().await?;
+            }
+
+            let transaction = self
+                .transaction
+                .as_mut()
+                .expect("must send invite before calling receive");
+
+            let response = if let Cancellation::Armed(token) = &self.cancellation {
+                tokio::select! {
+                    response = transaction.receive() => response?,
+                    _ = token.cancelled() => {
+                        self.cancellation = Cancellation::Requested;
+                        continue;
+                    }
+                }
+            } else {
+                transaction.receive().await?
+            };
+
+            let Some(response) = response else {
+                return Ok(Response::Finished);
             };
 
             let code = response.line.code.into_u16();
 
+            if code < 200 {
+                self.provisional_received = true;
+            }
+
             if code <= 100 {
                 // 100 Trying, cannot create dialog - just return
                 return Ok(Response::Provisional(response));
@@ -181,6 +333,11 @@ impl InviteInitiator {
 
             // Verify that there's a to-tag set
             let Some(to_tag) = response.base_headers.to.tag.as_ref() else {
+                if code < 200 {
+                    // Provisional responses without To-tag cannot create an early dialog
+                    return Ok(Response::Provisional(response));
+                }
+
                 log::warn!("Cannot handle success response without To-tag, ignoring");
                 continue;
             };
@@ -255,11 +412,13 @@ impl InviteInitiator {
 
         let peer_supports_timer = supported.iter().any(|ext| ext.0 == "timer");
         let peer_supports_100rel = supported.iter().any(|ext| ext.0 == "100rel");
+        let peer_allows_update = super::allows_update(&response.headers);
 
         let inner = Arc::new(Inner {
             state: Mutex::new(InviteSessionState::Established { evt_sink }),
             peer_supports_timer,
             peer_supports_100rel,
+            peer_allows_update,
             awaited_ack: pl::Mutex::new(None),
             awaited_prack: pl::Mutex::new(None),
         });
@@ -288,6 +447,34 @@ enum EarlyEvent {
     Terminate,
 }
 
+/// Dialog of another user agent as reported by the dialog event package
+/// ([RFC4235](https://datatracker.ietf.org/doc/html/rfc4235)), e.g. when monitoring it using BLF
+#[derive(Debug, Clone)]
+pub struct DialogInfo {
+    /// `call-id` attribute of the `<dialog>` element
+    pub call_id: BytesStr,
+    /// `local-tag` attribute, the tag of the monitored user agent
+    pub local_tag: BytesStr,
+    /// `remote-tag` attribute, the tag of the monitored user agent's peer
+    pub remote_tag: BytesStr,
+    /// The dialog is still in the `early` state (ringing)
+    pub early: bool,
+}
+
+impl DialogInfo {
+    /// Create the `Replaces` header matching this dialog at the monitored user agent
+    ///
+    /// Early dialogs are only replaced while they are still ringing.
+    pub fn replaces(&self) -> Replaces {
+        Replaces {
+            call_id: self.call_id.clone(),
+            from_tag: self.remote_tag.clone(),
+            to_tag: self.local_tag.clone(),
+            early_only: self.early,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Early {
     endpoint: Endpoint,
@@ -326,11 +513,13 @@ impl Early {
 
                     let peer_supports_timer = supported.iter().any(|ext| ext.0 == "timer");
                     let peer_supports_100rel = supported.iter().any(|ext| ext.0 == "100rel");
+                    let peer_allows_update = super::allows_update(&response.headers);
 
                     let inner = Arc::new(Inner {
                         state: Mutex::new(InviteSessionState::Established { evt_sink }),
                         peer_supports_timer,
                         peer_supports_100rel,
+                        peer_allows_update,
                         awaited_ack: pl::Mutex::new(None),
                         awaited_prack: pl::Mutex::new(None),
                     });
@@ -363,6 +552,36 @@ impl Early {
         poll_fn(|cx| self.poll_receive(cx)).await
     }
 
+    /// Create an UPDATE request in the early dialog (RFC 3311), e.g. to change the early media
+    ///
+    /// An UPDATE may only carry a new SDP offer once the offer of the INVITE was answered, usually in a reliable
+    /// provisional response.
+    pub fn create_update(&self) -> Request {
+        self.dialog
+            .as_ref()
+            .expect("early dialog was not confirmed")
+            .create_request(Method::UPDATE)
+    }
+
+    /// Send an UPDATE request created using [`create_update`](Self::create_update), returns the final response
+    pub async fn send_update(&mut self, request: Request) -> Result<TsxResponse, Error> {
+        let dialog = self
+            .dialog
+            .as_ref()
+            .expect("early dialog was not confirmed");
+
+        let mut target_tp_info = dialog.target_tp_info.lock().await;
+
+        let mut transaction = self
+            .endpoint
+            .send_request(request, &mut target_tp_info)
+            .await?;
+
+        drop(target_tp_info);
+
+        transaction.receive_final().await
+    }
+
     pub async fn cancel(mut self) -> Result<(), Error> {
         let dialog = self.dialog.as_mut().unwrap();
 
@@ -392,3 +611,288 @@ impl Early {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dialog::DialogLayer;
+    use crate::invite::acceptor::InviteAcceptor;
+    use crate::invite::{create_ack, InviteLayer};
+    use sip_core::transport::udp::Udp;
+    use sip_core::{EndpointBuilder, IncomingRequest, Layer, MayTake};
+    use sip_types::header::typed::CSeq;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    /// Passes INVITE requests received outside of a dialog to the test
+    struct Invites(mpsc::UnboundedSender<IncomingRequest>);
+
+    #[async_trait::async_trait]
+    impl Layer for Invites {
+        fn name(&self) -> &'static str {
+            "test-invites"
+        }
+
+        async fn receive(&self, _: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+            if request.line.method == Method::INVITE && request.base_headers.to.tag.is_none() {
+                let _ = self.0.send(request.take());
+            }
+        }
+    }
+
+    /// Reports the branch and CSeq number of CANCEL requests, without handling them
+    struct Cancels(mpsc::UnboundedSender<(BytesStr, u32)>);
+
+    #[async_trait::async_trait]
+    impl Layer for Cancels {
+        fn name(&self) -> &'static str {
+            "test-cancels"
+        }
+
+        async fn receive(&self, _: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+            if request.line.method == Method::CANCEL {
+                let _ = self.0.send((
+                    request.tsx_key.branch().clone(),
+                    request.base_headers.cseq.cseq,
+                ));
+            }
+        }
+    }
+
+    async fn bind(builder: &mut EndpointBuilder) -> SipUri {
+        let udp = Udp::spawn(builder, (Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+
+        format!("sip:{}", udp.bound()).parse().unwrap()
+    }
+
+    /// Endpoint receiving the INVITE requests, controlled by the test
+    struct Peer {
+        endpoint: Endpoint,
+        uri: SipUri,
+        invites: mpsc::UnboundedReceiver<IncomingRequest>,
+        cancels: mpsc::UnboundedReceiver<(BytesStr, u32)>,
+    }
+
+    impl Peer {
+        async fn receive_invite(&mut self) -> IncomingRequest {
+            timeout(Duration::from_secs(5), self.invites.recv())
+                .await
+                .expect("timed out waiting for INVITE")
+                .unwrap()
+        }
+
+        fn acceptor(&self, invite: IncomingRequest) -> InviteAcceptor {
+            let contact = Contact::new(NameAddr::uri(self.uri.clone()));
+            let dialog = Dialog::new_server(self.endpoint.clone(), &invite, contact).unwrap();
+
+            InviteAcceptor::new(dialog, invite)
+        }
+    }
+
+    /// Create a peer and an initiator which sent an INVITE to it
+    async fn setup() -> (Peer, InviteInitiator) {
+        let (invites, invites_rx) = mpsc::unbounded_channel();
+        let (cancels, cancels_rx) = mpsc::unbounded_channel();
+
+        let mut builder = Endpoint::builder();
+        builder.add_layer(Cancels(cancels));
+        builder.add_layer(DialogLayer::default());
+        builder.add_layer(InviteLayer::default());
+        builder.add_layer(Invites(invites));
+        let peer_uri = bind(&mut builder).await;
+
+        let peer = Peer {
+            endpoint: builder.build(),
+            uri: peer_uri.clone(),
+            invites: invites_rx,
+            cancels: cancels_rx,
+        };
+
+        let mut builder = Endpoint::builder();
+        builder.add_layer(DialogLayer::default());
+        builder.add_layer(InviteLayer::default());
+        let uri = bind(&mut builder).await;
+        let endpoint = builder.build();
+
+        let mut initiator = InviteInitiator::new(
+            endpoint,
+            NameAddr::uri("sip:alice@example.org".parse::<SipUri>().unwrap()),
+            Contact::new(NameAddr::uri(uri)),
+            peer_uri,
+        );
+
+        let invite = initiator.create_invite();
+        initiator.send_invite(invite).await.unwrap();
+
+        (peer, initiator)
+    }
+
+    async fn receive(initiator: &mut InviteInitiator) -> Response {
+        timeout(Duration::from_secs(5), initiator.receive())
+            .await
+            .expect("timed out waiting for response")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cancellation_after_ringing() {
+        let (mut peer, mut initiator) = setup().await;
+
+        let token = CancellationToken::new();
+        initiator.set_cancellation(token.clone());
+
+        let invite = peer.receive_invite().await;
+        let invite_branch = invite.tsx_key.branch().clone();
+        let invite_cseq = invite.base_headers.cseq.cseq;
+
+        let mut acceptor = peer.acceptor(invite);
+        let ringing = acceptor
+            .create_response(StatusCode::RINGING, None)
+            .await
+            .unwrap();
+        acceptor.respond_provisional(ringing).await.unwrap();
+
+        loop {
+            match receive(&mut initiator).await {
+                Response::Provisional(response) | Response::Early(_, response, _)
+                    if response.line.code == StatusCode::RINGING =>
+                {
+                    break
+                }
+                Response::Provisional(_) => {}
+                response => panic!("expected provisional response, got {response:?}"),
+            }
+        }
+
+        token.cancel();
+
+        // The CANCEL is part of the INVITE transaction, the peer matches it and terminates the INVITE
+        let Response::Failure(response) = receive(&mut initiator).await else {
+            panic!("expected failure response");
+        };
+        assert_eq!(response.line.code, StatusCode::REQUEST_TERMINATED);
+
+        let (cancel_branch, cancel_cseq) = peer.cancels.recv().await.unwrap();
+        assert_eq!(cancel_branch, invite_branch);
+        assert_eq!(cancel_cseq, invite_cseq);
+
+        timeout(Duration::from_secs(1), acceptor.cancelled())
+            .await
+            .expect("acceptor must be cancelled");
+    }
+
+    #[tokio::test]
+    async fn ack_uses_invite_cseq() {
+        let (mut peer, mut initiator) = setup().await;
+
+        let invite = peer.receive_invite().await;
+        let invite_cseq = invite.base_headers.cseq.cseq;
+
+        let acceptor = peer.acceptor(invite);
+        let ok = acceptor
+            .create_response(StatusCode::OK, None)
+            .await
+            .unwrap();
+        let accepted = tokio::spawn(acceptor.respond_success(ok));
+
+        let session = loop {
+            match receive(&mut initiator).await {
+                Response::Provisional(_) => {}
+                Response::Session(session, _) => break session,
+                response => panic!("expected session, got {response:?}"),
+            }
+        };
+
+        let mut ack = create_ack(&session.dialog, invite_cseq).await.unwrap();
+        session
+            .endpoint
+            .send_outgoing_request(&mut ack)
+            .await
+            .unwrap();
+
+        let (_peer_session, ack) = accepted.await.unwrap().unwrap();
+        assert_eq!(ack.base_headers.cseq.cseq, invite_cseq);
+        assert_eq!(ack.base_headers.cseq.method, Method::ACK);
+
+        // The ACK doesn't consume a CSeq number, the following request uses the next one
+        let reinvite = session.dialog.create_request(Method::INVITE);
+        let reinvite_cseq: CSeq = reinvite.headers.get_named().unwrap();
+        assert_eq!(reinvite_cseq.cseq, invite_cseq + 1);
+    }
+
+    #[tokio::test]
+    async fn provisional_without_to_tag() {
+        let (mut peer, mut initiator) = setup().await;
+
+        let mut invite = peer.receive_invite().await;
+        let mut transaction = peer.endpoint.create_server_inv_tsx(&mut invite);
+
+        for code in [StatusCode::TRYING, StatusCode::RINGING] {
+            let mut response = peer.endpoint.create_response(&invite, code, None);
+            response
+                .msg
+                .headers
+                .insert_named(&Contact::new(NameAddr::uri(peer.uri.clone())));
+            transaction
+                .respond_provisional(&mut response)
+                .await
+                .unwrap();
+
+            let Response::Provisional(response) = receive(&mut initiator).await else {
+                panic!("expected provisional response");
+            };
+            assert_eq!(response.line.code, code);
+            assert!(response.base_headers.to.tag.is_none());
+        }
+
+        assert!(initiator.early_list.is_empty());
+    }
+
+    fn monitored_dialog(early: bool) -> DialogInfo {
+        DialogInfo {
+            call_id: BytesStr::from_static("monitored-call"),
+            local_tag: BytesStr::from_static("monitored-tag"),
+            remote_tag: BytesStr::from_static("peer-tag"),
+            early,
+        }
+    }
+
+    #[test]
+    fn dialog_info_replaces() {
+        // The monitored user agent's tag is the To tag of its dialog as seen by the picking up user agent
+        assert_eq!(
+            monitored_dialog(true).replaces(),
+            Replaces {
+                call_id: BytesStr::from_static("monitored-call"),
+                from_tag: BytesStr::from_static("peer-tag"),
+                to_tag: BytesStr::from_static("monitored-tag"),
+                early_only: true,
+            }
+        );
+
+        assert!(!monitored_dialog(false).replaces().early_only);
+    }
+
+    #[tokio::test]
+    async fn pickup_invite_requires_replaces() {
+        let mut initiator = InviteInitiator::pickup(
+            Endpoint::builder().build(),
+            NameAddr::uri("sip:carol@example.org".parse::<SipUri>().unwrap()),
+            Contact::new(NameAddr::uri(
+                "sip:carol@127.0.0.1".parse::<SipUri>().unwrap(),
+            )),
+            "sip:alice@example.org".parse().unwrap(),
+            &monitored_dialog(true),
+        );
+
+        let invite = initiator.create_invite();
+
+        assert_eq!(
+            invite.headers.get_named::<Replaces>().unwrap(),
+            monitored_dialog(true).replaces()
+        );
+        let require: Vec<Require> = invite.headers.get_named().unwrap();
+        assert!(require.iter().any(|require| require.0 == "replaces"));
+    }
+}
@@ -7,10 +7,11 @@ use sip_core::transaction::consts::{T1, T2};
 use sip_core::transaction::{Accepted, ServerInvTsx, TsxKey};
 use sip_core::transport::OutgoingRequest;
 use sip_core::{Endpoint, EndpointBuilder, Error, IncomingRequest, Layer, MayTake, Result};
-use sip_types::header::typed::CSeq;
-use sip_types::{Method, StatusCode};
+use sip_types::header::typed::Allow;
+use sip_types::{Headers, Method, StatusCode};
 use std::collections::HashMap;
 use std::mem::replace;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::{mpsc, oneshot, Mutex, Notify};
@@ -19,6 +20,7 @@ use tokio::time::timeout;
 pub mod acceptor;
 pub mod initiator;
 pub mod prack;
+pub mod refer;
 pub mod session;
 mod timer;
 
@@ -36,6 +38,7 @@ struct Inner {
 
     peer_supports_timer: bool,
     peer_supports_100rel: bool,
+    peer_allows_update: bool,
 
     awaited_ack: pl::Mutex<Option<AwaitedAck>>,
     awaited_prack: pl::Mutex<Option<AwaitedPrack>>,
@@ -50,6 +53,8 @@ enum InviteSessionState {
         tsx: ServerInvTsx,
         invite: IncomingRequest,
         cancelled_notify: Arc<Notify>,
+        /// Forwards UPDATE requests received in the early dialog to the acceptor
+        update_sink: mpsc::Sender<IncomingRequest>,
     },
 
     /// Cancelled: A CANCEL Request for the invite has been received
@@ -77,6 +82,7 @@ impl InviteSessionState {
                 tsx,
                 invite,
                 cancelled_notify,
+                ..
             } = replace(self, InviteSessionState::Cancelled)
             {
                 cancelled_notify.notify_one();
@@ -100,7 +106,7 @@ impl InviteSessionState {
                 dialog,
                 tsx,
                 invite,
-                cancelled_notify: _,
+                ..
             } = replace(self, InviteSessionState::Established { evt_sink })
             {
                 Some((dialog, tsx, invite))
@@ -136,6 +142,9 @@ impl Layer for InviteLayer {
         endpoint.add_allow(Method::ACK);
         endpoint.add_allow(Method::CANCEL);
         endpoint.add_allow(Method::PRACK);
+        endpoint.add_allow(Method::REFER);
+        endpoint.add_allow(Method::NOTIFY);
+        endpoint.add_allow(Method::INFO);
 
         endpoint.add_supported("100rel");
         endpoint.add_supported("timer");
@@ -228,6 +237,68 @@ impl Usage for InviteUsage {
                     }
                 }
             }
+            Method::REFER => {
+                let state = self.inner.state.lock().await;
+
+                if let InviteSessionState::Established { evt_sink } = &*state {
+                    let refer = request.inner().take().unwrap();
+
+                    if let Err(SendError(UsageEvent::Refer(refer))) =
+                        evt_sink.send(UsageEvent::Refer(refer)).await
+                    {
+                        *request.inner() = Some(refer);
+                    }
+                }
+            }
+            Method::NOTIFY if refer::is_refer_event(&request) => {
+                let state = self.inner.state.lock().await;
+
+                if let InviteSessionState::Established { evt_sink } = &*state {
+                    let notify = request.inner().take().unwrap();
+
+                    if let Err(SendError(UsageEvent::Notify(notify))) =
+                        evt_sink.send(UsageEvent::Notify(notify)).await
+                    {
+                        *request.inner() = Some(notify);
+                    }
+                }
+            }
+            Method::INFO => {
+                let state = self.inner.state.lock().await;
+
+                if let InviteSessionState::Established { evt_sink } = &*state {
+                    let info = request.inner().take().unwrap();
+
+                    if let Err(SendError(UsageEvent::Info(info))) =
+                        evt_sink.send(UsageEvent::Info(info)).await
+                    {
+                        *request.inner() = Some(info);
+                    }
+                }
+            }
+            Method::UPDATE => {
+                let state = self.inner.state.lock().await;
+
+                match &*state {
+                    InviteSessionState::UasProvisional { update_sink, .. } => {
+                        let update = request.inner().take().unwrap();
+
+                        if let Err(SendError(update)) = update_sink.send(update).await {
+                            *request.inner() = Some(update);
+                        }
+                    }
+                    InviteSessionState::Established { evt_sink } => {
+                        let update = request.inner().take().unwrap();
+
+                        if let Err(SendError(UsageEvent::Update(update))) =
+                            evt_sink.send(UsageEvent::Update(update)).await
+                        {
+                            *request.inner() = Some(update);
+                        }
+                    }
+                    InviteSessionState::Cancelled | InviteSessionState::Terminated => {}
+                }
+            }
             Method::ACK => {
                 let mut awaited_ack_opt = self.inner.awaited_ack.lock();
 
@@ -254,6 +325,7 @@ impl Usage for InviteUsage {
                         tsx,
                         invite,
                         cancelled_notify,
+                        ..
                     } => {
                         cancelled_notify.notify_one();
                         if let Err(e) = self
@@ -325,11 +397,11 @@ impl InviteUsage {
 }
 
 pub async fn create_ack(dialog: &Dialog, cseq_num: u32) -> Result<OutgoingRequest> {
-    let mut ack = dialog.create_request(Method::ACK);
+    let ack = dialog.create_request_with_cseq(Method::ACK, cseq_num);
 
-    // Set CSeq
-    ack.headers
-        .edit_named(|cseq: &mut CSeq| cseq.cseq = cseq_num)?;
+    // The ACK shares the CSeq number of the INVITE, requests following it must use a higher one without
+    // skipping any, as the peer waits for missing CSeq numbers before handling a request
+    dialog.local_cseq.fetch_max(cseq_num + 1, Ordering::Relaxed);
 
     let mut target_tp_info = dialog.target_tp_info.lock().await;
 
@@ -353,6 +425,15 @@ pub async fn create_ack(dialog: &Dialog, cseq_num: u32) -> Result<OutgoingReques
     Ok(ack)
 }
 
+/// Returns if UPDATE is listed in the `Allow` header of the peer's INVITE or response
+fn allows_update(headers: &Headers) -> bool {
+    headers
+        .get_named::<Vec<Allow>>()
+        .unwrap_or_default()
+        .iter()
+        .any(|allow| allow.0 == Method::UPDATE)
+}
+
 /// Helper function to receive the ACK response from invite-usage
 /// after sending a success-response
 async fn receive_ack(
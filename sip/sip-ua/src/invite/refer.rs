@@ -0,0 +1,98 @@
+//! Implicit `refer` event subscription of REFER requests received inside an INVITE session
+//!
+//! [RFC3515](https://datatracker.ietf.org/doc/html/rfc3515)
+
+use crate::dialog::Dialog;
+use sip_core::{IncomingRequest, Request, Result};
+use sip_types::header::typed::{
+    ContentType, Event, EventReasonValue, SubStateValue, SubscriptionState,
+};
+use sip_types::{CodeKind, Method, StatusCode};
+use std::sync::Arc;
+
+const SIPFRAG: &str = "message/sipfrag;version=2.0";
+
+/// Subscription created by accepting a REFER request, used to report the progress of the referenced request to the
+/// REFER's sender
+#[derive(Debug, Clone)]
+pub struct ReferSubscription {
+    dialog: Arc<Dialog>,
+    /// CSeq of the REFER request, used as `id` of the `Event` header
+    id: u32,
+}
+
+impl ReferSubscription {
+    pub(super) fn new(dialog: Arc<Dialog>, refer: &IncomingRequest) -> Self {
+        Self {
+            dialog,
+            id: refer.base_headers.cseq.cseq,
+        }
+    }
+
+    /// Create a NOTIFY request containing the status of the referenced request
+    ///
+    /// The subscription is terminated with a final status, no more NOTIFY requests may be sent afterwards.
+    pub fn create_notify(&self, status: StatusCode) -> Request {
+        let mut request = self.dialog.create_request(Method::NOTIFY);
+
+        let state = if status.kind() == CodeKind::Provisional {
+            SubscriptionState::new(SubStateValue::Active)
+        } else {
+            SubscriptionState::new(SubStateValue::Terminated)
+                .with_reason(EventReasonValue::NoResource)
+        };
+
+        request
+            .headers
+            .insert_named(&Event::new(format!("refer;id={}", self.id)));
+        request.headers.insert_named(&state);
+        request.headers.insert_named(&ContentType(SIPFRAG.into()));
+
+        request.body = format!(
+            "SIP/2.0 {} {}\r\n",
+            status.into_u16(),
+            status.text().unwrap_or_default()
+        )
+        .into();
+
+        request
+    }
+
+    /// Create and send a NOTIFY request containing the status of the referenced request
+    pub async fn notify(&self, status: StatusCode) -> Result<()> {
+        let request = self.create_notify(status);
+
+        let mut target_tp_info = self.dialog.target_tp_info.lock().await;
+        let mut transaction = self
+            .dialog
+            .endpoint
+            .send_request(request, &mut target_tp_info)
+            .await?;
+        drop(target_tp_info);
+
+        transaction.receive_final().await?;
+
+        Ok(())
+    }
+}
+
+/// Returns if the request belongs to the `refer` event package
+pub(super) fn is_refer_event(request: &IncomingRequest) -> bool {
+    request
+        .headers
+        .get_named::<Event>()
+        .is_ok_and(|event| event.0.split(';').next().map(str::trim) == Some("refer"))
+}
+
+/// Parse the status line of a `message/sipfrag` body, e.g. `SIP/2.0 200 OK`
+pub(super) fn parse_sipfrag(body: &[u8]) -> Option<StatusCode> {
+    let body = std::str::from_utf8(body).ok()?;
+
+    let mut status_line = body.lines().next()?.split_whitespace();
+
+    if status_line.next()? != "SIP/2.0" {
+        return None;
+    }
+
+    status_line.next()?.parse().ok()
+}
@@ -1,16 +1,18 @@
-use super::timer::SessionTimer;
-use super::Inner;
-use crate::dialog::{Dialog, UsageGuard};
+use super::refer::{self, ReferSubscription};
+use super::timer::{self, SessionTimer};
+use super::{Inner, InviteSessionState, InviteUsage};
+use crate::dialog::{Dialog, DialogSnapshot, UsageGuard};
 use crate::invite::AwaitedAck;
+use parking_lot as pl;
 use sip_core::transaction::{ServerInvTsx, ServerTsx, TsxResponse};
 use sip_core::transport::OutgoingResponse;
-use sip_core::{Endpoint, IncomingRequest, Result};
-use sip_types::header::typed::Refresher;
+use sip_core::{Endpoint, IncomingRequest, Request, Result};
+use sip_types::header::typed::{ReferTo, Refresher, SubStateValue, SubscriptionState};
 use sip_types::{CodeKind, Method, StatusCode};
 use std::sync::Arc;
 use tokio::select;
 use tokio::sync::mpsc::{self, Receiver};
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, Mutex};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Role {
@@ -95,6 +97,21 @@ impl RefreshNeeded<'_> {
 
         Ok(())
     }
+
+    /// Send an UPDATE request without body refreshing the INVITE session, for peers which prefer it over a
+    /// re-INVITE (RFC 4028 section 7.4)
+    pub async fn process_update(self) -> Result<(), SessionRefreshError> {
+        let mut update = self.session.create_update();
+        self.session.session_timer.populate_refresh(&mut update);
+
+        let response = self.session.send_update(update).await?;
+
+        if response.line.code.kind() != CodeKind::Success {
+            return Err(SessionRefreshError::UnexpectedStatus(response.line.code));
+        }
+
+        Ok(())
+    }
 }
 
 pub struct ReInviteReceived<'s> {
@@ -105,7 +122,9 @@ pub struct ReInviteReceived<'s> {
 
 impl ReInviteReceived<'_> {
     /// Respond with a successful response, returns the received ACK request
-    pub async fn respond_success(self, response: OutgoingResponse) -> Result<IncomingRequest> {
+    pub async fn respond_success(self, mut response: OutgoingResponse) -> Result<IncomingRequest> {
+        timer::populate_refresh_response(&self.invite, &mut response);
+
         let (ack_sender, ack_recv) = oneshot::channel();
 
         *self.session.inner.awaited_ack.lock() = Some(AwaitedAck {
@@ -137,11 +156,145 @@ impl ByeEvent<'_> {
     }
 }
 
+/// UPDATE request received inside the session (RFC 3311)
+///
+/// Contains a new SDP offer if it has a body, otherwise it only refreshes the session. Receiving it resets the
+/// session timer.
+pub struct UpdateReceived<'s> {
+    pub session: &'s mut InviteSession,
+    pub update: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
+impl UpdateReceived<'_> {
+    /// Respond with the given response, e.g. a `200 OK` containing the SDP answer
+    pub async fn respond(self, mut response: OutgoingResponse) -> Result<()> {
+        timer::populate_refresh_response(&self.update, &mut response);
+
+        self.transaction.respond(response).await
+    }
+
+    /// Process the UPDATE as one would expect of an UPDATE without body, respond with a 200 OK
+    pub async fn process_default(self) -> Result<()> {
+        let response = self
+            .session
+            .dialog
+            .create_response(&self.update, StatusCode::OK, None)?;
+
+        self.respond(response).await
+    }
+}
+
+/// REFER request received inside the session, asking to send a request (usually an INVITE) to the `Refer-To` URI
+pub struct ReferReceived<'s> {
+    pub session: &'s mut InviteSession,
+    pub refer: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
+impl ReferReceived<'_> {
+    /// Parse the `Refer-To` header of the REFER request
+    pub fn refer_to(&self) -> Result<ReferTo> {
+        Ok(self.refer.headers.get_named()?)
+    }
+
+    /// Accept the REFER with a `202 Accepted` response
+    ///
+    /// The progress of the referenced request must be reported using the returned subscription, starting with a
+    /// NOTIFY request sent right away.
+    pub async fn accept(self) -> Result<ReferSubscription> {
+        let response =
+            self.session
+                .dialog
+                .create_response(&self.refer, StatusCode::ACCEPTED, None)?;
+
+        self.transaction.respond(response).await?;
+
+        Ok(ReferSubscription::new(
+            self.session.dialog.clone(),
+            &self.refer,
+        ))
+    }
+
+    /// Reject the REFER with the given final status code
+    pub async fn reject(self, code: StatusCode) -> Result<()> {
+        let response = self
+            .session
+            .dialog
+            .create_response(&self.refer, code, None)?;
+
+        self.transaction.respond(response).await
+    }
+}
+
+/// NOTIFY request of the `refer` event package received inside the session, reporting the progress of a REFER
+/// request sent in it
+pub struct NotifyReceived<'s> {
+    pub session: &'s mut InviteSession,
+    pub notify: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
+impl NotifyReceived<'_> {
+    /// Status code of the referenced request, parsed from the `message/sipfrag` body
+    pub fn status(&self) -> Option<StatusCode> {
+        refer::parse_sipfrag(&self.notify.body)
+    }
+
+    /// Returns if this is the last NOTIFY of the subscription
+    pub fn is_terminated(&self) -> bool {
+        self.notify
+            .headers
+            .get_named::<SubscriptionState>()
+            .is_ok_and(|state| state.state == SubStateValue::Terminated)
+    }
+
+    /// Process the NOTIFY as one would expect, respond with a 200 OK
+    pub async fn process_default(self) -> Result<()> {
+        let response = self
+            .session
+            .dialog
+            .create_response(&self.notify, StatusCode::OK, None)?;
+
+        self.transaction.respond(response).await
+    }
+}
+
+/// INFO request received inside the session, carrying application data like DTMF digits (RFC 6086)
+pub struct InfoReceived<'s> {
+    pub session: &'s mut InviteSession,
+    pub info: IncomingRequest,
+    pub transaction: ServerTsx,
+}
+
+impl InfoReceived<'_> {
+    /// Respond to the INFO with the given final status code, e.g. `415 Unsupported Media Type` for unknown bodies
+    pub async fn respond(self, code: StatusCode) -> Result<()> {
+        let response = self
+            .session
+            .dialog
+            .create_response(&self.info, code, None)?;
+
+        self.transaction.respond(response).await
+    }
+
+    /// Process the INFO as one would expect, respond with a 200 OK
+    pub async fn process_default(self) -> Result<()> {
+        self.respond(StatusCode::OK).await
+    }
+}
+
 #[allow(clippy::large_enum_variant)] // TODO address this
 pub enum InviteSessionEvent<'s> {
     RefreshNeeded(RefreshNeeded<'s>),
     ReInviteReceived(ReInviteReceived<'s>),
+    UpdateReceived(UpdateReceived<'s>),
+    ReferReceived(ReferReceived<'s>),
+    NotifyReceived(NotifyReceived<'s>),
+    InfoReceived(InfoReceived<'s>),
     Bye(ByeEvent<'s>),
+    /// The peer didn't refresh the session in time, a BYE was sent to terminate it (RFC 4028 section 10)
+    SessionExpired,
     Terminated,
 }
 
@@ -166,6 +319,53 @@ impl InviteSession {
         }
     }
 
+    /// Capture the state of the session's dialog and session timer, to be able to [`restore`](Self::restore) it
+    /// later
+    pub fn snapshot(&self) -> DialogSnapshot {
+        let mut snapshot = self.dialog.snapshot();
+        snapshot.session_expires = self.session_timer.session_expires();
+        snapshot
+    }
+
+    /// Resume an established session from a snapshot taken using [`InviteSession::snapshot`]
+    ///
+    /// The dialog is restored using [`Dialog::restore`]. The time that passed since the snapshot was taken is
+    /// unknown, so if this side is the refresher the session is refreshed right away, otherwise the peer has the
+    /// whole session interval to refresh it.
+    pub fn restore(endpoint: Endpoint, snapshot: DialogSnapshot, role: Role) -> Self {
+        let session_timer = match snapshot.session_expires {
+            Some(session_expires) => SessionTimer::restore(session_expires, role),
+            None => SessionTimer::new_unsupported(),
+        };
+
+        let dialog = Dialog::restore(endpoint, snapshot);
+
+        let (evt_sink, usage_events) = mpsc::channel(4);
+
+        let inner = Arc::new(Inner {
+            state: Mutex::new(InviteSessionState::Established { evt_sink }),
+            peer_supports_timer: session_timer.session_expires().is_some(),
+            peer_supports_100rel: false,
+            peer_allows_update: false,
+            awaited_ack: pl::Mutex::new(None),
+            awaited_prack: pl::Mutex::new(None),
+        });
+
+        let usage_guard = dialog.register_usage(InviteUsage {
+            inner: inner.clone(),
+        });
+
+        Self::new(
+            dialog.endpoint.clone(),
+            inner,
+            role,
+            usage_events,
+            session_timer,
+            usage_guard,
+            dialog,
+        )
+    }
+
     pub async fn drive(&mut self) -> Result<InviteSessionEvent<'_>> {
         select! {
             _ = self.session_timer.wait() => {
@@ -177,6 +377,38 @@ impl InviteSession {
         }
     }
 
+    /// Returns if the peer listed UPDATE in the `Allow` header of the INVITE or its response
+    pub fn peer_allows_update(&self) -> bool {
+        self.inner.peer_allows_update
+    }
+
+    /// Create an UPDATE request (RFC 3311), e.g. to send a new SDP offer without a re-INVITE
+    pub fn create_update(&self) -> Request {
+        self.dialog.create_request(Method::UPDATE)
+    }
+
+    /// Send an UPDATE request created using [`create_update`](Self::create_update), returns the final response
+    ///
+    /// A successful response resets the session timer.
+    pub async fn send_update(&mut self, request: Request) -> Result<TsxResponse> {
+        let mut target_tp_info = self.dialog.target_tp_info.lock().await;
+
+        let mut transaction = self
+            .endpoint
+            .send_request(request, &mut target_tp_info)
+            .await?;
+
+        drop(target_tp_info);
+
+        let response = transaction.receive_final().await?;
+
+        if response.line.code.kind() == CodeKind::Success {
+            self.session_timer.reset();
+        }
+
+        Ok(response)
+    }
+
     pub async fn terminate(&mut self) -> Result<TsxResponse> {
         let mut state = self.inner.state.lock().await;
         state.set_terminated();
@@ -225,6 +457,44 @@ impl InviteSession {
                     transaction,
                 }))
             }
+            UsageEvent::Update(mut update) => {
+                self.session_timer.reset();
+
+                let transaction = self.endpoint.create_server_tsx(&mut update);
+
+                Ok(InviteSessionEvent::UpdateReceived(UpdateReceived {
+                    session: self,
+                    update,
+                    transaction,
+                }))
+            }
+            UsageEvent::Refer(mut refer) => {
+                let transaction = self.endpoint.create_server_tsx(&mut refer);
+
+                Ok(InviteSessionEvent::ReferReceived(ReferReceived {
+                    session: self,
+                    refer,
+                    transaction,
+                }))
+            }
+            UsageEvent::Notify(mut notify) => {
+                let transaction = self.endpoint.create_server_tsx(&mut notify);
+
+                Ok(InviteSessionEvent::NotifyReceived(NotifyReceived {
+                    session: self,
+                    notify,
+                    transaction,
+                }))
+            }
+            UsageEvent::Info(mut info) => {
+                let transaction = self.endpoint.create_server_tsx(&mut info);
+
+                Ok(InviteSessionEvent::InfoReceived(InfoReceived {
+                    session: self,
+                    info,
+                    transaction,
+                }))
+            }
         }
     }
 
@@ -242,9 +512,9 @@ impl InviteSession {
             }
             (Role::Uac, Refresher::Uas) | (Role::Uas, Refresher::Uac) => {
                 // Peer is responsible for refresh
-                // Timer expired meaning we didn't get a RE-INVITE
+                // Timer expired meaning we didn't get a RE-INVITE or UPDATE
                 self.terminate().await?;
-                Ok(InviteSessionEvent::Terminated)
+                Ok(InviteSessionEvent::SessionExpired)
             }
         }
     }
@@ -252,5 +522,91 @@ impl InviteSession {
 
 pub(super) enum UsageEvent {
     ReInvite(IncomingRequest),
+    Update(IncomingRequest),
+    Refer(IncomingRequest),
+    Notify(IncomingRequest),
+    Info(IncomingRequest),
     Bye(IncomingRequest),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dialog::DialogLayer;
+    use crate::invite::InviteLayer;
+    use sip_types::header::typed::{CallID, Contact, FromTo, SessionExpires};
+    use sip_types::uri::{NameAddr, SipUri};
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn endpoint() -> Endpoint {
+        let mut builder = Endpoint::builder();
+        builder.add_layer(DialogLayer::default());
+        builder.add_layer(InviteLayer::default());
+        builder.build()
+    }
+
+    fn snapshot(session_expires: Option<SessionExpires>) -> DialogSnapshot {
+        let name_addr = |uri: &str| NameAddr::uri(uri.parse::<SipUri>().unwrap());
+
+        DialogSnapshot {
+            local_cseq: 2,
+            peer_cseq: Some(1),
+            local_fromto: FromTo::new(name_addr("sip:alice@example.org"), Some("a".into())),
+            peer_fromto: FromTo::new(name_addr("sip:bob@example.org"), Some("b".into())),
+            local_contact: Contact::new(name_addr("sip:alice@127.0.0.1:5060")),
+            peer_contact: Contact::new(name_addr("sip:bob@127.0.0.1:5070")),
+            call_id: CallID::new("restored"),
+            route_set: vec![],
+            secure: false,
+            session_expires,
+        }
+    }
+
+    fn uac_refreshes() -> Option<SessionExpires> {
+        Some(SessionExpires {
+            delta_secs: 90,
+            refresher: Refresher::Uac,
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restored_refresher_refreshes_right_away() {
+        let mut session = InviteSession::restore(endpoint(), snapshot(uac_refreshes()), Role::Uac);
+
+        let event = timeout(Duration::from_secs(1), session.drive())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, InviteSessionEvent::RefreshNeeded(_)));
+
+        // Following refreshes use half the interval
+        assert_eq!(session.session_timer.real_delta_secs, 45);
+
+        let session_expires = session.snapshot().session_expires.unwrap();
+        assert_eq!(session_expires.delta_secs, 90);
+        assert_eq!(session_expires.refresher, Refresher::Uac);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restored_session_waits_for_peer_refresh() {
+        let mut session = InviteSession::restore(endpoint(), snapshot(uac_refreshes()), Role::Uas);
+
+        // The peer has the whole interval to refresh the session
+        assert!(timeout(Duration::from_secs(89), session.drive())
+            .await
+            .is_err());
+
+        assert_eq!(session.session_timer.real_delta_secs, 90);
+        assert!(session.snapshot().session_expires.is_some());
+    }
+
+    #[tokio::test]
+    async fn restored_without_timer() {
+        let session = InviteSession::restore(endpoint(), snapshot(None), Role::Uac);
+
+        assert!(session.session_timer.session_expires().is_none());
+        assert!(session.snapshot().session_expires.is_none());
+        assert_eq!(session.snapshot().local_cseq, 2);
+    }
+}
@@ -1,9 +1,10 @@
+use super::session::Role;
 use sip_core::transaction::TsxResponse;
 use sip_core::transport::OutgoingResponse;
 use sip_core::{IncomingRequest, Request};
 use sip_types::header::typed::{MinSe, Refresher, Require, SessionExpires};
 use sip_types::header::HeaderError;
-use sip_types::Name;
+use sip_types::{CodeKind, Name};
 use std::future::pending;
 use std::pin::Pin;
 use std::time::Duration;
@@ -64,6 +65,7 @@ impl AcceptorTimerConfig {
 
         SessionTimer {
             refresher: self.refresher,
+            delta_secs,
             real_delta_secs,
             interval: RefreshInterval::Sleeping(Box::pin(sleep)),
         }
@@ -91,6 +93,27 @@ impl InitiatorTimerConfig {
         request.headers.insert_named(&MinSe(self.expires_secs_min));
     }
 
+    /// Raise the requested session interval to the `Min-SE` of a `422 Session Interval Too Small` response
+    ///
+    /// Returns if the INVITE should be sent again using the raised interval (RFC 4028 section 7.4).
+    pub fn on_session_interval_too_small(&mut self, response: &TsxResponse) -> bool {
+        let Ok(min_se) = response.headers.get_named::<MinSe>() else {
+            return false;
+        };
+
+        if self
+            .expires_secs
+            .is_some_and(|expires_secs| expires_secs >= min_se.0)
+        {
+            return false;
+        }
+
+        self.expires_secs = Some(min_se.0);
+        self.expires_secs_min = self.expires_secs_min.max(min_se.0);
+
+        true
+    }
+
     pub fn create_timer_from_response(
         &self,
         response: &TsxResponse,
@@ -117,6 +140,7 @@ impl InitiatorTimerConfig {
 
             Ok(SessionTimer {
                 refresher,
+                delta_secs: se.delta_secs,
                 real_delta_secs,
                 interval: RefreshInterval::Sleeping(Box::pin(sleep)),
             })
@@ -131,6 +155,8 @@ impl InitiatorTimerConfig {
 #[derive(Debug)]
 pub struct SessionTimer {
     pub refresher: Refresher,
+    /// The negotiated session interval
+    pub delta_secs: u32,
     pub real_delta_secs: u32,
     pub interval: RefreshInterval,
 }
@@ -141,11 +167,50 @@ impl SessionTimer {
     pub fn new_unsupported() -> Self {
         Self {
             refresher: Refresher::Unspecified,
+            delta_secs: 0,
             real_delta_secs: 0,
             interval: RefreshInterval::Unsupported,
         }
     }
 
+    /// Re-create the timer of a session restored using `InviteSession::restore`
+    ///
+    /// The time that passed before the session was restored is unknown. If this side is the refresher, the session
+    /// is refreshed right away, otherwise the peer has the whole interval to refresh it.
+    pub fn restore(session_expires: SessionExpires, role: Role) -> Self {
+        let refresher = match session_expires.refresher {
+            Refresher::Uas => Refresher::Uas,
+            Refresher::Unspecified | Refresher::Uac => Refresher::Uac,
+        };
+
+        let delta_secs = session_expires.delta_secs;
+
+        let (real_delta_secs, first_secs) = match (role, refresher) {
+            (Role::Uac, Refresher::Uac) | (Role::Uas, Refresher::Uas) => (delta_secs / 2, 0),
+            _ => (delta_secs, delta_secs),
+        };
+
+        Self {
+            refresher,
+            delta_secs,
+            real_delta_secs,
+            interval: RefreshInterval::Sleeping(Box::pin(sleep(Duration::from_secs(
+                first_secs.into(),
+            )))),
+        }
+    }
+
+    /// Returns the negotiated `Session-Expires`, or `None` if the session doesn't use the `timer` extension
+    pub fn session_expires(&self) -> Option<SessionExpires> {
+        match self.interval {
+            RefreshInterval::Unsupported => None,
+            RefreshInterval::Sleeping(_) => Some(SessionExpires {
+                delta_secs: self.delta_secs,
+                refresher: self.refresher,
+            }),
+        }
+    }
+
     /// Wait for the session to expire. Will never return if no session expiry is set
     pub async fn wait(&mut self) {
         match &mut self.interval {
@@ -164,15 +229,44 @@ impl SessionTimer {
         }
     }
 
-    /// Populate headers of an INVITE refresh request
-    pub fn populate_refresh(&mut self, request: &mut Request) {
-        if let RefreshInterval::Unsupported = &mut self.interval {
+    /// Populate headers of an INVITE or UPDATE refresh request, sent by the refresher
+    pub fn populate_refresh(&self, request: &mut Request) {
+        if let RefreshInterval::Unsupported = &self.interval {
             return;
         }
 
         request.headers.insert(Name::SUPPORTED, "timer");
         request.headers.insert(Name::REQUIRE, "timer");
+        request.headers.insert_named(&SessionExpires {
+            delta_secs: self.delta_secs,
+            refresher: Refresher::Uac,
+        });
+    }
+}
+
+/// Copy the `Session-Expires` of a received refresh request into the successful response (RFC 4028 section 9)
+///
+/// Keeps the sender of the request as refresher if it didn't specify one.
+pub(super) fn populate_refresh_response(
+    request: &IncomingRequest,
+    response: &mut OutgoingResponse,
+) {
+    if response.msg.line.code.kind() != CodeKind::Success
+        || response.msg.headers.contains(&Name::SESSION_EXPIRES)
+    {
+        return;
     }
+
+    let Ok(mut session_expires) = request.headers.get_named::<SessionExpires>() else {
+        return;
+    };
+
+    if session_expires.refresher == Refresher::Unspecified {
+        session_expires.refresher = Refresher::Uac;
+    }
+
+    response.msg.headers.insert_named(&Require("timer".into()));
+    response.msg.headers.insert_named(&session_expires);
 }
 
 #[derive(Debug)]
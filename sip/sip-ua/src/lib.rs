@@ -1,4 +1,6 @@
 pub mod dialog;
 pub mod invite;
 pub mod register;
+pub mod retry;
+pub mod subscription;
 pub mod util;
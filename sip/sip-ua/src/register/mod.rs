@@ -1,14 +1,92 @@
+use crate::retry::{Backoff, RetryPolicy};
+use crate::subscription::{message_summary, SubscriptionInitiator};
 use crate::util::{random_sequence_number, random_string};
+use bytesstr::BytesStr;
 use sip_core::transaction::TsxResponse;
-use sip_core::Request;
-use sip_types::header::typed::{CSeq, CallID, Contact, Expires, FromTo, MinExpires};
+use sip_core::{Endpoint, Request};
+use sip_types::header::typed::{
+    CSeq, CallID, Contact, Event, Expires, FeatureCaps, FromTo, MinExpires, Routing, Via,
+};
+use sip_types::host::{Host, HostPort};
+use sip_types::uri::params::Param;
 use sip_types::uri::{NameAddr, SipUri};
-use sip_types::{CodeKind, Method, Name};
+use sip_types::{CodeKind, Method, Name, StatusCode};
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
-use tokio::time::{interval_at, Instant, Interval};
+use tokio::time::{sleep, sleep_until, Instant};
+
+mod set;
+
+pub use set::{RegistrationEvent, RegistrationId, RegistrationSet};
+
+/// Push notification parameters added to the Contact URI when registering
+/// ([RFC8599](https://datatracker.ietf.org/doc/html/rfc8599))
+#[derive(Debug, Clone)]
+pub struct PushNotification {
+    /// Type of the push notification service (`pn-provider`), e.g. `apns` or `fcm`
+    pub provider: BytesStr,
+    /// Identifier of the device at the push notification service (`pn-prid`)
+    pub prid: BytesStr,
+    /// Additional provider specific parameter (`pn-param`), e.g. the APNs topic
+    pub param: Option<BytesStr>,
+}
+
+impl PushNotification {
+    pub fn new(provider: impl Into<BytesStr>, prid: impl Into<BytesStr>) -> Self {
+        Self {
+            provider: provider.into(),
+            prid: prid.into(),
+            param: None,
+        }
+    }
+
+    pub fn with_param(mut self, param: impl Into<BytesStr>) -> Self {
+        self.param = Some(param.into());
+        self
+    }
+}
+
+/// The public signaling address as seen by the registrar changed, e.g. because a NAT mapping changed
+///
+/// Returned by [`Registration::receive_success_response`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicAddressChanged {
+    /// Previously learned public address, `None` if this is the first one
+    pub old: Option<SocketAddr>,
+    pub new: SocketAddr,
+    /// The Contact was rewritten to the new address, [`Registration::wait_for_expiry`] returns immediately to
+    /// refresh the binding
+    pub contact_rewritten: bool,
+}
+
+/// Registrar of a [`Registration`] and its health
+#[derive(Debug, Clone)]
+pub struct RegistrarTarget {
+    pub uri: SipUri,
+    /// Number of REGISTER requests which failed in a row
+    pub consecutive_failures: u32,
+    /// When the registrar last accepted a REGISTER request
+    pub last_success: Option<Instant>,
+}
+
+impl RegistrarTarget {
+    fn new(uri: SipUri) -> Self {
+        Self {
+            uri,
+            consecutive_failures: 0,
+            last_success: None,
+        }
+    }
+}
 
 pub struct Registration {
-    registrar: SipUri,
+    /// Registrars in order of priority, the first one is the primary
+    registrars: Vec<RegistrarTarget>,
+    /// Index of the registrar currently in use
+    active: usize,
+    /// How long to stay with a fallback registrar until trying the primary again
+    primary_retry_interval: Duration,
+    primary_retry: Option<Instant>,
 
     to: FromTo,
     from: FromTo,
@@ -16,35 +94,154 @@ pub struct Registration {
     cseq: u32,
     call_id: CallID,
     contact: Contact,
+    /// Preloaded route set of all requests, e.g. an outbound proxy
+    route_set: Vec<Routing>,
 
     /// Duration until the registration expires
     expires: Duration,
 
     /// Re-registration interval, is set to `expires - 10`
-    register_interval: Interval,
+    register_interval: RefreshTimer,
+
+    push_notification: Option<PushNotification>,
+    /// Set when the registrar indicated support for push notifications (`+sip.pns` feature-capability)
+    push_notifications_supported: bool,
+
+    /// Public address learned from the `received` & `rport` Via parameters of REGISTER responses
+    public_address: Option<SocketAddr>,
+    rewrite_contact: bool,
+
+    backoff: Backoff,
+    /// Delay before registering again after the last failure, `None` if the retry policy gave up
+    retry_delay: Option<Duration>,
+
+    /// Interval of OPTIONS requests sent to check if the registrar is still reachable
+    keepalive: Option<Duration>,
 }
 
 impl Registration {
     pub fn new(id: NameAddr, contact: Contact, registrar: SipUri, expiry: Duration) -> Self {
         Self {
-            registrar,
+            registrars: vec![RegistrarTarget::new(registrar)],
+            active: 0,
+            primary_retry_interval: Duration::from_secs(300),
+            primary_retry: None,
             to: FromTo::new(id.clone(), None),
             from: FromTo::new(id, Some(random_string())),
             cseq: random_sequence_number(),
             call_id: CallID::new(random_string()),
             contact,
+            route_set: vec![],
 
             expires: expiry,
             register_interval: create_reg_interval(expiry),
+
+            push_notification: None,
+            push_notifications_supported: false,
+
+            public_address: None,
+            rewrite_contact: false,
+
+            backoff: Backoff::new(RetryPolicy::default()),
+            retry_delay: None,
+
+            keepalive: None,
         }
     }
 
+    /// Set the policy used to delay registering again after a failed registration, see
+    /// [`wait_for_retry`](Self::wait_for_retry)
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.backoff = Backoff::new(policy);
+        self
+    }
+
+    /// Registrars to fail over to, in order of priority, when the registrar responds with a server error or
+    /// doesn't respond at all (see [`receive_timeout`](Self::receive_timeout))
+    ///
+    /// While a fallback is in use, registering at the primary registrar is tried again every
+    /// `primary_retry_interval`.
+    pub fn with_fallback_registrars(
+        mut self,
+        registrars: impl IntoIterator<Item = SipUri>,
+        primary_retry_interval: Duration,
+    ) -> Self {
+        self.registrars
+            .extend(registrars.into_iter().map(RegistrarTarget::new));
+        self.primary_retry_interval = primary_retry_interval;
+        self
+    }
+
+    /// Send all requests via the given route set, e.g. a single `Route` to an outbound proxy
+    ///
+    /// Also used by the subscription created using [`subscribe_mwi`](Self::subscribe_mwi).
+    pub fn with_route_set(mut self, route_set: Vec<Routing>) -> Self {
+        self.route_set = route_set;
+        self
+    }
+
+    /// Check if the registrar is still reachable by sending OPTIONS requests in the given interval between
+    /// refreshes of the binding, see [`wait_for_keepalive`](Self::wait_for_keepalive)
+    ///
+    /// Also keeps NAT mappings open, which may expire long before the registration does.
+    pub fn with_keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive = Some(interval);
+        self
+    }
+
+    /// Returns the registrar REGISTER requests are currently sent to
+    ///
+    /// When it changes, the target transport info used to send the requests must be reset.
+    pub fn registrar(&self) -> &SipUri {
+        &self.registrars[self.active].uri
+    }
+
+    /// Returns all registrars in order of priority, including their health
+    pub fn registrars(&self) -> &[RegistrarTarget] {
+        &self.registrars
+    }
+
+    /// Rewrite the Contact to the public address reported by the registrar and re-register whenever it changes
+    ///
+    /// Lets incoming requests reach this user agent behind a NAT without configuring the public address.
+    pub fn with_contact_rewrite(mut self) -> Self {
+        self.rewrite_contact = true;
+        self
+    }
+
+    /// Returns the public signaling address as seen by the registrar, if the registrar reported it
+    pub fn public_address(&self) -> Option<SocketAddr> {
+        self.public_address
+    }
+
+    /// Register for push notification based incoming calls using the given parameters
+    pub fn with_push_notification(mut self, push_notification: PushNotification) -> Self {
+        self.push_notification = Some(push_notification);
+        self
+    }
+
+    /// Returns if the registrar accepted the push notification parameters
+    pub fn push_notifications_supported(&self) -> bool {
+        self.push_notifications_supported
+    }
+
+    /// Must be called when the application has been woken up by a push notification
+    ///
+    /// [`Self::wait_for_expiry`] returns immediately afterwards. The REGISTER request sent then refreshes the binding,
+    /// which signals the registrar that the incoming call can now be forwarded to this user agent.
+    pub fn push_notification_received(&mut self) {
+        self.register_interval.reset_immediately();
+    }
+
     /// Create a new REGISTER request.
     ///
     /// `remove_binding` must be `false` to create a new binding on the registrar.
     /// If the value is `true` the REGISTER request will remove any active bindings.
+    ///
+    /// To give up on a registrar which doesn't respond in time, receive the response using
+    /// [`ClientTsx::receive_final_cancellable`](sip_core::transaction::ClientTsx::receive_final_cancellable).
     pub fn create_register(&mut self, remove_binding: bool) -> Request {
-        let mut request = Request::new(Method::REGISTER, self.registrar.clone());
+        let mut request = Request::new(Method::REGISTER, self.registrar().clone());
 
         request.headers.insert_type(Name::FROM, &self.from);
         request.headers.insert_type(Name::TO, &self.to);
@@ -62,18 +259,130 @@ impl Registration {
         };
 
         request.headers.insert_named(&expires);
-        request.headers.insert_named(&self.contact);
+
+        if let Some(push_notification) = &self.push_notification {
+            let mut contact = self.contact.clone();
+            let params = &mut contact.uri.uri.uri_params;
+
+            params.push(Param::value(
+                "pn-provider",
+                push_notification.provider.clone(),
+            ));
+            params.push(Param::value("pn-prid", push_notification.prid.clone()));
+
+            if let Some(param) = &push_notification.param {
+                params.push(Param::value("pn-param", param.clone()));
+            }
+
+            request.headers.insert_named(&contact);
+        } else {
+            request.headers.insert_named(&self.contact);
+        }
+
+        self.insert_route_set(&mut request);
+
+        request
+    }
+
+    /// Create a new OPTIONS request to the registrar, used to check if it's still reachable
+    ///
+    /// Any response means the registrar is reachable. If the request times out or cannot be sent, the registration
+    /// should be refreshed using [`create_register`](Self::create_register), handling a failure using
+    /// [`receive_timeout`](Self::receive_timeout) as usual.
+    pub fn create_options(&mut self) -> Request {
+        let mut request = Request::new(Method::OPTIONS, self.registrar().clone());
+
+        request.headers.insert_type(Name::FROM, &self.from);
+        request
+            .headers
+            .insert_type(Name::TO, &FromTo::new(self.to.uri.clone(), None));
+        request.headers.insert_named(&CallID::new(random_string()));
+        request
+            .headers
+            .insert_named(&CSeq::new(random_sequence_number(), Method::OPTIONS));
+
+        self.insert_route_set(&mut request);
 
         request
     }
 
+    fn insert_route_set(&self, request: &mut Request) {
+        if !self.route_set.is_empty() {
+            request.headers.insert_type(Name::ROUTE, &self.route_set);
+        }
+    }
+
+    /// Create an initiator subscribing to the message waiting indication (MWI) of the registered address of record
+    /// using the `message-summary` event package
+    ///
+    /// The bodies of the received notifications are parsed using
+    /// [`MessageSummary::parse`](message_summary::MessageSummary::parse) to get the number of waiting voicemails. The
+    /// [`SubscriptionLayer`](crate::subscription::SubscriptionLayer) must be added to the endpoint.
+    pub fn subscribe_mwi(&self, endpoint: Endpoint) -> SubscriptionInitiator {
+        SubscriptionInitiator::new(
+            endpoint,
+            self.from.uri.clone(),
+            self.contact.clone(),
+            self.to.uri.uri.clone(),
+            Event::new(message_summary::PACKAGE),
+        )
+        .with_accept(message_summary::CONTENT_TYPE)
+        .with_route_set(self.route_set.clone())
+    }
+
+    /// Like [`wait_for_expiry`](Self::wait_for_expiry), but also returns when an OPTIONS keepalive request must be
+    /// sent, see [`with_keepalive`](Self::with_keepalive)
+    ///
+    /// Returns `true` if a keepalive request must be sent and `false` if the binding must be refreshed. The keepalive
+    /// interval restarts with each call.
+    pub async fn wait_for_keepalive(&mut self) -> bool {
+        let deadline = self.next_deadline();
+
+        if let Some(interval) = self.keepalive {
+            let keepalive = Instant::now() + interval;
+
+            if keepalive < deadline {
+                sleep_until(keepalive).await;
+                return true;
+            }
+        }
+
+        sleep_until(deadline).await;
+        self.on_deadline(Instant::now());
+
+        false
+    }
+
     /// Handle the success response received from a registrar
     ///
     /// Updates internal re-registration timer.
     /// [`Self::wait_for_expiry`] should be used to wait until refreshing the binding with the registrar.
-    pub fn receive_success_response(&mut self, response: TsxResponse) {
+    ///
+    /// Returns the change of the public address, if the registrar reported a different one than before.
+    pub fn receive_success_response(
+        &mut self,
+        response: TsxResponse,
+    ) -> Option<PublicAddressChanged> {
         assert_eq!(response.line.code.kind(), CodeKind::Success);
 
+        self.backoff.on_success();
+        self.retry_delay = None;
+
+        let registrar = &mut self.registrars[self.active];
+        registrar.consecutive_failures = 0;
+        registrar.last_success = Some(Instant::now());
+
+        if self.active != 0 && self.primary_retry.is_none() {
+            self.primary_retry = Some(Instant::now() + self.primary_retry_interval);
+        }
+
+        let public_address_changed = response
+            .base_headers
+            .via
+            .first()
+            .and_then(reported_address)
+            .and_then(|address| self.update_public_address(address));
+
         if let Ok(expires) = response.headers.get_named::<Expires>() {
             let expires = Duration::from_secs(expires.0 as _);
 
@@ -83,19 +392,93 @@ impl Registration {
             }
         }
 
+        if self.push_notification.is_some() {
+            let feature_caps: Vec<FeatureCaps> = response.headers.get_named().unwrap_or_default();
+
+            self.push_notifications_supported = feature_caps
+                .iter()
+                .any(|caps| caps.params.get("+sip.pns").is_some());
+
+            // The registrar may require binding refreshes more often than the registration expires
+            let pnsreg = feature_caps
+                .iter()
+                .filter_map(|caps| caps.params.get_val("+sip.pnsreg"))
+                .find_map(|value| value.parse::<u64>().ok())
+                .map(Duration::from_secs);
+
+            if let Some(pnsreg) = pnsreg.filter(|pnsreg| *pnsreg < self.expires) {
+                self.register_interval = create_reg_interval(pnsreg);
+            }
+        }
+
         if self.to.tag.is_none() {
             self.to.tag = response.base_headers.to.tag;
         }
+
+        public_address_changed
+    }
+
+    fn update_public_address(&mut self, address: SocketAddr) -> Option<PublicAddressChanged> {
+        if self.public_address == Some(address) {
+            return None;
+        }
+
+        let old = self.public_address.replace(address);
+
+        log::info!("Public address changed from {old:?} to {address}");
+
+        let new_host_port = HostPort {
+            host: address.ip().into(),
+            port: Some(address.port()),
+        };
+
+        let contact_rewritten =
+            self.rewrite_contact && self.contact.uri.uri.host_port != new_host_port;
+
+        if contact_rewritten {
+            self.contact.uri.uri.host_port = new_host_port;
+            self.register_interval.reset_immediately();
+        }
+
+        Some(PublicAddressChanged {
+            old,
+            new: address,
+            contact_rewritten,
+        })
     }
 
     /// Handle an error response received from a registrar
     ///
-    /// Returns whether or not to retry the registration
+    /// Returns whether or not to retry the registration right away. Otherwise [`wait_for_retry`](Self::wait_for_retry)
+    /// should be used to wait before registering again.
     pub fn receive_error_response(&mut self, response: TsxResponse) -> bool {
-        if !matches!(response.line.code.kind(), CodeKind::RequestFailure) {
-            return false;
+        if response.line.code == StatusCode::PUSH_NOTIFICATION_SERVICE_NOT_SUPPORTED
+            && self.push_notification.is_some()
+        {
+            // Retry without push notifications
+            log::warn!("Registrar does not support the push notification service");
+
+            self.push_notification = None;
+            self.push_notifications_supported = false;
+
+            return true;
         }
 
+        let retry = match response.line.code.kind() {
+            CodeKind::ServerFailure => self.fail_over(),
+            CodeKind::RequestFailure => self.apply_min_expires(&response),
+            _ => false,
+        };
+
+        if !retry {
+            self.retry_delay = self.backoff.on_failure(Some(&response));
+        }
+
+        retry
+    }
+
+    /// Adopt the `Min-Expires` of a `423 Interval Too Brief` response, returns if one was found
+    fn apply_min_expires(&mut self, response: &TsxResponse) -> bool {
         let Ok(expires) = response.headers.get_named::<MinExpires>() else {
             return false;
         };
@@ -106,19 +489,141 @@ impl Registration {
         true
     }
 
+    /// Handle a REGISTER request which timed out or could not be sent
+    ///
+    /// Returns whether to retry the registration right away, using the next fallback registrar. Otherwise
+    /// [`wait_for_retry`](Self::wait_for_retry) should be used to wait before registering again.
+    pub fn receive_timeout(&mut self) -> bool {
+        let retry = self.fail_over();
+
+        if !retry {
+            self.retry_delay = self.backoff.on_failure(None);
+        }
+
+        retry
+    }
+
+    /// Wait before registering again after a failure which wasn't retried right away
+    ///
+    /// The delay is determined by the [`RetryPolicy`]. Returns `false` without waiting if the policy gave up.
+    pub async fn wait_for_retry(&mut self) -> bool {
+        let Some(delay) = self.retry_delay.take() else {
+            return false;
+        };
+
+        sleep(delay).await;
+
+        true
+    }
+
+    /// Switch to the next registrar, returns `false` if all registrars failed
+    fn fail_over(&mut self) -> bool {
+        self.registrars[self.active].consecutive_failures += 1;
+
+        let next = self.active + 1;
+
+        if next < self.registrars.len() {
+            log::warn!(
+                "Registrar {:?} failed, trying the next one",
+                self.registrar()
+            );
+            self.switch_registrar(next);
+            true
+        } else {
+            // Start again with the primary once the application retries
+            self.switch_registrar(0);
+            false
+        }
+    }
+
+    fn switch_registrar(&mut self, index: usize) {
+        self.active = index;
+        self.primary_retry = None;
+
+        // The new registrar creates its own to-tag
+        self.to.tag = None;
+    }
+
     /// Returns when a new REGISTER request must be sent to refresh the binding on the registrar.
+    ///
+    /// Also returns when it's time to try the primary registrar again, while a fallback registrar is in use.
     pub async fn wait_for_expiry(&mut self) {
-        self.register_interval.tick().await;
+        sleep_until(self.next_deadline()).await;
+        self.on_deadline(Instant::now());
+    }
+
+    /// Returns when the next REGISTER request must be sent
+    fn next_deadline(&self) -> Instant {
+        match self.primary_retry {
+            Some(primary_retry) => primary_retry.min(self.register_interval.next),
+            None => self.register_interval.next,
+        }
+    }
+
+    /// Must be called once the instant returned by [`Self::next_deadline`] was reached
+    fn on_deadline(&mut self, now: Instant) {
+        match self.primary_retry {
+            Some(primary_retry) if primary_retry <= now => {
+                log::info!("Trying to return to the primary registrar");
+                self.switch_registrar(0);
+            }
+            _ => self.register_interval.advance(now),
+        }
     }
 }
 
-fn create_reg_interval(period: Duration) -> Interval {
+/// Tracks when to refresh a registration, without registering a timer until it's awaited
+#[derive(Debug)]
+struct RefreshTimer {
+    period: Duration,
+    next: Instant,
+}
+
+impl RefreshTimer {
+    fn reset_immediately(&mut self) {
+        self.next = Instant::now();
+    }
+
+    /// Schedule the next refresh after the current one was reached, missed refreshes are not caught up on
+    fn advance(&mut self, now: Instant) {
+        self.next = now + self.period;
+    }
+}
+
+/// Returns the source address the peer received the request from, if it reported it using the `received` or
+/// `rport` parameters (RFC 3261 Section 18.2.1, RFC 3581)
+fn reported_address(via: &Via) -> Option<SocketAddr> {
+    let received = via.params.get_val("received");
+    let rport = via.params.get_val("rport");
+
+    if received.is_none() && rport.is_none() {
+        return None;
+    }
+
+    let ip = match received {
+        Some(received) => received.parse().ok()?,
+        None => match via.sent_by.host {
+            Host::IP4(ip) => IpAddr::V4(ip),
+            Host::IP6(ip) => IpAddr::V6(ip),
+            Host::Name(..) => return None,
+        },
+    };
+
+    let port = match rport {
+        Some(rport) => rport.parse().ok()?,
+        None => via.sent_by.port.unwrap_or(5060),
+    };
+
+    Some(SocketAddr::new(ip, port))
+}
+
+fn create_reg_interval(period: Duration) -> RefreshTimer {
     // Avoid underflow and zero duration intervals by limiting `period` to be at least 20s
     let period = period.max(Duration::from_secs(20));
     let period = period - Duration::from_secs(10);
 
-    let next = Instant::now() + period;
-    let mut register_interval = interval_at(next, period);
-    register_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
-    register_interval
+    RefreshTimer {
+        period,
+        next: Instant::now() + period,
+    }
 }
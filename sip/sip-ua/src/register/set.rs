@@ -0,0 +1,522 @@
+use super::{PublicAddressChanged, Registration};
+use sip_core::transaction::TsxResponse;
+use sip_core::transport::TargetTransportInfo;
+use sip_core::{Endpoint, Result};
+use sip_types::{CodeKind, StatusCode};
+use slotmap::{new_key_type, SlotMap};
+use std::collections::BTreeSet;
+use std::future::pending;
+use tokio::task::JoinSet;
+use tokio::time::{sleep_until, Instant};
+
+new_key_type! {
+    /// Identifies a [`Registration`] inside a [`RegistrationSet`]
+    pub struct RegistrationId;
+}
+
+/// Event returned by [`RegistrationSet::run`]
+#[derive(Debug)]
+pub enum RegistrationEvent {
+    /// The registrar accepted the REGISTER request
+    Registered {
+        id: RegistrationId,
+        public_address_changed: Option<PublicAddressChanged>,
+    },
+
+    /// The REGISTER request failed and will be retried
+    ///
+    /// `code` is the status code of the error response, `None` if the request timed out or couldn't be sent.
+    Failed {
+        id: RegistrationId,
+        code: Option<StatusCode>,
+    },
+
+    /// The retry policy of the registration gave up, no more requests are sent for it
+    GaveUp(RegistrationId),
+}
+
+struct Entry {
+    registration: Registration,
+
+    /// Transport to the registrar, taken while a REGISTER request is in flight
+    target: Option<TargetTransportInfo>,
+
+    /// When the next REGISTER request is sent, `None` while a request is in flight or after giving up
+    scheduled: Option<Instant>,
+    /// Whether the scheduled request refreshes the binding, instead of retrying a failed one
+    refresh: bool,
+}
+
+type InFlight = (RegistrationId, TargetTransportInfo, Result<TsxResponse>);
+
+/// Drives many [`Registration`]s from a single task
+///
+/// Instead of every registration waiting on its own timer, all pending refreshes and retries are kept in a single
+/// ordered schedule. Only requests in flight use a task of their own, their number is limited by `max_in_flight` to
+/// avoid bursts of REGISTER requests, e.g. after startup or when a registrar was unreachable.
+///
+/// Each registration must be configured (retry policy, fallback registrars, ...) before being inserted.
+pub struct RegistrationSet {
+    endpoint: Endpoint,
+    max_in_flight: usize,
+
+    registrations: SlotMap<RegistrationId, Entry>,
+    schedule: BTreeSet<(Instant, RegistrationId)>,
+    in_flight: JoinSet<InFlight>,
+}
+
+impl RegistrationSet {
+    pub fn new(endpoint: Endpoint, max_in_flight: usize) -> Self {
+        Self {
+            endpoint,
+            max_in_flight: max_in_flight.max(1),
+            registrations: SlotMap::with_key(),
+            schedule: BTreeSet::new(),
+            in_flight: JoinSet::new(),
+        }
+    }
+
+    /// Add a registration, its first REGISTER request is sent as soon as possible
+    pub fn insert(&mut self, registration: Registration) -> RegistrationId {
+        let id = self.registrations.insert(Entry {
+            registration,
+            target: Some(TargetTransportInfo::default()),
+            scheduled: None,
+            refresh: false,
+        });
+
+        self.schedule(id, Instant::now(), false);
+
+        id
+    }
+
+    /// Add many registrations at once, e.g. when starting a gateway
+    ///
+    /// Their first REGISTER requests are due right away, but only `max_in_flight` of them are sent at once.
+    pub fn insert_many(
+        &mut self,
+        registrations: impl IntoIterator<Item = Registration>,
+    ) -> Vec<RegistrationId> {
+        registrations
+            .into_iter()
+            .map(|registration| self.insert(registration))
+            .collect()
+    }
+
+    /// Remove a registration from the set
+    ///
+    /// No more requests are sent for it, the binding on the registrar must be removed by the caller if desired.
+    pub fn remove(&mut self, id: RegistrationId) -> Option<Registration> {
+        let entry = self.registrations.remove(id)?;
+
+        if let Some(at) = entry.scheduled {
+            self.schedule.remove(&(at, id));
+        }
+
+        Some(entry.registration)
+    }
+
+    /// Remove many registrations at once, see [`remove`](Self::remove)
+    ///
+    /// Returns the registrations which were part of the set.
+    pub fn remove_many(
+        &mut self,
+        ids: impl IntoIterator<Item = RegistrationId>,
+    ) -> Vec<Registration> {
+        ids.into_iter().filter_map(|id| self.remove(id)).collect()
+    }
+
+    pub fn get(&self, id: RegistrationId) -> Option<&Registration> {
+        self.registrations.get(id).map(|entry| &entry.registration)
+    }
+
+    pub fn len(&self) -> usize {
+        self.registrations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registrations.is_empty()
+    }
+
+    /// Send the REGISTER requests which are due and wait for the next event
+    ///
+    /// Must be called in a loop to keep the registrations alive. Is cancel safe, so registrations can be inserted
+    /// or removed between calls.
+    pub async fn run(&mut self) -> RegistrationEvent {
+        loop {
+            let next_due = self
+                .schedule
+                .first()
+                .map(|(at, _)| *at)
+                .filter(|_| self.in_flight.len() < self.max_in_flight);
+
+            tokio::select! {
+                Some(result) = self.in_flight.join_next() => {
+                    let (id, target, result) = match result {
+                        Ok(in_flight) => in_flight,
+                        Err(e) => {
+                            log::error!("REGISTER task failed, {e}");
+                            continue;
+                        }
+                    };
+
+                    if let Some(event) = self.handle_result(id, target, result) {
+                        return event;
+                    }
+                }
+                _ = sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                    self.send_due(Instant::now());
+                }
+                else => pending().await,
+            }
+        }
+    }
+
+    fn schedule(&mut self, id: RegistrationId, at: Instant, refresh: bool) {
+        if let Some(entry) = self.registrations.get_mut(id) {
+            entry.scheduled = Some(at);
+            entry.refresh = refresh;
+            self.schedule.insert((at, id));
+        }
+    }
+
+    fn send_due(&mut self, now: Instant) {
+        while self.in_flight.len() < self.max_in_flight {
+            let Some(&(at, id)) = self.schedule.first() else {
+                return;
+            };
+
+            if at > now {
+                return;
+            }
+
+            self.schedule.pop_first();
+
+            let Some(entry) = self.registrations.get_mut(id) else {
+                continue;
+            };
+
+            entry.scheduled = None;
+
+            if entry.refresh {
+                entry.registration.on_deadline(now);
+            }
+
+            let request = entry.registration.create_register(false);
+            let mut target = entry.target.take().unwrap_or_default();
+            let endpoint = self.endpoint.clone();
+
+            self.in_flight.spawn(async move {
+                let result = async {
+                    let mut transaction = endpoint.send_request(request, &mut target).await?;
+                    transaction.receive_final().await
+                }
+                .await;
+
+                (id, target, result)
+            });
+        }
+    }
+
+    fn handle_result(
+        &mut self,
+        id: RegistrationId,
+        target: TargetTransportInfo,
+        result: Result<TsxResponse>,
+    ) -> Option<RegistrationEvent> {
+        // The registration might have been removed in the meantime
+        let entry = self.registrations.get_mut(id)?;
+        let registration = &mut entry.registration;
+        let active_registrar = registration.active;
+
+        let (retry, code) = match result {
+            Ok(response) if response.line.code.kind() == CodeKind::Success => {
+                let public_address_changed = registration.receive_success_response(response);
+                let next_deadline = registration.next_deadline();
+
+                entry.target = Some(target);
+                self.schedule(id, next_deadline, true);
+
+                return Some(RegistrationEvent::Registered {
+                    id,
+                    public_address_changed,
+                });
+            }
+            Ok(response) => {
+                let code = response.line.code;
+                (registration.receive_error_response(response), Some(code))
+            }
+            Err(e) => {
+                log::debug!("REGISTER request failed, {e}");
+                (registration.receive_timeout(), None)
+            }
+        };
+
+        // Resolve the transport again when switching to another registrar
+        if registration.active == active_registrar {
+            entry.target = Some(target);
+        }
+
+        let retry_at = if retry {
+            Some(Instant::now())
+        } else {
+            registration
+                .retry_delay
+                .take()
+                .map(|delay| Instant::now() + delay)
+        };
+
+        match retry_at {
+            Some(retry_at) => {
+                self.schedule(id, retry_at, false);
+                Some(RegistrationEvent::Failed { id, code })
+            }
+            None => Some(RegistrationEvent::GaveUp(id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::retry::RetryPolicy;
+    use sip_core::transaction::ServerTsx;
+    use sip_core::transport::udp::Udp;
+    use sip_core::{IncomingRequest, Layer, MayTake};
+    use sip_types::header::typed::{Contact, Expires};
+    use sip_types::uri::{NameAddr, SipUri};
+    use sip_types::Method;
+    use std::future::Future;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio::time::{timeout, timeout_at};
+
+    const EXPIRES: Duration = Duration::from_secs(60);
+
+    /// Passes REGISTER requests to the test, which responds to them
+    struct Registrar(mpsc::UnboundedSender<(IncomingRequest, ServerTsx)>);
+
+    #[async_trait::async_trait]
+    impl Layer for Registrar {
+        fn name(&self) -> &'static str {
+            "test-registrar"
+        }
+
+        async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+            if request.line.method == Method::REGISTER {
+                let mut request = request.take();
+                let tsx = endpoint.create_server_tsx(&mut request);
+                let _ = self.0.send((request, tsx));
+            }
+        }
+    }
+
+    struct Peer {
+        endpoint: Endpoint,
+        uri: SipUri,
+        requests: mpsc::UnboundedReceiver<(IncomingRequest, ServerTsx)>,
+    }
+
+    impl Peer {
+        async fn respond(&self, (request, tsx): (IncomingRequest, ServerTsx), code: StatusCode) {
+            let mut response = self.endpoint.create_response(&request, code, None);
+            response
+                .msg
+                .headers
+                .insert_named(&Expires(EXPIRES.as_secs() as u32));
+
+            tsx.respond(response).await.unwrap();
+        }
+    }
+
+    async fn bind(builder: &mut sip_core::EndpointBuilder) -> SipUri {
+        let udp = Udp::spawn(builder, (Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+
+        format!("sip:{}", udp.bound()).parse().unwrap()
+    }
+
+    /// Create a registrar and a set registering with it
+    async fn setup(max_in_flight: usize) -> (Peer, RegistrationSet, SipUri) {
+        let (requests, requests_rx) = mpsc::unbounded_channel();
+
+        let mut builder = Endpoint::builder();
+        builder.add_layer(Registrar(requests));
+        let uri = bind(&mut builder).await;
+
+        let peer = Peer {
+            endpoint: builder.build(),
+            uri,
+            requests: requests_rx,
+        };
+
+        let mut builder = Endpoint::builder();
+        let client_uri = bind(&mut builder).await;
+        let set = RegistrationSet::new(builder.build(), max_in_flight);
+
+        (peer, set, client_uri)
+    }
+
+    fn registration(peer: &Peer, client_uri: &SipUri, user: usize) -> Registration {
+        let id: SipUri = format!("sip:user{user}@example.org").parse().unwrap();
+
+        Registration::new(
+            NameAddr::uri(id),
+            Contact::new(NameAddr::uri(client_uri.clone())),
+            peer.uri.clone(),
+            EXPIRES,
+        )
+    }
+
+    /// Run the set until `f` completes, returns the output of `f` and the events of the set
+    async fn run_until<F: Future>(
+        set: &mut RegistrationSet,
+        f: F,
+    ) -> (F::Output, Vec<RegistrationEvent>) {
+        let mut events = vec![];
+        tokio::pin!(f);
+
+        loop {
+            tokio::select! {
+                output = &mut f => return (output, events),
+                event = set.run() => events.push(event),
+            }
+        }
+    }
+
+    /// Run the set until it returned `n` events, responding to all REGISTER requests with `code`
+    async fn respond_all(
+        peer: &mut Peer,
+        set: &mut RegistrationSet,
+        n: usize,
+        code: StatusCode,
+    ) -> Vec<(Instant, RegistrationEvent)> {
+        let mut events = vec![];
+
+        while events.len() < n {
+            tokio::select! {
+                Some(request) = peer.requests.recv() => peer.respond(request, code).await,
+                event = set.run() => events.push((Instant::now(), event)),
+            }
+        }
+
+        events
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shared_refresh_schedule() {
+        let (mut peer, mut set, client_uri) = setup(8).await;
+        let ids = set.insert_many((0..3).map(|user| registration(&peer, &client_uri, user)));
+        let start = Instant::now();
+
+        let registered = respond_all(&mut peer, &mut set, 3, StatusCode::OK).await;
+        assert!(registered
+            .iter()
+            .all(|(_, event)| matches!(event, RegistrationEvent::Registered { .. })));
+
+        // All refreshes wait in the schedule of the set, nothing is in flight
+        assert!(set.in_flight.is_empty());
+        let scheduled: Vec<RegistrationId> = set.schedule.iter().map(|(_, id)| *id).collect();
+        assert_eq!(scheduled.len(), 3);
+        assert!(ids.iter().all(|id| scheduled.contains(id)));
+        assert!(ids.iter().all(|id| set.registrations[*id].refresh));
+
+        // Nothing is sent until the refreshes are due, 10 seconds before the registrations expire
+        let refresh_at = start + EXPIRES - Duration::from_secs(10);
+        let (request, _) = run_until(&mut set, timeout_at(refresh_at, peer.requests.recv())).await;
+        assert!(request.is_err());
+
+        // Then all of them are refreshed at once
+        let mut refreshed = vec![];
+        for _ in 0..3 {
+            let (request, _) = run_until(&mut set, peer.requests.recv()).await;
+            let (request, _) = request.unwrap();
+            assert!(Instant::now() >= refresh_at);
+            refreshed.push(request.base_headers.call_id.clone());
+        }
+
+        refreshed.sort();
+        refreshed.dedup();
+        assert_eq!(refreshed.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn max_in_flight() {
+        let (mut peer, mut set, client_uri) = setup(2).await;
+        set.insert_many((0..5).map(|user| registration(&peer, &client_uri, user)));
+
+        let mut pending = vec![];
+        for _ in 0..2 {
+            let (request, _) = run_until(&mut set, peer.requests.recv()).await;
+            pending.push(request.unwrap());
+        }
+
+        // No other request is sent while two are waiting for a response
+        let (result, _) = run_until(
+            &mut set,
+            timeout(Duration::from_millis(200), peer.requests.recv()),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(set.in_flight.len(), 2);
+
+        // Completing one request lets the next one be sent
+        peer.respond(pending.remove(0), StatusCode::OK).await;
+        let (request, events) = run_until(&mut set, peer.requests.recv()).await;
+        assert!(request.is_some());
+        assert!(matches!(events[..], [RegistrationEvent::Registered { .. }]));
+        assert_eq!(set.in_flight.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn remove_while_in_flight() {
+        let (mut peer, mut set, client_uri) = setup(8).await;
+        let id = set.insert(registration(&peer, &client_uri, 0));
+
+        let (request, _) = run_until(&mut set, peer.requests.recv()).await;
+
+        assert!(set.remove(id).is_some());
+        assert!(set.is_empty());
+
+        // The response of the removed registration is ignored and nothing is scheduled for it
+        peer.respond(request.unwrap(), StatusCode::OK).await;
+        assert!(timeout(Duration::from_millis(200), set.run())
+            .await
+            .is_err());
+        assert!(set.in_flight.is_empty());
+        assert!(set.schedule.is_empty());
+        assert!(set.remove_many([id]).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_backoff() {
+        let (mut peer, mut set, client_uri) = setup(8).await;
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.0,
+            max_attempts: Some(3),
+            ..RetryPolicy::default()
+        };
+        let id = set.insert(registration(&peer, &client_uri, 0).with_retry_policy(policy));
+
+        let events = respond_all(&mut peer, &mut set, 3, StatusCode::FORBIDDEN).await;
+
+        for (_, event) in &events[..2] {
+            assert!(matches!(
+                event,
+                RegistrationEvent::Failed { id: failed, code: Some(StatusCode::FORBIDDEN) } if *failed == id
+            ));
+        }
+        assert!(matches!(events[2].1, RegistrationEvent::GaveUp(gave_up) if gave_up == id));
+
+        // The delay doubles with every failed attempt
+        assert!(events[1].0 - events[0].0 >= Duration::from_secs(1));
+        assert!(events[2].0 - events[1].0 >= Duration::from_secs(2));
+
+        // Nothing is sent anymore
+        assert!(timeout(Duration::from_secs(600), set.run()).await.is_err());
+        assert!(set.schedule.is_empty());
+        assert!(peer.requests.try_recv().is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use rand::Rng;
+use sip_core::transaction::TsxResponse;
+use sip_types::header::typed::RetryAfter;
+use std::time::Duration;
+
+/// Describes how to retry a failed operation, e.g. a registration refresh or a call attempt
+///
+/// Delays grow exponentially with every failed attempt up to `max_delay`. Every delay is randomly
+/// shortened by up to `jitter` so many clients failing at the same time don't retry all at once.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Delay before the first retry. Is 2 seconds by default
+    pub initial_delay: Duration,
+    /// Upper bound of any delay. Is 5 minutes by default
+    pub max_delay: Duration,
+    /// Factor the delay grows by with every failed attempt. Is 2 by default
+    pub multiplier: f64,
+    /// Fraction between 0 and 1 by which delays are randomly shortened. Is 0.5 by default
+    pub jitter: f64,
+    /// Give up after this many failed attempts. Retries forever by default
+    pub max_attempts: Option<u32>,
+    /// Wait as long as requested by the `Retry-After` header of a response, when it's longer than the calculated
+    /// delay. Is true by default
+    pub honor_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(300),
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_attempts: None,
+            honor_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay before retrying after the given number of failed attempts (starting at 1), `None` if no
+    /// more attempts should be made
+    pub fn delay(&self, failed_attempts: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| failed_attempts >= max) {
+            return None;
+        }
+
+        let exponent = failed_attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let delay = delay.min(self.max_delay.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let delay = if jitter > 0.0 {
+            delay * (1.0 - rand::rng().random_range(0.0..=jitter))
+        } else {
+            delay
+        };
+
+        let delay = Duration::from_secs_f64(delay);
+
+        match retry_after {
+            Some(retry_after) if self.honor_retry_after => Some(delay.max(retry_after)),
+            _ => Some(delay),
+        }
+    }
+}
+
+/// Counts failed attempts of an operation retried using a [`RetryPolicy`]
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    failed_attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            failed_attempts: 0,
+        }
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Number of failed attempts since the last success
+    pub fn failed_attempts(&self) -> u32 {
+        self.failed_attempts
+    }
+
+    /// Register a failed attempt and return how long to wait before retrying, `None` if the policy gives up
+    ///
+    /// The response which caused the failure can be passed to honor its `Retry-After` header.
+    pub fn on_failure(&mut self, response: Option<&TsxResponse>) -> Option<Duration> {
+        self.failed_attempts = self.failed_attempts.saturating_add(1);
+
+        let retry_after = response
+            .and_then(|response| response.headers.get_named::<RetryAfter>().ok())
+            .map(|retry_after| Duration::from_secs(retry_after.value.into()));
+
+        self.policy.delay(self.failed_attempts, retry_after)
+    }
+
+    /// Register a successful attempt, the next failure starts with the initial delay again
+    pub fn on_success(&mut self) {
+        self.failed_attempts = 0;
+    }
+}
@@ -0,0 +1,380 @@
+//! `dialog` event package ([RFC4235](https://datatracker.ietf.org/doc/html/rfc4235)), e.g. to implement busy lamp
+//! fields (BLF)
+
+use super::xml::{esc, BodyError, Element};
+use crate::invite::initiator::DialogInfo;
+use std::fmt;
+
+/// Name of the event package, used in the `Event` header
+pub const PACKAGE: &str = "dialog";
+
+/// Content type of dialog information documents
+pub const CONTENT_TYPE: &str = "application/dialog-info+xml";
+
+/// Whether a document contains all dialogs or only the changed ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentState {
+    Full,
+    Partial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialogState {
+    Trying,
+    Proceeding,
+    Early,
+    Confirmed,
+    Terminated,
+}
+
+impl DialogState {
+    fn as_str(self) -> &'static str {
+        match self {
+            DialogState::Trying => "trying",
+            DialogState::Proceeding => "proceeding",
+            DialogState::Early => "early",
+            DialogState::Confirmed => "confirmed",
+            DialogState::Terminated => "terminated",
+        }
+    }
+
+    fn parse(state: &str) -> Option<Self> {
+        [
+            DialogState::Trying,
+            DialogState::Proceeding,
+            DialogState::Early,
+            DialogState::Confirmed,
+            DialogState::Terminated,
+        ]
+        .into_iter()
+        .find(|s| state.eq_ignore_ascii_case(s.as_str()))
+    }
+
+    /// The dialog has not been answered yet
+    fn is_early(self) -> bool {
+        matches!(
+            self,
+            DialogState::Trying | DialogState::Proceeding | DialogState::Early
+        )
+    }
+}
+
+/// Which side of the dialog the monitored user agent is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialogDirection {
+    /// The monitored user agent sent the INVITE
+    Initiator,
+    /// The monitored user agent received the INVITE
+    Recipient,
+}
+
+/// State of the line of the monitored user agent as shown by a busy lamp field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineState {
+    Idle,
+    /// A call to the user agent is ringing and can be picked up
+    Ringing,
+    Busy,
+}
+
+/// A dialog of the monitored user agent (`<dialog>`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialogElement {
+    pub id: String,
+    pub call_id: Option<String>,
+    /// Tag of the monitored user agent
+    pub local_tag: Option<String>,
+    /// Tag of the monitored user agent's peer
+    pub remote_tag: Option<String>,
+    pub direction: Option<DialogDirection>,
+    pub state: DialogState,
+    pub local_identity: Option<String>,
+    pub remote_identity: Option<String>,
+}
+
+impl DialogElement {
+    pub fn new(id: impl Into<String>, state: DialogState) -> Self {
+        Self {
+            id: id.into(),
+            call_id: None,
+            local_tag: None,
+            remote_tag: None,
+            direction: None,
+            state,
+            local_identity: None,
+            remote_identity: None,
+        }
+    }
+
+    /// Returns the information required to pick up or replace the dialog, if the notifier reported it
+    ///
+    /// See [`InviteInitiator::pickup`](crate::invite::initiator::InviteInitiator::pickup).
+    pub fn dialog_info(&self) -> Option<DialogInfo> {
+        Some(DialogInfo {
+            call_id: self.call_id.as_deref()?.into(),
+            local_tag: self.local_tag.as_deref()?.into(),
+            remote_tag: self.remote_tag.as_deref()?.into(),
+            early: self.state.is_early(),
+        })
+    }
+
+    fn parse(dialog: &Element) -> Result<Self, BodyError> {
+        let id = dialog
+            .attribute("id")
+            .ok_or(BodyError::Missing("dialog id attribute"))?;
+
+        let state = dialog
+            .child_text("state")
+            .and_then(DialogState::parse)
+            .ok_or(BodyError::Missing("dialog state"))?;
+
+        let direction = dialog
+            .attribute("direction")
+            .and_then(|direction| match direction {
+                "initiator" => Some(DialogDirection::Initiator),
+                "recipient" => Some(DialogDirection::Recipient),
+                _ => None,
+            });
+
+        let identity = |participant: &str| {
+            dialog
+                .child(participant)
+                .and_then(|participant| participant.child_text("identity"))
+                .map(String::from)
+        };
+
+        Ok(Self {
+            id: id.into(),
+            call_id: dialog.attribute("call-id").map(Into::into),
+            local_tag: dialog.attribute("local-tag").map(Into::into),
+            remote_tag: dialog.attribute("remote-tag").map(Into::into),
+            direction,
+            state,
+            local_identity: identity("local"),
+            remote_identity: identity("remote"),
+        })
+    }
+}
+
+/// Dialog information document reporting the dialogs of the monitored user agent (`<dialog-info>`)
+///
+/// Printed using its [`Display`](fmt::Display) implementation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialogInfoDocument {
+    /// Incremented with every document sent in a subscription
+    pub version: u32,
+    pub state: DocumentState,
+    /// URI of the monitored user agent
+    pub entity: String,
+    pub dialogs: Vec<DialogElement>,
+}
+
+impl DialogInfoDocument {
+    pub fn new(entity: impl Into<String>, version: u32, state: DocumentState) -> Self {
+        Self {
+            version,
+            state,
+            entity: entity.into(),
+            dialogs: vec![],
+        }
+    }
+
+    pub fn with_dialog(mut self, dialog: DialogElement) -> Self {
+        self.dialogs.push(dialog);
+        self
+    }
+
+    /// Merge a document received later in the subscription into this one
+    ///
+    /// A full document replaces all dialogs, a partial one only the dialogs it contains. Terminated dialogs are
+    /// removed after merging.
+    pub fn apply(&mut self, update: DialogInfoDocument) {
+        self.version = update.version;
+
+        match update.state {
+            DocumentState::Full => self.dialogs = update.dialogs,
+            DocumentState::Partial => {
+                for dialog in update.dialogs {
+                    match self.dialogs.iter_mut().find(|d| d.id == dialog.id) {
+                        Some(existing) => *existing = dialog,
+                        None => self.dialogs.push(dialog),
+                    }
+                }
+            }
+        }
+
+        self.dialogs
+            .retain(|dialog| dialog.state != DialogState::Terminated);
+    }
+
+    /// State of the monitored line, a ringing incoming call takes precedence over other calls
+    pub fn line_state(&self) -> LineState {
+        let active = self
+            .dialogs
+            .iter()
+            .filter(|dialog| dialog.state != DialogState::Terminated);
+
+        let mut state = LineState::Idle;
+
+        for dialog in active {
+            if dialog.state.is_early() && dialog.direction == Some(DialogDirection::Recipient) {
+                return LineState::Ringing;
+            }
+
+            state = LineState::Busy;
+        }
+
+        state
+    }
+
+    pub fn parse(xml: &str) -> Result<Self, BodyError> {
+        let dialog_info = Element::parse_root(xml, "dialog-info")?;
+
+        let version = dialog_info
+            .attribute("version")
+            .and_then(|version| version.parse().ok())
+            .ok_or(BodyError::Missing("version attribute"))?;
+
+        let state = match dialog_info.attribute("state") {
+            Some("full") => DocumentState::Full,
+            Some("partial") => DocumentState::Partial,
+            _ => return Err(BodyError::Missing("state attribute")),
+        };
+
+        let entity = dialog_info
+            .attribute("entity")
+            .ok_or(BodyError::Missing("entity attribute"))?;
+
+        Ok(Self {
+            version,
+            state,
+            entity: entity.into(),
+            dialogs: dialog_info
+                .children("dialog")
+                .map(DialogElement::parse)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl fmt::Display for DialogInfoDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            DocumentState::Full => "full",
+            DocumentState::Partial => "partial",
+        };
+
+        write!(
+            f,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n\
+             <dialog-info xmlns=\"urn:ietf:params:xml:ns:dialog-info\" version=\"{}\" state=\"{state}\" \
+             entity=\"{}\">\r\n",
+            self.version,
+            esc(&self.entity)
+        )?;
+
+        for dialog in &self.dialogs {
+            write!(f, "<dialog id=\"{}\"", esc(&dialog.id))?;
+
+            let attributes = [
+                ("call-id", &dialog.call_id),
+                ("local-tag", &dialog.local_tag),
+                ("remote-tag", &dialog.remote_tag),
+            ];
+
+            for (name, value) in attributes {
+                if let Some(value) = value {
+                    write!(f, " {name}=\"{}\"", esc(value))?;
+                }
+            }
+
+            match dialog.direction {
+                Some(DialogDirection::Initiator) => f.write_str(" direction=\"initiator\"")?,
+                Some(DialogDirection::Recipient) => f.write_str(" direction=\"recipient\"")?,
+                None => {}
+            }
+
+            write!(f, "><state>{}</state>", dialog.state.as_str())?;
+
+            for (participant, identity) in [
+                ("local", &dialog.local_identity),
+                ("remote", &dialog.remote_identity),
+            ] {
+                if let Some(identity) = identity {
+                    write!(
+                        f,
+                        "<{participant}><identity>{}</identity></{participant}>",
+                        esc(identity)
+                    )?;
+                }
+            }
+
+            f.write_str("</dialog>\r\n")?;
+        }
+
+        f.write_str("</dialog-info>\r\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ringing() -> DialogElement {
+        DialogElement {
+            call_id: Some("a84b4c76e66710".into()),
+            local_tag: Some("1928301774".into()),
+            remote_tag: Some("456248".into()),
+            direction: Some(DialogDirection::Recipient),
+            remote_identity: Some("sip:bob@example.com".into()),
+            ..DialogElement::new("as7d900as8", DialogState::Early)
+        }
+    }
+
+    #[test]
+    fn print_parse_dialog_info() {
+        let document = DialogInfoDocument::new("sip:alice@example.com", 3, DocumentState::Full)
+            .with_dialog(ringing());
+
+        let parsed = DialogInfoDocument::parse(&document.to_string()).unwrap();
+
+        assert_eq!(parsed, document);
+        assert_eq!(parsed.line_state(), LineState::Ringing);
+
+        let info = parsed.dialogs[0].dialog_info().unwrap();
+        assert_eq!(info.call_id, "a84b4c76e66710");
+        assert!(info.early);
+    }
+
+    #[test]
+    fn apply_partial() {
+        let mut document = DialogInfoDocument::new("sip:alice@example.com", 0, DocumentState::Full)
+            .with_dialog(ringing());
+
+        let confirmed = DialogElement {
+            state: DialogState::Confirmed,
+            ..ringing()
+        };
+
+        document.apply(
+            DialogInfoDocument::new("sip:alice@example.com", 1, DocumentState::Partial)
+                .with_dialog(confirmed),
+        );
+
+        assert_eq!(document.version, 1);
+        assert_eq!(document.line_state(), LineState::Busy);
+
+        let terminated = DialogElement {
+            state: DialogState::Terminated,
+            ..ringing()
+        };
+
+        document.apply(
+            DialogInfoDocument::new("sip:alice@example.com", 2, DocumentState::Partial)
+                .with_dialog(terminated),
+        );
+
+        assert!(document.dialogs.is_empty());
+        assert_eq!(document.line_state(), LineState::Idle);
+    }
+}
@@ -0,0 +1,214 @@
+//! `message-summary` event package ([RFC3842](https://datatracker.ietf.org/doc/html/rfc3842)), used for message
+//! waiting indication (MWI) of voicemail boxes
+
+use super::xml::BodyError;
+use std::fmt;
+
+/// Name of the event package, used in the `Event` header
+pub const PACKAGE: &str = "message-summary";
+
+/// Content type of message summaries
+pub const CONTENT_TYPE: &str = "application/simple-message-summary";
+
+/// Message context class of voicemails
+pub const VOICE_MESSAGE: &str = "voice-message";
+
+/// Number of messages of a message context class, e.g. `Voice-Message: 2/8 (0/2)`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub new: u32,
+    pub old: u32,
+    pub urgent_new: u32,
+    pub urgent_old: u32,
+}
+
+impl MessageCounts {
+    fn parse(value: &str) -> Option<Self> {
+        fn pair(value: &str) -> Option<(u32, u32)> {
+            let (new, old) = value.split_once('/')?;
+            Some((new.trim().parse().ok()?, old.trim().parse().ok()?))
+        }
+
+        let (counts, urgent) = match value.split_once('(') {
+            Some((counts, urgent)) => (counts, Some(urgent.strip_suffix(')')?)),
+            None => (value, None),
+        };
+
+        let (new, old) = pair(counts)?;
+        let (urgent_new, urgent_old) = match urgent {
+            Some(urgent) => pair(urgent)?,
+            None => (0, 0),
+        };
+
+        Some(Self {
+            new,
+            old,
+            urgent_new,
+            urgent_old,
+        })
+    }
+}
+
+/// Message summary reported by a `message-summary` notification
+///
+/// Printed using its [`Display`](fmt::Display) implementation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSummary {
+    /// New messages are waiting (`Messages-Waiting`)
+    pub messages_waiting: bool,
+    /// URI of the message account, e.g. to call the voicemail box (`Message-Account`)
+    pub account: Option<String>,
+    /// Message counts by message context class, e.g. `voice-message`
+    pub counts: Vec<(String, MessageCounts)>,
+}
+
+impl MessageSummary {
+    pub fn new(messages_waiting: bool) -> Self {
+        Self {
+            messages_waiting,
+            account: None,
+            counts: vec![],
+        }
+    }
+
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    pub fn with_counts(mut self, class: impl Into<String>, counts: MessageCounts) -> Self {
+        self.counts.push((class.into(), counts));
+        self
+    }
+
+    /// Returns the message counts of the given message context class
+    pub fn counts(&self, class: &str) -> Option<MessageCounts> {
+        self.counts
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(class))
+            .map(|(_, counts)| *counts)
+    }
+
+    /// Returns the number of voicemails, if the notifier reported them
+    pub fn voice_messages(&self) -> Option<MessageCounts> {
+        self.counts(VOICE_MESSAGE)
+    }
+
+    pub fn parse(body: &str) -> Result<Self, BodyError> {
+        let mut messages_waiting = None;
+        let mut account = None;
+        let mut counts = vec![];
+
+        for line in body.lines() {
+            let line = line.trim();
+
+            // Optional message headers follow the summary after an empty line
+            if line.is_empty() {
+                if messages_waiting.is_some() {
+                    break;
+                }
+
+                continue;
+            }
+
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+
+            let (name, value) = (name.trim(), value.trim());
+
+            if name.eq_ignore_ascii_case("Messages-Waiting") {
+                messages_waiting = match value.to_ascii_lowercase().as_str() {
+                    "yes" => Some(true),
+                    "no" => Some(false),
+                    _ => return Err(BodyError::Invalid("Messages-Waiting value")),
+                };
+            } else if name.eq_ignore_ascii_case("Message-Account") {
+                account = Some(value.into());
+            } else if let Some(message_counts) = MessageCounts::parse(value) {
+                counts.push((name.to_ascii_lowercase(), message_counts));
+            }
+        }
+
+        Ok(Self {
+            messages_waiting: messages_waiting.ok_or(BodyError::Missing("Messages-Waiting"))?,
+            account,
+            counts,
+        })
+    }
+}
+
+impl fmt::Display for MessageSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages_waiting = if self.messages_waiting { "yes" } else { "no" };
+
+        write!(f, "Messages-Waiting: {messages_waiting}\r\n")?;
+
+        if let Some(account) = &self.account {
+            write!(f, "Message-Account: {account}\r\n")?;
+        }
+
+        for (class, counts) in &self.counts {
+            write!(f, "{class}: {}/{}", counts.new, counts.old)?;
+
+            if counts.urgent_new != 0 || counts.urgent_old != 0 {
+                write!(f, " ({}/{})", counts.urgent_new, counts.urgent_old)?;
+            }
+
+            f.write_str("\r\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn print_parse_message_summary() {
+        let summary = MessageSummary::new(true)
+            .with_account("sip:alice@vmail.example.com")
+            .with_counts(
+                VOICE_MESSAGE,
+                MessageCounts {
+                    new: 2,
+                    old: 8,
+                    urgent_new: 0,
+                    urgent_old: 2,
+                },
+            );
+
+        let parsed = MessageSummary::parse(&summary.to_string()).unwrap();
+
+        assert_eq!(parsed, summary);
+        assert_eq!(parsed.voice_messages().unwrap().new, 2);
+    }
+
+    #[test]
+    fn parse_message_summary_with_headers() {
+        let body = "Messages-Waiting: no\r\n\
+                    Voice-Message: 0/3\r\n\
+                    Fax-Message: 1/0 (1/0)\r\n\
+                    \r\n\
+                    To: <alice@example.com>\r\n\
+                    Subject: Call me: 2/2\r\n";
+
+        let parsed = MessageSummary::parse(body).unwrap();
+
+        assert!(!parsed.messages_waiting);
+        assert_eq!(parsed.account, None);
+        assert_eq!(
+            parsed.voice_messages(),
+            Some(MessageCounts {
+                old: 3,
+                ..MessageCounts::default()
+            })
+        );
+        assert_eq!(parsed.counts("fax-message").unwrap().urgent_new, 1);
+        assert_eq!(parsed.counts.len(), 2);
+
+        assert!(MessageSummary::parse("Voice-Message: 1/0\r\n").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Event subscriptions using SUBSCRIBE & NOTIFY requests
+//!
+//! [RFC6665](https://datatracker.ietf.org/doc/html/rfc6665)
+//!
+//! A [`SubscriptionInitiator`] subscribes to an event package of a remote resource, the created [`Subscription`]
+//! refreshes itself and reports the received notifications. The other side accepts SUBSCRIBE requests using
+//! [`Notifier::accept`] and sends the state of the resource using the created [`Notifier`].
+//!
+//! The [`SubscriptionLayer`] must be added to the endpoint after the [`DialogLayer`](crate::dialog::DialogLayer).
+//!
+//! Bodies of the `presence`, `dialog` and `message-summary` event packages are provided by the [`presence`],
+//! [`dialog_event`] and [`message_summary`] modules.
+
+use bytesstr::BytesStr;
+use parking_lot::Mutex;
+use sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake};
+use sip_types::header::typed::{Event, Expires, SubscriptionState};
+use sip_types::Method;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub mod dialog_event;
+pub mod message_summary;
+mod notifier;
+pub mod presence;
+mod subscriber;
+mod xml;
+
+pub use notifier::{Notifier, NotifierEvent};
+pub use subscriber::{
+    Notification, SubscribeResponse, Subscription, SubscriptionEvent, SubscriptionInitiator,
+};
+pub use xml::BodyError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Core(#[from] sip_core::Error),
+    #[error(transparent)]
+    Header(#[from] sip_types::header::HeaderError),
+    #[error("the subscription has been terminated")]
+    Terminated,
+}
+
+/// Layer receiving NOTIFY requests which arrive before the response of the SUBSCRIBE request creating the
+/// subscription
+#[derive(Default)]
+pub struct SubscriptionLayer {
+    pending: Mutex<HashMap<PendingKey, mpsc::UnboundedSender<IncomingRequest>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PendingKey {
+    call_id: BytesStr,
+    local_tag: BytesStr,
+}
+
+#[async_trait::async_trait]
+impl Layer for SubscriptionLayer {
+    fn name(&self) -> &'static str {
+        "subscription"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        endpoint.add_allow(Method::SUBSCRIBE);
+        endpoint.add_allow(Method::NOTIFY);
+    }
+
+    async fn receive(&self, _: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::NOTIFY {
+            return;
+        }
+
+        let Some(local_tag) = request.base_headers.to.tag.clone() else {
+            return;
+        };
+
+        let key = PendingKey {
+            call_id: request.base_headers.call_id.0.clone(),
+            local_tag,
+        };
+
+        let pending = self.pending.lock();
+
+        if let Some(sender) = pending.get(&key) {
+            let notify = request.inner().take().unwrap();
+
+            if let Err(mpsc::error::SendError(notify)) = sender.send(notify) {
+                *request.inner() = Some(notify);
+            }
+        }
+    }
+}
+
+/// Removes the pending subscription from the [`SubscriptionLayer`] when dropped
+#[derive(Debug)]
+struct PendingGuard {
+    endpoint: Endpoint,
+    key: PendingKey,
+}
+
+impl PendingGuard {
+    fn new(
+        endpoint: Endpoint,
+        key: PendingKey,
+    ) -> (Self, mpsc::UnboundedReceiver<IncomingRequest>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        endpoint
+            .layer::<SubscriptionLayer>()
+            .pending
+            .lock()
+            .insert(key.clone(), sender);
+
+        (Self { endpoint, key }, receiver)
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.endpoint
+            .layer::<SubscriptionLayer>()
+            .pending
+            .lock()
+            .remove(&self.key);
+    }
+}
+
+/// Split the value of an `Event` header into the event package and its `id` parameter
+fn parse_event(event: &Event) -> (&str, Option<&str>) {
+    let mut parts = event.0.split(';');
+    let package = parts.next().unwrap_or_default().trim();
+
+    let id = parts.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("id").then(|| value.trim())
+    });
+
+    (package, id)
+}
+
+/// Returns if both `Event` headers identify the same subscription, by event package and `id` parameter
+fn event_matches(a: &Event, b: &Event) -> bool {
+    let (a_package, a_id) = parse_event(a);
+    let (b_package, b_id) = parse_event(b);
+
+    a_package.eq_ignore_ascii_case(b_package) && a_id == b_id
+}
+
+/// Returns if the request belongs to the subscription with the given `Event` header
+fn request_matches(request: &IncomingRequest, event: &Event) -> bool {
+    request
+        .headers
+        .get_named::<Event>()
+        .is_ok_and(|requested| event_matches(&requested, event))
+}
+
+/// Returns the duration requested using the `Expires` header of a SUBSCRIBE request
+fn requested_expires(request: &IncomingRequest) -> Option<Duration> {
+    request
+        .headers
+        .get_named::<Expires>()
+        .ok()
+        .map(|expires| Duration::from_secs(expires.0.into()))
+}
+
+/// Returns the state of the subscription reported by a NOTIFY request
+fn notify_state(notify: &IncomingRequest) -> Option<SubscriptionState> {
+    notify.headers.get_named::<SubscriptionState>().ok()
+}
+
+/// Duration after which a subscription with the given expiry must be refreshed
+fn refresh_interval(expires: Duration) -> Duration {
+    // Refresh ahead of the expiry by a fifth of it, but at least 10 seconds. Short subscriptions halfway through.
+    if expires <= Duration::from_secs(20) {
+        return expires / 2;
+    }
+
+    expires - (expires / 5).max(Duration::from_secs(10))
+}
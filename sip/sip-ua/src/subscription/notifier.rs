@@ -0,0 +1,281 @@
+use super::{request_matches, requested_expires, Error};
+use crate::dialog::{Dialog, Usage, UsageGuard};
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use sip_core::transaction::TsxResponse;
+use sip_core::{Endpoint, IncomingRequest, MayTake, Request};
+use sip_types::header::typed::{
+    Contact, ContentType, Event, EventReasonValue, Expires, SubStateValue, SubscriptionState,
+};
+use sip_types::{CodeKind, Method, StatusCode};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::mpsc::{self, error::SendError};
+use tokio::time::{sleep_until, Instant};
+
+/// Subscription accepted from a subscriber, used to notify it about the state of the resource
+/// ([RFC6665 Section 4.2](https://datatracker.ietf.org/doc/html/rfc6665#section-4.2))
+///
+/// Must be driven using [`receive`](Self::receive) to handle refreshes of the subscriber.
+#[derive(Debug)]
+pub struct Notifier {
+    event: Event,
+    max_expires: Duration,
+    expires_at: Instant,
+    terminated: bool,
+
+    subscribes: mpsc::Receiver<IncomingRequest>,
+
+    // drop usage before dialog
+    _usage_guard: UsageGuard,
+    pub dialog: Arc<Dialog>,
+}
+
+#[derive(Debug)]
+pub enum NotifierEvent {
+    /// The subscriber refreshed the subscription, which now expires after the given duration
+    ///
+    /// The current state should be sent using [`Notifier::notify`].
+    Refreshed(Duration),
+    /// The subscriber ended the subscription, the final state must be sent using [`Notifier::terminate`]
+    Unsubscribed,
+    /// The subscriber didn't refresh the subscription in time, the final state must be sent using
+    /// [`Notifier::terminate`] with [`EventReasonValue::Timeout`]
+    Expired,
+}
+
+impl Notifier {
+    /// Accept a SUBSCRIBE request received outside of a dialog with a `200 OK` response
+    ///
+    /// The duration requested by the subscriber is limited to `max_expires`, which is also used if the subscriber
+    /// didn't request one. The current state of the resource must be sent right away using [`notify`](Self::notify),
+    /// or using [`terminate`](Self::terminate) if the subscriber only fetches it with an expiry of zero.
+    pub async fn accept(
+        endpoint: &Endpoint,
+        mut subscribe: IncomingRequest,
+        local_contact: Contact,
+        max_expires: Duration,
+    ) -> Result<Self, Error> {
+        let event: Event = subscribe.headers.get_named()?;
+        let expires = requested_expires(&subscribe)
+            .unwrap_or(max_expires)
+            .min(max_expires);
+
+        let transaction = endpoint.create_server_tsx(&mut subscribe);
+        let dialog = Dialog::new_server(endpoint.clone(), &subscribe, local_contact)?;
+
+        let mut response = dialog.create_response(&subscribe, StatusCode::OK, None)?;
+        response
+            .msg
+            .headers
+            .insert_named(&Expires(expires.as_secs() as u32));
+
+        transaction.respond(response).await?;
+
+        let (subscribe_sink, subscribes) = mpsc::channel(4);
+
+        let usage_guard = dialog.register_usage(NotifierUsage {
+            event: event.clone(),
+            subscribe_sink,
+        });
+
+        Ok(Self {
+            event,
+            max_expires,
+            expires_at: Instant::now() + expires,
+            terminated: false,
+            subscribes,
+            _usage_guard: usage_guard,
+            dialog: Arc::new(dialog),
+        })
+    }
+
+    /// Reject a SUBSCRIBE request received outside of a dialog, e.g. with `489 Bad Event` if the event package
+    /// isn't supported
+    pub async fn reject(
+        endpoint: &Endpoint,
+        mut subscribe: IncomingRequest,
+        code: StatusCode,
+    ) -> Result<(), Error> {
+        let transaction = endpoint.create_server_tsx(&mut subscribe);
+        let response = endpoint.create_response(&subscribe, code, None);
+
+        Ok(transaction.respond(response).await?)
+    }
+
+    /// The `Event` header identifying the subscription
+    pub fn event(&self) -> &Event {
+        &self.event
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Create a NOTIFY request reporting the given state of the subscription, without a body
+    ///
+    /// Active and pending states include the remaining duration of the subscription.
+    pub fn create_notify(&self, state: SubStateValue) -> Request {
+        let mut request = self.dialog.create_request(Method::NOTIFY);
+
+        let state = match state {
+            SubStateValue::Terminated => SubscriptionState::new(state),
+            SubStateValue::Active | SubStateValue::Pending => {
+                let remaining = self.expires_at.saturating_duration_since(Instant::now());
+
+                SubscriptionState::new(state).with_expires(remaining.as_secs() as u32)
+            }
+        };
+
+        request.headers.insert_named(&self.dialog.local_contact);
+        request.headers.insert_named(&self.event);
+        request.headers.insert_named(&state);
+
+        request
+    }
+
+    /// Send the state of the resource to the subscriber, returns the response to the NOTIFY request
+    ///
+    /// The subscription is terminated if the subscriber doesn't know it anymore.
+    pub async fn notify(
+        &mut self,
+        content_type: impl Into<BytesStr>,
+        body: impl Into<Bytes>,
+    ) -> Result<TsxResponse, Error> {
+        if self.terminated {
+            return Err(Error::Terminated);
+        }
+
+        let mut request = self.create_notify(SubStateValue::Active);
+        request
+            .headers
+            .insert_named(&ContentType(content_type.into()));
+        request.body = body.into();
+
+        let response = self.send(request).await?;
+
+        if let 481 | 408 = response.line.code.into_u16() {
+            self.terminated = true;
+        }
+
+        Ok(response)
+    }
+
+    /// Terminate the subscription by sending a NOTIFY request with the final state of the resource, if one is given
+    pub async fn terminate(
+        &mut self,
+        reason: EventReasonValue,
+        content: Option<(ContentType, Bytes)>,
+    ) -> Result<(), Error> {
+        if self.terminated {
+            return Ok(());
+        }
+
+        self.terminated = true;
+
+        let mut request = self.dialog.create_request(Method::NOTIFY);
+
+        request.headers.insert_named(&self.dialog.local_contact);
+        request.headers.insert_named(&self.event);
+        request
+            .headers
+            .insert_named(&SubscriptionState::new(SubStateValue::Terminated).with_reason(reason));
+
+        if let Some((content_type, body)) = content {
+            request.headers.insert_named(&content_type);
+            request.body = body;
+        }
+
+        self.send(request).await?;
+
+        Ok(())
+    }
+
+    /// Wait for the subscriber to refresh or end the subscription
+    pub async fn receive(&mut self) -> Result<NotifierEvent, Error> {
+        if self.terminated {
+            return Err(Error::Terminated);
+        }
+
+        let subscribe = select! {
+            subscribe = self.subscribes.recv() => subscribe.ok_or(Error::Terminated)?,
+            _ = sleep_until(self.expires_at) => return Ok(NotifierEvent::Expired),
+        };
+
+        self.handle_subscribe(subscribe).await
+    }
+
+    async fn handle_subscribe(
+        &mut self,
+        mut subscribe: IncomingRequest,
+    ) -> Result<NotifierEvent, Error> {
+        let expires = requested_expires(&subscribe)
+            .unwrap_or(self.max_expires)
+            .min(self.max_expires);
+
+        let transaction = self.dialog.endpoint.create_server_tsx(&mut subscribe);
+
+        let mut response = self
+            .dialog
+            .create_response(&subscribe, StatusCode::OK, None)?;
+        response
+            .msg
+            .headers
+            .insert_named(&Expires(expires.as_secs() as u32));
+
+        transaction.respond(response).await?;
+
+        self.expires_at = Instant::now() + expires;
+
+        if expires.is_zero() {
+            Ok(NotifierEvent::Unsubscribed)
+        } else {
+            Ok(NotifierEvent::Refreshed(expires))
+        }
+    }
+
+    async fn send(&self, request: Request) -> Result<TsxResponse, Error> {
+        let mut target_tp_info = self.dialog.target_tp_info.lock().await;
+
+        let mut transaction = self
+            .dialog
+            .endpoint
+            .send_request(request, &mut target_tp_info)
+            .await?;
+
+        drop(target_tp_info);
+
+        let response = transaction.receive_final().await?;
+
+        if response.line.code.kind() != CodeKind::Success {
+            log::debug!("NOTIFY request failed with {:?}", response.line.code);
+        }
+
+        Ok(response)
+    }
+}
+
+struct NotifierUsage {
+    event: Event,
+    subscribe_sink: mpsc::Sender<IncomingRequest>,
+}
+
+#[async_trait::async_trait]
+impl Usage for NotifierUsage {
+    fn name(&self) -> &'static str {
+        "notifier-usage"
+    }
+
+    async fn receive(&self, _: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::SUBSCRIBE || !request_matches(&request, &self.event) {
+            return;
+        }
+
+        let subscribe = request.inner().take().unwrap();
+
+        if let Err(SendError(subscribe)) = self.subscribe_sink.send(subscribe).await {
+            *request.inner() = Some(subscribe);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! `presence` event package ([RFC3856](https://datatracker.ietf.org/doc/html/rfc3856)) using the Presence
+//! Information Data Format ([RFC3863](https://datatracker.ietf.org/doc/html/rfc3863))
+
+use super::xml::{esc, BodyError, Element};
+use std::fmt;
+
+/// Name of the event package, used in the `Event` header
+pub const PACKAGE: &str = "presence";
+
+/// Content type of PIDF documents
+pub const CONTENT_TYPE: &str = "application/pidf+xml";
+
+/// Basic availability of a presence tuple
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BasicStatus {
+    /// Willing to communicate
+    Open,
+    /// Unwilling or unable to communicate
+    Closed,
+}
+
+/// Presence of one communication means of the presentity (`<tuple>`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tuple {
+    pub id: String,
+    pub status: BasicStatus,
+    /// Address to use to contact the presentity using this tuple
+    pub contact: Option<String>,
+    pub note: Option<String>,
+}
+
+impl Tuple {
+    pub fn new(id: impl Into<String>, status: BasicStatus) -> Self {
+        Self {
+            id: id.into(),
+            status,
+            contact: None,
+            note: None,
+        }
+    }
+
+    pub fn with_contact(mut self, contact: impl Into<String>) -> Self {
+        self.contact = Some(contact.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+}
+
+/// PIDF document describing the presence of a presentity (`<presence>`)
+///
+/// Printed using its [`Display`](fmt::Display) implementation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresenceDocument {
+    /// URI of the presentity, e.g. `pres:alice@example.com`
+    pub entity: String,
+    pub tuples: Vec<Tuple>,
+    pub notes: Vec<String>,
+}
+
+impl PresenceDocument {
+    pub fn new(entity: impl Into<String>) -> Self {
+        Self {
+            entity: entity.into(),
+            tuples: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn with_tuple(mut self, tuple: Tuple) -> Self {
+        self.tuples.push(tuple);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Returns if any tuple of the presentity is open
+    pub fn is_open(&self) -> bool {
+        self.tuples
+            .iter()
+            .any(|tuple| tuple.status == BasicStatus::Open)
+    }
+
+    pub fn parse(xml: &str) -> Result<Self, BodyError> {
+        let presence = Element::parse_root(xml, "presence")?;
+
+        let entity = presence
+            .attribute("entity")
+            .ok_or(BodyError::Missing("entity attribute"))?;
+
+        let tuples = presence
+            .children("tuple")
+            .map(|tuple| {
+                let id = tuple
+                    .attribute("id")
+                    .ok_or(BodyError::Missing("tuple id attribute"))?;
+
+                let basic = tuple
+                    .child("status")
+                    .and_then(|status| status.child_text("basic"))
+                    .ok_or(BodyError::Missing("basic status"))?;
+
+                Ok(Tuple {
+                    id: id.into(),
+                    status: if basic.eq_ignore_ascii_case("open") {
+                        BasicStatus::Open
+                    } else {
+                        BasicStatus::Closed
+                    },
+                    contact: tuple.child_text("contact").map(Into::into),
+                    note: tuple.child_text("note").map(Into::into),
+                })
+            })
+            .collect::<Result<_, BodyError>>()?;
+
+        Ok(Self {
+            entity: entity.into(),
+            tuples,
+            notes: presence
+                .children("note")
+                .map(|note| note.text().into())
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Display for PresenceDocument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n\
+             <presence xmlns=\"urn:ietf:params:xml:ns:pidf\" entity=\"{}\">\r\n",
+            esc(&self.entity)
+        )?;
+
+        for tuple in &self.tuples {
+            let basic = match tuple.status {
+                BasicStatus::Open => "open",
+                BasicStatus::Closed => "closed",
+            };
+
+            write!(
+                f,
+                "<tuple id=\"{}\"><status><basic>{basic}</basic></status>",
+                esc(&tuple.id)
+            )?;
+
+            if let Some(contact) = &tuple.contact {
+                write!(f, "<contact>{}</contact>", esc(contact))?;
+            }
+
+            if let Some(note) = &tuple.note {
+                write!(f, "<note>{}</note>", esc(note))?;
+            }
+
+            f.write_str("</tuple>\r\n")?;
+        }
+
+        for note in &self.notes {
+            write!(f, "<note>{}</note>\r\n", esc(note))?;
+        }
+
+        f.write_str("</presence>\r\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn print_parse_presence() {
+        let document = PresenceDocument::new("pres:alice@example.com")
+            .with_tuple(
+                Tuple::new("phone", BasicStatus::Open)
+                    .with_contact("sip:alice@192.0.2.1")
+                    .with_note("In the office"),
+            )
+            .with_tuple(Tuple::new("mobile", BasicStatus::Closed))
+            .with_note("Back at <5>");
+
+        let parsed = PresenceDocument::parse(&document.to_string()).unwrap();
+
+        assert_eq!(parsed, document);
+        assert!(parsed.is_open());
+    }
+
+    #[test]
+    fn parse_prefixed_presence() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<p:presence xmlns:p="urn:ietf:params:xml:ns:pidf" entity="pres:bob@example.com">
+  <p:tuple id="t1">
+    <p:status><p:basic>closed</p:basic></p:status>
+  </p:tuple>
+</p:presence>"#;
+
+        let parsed = PresenceDocument::parse(xml).unwrap();
+
+        assert_eq!(parsed.entity, "pres:bob@example.com");
+        assert_eq!(parsed.tuples, [Tuple::new("t1", BasicStatus::Closed)]);
+        assert!(!parsed.is_open());
+    }
+}
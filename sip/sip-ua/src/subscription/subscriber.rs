@@ -0,0 +1,1008 @@
+use super::{notify_state, refresh_interval, request_matches, Error, PendingGuard, PendingKey};
+use crate::dialog::{ClientDialogBuilder, Dialog, Usage, UsageGuard};
+use crate::retry::{Backoff, RetryPolicy};
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use sip_core::transaction::consts::T1;
+use sip_core::transaction::TsxResponse;
+use sip_core::{Endpoint, IncomingRequest, MayTake, Request};
+use sip_types::header::typed::{
+    Accept, Contact, ContentType, Event, Expires, Routing, SubStateValue, SubscriptionState,
+};
+use sip_types::header::HeaderError;
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{CodeKind, Method, Name, StatusCode};
+use std::collections::VecDeque;
+use std::future::pending;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::mpsc::{self, error::SendError};
+use tokio::time::{sleep_until, Instant};
+
+/// Subscribes to an event package of a remote resource using a SUBSCRIBE request
+/// ([RFC6665 Section 4.1](https://datatracker.ietf.org/doc/html/rfc6665#section-4.1))
+#[derive(Debug)]
+pub struct SubscriptionInitiator {
+    dialog_builder: ClientDialogBuilder,
+    event: Event,
+    accept: Vec<Accept>,
+    expires: Duration,
+    retry_policy: RetryPolicy,
+
+    /// NOTIFY requests received before the response to the SUBSCRIBE request
+    early_notifies: mpsc::UnboundedReceiver<IncomingRequest>,
+    _pending_guard: PendingGuard,
+}
+
+/// Response to a SUBSCRIBE request sent using [`SubscriptionInitiator::send_subscribe`]
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum SubscribeResponse {
+    /// The subscription was created, the notifier sends its first NOTIFY request right away
+    Subscribed(Subscription, TsxResponse),
+    /// The SUBSCRIBE request was rejected, e.g. with `489 Bad Event` for an unsupported event package
+    ///
+    /// Challenges can be answered by sending another request created using
+    /// [`create_subscribe`](SubscriptionInitiator::create_subscribe).
+    Failure(TsxResponse),
+}
+
+impl SubscriptionInitiator {
+    /// Create an initiator subscribing to the `event` package of `target`, e.g. `presence`
+    ///
+    /// The [`SubscriptionLayer`](super::SubscriptionLayer) must be added to the endpoint.
+    pub fn new(
+        endpoint: Endpoint,
+        local_addr: NameAddr,
+        local_contact: Contact,
+        target: SipUri,
+        event: Event,
+    ) -> Self {
+        let dialog_builder = ClientDialogBuilder::new(endpoint, local_addr, local_contact, target);
+
+        let (pending_guard, early_notifies) = PendingGuard::new(
+            dialog_builder.endpoint.clone(),
+            PendingKey {
+                call_id: dialog_builder.call_id.0.clone(),
+                local_tag: dialog_builder
+                    .local_fromto
+                    .tag
+                    .clone()
+                    .expect("builder always creates a local tag"),
+            },
+        );
+
+        Self {
+            dialog_builder,
+            event,
+            accept: vec![],
+            expires: Duration::from_secs(3600),
+            retry_policy: RetryPolicy::default(),
+            early_notifies,
+            _pending_guard: pending_guard,
+        }
+    }
+
+    /// Requested duration of the subscription, defaults to one hour
+    ///
+    /// The notifier may choose a shorter duration. A duration of zero fetches the current state of the resource
+    /// using a single NOTIFY request.
+    pub fn with_expires(mut self, expires: Duration) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    /// Set the policy used to retry refreshing the subscription when a refresh fails
+    ///
+    /// Refreshes are retried until the subscription expires, unless the notifier responds with
+    /// `481 Call/Transaction Does Not Exist`.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Send the SUBSCRIBE request via the given route set, e.g. a single `Route` to an outbound proxy
+    pub fn with_route_set(mut self, route_set: Vec<Routing>) -> Self {
+        self.dialog_builder.route_set = route_set;
+        self
+    }
+
+    /// Add a body type accepted in NOTIFY requests, e.g. `application/pidf+xml`
+    pub fn with_accept(mut self, accept: impl Into<BytesStr>) -> Self {
+        self.accept.push(Accept(accept.into()));
+        self
+    }
+
+    pub fn create_subscribe(&mut self) -> Request {
+        self.dialog_builder.local_cseq += 1;
+
+        let mut request = self.dialog_builder.create_request(Method::SUBSCRIBE);
+
+        request.headers.insert_named(&self.event);
+        request
+            .headers
+            .insert_named(&Expires(self.expires.as_secs() as u32));
+
+        if !self.accept.is_empty() {
+            request.headers.insert_named(&self.accept);
+        }
+
+        request
+    }
+
+    /// Send the SUBSCRIBE request and wait for its final response
+    ///
+    /// NOTIFY requests received before the response are reported by the created subscription.
+    pub async fn send_subscribe(&mut self, request: Request) -> Result<SubscribeResponse, Error> {
+        let mut transaction = self
+            .dialog_builder
+            .endpoint
+            .send_request(request, &mut self.dialog_builder.target_tp_info)
+            .await?;
+
+        let mut early = VecDeque::new();
+
+        let response = loop {
+            select! {
+                response = transaction.receive_final() => break response?,
+                Some(notify) = self.early_notifies.recv() => early.push_back(notify),
+            }
+        };
+
+        if response.line.code.kind() != CodeKind::Success {
+            for notify in early {
+                reject(&self.dialog_builder.endpoint, notify).await;
+            }
+
+            return Ok(SubscribeResponse::Failure(response));
+        }
+
+        if response.base_headers.to.tag.is_none() {
+            return Err(HeaderError::malformed_adhoc(Name::TO, "missing tag parameter").into());
+        }
+
+        // The notifier may shorten the subscription
+        let expires = response
+            .headers
+            .get_named::<Expires>()
+            .map(|expires| Duration::from_secs(expires.0.into()))
+            .unwrap_or(self.expires);
+
+        let dialog = self.dialog_builder.create_dialog_from_response(&response)?;
+
+        let mut subscription = Subscription::new(
+            dialog,
+            self.event.clone(),
+            self.accept.clone(),
+            expires,
+            Backoff::new(self.retry_policy.clone()),
+            early,
+        );
+
+        // NOTIFY requests may have arrived while the response was handled, later ones are received by the dialog
+        while let Ok(notify) = self.early_notifies.try_recv() {
+            subscription.backlog.push_back(notify);
+        }
+
+        Ok(SubscribeResponse::Subscribed(subscription, response))
+    }
+}
+
+/// Subscription created using a [`SubscriptionInitiator`]
+///
+/// Must be driven using [`receive`](Self::receive), which also refreshes the subscription before it expires.
+#[derive(Debug)]
+pub struct Subscription {
+    event: Event,
+    accept: Vec<Accept>,
+    expires: Duration,
+    /// `None` if the subscription isn't refreshed anymore, e.g. after unsubscribing
+    refresh_at: Option<Instant>,
+    /// The subscription is considered terminated when this passes without a successful refresh
+    expires_at: Instant,
+    backoff: Backoff,
+    terminated: bool,
+
+    /// NOTIFY requests received before the subscription was created
+    backlog: VecDeque<IncomingRequest>,
+    notifies: mpsc::Receiver<IncomingRequest>,
+
+    // drop usage before dialog
+    _usage_guard: UsageGuard,
+    pub dialog: Arc<Dialog>,
+}
+
+/// NOTIFY request received by a [`Subscription`], it has already been responded to
+#[derive(Debug)]
+pub struct Notification {
+    pub state: SubscriptionState,
+    pub content_type: Option<ContentType>,
+    pub body: Bytes,
+}
+
+impl Notification {
+    /// Returns if this is the last notification of the subscription
+    pub fn is_terminated(&self) -> bool {
+        self.state.state == SubStateValue::Terminated
+    }
+}
+
+#[derive(Debug)]
+pub enum SubscriptionEvent {
+    Notification(Notification),
+    /// The subscription has ended without a final notification, e.g. because refreshing it failed
+    ///
+    /// Also returned by every call to [`Subscription::receive`] after the subscription has been terminated.
+    Terminated,
+}
+
+impl Subscription {
+    fn new(
+        dialog: Dialog,
+        event: Event,
+        accept: Vec<Accept>,
+        expires: Duration,
+        backoff: Backoff,
+        backlog: VecDeque<IncomingRequest>,
+    ) -> Self {
+        let (notify_sink, notifies) = mpsc::channel(4);
+
+        let usage_guard = dialog.register_usage(SubscriberUsage {
+            event: event.clone(),
+            notify_sink,
+        });
+
+        let mut subscription = Self {
+            event,
+            accept,
+            expires,
+            refresh_at: None,
+            expires_at: Instant::now(),
+            backoff,
+            terminated: false,
+            backlog,
+            notifies,
+            _usage_guard: usage_guard,
+            dialog: Arc::new(dialog),
+        };
+
+        subscription.set_expires(expires);
+        subscription
+    }
+
+    /// The `Event` header identifying the subscription
+    pub fn event(&self) -> &Event {
+        &self.event
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Wait for the next NOTIFY request, refreshing the subscription when necessary
+    pub async fn receive(&mut self) -> Result<SubscriptionEvent, Error> {
+        loop {
+            if self.terminated {
+                return Ok(SubscriptionEvent::Terminated);
+            }
+
+            let notify = if let Some(notify) = self.backlog.pop_front() {
+                notify
+            } else {
+                select! {
+                    notify = self.notifies.recv() => match notify {
+                        Some(notify) => notify,
+                        None => {
+                            self.terminated = true;
+                            continue;
+                        }
+                    },
+                    _ = sleep_until_opt(self.refresh_at) => {
+                        self.refresh().await;
+                        continue;
+                    }
+                    _ = sleep_until(self.expires_at) => {
+                        log::debug!("Subscription expired");
+                        self.terminated = true;
+                        continue;
+                    }
+                }
+            };
+
+            if let Some(notification) = self.handle_notify(notify).await? {
+                return Ok(SubscriptionEvent::Notification(notification));
+            }
+        }
+    }
+
+    /// End the subscription by sending a SUBSCRIBE request with an expiry of zero
+    ///
+    /// The notifier responds with a final NOTIFY request, which is reported by [`receive`](Self::receive).
+    pub async fn unsubscribe(&mut self) -> Result<(), Error> {
+        let request = self.create_subscribe(Duration::ZERO);
+        let response = self.send(request).await?;
+
+        if response.line.code.kind() == CodeKind::Success {
+            self.expires = Duration::ZERO;
+            self.set_expires(self.expires);
+        } else {
+            // The notifier already forgot the subscription
+            self.terminated = true;
+        }
+
+        Ok(())
+    }
+
+    /// Schedule the next refresh for a subscription which expires after the given duration
+    ///
+    /// Subscriptions with an expiry of zero are not refreshed, only their final NOTIFY request is awaited.
+    fn set_expires(&mut self, expires: Duration) {
+        let now = Instant::now();
+
+        if expires.is_zero() {
+            // RFC6665 Section 4.1.2.4, give up waiting for the final NOTIFY after 64*T1
+            self.refresh_at = None;
+            self.expires_at = now + 64 * T1;
+        } else {
+            self.refresh_at = Some(now + refresh_interval(expires));
+            self.expires_at = now + expires;
+        }
+    }
+
+    fn create_subscribe(&self, expires: Duration) -> Request {
+        let mut request = self.dialog.create_request(Method::SUBSCRIBE);
+
+        request.headers.insert_named(&self.dialog.local_contact);
+        request.headers.insert_named(&self.event);
+        request
+            .headers
+            .insert_named(&Expires(expires.as_secs() as u32));
+
+        if !self.accept.is_empty() {
+            request.headers.insert_named(&self.accept);
+        }
+
+        request
+    }
+
+    async fn send(&self, request: Request) -> Result<TsxResponse, Error> {
+        let mut target_tp_info = self.dialog.target_tp_info.lock().await;
+
+        let mut transaction = self
+            .dialog
+            .endpoint
+            .send_request(request, &mut target_tp_info)
+            .await?;
+
+        drop(target_tp_info);
+
+        Ok(transaction.receive_final().await?)
+    }
+
+    /// Refresh the subscription, failed refreshes are retried using the [`RetryPolicy`] until it expires
+    async fn refresh(&mut self) {
+        let request = self.create_subscribe(self.expires);
+
+        let response = match self.send(request).await {
+            Ok(response) if response.line.code.kind() == CodeKind::Success => {
+                if let Ok(expires) = response.headers.get_named::<Expires>() {
+                    self.expires = Duration::from_secs(expires.0.into());
+                }
+
+                self.backoff.on_success();
+                self.set_expires(self.expires);
+                return;
+            }
+            Ok(response)
+                if response.line.code == StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST =>
+            {
+                log::warn!("Notifier no longer knows the subscription");
+                self.terminated = true;
+                return;
+            }
+            Ok(response) => {
+                log::warn!(
+                    "Refreshing subscription failed with {:?}",
+                    response.line.code
+                );
+                Some(response)
+            }
+            Err(e) => {
+                log::warn!("Refreshing subscription failed, {e}");
+                None
+            }
+        };
+
+        // The subscription stays valid until it expires (RFC6665 Section 4.1.2.2)
+        let now = Instant::now();
+
+        self.refresh_at = self
+            .backoff
+            .on_failure(response.as_ref())
+            .map(|delay| now + delay)
+            .filter(|retry_at| *retry_at < self.expires_at);
+    }
+
+    /// Respond to the NOTIFY request, returns `None` if it doesn't belong to the subscription
+    async fn handle_notify(
+        &mut self,
+        mut notify: IncomingRequest,
+    ) -> Result<Option<Notification>, Error> {
+        // NOTIFY requests received before the dialog was created may come from another fork of the SUBSCRIBE
+        if notify.base_headers.from.tag != self.dialog.peer_fromto.tag {
+            reject(&self.dialog.endpoint, notify).await;
+            return Ok(None);
+        }
+
+        let Some(state) = notify_state(&notify) else {
+            let transaction = self.dialog.endpoint.create_server_tsx(&mut notify);
+            let response = self
+                .dialog
+                .create_response(&notify, StatusCode::BAD_REQUEST, None)?;
+            transaction.respond(response).await?;
+
+            return Ok(None);
+        };
+
+        let transaction = self.dialog.endpoint.create_server_tsx(&mut notify);
+        let response = self.dialog.create_response(&notify, StatusCode::OK, None)?;
+        transaction.respond(response).await?;
+
+        match state.state {
+            SubStateValue::Terminated => self.terminated = true,
+            SubStateValue::Active | SubStateValue::Pending => {
+                // Fetches and ended subscriptions only wait for the final NOTIFY
+                if let Some(expires) = state.expires.filter(|_| !self.expires.is_zero()) {
+                    self.set_expires(Duration::from_secs(expires.into()));
+                }
+            }
+        }
+
+        Ok(Some(Notification {
+            state,
+            content_type: notify.headers.get_named().ok(),
+            body: notify.body,
+        }))
+    }
+}
+
+async fn sleep_until_opt(at: Option<Instant>) {
+    match at {
+        Some(at) => sleep_until(at).await,
+        None => pending().await,
+    }
+}
+
+/// Respond to a NOTIFY request that doesn't belong to any subscription
+async fn reject(endpoint: &Endpoint, mut notify: IncomingRequest) {
+    let transaction = endpoint.create_server_tsx(&mut notify);
+    let response = endpoint.create_response(
+        &notify,
+        StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST,
+        None,
+    );
+
+    if let Err(e) = transaction.respond(response).await {
+        log::warn!("Failed to reject NOTIFY request, {e}");
+    }
+}
+
+struct SubscriberUsage {
+    event: Event,
+    notify_sink: mpsc::Sender<IncomingRequest>,
+}
+
+#[async_trait::async_trait]
+impl Usage for SubscriberUsage {
+    fn name(&self) -> &'static str {
+        "subscriber-usage"
+    }
+
+    async fn receive(&self, _: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::NOTIFY || !request_matches(&request, &self.event) {
+            return;
+        }
+
+        let notify = request.inner().take().unwrap();
+
+        if let Err(SendError(notify)) = self.notify_sink.send(notify).await {
+            *request.inner() = Some(notify);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dialog::DialogLayer;
+    use crate::subscription::SubscriptionLayer;
+    use sip_core::transaction::ClientTsx;
+    use sip_core::transport::udp::Udp;
+    use sip_core::{EndpointBuilder, Layer};
+    use std::net::Ipv4Addr;
+    use tokio::time::sleep;
+
+    const EXPIRES: Duration = Duration::from_secs(4);
+
+    /// Passes SUBSCRIBE requests received outside of a dialog to the test
+    struct Subscribes(mpsc::UnboundedSender<IncomingRequest>);
+
+    #[async_trait::async_trait]
+    impl Layer for Subscribes {
+        fn name(&self) -> &'static str {
+            "test-subscribes"
+        }
+
+        async fn receive(&self, _: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+            if request.line.method == Method::SUBSCRIBE {
+                let _ = self.0.send(request.inner().take().unwrap());
+            }
+        }
+    }
+
+    /// Passes requests received inside a dialog to the test
+    struct DialogRequests(mpsc::UnboundedSender<IncomingRequest>);
+
+    #[async_trait::async_trait]
+    impl Usage for DialogRequests {
+        fn name(&self) -> &'static str {
+            "test-dialog-requests"
+        }
+
+        async fn receive(&self, _: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+            let _ = self.0.send(request.inner().take().unwrap());
+        }
+    }
+
+    async fn bind(builder: &mut EndpointBuilder) -> SipUri {
+        let udp = Udp::spawn(builder, (Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+
+        format!("sip:{}", udp.bound()).parse().unwrap()
+    }
+
+    /// Endpoint accepting subscriptions, controlled by the test
+    struct Peer {
+        endpoint: Endpoint,
+        uri: SipUri,
+        subscribes: mpsc::UnboundedReceiver<IncomingRequest>,
+    }
+
+    /// Create a notifier and an initiator subscribing to its `presence` package
+    async fn setup(expires: Duration, policy: RetryPolicy) -> (Peer, SubscriptionInitiator) {
+        let (subscribes, subscribes_rx) = mpsc::unbounded_channel();
+
+        let mut builder = Endpoint::builder();
+        builder.add_layer(DialogLayer::default());
+        builder.add_layer(Subscribes(subscribes));
+        let notifier_uri = bind(&mut builder).await;
+
+        let peer = Peer {
+            endpoint: builder.build(),
+            uri: notifier_uri.clone(),
+            subscribes: subscribes_rx,
+        };
+
+        let mut builder = Endpoint::builder();
+        builder.add_layer(DialogLayer::default());
+        builder.add_layer(SubscriptionLayer::default());
+        let subscriber_uri = bind(&mut builder).await;
+
+        let initiator = SubscriptionInitiator::new(
+            builder.build(),
+            NameAddr::uri(subscriber_uri.clone()),
+            Contact::new(NameAddr::uri(subscriber_uri)),
+            notifier_uri,
+            Event::new("presence"),
+        )
+        .with_expires(expires)
+        .with_retry_policy(policy);
+
+        (peer, initiator)
+    }
+
+    /// Notifier side of a subscription, or of one fork of it
+    struct Notifier {
+        _usage_guard: UsageGuard,
+        dialog: Arc<Dialog>,
+        requests: mpsc::UnboundedReceiver<IncomingRequest>,
+    }
+
+    impl Notifier {
+        fn new(peer: &Peer, subscribe: &IncomingRequest) -> Self {
+            let dialog = Dialog::new_server(
+                peer.endpoint.clone(),
+                subscribe,
+                Contact::new(NameAddr::uri(peer.uri.clone())),
+            )
+            .unwrap();
+
+            let (requests, requests_rx) = mpsc::unbounded_channel();
+
+            Self {
+                _usage_guard: dialog.register_usage(DialogRequests(requests)),
+                dialog: Arc::new(dialog),
+                requests: requests_rx,
+            }
+        }
+
+        async fn respond(&self, mut request: IncomingRequest, code: StatusCode, expires: Duration) {
+            let transaction = self.dialog.endpoint.create_server_tsx(&mut request);
+            let mut response = self.dialog.create_response(&request, code, None).unwrap();
+            response
+                .msg
+                .headers
+                .insert_named(&Expires(expires.as_secs() as u32));
+
+            transaction.respond(response).await.unwrap();
+        }
+
+        /// Wait for the next request of the subscriber, e.g. a refresh
+        async fn receive(&mut self) -> IncomingRequest {
+            self.requests.recv().await.unwrap()
+        }
+
+        /// Send a NOTIFY request, its response is received using the returned transaction
+        async fn notify(&self, state: SubscriptionState) -> ClientTsx {
+            let mut request = self.dialog.create_request(Method::NOTIFY);
+            request.headers.insert_named(&self.dialog.local_contact);
+            request.headers.insert_named(&Event::new("presence"));
+            request.headers.insert_named(&state);
+
+            let mut target_tp_info = self.dialog.target_tp_info.lock().await;
+
+            self.dialog
+                .endpoint
+                .send_request(request, &mut target_tp_info)
+                .await
+                .unwrap()
+        }
+    }
+
+    fn active(expires: Duration) -> SubscriptionState {
+        SubscriptionState::new(SubStateValue::Active).with_expires(expires.as_secs() as u32)
+    }
+
+    fn terminated() -> SubscriptionState {
+        SubscriptionState::new(SubStateValue::Terminated)
+    }
+
+    async fn final_code(mut transaction: ClientTsx) -> StatusCode {
+        transaction.receive_final().await.unwrap().line.code
+    }
+
+    fn requested_expires(request: &IncomingRequest) -> Duration {
+        super::super::requested_expires(request).unwrap()
+    }
+
+    /// Drive the subscription in a task, reporting its events
+    fn drive(mut subscription: Subscription) -> mpsc::UnboundedReceiver<SubscriptionEvent> {
+        let (events, events_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let event = subscription.receive().await.unwrap();
+                let terminated = matches!(event, SubscriptionEvent::Terminated);
+
+                let _ = events.send(event);
+
+                if terminated {
+                    break;
+                }
+            }
+        });
+
+        events_rx
+    }
+
+    /// Subscribe and receive the first notification
+    async fn subscribe(
+        expires: Duration,
+        policy: RetryPolicy,
+    ) -> (Notifier, mpsc::UnboundedReceiver<SubscriptionEvent>) {
+        let (mut peer, mut initiator) = setup(expires, policy).await;
+        let request = initiator.create_subscribe();
+
+        let (response, notifier) = tokio::join!(initiator.send_subscribe(request), async {
+            let subscribe = peer.subscribes.recv().await.unwrap();
+            let notifier = Notifier::new(&peer, &subscribe);
+            notifier.respond(subscribe, StatusCode::OK, expires).await;
+            notifier
+        });
+
+        let SubscribeResponse::Subscribed(subscription, _) = response.unwrap() else {
+            panic!("expected the subscription to be created");
+        };
+
+        let mut events = drive(subscription);
+
+        let transaction = notifier.notify(active(expires)).await;
+        assert_eq!(final_code(transaction).await, StatusCode::OK);
+        assert_notification(&mut events, SubStateValue::Active).await;
+
+        (notifier, events)
+    }
+
+    async fn assert_notification(
+        events: &mut mpsc::UnboundedReceiver<SubscriptionEvent>,
+        state: SubStateValue,
+    ) {
+        match events.recv().await.unwrap() {
+            SubscriptionEvent::Notification(notification) => {
+                assert_eq!(notification.state.state, state)
+            }
+            event => panic!("expected notification, got {event:?}"),
+        }
+    }
+
+    async fn assert_terminated(events: &mut mpsc::UnboundedReceiver<SubscriptionEvent>) {
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            SubscriptionEvent::Terminated
+        ));
+    }
+
+    fn assert_elapsed(start: Instant, expected: Duration) {
+        let elapsed = start.elapsed();
+
+        assert!(
+            elapsed + Duration::from_millis(100) >= expected
+                && elapsed < expected + Duration::from_millis(500),
+            "expected {expected:?}, took {elapsed:?}"
+        );
+    }
+
+    fn no_retry() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: Some(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh() {
+        let (mut notifier, mut events) = subscribe(EXPIRES, no_retry()).await;
+        let start = Instant::now();
+
+        let refresh = notifier.receive().await;
+        assert_eq!(refresh.line.method, Method::SUBSCRIBE);
+        assert_eq!(requested_expires(&refresh), EXPIRES);
+        assert_elapsed(start, EXPIRES / 2);
+
+        // The notifier shortens the subscription
+        notifier
+            .respond(refresh, StatusCode::OK, Duration::from_secs(2))
+            .await;
+        let start = Instant::now();
+
+        let refresh = notifier.receive().await;
+        assert_eq!(requested_expires(&refresh), Duration::from_secs(2));
+        assert_elapsed(start, Duration::from_secs(1));
+
+        notifier.respond(refresh, StatusCode::OK, EXPIRES).await;
+
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_refresh_is_retried() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_millis(500),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+
+        let (mut notifier, mut events) = subscribe(EXPIRES, policy).await;
+
+        let refresh = notifier.receive().await;
+        notifier
+            .respond(refresh, StatusCode::SERVICE_UNAVAILABLE, EXPIRES)
+            .await;
+        let start = Instant::now();
+
+        let refresh = notifier.receive().await;
+        assert_elapsed(start, Duration::from_millis(500));
+        notifier.respond(refresh, StatusCode::OK, EXPIRES).await;
+
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn expires_when_refreshes_keep_failing() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_millis(500),
+            ..RetryPolicy::default()
+        };
+
+        let (mut notifier, mut events) = subscribe(EXPIRES, policy).await;
+        let start = Instant::now();
+
+        let refresh = notifier.receive().await;
+        notifier
+            .respond(refresh, StatusCode::SERVICE_UNAVAILABLE, EXPIRES)
+            .await;
+
+        // Retries stop at the expiry of the subscription
+        loop {
+            select! {
+                refresh = notifier.receive() => {
+                    notifier
+                        .respond(refresh, StatusCode::SERVICE_UNAVAILABLE, EXPIRES)
+                        .await;
+                }
+                event = events.recv() => {
+                    assert!(matches!(event, Some(SubscriptionEvent::Terminated)));
+                    break;
+                }
+            }
+        }
+
+        assert_elapsed(start, EXPIRES);
+    }
+
+    #[tokio::test]
+    async fn refresh_rejected_by_notifier_terminates() {
+        let (mut notifier, mut events) = subscribe(EXPIRES, RetryPolicy::default()).await;
+
+        let refresh = notifier.receive().await;
+        notifier
+            .respond(
+                refresh,
+                StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST,
+                EXPIRES,
+            )
+            .await;
+
+        assert_terminated(&mut events).await;
+    }
+
+    #[tokio::test]
+    async fn terminated_by_notifier() {
+        let (notifier, mut events) = subscribe(EXPIRES, no_retry()).await;
+
+        let transaction = notifier.notify(terminated()).await;
+        assert_eq!(final_code(transaction).await, StatusCode::OK);
+
+        assert_notification(&mut events, SubStateValue::Terminated).await;
+        assert_terminated(&mut events).await;
+    }
+
+    #[tokio::test]
+    async fn unsubscribe() {
+        let (mut peer, mut initiator) = setup(EXPIRES, no_retry()).await;
+        let request = initiator.create_subscribe();
+
+        let (response, mut notifier) = tokio::join!(initiator.send_subscribe(request), async {
+            let subscribe = peer.subscribes.recv().await.unwrap();
+            let notifier = Notifier::new(&peer, &subscribe);
+            notifier.respond(subscribe, StatusCode::OK, EXPIRES).await;
+            notifier
+        });
+
+        let SubscribeResponse::Subscribed(mut subscription, _) = response.unwrap() else {
+            panic!("expected the subscription to be created");
+        };
+
+        let (result, ()) = tokio::join!(subscription.unsubscribe(), async {
+            let request = notifier.receive().await;
+            assert_eq!(requested_expires(&request), Duration::ZERO);
+            notifier
+                .respond(request, StatusCode::OK, Duration::ZERO)
+                .await;
+        });
+        result.unwrap();
+
+        let mut events = drive(subscription);
+
+        // The NOTIFY's expires doesn't restart refreshing
+        let transaction = notifier.notify(active(EXPIRES)).await;
+        assert_eq!(final_code(transaction).await, StatusCode::OK);
+        assert_notification(&mut events, SubStateValue::Active).await;
+
+        let transaction = notifier.notify(terminated()).await;
+        assert_eq!(final_code(transaction).await, StatusCode::OK);
+        assert_notification(&mut events, SubStateValue::Terminated).await;
+        assert_terminated(&mut events).await;
+
+        sleep(EXPIRES).await;
+        assert!(notifier.requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_is_not_refreshed() {
+        let (mut peer, mut initiator) = setup(Duration::ZERO, no_retry()).await;
+        let request = initiator.create_subscribe();
+
+        let (response, mut notifier) = tokio::join!(initiator.send_subscribe(request), async {
+            let subscribe = peer.subscribes.recv().await.unwrap();
+            let notifier = Notifier::new(&peer, &subscribe);
+            notifier
+                .respond(subscribe, StatusCode::OK, Duration::ZERO)
+                .await;
+            notifier
+        });
+
+        let SubscribeResponse::Subscribed(subscription, _) = response.unwrap() else {
+            panic!("expected the subscription to be created");
+        };
+
+        // Nothing is sent anymore, so time can be skipped
+        tokio::time::pause();
+
+        let start = Instant::now();
+        let mut events = drive(subscription);
+
+        // Without the final NOTIFY the subscription ends after 64*T1, without sending any SUBSCRIBE
+        assert_terminated(&mut events).await;
+        assert_elapsed(start, 64 * T1);
+        assert!(notifier.requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn notify_before_response() {
+        let (mut peer, mut initiator) = setup(EXPIRES, no_retry()).await;
+        let request = initiator.create_subscribe();
+
+        let (response, (notifier, transaction)) =
+            tokio::join!(initiator.send_subscribe(request), async {
+                let subscribe = peer.subscribes.recv().await.unwrap();
+                let notifier = Notifier::new(&peer, &subscribe);
+
+                let transaction = notifier.notify(active(EXPIRES)).await;
+                sleep(Duration::from_millis(100)).await;
+                notifier.respond(subscribe, StatusCode::OK, EXPIRES).await;
+
+                (notifier, transaction)
+            });
+
+        let SubscribeResponse::Subscribed(subscription, _) = response.unwrap() else {
+            panic!("expected the subscription to be created");
+        };
+
+        let mut events = drive(subscription);
+
+        assert_notification(&mut events, SubStateValue::Active).await;
+        assert_eq!(final_code(transaction).await, StatusCode::OK);
+
+        drop(notifier);
+    }
+
+    #[tokio::test]
+    async fn forked_notify_is_rejected() {
+        let (mut peer, mut initiator) = setup(EXPIRES, no_retry()).await;
+        let request = initiator.create_subscribe();
+
+        let (response, (notifier, forked, accepted)) =
+            tokio::join!(initiator.send_subscribe(request), async {
+                let subscribe = peer.subscribes.recv().await.unwrap();
+
+                // Another fork of the SUBSCRIBE sends a NOTIFY, but the request is accepted by this notifier
+                let fork = Notifier::new(&peer, &subscribe);
+                let forked = fork.notify(active(EXPIRES)).await;
+
+                let notifier = Notifier::new(&peer, &subscribe);
+                let accepted = notifier.notify(active(EXPIRES)).await;
+
+                sleep(Duration::from_millis(100)).await;
+                notifier.respond(subscribe, StatusCode::OK, EXPIRES).await;
+
+                (notifier, forked, accepted)
+            });
+
+        let SubscribeResponse::Subscribed(subscription, _) = response.unwrap() else {
+            panic!("expected the subscription to be created");
+        };
+
+        let mut events = drive(subscription);
+
+        assert_notification(&mut events, SubStateValue::Active).await;
+        assert_eq!(
+            final_code(forked).await,
+            StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST
+        );
+        assert_eq!(final_code(accepted).await, StatusCode::OK);
+        assert!(events.try_recv().is_err());
+
+        drop(notifier);
+    }
+}
@@ -0,0 +1,131 @@
+//! Minimal XML tree used to parse the bodies of the event packages
+
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::borrow::Cow;
+
+/// Error returned when parsing the body of a notification
+#[derive(Debug, thiserror::Error)]
+pub enum BodyError {
+    #[error(transparent)]
+    Xml(#[from] quick_xml::Error),
+    #[error(transparent)]
+    Attribute(#[from] quick_xml::events::attributes::AttrError),
+    #[error("expected <{0}> as root element")]
+    UnexpectedRoot(&'static str),
+    #[error("missing {0}")]
+    Missing(&'static str),
+    #[error("invalid {0}")]
+    Invalid(&'static str),
+}
+
+/// Element of a parsed XML document, names are stored without namespace prefix
+#[derive(Debug, Default)]
+pub(super) struct Element {
+    pub(super) name: String,
+    attributes: Vec<(String, String)>,
+    pub(super) children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    /// Parse the document and return its root element, which must have the given name
+    pub(super) fn parse_root(xml: &str, name: &'static str) -> Result<Self, BodyError> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+
+        let mut stack: Vec<Element> = vec![];
+
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => stack.push(Self::from_start(&start)?),
+                Event::Empty(start) => {
+                    let element = Self::from_start(&start)?;
+
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return element.expect_name(name),
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text.unescape()?);
+                    }
+                }
+                Event::CData(data) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&String::from_utf8_lossy(&data));
+                    }
+                }
+                Event::End(_) => {
+                    let element = stack.pop().ok_or(BodyError::Missing("start tag"))?;
+
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return element.expect_name(name),
+                    }
+                }
+                Event::Eof => return Err(BodyError::Missing("root element")),
+                _ => {}
+            }
+        }
+    }
+
+    fn from_start(start: &BytesStart<'_>) -> Result<Self, BodyError> {
+        let mut attributes = vec![];
+
+        for attribute in start.attributes() {
+            let attribute = attribute?;
+
+            attributes.push((
+                String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned(),
+                attribute.unescape_value()?.into_owned(),
+            ));
+        }
+
+        Ok(Self {
+            name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+            attributes,
+            children: vec![],
+            text: String::new(),
+        })
+    }
+
+    fn expect_name(self, name: &'static str) -> Result<Self, BodyError> {
+        if self.name == name {
+            Ok(self)
+        } else {
+            Err(BodyError::UnexpectedRoot(name))
+        }
+    }
+
+    pub(super) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(super) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub(super) fn children<'e>(&'e self, name: &'e str) -> impl Iterator<Item = &'e Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    pub(super) fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Text of the child element with the given name
+    pub(super) fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(Element::text)
+    }
+}
+
+/// Escape text or attribute values written into a document
+pub(super) fn esc(text: &str) -> Cow<'_, str> {
+    escape(text)
+}
@@ -1,5 +1,7 @@
 use bytesstr::BytesStr;
 use rand::{distr::Alphanumeric, rng, Rng};
+use tokio::time::{sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
 
 pub fn random_string() -> BytesStr {
     rng()
@@ -13,3 +15,19 @@ pub fn random_string() -> BytesStr {
 pub fn random_sequence_number() -> u32 {
     rand::rng().random_range(0..(u32::MAX >> 1))
 }
+
+/// Create a token which is cancelled once the deadline is reached, to pass a deadline where a
+/// [`CancellationToken`] is accepted
+pub fn cancel_at(deadline: Instant) -> CancellationToken {
+    let token = CancellationToken::new();
+    let task_token = token.clone();
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = sleep_until(deadline) => task_token.cancel(),
+            _ = task_token.cancelled() => {}
+        }
+    });
+
+    token
+}
@@ -0,0 +1,171 @@
+use sip_auth::{DigestCredentials, DigestUser};
+use sip_types::header::typed::{Contact, Routing};
+use sip_types::uri::{NameAddr, SipUri, SipUriUserPart};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+slotmap::new_key_type! {
+    /// Identifies an account of a [`Softphone`](crate::Softphone)
+    pub struct AccountId;
+}
+
+/// Identity used to register and to make & receive calls, see [`Softphone::add_account`](crate::Softphone::add_account)
+pub struct Account {
+    pub(crate) aor: SipUri,
+    pub(crate) display_name: Option<String>,
+    pub(crate) registrar: Option<SipUri>,
+    pub(crate) outbound_proxy: Option<SipUri>,
+    pub(crate) expiry: Duration,
+    pub(crate) keepalive: Option<Duration>,
+    pub(crate) message_waiting: bool,
+    pub(crate) credentials: DigestCredentials,
+}
+
+impl Account {
+    /// Create an account using the given address of record
+    pub fn new(aor: SipUri) -> Self {
+        Self {
+            aor,
+            display_name: None,
+            registrar: None,
+            outbound_proxy: None,
+            expiry: Duration::from_secs(600),
+            keepalive: None,
+            message_waiting: false,
+            credentials: DigestCredentials::new(),
+        }
+    }
+
+    /// Display name used in the `From` header of dialed calls
+    pub fn display_name(mut self, name: impl Into<String>) -> Self {
+        self.display_name = Some(name.into());
+        self
+    }
+
+    /// Register the address of record at the given registrar, the registration is refreshed until the account is
+    /// removed
+    pub fn registrar(mut self, registrar: SipUri) -> Self {
+        self.registrar = Some(registrar);
+        self
+    }
+
+    /// Send all requests of the account via the given outbound proxy, e.g. a session border controller
+    ///
+    /// Overrides the [`SoftphoneBuilder::outbound_proxy`](crate::SoftphoneBuilder::outbound_proxy) of the
+    /// softphone.
+    pub fn outbound_proxy(mut self, proxy: SipUri) -> Self {
+        self.outbound_proxy = Some(proxy);
+        self
+    }
+
+    /// Requested expiry of the registration, defaults to 10 minutes
+    pub fn registration_expiry(mut self, expiry: Duration) -> Self {
+        self.expiry = expiry;
+        self
+    }
+
+    /// Send OPTIONS requests to the registrar in the given interval, disabled by default
+    ///
+    /// When the registrar stops responding, [`SoftphoneEvent::RegistrarUnreachable`](crate::SoftphoneEvent::RegistrarUnreachable)
+    /// is emitted and the account registers again right away.
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive = Some(interval);
+        self
+    }
+
+    /// Subscribe to the message waiting indication (MWI) of the address of record once registered, disabled by
+    /// default
+    ///
+    /// The number of waiting voicemails is reported using
+    /// [`SoftphoneEvent::MessageWaiting`](crate::SoftphoneEvent::MessageWaiting). Requires a registrar.
+    pub fn message_waiting(mut self) -> Self {
+        self.message_waiting = true;
+        self
+    }
+
+    /// Credentials used to answer digest authentication challenges of registrar and proxies
+    pub fn credentials(mut self, user: impl Into<String>, password: impl Into<Vec<u8>>) -> Self {
+        self.credentials
+            .set_default(DigestUser::new(user, password));
+        self
+    }
+
+    pub(crate) fn local_addr(&self) -> NameAddr {
+        NameAddr {
+            name: self.display_name.clone().map(Into::into),
+            uri: self.aor.clone(),
+        }
+    }
+}
+
+/// State of an account added to the softphone
+pub(crate) struct AccountEntry {
+    pub(crate) local_addr: NameAddr,
+    pub(crate) contact: Contact,
+    /// Preloaded route set of requests outside of dialogs, contains the outbound proxy if there is one
+    pub(crate) route_set: Vec<Routing>,
+    pub(crate) credentials: DigestCredentials,
+    /// Task keeping the registration alive, if a registrar is configured
+    pub(crate) registration: Option<JoinHandle<()>>,
+}
+
+impl AccountEntry {
+    /// Is the request URI of an incoming request addressed to this account
+    ///
+    /// Requests are sent to the registered contact, but may also be addressed to the address of record directly.
+    pub(crate) fn matches(&self, request_uri: &SipUri) -> bool {
+        let SipUriUserPart::User(user) = &request_uri.user_part else {
+            return false;
+        };
+
+        [&self.contact.uri.uri, &self.local_addr.uri]
+            .into_iter()
+            .any(|uri| matches!(&uri.user_part, SipUriUserPart::User(u) if u == user))
+    }
+}
+
+impl Drop for AccountEntry {
+    fn drop(&mut self) {
+        if let Some(registration) = &self.registration {
+            registration.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(account: Account, contact: &str) -> AccountEntry {
+        AccountEntry {
+            local_addr: account.local_addr(),
+            contact: Contact::new(NameAddr::uri(contact.parse().unwrap())),
+            route_set: vec![],
+            credentials: account.credentials,
+            registration: None,
+        }
+    }
+
+    #[test]
+    fn local_addr() {
+        let account = Account::new("sip:alice@example.org".parse().unwrap());
+        assert!(account.local_addr().name.is_none());
+
+        let account = account.display_name("Alice");
+        assert_eq!(account.local_addr().name.as_deref(), Some("Alice"));
+    }
+
+    #[test]
+    fn matches() {
+        let account = Account::new("sip:alice@example.org".parse().unwrap());
+        let entry = entry(account, "sip:alice-contact@127.0.0.1:5060");
+
+        // Addressed to the registered contact
+        assert!(entry.matches(&"sip:alice-contact@127.0.0.1:5060".parse().unwrap()));
+        // Addressed to the address of record
+        assert!(entry.matches(&"sip:alice@example.org".parse().unwrap()));
+
+        assert!(!entry.matches(&"sip:bob@example.org".parse().unwrap()));
+        assert!(!entry.matches(&"sip:127.0.0.1:5060".parse().unwrap()));
+    }
+}
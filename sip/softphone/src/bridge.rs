@@ -0,0 +1,88 @@
+use crate::CallId;
+use rtp::{RtpPacket, G711};
+use session::MediaInfo;
+
+/// Converts the audio relayed between bridged calls which negotiated different codecs, see
+/// [`Softphone::bridge_with`](crate::Softphone::bridge_with)
+///
+/// Packets of calls which negotiated the same codec are relayed without it, only their payload type is replaced.
+pub trait Transcoder: Send + 'static {
+    /// Convert a packet received in the other call using the codec `from` to the codec `to` of this call, returns
+    /// `None` to drop the packet
+    fn transcode(
+        &mut self,
+        packet: RtpPacket,
+        from: &MediaInfo,
+        to: &MediaInfo,
+    ) -> Option<RtpPacket>;
+}
+
+/// [`Transcoder`] converting between PCMU and PCMA, used by [`Softphone::bridge`](crate::Softphone::bridge)
+///
+/// Packets of any other codec are dropped.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct G711Transcoder;
+
+impl Transcoder for G711Transcoder {
+    fn transcode(
+        &mut self,
+        mut packet: RtpPacket,
+        from: &MediaInfo,
+        to: &MediaInfo,
+    ) -> Option<RtpPacket> {
+        let (from_law, to_law) = (g711_law(from)?, g711_law(to)?);
+
+        let mut samples = Vec::with_capacity(packet.payload.len());
+        from_law.decode(&packet.payload, &mut samples);
+
+        let mut payload = Vec::with_capacity(samples.len());
+        to_law.encode(&samples, &mut payload);
+
+        packet.pt = to.payload_type;
+        packet.payload = payload.into();
+
+        Some(packet)
+    }
+}
+
+fn g711_law(info: &MediaInfo) -> Option<G711> {
+    if info.clock_rate != 8000 {
+        return None;
+    }
+
+    if info.codec_name.eq_ignore_ascii_case("PCMU") {
+        Some(G711::Pcmu)
+    } else if info.codec_name.eq_ignore_ascii_case("PCMA") {
+        Some(G711::Pcma)
+    } else {
+        None
+    }
+}
+
+/// The other call of a bridged call, and the transcoder of the audio relayed from it
+pub(crate) struct Bridge {
+    pub(crate) peer: CallId,
+    transcoder: Box<dyn Transcoder>,
+}
+
+impl Bridge {
+    pub(crate) fn new(peer: CallId, transcoder: Box<dyn Transcoder>) -> Self {
+        Self { peer, transcoder }
+    }
+
+    /// Prepare a packet received in the peer call using the codec `from` to be sent using the codec `to`
+    pub(crate) fn relay(
+        &mut self,
+        mut packet: RtpPacket,
+        from: &MediaInfo,
+        to: &MediaInfo,
+    ) -> Option<RtpPacket> {
+        if from.codec_name.eq_ignore_ascii_case(&to.codec_name) && from.clock_rate == to.clock_rate
+        {
+            packet.pt = to.payload_type;
+            return Some(packet);
+        }
+
+        self.transcoder.transcode(packet, from, to)
+    }
+}
@@ -0,0 +1,1676 @@
+use crate::bridge::Bridge;
+use crate::{
+    authenticate, is_dtmf_digit, AccountId, CallId, EndReason, Error, IncomingCall, Shared,
+    SoftphoneEvent,
+};
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use rtp::{RingbackGenerator, RtpPacket, Tone};
+use session::{
+    AsyncEvent, AsyncSdpSession, Direction, LocalMediaId, MediaId, MediaInfo, MediaType, Options,
+    SessionDescription,
+};
+use sip_auth::{ClientAuthenticator, DigestAuthenticator, DigestCredentials};
+use sip_core::transport::OutgoingResponse;
+use sip_core::{Endpoint, IncomingRequest, Layer, MayTake, Request};
+use sip_types::header::typed::{Contact, ContentType, ReferTo, Replaces, RetryAfter, Routing};
+use sip_types::header::DecodeValues;
+use sip_types::uri::params::Params;
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{CodeKind, Method, Name, StatusCode};
+use sip_ua::dialog::{Dialog, DialogKey};
+use sip_ua::invite::acceptor::{EarlyUpdate, InviteAcceptor};
+use sip_ua::invite::create_ack;
+use sip_ua::invite::initiator::{Early, EarlyResponse, InviteInitiator, Response};
+use sip_ua::invite::refer::ReferSubscription;
+use sip_ua::invite::session::{
+    InfoReceived, InviteSession, InviteSessionEvent, NotifyReceived, ReInviteReceived,
+    ReferReceived, RefreshNeeded, UpdateReceived,
+};
+use std::future::pending;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// Request sent from the [`Softphone`](crate::Softphone) to the task running a call
+pub(crate) enum Command {
+    Answer(oneshot::Sender<Result<(), Error>>),
+    Hangup,
+    Hold {
+        hold: bool,
+        result: oneshot::Sender<Result<(), Error>>,
+    },
+    Transfer {
+        target: SipUri,
+        result: oneshot::Sender<Result<(), Error>>,
+    },
+    Dtmf {
+        digits: String,
+        result: oneshot::Sender<Result<(), Error>>,
+    },
+    SendRtp(RtpPacket),
+    AudioSamples(Vec<i16>),
+    MaxDuration(Duration),
+    TerminateAt(Instant),
+    /// The call's dialog was replaced by the given call, using an INVITE with a `Replaces` header
+    Replaced(CallId),
+    Bridge {
+        bridge: Bridge,
+        result: oneshot::Sender<Result<(), Error>>,
+    },
+    /// Dissolve the call's bridge, only if it is with the given call if set
+    Unbridge {
+        peer: Option<CallId>,
+    },
+    /// RTP packet received in the bridged call, encoded using the codec of its audio
+    RelayRtp {
+        packet: RtpPacket,
+        codec: MediaInfo,
+    },
+}
+
+impl Command {
+    /// Respond to a command which cannot be executed in the current state of the call
+    fn reject(self) {
+        self.fail(Error::InvalidState);
+    }
+
+    /// Respond to the command with the given error, if it expects a response
+    fn fail(self, error: Error) {
+        match self {
+            Command::Answer(result)
+            | Command::Hold { result, .. }
+            | Command::Transfer { result, .. }
+            | Command::Dtmf { result, .. }
+            | Command::Bridge { result, .. } => {
+                let _ = result.send(Err(error));
+            }
+            Command::Hangup
+            | Command::SendRtp(_)
+            | Command::AudioSamples(_)
+            | Command::MaxDuration(_)
+            | Command::TerminateAt(_)
+            | Command::Replaced(_)
+            | Command::Unbridge { .. }
+            | Command::RelayRtp { .. } => {}
+        }
+    }
+}
+
+/// The softphone's side of a call task
+pub(crate) struct CallHandle {
+    pub(crate) commands: mpsc::UnboundedSender<Command>,
+    /// The account the call was made with or addressed to
+    pub(crate) account: AccountId,
+    /// Cancels an outgoing call which has not been answered yet
+    pub(crate) cancellation: CancellationToken,
+    /// Dialog of the call, set once it's established
+    pub(crate) dialog: Option<EstablishedDialog>,
+    /// The caller cancelled the call, the handle is kept until the end was reported to the application
+    pub(crate) cancelled: bool,
+}
+
+/// Dialog of an established call, used to replace it in an attended transfer
+#[derive(Clone)]
+pub(crate) struct EstablishedDialog {
+    pub(crate) key: DialogKey,
+    /// Contact URI of the peer
+    pub(crate) remote_target: SipUri,
+}
+
+impl EstablishedDialog {
+    /// URI which a third party can call to replace this call's dialog at its peer
+    pub(crate) fn replacing_uri(&self) -> Option<SipUri> {
+        let replaces = self.key.to_replaces()?;
+
+        let mut uri = self.remote_target.clone();
+        uri.header_params = Params::new();
+        uri.header_params
+            .push_or_edit("Replaces", replaces.to_string());
+
+        Some(uri)
+    }
+}
+
+impl CallHandle {
+    pub(crate) fn new(
+        account: AccountId,
+    ) -> (Self, mpsc::UnboundedReceiver<Command>, CancellationToken) {
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let cancellation = CancellationToken::new();
+
+        let handle = Self {
+            commands,
+            account,
+            cancellation: cancellation.clone(),
+            dialog: None,
+            cancelled: false,
+        };
+
+        (handle, commands_rx, cancellation)
+    }
+}
+
+/// Accepts INVITE requests outside of any dialog as incoming calls
+pub(crate) struct IncomingCallLayer {
+    shared: Arc<Shared>,
+}
+
+impl IncomingCallLayer {
+    pub(crate) fn new(shared: Arc<Shared>) -> Self {
+        Self { shared }
+    }
+}
+
+#[async_trait::async_trait]
+impl Layer for IncomingCallLayer {
+    fn name(&self) -> &'static str {
+        "softphone-incoming-call"
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::INVITE || request.base_headers.to.tag.is_some() {
+            return;
+        }
+
+        let Some((account, contact)) = self.shared.route_incoming(&request.line.uri) else {
+            let mut invite = request.take();
+            let response = endpoint.create_response(&invite, StatusCode::NOT_FOUND, None);
+
+            if let Err(e) = endpoint
+                .create_server_inv_tsx(&mut invite)
+                .respond_failure(response)
+                .await
+            {
+                log::warn!("Failed to reject INVITE to unknown account, {e}");
+            }
+
+            return;
+        };
+
+        let dialog = match Dialog::new_server(endpoint.clone(), &request, contact) {
+            Ok(dialog) => dialog,
+            Err(e) => {
+                log::warn!("Failed to create dialog for incoming INVITE, {e}");
+                return;
+            }
+        };
+
+        let replaces = request.headers.try_get_named::<Replaces>();
+
+        let invite = request.take();
+        let from = invite.base_headers.from.uri.clone();
+        let to = invite.base_headers.to.uri.clone();
+        let request_uri = invite.line.uri.clone();
+        let offer = invite.body.clone();
+
+        // Create the acceptor right away, so retransmissions of the INVITE are absorbed by its transaction
+        let acceptor = InviteAcceptor::new(dialog, invite);
+
+        let replaces = match replaces {
+            None => None,
+            Some(Ok(replaces)) => match self.shared.find_replaced_call(&replaces) {
+                Some(replaced) => Some(replaced),
+                None => {
+                    tokio::spawn(decline(
+                        acceptor,
+                        StatusCode::CALL_OR_TRANSACTION_DOES_NOT_EXIST,
+                    ));
+                    return;
+                }
+            },
+            Some(Err(e)) => {
+                log::warn!("Rejecting INVITE with invalid Replaces header, {e}");
+                tokio::spawn(decline(acceptor, StatusCode::BAD_REQUEST));
+                return;
+            }
+        };
+
+        let (id, commands, _) = self.shared.add_call(account);
+
+        let incoming = IncomingCall {
+            call: id,
+            account,
+            from,
+            to,
+            request_uri,
+        };
+
+        tokio::spawn(run_incoming(
+            self.shared.clone(),
+            id,
+            commands,
+            acceptor,
+            offer,
+            incoming,
+            replaces,
+        ));
+    }
+}
+
+/// How an outgoing call is made
+pub(crate) enum Outgoing {
+    /// Using the account's outbound proxy and credentials, see [`Softphone::dial`](crate::Softphone::dial)
+    Dial,
+    /// Bypassing the account's outbound proxy and authentication, see
+    /// [`Softphone::direct_call`](crate::Softphone::direct_call)
+    Direct,
+    /// On behalf of the peer of another call, which sent a REFER request
+    Referred(Referred),
+    /// Replacing a call ringing at another user agent, see [`Softphone::pickup`](crate::Softphone::pickup)
+    Pickup(Replaces),
+}
+
+/// Call made on behalf of the peer of another call, which sent a REFER request
+pub(crate) struct Referred {
+    /// `Replaces` header to send in the INVITE (attended transfer)
+    replaces: Option<Replaces>,
+    /// Receives the status codes of the call's responses, to be reported to the REFER's sender
+    progress: mpsc::UnboundedSender<StatusCode>,
+}
+
+/// Start dialing the given target, the progress of the call is reported using events
+pub(crate) fn dial(
+    shared: &Arc<Shared>,
+    endpoint: Endpoint,
+    account: AccountId,
+    target: SipUri,
+    outgoing: Outgoing,
+) -> CallId {
+    let (id, commands, cancellation) = shared.add_call(account);
+
+    tokio::spawn(run_outgoing(
+        shared.clone(),
+        endpoint,
+        id,
+        commands,
+        cancellation,
+        target,
+        outgoing,
+    ));
+
+    id
+}
+
+async fn run_outgoing(
+    shared: Arc<Shared>,
+    endpoint: Endpoint,
+    id: CallId,
+    commands: mpsc::UnboundedReceiver<Command>,
+    cancellation: CancellationToken,
+    target: SipUri,
+    outgoing: Outgoing,
+) {
+    let direct = matches!(outgoing, Outgoing::Direct);
+
+    let (replaces, refer_progress) = match outgoing {
+        Outgoing::Referred(referred) => (referred.replaces, Some(referred.progress)),
+        Outgoing::Pickup(replaces) => (Some(replaces), None),
+        Outgoing::Dial | Outgoing::Direct => (None, None),
+    };
+
+    let call = Call::new(shared.clone(), id, commands).and_then(|mut call| {
+        if direct {
+            call.make_direct()?;
+        }
+
+        Ok(call)
+    });
+
+    let reason = match call {
+        Ok(mut call) => {
+            call.refer_progress = refer_progress;
+
+            let setup = call.dial(endpoint, target, replaces, cancellation).await;
+
+            call.report_refer_progress(match &setup {
+                Ok(Setup::Established(_)) => StatusCode::OK,
+                Ok(Setup::Ended(EndReason::Rejected(code))) => *code,
+                Ok(Setup::Ended(_)) => StatusCode::REQUEST_TERMINATED,
+                Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+            });
+
+            match setup {
+                Ok(Setup::Established(session)) => call.run(session).await,
+                Ok(Setup::Ended(reason)) => reason,
+                Err(e) => EndReason::Failed(e),
+            }
+        }
+        Err(e) => {
+            if let Some(progress) = refer_progress {
+                let _ = progress.send(StatusCode::SERVER_INTERNAL_ERROR);
+            }
+
+            EndReason::Failed(e)
+        }
+    };
+
+    shared.end_call(id, reason);
+}
+
+async fn run_incoming(
+    shared: Arc<Shared>,
+    id: CallId,
+    commands: mpsc::UnboundedReceiver<Command>,
+    acceptor: InviteAcceptor,
+    offer: Bytes,
+    incoming: IncomingCall,
+    replaces: Option<CallId>,
+) {
+    let reason = match Call::new(shared.clone(), id, commands) {
+        Ok(mut call) => {
+            let setup = match replaces {
+                // Calls replacing an established call are answered right away (RFC 3891 section 3)
+                Some(replaced) => {
+                    let setup = call.answer(acceptor, offer).await;
+
+                    if let Ok(Setup::Established(_)) = &setup {
+                        let _ = shared.send_command(replaced, Command::Replaced(id));
+                    }
+
+                    setup
+                }
+                None => call.ring(acceptor, offer, incoming).await,
+            };
+
+            match setup {
+                Ok(Setup::Established(session)) => call.run(session).await,
+                Ok(Setup::Ended(reason)) => reason,
+                Err(e) => EndReason::Failed(e),
+            }
+        }
+        Err(e) => {
+            decline(acceptor, StatusCode::SERVER_INTERNAL_ERROR).await;
+            EndReason::Failed(e)
+        }
+    };
+
+    shared.end_call(id, reason);
+}
+
+/// Outcome of setting up a call
+enum Setup {
+    Established(InviteSession),
+    Ended(EndReason),
+}
+
+struct Call {
+    id: CallId,
+    shared: Arc<Shared>,
+    /// The account the call was made with or addressed to
+    account: AccountId,
+    local_addr: NameAddr,
+    contact: Contact,
+    /// Route set of the INVITE of a dialed call, see [`Account::outbound_proxy`](crate::Account::outbound_proxy)
+    route_set: Vec<Routing>,
+    commands: mpsc::UnboundedReceiver<Command>,
+    authenticator: DigestAuthenticator,
+
+    media: AsyncSdpSession,
+    local_media: LocalMediaId,
+    /// The negotiated audio media, set once the SDP exchange completed
+    media_id: Option<MediaId>,
+    /// The call was put on hold using [`Softphone::hold`](crate::Softphone::hold)
+    held: bool,
+
+    /// Limit of the call's duration, counted from its establishment
+    max_duration: Option<Duration>,
+    /// When to hang up the call
+    terminate_at: Option<Instant>,
+    /// When the call was established
+    established_at: Option<Instant>,
+    /// [`SoftphoneEvent::TerminationWarning`] was emitted for the current deadline
+    warned: bool,
+
+    /// Reports the progress of this call to the call which received the REFER request it was made for
+    refer_progress: Option<mpsc::UnboundedSender<StatusCode>>,
+    /// Subscription of a REFER received in this call and the progress of the call made for it
+    accepted_refer: Option<(ReferSubscription, mpsc::UnboundedReceiver<StatusCode>)>,
+
+    /// The call this call is bridged with, see [`Softphone::bridge`](crate::Softphone::bridge)
+    bridge: Option<Bridge>,
+}
+
+impl Call {
+    fn new(
+        shared: Arc<Shared>,
+        id: CallId,
+        commands: mpsc::UnboundedReceiver<Command>,
+    ) -> Result<Self, Error> {
+        let account = shared.call_account(id).ok_or(Error::UnknownCall(id))?;
+
+        let (local_addr, contact, route_set, credentials) = {
+            let accounts = shared.accounts.lock().unwrap();
+            let entry = accounts
+                .get(account)
+                .ok_or(Error::UnknownAccount(account))?;
+
+            (
+                entry.local_addr.clone(),
+                entry.contact.clone(),
+                entry.route_set.clone(),
+                entry.credentials.clone(),
+            )
+        };
+
+        let (media, local_media) = new_media(&shared, shared.config.media_options.clone())?;
+
+        Ok(Self {
+            id,
+            authenticator: DigestAuthenticator::new(credentials),
+            shared,
+            account,
+            local_addr,
+            contact,
+            route_set,
+            commands,
+            media,
+            local_media,
+            media_id: None,
+            held: false,
+            max_duration: None,
+            terminate_at: None,
+            established_at: None,
+            warned: false,
+            refer_progress: None,
+            accepted_refer: None,
+            bridge: None,
+        })
+    }
+
+    /// Bypass the outbound proxy & authentication and negotiate plain RTP, see
+    /// [`Softphone::direct_call`](crate::Softphone::direct_call)
+    fn make_direct(&mut self) -> Result<(), Error> {
+        self.route_set.clear();
+        self.authenticator = DigestAuthenticator::new(DigestCredentials::new());
+        (self.media, self.local_media) = new_media(&self.shared, Options::lan())?;
+
+        Ok(())
+    }
+
+    async fn dial(
+        &mut self,
+        endpoint: Endpoint,
+        target: SipUri,
+        replaces: Option<Replaces>,
+        cancellation: CancellationToken,
+    ) -> Result<Setup, Error> {
+        let mut initiator = InviteInitiator::new(
+            endpoint.clone(),
+            self.local_addr.clone(),
+            self.contact.clone(),
+            target,
+        );
+        initiator.set_route_set(self.route_set.clone());
+        initiator.replaces = replaces;
+
+        // Gathering the ICE candidates may wait for the STUN server, resolve the target in the meantime
+        self.media.add_media(self.local_media, Direction::SendRecv);
+        let (gathered, resolved) =
+            tokio::join!(self.media.gather_candidates(), initiator.resolve());
+        gathered?;
+        resolved?;
+
+        self.shared.refill_candidate_cache();
+
+        let offer = self.media.create_sdp_offer().await?.to_string();
+
+        // Early media is received using the SDP answer of unreliable provisional responses, which must match the
+        // answer of the final response (RFC 3261 section 13.2.1)
+        initiator.support_100rel = false;
+        initiator.timer_config.expires_secs = Some(self.shared.config.session_expires_secs());
+        initiator.set_cancellation(cancellation.clone());
+
+        let mut early = vec![];
+        let mut ringing = false;
+        // The SDP offer was answered in a provisional response
+        let mut early_media = false;
+        // Stopped when dropped, once early media arrives, the call is answered or ends
+        let mut ringback = None;
+
+        let (mut session, response) = 'attempts: loop {
+            let mut invite = initiator.create_invite();
+            invite.headers.insert(Name::CONTENT_TYPE, "application/sdp");
+            invite.body = offer.clone().into();
+
+            self.authenticator
+                .authorize_request(&invite.line, &mut invite.headers, &invite.body);
+
+            match initiator.send_invite(invite).await {
+                Ok(()) => {}
+                // The server in use is unreachable (RFC 3263 Section 4.3)
+                Err(sip_core::Error::Io(e)) => {
+                    if initiator.failover().await {
+                        log::debug!("Failed to send INVITE, failing over, {e}");
+                        continue 'attempts;
+                    }
+
+                    return Err(sip_core::Error::Io(e).into());
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            loop {
+                let provisional = match self.receive_response(&mut initiator, early_media).await? {
+                    Response::Provisional(response) => response,
+                    Response::Early(dialog, response, _) => {
+                        early.push(dialog);
+                        response
+                    }
+                    Response::EarlyEvent => match forwarded_early_response(&mut early)? {
+                        Some(EarlyResponse::Provisional(response, _)) => response,
+                        Some(EarlyResponse::Success(session, response)) => {
+                            break 'attempts (session, response);
+                        }
+                        Some(EarlyResponse::Terminated) | None => continue,
+                    },
+                    Response::Session(session, response) => break 'attempts (session, response),
+                    Response::Failure(response) => {
+                        if cancellation.is_cancelled() {
+                            return Ok(Setup::Ended(EndReason::LocalHangup));
+                        }
+
+                        let transaction = initiator.transaction().expect("INVITE was sent");
+
+                        if authenticate(
+                            &mut self.authenticator,
+                            &transaction.request().msg,
+                            &response,
+                        ) {
+                            continue 'attempts;
+                        }
+
+                        if response.line.code == StatusCode::SESSION_INTERVAL_TOO_SMALL
+                            && initiator
+                                .timer_config
+                                .on_session_interval_too_small(&response)
+                        {
+                            continue 'attempts;
+                        }
+
+                        if response.line.code == StatusCode::SERVICE_UNAVAILABLE
+                            && initiator.failover().await
+                        {
+                            continue 'attempts;
+                        }
+
+                        return Ok(Setup::Ended(EndReason::Rejected(response.line.code)));
+                    }
+                    Response::Finished => {
+                        if cancellation.is_cancelled() {
+                            return Ok(Setup::Ended(EndReason::LocalHangup));
+                        }
+
+                        if initiator.failover().await {
+                            continue 'attempts;
+                        }
+
+                        return Err(sip_core::Error::RequestTimedOut.into());
+                    }
+                };
+
+                let code = provisional.line.code;
+
+                if code.into_u16() > 100 {
+                    self.shared.emit(SoftphoneEvent::Progress {
+                        call: self.id,
+                        code,
+                    });
+                }
+
+                if !early_media && !provisional.body.is_empty() {
+                    self.media
+                        .receive_sdp_answer(parse_sdp(&provisional.body)?)
+                        .await?;
+
+                    early_media = true;
+
+                    // The network provides the ringback tone
+                    ringback = None;
+
+                    self.shared.emit(SoftphoneEvent::EarlyMedia {
+                        call: self.id,
+                        audio: self.audio_info(),
+                    });
+                }
+
+                if !ringing && (code == StatusCode::RINGING || code == StatusCode::SESSION_PROGRESS)
+                {
+                    ringing = true;
+                    self.shared.emit(SoftphoneEvent::Ringing { call: self.id });
+                    self.report_refer_progress(code);
+
+                    if !early_media {
+                        ringback = LocalRingback::start(&self.shared, self.id);
+                    }
+                }
+            }
+        };
+
+        drop(ringback);
+
+        let mut ack = create_ack(&session.dialog, response.base_headers.cseq.cseq).await?;
+        endpoint
+            .send_outgoing_request(&mut ack)
+            .await
+            .map_err(sip_core::Error::from)?;
+
+        // Answered before the CANCEL request reached the peer
+        if cancellation.is_cancelled() {
+            session.terminate().await?;
+            return Ok(Setup::Ended(EndReason::LocalHangup));
+        }
+
+        // The answer was already received with the early media
+        if !early_media {
+            self.media
+                .receive_sdp_answer(parse_sdp(&response.body)?)
+                .await?;
+        }
+
+        Ok(Setup::Established(session))
+    }
+
+    /// Receive the next response to the INVITE, receiving early media while waiting if it was negotiated
+    async fn receive_response(
+        &mut self,
+        initiator: &mut InviteInitiator,
+        early_media: bool,
+    ) -> Result<Response, Error> {
+        let receive = initiator.receive();
+        tokio::pin!(receive);
+
+        loop {
+            select! {
+                response = &mut receive => return Ok(response?),
+                event = self.media.run(), if early_media => self.handle_media_event(event?),
+            }
+        }
+    }
+
+    async fn ring(
+        &mut self,
+        mut acceptor: InviteAcceptor,
+        offer: Bytes,
+        incoming: IncomingCall,
+    ) -> Result<Setup, Error> {
+        let ringing = acceptor.create_response(StatusCode::RINGING, None).await?;
+        acceptor.respond_provisional(ringing).await?;
+
+        self.shared.dispatch_incoming(incoming);
+
+        loop {
+            let command = select! {
+                // A CANCEL takes precedence over an answer sent at the same time
+                biased;
+                update = acceptor.receive_update() => match update {
+                    Some(update) => {
+                        respond_early_update(&acceptor, update).await?;
+                        continue;
+                    }
+                    // The peer cancelled the INVITE
+                    None => {
+                        self.fail_pending_commands(|| Error::Cancelled);
+                        return Ok(Setup::Ended(EndReason::Cancelled));
+                    }
+                },
+                command = self.commands.recv() => command,
+            };
+
+            match command {
+                Some(Command::Answer(result)) => {
+                    // Failures to establish the call are reported with the call's end
+                    let _ = result.send(Ok(()));
+
+                    return self.answer(acceptor, offer).await;
+                }
+                Some(Command::Hangup) | None => {
+                    decline(acceptor, StatusCode::DECLINE).await;
+                    return Ok(Setup::Ended(EndReason::LocalHangup));
+                }
+                Some(Command::MaxDuration(max_duration)) => self.set_max_duration(max_duration),
+                Some(Command::TerminateAt(at)) => self.set_terminate_at(at),
+                Some(Command::AudioSamples(_))
+                | Some(Command::SendRtp(_))
+                | Some(Command::Unbridge { .. })
+                | Some(Command::RelayRtp { .. }) => {}
+                Some(command) => command.reject(),
+            }
+        }
+    }
+
+    /// Stop receiving commands and respond to the ones already sent with an error
+    fn fail_pending_commands(&mut self, error: impl Fn() -> Error) {
+        self.commands.close();
+
+        while let Ok(command) = self.commands.try_recv() {
+            command.fail(error());
+        }
+    }
+
+    async fn answer(&mut self, mut acceptor: InviteAcceptor, offer: Bytes) -> Result<Setup, Error> {
+        acceptor.timer_config().interval_secs = self.shared.config.session_expires_secs();
+
+        if offer.is_empty() {
+            // The peer expects the offer in the response and sends its answer with the ACK
+            self.media.add_media(self.local_media, Direction::SendRecv);
+            let offer = self.media.create_sdp_offer().await?;
+            self.shared.refill_candidate_cache();
+
+            let (session, ack) = acceptor.accept_with_sdp(offer.to_string()).await?;
+
+            self.media.receive_sdp_answer(parse_sdp(&ack.body)?).await?;
+
+            return Ok(Setup::Established(session));
+        }
+
+        let offer = match parse_sdp(&offer) {
+            Ok(offer) => offer,
+            Err(e) => {
+                decline(acceptor, StatusCode::NOT_ACCEPTABLE_HERE).await;
+                return Err(e);
+            }
+        };
+
+        let answer = self.media.receive_sdp_offer(offer).await?;
+        self.shared.refill_candidate_cache();
+
+        let (session, _ack) = acceptor.accept_with_sdp(answer.to_string()).await?;
+
+        Ok(Setup::Established(session))
+    }
+
+    async fn run(&mut self, mut session: InviteSession) -> EndReason {
+        self.established_at = Some(Instant::now());
+
+        self.shared.set_dialog(
+            self.id,
+            EstablishedDialog {
+                key: session.dialog.key(),
+                remote_target: session.dialog.peer_contact.uri.uri.clone(),
+            },
+        );
+
+        self.shared
+            .emit(SoftphoneEvent::Established { call: self.id });
+
+        let reason = match self.run_session(&mut session).await {
+            Ok(reason) => reason,
+            Err(e) => {
+                if let Err(e) = session.terminate().await {
+                    log::debug!("Failed to send BYE after the call failed, {e}");
+                }
+
+                EndReason::Failed(e)
+            }
+        };
+
+        // The bridged call ends with this one
+        if let Some(bridge) = self.bridge.take() {
+            let _ = self.shared.hangup(bridge.peer);
+        }
+
+        reason
+    }
+
+    async fn run_session(&mut self, session: &mut InviteSession) -> Result<EndReason, Error> {
+        loop {
+            let deadline = self.deadline();
+            let warning_at = deadline
+                .zip(self.shared.config.termination_warning)
+                .filter(|_| !self.warned)
+                .map(|(deadline, warning)| deadline.checked_sub(warning).unwrap_or(deadline));
+
+            select! {
+                // A deadline which passed already hangs up without warning first
+                biased;
+                _ = sleep_until_opt(deadline) => {
+                    session.terminate().await?;
+                    return Ok(EndReason::TimeLimitReached);
+                }
+                _ = sleep_until_opt(warning_at) => {
+                    self.warned = true;
+
+                    if let Some(deadline) = deadline {
+                        self.shared.emit(SoftphoneEvent::TerminationWarning {
+                            call: self.id,
+                            remaining: deadline.saturating_duration_since(Instant::now()),
+                        });
+                    }
+                }
+                event = session.drive() => match event? {
+                    InviteSessionEvent::RefreshNeeded(event) => self.refresh_session(event).await?,
+                    InviteSessionEvent::ReInviteReceived(event) => self.handle_reinvite(event).await?,
+                    InviteSessionEvent::UpdateReceived(event) => self.handle_update(event).await?,
+                    InviteSessionEvent::ReferReceived(event) => self.handle_refer(event).await?,
+                    InviteSessionEvent::NotifyReceived(event) => {
+                        if self.handle_notify(event).await? {
+                            session.terminate().await?;
+                            return Ok(EndReason::Transferred);
+                        }
+                    }
+                    InviteSessionEvent::InfoReceived(event) => self.handle_info(event).await?,
+                    InviteSessionEvent::Bye(event) => {
+                        event.process_default().await?;
+                        return Ok(EndReason::RemoteHangup);
+                    }
+                    InviteSessionEvent::SessionExpired => return Ok(EndReason::SessionExpired),
+                    InviteSessionEvent::Terminated => return Ok(EndReason::RemoteHangup),
+                },
+                event = self.media.run() => self.handle_media_event(event?),
+                code = recv_refer_progress(&mut self.accepted_refer) => {
+                    // The referred call's task is gone without reporting a final status
+                    let code = code.unwrap_or(StatusCode::SERVER_INTERNAL_ERROR);
+
+                    self.notify_refer_progress(&session.dialog, code).await;
+                }
+                command = self.commands.recv() => match command {
+                    Some(Command::Hangup) | None => {
+                        session.terminate().await?;
+                        return Ok(EndReason::LocalHangup);
+                    }
+                    Some(Command::Hold { hold, result }) => {
+                        let _ = result.send(self.hold(session, hold).await);
+                    }
+                    Some(Command::Transfer { target, result }) => {
+                        // The call is hung up once the peer reports that the target answered
+                        let _ = result.send(self.transfer(session, target).await);
+                    }
+                    Some(Command::Dtmf { digits, result }) => {
+                        let _ = result.send(self.send_dtmf(session, &digits).await);
+                    }
+                    Some(Command::SendRtp(packet)) => {
+                        if let Some(media_id) = self.media_id {
+                            self.media.send_rtp(media_id, packet);
+                        }
+                    }
+                    Some(Command::AudioSamples(samples)) => {
+                        if let Some(media_id) = self.media_id {
+                            self.media.process_audio_samples(media_id, &samples);
+                        }
+                    }
+                    Some(Command::MaxDuration(max_duration)) => self.set_max_duration(max_duration),
+                    Some(Command::TerminateAt(at)) => self.set_terminate_at(at),
+                    Some(Command::Replaced(by)) => {
+                        session.terminate().await?;
+                        return Ok(EndReason::Replaced(by));
+                    }
+                    Some(Command::Bridge { bridge, result }) => {
+                        let _ = result.send(self.set_bridge(bridge));
+                    }
+                    Some(Command::Unbridge { peer }) => self.unbridge(peer),
+                    Some(Command::RelayRtp { packet, codec }) => self.relay_rtp(packet, &codec),
+                    Some(command) => command.reject(),
+                },
+            }
+        }
+    }
+
+    fn set_max_duration(&mut self, max_duration: Duration) {
+        self.max_duration = Some(max_duration);
+        self.warned = false;
+    }
+
+    fn set_terminate_at(&mut self, at: Instant) {
+        self.terminate_at = Some(at);
+        self.warned = false;
+    }
+
+    /// When the established call must be hung up, the earlier of the duration limit and the scheduled termination
+    fn deadline(&self) -> Option<Instant> {
+        let max_duration_reached = self
+            .established_at
+            .zip(self.max_duration)
+            .map(|(established_at, max_duration)| established_at + max_duration);
+
+        match (max_duration_reached, self.terminate_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn handle_media_event(&mut self, event: AsyncEvent) {
+        match event {
+            AsyncEvent::MediaAdded(added) => {
+                self.media_id = Some(added.id);
+                self.media.set_tone_detection(added.id, true);
+            }
+            AsyncEvent::MediaRemoved(media_id) if self.media_id == Some(media_id) => {
+                self.media_id = None;
+            }
+            AsyncEvent::ToneDetected(detected) => {
+                if let Tone::Dtmf(digit) = detected.tone {
+                    self.dtmf_received(digit);
+                }
+            }
+            AsyncEvent::DtmfReceived(received) => self.dtmf_received(received.digit),
+            AsyncEvent::ReceiveRTP { packet, .. } => {
+                if let Some(bridge) = &self.bridge {
+                    if let Some(codec) = self.audio_info() {
+                        let _ = self
+                            .shared
+                            .send_command(bridge.peer, Command::RelayRtp { packet, codec });
+                    }
+
+                    return;
+                }
+
+                self.shared.emit(SoftphoneEvent::Rtp {
+                    call: self.id,
+                    packet,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_reinvite(&mut self, event: ReInviteReceived<'_>) -> Result<(), Error> {
+        let dialog = event.session.dialog.clone();
+
+        if event.invite.body.is_empty() {
+            // The peer expects the offer in the response and sends its answer with the ACK
+            let offer = self.media.create_sdp_offer().await?;
+
+            let mut response = dialog.create_response(&event.invite, StatusCode::OK, None)?;
+            set_sdp(&mut response, &offer);
+
+            let ack = event.respond_success(response).await?;
+
+            self.media.receive_sdp_answer(parse_sdp(&ack.body)?).await?;
+            self.emit_renegotiated();
+
+            return Ok(());
+        }
+
+        let offer = match parse_sdp(&event.invite.body) {
+            Ok(offer) => offer,
+            Err(e) => {
+                log::warn!("Rejecting re-INVITE with invalid SDP, {e}");
+
+                let response =
+                    dialog.create_response(&event.invite, StatusCode::NOT_ACCEPTABLE_HERE, None)?;
+                event.transaction.respond_failure(response).await?;
+
+                return Ok(());
+            }
+        };
+
+        let answer = self.media.receive_sdp_offer(offer).await?;
+
+        let mut response = dialog.create_response(&event.invite, StatusCode::OK, None)?;
+        set_sdp(&mut response, &answer);
+
+        event.respond_success(response).await?;
+        self.emit_renegotiated();
+
+        Ok(())
+    }
+
+    /// Answer the SDP offer of an UPDATE, or only refresh the session if it has no body
+    /// Emit DTMF digits received using INFO requests with `application/dtmf-relay` or `application/dtmf` bodies
+    async fn handle_info(&mut self, event: InfoReceived<'_>) -> Result<(), Error> {
+        if event.info.body.is_empty() {
+            // Empty INFO requests are used to probe if the dialog is still alive
+            event.process_default().await?;
+            return Ok(());
+        }
+
+        let Ok(content_type) = event.info.headers.get_named::<ContentType>() else {
+            event.respond(StatusCode::UNSUPPORTED_MEDIA_TYPE).await?;
+            return Ok(());
+        };
+
+        let media_type = content_type.0.split(';').next().unwrap_or_default().trim();
+
+        let digit = if media_type.eq_ignore_ascii_case("application/dtmf-relay") {
+            parse_dtmf_relay(&event.info.body)
+        } else if media_type.eq_ignore_ascii_case("application/dtmf") {
+            parse_dtmf_digit(&event.info.body)
+        } else {
+            event.respond(StatusCode::UNSUPPORTED_MEDIA_TYPE).await?;
+            return Ok(());
+        };
+
+        let Some(digit) = digit else {
+            event.respond(StatusCode::BAD_REQUEST).await?;
+            return Ok(());
+        };
+
+        event.process_default().await?;
+
+        self.dtmf_received(digit);
+
+        Ok(())
+    }
+
+    /// Report a DTMF digit received in any way, and send it to the bridged call
+    fn dtmf_received(&self, digit: char) {
+        self.shared.emit(SoftphoneEvent::Dtmf {
+            call: self.id,
+            digit,
+        });
+
+        if let Some(bridge) = &self.bridge {
+            // Nobody waits for the result of forwarded digits
+            let (result, _) = oneshot::channel();
+
+            let _ = self.shared.send_command(
+                bridge.peer,
+                Command::Dtmf {
+                    digits: digit.to_string(),
+                    result,
+                },
+            );
+        }
+    }
+
+    fn set_bridge(&mut self, bridge: Bridge) -> Result<(), Error> {
+        if self
+            .bridge
+            .as_ref()
+            .is_some_and(|existing| existing.peer != bridge.peer)
+        {
+            return Err(Error::InvalidState);
+        }
+
+        self.bridge = Some(bridge);
+
+        Ok(())
+    }
+
+    fn unbridge(&mut self, peer: Option<CallId>) {
+        if peer.is_some_and(|peer| {
+            self.bridge
+                .as_ref()
+                .is_some_and(|bridge| bridge.peer != peer)
+        }) {
+            return;
+        }
+
+        // The peer dissolves its side of the bridge if it is still with this call
+        if let Some(bridge) = self.bridge.take() {
+            let _ = self.shared.send_command(
+                bridge.peer,
+                Command::Unbridge {
+                    peer: Some(self.id),
+                },
+            );
+        }
+    }
+
+    /// Send audio received in the bridged call, if this call is still bridged with it
+    fn relay_rtp(&mut self, packet: RtpPacket, codec: &MediaInfo) {
+        let Some(media_id) = self.media_id else {
+            return;
+        };
+
+        let Some(audio) = self.audio_info() else {
+            return;
+        };
+
+        let Some(bridge) = &mut self.bridge else {
+            return;
+        };
+
+        if let Some(packet) = bridge.relay(packet, codec, &audio) {
+            self.media.send_rtp(media_id, packet);
+        }
+    }
+
+    async fn handle_update(&mut self, event: UpdateReceived<'_>) -> Result<(), Error> {
+        if event.update.body.is_empty() {
+            event.process_default().await?;
+            return Ok(());
+        }
+
+        let dialog = event.session.dialog.clone();
+
+        let offer = match parse_sdp(&event.update.body) {
+            Ok(offer) => offer,
+            Err(e) => {
+                log::warn!("Rejecting UPDATE with invalid SDP, {e}");
+
+                let response =
+                    dialog.create_response(&event.update, StatusCode::NOT_ACCEPTABLE_HERE, None)?;
+                event.respond(response).await?;
+
+                return Ok(());
+            }
+        };
+
+        let answer = self.media.receive_sdp_offer(offer).await?;
+
+        let mut response = dialog.create_response(&event.update, StatusCode::OK, None)?;
+        set_sdp(&mut response, &answer);
+
+        event.respond(response).await?;
+        self.emit_renegotiated();
+
+        Ok(())
+    }
+
+    /// Report the state of the audio after the peer changed it using a re-INVITE or UPDATE
+    fn emit_renegotiated(&self) {
+        self.shared.emit(SoftphoneEvent::Renegotiated {
+            call: self.id,
+            audio: self.audio_info(),
+        });
+    }
+
+    fn audio_info(&self) -> Option<MediaInfo> {
+        self.media
+            .medias()
+            .find(|media| media.media_type == MediaType::Audio)
+    }
+
+    /// Put the audio on hold or resume it using a re-INVITE
+    ///
+    /// Held audio is offered `sendonly`, or `inactive` if the peer already put the call on hold.
+    async fn hold(&mut self, session: &InviteSession, hold: bool) -> Result<(), Error> {
+        let media_id = self.media_id.ok_or(Error::InvalidState)?;
+
+        if self.held == hold {
+            return Ok(());
+        }
+
+        let remote_hold = self
+            .media
+            .medias()
+            .any(|media| media.id == media_id && media.remote_hold);
+
+        let direction = match (hold, remote_hold) {
+            (true, true) => Direction::Inactive,
+            (true, false) => Direction::SendOnly,
+            (false, _) => Direction::SendRecv,
+        };
+
+        self.media.update_media(media_id, direction);
+
+        let offer = self.media.create_sdp_offer().await?;
+
+        match self.send_reinvite(session, &offer, false).await {
+            Ok(answer) => self.media.receive_sdp_answer(answer).await?,
+            Err(e) => {
+                self.media.rollback_changes().await?;
+                return Err(e);
+            }
+        }
+
+        self.held = hold;
+
+        let event = if hold {
+            SoftphoneEvent::Held { call: self.id }
+        } else {
+            SoftphoneEvent::Resumed { call: self.id }
+        };
+        self.shared.emit(event);
+
+        Ok(())
+    }
+
+    /// Refresh the session before it expires (RFC 4028)
+    ///
+    /// Uses an UPDATE without body if the peer allows it, otherwise a re-INVITE offering the current media.
+    async fn refresh_session(&mut self, event: RefreshNeeded<'_>) -> Result<(), Error> {
+        if event.session.peer_allows_update() {
+            event.process_update().await?;
+            return Ok(());
+        }
+
+        let offer = self.media.create_sdp_offer().await?;
+
+        match self.send_reinvite(event.session, &offer, true).await {
+            Ok(answer) => Ok(self.media.receive_sdp_answer(answer).await?),
+            Err(e) => {
+                self.media.rollback_changes().await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Send a re-INVITE with the given offer, returns the answer
+    ///
+    /// Refreshes of the session contain the `Session-Expires` header of the negotiated session timer.
+    async fn send_reinvite(
+        &mut self,
+        session: &InviteSession,
+        offer: &SessionDescription,
+        refresh: bool,
+    ) -> Result<SessionDescription, Error> {
+        let body = offer.to_string();
+
+        'attempts: loop {
+            let mut invite = session.dialog.create_request(Method::INVITE);
+            invite.headers.insert(Name::CONTENT_TYPE, "application/sdp");
+            invite.body = body.clone().into();
+
+            if refresh {
+                session.session_timer.populate_refresh(&mut invite);
+            }
+
+            self.authenticator
+                .authorize_request(&invite.line, &mut invite.headers, &invite.body);
+
+            let mut target_tp_info = session.dialog.target_tp_info.lock().await;
+            let mut transaction = session
+                .endpoint
+                .send_invite(invite, &mut target_tp_info)
+                .await?;
+            drop(target_tp_info);
+
+            while let Some(response) = transaction.receive().await? {
+                match response.line.code.kind() {
+                    CodeKind::Provisional => {}
+                    CodeKind::Success => {
+                        let mut ack =
+                            create_ack(&session.dialog, response.base_headers.cseq.cseq).await?;
+
+                        session
+                            .endpoint
+                            .send_outgoing_request(&mut ack)
+                            .await
+                            .map_err(sip_core::Error::from)?;
+
+                        return parse_sdp(&response.body);
+                    }
+                    _ if authenticate(
+                        &mut self.authenticator,
+                        &transaction.request().msg,
+                        &response,
+                    ) =>
+                    {
+                        continue 'attempts;
+                    }
+                    _ => return Err(Error::Rejected(response.line.code)),
+                }
+            }
+
+            return Err(sip_core::Error::RequestTimedOut.into());
+        }
+    }
+
+    /// Ask the peer to call the target using a REFER request
+    async fn transfer(&mut self, session: &InviteSession, target: SipUri) -> Result<(), Error> {
+        let refer_to = ReferTo::new(NameAddr::uri(target));
+
+        self.send_request(&session.dialog, |dialog| {
+            let mut request = dialog.create_request(Method::REFER);
+            request.headers.insert_named(&refer_to);
+            request
+        })
+        .await
+    }
+
+    /// Report the progress of a transfer requested using [`transfer`](Self::transfer), returns if the transfer
+    /// succeeded and the call must be hung up
+    async fn handle_notify(&mut self, event: NotifyReceived<'_>) -> Result<bool, Error> {
+        let status = event.status();
+
+        event.process_default().await?;
+
+        let Some(code) = status else {
+            log::warn!("Ignoring NOTIFY without valid sipfrag body");
+            return Ok(false);
+        };
+
+        self.shared.emit(SoftphoneEvent::TransferProgress {
+            call: self.id,
+            code,
+        });
+
+        Ok(code.kind() == CodeKind::Success)
+    }
+
+    /// Accept a REFER request by calling its target, the progress of that call is reported using NOTIFY requests
+    async fn handle_refer(&mut self, event: ReferReceived<'_>) -> Result<(), Error> {
+        if self.accepted_refer.is_some() {
+            event.reject(StatusCode::REQUEST_PENDING).await?;
+            return Ok(());
+        }
+
+        let mut target = match event.refer_to() {
+            Ok(refer_to) => refer_to.uri.uri,
+            Err(e) => {
+                log::warn!("Rejecting REFER with invalid Refer-To header, {e}");
+                event.reject(StatusCode::BAD_REQUEST).await?;
+                return Ok(());
+            }
+        };
+
+        // Headers of the URI are added to the request sent to it, only Replaces is supported
+        let replaces = match target.header_params.take("Replaces").map(parse_replaces) {
+            None => None,
+            Some(Some(replaces)) => Some(replaces),
+            Some(None) => {
+                log::warn!("Rejecting REFER with invalid Replaces in Refer-To");
+                event.reject(StatusCode::BAD_REQUEST).await?;
+                return Ok(());
+            }
+        };
+        target.header_params = Params::new();
+
+        let dialog = event.session.dialog.clone();
+        let endpoint = event.session.endpoint.clone();
+
+        let subscription = event.accept().await?;
+
+        let (progress, progress_rx) = mpsc::unbounded_channel();
+        self.accepted_refer = Some((subscription, progress_rx));
+
+        self.notify_refer_progress(&dialog, StatusCode::TRYING)
+            .await;
+
+        let new_call = dial(
+            &self.shared,
+            endpoint,
+            self.account,
+            target.clone(),
+            Outgoing::Referred(Referred { replaces, progress }),
+        );
+
+        self.shared.emit(SoftphoneEvent::Referred {
+            call: self.id,
+            new_call,
+            target,
+        });
+
+        Ok(())
+    }
+
+    /// Send a NOTIFY for the accepted REFER, ends the subscription with a final status code
+    async fn notify_refer_progress(&mut self, dialog: &Dialog, code: StatusCode) {
+        let Some((subscription, _)) = &self.accepted_refer else {
+            return;
+        };
+
+        let subscription = subscription.clone();
+
+        if code.kind() != CodeKind::Provisional {
+            self.accepted_refer = None;
+        }
+
+        if let Err(e) = self
+            .send_request(dialog, |_| subscription.create_notify(code))
+            .await
+        {
+            log::debug!("Failed to send NOTIFY for accepted REFER, {e}");
+        }
+    }
+
+    /// Report the progress of a call made for a REFER request, to the call which received it
+    fn report_refer_progress(&mut self, code: StatusCode) {
+        let Some(progress) = &self.refer_progress else {
+            return;
+        };
+
+        let _ = progress.send(code);
+
+        if code.kind() != CodeKind::Provisional {
+            self.refer_progress = None;
+        }
+    }
+
+    /// Send DTMF digits using INFO requests with `application/dtmf-relay` bodies
+    async fn send_dtmf(&mut self, session: &InviteSession, digits: &str) -> Result<(), Error> {
+        for digit in digits.chars() {
+            let body = format!("Signal={digit}\r\nDuration=160\r\n");
+
+            self.send_request(&session.dialog, |dialog| {
+                let mut request = dialog.create_request(Method::INFO);
+                request
+                    .headers
+                    .insert(Name::CONTENT_TYPE, "application/dtmf-relay");
+                request.body = body.clone().into();
+                request
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send a request inside the call's dialog, authenticating it if required
+    async fn send_request(
+        &mut self,
+        dialog: &Dialog,
+        create: impl Fn(&Dialog) -> Request,
+    ) -> Result<(), Error> {
+        loop {
+            let mut request = create(dialog);
+            self.authenticator.authorize_request(
+                &request.line,
+                &mut request.headers,
+                &request.body,
+            );
+
+            let mut target_tp_info = dialog.target_tp_info.lock().await;
+            let mut transaction = dialog
+                .endpoint
+                .send_request(request, &mut target_tp_info)
+                .await?;
+            drop(target_tp_info);
+
+            let response = transaction.receive_final().await?;
+
+            if response.line.code.kind() == CodeKind::Success {
+                return Ok(());
+            }
+
+            if !authenticate(
+                &mut self.authenticator,
+                &transaction.request().msg,
+                &response,
+            ) {
+                return Err(Error::Rejected(response.line.code));
+            }
+        }
+    }
+}
+
+/// Task generating ringback tone audio for a ringing dialed call, stopped when dropped
+struct LocalRingback(JoinHandle<()>);
+
+impl LocalRingback {
+    /// Start generating ringback if it's enabled in the softphone's config
+    fn start(shared: &Arc<Shared>, call: CallId) -> Option<Self> {
+        let (region, sample_rate) = shared.config.local_ringback?;
+
+        let shared = shared.clone();
+        let mut generator = RingbackGenerator::new(region, sample_rate);
+        let chunk_len = sample_rate as usize / 50;
+
+        let task = tokio::spawn(async move {
+            let mut interval = interval(Duration::from_millis(20));
+
+            loop {
+                interval.tick().await;
+
+                let mut samples = vec![0; chunk_len];
+                generator.fill(&mut samples);
+
+                shared.emit(SoftphoneEvent::RingbackAudio { call, samples });
+            }
+        });
+
+        Some(Self(task))
+    }
+}
+
+impl Drop for LocalRingback {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Sleep until the given instant, forever if there is none
+async fn sleep_until_opt(at: Option<Instant>) {
+    match at {
+        Some(at) => sleep_until(at).await,
+        None => pending().await,
+    }
+}
+
+/// Receive the progress of the call made for an accepted REFER, forever if there is none
+async fn recv_refer_progress(
+    accepted_refer: &mut Option<(ReferSubscription, mpsc::UnboundedReceiver<StatusCode>)>,
+) -> Option<StatusCode> {
+    match accepted_refer {
+        Some((_, progress)) => progress.recv().await,
+        None => pending().await,
+    }
+}
+
+/// Create the media session of a call, offering the configured codecs
+fn new_media(shared: &Shared, options: Options) -> Result<(AsyncSdpSession, LocalMediaId), Error> {
+    let config = &shared.config;
+
+    let mut media = AsyncSdpSession::new(config.local_ip, options);
+    media.set_local_ip(config.local_ip);
+
+    if let Some(server) = config.stun_server {
+        media.add_stun_server(server);
+    }
+
+    if let Some(cached) = &shared.candidate_cache {
+        media.set_candidate_cache(cached.cache.clone());
+    }
+
+    let local_media = media
+        .add_local_media(config.codecs.clone(), 1, Direction::SendRecv)
+        .ok_or(Error::NoCodecs)?;
+
+    Ok((media, local_media))
+}
+
+/// Take the response the initiator just forwarded to one of the early dialogs
+fn forwarded_early_response(early: &mut [Early]) -> Result<Option<EarlyResponse>, Error> {
+    let mut cx = Context::from_waker(Waker::noop());
+
+    for early in early {
+        if let Poll::Ready(response) = early.poll_receive(&mut cx) {
+            return Ok(Some(response?));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Respond to an UPDATE received while an incoming call is ringing
+///
+/// The offer of the INVITE is only answered once the call is accepted, a new offer cannot be accepted before that
+/// (RFC 3311 section 5.2). UPDATE requests without body only refresh the early dialog.
+async fn respond_early_update(acceptor: &InviteAcceptor, update: EarlyUpdate) -> Result<(), Error> {
+    let EarlyUpdate {
+        update,
+        transaction,
+    } = update;
+
+    let response = if update.body.is_empty() {
+        acceptor
+            .create_update_response(&update, StatusCode::OK, None)
+            .await?
+    } else {
+        let mut response = acceptor
+            .create_update_response(&update, StatusCode::SERVER_INTERNAL_ERROR, None)
+            .await?;
+        response
+            .msg
+            .headers
+            .insert_named(&RetryAfter::new(rand::random_range(0..=10)));
+        response
+    };
+
+    transaction.respond(response).await?;
+
+    Ok(())
+}
+
+async fn decline(acceptor: InviteAcceptor, code: StatusCode) {
+    let result = async {
+        let response = acceptor.create_response(code, None).await?;
+        acceptor.respond_failure(response).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        log::debug!("Failed to decline incoming call, {e}");
+    }
+}
+
+/// Parse the `Signal` of an `application/dtmf-relay` body, e.g. `Signal=5\r\nDuration=160\r\n`
+/// Parse the value of the `Replaces` header of a Refer-To URI
+fn parse_replaces(value: BytesStr) -> Option<Replaces> {
+    Replaces::decode(&mut std::iter::once(&value))
+        .ok()
+        .map(|(_, replaces)| replaces)
+}
+
+fn parse_dtmf_relay(body: &Bytes) -> Option<char> {
+    let body = std::str::from_utf8(body).ok()?;
+
+    body.lines().find_map(|line| {
+        let (name, value) = line.split_once('=')?;
+
+        if name.trim().eq_ignore_ascii_case("signal") {
+            parse_dtmf_digit(value.trim().as_bytes())
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse a single DTMF digit, as the body of an `application/dtmf` INFO request
+fn parse_dtmf_digit(body: &[u8]) -> Option<char> {
+    match std::str::from_utf8(body).ok()?.trim().as_bytes() {
+        &[digit] => Some(char::from(digit).to_ascii_uppercase()).filter(|c| is_dtmf_digit(*c)),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_sdp(body: &Bytes) -> Result<SessionDescription, Error> {
+    let sdp = BytesStr::from_utf8_bytes(body.clone()).map_err(|_| Error::InvalidSdpEncoding)?;
+
+    Ok(SessionDescription::parse(&sdp)?)
+}
+
+pub(crate) fn set_sdp(response: &mut OutgoingResponse, sdp: &SessionDescription) {
+    response
+        .msg
+        .headers
+        .insert_named(&ContentType("application/sdp".into()));
+    response.msg.body = sdp.to_string().into();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dtmf_relay() {
+        let parse = |body: &'static str| parse_dtmf_relay(&Bytes::from_static(body.as_bytes()));
+
+        assert_eq!(parse("Signal=5\r\nDuration=160\r\n"), Some('5'));
+        assert_eq!(parse("Duration=160\r\nsignal = #\r\n"), Some('#'));
+        assert_eq!(parse("Signal=a\r\n"), Some('A'));
+        assert_eq!(parse("Signal=16\r\n"), None);
+        assert_eq!(parse("Signal=E\r\n"), None);
+        assert_eq!(parse("Duration=160\r\n"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn dtmf_digit() {
+        assert_eq!(parse_dtmf_digit(b"7"), Some('7'));
+        assert_eq!(parse_dtmf_digit(b" *\r\n"), Some('*'));
+        assert_eq!(parse_dtmf_digit(b"d"), Some('D'));
+        assert_eq!(parse_dtmf_digit(b"12"), None);
+        assert_eq!(parse_dtmf_digit(b"x"), None);
+        assert_eq!(parse_dtmf_digit(b""), None);
+        assert_eq!(parse_dtmf_digit(&[0xff]), None);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{AccountId, Error};
+use rtp::RtpPacket;
+use session::MediaInfo;
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::StatusCode;
+use sip_ua::subscription::message_summary::MessageSummary;
+use std::fmt;
+use std::time::Duration;
+
+/// Identifies a call of a [`Softphone`](crate::Softphone)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CallId(pub(crate) u64);
+
+impl fmt::Display for CallId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "call-{}", self.0)
+    }
+}
+
+/// Event returned by [`Softphone::next_event`](crate::Softphone::next_event)
+#[derive(Debug)]
+pub enum SoftphoneEvent {
+    /// The registration of the account was successful or has been refreshed
+    Registered { account: AccountId },
+
+    /// Registering the account failed, contains the status code of the registrar's response if one was received
+    ///
+    /// The registration is retried as long as the retry policy allows it.
+    RegistrationFailed {
+        account: AccountId,
+        code: Option<StatusCode>,
+    },
+
+    /// The retry policy gave up after registering the account failed, no more attempts are made
+    RegistrationGaveUp { account: AccountId },
+
+    /// The registrar of the account didn't respond to an OPTIONS keepalive request, see
+    /// [`Account::keepalive`](crate::Account::keepalive)
+    ///
+    /// The account registers again right away, which is reported using [`SoftphoneEvent::Registered`] or
+    /// [`SoftphoneEvent::RegistrationFailed`].
+    RegistrarUnreachable { account: AccountId },
+
+    /// The message waiting indication of the account changed, see [`Account::message_waiting`](crate::Account::message_waiting)
+    ///
+    /// Use [`MessageSummary::voice_messages`] to get the number of new and old voicemails.
+    MessageWaiting {
+        account: AccountId,
+        summary: MessageSummary,
+    },
+
+    /// A call to the account is ringing, accept it using [`Softphone::answer`](crate::Softphone::answer) or
+    /// decline it using [`Softphone::hangup`](crate::Softphone::hangup)
+    IncomingCall {
+        call: CallId,
+        account: AccountId,
+        from: NameAddr,
+    },
+
+    /// The peer of a dialed call is ringing
+    Ringing { call: CallId },
+
+    /// The peer of a dialed call sent a provisional response, e.g. `180 Ringing` or `183 Session Progress`
+    Progress { call: CallId, code: StatusCode },
+
+    /// A provisional response of a dialed call answered the SDP offer, contains the negotiated audio
+    ///
+    /// Audio sent before the call is answered, e.g. ringback tone or announcements of the network, is reported
+    /// using [`SoftphoneEvent::Rtp`]. Local ringback is not generated for the call.
+    EarlyMedia {
+        call: CallId,
+        audio: Option<MediaInfo>,
+    },
+
+    /// Ringback tone audio of a ringing dialed call, to be played locally, see
+    /// [`SoftphoneBuilder::local_ringback`](crate::SoftphoneBuilder::local_ringback)
+    RingbackAudio { call: CallId, samples: Vec<i16> },
+
+    /// The call was answered and media is being exchanged
+    Established { call: CallId },
+
+    /// The peer sent a DTMF digit, either using SIP INFO, as RFC 4733 telephone-event or detected inband in the
+    /// received audio
+    Dtmf { call: CallId, digit: char },
+
+    /// The call is about to be hung up because its time limit is reached, see
+    /// [`Softphone::set_max_duration`](crate::Softphone::set_max_duration) and
+    /// [`Softphone::terminate_at`](crate::Softphone::terminate_at)
+    ///
+    /// Emitted once per deadline, ahead of it by the configured
+    /// [`termination_warning`](crate::SoftphoneBuilder::termination_warning), e.g. to play an announcement.
+    TerminationWarning { call: CallId, remaining: Duration },
+
+    /// The peer reported the progress of a transfer requested using [`Softphone::transfer`](crate::Softphone::transfer)
+    ///
+    /// Contains the status code of the peer's call to the transfer target. A final failure code means the transfer
+    /// failed and the call continues.
+    TransferProgress { call: CallId, code: StatusCode },
+
+    /// The peer transferred the call, `new_call` is dialing the transfer target
+    ///
+    /// The progress of `new_call` is reported to the peer, which usually hangs up the call once the target answered.
+    Referred {
+        call: CallId,
+        new_call: CallId,
+        target: SipUri,
+    },
+
+    /// The call was put on hold using [`Softphone::hold`](crate::Softphone::hold)
+    Held { call: CallId },
+
+    /// The call was resumed using [`Softphone::resume`](crate::Softphone::resume)
+    Resumed { call: CallId },
+
+    /// The peer changed the call's media using a re-INVITE, e.g. to put the call on hold or to resume it
+    ///
+    /// Contains the call's audio after the change, `None` if the peer removed it. The peer put the call on hold if
+    /// [`remote_hold`](MediaInfo::remote_hold) is set.
+    Renegotiated {
+        call: CallId,
+        audio: Option<MediaInfo>,
+    },
+
+    /// Received an RTP packet of the call's audio
+    Rtp { call: CallId, packet: RtpPacket },
+
+    /// The call has ended, no more events are emitted for it
+    Ended { call: CallId, reason: EndReason },
+}
+
+/// Why a call has ended, see [`SoftphoneEvent::Ended`]
+#[derive(Debug)]
+pub enum EndReason {
+    /// Hung up using [`Softphone::hangup`](crate::Softphone::hangup)
+    LocalHangup,
+    /// Hung up by the peer
+    RemoteHangup,
+    /// The peer cancelled the incoming call before it was answered
+    Cancelled,
+    /// The dialed call was rejected with the given status code
+    Rejected(StatusCode),
+    /// The call was transferred using [`Softphone::transfer`](crate::Softphone::transfer) or
+    /// [`Softphone::transfer_attended`](crate::Softphone::transfer_attended)
+    Transferred,
+    /// The peer replaced the call with the given call, which was answered automatically (attended transfer)
+    Replaced(CallId),
+    /// The peer didn't refresh the session before the negotiated session interval expired, see
+    /// [`SoftphoneBuilder::session_expires`](crate::SoftphoneBuilder::session_expires)
+    SessionExpired,
+    /// The call was hung up after reaching its time limit, see
+    /// [`Softphone::set_max_duration`](crate::Softphone::set_max_duration) and
+    /// [`Softphone::terminate_at`](crate::Softphone::terminate_at)
+    TimeLimitReached,
+    /// The call failed due to an error
+    Failed(Error),
+}
@@ -0,0 +1,161 @@
+use crate::{AccountId, CallId};
+use sip_types::uri::{NameAddr, SipUri, SipUriUserPart};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tokio_stream::Stream;
+
+/// Selects the incoming calls delivered to a subscription, see
+/// [`Softphone::incoming_calls`](crate::Softphone::incoming_calls)
+///
+/// All criteria must match, a filter without any criteria matches every call.
+#[derive(Debug, Default, Clone)]
+pub struct IncomingCallFilter {
+    account: Option<AccountId>,
+    request_user: Option<String>,
+    to_user: Option<String>,
+}
+
+impl IncomingCallFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match calls assigned to the given account
+    pub fn account(mut self, account: AccountId) -> Self {
+        self.account = Some(account);
+        self
+    }
+
+    /// Only match calls whose request URI has the given user part
+    pub fn request_user(mut self, user: impl Into<String>) -> Self {
+        self.request_user = Some(user.into());
+        self
+    }
+
+    /// Only match calls whose `To` header URI has the given user part
+    pub fn to_user(mut self, user: impl Into<String>) -> Self {
+        self.to_user = Some(user.into());
+        self
+    }
+
+    pub(crate) fn matches(&self, call: &IncomingCall) -> bool {
+        fn user_matches(expected: &Option<String>, uri: &SipUri) -> bool {
+            match (expected, &uri.user_part) {
+                (None, _) => true,
+                (Some(expected), SipUriUserPart::User(user)) => user == expected.as_str(),
+                (Some(_), _) => false,
+            }
+        }
+
+        self.account.is_none_or(|account| account == call.account)
+            && user_matches(&self.request_user, &call.request_uri)
+            && user_matches(&self.to_user, &call.to.uri)
+    }
+}
+
+/// A ringing incoming call delivered to a subscription
+///
+/// Accept it using [`Softphone::answer`](crate::Softphone::answer) or decline it using
+/// [`Softphone::hangup`](crate::Softphone::hangup), all further events of the call are reported using
+/// [`Softphone::next_event`](crate::Softphone::next_event).
+#[derive(Debug, Clone)]
+pub struct IncomingCall {
+    pub call: CallId,
+    pub account: AccountId,
+    pub from: NameAddr,
+    pub to: NameAddr,
+    pub request_uri: SipUri,
+}
+
+/// Stream of the incoming calls matching a filter, see
+/// [`Softphone::incoming_calls`](crate::Softphone::incoming_calls)
+///
+/// Dropping it ends the subscription, matching calls are then reported using
+/// [`SoftphoneEvent::IncomingCall`](crate::SoftphoneEvent::IncomingCall) again.
+pub struct IncomingCalls {
+    pub(crate) calls: mpsc::UnboundedReceiver<IncomingCall>,
+}
+
+impl IncomingCalls {
+    /// Wait for the next matching call, returns `None` once the softphone is gone
+    pub async fn next(&mut self) -> Option<IncomingCall> {
+        self.calls.recv().await
+    }
+}
+
+impl Stream for IncomingCalls {
+    type Item = IncomingCall;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.calls.poll_recv(cx)
+    }
+}
+
+/// Subscription created using [`Softphone::incoming_calls`](crate::Softphone::incoming_calls)
+pub(crate) struct Subscription {
+    pub(crate) filter: IncomingCallFilter,
+    pub(crate) calls: mpsc::UnboundedSender<IncomingCall>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use slotmap::SlotMap;
+
+    fn incoming_call(account: AccountId, request_uri: &str, to: &str) -> IncomingCall {
+        IncomingCall {
+            call: CallId(0),
+            account,
+            from: NameAddr::uri("sip:bob@example.org".parse().unwrap()),
+            to: NameAddr::uri(to.parse().unwrap()),
+            request_uri: request_uri.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let mut accounts = SlotMap::<AccountId, ()>::with_key();
+        let (alice, carol) = (accounts.insert(()), accounts.insert(()));
+
+        let call = incoming_call(alice, "sip:alice@127.0.0.1:5060", "sip:support@example.org");
+
+        assert!(IncomingCallFilter::new().matches(&call));
+        assert!(IncomingCallFilter::new().account(alice).matches(&call));
+        assert!(!IncomingCallFilter::new().account(carol).matches(&call));
+
+        assert!(IncomingCallFilter::new()
+            .request_user("alice")
+            .matches(&call));
+        assert!(!IncomingCallFilter::new().request_user("bob").matches(&call));
+
+        assert!(IncomingCallFilter::new().to_user("support").matches(&call));
+        assert!(!IncomingCallFilter::new().to_user("sales").matches(&call));
+
+        // All criteria must match
+        assert!(IncomingCallFilter::new()
+            .account(alice)
+            .request_user("alice")
+            .to_user("support")
+            .matches(&call));
+        assert!(!IncomingCallFilter::new()
+            .account(alice)
+            .request_user("alice")
+            .to_user("sales")
+            .matches(&call));
+    }
+
+    #[test]
+    fn filter_without_user_part() {
+        let mut accounts = SlotMap::<AccountId, ()>::with_key();
+        let alice = accounts.insert(());
+
+        let call = incoming_call(alice, "sip:127.0.0.1:5060", "sip:example.org");
+
+        assert!(IncomingCallFilter::new().matches(&call));
+        assert!(!IncomingCallFilter::new()
+            .request_user("alice")
+            .matches(&call));
+        assert!(!IncomingCallFilter::new().to_user("alice").matches(&call));
+    }
+}
@@ -0,0 +1,1276 @@
+//! High level SIP softphone
+//!
+//! [`Softphone`] combines a SIP endpoint, accounts with optional registrations and audio-only media sessions into a
+//! single object. Calls are dialed, answered, held, transferred and hung up using its methods, everything that happens
+//! is reported through [`Softphone::next_event`].
+//!
+//! The softphone makes opinionated choices: UDP signaling (optionally TCP or TLS), plain RTP audio using PCMU & PCMA by default, DTMF sent
+//! using SIP INFO and received using SIP INFO, RFC 4733 telephone-events or inband detection. Applications requiring anything else can use
+//! `ezk-sip-ua` and `ezk-session` directly, the underlying [`Endpoint`] is available using
+//! [`Softphone::endpoint`].
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), ezk_softphone::Error> {
+//! use ezk_softphone::{Softphone, SoftphoneEvent};
+//!
+//! let mut phone = Softphone::builder("sip:alice@example.com".parse().unwrap(), "192.168.1.2".parse().unwrap())
+//!     .registrar("sip:example.com".parse().unwrap())
+//!     .credentials("alice", "secret")
+//!     .build()
+//!     .await?;
+//!
+//! while let Some(event) = phone.next_event().await {
+//!     if let SoftphoneEvent::IncomingCall { call, .. } = event {
+//!         phone.answer(call).await?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use account::AccountEntry;
+use bridge::Bridge;
+use call::{CallHandle, Command, EstablishedDialog, IncomingCallLayer, Outgoing};
+use incoming::Subscription;
+use options::OptionsLayer;
+use rtp::{RingbackRegion, RtpPacket};
+use session::{CandidateCache, Codec, Codecs, MediaType, Options};
+use sip_auth::{ClientAuthenticator, DigestAuthenticator, RequestParts, ResponseParts};
+use sip_core::transaction::TsxResponse;
+use sip_core::transport::streaming::StreamingListenerBuilder;
+use sip_core::transport::tcp::{TcpConnector, TcpListener};
+use sip_core::transport::udp::Udp;
+use sip_core::{Endpoint, Request};
+use sip_types::header::typed::{Contact, Replaces, Routing};
+use sip_types::uri::params::{Param, Params};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::StatusCode;
+use sip_ua::dialog::DialogLayer;
+use sip_ua::invite::initiator::DialogInfo;
+use sip_ua::invite::session::SessionRefreshError;
+use sip_ua::invite::InviteLayer;
+use sip_ua::register::Registration;
+use sip_ua::subscription::SubscriptionLayer;
+use slotmap::SlotMap;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+#[cfg(feature = "tls-rustls")]
+use tokio_rustls::{
+    rustls::{ClientConfig, ServerConfig},
+    TlsAcceptor, TlsConnector,
+};
+
+mod account;
+mod bridge;
+mod call;
+mod event;
+mod incoming;
+mod message_waiting;
+mod options;
+mod registration;
+pub mod testsupport;
+
+pub use account::{Account, AccountId};
+pub use bridge::{G711Transcoder, Transcoder};
+pub use event::{CallId, EndReason, SoftphoneEvent};
+pub use incoming::{IncomingCall, IncomingCallFilter, IncomingCalls};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Core(#[from] sip_core::Error),
+    #[error(transparent)]
+    Accept(#[from] sip_ua::invite::acceptor::Error),
+    #[error(transparent)]
+    Media(#[from] session::Error),
+    #[error("failed to parse SDP, {0}")]
+    InvalidSdp(#[from] session::ParseSessionDescriptionError),
+    #[error("SDP is not valid UTF-8")]
+    InvalidSdpEncoding,
+    #[error("none of the configured codecs can be used")]
+    NoCodecs,
+    #[error("peer responded with {0:?}")]
+    Rejected(StatusCode),
+    #[error("unknown call {0}")]
+    UnknownCall(CallId),
+    #[error("unknown account {0:?}")]
+    UnknownAccount(AccountId),
+    #[error("call is not in a state to perform this action")]
+    InvalidState,
+    #[error("the caller cancelled the call")]
+    Cancelled,
+    #[error("invalid DTMF digit {0:?}")]
+    InvalidDtmf(char),
+    #[error("invalid call target {0:?}, expected an IP address or SIP URI")]
+    InvalidTarget(String),
+}
+
+impl From<SessionRefreshError> for Error {
+    fn from(e: SessionRefreshError) -> Self {
+        match e {
+            SessionRefreshError::Core(e) => Self::Core(e),
+            SessionRefreshError::UnexpectedStatus(code) => Self::Rejected(code),
+        }
+    }
+}
+
+/// Builder for a [`Softphone`], created using [`Softphone::builder`]
+pub struct SoftphoneBuilder {
+    account: Account,
+    local_ip: IpAddr,
+    sip_port: u16,
+    tcp: bool,
+    outbound_proxy: Option<SipUri>,
+    #[cfg(feature = "tls-rustls")]
+    tls_client: Option<Arc<ClientConfig>>,
+    #[cfg(feature = "tls-rustls")]
+    tls_server: Option<(u16, Arc<ServerConfig>)>,
+    codecs: Codecs,
+    media_options: Options,
+    termination_warning: Option<Duration>,
+    local_ringback: Option<(RingbackRegion, u32)>,
+    session_expires: Duration,
+    stun_server: Option<SocketAddr>,
+    candidate_cache: Option<(Duration, usize)>,
+}
+
+impl SoftphoneBuilder {
+    /// Display name used in the `From` header of dialed calls
+    pub fn display_name(mut self, name: impl Into<String>) -> Self {
+        self.account = self.account.display_name(name);
+        self
+    }
+
+    /// Local UDP port for SIP signaling, defaults to 5060
+    pub fn sip_port(mut self, port: u16) -> Self {
+        self.sip_port = port;
+        self
+    }
+
+    /// Also use SIP over TCP, disabled by default
+    ///
+    /// TCP connections are accepted on the SIP port. Outbound connections are made for targets with a
+    /// `transport=tcp` URI parameter and for requests too large to be sent using UDP (RFC 3261 section 18.1.1),
+    /// existing connections are reused. A registrar URI with a `transport=tcp` parameter registers a contact
+    /// requesting TCP, which allows the softphone to be used where UDP is blocked.
+    pub fn listen_tcp(mut self) -> Self {
+        self.tcp = true;
+        self
+    }
+
+    /// Connect to `sips:` targets and targets with a `transport=tls` URI parameter using TLS, disabled by default
+    ///
+    /// The server certificate is verified by the given config against the host of the target URI, e.g. the
+    /// registrar's hostname.
+    #[cfg(feature = "tls-rustls")]
+    pub fn tls(mut self, client_config: Arc<ClientConfig>) -> Self {
+        self.tls_client = Some(client_config);
+        self
+    }
+
+    /// Accept TLS connections on the given port, disabled by default
+    ///
+    /// If the registrar URI is a `sips:` URI, the registered contact is upgraded to a `sips:` URI using this port.
+    #[cfg(feature = "tls-rustls")]
+    pub fn listen_tls(mut self, port: u16, server_config: Arc<ServerConfig>) -> Self {
+        self.tls_server = Some((port, server_config));
+        self
+    }
+
+    /// Send all requests outside of dialogs via the given outbound proxy, e.g. a session border controller,
+    /// disabled by default
+    ///
+    /// The requests are sent to the proxy with a preloaded `Route` header, requests within a dialog follow the
+    /// route set of the dialog. Used by all accounts which don't set their own
+    /// [`Account::outbound_proxy`].
+    pub fn outbound_proxy(mut self, proxy: SipUri) -> Self {
+        self.outbound_proxy = Some(proxy);
+        self
+    }
+
+    /// Register the address of record at the given registrar, the registration is refreshed until the softphone is
+    /// dropped
+    pub fn registrar(mut self, registrar: SipUri) -> Self {
+        self.account = self.account.registrar(registrar);
+        self
+    }
+
+    /// Requested expiry of the registration, defaults to 10 minutes
+    pub fn registration_expiry(mut self, expiry: Duration) -> Self {
+        self.account = self.account.registration_expiry(expiry);
+        self
+    }
+
+    /// Send OPTIONS requests to the registrar in the given interval, see [`Account::keepalive`]
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.account = self.account.keepalive(interval);
+        self
+    }
+
+    /// Subscribe to the message waiting indication once registered, see [`Account::message_waiting`]
+    pub fn message_waiting(mut self) -> Self {
+        self.account = self.account.message_waiting();
+        self
+    }
+
+    /// Credentials used to answer digest authentication challenges of registrar and proxies
+    pub fn credentials(mut self, user: impl Into<String>, password: impl Into<Vec<u8>>) -> Self {
+        self.account = self.account.credentials(user, password);
+        self
+    }
+
+    /// Audio codecs offered & accepted in calls, defaults to PCMU and PCMA
+    pub fn codecs(mut self, codecs: Codecs) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    /// Options of the media sessions created for calls, defaults to [`Options::lan`]
+    pub fn media_options(mut self, options: Options) -> Self {
+        self.media_options = options;
+        self
+    }
+
+    /// Emit [`SoftphoneEvent::TerminationWarning`] this long before a call is hung up for reaching its time limit,
+    /// disabled by default
+    pub fn termination_warning(mut self, warning: Duration) -> Self {
+        self.termination_warning = Some(warning);
+        self
+    }
+
+    /// Generate ringback tone audio while a dialed call is ringing, reported using
+    /// [`SoftphoneEvent::RingbackAudio`] in 20ms chunks with the given sample rate, disabled by default
+    ///
+    /// The tone is generated while an outgoing call is ringing and stops once early media is received, the call is
+    /// answered or it ends.
+    pub fn local_ringback(mut self, region: RingbackRegion, sample_rate: u32) -> Self {
+        self.local_ringback = Some((region, sample_rate));
+        self
+    }
+
+    /// STUN server used to discover the server reflexive ICE candidates of calls, disabled by default
+    ///
+    /// Only used if ICE is enabled in the [media options](Self::media_options), see [`Options::offer_ice`].
+    pub fn stun_server(mut self, server: SocketAddr) -> Self {
+        self.stun_server = Some(server);
+        self
+    }
+
+    /// Keep the given number of media sockets, with their server reflexive address discovered ahead of time, in a
+    /// cache shared by all calls, disabled by default
+    ///
+    /// Calls take their sockets from the cache instead of waiting for the [STUN server](Self::stun_server) to
+    /// respond when creating their SDP. The cache is filled once the softphone is built and refilled in the
+    /// background whenever calls took sockets from it. Sockets are discarded after `ttl`, see
+    /// [`CandidateCache`](session::CandidateCache).
+    pub fn candidate_cache(mut self, ttl: Duration, sockets: usize) -> Self {
+        self.candidate_cache = Some((ttl, sockets));
+        self
+    }
+
+    /// Session interval of calls (RFC 4028 session timers), defaults to 30 minutes
+    ///
+    /// Requested in dialed calls and used when accepting calls of peers supporting session timers. Calls are
+    /// refreshed using an UPDATE or re-INVITE before the interval expires, calls which the peer doesn't refresh
+    /// end with [`EndReason::SessionExpired`]. Peers may raise the interval, but not below 90 seconds.
+    pub fn session_expires(mut self, interval: Duration) -> Self {
+        self.session_expires = interval;
+        self
+    }
+
+    /// Bind the SIP socket, start the registration (if configured) and accept incoming calls
+    pub async fn build(self) -> Result<Softphone, Error> {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+
+        #[cfg(feature = "tls-rustls")]
+        let tls_port = self.tls_server.as_ref().map(|(port, _)| *port);
+        #[cfg(not(feature = "tls-rustls"))]
+        let tls_port = None;
+
+        let config = Config {
+            local_ip: self.local_ip,
+            sip_port: self.sip_port,
+            tcp: self.tcp,
+            tls_port,
+            outbound_proxy: self.outbound_proxy,
+            codecs: self.codecs,
+            media_options: self.media_options,
+            termination_warning: self.termination_warning,
+            local_ringback: self.local_ringback,
+            session_expires: self.session_expires,
+            stun_server: self.stun_server,
+        };
+
+        let candidate_cache =
+            self.candidate_cache
+                .zip(self.stun_server)
+                .map(|((ttl, sockets), server)| CachedCandidates {
+                    cache: CandidateCache::new(ttl),
+                    server,
+                    sockets,
+                    refilling: Arc::new(AtomicBool::new(false)),
+                });
+
+        let mut accounts = SlotMap::with_key();
+        let default_account = accounts.insert(AccountEntry {
+            local_addr: self.account.local_addr(),
+            contact: config.contact_for(&self.account),
+            route_set: config.route_set_for(&self.account),
+            credentials: self.account.credentials.clone(),
+            registration: None,
+        });
+
+        let shared = Arc::new(Shared {
+            config,
+            accounts: Mutex::new(accounts),
+            default_account,
+            subscriptions: Mutex::new(Vec::new()),
+            calls: Mutex::new(HashMap::new()),
+            next_call_id: AtomicU64::new(0),
+            events: events_tx,
+            candidate_cache,
+        });
+
+        shared.refill_candidate_cache();
+
+        let mut builder = Endpoint::builder();
+
+        builder.add_layer(DialogLayer::default());
+        builder.add_layer(SubscriptionLayer::default());
+        builder.add_layer(InviteLayer::default());
+        builder.add_layer(IncomingCallLayer::new(shared.clone()));
+        builder.add_layer(OptionsLayer::new(shared.clone()));
+
+        Udp::spawn(&mut builder, (self.local_ip, self.sip_port))
+            .await
+            .map_err(sip_core::Error::from)?;
+
+        if self.tcp {
+            TcpListener::new()
+                .spawn(&mut builder, (self.local_ip, self.sip_port))
+                .await
+                .map_err(sip_core::Error::from)?;
+
+            builder.add_transport_factory(Arc::new(TcpConnector::new_with_bind(SocketAddr::new(
+                self.local_ip,
+                0,
+            ))));
+        }
+
+        #[cfg(feature = "tls-rustls")]
+        {
+            if let Some(client_config) = self.tls_client {
+                builder.add_transport_factory(Arc::new(TlsConnector::from(client_config)));
+            }
+
+            if let Some((tls_port, server_config)) = self.tls_server {
+                TlsAcceptor::from(server_config)
+                    .spawn(&mut builder, (self.local_ip, tls_port))
+                    .await
+                    .map_err(sip_core::Error::from)?;
+            }
+        }
+
+        let endpoint = builder.build();
+
+        let registration = spawn_registration(&endpoint, &shared, default_account, &self.account);
+
+        if let Some(entry) = shared.accounts.lock().unwrap().get_mut(default_account) {
+            entry.registration = registration;
+        }
+
+        Ok(Softphone {
+            endpoint,
+            shared,
+            events: events_rx,
+        })
+    }
+}
+
+/// Keep the account registered at its registrar, if it has one
+fn spawn_registration(
+    endpoint: &Endpoint,
+    shared: &Arc<Shared>,
+    id: AccountId,
+    account: &Account,
+) -> Option<JoinHandle<()>> {
+    let registrar = account.registrar.clone()?;
+
+    let mut registration = Registration::new(
+        account.local_addr(),
+        shared.config.contact_for(account),
+        registrar,
+        account.expiry,
+    );
+
+    if let Some(interval) = account.keepalive {
+        registration = registration.with_keepalive(interval);
+    }
+
+    registration = registration.with_route_set(shared.config.route_set_for(account));
+
+    Some(tokio::spawn(registration::run(
+        endpoint.clone(),
+        registration,
+        shared.clone(),
+        id,
+        account.credentials.clone(),
+        account.message_waiting,
+    )))
+}
+
+/// High level SIP user agent handling registration, calls and their audio
+///
+/// See the [crate level documentation](crate) for an example. Dropping the softphone hangs up all calls and stops
+/// refreshing the registrations.
+pub struct Softphone {
+    endpoint: Endpoint,
+    shared: Arc<Shared>,
+    events: mpsc::UnboundedReceiver<SoftphoneEvent>,
+}
+
+impl Softphone {
+    /// Create a builder for a softphone using the given address of record, signaling and media are bound to
+    /// `local_ip`
+    ///
+    /// The address of record and the registration configured using the builder form the
+    /// [default account](Self::default_account).
+    pub fn builder(aor: SipUri, local_ip: IpAddr) -> SoftphoneBuilder {
+        SoftphoneBuilder {
+            account: Account::new(aor),
+            local_ip,
+            sip_port: 5060,
+            tcp: false,
+            outbound_proxy: None,
+            #[cfg(feature = "tls-rustls")]
+            tls_client: None,
+            #[cfg(feature = "tls-rustls")]
+            tls_server: None,
+            codecs: Codecs::new(MediaType::Audio)
+                .with_codec(Codec::PCMU)
+                .with_codec(Codec::PCMA),
+            media_options: Options::lan(),
+            termination_warning: None,
+            local_ringback: None,
+            session_expires: Duration::from_secs(1800),
+            stun_server: None,
+            candidate_cache: None,
+        }
+    }
+
+    /// The SIP endpoint used by the softphone, e.g. to send requests not covered by the softphone
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// Wait for the next event, returns `None` once the softphone's background tasks are gone
+    pub async fn next_event(&mut self) -> Option<SoftphoneEvent> {
+        let event = self.events.recv().await?;
+
+        // Cancelled calls are known until the application learned about the cancellation
+        if let SoftphoneEvent::Ended {
+            call,
+            reason: EndReason::Cancelled,
+        } = &event
+        {
+            self.shared.calls.lock().unwrap().remove(call);
+        }
+
+        Some(event)
+    }
+
+    /// The account configured using the [`SoftphoneBuilder`]
+    pub fn default_account(&self) -> AccountId {
+        self.shared.default_account
+    }
+
+    /// Add another account, e.g. to use multiple identities or registrars with a single softphone
+    ///
+    /// The account is registered if a registrar is configured. Incoming calls are assigned to the account whose
+    /// contact or address of record matches the user of the request URI, calls matching no account are assigned
+    /// to the default account.
+    pub fn add_account(&self, account: Account) -> AccountId {
+        let id = self.shared.accounts.lock().unwrap().insert(AccountEntry {
+            local_addr: account.local_addr(),
+            contact: self.shared.config.contact_for(&account),
+            route_set: self.shared.config.route_set_for(&account),
+            credentials: account.credentials.clone(),
+            registration: None,
+        });
+
+        let registration = spawn_registration(&self.endpoint, &self.shared, id, &account);
+
+        if let Some(entry) = self.shared.accounts.lock().unwrap().get_mut(id) {
+            entry.registration = registration;
+        }
+
+        id
+    }
+
+    /// Remove an account and stop refreshing its registration, calls of the account are not affected
+    ///
+    /// The binding at the registrar is left to expire.
+    pub fn remove_account(&self, account: AccountId) -> Result<(), Error> {
+        self.shared
+            .accounts
+            .lock()
+            .unwrap()
+            .remove(account)
+            .map(drop)
+            .ok_or(Error::UnknownAccount(account))
+    }
+
+    /// Subscribe to the incoming calls matching the filter, e.g. to dispatch the calls of an account to its own task
+    ///
+    /// Each incoming call is delivered to the first subscription with a matching filter, in the order they were
+    /// created. Calls matching no subscription are reported using [`SoftphoneEvent::IncomingCall`].
+    pub fn incoming_calls(&self, filter: IncomingCallFilter) -> IncomingCalls {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        self.shared
+            .subscriptions
+            .lock()
+            .unwrap()
+            .push(Subscription { filter, calls: tx });
+
+        IncomingCalls { calls: rx }
+    }
+
+    /// Call the given target using the default account, the progress of the call is reported using events
+    pub fn dial(&self, target: SipUri) -> CallId {
+        call::dial(
+            &self.shared,
+            self.endpoint.clone(),
+            self.shared.default_account,
+            target,
+            Outgoing::Dial,
+        )
+    }
+
+    /// Call the given target using the given account, see [`dial`](Self::dial)
+    pub fn dial_from(&self, account: AccountId, target: SipUri) -> Result<CallId, Error> {
+        if !self.shared.accounts.lock().unwrap().contains_key(account) {
+            return Err(Error::UnknownAccount(account));
+        }
+
+        Ok(call::dial(
+            &self.shared,
+            self.endpoint.clone(),
+            account,
+            target,
+            Outgoing::Dial,
+        ))
+    }
+
+    /// Pick up a call ringing at another user agent (directed call pickup) using the default account
+    ///
+    /// `target` is the URI of the user agent and `dialog` the call's dialog at it, as reported by the dialog event
+    /// package, e.g. when monitoring the user agent using BLF. The INVITE replaces that dialog (RFC 3891), the
+    /// progress of the call is reported using events like for [`dial`](Self::dial).
+    pub fn pickup(&self, target: SipUri, dialog: &DialogInfo) -> CallId {
+        call::dial(
+            &self.shared,
+            self.endpoint.clone(),
+            self.shared.default_account,
+            target,
+            Outgoing::Pickup(dialog.replaces()),
+        )
+    }
+
+    /// Call a user agent directly using its IP address (optionally with a port) or SIP URI, e.g. an intercom or a
+    /// local test PBX
+    ///
+    /// The call uses the identity of the default account, but no registration is required. It bypasses any outbound
+    /// proxy, doesn't answer authentication challenges and always negotiates plain RTP ([`Options::lan`]) using the
+    /// configured codecs. The progress of the call is reported using events, like for [`dial`](Self::dial).
+    ///
+    /// ```no_run
+    /// # fn example(phone: &ezk_softphone::Softphone) -> Result<(), ezk_softphone::Error> {
+    /// let call = phone.direct_call("192.168.1.20:5060")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn direct_call(&self, target: &str) -> Result<CallId, Error> {
+        let uri = direct_call_target(target).ok_or_else(|| Error::InvalidTarget(target.into()))?;
+
+        Ok(call::dial(
+            &self.shared,
+            self.endpoint.clone(),
+            self.shared.default_account,
+            uri,
+            Outgoing::Direct,
+        ))
+    }
+
+    /// Answer an incoming call
+    ///
+    /// Returns [`Error::Cancelled`] if the caller cancelled the call before it could be answered. The cancellation
+    /// is also reported using [`SoftphoneEvent::Ended`] with [`EndReason::Cancelled`], once that event was received
+    /// the call is unknown.
+    pub async fn answer(&self, call: CallId) -> Result<(), Error> {
+        self.request(call, Command::Answer).await
+    }
+
+    /// Hang up a call, declines incoming calls which have not been answered yet
+    pub fn hangup(&self, call: CallId) -> Result<(), Error> {
+        self.shared.hangup(call)
+    }
+
+    /// Put a call on hold using a re-INVITE, the peer stops sending audio
+    ///
+    /// [`SoftphoneEvent::Held`] is emitted once the peer accepted it. Does nothing if the call is already on hold.
+    pub async fn hold(&self, call: CallId) -> Result<(), Error> {
+        self.request(call, |result| Command::Hold { hold: true, result })
+            .await
+    }
+
+    /// Resume a call put on hold using [`hold`](Self::hold)
+    ///
+    /// [`SoftphoneEvent::Resumed`] is emitted once the peer accepted it. Does nothing if the call isn't on hold.
+    pub async fn resume(&self, call: CallId) -> Result<(), Error> {
+        self.request(call, |result| Command::Hold {
+            hold: false,
+            result,
+        })
+        .await
+    }
+
+    /// Transfer the peer of the call to the given target (blind transfer)
+    ///
+    /// Returns once the peer accepted the transfer. Its progress is reported using
+    /// [`SoftphoneEvent::TransferProgress`], the call is hung up once the target answered, ending it with
+    /// [`EndReason::Transferred`]. If the transfer fails the call continues.
+    pub async fn transfer(&self, call: CallId, target: SipUri) -> Result<(), Error> {
+        self.request(call, |result| Command::Transfer { target, result })
+            .await
+    }
+
+    /// Transfer the peer of the call to the peer of the established `consultation` call (attended transfer)
+    ///
+    /// The peer is asked to replace the consultation call with a call of its own, which ends the consultation
+    /// call. Otherwise behaves like [`transfer`](Self::transfer).
+    pub async fn transfer_attended(&self, call: CallId, consultation: CallId) -> Result<(), Error> {
+        let target = {
+            let calls = self.shared.calls.lock().unwrap();
+            let handle = calls
+                .get(&consultation)
+                .ok_or(Error::UnknownCall(consultation))?;
+
+            handle
+                .dialog
+                .as_ref()
+                .and_then(EstablishedDialog::replacing_uri)
+                .ok_or(Error::InvalidState)?
+        };
+
+        self.request(call, |result| Command::Transfer { target, result })
+            .await
+    }
+
+    /// Hang up the call once it has been established for the given duration, e.g. to enforce prepaid credit
+    ///
+    /// Replaces a previously set limit. The call ends with [`EndReason::TimeLimitReached`].
+    pub fn set_max_duration(&self, call: CallId, max_duration: Duration) -> Result<(), Error> {
+        self.shared
+            .send_command(call, Command::MaxDuration(max_duration))
+    }
+
+    /// Hang up the established call at the given instant
+    ///
+    /// Replaces a previously scheduled termination, the call ends with [`EndReason::TimeLimitReached`]. A call
+    /// which is not established yet is hung up right after its establishment if the instant has passed by then.
+    pub fn terminate_at(&self, call: CallId, at: Instant) -> Result<(), Error> {
+        self.shared
+            .send_command(call, Command::TerminateAt(at.into()))
+    }
+
+    /// Connect two established calls (back-to-back user agent), relaying the audio and DTMF digits received in
+    /// one call to the other, e.g. for click-to-dial
+    ///
+    /// Audio is relayed unchanged if both calls negotiated the same codec, PCMU and PCMA are converted into each
+    /// other using the [`G711Transcoder`], use [`bridge_with`](Self::bridge_with) for other codecs. Audio of
+    /// bridged calls is not reported using [`SoftphoneEvent::Rtp`], DTMF digits still are. Once one of the calls
+    /// ends, the other one is hung up.
+    ///
+    /// Returns [`Error::InvalidState`] if one of the calls is bridged with another call already.
+    pub async fn bridge(&self, a: CallId, b: CallId) -> Result<(), Error> {
+        self.bridge_with(a, b, Box::new(G711Transcoder), Box::new(G711Transcoder))
+            .await
+    }
+
+    /// Bridge two calls like [`bridge`](Self::bridge), converting the audio relayed to call `a` using `to_a` and
+    /// the audio relayed to call `b` using `to_b` if their codecs differ
+    pub async fn bridge_with(
+        &self,
+        a: CallId,
+        b: CallId,
+        to_a: Box<dyn Transcoder>,
+        to_b: Box<dyn Transcoder>,
+    ) -> Result<(), Error> {
+        if a == b {
+            return Err(Error::InvalidState);
+        }
+
+        self.request(a, |result| Command::Bridge {
+            bridge: Bridge::new(b, to_a),
+            result,
+        })
+        .await?;
+
+        let result = self
+            .request(b, |result| Command::Bridge {
+                bridge: Bridge::new(a, to_b),
+                result,
+            })
+            .await;
+
+        if result.is_err() {
+            let _ = self
+                .shared
+                .send_command(a, Command::Unbridge { peer: Some(b) });
+        }
+
+        result
+    }
+
+    /// Dissolve the bridge of a call created using [`bridge`](Self::bridge), both calls continue
+    pub fn unbridge(&self, call: CallId) -> Result<(), Error> {
+        self.shared
+            .send_command(call, Command::Unbridge { peer: None })
+    }
+
+    /// Send DTMF digits (`0-9`, `*`, `#`, `A-D`) to the peer using SIP INFO requests
+    pub async fn send_dtmf(&self, call: CallId, digits: &str) -> Result<(), Error> {
+        if let Some(invalid) = digits.chars().find(|c| !is_dtmf_digit(*c)) {
+            return Err(Error::InvalidDtmf(invalid));
+        }
+
+        let digits = digits.to_owned();
+
+        self.request(call, |result| Command::Dtmf { digits, result })
+            .await
+    }
+
+    /// Send an RTP packet of encoded audio to the peer of an established call
+    pub fn send_rtp(&self, call: CallId, packet: RtpPacket) -> Result<(), Error> {
+        self.shared.send_command(call, Command::SendRtp(packet))
+    }
+
+    /// Pass decoded audio samples received in the call to the DTMF detection
+    ///
+    /// Received DTMF digits are only reported if the application decodes the received RTP and passes the samples
+    /// using this.
+    pub fn process_audio_samples(&self, call: CallId, samples: Vec<i16>) -> Result<(), Error> {
+        self.shared
+            .send_command(call, Command::AudioSamples(samples))
+    }
+
+    async fn request(
+        &self,
+        call: CallId,
+        command: impl FnOnce(oneshot::Sender<Result<(), Error>>) -> Command,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+
+        self.shared.send_command(call, command(tx))?;
+
+        // The call task dropped the sender without responding when the call ended
+        rx.await.map_err(|_| Error::UnknownCall(call))?
+    }
+}
+
+impl Drop for Softphone {
+    fn drop(&mut self) {
+        // Stops the registrations
+        self.shared.accounts.lock().unwrap().clear();
+
+        for handle in self.shared.calls.lock().unwrap().values() {
+            handle.cancellation.cancel();
+            let _ = handle.commands.send(Command::Hangup);
+        }
+    }
+}
+
+/// Parse the target of [`Softphone::direct_call`], an IP address, socket address or SIP URI
+fn direct_call_target(target: &str) -> Option<SipUri> {
+    let target = target.trim();
+
+    if target.starts_with("sip:") || target.starts_with("sips:") {
+        return target.parse().ok();
+    }
+
+    let host_port = match target.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => format!("[{ip}]"),
+        Ok(IpAddr::V4(ip)) => ip.to_string(),
+        Err(_) => target.parse::<SocketAddr>().ok()?.to_string(),
+    };
+
+    format!("sip:{host_port}").parse().ok()
+}
+
+fn is_dtmf_digit(c: char) -> bool {
+    matches!(c, '0'..='9' | '*' | '#' | 'A'..='D')
+}
+
+/// Answer a digest authentication challenge, returns if the request should be sent again
+fn authenticate(
+    authenticator: &mut DigestAuthenticator,
+    request: &Request,
+    response: &TsxResponse,
+) -> bool {
+    if !matches!(
+        response.line.code,
+        StatusCode::UNAUTHORIZED | StatusCode::PROXY_AUTHENTICATION_REQUIRED
+    ) {
+        return false;
+    }
+
+    let result = authenticator.handle_rejection(
+        RequestParts {
+            line: &request.line,
+            headers: &request.headers,
+            body: &request.body,
+        },
+        ResponseParts {
+            line: &response.line,
+            headers: &response.headers,
+            body: &response.body,
+        },
+    );
+
+    if let Err(e) = &result {
+        log::warn!(
+            "Failed to authenticate {} request, {e}",
+            request.line.method
+        );
+    }
+
+    result.is_ok()
+}
+
+/// Configuration used by all calls of a softphone
+struct Config {
+    local_ip: IpAddr,
+    sip_port: u16,
+    /// SIP over TCP is enabled, see [`SoftphoneBuilder::listen_tcp`]
+    tcp: bool,
+    /// Port accepting TLS connections, if enabled
+    tls_port: Option<u16>,
+    /// Outbound proxy of accounts without their own, see [`SoftphoneBuilder::outbound_proxy`]
+    outbound_proxy: Option<SipUri>,
+    codecs: Codecs,
+    media_options: Options,
+    termination_warning: Option<Duration>,
+    local_ringback: Option<(RingbackRegion, u32)>,
+    session_expires: Duration,
+    stun_server: Option<SocketAddr>,
+}
+
+impl Config {
+    /// Contact of the account, requesting the transport the account registers with
+    fn contact_for(&self, account: &Account) -> Contact {
+        let mut contact_uri = SipUri::new(SocketAddr::new(self.local_ip, self.sip_port).into());
+        contact_uri.user_part = account.aor.user_part.clone();
+
+        let register_using_tcp = account.registrar.as_ref().is_some_and(|registrar| {
+            registrar
+                .uri_params
+                .get_val("transport")
+                .is_some_and(|transport| transport.eq_ignore_ascii_case("tcp"))
+        });
+
+        if self.tcp && register_using_tcp {
+            contact_uri.uri_params.push_or_edit("transport", "tcp");
+        }
+
+        if let Some(tls_port) = self.tls_port {
+            if account
+                .registrar
+                .as_ref()
+                .is_some_and(|registrar| registrar.sips)
+            {
+                contact_uri.sips = true;
+                contact_uri.host_port.port = Some(tls_port);
+                contact_uri.uri_params.take("transport");
+            }
+        }
+
+        Contact::new(NameAddr::uri(contact_uri))
+    }
+
+    /// Route set of requests of the account sent outside of dialogs, contains the account's outbound proxy or the
+    /// softphone's
+    fn route_set_for(&self, account: &Account) -> Vec<Routing> {
+        let Some(proxy) = account
+            .outbound_proxy
+            .as_ref()
+            .or(self.outbound_proxy.as_ref())
+        else {
+            return vec![];
+        };
+
+        // The proxy must not treat the request as addressed to itself (loose routing, RFC 3261 Section 16.12)
+        let mut uri = proxy.clone();
+        if uri.uri_params.get("lr").is_none() {
+            uri.uri_params.push(Param::name("lr"));
+        }
+
+        vec![Routing {
+            uri: NameAddr::uri(uri),
+            params: Params::new(),
+        }]
+    }
+
+    /// Session interval in seconds, not below the minimum of RFC 4028
+    fn session_expires_secs(&self) -> u32 {
+        u32::try_from(self.session_expires.as_secs())
+            .unwrap_or(u32::MAX)
+            .max(90)
+    }
+}
+
+/// State shared between the softphone, its incoming call layer and the tasks running calls & registration
+struct Shared {
+    config: Config,
+    accounts: Mutex<SlotMap<AccountId, AccountEntry>>,
+    /// Account used for dialed calls and incoming calls not addressed to any account
+    default_account: AccountId,
+    subscriptions: Mutex<Vec<Subscription>>,
+    calls: Mutex<HashMap<CallId, CallHandle>>,
+    next_call_id: AtomicU64,
+    events: mpsc::UnboundedSender<SoftphoneEvent>,
+    candidate_cache: Option<CachedCandidates>,
+}
+
+/// Media sockets prepared ahead of time, see [`SoftphoneBuilder::candidate_cache`]
+struct CachedCandidates {
+    cache: CandidateCache,
+    server: SocketAddr,
+    /// Number of sockets to keep in the cache
+    sockets: usize,
+    refilling: Arc<AtomicBool>,
+}
+
+impl Shared {
+    /// Top up the candidate cache in the background, if it's enabled and not being refilled already
+    fn refill_candidate_cache(&self) {
+        let Some(cached) = &self.candidate_cache else {
+            return;
+        };
+
+        let missing = cached.sockets.saturating_sub(cached.cache.len());
+
+        if missing == 0 || cached.refilling.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let cache = cached.cache.clone();
+        let refilling = cached.refilling.clone();
+        let local_ip = self.config.local_ip;
+        let server = cached.server;
+
+        tokio::spawn(async move {
+            if let Err(e) = cache.prepare(local_ip, server, missing).await {
+                log::warn!("Failed to prepare sockets for the candidate cache, {e}");
+            }
+
+            refilling.store(false, Ordering::Relaxed);
+        });
+    }
+
+    fn add_call(
+        &self,
+        account: AccountId,
+    ) -> (
+        CallId,
+        mpsc::UnboundedReceiver<Command>,
+        tokio_util::sync::CancellationToken,
+    ) {
+        let id = CallId(self.next_call_id.fetch_add(1, Ordering::Relaxed));
+        let (handle, commands, cancellation) = CallHandle::new(account);
+
+        self.calls.lock().unwrap().insert(id, handle);
+
+        (id, commands, cancellation)
+    }
+
+    fn call_account(&self, call: CallId) -> Option<AccountId> {
+        self.calls
+            .lock()
+            .unwrap()
+            .get(&call)
+            .map(|handle| handle.account)
+    }
+
+    /// Find the account an incoming request is addressed to, returns its id & contact
+    fn route_incoming(&self, request_uri: &SipUri) -> Option<(AccountId, Contact)> {
+        let accounts = self.accounts.lock().unwrap();
+
+        let id = accounts
+            .iter()
+            .find(|(_, entry)| entry.matches(request_uri))
+            .map(|(id, _)| id)
+            .or_else(|| {
+                accounts
+                    .contains_key(self.default_account)
+                    .then_some(self.default_account)
+            })?;
+
+        Some((id, accounts[id].contact.clone()))
+    }
+
+    fn send_command(&self, call: CallId, command: Command) -> Result<(), Error> {
+        let calls = self.calls.lock().unwrap();
+        let handle = calls.get(&call).ok_or(Error::UnknownCall(call))?;
+
+        if handle.cancelled {
+            return Err(Error::Cancelled);
+        }
+
+        handle
+            .commands
+            .send(command)
+            .map_err(|_| Error::UnknownCall(call))
+    }
+
+    fn hangup(&self, call: CallId) -> Result<(), Error> {
+        let calls = self.calls.lock().unwrap();
+        let handle = calls.get(&call).ok_or(Error::UnknownCall(call))?;
+
+        // Cancels outgoing calls which have not been answered yet
+        handle.cancellation.cancel();
+        let _ = handle.commands.send(Command::Hangup);
+
+        Ok(())
+    }
+
+    fn set_dialog(&self, call: CallId, dialog: EstablishedDialog) {
+        if let Some(handle) = self.calls.lock().unwrap().get_mut(&call) {
+            handle.dialog = Some(dialog);
+        }
+    }
+
+    /// Find the established call whose dialog is referenced by the `Replaces` header of an incoming INVITE
+    fn find_replaced_call(&self, replaces: &Replaces) -> Option<CallId> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, handle)| {
+                handle
+                    .dialog
+                    .as_ref()
+                    .is_some_and(|dialog| dialog.key.matches_replaces(replaces))
+            })
+            .map(|(id, _)| *id)
+    }
+
+    fn emit(&self, event: SoftphoneEvent) {
+        // The softphone was dropped if sending fails, nobody is interested in the event anymore
+        let _ = self.events.send(event);
+    }
+
+    /// Deliver a ringing incoming call to the first matching subscription, or report it as event
+    fn dispatch_incoming(&self, call: IncomingCall) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+
+        // Forget subscriptions whose stream was dropped
+        subscriptions.retain(|subscription| !subscription.calls.is_closed());
+
+        let subscription = subscriptions
+            .iter()
+            .find(|subscription| subscription.filter.matches(&call));
+
+        let call = match subscription {
+            Some(subscription) => match subscription.calls.send(call) {
+                Ok(()) => return,
+                Err(mpsc::error::SendError(call)) => call,
+            },
+            None => call,
+        };
+
+        drop(subscriptions);
+
+        self.emit(SoftphoneEvent::IncomingCall {
+            call: call.call,
+            account: call.account,
+            from: call.from,
+        });
+    }
+
+    fn end_call(&self, call: CallId, reason: EndReason) {
+        let mut calls = self.calls.lock().unwrap();
+
+        match (&reason, calls.get_mut(&call)) {
+            // Commands racing the cancellation fail with `Error::Cancelled`, see `Softphone::next_event`
+            (EndReason::Cancelled, Some(handle)) => handle.cancelled = true,
+            _ => {
+                calls.remove(&call);
+            }
+        }
+
+        drop(calls);
+
+        self.emit(SoftphoneEvent::Ended { call, reason });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sip_types::print::AppendCtx;
+    use sip_types::uri::SipUriUserPart;
+
+    fn config() -> Config {
+        Config {
+            local_ip: "127.0.0.1".parse().unwrap(),
+            sip_port: 5060,
+            tcp: false,
+            tls_port: None,
+            outbound_proxy: None,
+            codecs: Codecs::new(MediaType::Audio),
+            media_options: Options::lan(),
+            termination_warning: None,
+            local_ringback: None,
+            session_expires: Duration::from_secs(1800),
+            stun_server: None,
+        }
+    }
+
+    fn account() -> Account {
+        Account::new("sip:alice@example.org".parse().unwrap())
+    }
+
+    fn transport(contact: &Contact) -> Option<&str> {
+        contact
+            .uri
+            .uri
+            .uri_params
+            .get_val("transport")
+            .map(|t| t.as_str())
+    }
+
+    #[test]
+    fn dtmf_digits() {
+        for c in "0123456789*#ABCD".chars() {
+            assert!(is_dtmf_digit(c), "{c}");
+        }
+
+        for c in "abcdE!, ".chars() {
+            assert!(!is_dtmf_digit(c), "{c}");
+        }
+    }
+
+    #[test]
+    fn direct_call_targets() {
+        let host_port = |target| {
+            let uri = direct_call_target(target).unwrap();
+            (uri.sips, uri.host_port.default_print_ctx().to_string())
+        };
+
+        assert_eq!(host_port("192.168.1.20"), (false, "192.168.1.20".into()));
+        assert_eq!(
+            host_port("192.168.1.20:5080"),
+            (false, "192.168.1.20:5080".into())
+        );
+        assert_eq!(host_port("fe80::1"), (false, "[fe80::1]".into()));
+        assert_eq!(
+            host_port("[fe80::1]:5080"),
+            (false, "[fe80::1]:5080".into())
+        );
+        assert_eq!(
+            host_port("sip:intercom@192.168.1.20"),
+            (false, "192.168.1.20".into())
+        );
+        assert_eq!(
+            host_port("sips:pbx.local:5061"),
+            (true, "pbx.local:5061".into())
+        );
+
+        assert!(direct_call_target("pbx.local").is_none());
+        assert!(direct_call_target("").is_none());
+    }
+
+    #[test]
+    fn contact() {
+        let contact = config().contact_for(&account());
+        let uri = &contact.uri.uri;
+
+        assert!(!uri.sips);
+        assert_eq!(uri.host_port.port, Some(5060));
+        assert!(matches!(&uri.user_part, SipUriUserPart::User(user) if user == "alice"));
+        assert_eq!(transport(&contact), None);
+    }
+
+    #[test]
+    fn contact_registering_using_tcp() {
+        let account = account().registrar("sip:example.org;transport=TCP".parse().unwrap());
+
+        // Without a TCP listener the transport of the registrar cannot be requested
+        assert_eq!(transport(&config().contact_for(&account)), None);
+
+        let config = Config {
+            tcp: true,
+            ..config()
+        };
+        assert_eq!(transport(&config.contact_for(&account)), Some("tcp"));
+    }
+
+    #[test]
+    fn contact_registering_using_tls() {
+        let account = account().registrar("sips:example.org;transport=tcp".parse().unwrap());
+        let config = Config {
+            tcp: true,
+            tls_port: Some(5061),
+            ..config()
+        };
+
+        let contact = config.contact_for(&account);
+
+        assert!(contact.uri.uri.sips);
+        assert_eq!(contact.uri.uri.host_port.port, Some(5061));
+        assert_eq!(transport(&contact), None);
+    }
+
+    #[test]
+    fn route_set() {
+        assert!(config().route_set_for(&account()).is_empty());
+
+        let config = Config {
+            outbound_proxy: Some("sip:proxy.example.org".parse().unwrap()),
+            ..config()
+        };
+
+        let route_set = config.route_set_for(&account());
+        assert_eq!(route_set.len(), 1);
+        assert!(route_set[0].uri.uri.uri_params.get("lr").is_some());
+
+        // The outbound proxy of the account is preferred and `lr` isn't added twice
+        let account = account().outbound_proxy("sip:sbc.example.org;lr".parse().unwrap());
+        let route_set = config.route_set_for(&account);
+        assert_eq!(route_set.len(), 1);
+        let mut params = route_set[0].uri.uri.uri_params.clone();
+        params.take("lr");
+        assert!(params.get("lr").is_none());
+        assert_eq!(
+            route_set[0].uri.uri.host_port,
+            account.outbound_proxy.unwrap().host_port
+        );
+    }
+
+    #[test]
+    fn session_expires() {
+        assert_eq!(config().session_expires_secs(), 1800);
+
+        let config = Config {
+            session_expires: Duration::from_secs(30),
+            ..config()
+        };
+        assert_eq!(config.session_expires_secs(), 90);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{authenticate, AccountId, Shared, SoftphoneEvent};
+use sip_auth::{ClientAuthenticator, DigestAuthenticator, DigestCredentials};
+use sip_ua::subscription::message_summary::MessageSummary;
+use sip_ua::subscription::{SubscribeResponse, SubscriptionEvent, SubscriptionInitiator};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Message waiting indication subscription of an account, stopped when dropped
+pub(crate) struct MessageWaiting(JoinHandle<()>);
+
+impl MessageWaiting {
+    pub(crate) fn spawn(
+        initiator: SubscriptionInitiator,
+        shared: Arc<Shared>,
+        account: AccountId,
+        credentials: DigestCredentials,
+    ) -> Self {
+        Self(tokio::spawn(run(initiator, shared, account, credentials)))
+    }
+
+    /// The subscription failed or was terminated by the notifier
+    pub(crate) fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+}
+
+impl Drop for MessageWaiting {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Subscribe to the `message-summary` event package and report the notifications until the subscription ends
+async fn run(
+    mut initiator: SubscriptionInitiator,
+    shared: Arc<Shared>,
+    account: AccountId,
+    credentials: DigestCredentials,
+) {
+    let mut authenticator = DigestAuthenticator::new(credentials);
+
+    let mut subscription = loop {
+        let mut request = initiator.create_subscribe();
+        authenticator.authorize_request(&request.line, &mut request.headers, &request.body);
+
+        match initiator.send_subscribe(request.clone()).await {
+            Ok(SubscribeResponse::Subscribed(subscription, _)) => break subscription,
+            Ok(SubscribeResponse::Failure(response)) => {
+                if authenticate(&mut authenticator, &request, &response) {
+                    continue;
+                }
+
+                log::warn!(
+                    "Message waiting subscription rejected with {:?}",
+                    response.line.code
+                );
+                return;
+            }
+            Err(e) => {
+                log::warn!("Failed to subscribe to message waiting indication, {e}");
+                return;
+            }
+        }
+    };
+
+    loop {
+        let notification = match subscription.receive().await {
+            Ok(SubscriptionEvent::Notification(notification)) => notification,
+            Ok(SubscriptionEvent::Terminated) => return,
+            Err(e) => {
+                log::warn!("Message waiting subscription failed, {e}");
+                return;
+            }
+        };
+
+        // Notifications of pending subscriptions carry no summary
+        if notification.body.is_empty() {
+            continue;
+        }
+
+        let summary = std::str::from_utf8(&notification.body)
+            .map_err(|_| log::warn!("Message summary is not valid UTF-8"))
+            .and_then(|body| {
+                MessageSummary::parse(body)
+                    .map_err(|e| log::warn!("Failed to parse message summary, {e}"))
+            });
+
+        if let Ok(summary) = summary {
+            shared.emit(SoftphoneEvent::MessageWaiting { account, summary });
+        }
+    }
+}
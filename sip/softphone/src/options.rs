@@ -0,0 +1,81 @@
+use crate::call::set_sdp;
+use crate::Shared;
+use session::{Direction, SdpSession};
+use sip_core::{Endpoint, EndpointBuilder, IncomingRequest, Layer, MayTake};
+use sip_types::header::typed::{Accept, Supported};
+use sip_types::{Method, StatusCode};
+use std::sync::Arc;
+
+/// Responds to OPTIONS requests outside of dialogs with the capabilities of the softphone
+///
+/// The response lists the allowed methods, the accepted body types and extensions, and contains a SDP describing
+/// the configured codecs ([RFC3261 Section 11.2](https://www.rfc-editor.org/rfc/rfc3261#section-11.2)).
+pub(crate) struct OptionsLayer {
+    shared: Arc<Shared>,
+}
+
+impl OptionsLayer {
+    pub(crate) fn new(shared: Arc<Shared>) -> Self {
+        Self { shared }
+    }
+}
+
+#[async_trait::async_trait]
+impl Layer for OptionsLayer {
+    fn name(&self) -> &'static str {
+        "softphone-options"
+    }
+
+    fn init(&mut self, endpoint: &mut EndpointBuilder) {
+        endpoint.add_allow(Method::OPTIONS);
+        endpoint.add_accept(Accept("application/sdp".into()));
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::OPTIONS || request.base_headers.to.tag.is_some() {
+            return;
+        }
+
+        let mut request = request.take();
+
+        // Answer the way an INVITE to the same request URI would be answered
+        let response = if self.shared.route_incoming(&request.line.uri).is_some() {
+            let mut response = endpoint.create_response(&request, StatusCode::OK, None);
+
+            let config = &self.shared.config;
+            let mut media = SdpSession::new(config.local_ip, config.media_options.clone());
+
+            if media
+                .add_local_media(config.codecs.clone(), 1, Direction::SendRecv)
+                .is_some()
+            {
+                set_sdp(&mut response, &media.create_sdp_capabilities());
+            }
+
+            response.msg.headers.insert_named(&endpoint.allow_header());
+            response
+                .msg
+                .headers
+                .insert_named(&Accept("application/sdp".into()));
+
+            if !endpoint.supported().is_empty() {
+                response
+                    .msg
+                    .headers
+                    .insert_named::<Vec<Supported>>(endpoint.supported());
+            }
+
+            response
+        } else {
+            endpoint.create_response(&request, StatusCode::NOT_FOUND, None)
+        };
+
+        if let Err(e) = endpoint
+            .create_server_tsx(&mut request)
+            .respond(response)
+            .await
+        {
+            log::warn!("Failed to respond to OPTIONS request, {e}");
+        }
+    }
+}
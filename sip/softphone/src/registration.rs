@@ -0,0 +1,160 @@
+use crate::message_waiting::MessageWaiting;
+use crate::{authenticate, AccountId, Shared, SoftphoneEvent};
+use sip_auth::{ClientAuthenticator, DigestAuthenticator, DigestCredentials};
+use sip_core::transaction::TsxResponse;
+use sip_core::transport::TargetTransportInfo;
+use sip_core::{Endpoint, Request};
+use sip_types::{CodeKind, StatusCode};
+use sip_ua::register::Registration;
+use std::sync::Arc;
+
+/// Keep the registration alive until the task is aborted or the retry policy gives up
+///
+/// If `subscribe_mwi` is set, the message waiting indication is subscribed to once registered. A subscription which
+/// ended is renewed with the next refresh of the registration.
+pub(crate) async fn run(
+    endpoint: Endpoint,
+    mut registration: Registration,
+    shared: Arc<Shared>,
+    account: AccountId,
+    credentials: DigestCredentials,
+    subscribe_mwi: bool,
+) {
+    let mut authenticator = DigestAuthenticator::new(credentials.clone());
+    let mut target = TargetTransportInfo::default();
+    let mut message_waiting: Option<MessageWaiting> = None;
+
+    loop {
+        let mut request = registration.create_register(false);
+        authenticator.authorize_request(&request.line, &mut request.headers, &request.body);
+
+        let response = match send(&endpoint, &mut target, &mut authenticator, request).await {
+            Ok(response) => response,
+            Err(e) => {
+                log::debug!("REGISTER request failed, {e}");
+
+                if endpoint.failover(&mut target).await {
+                    continue;
+                }
+
+                // All servers failed, start over with the first one
+                target = TargetTransportInfo::default();
+
+                if registration.receive_timeout() {
+                    continue;
+                }
+
+                if !report_failure_and_wait(&mut registration, &shared, account, None).await {
+                    return;
+                }
+
+                continue;
+            }
+        };
+
+        let Some(response) = response else {
+            // Challenged by the registrar, send the request again with credentials
+            continue;
+        };
+
+        if response.line.code.kind() == CodeKind::Success {
+            registration.receive_success_response(response);
+            shared.emit(SoftphoneEvent::Registered { account });
+
+            if subscribe_mwi
+                && message_waiting
+                    .as_ref()
+                    .is_none_or(MessageWaiting::is_finished)
+            {
+                message_waiting = Some(MessageWaiting::spawn(
+                    registration.subscribe_mwi(endpoint.clone()),
+                    shared.clone(),
+                    account,
+                    credentials.clone(),
+                ));
+            }
+
+            keep_alive(&endpoint, &mut target, &mut registration, &shared, account).await;
+            continue;
+        }
+
+        let code = response.line.code;
+
+        if code == StatusCode::SERVICE_UNAVAILABLE {
+            if endpoint.failover(&mut target).await {
+                continue;
+            }
+
+            target = TargetTransportInfo::default();
+        }
+
+        if registration.receive_error_response(response) {
+            continue;
+        }
+
+        if !report_failure_and_wait(&mut registration, &shared, account, Some(code)).await {
+            return;
+        }
+    }
+}
+
+/// Wait until the binding must be refreshed, checking if the registrar is still reachable in the meantime
+async fn keep_alive(
+    endpoint: &Endpoint,
+    target: &mut TargetTransportInfo,
+    registration: &mut Registration,
+    shared: &Shared,
+    account: AccountId,
+) {
+    while registration.wait_for_keepalive().await {
+        let request = registration.create_options();
+
+        // Any response, even a failure, shows the registrar is reachable
+        let result = match endpoint.send_request(request, target).await {
+            Ok(mut transaction) => transaction.receive_final().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            log::debug!("OPTIONS keepalive request failed, {e}");
+
+            shared.emit(SoftphoneEvent::RegistrarUnreachable { account });
+            return;
+        }
+    }
+}
+
+/// Send the REGISTER request, returns `None` if it has been challenged and must be sent again
+async fn send(
+    endpoint: &Endpoint,
+    target: &mut TargetTransportInfo,
+    authenticator: &mut DigestAuthenticator,
+    request: Request,
+) -> Result<Option<TsxResponse>, sip_core::Error> {
+    let mut transaction = endpoint.send_request(request, target).await?;
+    let response = transaction.receive_final().await?;
+
+    if authenticate(authenticator, &transaction.request().msg, &response) {
+        Ok(None)
+    } else {
+        Ok(Some(response))
+    }
+}
+
+/// Report the failure and wait before registering again, returns `false` if the retry policy gave up
+async fn report_failure_and_wait(
+    registration: &mut Registration,
+    shared: &Shared,
+    account: AccountId,
+    code: Option<StatusCode>,
+) -> bool {
+    shared.emit(SoftphoneEvent::RegistrationFailed { account, code });
+
+    if registration.wait_for_retry().await {
+        return true;
+    }
+
+    shared.emit(SoftphoneEvent::RegistrationGaveUp { account });
+
+    false
+}
@@ -0,0 +1,469 @@
+//! In-process SIP peer for end-to-end call tests
+//!
+//! [`TestUas`] is a minimal user agent server which answers every INVITE it receives. Its SDP behavior can be
+//! changed using [`Quirk`]s to reproduce peers found in the wild, so calls can be tested against them without
+//! any external PBX.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), ezk_softphone::Error> {
+//! use ezk_softphone::testsupport::{Quirk, TestUas, TestUasEvent};
+//! use ezk_softphone::Softphone;
+//!
+//! let local_ip = "127.0.0.1".parse().unwrap();
+//!
+//! let mut uas = TestUas::builder(local_ip).quirk(Quirk::NoRtcpMux).build().await?;
+//! let phone = Softphone::builder("sip:alice@127.0.0.1".parse().unwrap(), local_ip)
+//!     .sip_port(5070)
+//!     .build()
+//!     .await?;
+//!
+//! phone.dial(uas.uri());
+//!
+//! assert!(matches!(uas.next_event().await, Some(TestUasEvent::Answered { .. })));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::call::{parse_sdp, set_sdp};
+use crate::Error;
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use sdp_types::{Fmtp, Rtcp, TransportProtocol};
+use session::{AsyncSdpSession, Codec, Codecs, Direction, MediaType, Options, SessionDescription};
+use sip_core::transport::streaming::StreamingListenerBuilder;
+use sip_core::transport::tcp::TcpListener;
+use sip_core::transport::udp::Udp;
+use sip_core::{Endpoint, IncomingRequest, Layer, MayTake};
+use sip_types::header::typed::Contact;
+use sip_types::uri::{NameAddr, SipUri, SipUriUserPart};
+use sip_types::{CodeKind, Method, Name, StatusCode};
+use sip_ua::dialog::{Dialog, DialogLayer};
+use sip_ua::invite::acceptor::InviteAcceptor;
+use sip_ua::invite::create_ack;
+use sip_ua::invite::session::{InviteSession, InviteSessionEvent, ReInviteReceived};
+use sip_ua::invite::InviteLayer;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+
+/// Deviation from well-behaved SDP handling of the [`TestUas`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quirk {
+    /// Ignore `a=rtcp-mux` and announce a separate RTCP port
+    NoRtcpMux,
+
+    /// Answer `RTP/AVPF` offers with `RTP/AVP`
+    AvpPushback,
+
+    /// Send a re-INVITE without SDP right after the call is established, so the caller has to make the offer in
+    /// its `200 OK` and receives the answer in the ACK
+    DelayedOffer,
+
+    /// Add a malformed fmtp attribute to the first format of every media
+    BrokenFmtp,
+
+    /// Send an UPDATE with a new SDP offer right after the call is established, instead of a re-INVITE
+    UpdateOffer,
+
+    /// Send the SDP answer in a `183 Session Progress` response before answering the call
+    EarlyMedia,
+
+    /// Send `180 Ringing` without SDP before any other response, and wait [`RING_TIME`] after it and after the
+    /// `183 Session Progress` of [`Quirk::EarlyMedia`]
+    Ringing,
+}
+
+/// How long the [`TestUas`] waits after each provisional response when [`Quirk::Ringing`] is enabled
+pub const RING_TIME: Duration = Duration::from_millis(200);
+
+/// Event returned by [`TestUas::next_event`]
+#[derive(Debug)]
+pub enum TestUasEvent {
+    /// An INVITE was answered, contains the SDP sent in the `200 OK`: the answer, or the offer if the INVITE
+    /// carried no SDP
+    Answered { sdp: Box<SessionDescription> },
+
+    /// The re-INVITE sent because of [`Quirk::DelayedOffer`] completed
+    DelayedOfferCompleted,
+
+    /// The UPDATE sent because of [`Quirk::UpdateOffer`] was answered
+    UpdateCompleted,
+
+    /// The caller hung up
+    Bye,
+
+    /// Handling a call failed
+    Failed(Error),
+}
+
+/// Builder for a [`TestUas`], created using [`TestUas::builder`]
+pub struct TestUasBuilder {
+    local_ip: IpAddr,
+    quirks: Vec<Quirk>,
+    codecs: Codecs,
+    tcp: bool,
+}
+
+impl TestUasBuilder {
+    /// Accept TCP connections on the SIP port, the URI of the UAS then requests TCP
+    pub fn listen_tcp(mut self) -> Self {
+        self.tcp = true;
+        self
+    }
+
+    /// Enable a quirk, can be called multiple times
+    pub fn quirk(mut self, quirk: Quirk) -> Self {
+        self.quirks.push(quirk);
+        self
+    }
+
+    /// Audio codecs accepted & offered, defaults to PCMU and PCMA
+    pub fn codecs(mut self, codecs: Codecs) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    /// Bind the SIP socket to a random port and start answering calls
+    pub async fn build(self) -> Result<TestUas, Error> {
+        let (events_tx, events) = mpsc::unbounded_channel();
+
+        let mut builder = Endpoint::builder();
+
+        builder.add_layer(DialogLayer::default());
+        builder.add_layer(InviteLayer::default());
+
+        let transport = Udp::spawn(&mut builder, (self.local_ip, 0))
+            .await
+            .map_err(sip_core::Error::from)?;
+
+        let mut uri = SipUri::new(transport.bound().into());
+        uri.user_part = SipUriUserPart::User("uas".into());
+
+        if self.tcp {
+            TcpListener::new()
+                .spawn(&mut builder, transport.bound())
+                .await
+                .map_err(sip_core::Error::from)?;
+
+            uri.uri_params.push_or_edit("transport", "tcp");
+        }
+
+        builder.add_layer(UasLayer {
+            config: Arc::new(Config {
+                local_ip: self.local_ip,
+                contact: Contact::new(NameAddr::uri(uri.clone())),
+                quirks: self.quirks,
+                codecs: self.codecs,
+                events: events_tx,
+            }),
+        });
+
+        Ok(TestUas {
+            endpoint: builder.build(),
+            uri,
+            events,
+        })
+    }
+}
+
+/// Minimal user agent server answering every call, see the [module level documentation](self)
+pub struct TestUas {
+    endpoint: Endpoint,
+    uri: SipUri,
+    events: mpsc::UnboundedReceiver<TestUasEvent>,
+}
+
+impl TestUas {
+    /// Create a builder for a test UAS bound to `local_ip`
+    pub fn builder(local_ip: IpAddr) -> TestUasBuilder {
+        TestUasBuilder {
+            local_ip,
+            quirks: vec![],
+            codecs: Codecs::new(MediaType::Audio)
+                .with_codec(Codec::PCMU)
+                .with_codec(Codec::PCMA),
+            tcp: false,
+        }
+    }
+
+    /// URI to call the UAS at
+    pub fn uri(&self) -> SipUri {
+        self.uri.clone()
+    }
+
+    /// The SIP endpoint of the UAS
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// Wait for the next event, returns `None` once the endpoint is gone
+    pub async fn next_event(&mut self) -> Option<TestUasEvent> {
+        self.events.recv().await
+    }
+}
+
+struct Config {
+    local_ip: IpAddr,
+    contact: Contact,
+    quirks: Vec<Quirk>,
+    codecs: Codecs,
+    events: mpsc::UnboundedSender<TestUasEvent>,
+}
+
+impl Config {
+    fn has(&self, quirk: Quirk) -> bool {
+        self.quirks.contains(&quirk)
+    }
+
+    fn emit(&self, event: TestUasEvent) {
+        // The TestUas was dropped if sending fails
+        let _ = self.events.send(event);
+    }
+
+    /// Apply the SDP quirks to SDP which is about to be sent
+    fn apply_quirks(&self, sdp: &mut SessionDescription) {
+        for desc in &mut sdp.media_descriptions {
+            if self.has(Quirk::NoRtcpMux) && desc.media.port != 0 {
+                desc.rtcp_mux = false;
+                desc.rtcp_mux_only = false;
+                desc.rtcp = Some(Rtcp {
+                    port: desc.media.port + 1,
+                    address: None,
+                });
+            }
+
+            if self.has(Quirk::AvpPushback) && desc.media.proto == TransportProtocol::RtpAvpf {
+                desc.media.proto = TransportProtocol::RtpAvp;
+            }
+
+            if self.has(Quirk::BrokenFmtp) {
+                if let Some(format) = desc.media.fmts.first() {
+                    desc.fmtp.push(Fmtp {
+                        format: *format,
+                        params: BytesStr::from_static(";=;mode=="),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Accepts INVITE requests outside of any dialog
+struct UasLayer {
+    config: Arc<Config>,
+}
+
+#[async_trait::async_trait]
+impl Layer for UasLayer {
+    fn name(&self) -> &'static str {
+        "test-uas"
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method != Method::INVITE || request.base_headers.to.tag.is_some() {
+            return;
+        }
+
+        let dialog =
+            match Dialog::new_server(endpoint.clone(), &request, self.config.contact.clone()) {
+                Ok(dialog) => dialog,
+                Err(e) => {
+                    log::warn!("Failed to create dialog for incoming INVITE, {e}");
+                    return;
+                }
+            };
+
+        let invite = request.take();
+        let offer = invite.body.clone();
+        let acceptor = InviteAcceptor::new(dialog, invite);
+
+        let config = self.config.clone();
+
+        tokio::spawn(async move {
+            let event = match run_call(&config, acceptor, offer).await {
+                Ok(()) => TestUasEvent::Bye,
+                Err(e) => TestUasEvent::Failed(e),
+            };
+
+            config.emit(event);
+        });
+    }
+}
+
+async fn run_call(
+    config: &Config,
+    mut acceptor: InviteAcceptor,
+    offer: Bytes,
+) -> Result<(), Error> {
+    let mut media = AsyncSdpSession::new(config.local_ip, Options::lan());
+    media.set_local_ip(config.local_ip);
+
+    let local_media = media
+        .add_local_media(config.codecs.clone(), 1, Direction::SendRecv)
+        .ok_or(Error::NoCodecs)?;
+
+    let (mut session, sdp) = if offer.is_empty() {
+        media.add_media(local_media, Direction::SendRecv);
+
+        let mut sdp = media.create_sdp_offer().await?;
+        config.apply_quirks(&mut sdp);
+
+        let (session, ack) = acceptor.accept_with_sdp(sdp.to_string()).await?;
+        media.receive_sdp_answer(parse_sdp(&ack.body)?).await?;
+
+        (session, sdp)
+    } else {
+        let mut sdp = media.receive_sdp_offer(parse_sdp(&offer)?).await?;
+        config.apply_quirks(&mut sdp);
+
+        if config.has(Quirk::Ringing) {
+            let ringing = acceptor.create_response(StatusCode::RINGING, None).await?;
+            acceptor.respond_provisional(ringing).await?;
+            sleep(RING_TIME).await;
+        }
+
+        if config.has(Quirk::EarlyMedia) {
+            let mut progress = acceptor
+                .create_response(StatusCode::SESSION_PROGRESS, None)
+                .await?;
+            set_sdp(&mut progress, &sdp);
+            acceptor.respond_provisional(progress).await?;
+
+            if config.has(Quirk::Ringing) {
+                sleep(RING_TIME).await;
+            }
+        }
+
+        let (session, _ack) = acceptor.accept_with_sdp(sdp.to_string()).await?;
+
+        (session, sdp)
+    };
+
+    config.emit(TestUasEvent::Answered { sdp: Box::new(sdp) });
+
+    if config.has(Quirk::DelayedOffer) {
+        send_offerless_reinvite(config, &session, &mut media).await?;
+        config.emit(TestUasEvent::DelayedOfferCompleted);
+    }
+
+    if config.has(Quirk::UpdateOffer) {
+        send_update(config, &mut session, &mut media).await?;
+        config.emit(TestUasEvent::UpdateCompleted);
+    }
+
+    loop {
+        select! {
+            event = session.drive() => match event? {
+                InviteSessionEvent::RefreshNeeded(event) => event.process_default().await?,
+                InviteSessionEvent::ReInviteReceived(event) => {
+                    answer_reinvite(config, event, &mut media).await?;
+                }
+                InviteSessionEvent::UpdateReceived(event) => event.process_default().await?,
+                InviteSessionEvent::ReferReceived(event) => {
+                    event.reject(StatusCode::DECLINE).await?;
+                }
+                InviteSessionEvent::NotifyReceived(event) => event.process_default().await?,
+                InviteSessionEvent::InfoReceived(event) => event.process_default().await?,
+                InviteSessionEvent::Bye(event) => {
+                    event.process_default().await?;
+                    return Ok(());
+                }
+                InviteSessionEvent::SessionExpired | InviteSessionEvent::Terminated => return Ok(()),
+            },
+            event = media.run() => {
+                event?;
+            }
+        }
+    }
+}
+
+async fn answer_reinvite(
+    config: &Config,
+    event: ReInviteReceived<'_>,
+    media: &mut AsyncSdpSession,
+) -> Result<(), Error> {
+    let dialog = event.session.dialog.clone();
+
+    let mut answer = media
+        .receive_sdp_offer(parse_sdp(&event.invite.body)?)
+        .await?;
+    config.apply_quirks(&mut answer);
+
+    let mut response = dialog.create_response(&event.invite, StatusCode::OK, None)?;
+    set_sdp(&mut response, &answer);
+
+    event.respond_success(response).await?;
+
+    Ok(())
+}
+
+/// Send an UPDATE with a new SDP offer, the answer is contained in the `200 OK`
+async fn send_update(
+    config: &Config,
+    session: &mut InviteSession,
+    media: &mut AsyncSdpSession,
+) -> Result<(), Error> {
+    let mut offer = media.create_sdp_offer().await?;
+    config.apply_quirks(&mut offer);
+
+    let mut update = session.create_update();
+    update.headers.insert(Name::CONTENT_TYPE, "application/sdp");
+    update.body = offer.to_string().into();
+
+    let response = session.send_update(update).await?;
+
+    if response.line.code.kind() != CodeKind::Success {
+        media.rollback_changes().await?;
+        return Err(Error::Rejected(response.line.code));
+    }
+
+    media.receive_sdp_answer(parse_sdp(&response.body)?).await?;
+
+    Ok(())
+}
+
+/// Send a re-INVITE without SDP, answer the offer of the `200 OK` in the ACK
+async fn send_offerless_reinvite(
+    config: &Config,
+    session: &InviteSession,
+    media: &mut AsyncSdpSession,
+) -> Result<(), Error> {
+    let invite = session.dialog.create_request(Method::INVITE);
+
+    let mut target_tp_info = session.dialog.target_tp_info.lock().await;
+    let mut transaction = session
+        .endpoint
+        .send_invite(invite, &mut target_tp_info)
+        .await?;
+    drop(target_tp_info);
+
+    while let Some(response) = transaction.receive().await? {
+        match response.line.code.kind() {
+            CodeKind::Provisional => {}
+            CodeKind::Success => {
+                let mut answer = media.receive_sdp_offer(parse_sdp(&response.body)?).await?;
+                config.apply_quirks(&mut answer);
+
+                let mut ack = create_ack(&session.dialog, response.base_headers.cseq.cseq).await?;
+                ack.msg
+                    .headers
+                    .insert(Name::CONTENT_TYPE, "application/sdp");
+                ack.msg.body = answer.to_string().into();
+
+                session
+                    .endpoint
+                    .send_outgoing_request(&mut ack)
+                    .await
+                    .map_err(sip_core::Error::from)?;
+
+                return Ok(());
+            }
+            _ => return Err(Error::Rejected(response.line.code)),
+        }
+    }
+
+    Err(sip_core::Error::RequestTimedOut.into())
+}
@@ -0,0 +1,1232 @@
+use bytes::Bytes;
+use bytesstr::BytesStr;
+use ezk_softphone::testsupport::{Quirk, TestUas, TestUasEvent};
+use ezk_softphone::{
+    Account, CallId, EndReason, Error, IncomingCallFilter, Softphone, SoftphoneBuilder,
+    SoftphoneEvent,
+};
+use rtp::{RingbackRegion, RtpExtensions, RtpPacket, RtpTimestamp, SequenceNumber, Ssrc};
+use sdp_types::SessionDescription;
+use session::{Direction, MediaInfo, Options};
+use sip_core::transport::udp::Udp;
+use sip_core::transport::TargetTransportInfo;
+use sip_core::{Endpoint, IncomingRequest, Layer, MayTake};
+use sip_types::header::typed::{Accept, Allow, Contact, Expires, Replaces, Require};
+use sip_types::uri::{NameAddr, SipUri};
+use sip_types::{Method, StatusCode};
+use sip_ua::dialog::DialogLayer;
+use sip_ua::invite::initiator::DialogInfo;
+use sip_ua::register::Registration;
+use sip_ua::subscription::message_summary::{self, MessageCounts, MessageSummary};
+use sip_ua::subscription::Notifier;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use stun_types::attributes::{Fingerprint, XorMappedAddress};
+use stun_types::{Class, Message, MessageBuilder, Method as StunMethod};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout, timeout_at};
+
+const LOCAL_IP: &str = "127.0.0.1";
+
+async fn dial(sip_port: u16, quirks: &[Quirk]) -> (TestUas, Softphone, CallId) {
+    dial_with_options(sip_port, quirks, Options::lan()).await
+}
+
+async fn dial_with_options(
+    sip_port: u16,
+    quirks: &[Quirk],
+    media_options: Options,
+) -> (TestUas, Softphone, CallId) {
+    dial_with(sip_port, quirks, |builder| {
+        builder.media_options(media_options)
+    })
+    .await
+}
+
+async fn dial_with(
+    sip_port: u16,
+    quirks: &[Quirk],
+    configure: impl FnOnce(SoftphoneBuilder) -> SoftphoneBuilder,
+) -> (TestUas, Softphone, CallId) {
+    let local_ip: IpAddr = LOCAL_IP.parse().unwrap();
+
+    let mut builder = TestUas::builder(local_ip);
+    for quirk in quirks {
+        builder = builder.quirk(*quirk);
+    }
+    let uas = builder.build().await.unwrap();
+
+    let builder =
+        Softphone::builder("sip:alice@127.0.0.1".parse().unwrap(), local_ip).sip_port(sip_port);
+    let phone = configure(builder).build().await.unwrap();
+
+    let call = phone.dial(uas.uri());
+
+    (uas, phone, call)
+}
+
+async fn next_phone_event(phone: &mut Softphone) -> SoftphoneEvent {
+    timeout(Duration::from_secs(10), phone.next_event())
+        .await
+        .expect("timed out waiting for softphone event")
+        .unwrap()
+}
+
+async fn next_uas_event(uas: &mut TestUas) -> TestUasEvent {
+    timeout(Duration::from_secs(10), uas.next_event())
+        .await
+        .expect("timed out waiting for test UAS event")
+        .unwrap()
+}
+
+async fn wait_established(phone: &mut Softphone) {
+    loop {
+        match next_phone_event(phone).await {
+            SoftphoneEvent::Established { .. } => return,
+            SoftphoneEvent::Ended { reason, .. } => {
+                panic!("call ended before established: {reason:?}")
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn hangup(uas: &mut TestUas, phone: &mut Softphone, call: CallId) {
+    phone.hangup(call).unwrap();
+
+    loop {
+        if let SoftphoneEvent::Ended { reason, .. } = next_phone_event(phone).await {
+            assert!(matches!(reason, EndReason::LocalHangup), "{reason:?}");
+            break;
+        }
+    }
+
+    assert!(matches!(next_uas_event(uas).await, TestUasEvent::Bye));
+}
+
+#[tokio::test]
+async fn happy_path() {
+    let (mut uas, mut phone, call) = dial(15071, &[]).await;
+
+    wait_established(&mut phone).await;
+
+    let TestUasEvent::Answered { sdp } = next_uas_event(&mut uas).await else {
+        panic!("expected answered");
+    };
+    assert!(sdp.media_descriptions[0].rtcp_mux);
+
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+#[tokio::test]
+async fn tcp() {
+    let local_ip: IpAddr = LOCAL_IP.parse().unwrap();
+
+    let mut uas = TestUas::builder(local_ip)
+        .listen_tcp()
+        .build()
+        .await
+        .unwrap();
+
+    let mut phone = Softphone::builder("sip:alice@127.0.0.1".parse().unwrap(), local_ip)
+        .sip_port(15087)
+        .listen_tcp()
+        .build()
+        .await
+        .unwrap();
+
+    let call = phone.dial(uas.uri());
+
+    wait_established(&mut phone).await;
+
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+#[tokio::test]
+async fn no_rtcp_mux() {
+    let (mut uas, mut phone, call) = dial(15072, &[Quirk::NoRtcpMux]).await;
+
+    wait_established(&mut phone).await;
+
+    let TestUasEvent::Answered { sdp } = next_uas_event(&mut uas).await else {
+        panic!("expected answered");
+    };
+    assert!(!sdp.media_descriptions[0].rtcp_mux);
+
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+#[tokio::test]
+async fn delayed_offer() {
+    let (mut uas, mut phone, call) = dial(15073, &[Quirk::DelayedOffer]).await;
+
+    wait_established(&mut phone).await;
+
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::DelayedOfferCompleted
+    ));
+
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+#[tokio::test]
+async fn broken_fmtp() {
+    let (mut uas, mut phone, call) = dial(15074, &[Quirk::BrokenFmtp]).await;
+
+    wait_established(&mut phone).await;
+
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+#[tokio::test]
+async fn update_offer() {
+    let (mut uas, mut phone, call) = dial(15084, &[Quirk::UpdateOffer]).await;
+
+    wait_established(&mut phone).await;
+
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::UpdateCompleted
+    ));
+
+    let audio = wait_renegotiated(&mut phone, call).await;
+    assert!(!audio.remote_hold);
+
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+#[tokio::test]
+async fn avp_pushback() {
+    let options = Options {
+        offer_avpf: true,
+        ..Options::lan()
+    };
+
+    let (mut uas, mut phone, call) = dial_with_options(15075, &[Quirk::AvpPushback], options).await;
+
+    wait_established(&mut phone).await;
+
+    let TestUasEvent::Answered { sdp } = next_uas_event(&mut uas).await else {
+        panic!("expected answered");
+    };
+    assert_eq!(
+        sdp.media_descriptions[0].media.proto,
+        sdp_types::TransportProtocol::RtpAvp
+    );
+
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+async fn softphone(user: &str, sip_port: u16) -> Softphone {
+    Softphone::builder(
+        format!("sip:{user}@{LOCAL_IP}").parse().unwrap(),
+        LOCAL_IP.parse().unwrap(),
+    )
+    .sip_port(sip_port)
+    .build()
+    .await
+    .unwrap()
+}
+
+fn phone_uri(user: &str, sip_port: u16) -> SipUri {
+    format!("sip:{user}@{LOCAL_IP}:{sip_port}").parse().unwrap()
+}
+
+/// Dial from one softphone to another and answer the call, returns the call on both sides
+async fn connect(
+    caller: &mut Softphone,
+    callee: &mut Softphone,
+    target: SipUri,
+) -> (CallId, CallId) {
+    let outgoing = caller.dial(target);
+
+    let incoming = loop {
+        if let SoftphoneEvent::IncomingCall { call, .. } = next_phone_event(callee).await {
+            break call;
+        }
+    };
+
+    callee.answer(incoming).await.unwrap();
+
+    wait_established(caller).await;
+    wait_established(callee).await;
+
+    (outgoing, incoming)
+}
+
+async fn wait_ended(phone: &mut Softphone, call: CallId) -> EndReason {
+    loop {
+        if let SoftphoneEvent::Ended {
+            call: ended,
+            reason,
+        } = next_phone_event(phone).await
+        {
+            if ended == call {
+                return reason;
+            }
+        }
+    }
+}
+
+/// Wait for the transferor's call to end after the transfer target answered, and for the consultation call to be
+/// hung up by the transfer target if there is one
+async fn wait_transferred(transferor: &mut Softphone, call: CallId, consultation: Option<CallId>) {
+    let mut progress = vec![];
+    let mut transferred = false;
+    let mut consultation_ended = consultation.is_none();
+
+    while !(transferred && consultation_ended) {
+        match next_phone_event(transferor).await {
+            SoftphoneEvent::TransferProgress { call: c, code } if c == call => progress.push(code),
+            SoftphoneEvent::Ended { call: c, reason } if c == call => {
+                assert!(matches!(reason, EndReason::Transferred), "{reason:?}");
+                transferred = true;
+            }
+            SoftphoneEvent::Ended { call: c, reason } if Some(c) == consultation => {
+                assert!(matches!(reason, EndReason::RemoteHangup), "{reason:?}");
+                consultation_ended = true;
+            }
+            _ => {}
+        }
+    }
+
+    assert_eq!(progress.first(), Some(&StatusCode::TRYING));
+    assert_eq!(progress.last(), Some(&StatusCode::OK));
+}
+
+/// Wait for the transferee to dial the transfer target, and to be hung up by the transferor
+async fn wait_referred(transferee: &mut Softphone, call: CallId) -> CallId {
+    let new_call = loop {
+        if let SoftphoneEvent::Referred {
+            call: c, new_call, ..
+        } = next_phone_event(transferee).await
+        {
+            assert_eq!(c, call);
+            break new_call;
+        }
+    };
+
+    let (mut ended, mut established) = (false, false);
+
+    while !(ended && established) {
+        match next_phone_event(transferee).await {
+            SoftphoneEvent::Ended { call: c, reason } if c == call => {
+                assert!(matches!(reason, EndReason::RemoteHangup), "{reason:?}");
+                ended = true;
+            }
+            SoftphoneEvent::Established { call: c } if c == new_call => established = true,
+            SoftphoneEvent::Ended { call: c, reason } if c == new_call => {
+                panic!("transferred call ended: {reason:?}")
+            }
+            _ => {}
+        }
+    }
+
+    new_call
+}
+
+#[tokio::test]
+async fn blind_transfer() {
+    let mut alice = softphone("alice", 15076).await;
+    let mut bob = softphone("bob", 15077).await;
+    let mut carol = softphone("carol", 15078).await;
+
+    let (alice_bob, bob_alice) = connect(&mut alice, &mut bob, phone_uri("bob", 15077)).await;
+
+    alice
+        .transfer(alice_bob, phone_uri("carol", 15078))
+        .await
+        .unwrap();
+
+    let carol_bob = loop {
+        if let SoftphoneEvent::IncomingCall { call, .. } = next_phone_event(&mut carol).await {
+            break call;
+        }
+    };
+    carol.answer(carol_bob).await.unwrap();
+
+    wait_transferred(&mut alice, alice_bob, None).await;
+    let bob_carol = wait_referred(&mut bob, bob_alice).await;
+
+    bob.hangup(bob_carol).unwrap();
+    assert!(matches!(
+        wait_ended(&mut carol, carol_bob).await,
+        EndReason::RemoteHangup
+    ));
+}
+
+#[tokio::test]
+async fn attended_transfer() {
+    let mut alice = softphone("alice", 15079).await;
+    let mut bob = softphone("bob", 15080).await;
+    let mut carol = softphone("carol", 15081).await;
+
+    let (alice_bob, bob_alice) = connect(&mut alice, &mut bob, phone_uri("bob", 15080)).await;
+    let (alice_carol, carol_alice) =
+        connect(&mut alice, &mut carol, phone_uri("carol", 15081)).await;
+
+    alice
+        .transfer_attended(alice_bob, alice_carol)
+        .await
+        .unwrap();
+
+    // Carol's call with alice is replaced by a call with bob without ringing
+    let carol_bob = match wait_ended(&mut carol, carol_alice).await {
+        EndReason::Replaced(call) => call,
+        reason => panic!("expected replaced, got {reason:?}"),
+    };
+
+    wait_transferred(&mut alice, alice_bob, Some(alice_carol)).await;
+    let bob_carol = wait_referred(&mut bob, bob_alice).await;
+
+    bob.hangup(bob_carol).unwrap();
+    assert!(matches!(
+        wait_ended(&mut carol, carol_bob).await,
+        EndReason::RemoteHangup
+    ));
+}
+
+async fn wait_renegotiated(phone: &mut Softphone, call: CallId) -> MediaInfo {
+    loop {
+        if let SoftphoneEvent::Renegotiated { call: c, audio } = next_phone_event(phone).await {
+            assert_eq!(c, call);
+            return audio.expect("audio was removed");
+        }
+    }
+}
+
+async fn wait_held(phone: &mut Softphone, call: CallId, held: bool) {
+    loop {
+        match next_phone_event(phone).await {
+            SoftphoneEvent::Held { call: c } if c == call => return assert!(held),
+            SoftphoneEvent::Resumed { call: c } if c == call => return assert!(!held),
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn hold_resume() {
+    let mut alice = softphone("alice", 15082).await;
+    let mut bob = softphone("bob", 15083).await;
+
+    let (alice_bob, bob_alice) = connect(&mut alice, &mut bob, phone_uri("bob", 15083)).await;
+
+    alice.hold(alice_bob).await.unwrap();
+    wait_held(&mut alice, alice_bob, true).await;
+    let audio = wait_renegotiated(&mut bob, bob_alice).await;
+    assert!(audio.remote_hold);
+    assert_eq!(audio.direction, Direction::RecvOnly);
+
+    // Both sides holding the call makes the audio inactive
+    bob.hold(bob_alice).await.unwrap();
+    wait_held(&mut bob, bob_alice, true).await;
+    let audio = wait_renegotiated(&mut alice, alice_bob).await;
+    assert_eq!(audio.direction, Direction::Inactive);
+
+    bob.resume(bob_alice).await.unwrap();
+    wait_held(&mut bob, bob_alice, false).await;
+    wait_renegotiated(&mut alice, alice_bob).await;
+
+    alice.resume(alice_bob).await.unwrap();
+    wait_held(&mut alice, alice_bob, false).await;
+    let audio = wait_renegotiated(&mut bob, bob_alice).await;
+    assert!(!audio.remote_hold);
+    assert_eq!(audio.direction, Direction::SendRecv);
+
+    alice.hangup(alice_bob).unwrap();
+    assert!(matches!(
+        wait_ended(&mut bob, bob_alice).await,
+        EndReason::RemoteHangup
+    ));
+}
+
+#[tokio::test]
+async fn info_dtmf() {
+    let mut alice = softphone("alice", 15085).await;
+    let mut bob = softphone("bob", 15086).await;
+
+    let (alice_bob, bob_alice) = connect(&mut alice, &mut bob, phone_uri("bob", 15086)).await;
+
+    alice.send_dtmf(alice_bob, "1#").await.unwrap();
+
+    let mut digits = String::new();
+
+    while digits.len() < 2 {
+        if let SoftphoneEvent::Dtmf { call, digit } = next_phone_event(&mut bob).await {
+            assert_eq!(call, bob_alice);
+            digits.push(digit);
+        }
+    }
+
+    assert_eq!(digits, "1#");
+
+    alice.hangup(alice_bob).unwrap();
+    assert!(matches!(
+        wait_ended(&mut bob, bob_alice).await,
+        EndReason::RemoteHangup
+    ));
+}
+
+#[tokio::test]
+async fn bridge() {
+    let mut alice = softphone("alice", 15099).await;
+    let mut bob = softphone("bob", 15100).await;
+    let mut carol = softphone("carol", 15101).await;
+
+    // Bob connects alice's call with its call to carol
+    let (alice_bob, bob_alice) = connect(&mut alice, &mut bob, phone_uri("bob", 15100)).await;
+    let (bob_carol, carol_bob) = connect(&mut bob, &mut carol, phone_uri("carol", 15101)).await;
+
+    bob.bridge(bob_alice, bob_carol).await.unwrap();
+
+    let relayed = timeout(Duration::from_secs(10), async {
+        for sequence_number in 0.. {
+            let packet = RtpPacket {
+                pt: 0,
+                marker: false,
+                sequence_number: SequenceNumber(sequence_number),
+                ssrc: Ssrc(0),
+                timestamp: RtpTimestamp(u32::from(sequence_number) * 160),
+                extensions: RtpExtensions::default(),
+                payload: Bytes::from_static(&[0xFF; 160]),
+                padding: None,
+            };
+
+            alice.send_rtp(alice_bob, packet).unwrap();
+
+            if let Ok(Some(SoftphoneEvent::Rtp { call, packet })) =
+                timeout(Duration::from_millis(20), carol.next_event()).await
+            {
+                assert_eq!(call, carol_bob);
+                return packet;
+            }
+        }
+
+        unreachable!()
+    })
+    .await
+    .expect("timed out waiting for relayed RTP");
+
+    assert_eq!(relayed.pt, 0);
+    assert_eq!(&relayed.payload[..], &[0xFF; 160]);
+
+    alice.send_dtmf(alice_bob, "5").await.unwrap();
+
+    loop {
+        if let SoftphoneEvent::Dtmf { call, digit } = next_phone_event(&mut carol).await {
+            assert_eq!(call, carol_bob);
+            assert_eq!(digit, '5');
+            break;
+        }
+    }
+
+    // Carol hanging up ends alice's call too
+    carol.hangup(carol_bob).unwrap();
+
+    assert!(matches!(
+        wait_ended(&mut bob, bob_carol).await,
+        EndReason::RemoteHangup
+    ));
+    assert!(matches!(
+        wait_ended(&mut bob, bob_alice).await,
+        EndReason::LocalHangup
+    ));
+    assert!(matches!(
+        wait_ended(&mut alice, alice_bob).await,
+        EndReason::RemoteHangup
+    ));
+}
+
+#[tokio::test]
+async fn outbound_proxy() {
+    let mut alice = Softphone::builder(
+        format!("sip:alice@{LOCAL_IP}").parse().unwrap(),
+        LOCAL_IP.parse().unwrap(),
+    )
+    .sip_port(15102)
+    .outbound_proxy(format!("sip:{LOCAL_IP}:15103").parse().unwrap())
+    .build()
+    .await
+    .unwrap();
+
+    // Bob acts as proxy, the target itself is unreachable
+    let mut bob = softphone("bob", 15103).await;
+
+    let (alice_bob, bob_alice) = connect(&mut alice, &mut bob, phone_uri("carol", 1)).await;
+
+    alice.hangup(alice_bob).unwrap();
+    assert!(matches!(
+        wait_ended(&mut bob, bob_alice).await,
+        EndReason::RemoteHangup
+    ));
+}
+
+#[tokio::test]
+async fn accounts() {
+    let mut alice = softphone("alice", 15088).await;
+    let mut bob = softphone("bob", 15089).await;
+
+    let support = bob.add_account(Account::new(
+        format!("sip:support@{LOCAL_IP}").parse().unwrap(),
+    ));
+
+    for (user, expected) in [("support", support), ("bob", bob.default_account())] {
+        let outgoing = alice.dial(phone_uri(user, 15089));
+
+        let incoming = loop {
+            if let SoftphoneEvent::IncomingCall { call, account, .. } =
+                next_phone_event(&mut bob).await
+            {
+                assert_eq!(account, expected);
+                break call;
+            }
+        };
+
+        bob.hangup(incoming).unwrap();
+        assert!(matches!(
+            wait_ended(&mut alice, outgoing).await,
+            EndReason::Rejected(_)
+        ));
+    }
+
+    bob.remove_account(support).unwrap();
+    assert!(bob.dial_from(support, phone_uri("alice", 15088)).is_err());
+}
+
+#[tokio::test]
+async fn incoming_call_subscription() {
+    let mut alice = softphone("alice", 15090).await;
+    let mut bob = softphone("bob", 15091).await;
+
+    let mut sales = bob.incoming_calls(IncomingCallFilter::new().request_user("sales"));
+
+    let outgoing = alice.dial(phone_uri("sales", 15091));
+
+    let incoming = timeout(Duration::from_secs(10), sales.next())
+        .await
+        .expect("timed out waiting for incoming call")
+        .unwrap();
+    assert_eq!(incoming.account, bob.default_account());
+
+    bob.answer(incoming.call).await.unwrap();
+    wait_established(&mut alice).await;
+
+    alice.hangup(outgoing).unwrap();
+    wait_ended(&mut alice, outgoing).await;
+    assert!(matches!(
+        wait_ended(&mut bob, incoming.call).await,
+        EndReason::RemoteHangup
+    ));
+
+    // Calls not matching the filter are reported as event
+    drop(sales);
+    let (alice_bob, _) = connect(&mut alice, &mut bob, phone_uri("bob", 15091)).await;
+    alice.hangup(alice_bob).unwrap();
+}
+
+#[tokio::test]
+async fn cancelled() {
+    let mut alice = softphone("alice", 15092).await;
+    let mut bob = softphone("bob", 15093).await;
+
+    let outgoing = alice.dial(phone_uri("bob", 15093));
+
+    let incoming = loop {
+        if let SoftphoneEvent::IncomingCall { call, .. } = next_phone_event(&mut bob).await {
+            break call;
+        }
+    };
+
+    alice.hangup(outgoing).unwrap();
+    wait_ended(&mut alice, outgoing).await;
+
+    // Bob answers before learning about the cancellation
+    let res = bob.answer(incoming).await;
+    assert!(matches!(res, Err(Error::Cancelled)), "{res:?}");
+
+    assert!(matches!(
+        wait_ended(&mut bob, incoming).await,
+        EndReason::Cancelled
+    ));
+
+    let res = bob.answer(incoming).await;
+    assert!(matches!(res, Err(Error::UnknownCall(_))), "{res:?}");
+}
+
+#[tokio::test]
+async fn early_media() {
+    let (mut uas, mut phone, call) = dial(15094, &[Quirk::EarlyMedia]).await;
+
+    let mut progress = vec![];
+    let mut early_audio = None;
+
+    loop {
+        match next_phone_event(&mut phone).await {
+            SoftphoneEvent::Progress { code, .. } => progress.push(code),
+            SoftphoneEvent::EarlyMedia { audio, .. } => early_audio = audio,
+            SoftphoneEvent::Established { .. } => break,
+            SoftphoneEvent::Ended { reason, .. } => {
+                panic!("call ended before established: {reason:?}")
+            }
+            _ => {}
+        }
+    }
+
+    assert_eq!(progress, [StatusCode::SESSION_PROGRESS]);
+    assert!(early_audio.is_some());
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+/// Events of a call dialed with local ringback, tagged with whether they arrived before the call was established
+async fn ringback_events(sip_port: u16, quirks: &[Quirk]) -> Vec<(bool, SoftphoneEvent)> {
+    let (mut uas, mut phone, call) = dial_with(sip_port, quirks, |builder| {
+        builder.local_ringback(RingbackRegion::Europe, 8000)
+    })
+    .await;
+
+    let mut events = vec![];
+
+    loop {
+        let event = next_phone_event(&mut phone).await;
+
+        match event {
+            SoftphoneEvent::Established { .. } => break,
+            SoftphoneEvent::Ended { reason, .. } => {
+                panic!("call ended before established: {reason:?}")
+            }
+            event => events.push((true, event)),
+        }
+    }
+
+    // Give a ringback task which wasn't stopped the time to emit a few more chunks
+    while let Ok(event) = timeout(Duration::from_millis(100), phone.next_event()).await {
+        events.push((false, event.unwrap()));
+    }
+
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+
+    hangup(&mut uas, &mut phone, call).await;
+
+    events
+}
+
+#[tokio::test]
+async fn local_ringback() {
+    let events = ringback_events(15106, &[Quirk::Ringing]).await;
+
+    let ringing = events
+        .iter()
+        .position(|(_, event)| {
+            matches!(event, SoftphoneEvent::Progress { code, .. } if *code == StatusCode::RINGING)
+        })
+        .expect("expected 180 Ringing");
+
+    let ringback: Vec<_> = events
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (established, event))| match event {
+            SoftphoneEvent::RingbackAudio { samples, .. } => Some((i, *established, samples)),
+            _ => None,
+        })
+        .collect();
+
+    assert!(!ringback.is_empty());
+    assert!(ringback.iter().all(|(i, _, _)| *i > ringing));
+    assert!(ringback.iter().all(|(_, established, _)| *established));
+    // 20ms at 8kHz
+    assert!(ringback.iter().all(|(_, _, samples)| samples.len() == 160));
+}
+
+#[tokio::test]
+async fn early_media_stops_local_ringback() {
+    let events = ringback_events(15107, &[Quirk::Ringing, Quirk::EarlyMedia]).await;
+
+    let early_media = events
+        .iter()
+        .position(|(_, event)| matches!(event, SoftphoneEvent::EarlyMedia { .. }))
+        .expect("expected early media");
+
+    let is_ringback =
+        |(_, event): &(bool, SoftphoneEvent)| matches!(event, SoftphoneEvent::RingbackAudio { .. });
+
+    assert!(events[..early_media].iter().any(is_ringback));
+    assert!(!events[early_media..].iter().any(is_ringback));
+}
+
+#[tokio::test]
+async fn options() {
+    let alice = softphone("alice", 15095).await;
+    let _bob = softphone("bob", 15096).await;
+
+    let mut request = Registration::new(
+        NameAddr::uri(phone_uri("alice", 15095)),
+        Contact::new(NameAddr::uri(phone_uri("alice", 15095))),
+        phone_uri("bob", 15096),
+        Duration::from_secs(600),
+    )
+    .create_options();
+    request
+        .headers
+        .insert_named(&Accept("application/sdp".into()));
+
+    let mut target = TargetTransportInfo::default();
+    let mut transaction = alice
+        .endpoint()
+        .send_request(request, &mut target)
+        .await
+        .unwrap();
+    let response = transaction.receive_final().await.unwrap();
+
+    assert_eq!(response.line.code, StatusCode::OK);
+
+    let allow: Vec<Allow> = response.headers.get_named().unwrap();
+    assert!(allow.contains(&Allow(Method::INVITE)));
+    assert!(allow.contains(&Allow(Method::OPTIONS)));
+
+    let sdp =
+        SessionDescription::parse(&BytesStr::from_utf8_bytes(response.body).unwrap()).unwrap();
+    assert_eq!(sdp.media_descriptions[0].media.port, 0);
+    assert_eq!(sdp.media_descriptions[0].media.fmts, [0, 8]);
+}
+
+/// Registrar accepting every registration, which notifies subscribers of the message summary
+struct MwiServerLayer {
+    contact: Contact,
+    summary: MessageSummary,
+}
+
+#[async_trait::async_trait]
+impl Layer for MwiServerLayer {
+    fn name(&self) -> &'static str {
+        "mwi-server"
+    }
+
+    async fn receive(&self, endpoint: &Endpoint, mut request: MayTake<'_, IncomingRequest>) {
+        match request.line.method {
+            Method::REGISTER => {
+                let mut register = request.inner().take().unwrap();
+                let transaction = endpoint.create_server_tsx(&mut register);
+
+                let mut response = endpoint.create_response(&register, StatusCode::OK, None);
+                response.msg.headers.insert_named(&Expires(600));
+
+                transaction.respond(response).await.unwrap();
+            }
+            Method::SUBSCRIBE => {
+                let subscribe = request.inner().take().unwrap();
+
+                let mut notifier = Notifier::accept(
+                    endpoint,
+                    subscribe,
+                    self.contact.clone(),
+                    Duration::from_secs(600),
+                )
+                .await
+                .unwrap();
+
+                let summary = self.summary.to_string();
+
+                tokio::spawn(async move {
+                    notifier
+                        .notify(message_summary::CONTENT_TYPE, summary)
+                        .await
+                        .unwrap();
+
+                    while notifier.receive().await.is_ok() {}
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn message_waiting() {
+    let server_uri = phone_uri("voicemail", 15097);
+    let counts = MessageCounts {
+        new: 2,
+        old: 8,
+        ..MessageCounts::default()
+    };
+
+    let mut builder = Endpoint::builder();
+    builder.add_layer(DialogLayer::default());
+    builder.add_layer(MwiServerLayer {
+        contact: Contact::new(NameAddr::uri(server_uri.clone())),
+        summary: MessageSummary::new(true).with_counts(message_summary::VOICE_MESSAGE, counts),
+    });
+    Udp::spawn(&mut builder, (LOCAL_IP.parse::<IpAddr>().unwrap(), 15097))
+        .await
+        .unwrap();
+    let _server = builder.build();
+
+    let mut phone = Softphone::builder(phone_uri("alice", 15097), LOCAL_IP.parse().unwrap())
+        .sip_port(15098)
+        .registrar(server_uri)
+        .message_waiting()
+        .build()
+        .await
+        .unwrap();
+
+    loop {
+        if let SoftphoneEvent::MessageWaiting { account, summary } =
+            next_phone_event(&mut phone).await
+        {
+            assert_eq!(account, phone.default_account());
+            assert!(summary.messages_waiting);
+            assert_eq!(summary.voice_messages(), Some(counts));
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn direct_call() {
+    let local_ip: IpAddr = LOCAL_IP.parse().unwrap();
+
+    let mut uas = TestUas::builder(local_ip).build().await.unwrap();
+
+    // Direct calls neither use the unreachable outbound proxy nor offer ICE
+    let mut phone = Softphone::builder("sip:alice@127.0.0.1".parse().unwrap(), local_ip)
+        .sip_port(15104)
+        .outbound_proxy(format!("sip:{LOCAL_IP}:15105").parse().unwrap())
+        .media_options(Options {
+            offer_ice: true,
+            ..Options::lan()
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let port = uas.uri().host_port.port.unwrap();
+    let call = phone.direct_call(&format!("{LOCAL_IP}:{port}")).unwrap();
+
+    wait_established(&mut phone).await;
+
+    let TestUasEvent::Answered { sdp } = next_uas_event(&mut uas).await else {
+        panic!("expected answered");
+    };
+    assert!(sdp.media_descriptions[0].ice_ufrag.is_none());
+
+    hangup(&mut uas, &mut phone, call).await;
+
+    assert!(matches!(
+        phone.direct_call("intercom"),
+        Err(Error::InvalidTarget(_))
+    ));
+}
+
+/// STUN server answering binding requests after the given delay
+async fn slow_stun_server(delay: Duration) -> SocketAddr {
+    let socket = Arc::new(UdpSocket::bind((LOCAL_IP, 0)).await.unwrap());
+    let addr = socket.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut buf = vec![0; 1500];
+
+        loop {
+            let (len, source) = socket.recv_from(&mut buf).await.unwrap();
+
+            let Ok(request) = Message::parse(&buf[..len]) else {
+                continue;
+            };
+
+            let mut response = MessageBuilder::new(
+                Class::Success,
+                StunMethod::Binding,
+                request.transaction_id(),
+            );
+            response.add_attr(XorMappedAddress(source));
+            response.add_attr(Fingerprint);
+            let response = response.finish();
+
+            let socket = socket.clone();
+            tokio::spawn(async move {
+                sleep(delay).await;
+                let _ = socket.send_to(&response, source).await;
+            });
+        }
+    });
+
+    addr
+}
+
+#[tokio::test]
+async fn candidate_cache() {
+    const STUN_DELAY: Duration = Duration::from_secs(1);
+
+    let server = slow_stun_server(STUN_DELAY).await;
+    let options = Options {
+        offer_ice: true,
+        ..Options::lan()
+    };
+
+    let start = Instant::now();
+    let (mut uas, mut phone, call) = dial_with(15108, &[], |builder| {
+        builder
+            .media_options(options)
+            .stun_server(server)
+            .candidate_cache(Duration::from_secs(30), 4)
+    })
+    .await;
+
+    // The cache is still being filled, the first call waits for the STUN server
+    wait_established(&mut phone).await;
+    assert!(start.elapsed() >= STUN_DELAY);
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+    hangup(&mut uas, &mut phone, call).await;
+
+    // Let the cache be filled
+    sleep(STUN_DELAY).await;
+
+    // The second call takes its sockets from the cache, without waiting for the STUN server again
+    let start = Instant::now();
+    let call = phone.dial(uas.uri());
+    wait_established(&mut phone).await;
+    assert!(start.elapsed() < STUN_DELAY / 2, "{:?}", start.elapsed());
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+    hangup(&mut uas, &mut phone, call).await;
+}
+
+/// Passes INVITE requests to the test without responding to them
+struct Invites(mpsc::UnboundedSender<IncomingRequest>);
+
+#[async_trait::async_trait]
+impl Layer for Invites {
+    fn name(&self) -> &'static str {
+        "invites"
+    }
+
+    async fn receive(&self, _: &Endpoint, request: MayTake<'_, IncomingRequest>) {
+        if request.line.method == Method::INVITE {
+            let _ = self.0.send(request.take());
+        }
+    }
+}
+
+#[tokio::test]
+async fn pickup() {
+    let (invites, mut invites_rx) = mpsc::unbounded_channel();
+    let mut builder = Endpoint::builder();
+    builder.add_layer(Invites(invites));
+    let udp = Udp::spawn(&mut builder, (LOCAL_IP.parse::<IpAddr>().unwrap(), 0))
+        .await
+        .unwrap();
+    let target: SipUri = format!("sip:alice@{}", udp.bound()).parse().unwrap();
+    let _endpoint = builder.build();
+
+    let phone = softphone("carol", 15109).await;
+
+    // Call from bob ringing at alice, as reported by alice's dialog event package
+    let dialog = DialogInfo {
+        call_id: BytesStr::from_static("ringing-call"),
+        local_tag: BytesStr::from_static("alice-tag"),
+        remote_tag: BytesStr::from_static("bob-tag"),
+        early: true,
+    };
+    phone.pickup(target, &dialog);
+
+    let invite = timeout(Duration::from_secs(5), invites_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        invite.headers.get_named::<Replaces>().unwrap(),
+        Replaces {
+            call_id: BytesStr::from_static("ringing-call"),
+            from_tag: BytesStr::from_static("bob-tag"),
+            to_tag: BytesStr::from_static("alice-tag"),
+            early_only: true,
+        }
+    );
+    let require: Vec<Require> = invite.headers.get_named().unwrap();
+    assert!(require.iter().any(|require| require.0 == "replaces"));
+}
+
+/// Like [`next_phone_event`], but waits for events of a call's time limit using the paused clock
+async fn next_timed_event(phone: &mut Softphone) -> SoftphoneEvent {
+    timeout(Duration::from_secs(120), phone.next_event())
+        .await
+        .expect("timed out waiting for softphone event")
+        .unwrap()
+}
+
+/// Round to whole seconds, the call's time limit is counted from its establishment which was just before `start`
+fn secs(duration: Duration) -> u64 {
+    (duration + Duration::from_millis(500)).as_secs()
+}
+
+/// Wait for the next termination warning, returns the seconds since `start` it was emitted at and the remaining seconds
+async fn next_termination_warning(
+    phone: &mut Softphone,
+    start: tokio::time::Instant,
+) -> (u64, u64) {
+    loop {
+        if let SoftphoneEvent::TerminationWarning { remaining, .. } = next_timed_event(phone).await
+        {
+            return (secs(start.elapsed()), secs(remaining));
+        }
+    }
+}
+
+/// Wait for the call to end because of its time limit, without any further termination warning
+async fn wait_time_limit_reached(phone: &mut Softphone, call: CallId) {
+    loop {
+        match next_timed_event(phone).await {
+            SoftphoneEvent::Ended {
+                call: ended,
+                reason,
+                ..
+            } if ended == call => {
+                assert!(matches!(reason, EndReason::TimeLimitReached), "{reason:?}");
+                return;
+            }
+            event @ SoftphoneEvent::TerminationWarning { .. } => panic!("unexpected {event:?}"),
+            _ => {}
+        }
+    }
+}
+
+/// Assert that the BYE of the call is received at `deadline`
+///
+/// Delivering the BYE advances the paused clock further, so it must not arrive before the deadline and shortly after.
+async fn assert_bye_at(uas: &mut TestUas, deadline: tokio::time::Instant) {
+    assert!(
+        timeout_at(deadline - Duration::from_millis(100), uas.next_event())
+            .await
+            .is_err(),
+        "received BYE before the deadline"
+    );
+
+    wait_bye(uas, deadline + Duration::from_secs(5)).await;
+}
+
+async fn wait_bye(uas: &mut TestUas, until: tokio::time::Instant) {
+    let event = timeout_at(until, uas.next_event())
+        .await
+        .expect("timed out waiting for BYE")
+        .unwrap();
+
+    assert!(matches!(event, TestUasEvent::Bye), "{event:?}");
+}
+
+/// Establish a call with a termination warning 10 seconds ahead of its time limit, then pause the clock
+///
+/// The clock is paused once the call is established, since it advances while the SIP messages of the call's setup
+/// are in flight.
+async fn established_with_warning(sip_port: u16) -> (TestUas, Softphone, CallId) {
+    let (mut uas, mut phone, call) = dial_with(sip_port, &[], |builder| {
+        builder.termination_warning(Duration::from_secs(10))
+    })
+    .await;
+
+    wait_established(&mut phone).await;
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+
+    tokio::time::pause();
+
+    (uas, phone, call)
+}
+
+#[tokio::test]
+async fn max_duration() {
+    let (mut uas, mut phone, call) = established_with_warning(15110).await;
+
+    let start = tokio::time::Instant::now();
+    phone
+        .set_max_duration(call, Duration::from_secs(30))
+        .unwrap();
+    assert_eq!(next_termination_warning(&mut phone, start).await, (20, 10));
+
+    // A new limit is warned about again
+    phone
+        .set_max_duration(call, Duration::from_secs(40))
+        .unwrap();
+    assert_eq!(next_termination_warning(&mut phone, start).await, (30, 10));
+
+    // The BYE is sent at the deadline
+    assert_bye_at(&mut uas, start + Duration::from_secs(40)).await;
+    wait_time_limit_reached(&mut phone, call).await;
+}
+
+#[tokio::test]
+async fn terminate_at() {
+    let (mut uas, mut phone, call) = established_with_warning(15111).await;
+
+    let start = tokio::time::Instant::now();
+    phone
+        .terminate_at(call, (start + Duration::from_secs(60)).into_std())
+        .unwrap();
+    // The earlier of both limits applies, the warning is emitted once for it
+    phone
+        .set_max_duration(call, Duration::from_secs(30))
+        .unwrap();
+    assert_eq!(next_termination_warning(&mut phone, start).await, (20, 10));
+
+    assert_bye_at(&mut uas, start + Duration::from_secs(30)).await;
+    wait_time_limit_reached(&mut phone, call).await;
+}
+
+#[tokio::test]
+async fn terminate_at_passed_before_established() {
+    let (mut uas, mut phone, call) = dial_with(15112, &[], |builder| {
+        builder.termination_warning(Duration::from_secs(10))
+    })
+    .await;
+    phone.terminate_at(call, Instant::now()).unwrap();
+
+    wait_established(&mut phone).await;
+    tokio::time::pause();
+
+    // Hung up right away, without waiting for a warning
+    assert!(matches!(
+        next_uas_event(&mut uas).await,
+        TestUasEvent::Answered { .. }
+    ));
+    wait_bye(
+        &mut uas,
+        tokio::time::Instant::now() + Duration::from_secs(5),
+    )
+    .await;
+    wait_time_limit_reached(&mut phone, call).await;
+}